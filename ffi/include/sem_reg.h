@@ -0,0 +1,65 @@
+/*
+ * C ABI of the sem-reg crate. Link against `sem_reg_ffi.dll`, built with
+ * `cargo build -p sem-reg-ffi --release`.
+ *
+ * Every function returns a `SemRegResult`. On failure, a message is
+ * available via `sem_reg_last_error_message()` on the same thread.
+ */
+
+#ifndef SEM_REG_H
+#define SEM_REG_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum SemRegResult {
+    SEM_REG_OK = 0,
+    SEM_REG_IO_ERROR = 1,
+    SEM_REG_PARSE_ERROR = 2,
+    SEM_REG_DATA_ERROR = 3,
+    SEM_REG_INVALID_ARGUMENT = 4,
+    SEM_REG_BUFFER_TOO_SMALL = 5,
+    SEM_REG_PANIC = 6,
+} SemRegResult;
+
+/* Valid until the next call on the same thread. Empty after success. */
+const char *sem_reg_last_error_message(void);
+
+/* Sets the strictness of all following calls. */
+void sem_reg_set_lenient(bool lenient);
+
+SemRegResult sem_reg_night_light_get_active(bool *active);
+SemRegResult sem_reg_night_light_set_active(bool active);
+
+/* 0 means the default color temperature. */
+SemRegResult sem_reg_night_light_get_temp(uint16_t *kelvin);
+SemRegResult sem_reg_night_light_set_temp(uint16_t kelvin);
+
+SemRegResult sem_reg_night_light_get_schedule_active(bool *schedule_active);
+SemRegResult sem_reg_night_light_set_schedule_active(bool schedule_active);
+
+/* 24-hour clock times. */
+SemRegResult sem_reg_night_light_set_scheduled_night(
+    uint8_t start_hour, uint8_t start_minute, uint8_t end_hour, uint8_t end_minute);
+
+/* Pass a null buffer to query the required length, including the nul byte. */
+SemRegResult sem_reg_night_light_get_json(char *buffer, size_t buffer_len, size_t *required_len);
+
+/* Like `{"active": true, "nightColorTemp": 2700}`. Keys: `active`,
+ * `scheduleActive`, `scheduleType`, `scheduledNight`, `nightColorTemp`,
+ * `warmth`, `defaultTemp`. */
+SemRegResult sem_reg_night_light_apply_json(const char *change_json);
+
+/* 0 uses a reasonable default duration. */
+SemRegResult sem_reg_night_light_init(uint32_t duration_millis);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
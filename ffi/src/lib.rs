@@ -0,0 +1,304 @@
+//! A C ABI for the `sem-reg` crate, so that applications in other languages can control Night Light without spawning the command line program. See `include/sem_reg.h` for the declarations.
+//!
+//! Every function returns a [`SemRegResult`]. On failure, a message is available via [`sem_reg_last_error_message()`] on the same thread. Panics are caught and reported as [`SemRegResult::Panic`].
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    panic::{self, UnwindSafe},
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use sem_reg::{
+    cloud_store::night_light::{self, ChangeSet, ClockTime, ClockTimeFrame, NightLight},
+    data_conversion::Strictness,
+};
+
+static LENIENT: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static LAST_ERROR_MESSAGE: RefCell<CString> = RefCell::new(CString::default());
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SemRegResult {
+    Ok = 0,
+    /// Error interacting with the registry, e.g., because of a non-existent registry value.
+    IoError = 1,
+    /// Couldn't parse a registry value.
+    ParseError = 2,
+    /// The changes couldn't be written, e.g., because they're irreconcilable with other properties.
+    DataError = 3,
+    /// A null pointer, invalid UTF-8 or JSON, or an out-of-range number was passed.
+    InvalidArgument = 4,
+    /// The buffer is too small. The required length is stored, if requested.
+    BufferTooSmall = 5,
+    Panic = 6,
+}
+
+impl From<night_light::Error> for SemRegResult {
+    fn from(error: night_light::Error) -> Self {
+        set_last_error_message(&error.to_string());
+        Self::of(&error)
+    }
+}
+
+impl SemRegResult {
+    fn of(error: &night_light::Error) -> Self {
+        match error {
+            night_light::Error::IoError(_) => Self::IoError,
+            night_light::Error::ParseError(_) => Self::ParseError,
+            night_light::Error::DataError(_) => Self::DataError,
+            night_light::Error::RestoreFailed { error, .. } => Self::of(error),
+        }
+    }
+}
+
+fn set_last_error_message(message: &str) {
+    LAST_ERROR_MESSAGE.with(|last_error_message| {
+        *last_error_message.borrow_mut() =
+            CString::new(message.replace('\0', " ")).expect("nul bytes should be replaced");
+    });
+}
+
+fn invalid_argument(message: &str) -> SemRegResult {
+    set_last_error_message(message);
+    SemRegResult::InvalidArgument
+}
+
+fn catch<F: FnOnce() -> Result<(), SemRegResult> + UnwindSafe>(f: F) -> SemRegResult {
+    set_last_error_message("");
+
+    match panic::catch_unwind(f) {
+        Ok(Ok(())) => SemRegResult::Ok,
+        Ok(Err(result)) => result,
+        Err(_) => {
+            set_last_error_message("panicked");
+            SemRegResult::Panic
+        }
+    }
+}
+
+fn read_night_light() -> Result<NightLight, SemRegResult> {
+    Ok(NightLight::from_reg_with_strictness(
+        Strictness::from_lenient_bool(LENIENT.load(Ordering::Relaxed)),
+    )?)
+}
+
+fn modify_night_light<F: FnOnce(&mut NightLight)>(f: F) -> Result<(), SemRegResult> {
+    let mut night_light = read_night_light()?;
+    f(&mut night_light);
+    Ok(night_light.write_to_reg()?)
+}
+
+unsafe fn store<T>(out: *mut T, value: T) -> Result<(), SemRegResult> {
+    //! # Safety
+    //! `out` must be null or valid for writes.
+
+    if out.is_null() {
+        return Err(invalid_argument("null pointer passed"));
+    }
+
+    out.write(value);
+    Ok(())
+}
+
+unsafe fn copy_to_buffer(
+    text: &str,
+    buffer: *mut c_char,
+    buffer_len: usize,
+    required_len: *mut usize,
+) -> Result<(), SemRegResult> {
+    //! Copies the text with a terminating nul byte. `buffer` may be null to only query the required length.
+    //!
+    //! # Safety
+    //! `buffer` must be null or valid for writes of `buffer_len` bytes. `required_len` must be null or valid for writes.
+
+    let len = text.len() + 1;
+    if !required_len.is_null() {
+        required_len.write(len);
+    }
+
+    if buffer.is_null() || buffer_len < len {
+        set_last_error_message("buffer too small");
+        return Err(SemRegResult::BufferTooSmall);
+    }
+
+    ptr::copy_nonoverlapping(text.as_ptr(), buffer as *mut u8, text.len());
+    buffer.add(text.len()).write(0);
+
+    Ok(())
+}
+
+/// Returns the message of the last failed call on the current thread. Valid until the next call on this thread. Empty, if the last call succeeded.
+#[no_mangle]
+pub extern "C" fn sem_reg_last_error_message() -> *const c_char {
+    LAST_ERROR_MESSAGE.with(|last_error_message| last_error_message.borrow().as_ptr())
+}
+
+/// Sets the strictness of all following calls. See `Strictness::Lenient` of the Rust crate.
+#[no_mangle]
+pub extern "C" fn sem_reg_set_lenient(lenient: bool) {
+    LENIENT.store(lenient, Ordering::Relaxed);
+}
+
+/// # Safety
+/// The pointer must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sem_reg_night_light_get_active(active: *mut bool) -> SemRegResult {
+    catch(|| store(active, read_night_light()?.active()))
+}
+
+#[no_mangle]
+pub extern "C" fn sem_reg_night_light_set_active(active: bool) -> SemRegResult {
+    catch(|| modify_night_light(|night_light| night_light.set_active(active)))
+}
+
+/// Stores 0, if the default color temperature is in effect.
+///
+/// # Safety
+/// The pointer must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sem_reg_night_light_get_temp(kelvin: *mut u16) -> SemRegResult {
+    catch(|| store(kelvin, read_night_light()?.night_color_temp().unwrap_or(0)))
+}
+
+/// Pass 0 for the default color temperature.
+#[no_mangle]
+pub extern "C" fn sem_reg_night_light_set_temp(kelvin: u16) -> SemRegResult {
+    catch(|| {
+        modify_night_light(|night_light| {
+            night_light.set_night_color_temp((kelvin != 0).then_some(kelvin))
+        })
+    })
+}
+
+/// # Safety
+/// The pointer must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sem_reg_night_light_get_schedule_active(
+    schedule_active: *mut bool,
+) -> SemRegResult {
+    catch(|| store(schedule_active, read_night_light()?.schedule_active()))
+}
+
+#[no_mangle]
+pub extern "C" fn sem_reg_night_light_set_schedule_active(schedule_active: bool) -> SemRegResult {
+    catch(|| modify_night_light(|night_light| night_light.set_schedule_active(schedule_active)))
+}
+
+/// Sets the explicitly scheduled night with 24-hour clock times.
+#[no_mangle]
+pub extern "C" fn sem_reg_night_light_set_scheduled_night(
+    start_hour: u8,
+    start_minute: u8,
+    end_hour: u8,
+    end_minute: u8,
+) -> SemRegResult {
+    catch(|| {
+        let (Some(start), Some(end)) = (
+            ClockTime::from_h_min(start_hour, start_minute),
+            ClockTime::from_h_min(end_hour, end_minute),
+        ) else {
+            return Err(invalid_argument("clock time out of range"));
+        };
+
+        modify_night_light(|night_light| {
+            night_light.set_scheduled_night(ClockTimeFrame { start, end })
+        })
+    })
+}
+
+/// Copies the JSON representation of the configuration, like printed by `night-light --json`, into the buffer as a nul-terminated UTF-8 string. Pass a null buffer to only query the required length in bytes, including the nul byte.
+///
+/// # Safety
+/// `buffer` must be null or valid for writes of `buffer_len` bytes. `required_len` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sem_reg_night_light_get_json(
+    buffer: *mut c_char,
+    buffer_len: usize,
+    required_len: *mut usize,
+) -> SemRegResult {
+    catch(|| {
+        copy_to_buffer(
+            &read_night_light()?.to_json(),
+            buffer,
+            buffer_len,
+            required_len,
+        )
+    })
+}
+
+/// Applies changes given as a nul-terminated UTF-8 JSON object like `{"active": true, "nightColorTemp": 2700}`, with the keys `active`, `scheduleActive`, `scheduleType`, `scheduledNight`, `nightColorTemp`, `warmth` and `defaultTemp`, each optional. See [`ChangeSet`] for their values.
+///
+/// # Safety
+/// The pointer must be null or point to a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sem_reg_night_light_apply_json(
+    change_json: *const c_char,
+) -> SemRegResult {
+    catch(|| {
+        if change_json.is_null() {
+            return Err(invalid_argument("null pointer passed"));
+        }
+
+        let change_json = CStr::from_ptr(change_json)
+            .to_str()
+            .map_err(|_| invalid_argument("invalid UTF-8"))?;
+        let change_set = serde_json::from_str::<ChangeSet>(change_json)
+            .map_err(|error| invalid_argument(&format!("invalid JSON: {error}")))?;
+
+        modify_night_light(|night_light| {
+            change_set.apply_to(night_light);
+        })
+    })
+}
+
+/// Initializes Night Light after log-on or turning the screen back on, like `night-light init`. `duration_millis` is the time preview mode is held active; 0 uses a reasonable default.
+#[no_mangle]
+pub extern "C" fn sem_reg_night_light_init(duration_millis: u32) -> SemRegResult {
+    catch(|| {
+        let delay = if duration_millis == 0 {
+            NightLight::REASONABLE_INIT_DELAY
+        } else {
+            Duration::from_millis(duration_millis as _)
+        };
+
+        Ok(NightLight::init_with_strictness(
+            delay,
+            false,
+            Strictness::from_lenient_bool(LENIENT.load(Ordering::Relaxed)),
+        )?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CStr, ptr};
+
+    use super::{copy_to_buffer, sem_reg_last_error_message, SemRegResult};
+
+    #[test]
+    fn buffer_copying() {
+        let mut required_len = 0;
+        assert_eq!(
+            unsafe { copy_to_buffer("abc", ptr::null_mut(), 0, &mut required_len) },
+            Err(SemRegResult::BufferTooSmall)
+        );
+        assert_eq!(required_len, 4);
+        assert_eq!(
+            unsafe { CStr::from_ptr(sem_reg_last_error_message()) }.to_str(),
+            Ok("buffer too small")
+        );
+
+        let mut buffer = [1; 4];
+        assert_eq!(
+            unsafe { copy_to_buffer("abc", buffer.as_mut_ptr(), buffer.len(), ptr::null_mut()) },
+            Ok(())
+        );
+        assert_eq!(buffer, [b'a' as _, b'b' as _, b'c' as _, 0]);
+    }
+}
@@ -0,0 +1,261 @@
+//! Per-user autostart entries for the resident modes of the binaries (like `night-light keep-initing`), either as values of the `Run` registry key or as scheduled tasks with a log-on trigger. Only entries created by this module are listed and changed: `Run` values whose name starts with [`RUN_VALUE_NAME_PREFIX`], and tasks in the [`TASK_FOLDER`].
+
+use serde::Serialize;
+use std::{io, path::Path, process::Command};
+use thiserror::Error;
+use winreg::{
+    enums::{HKEY_CURRENT_USER, KEY_QUERY_VALUE, KEY_SET_VALUE},
+    RegKey,
+};
+
+const RUN_SUBKEY_PATH: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\Run";
+pub const RUN_VALUE_NAME_PREFIX: &str = "sem-reg ";
+pub const TASK_FOLDER: &str = r"\sem-reg\";
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum AutostartKind {
+    /// A value of `HKEY_CURRENT_USER\SOFTWARE\Microsoft\Windows\CurrentVersion\Run`. Doesn't require elevation.
+    RunEntry,
+    /// A task of the Task Scheduler, triggered on log-on of the current user. Creating it may require elevation.
+    ScheduledTask,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AutostartEntry {
+    /// Without the prefix or folder.
+    pub name: String,
+    pub kind: AutostartKind,
+    /// The program path, quoted if necessary, followed by the arguments.
+    pub command_line: String,
+}
+
+impl AutostartEntry {
+    pub fn program_path(&self) -> &str {
+        program_path(&self.command_line)
+    }
+
+    pub fn is_stale(&self) -> bool {
+        //! Whether the program doesn't exist anymore, e.g., because it was moved or uninstalled.
+
+        !Path::new(self.program_path()).is_file()
+    }
+
+    pub fn create_or_update(&self) -> Result<(), AutostartError> {
+        match self.kind {
+            AutostartKind::RunEntry => {
+                let (key, _) = RegKey::predef(HKEY_CURRENT_USER)
+                    .create_subkey_with_flags(RUN_SUBKEY_PATH, KEY_SET_VALUE)?;
+                key.set_value(
+                    format!("{RUN_VALUE_NAME_PREFIX}{}", self.name),
+                    &self.command_line,
+                )?;
+            }
+            AutostartKind::ScheduledTask => {
+                schtasks(&[
+                    "/create",
+                    "/tn",
+                    &format!("{TASK_FOLDER}{}", self.name),
+                    "/tr",
+                    &self.command_line,
+                    "/sc",
+                    "onlogon",
+                    "/rl",
+                    "limited",
+                    "/f",
+                ])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn remove(&self) -> Result<(), AutostartError> {
+        match self.kind {
+            AutostartKind::RunEntry => {
+                RegKey::predef(HKEY_CURRENT_USER)
+                    .open_subkey_with_flags(RUN_SUBKEY_PATH, KEY_SET_VALUE)?
+                    .delete_value(format!("{RUN_VALUE_NAME_PREFIX}{}", self.name))?;
+            }
+            AutostartKind::ScheduledTask => {
+                schtasks(&[
+                    "/delete",
+                    "/tn",
+                    &format!("{TASK_FOLDER}{}", self.name),
+                    "/f",
+                ])?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn list() -> Result<Vec<AutostartEntry>, AutostartError> {
+    //! Lists the entries of both kinds created by this module.
+
+    let mut entries = Vec::new();
+
+    match RegKey::predef(HKEY_CURRENT_USER).open_subkey_with_flags(RUN_SUBKEY_PATH, KEY_QUERY_VALUE)
+    {
+        Ok(key) => {
+            for result in key.enum_values() {
+                let (value_name, _) = result?;
+                if let Some(name) = value_name.strip_prefix(RUN_VALUE_NAME_PREFIX) {
+                    entries.push(AutostartEntry {
+                        name: name.to_string(),
+                        kind: AutostartKind::RunEntry,
+                        command_line: key.get_value(&value_name)?,
+                    });
+                }
+            }
+        }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error.into()),
+    }
+
+    let mut task_paths = schtasks(&["/query", "/fo", "csv", "/nh"])?
+        .lines()
+        .filter_map(first_csv_field)
+        .filter(|task_path| task_path.starts_with(TASK_FOLDER))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    task_paths.sort();
+    task_paths.dedup();
+
+    for task_path in task_paths {
+        let xml = schtasks(&["/query", "/tn", &task_path, "/xml"])?;
+        let command = xml_element_text(&xml, "Command").unwrap_or_default();
+        let command_line = match xml_element_text(&xml, "Arguments") {
+            Some(arguments) => format!("{command} {arguments}"),
+            None => command,
+        };
+
+        entries.push(AutostartEntry {
+            name: task_path[TASK_FOLDER.len()..].to_string(),
+            kind: AutostartKind::ScheduledTask,
+            command_line,
+        });
+    }
+
+    Ok(entries)
+}
+
+pub fn command_line<I, S>(program_path: &Path, args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    //! Builds a command line, quoting the program path and arguments containing spaces.
+
+    let quote = |string: &str| {
+        if string.contains(' ') {
+            format!("\"{string}\"")
+        } else {
+            string.to_string()
+        }
+    };
+
+    let mut command_line = quote(&program_path.to_string_lossy());
+    for arg in args {
+        command_line.push(' ');
+        command_line.push_str(&quote(arg.as_ref()));
+    }
+
+    command_line
+}
+
+fn program_path(command_line: &str) -> &str {
+    match command_line.strip_prefix('"') {
+        Some(rest) => rest.split('"').next().unwrap_or(rest),
+        None => command_line.split(' ').next().unwrap_or(command_line),
+    }
+}
+
+fn schtasks(args: &[&str]) -> Result<String, AutostartError> {
+    let output = Command::new("schtasks.exe").args(args).output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(AutostartError::SchtasksFailed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+fn first_csv_field(line: &str) -> Option<&str> {
+    line.strip_prefix('"')?.split('"').next()
+}
+
+fn xml_element_text(xml: &str, element_name: &str) -> Option<String> {
+    //! Returns the unescaped text of the first element with the name. Sufficient for the simple task XML.
+
+    let start_tag = format!("<{element_name}>");
+    let end_tag = format!("</{element_name}>");
+    let start_index = xml.find(&start_tag)? + start_tag.len();
+    let len = xml[start_index..].find(&end_tag)?;
+
+    Some(
+        xml[start_index..start_index + len]
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&"),
+    )
+}
+
+#[derive(Error, Debug)]
+pub enum AutostartError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+    /// `schtasks.exe` ended unsuccessfully, with the contained error output.
+    #[error("schtasks.exe failed: {0}")]
+    SchtasksFailed(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{command_line, first_csv_field, program_path, xml_element_text};
+
+    #[test]
+    fn command_lines() {
+        let line = command_line(
+            Path::new(r"C:\Program Files\sem-reg.exe"),
+            ["night-light", "keep-initing"],
+        );
+        assert_eq!(
+            line,
+            r#""C:\Program Files\sem-reg.exe" night-light keep-initing"#
+        );
+        assert_eq!(program_path(&line), r"C:\Program Files\sem-reg.exe");
+        assert_eq!(
+            program_path(r"C:\bin\night-light.exe keep"),
+            r"C:\bin\night-light.exe"
+        );
+    }
+
+    #[test]
+    fn schtasks_output() {
+        assert_eq!(
+            first_csv_field(r#""\sem-reg\daemon","N/A","Ready""#),
+            Some(r"\sem-reg\daemon")
+        );
+        assert_eq!(first_csv_field(""), None);
+
+        let xml = r#"<Exec><Command>"C:\a b\sem-reg.exe"</Command><Arguments>daemon &amp;</Arguments></Exec>"#;
+        assert_eq!(
+            xml_element_text(xml, "Command").as_deref(),
+            Some(r#""C:\a b\sem-reg.exe""#)
+        );
+        assert_eq!(
+            xml_element_text(xml, "Arguments").as_deref(),
+            Some("daemon &")
+        );
+        assert_eq!(xml_element_text(xml, "WorkingDirectory"), None);
+    }
+}
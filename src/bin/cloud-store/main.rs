@@ -0,0 +1,82 @@
+use clap::Parser;
+use sem_reg::{
+    cloud_store::{annotated_hex::AnnotatedHex, corpus},
+    reg::read_reg_bin_value,
+};
+
+/// Explore the CloudStore values of the current user, to reverse-engineer their formats.
+///
+/// Dumps every value as hex, annotated with the timestamp and body length of the prologue and the IDs, types and values of the body's fields.
+#[derive(clap::Parser, Debug)]
+#[command(name = env!("CARGO_BIN_NAME"), version)]
+struct Cli {
+    /// Only include values whose key path contains this text (case-insensitive).
+    filter: Option<String>,
+
+    /// Only list the values with their timestamp and body length, without dumping them.
+    #[arg(short, long)]
+    list: bool,
+
+    /// Skip values the crate has dedicated types for.
+    #[arg(short, long)]
+    unknown_only: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let filter = cli.filter.as_deref().map(str::to_lowercase);
+
+    let reg_value_paths = corpus::cloud_store_value_paths()?
+        .into_iter()
+        .filter(|reg_value_path| {
+            filter.as_ref().map_or(true, |filter| {
+                reg_value_path.subkey_path.to_lowercase().contains(filter)
+            })
+        })
+        .filter(|reg_value_path| !cli.unknown_only || !corpus::is_known(reg_value_path));
+
+    for (index, reg_value_path) in reg_value_paths.enumerate() {
+        //. Shortened to the part below `...\CloudStore\Store\DefaultAccount`.
+        let key_path = reg_value_path
+            .subkey_path
+            .split_once(r"\DefaultAccount\")
+            .map_or(&reg_value_path.subkey_path[..], |(_, key_path)| key_path);
+
+        //. Values can be deleted while enumerating.
+        let bytes = match read_reg_bin_value(&reg_value_path.as_borrowed()) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                eprintln!("{key_path}: {error}");
+                continue;
+            }
+        };
+        let annotated_hex = AnnotatedHex::new(&bytes);
+
+        if cli.list {
+            println!(
+                "{:19}  {:>5}  {key_path}",
+                annotated_hex
+                    .epoch_secs()
+                    .and_then(|epoch_secs| chrono::DateTime::from_timestamp(epoch_secs as _, 0))
+                    .map_or_else(
+                        || "-".to_string(),
+                        |date_time| date_time
+                            .with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string()
+                    ),
+                annotated_hex
+                    .num_body_bytes()
+                    .map_or_else(|| "-".to_string(), |num| num.to_string()),
+            );
+        } else {
+            if index != 0 {
+                println!();
+            }
+            println!("{key_path} ({} bytes)", bytes.len());
+            println!("{annotated_hex}");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1725 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use futures::channel::oneshot;
+use std::{
+    io, iter,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use windows::{
+    core::HSTRING,
+    Win32::{
+        Foundation::{ERROR_ACCESS_DENIED, HANDLE, HWND, LPARAM, LRESULT, WPARAM},
+        System::{
+            Console::{FreeConsole, GetConsoleProcessList},
+            Power::{PoAc, RegisterPowerSettingNotification, SYSTEM_POWER_CONDITION},
+            RemoteDesktop::{
+                WTSRegisterSessionNotification, WTSUnRegisterSessionNotification,
+                NOTIFY_FOR_THIS_SESSION,
+            },
+            SystemServices::{
+                PowerMonitorDim, PowerMonitorOff, PowerMonitorOn, GUID_ACDC_POWER_SOURCE,
+                GUID_CONSOLE_DISPLAY_STATE, MONITOR_DISPLAY_STATE,
+            },
+            Threading::GetCurrentProcessId,
+        },
+        UI::WindowsAndMessaging::{
+            DestroyWindow, KillTimer, PostMessageW, PostQuitMessage, RegisterWindowMessageW,
+            SendNotifyMessageW, SetTimer, DEVICE_NOTIFY_WINDOW_HANDLE, HWND_BROADCAST,
+            PBT_APMRESUMEAUTOMATIC, PBT_APMRESUMESUSPEND, WM_CREATE, WM_DESTROY, WM_HOTKEY,
+            WM_POWERBROADCAST, WM_TIMER, WM_WTSSESSION_CHANGE, WTS_CONSOLE_CONNECT,
+            WTS_REMOTE_CONNECT, WTS_SESSION_UNLOCK,
+        },
+    },
+};
+use windows_helpers::{
+    core::CheckNumberError,
+    dual_call,
+    power::PowerBroadcastSettingExt,
+    win32_app::{
+        error::{try_or_quit_now, try_then_favor_app_error},
+        msg_loop,
+        window::{translate_power_broadcast_msg, PowerBroadcastMsg, Window, WindowClass},
+    },
+    FirstCallExpectation, ResGuard,
+};
+
+use super::cli::{
+    self, CtlRequest, ExportFormat, GlobalArgs, HistoryValue, InitDurationArg, NightLightArgs,
+    PresetAction, RequiredOnOffArgs, ScheduleAction, ScheduleArgs, Subcmd, TempArgs, WarmthArg,
+};
+use super::tray::Tray;
+use sem_reg::{
+    cloud_store::night_light::{
+        self, ChangeSet, ClockTime, NightLight, NightLightBytes, NightPreviewGuard, WarmthRamp,
+        WriteOutcome,
+    },
+    config::Config,
+    daemon::{self, InstanceHealth, InstanceMutex, MonitorState, Request, Response},
+    data_conversion::{hex_bytes::HexBytes, Strictness},
+    hotkey::{Hotkey, HotkeyRegistration},
+    log_error, log_info, log_warn,
+    reg::{
+        monitor::{
+            self,
+            journal::{ChangeJournal, Retention},
+            StopSignal,
+        },
+        sid::current_user_sid,
+    },
+    sun::{Coordinates, SunOffsets},
+    webhook::Notifier,
+};
+
+pub const DEFAULT_KEEP_INITING_DELAY: u16 = 100;
+
+/// The warmth values offered by the tray menu and cycled through by the hotkey, in percent.
+pub const WARMTH_STEPS: [u8; 4] = [25, 50, 75, 100];
+
+const TOGGLE_HOTKEY_ID: i32 = 1;
+const CYCLE_HOTKEY_ID: i32 = 2;
+
+const RAMP_TIMER_ID: usize = 1;
+/// How often the color temperature is updated along the warmth ramp.
+const RAMP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long to wait for a replaced instance to end.
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often creating the control pipe is retried while a replaced instance may still hold it.
+const PIPE_CREATION_RETRY_COUNT: usize = 40;
+
+/// Ways to control Night Light while [`keep_initing()`] runs.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Interaction {
+    /// Show a tray icon with a menu.
+    pub tray: bool,
+    pub toggle_hotkey: Option<Hotkey>,
+    /// Cycles through [`WARMTH_STEPS`].
+    pub cycle_hotkey: Option<Hotkey>,
+    /// The gamma applied to the warmth steps. `None` for 1.
+    pub gamma: Option<f32>,
+    /// Follow a warmth ramp over the night.
+    pub ramp: Option<RampMode>,
+    /// The color temperature while on battery power. The previous one is restored on AC power and when stopping, unless the color temperature was changed in the meantime.
+    pub battery_night_color_temp: Option<u16>,
+    /// Serve requests on the pipe named by [`daemon::keep_initing_pipe_name()`].
+    pub ctl_pipe: bool,
+}
+
+/// The warmth ramp [`keep_initing()`] follows.
+#[derive(Clone, Copy, Debug)]
+pub struct RampMode {
+    pub ramp: WarmthRamp,
+    /// Where to follow the night from sunset to sunrise. `None` follows the schedule in effect.
+    pub coordinates: Option<Coordinates>,
+}
+
+pub fn run(global_args: &GlobalArgs, config: &Config, args: NightLightArgs) -> anyhow::Result<()> {
+    //! Executes the Night Light commands. Shared by the `night-light` and `sem-reg` binaries. Omitted flags fall back to the configuration file.
+
+    let &GlobalArgs {
+        lenient,
+        json,
+        dry_run,
+        ..
+    } = global_args;
+    let am_pm = args.am_pm || config.night_light.am_pm;
+    let verify_delay = args
+        .verify
+        .or(config.night_light.verify_delay)
+        .map(|millis| Duration::from_millis(millis as _));
+    let init_duration = |duration: Option<u16>| duration.or(config.night_light.init_duration);
+
+    match args.subcmd {
+        Some(
+            Subcmd::Init { .. }
+            | Subcmd::KeepIniting { .. }
+            | Subcmd::Cycle { .. }
+            | Subcmd::Fade { .. }
+            | Subcmd::Enforce { .. }
+            | Subcmd::Rollout { .. }
+            | Subcmd::Ctl {
+                request:
+                    CtlRequest::Set { .. } | CtlRequest::Toggle | CtlRequest::Reinit | CtlRequest::Stop,
+            },
+        ) if dry_run => {
+            return Err(anyhow!("'--dry-run' isn't supported by this command"));
+        }
+
+        // Export so that the user can be supported, e.g.
+        Some(Subcmd::Export { output, format }) => {
+            let has_user_defined_path = output.is_some();
+            let file_path = output.unwrap_or_else(|| {
+                let extension = match format {
+                    ExportFormat::Reg => "reg",
+                    ExportFormat::Json => "json",
+                };
+                chrono::Local::now()
+                    .format(if am_pm {
+                        "%Y-%m-%d, %I.%M.%S %P"
+                    } else {
+                        "%Y-%m-%d, %H.%M.%S"
+                    })
+                    .to_string()
+                    + "."
+                    + extension
+            });
+
+            match format {
+                ExportFormat::Reg => NightLight::export_reg(&file_path)?,
+                ExportFormat::Json => {
+                    NightLight::export_json(&file_path, Strictness::from_lenient_bool(lenient))?
+                }
+            }
+
+            if !has_user_defined_path {
+                println!("Wrote '{file_path}'.");
+            }
+        }
+
+        Some(Subcmd::Import { file }) if dry_run => {
+            println!(
+                "Would import the Night Light registry values from '{}'.",
+                file.display()
+            );
+        }
+
+        Some(Subcmd::Import { file }) => NightLight::import_reg(file)?,
+
+        Some(Subcmd::Delete { .. }) if dry_run => {
+            println!("Would delete the Night Light registry values.");
+        }
+
+        Some(Subcmd::Delete { backup }) => match backup {
+            Some(backup_file_path) => NightLight::delete_reg_with_backup(backup_file_path)?,
+            None => NightLight::delete_reg()?,
+        },
+
+        Some(Subcmd::Preset {
+            action: PresetAction::Save { name },
+        }) => {
+            let night_light =
+                NightLight::from_reg_with_strictness(Strictness::from_lenient_bool(lenient))?;
+
+            if dry_run {
+                println!("Would save the current configuration as preset '{name}'.");
+            } else {
+                night_light.save_preset(&name)?;
+            }
+        }
+
+        Some(Subcmd::Preset {
+            action: PresetAction::List,
+        }) => {
+            let presets = NightLight::presets()?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&presets)?);
+            } else if presets.is_empty() {
+                println!("No presets saved.");
+            } else {
+                for (name, preset) in presets {
+                    println!("{name}: {preset}");
+                }
+            }
+        }
+
+        Some(Subcmd::Preset {
+            action: PresetAction::Delete { name },
+        }) if dry_run => {
+            println!("Would delete preset '{name}'.");
+        }
+
+        Some(Subcmd::Preset {
+            action: PresetAction::Delete { name },
+        }) => NightLight::delete_preset(&name)?,
+
+        Some(Subcmd::Monitor { stats, record }) => {
+            if stats {
+                monitor::stats::enable();
+            }
+
+            if !json {
+                println!("Press Ctrl+C to abort. (On very fast changes, newer data than that triggering the change may be read.)");
+                println!();
+            }
+
+            let (stop_sender, stop_receiver) = oneshot::channel::<()>();
+            let mut stop_sender = Some(stop_sender);
+            ctrlc::set_handler(move || {
+                if let Some(stop_sender) = stop_sender.take() {
+                    stop_sender.send(()).unwrap();
+                }
+            })?;
+
+            //. Only the changed value comes with the events, so the other one is kept from here on.
+            let mut current_bytes = NightLightBytes::from_reg()?;
+            let notifier = Notifier::new(config.webhooks.clone());
+
+            let journal = if record { Some(open_journal()?) } else { None };
+            let strictness = Strictness::from_lenient_bool(lenient);
+
+            NightLight::monitor_events(Some(stop_receiver), |event| {
+                let value_id = event.id;
+                let previous_bytes = current_bytes.clone();
+                match &event.bytes {
+                    Some(value_bytes) => {
+                        current_bytes.set_bytes_of_value(value_id, value_bytes.clone())
+                    }
+                    None => {
+                        return Some(Err(night_light::Error::from(io::Error::from(
+                            io::ErrorKind::NotFound,
+                        ))))
+                    }
+                }
+                let bytes = current_bytes.clone();
+
+                if let Some(journal) = &journal {
+                    //. The summary keeps the journal readable without parsing the bytes again later.
+                    let summary =
+                        match NightLight::from_bytes_with_strictness(bytes.clone(), strictness) {
+                            Ok(night_light) => {
+                                serde_json::from_str::<serde_json::Value>(&night_light.to_json())
+                                    .expect("`NightLight` JSON should be valid")
+                                    .to_string()
+                            }
+                            Err(error) => format!("unparsable: {error}"),
+                        };
+                    if let Err(error) = journal.record_with_summary(&event, Some(summary)) {
+                        log_warn!("couldn't record change in journal: {error}");
+                    }
+
+                    //. Makes changes from elsewhere, like the Settings app, revertible with `undo`.
+                    if let (Ok(previous), Ok(new)) = (
+                        NightLight::from_bytes_lenient(previous_bytes),
+                        NightLight::from_bytes_lenient(bytes.clone()),
+                    ) {
+                        if let Err(error) = NightLight::push_observed_undo_point(
+                            ChangeSet::from_night_light(&previous),
+                            &ChangeSet::from_night_light(&new),
+                        ) {
+                            log_warn!("couldn't save undo point: {error}");
+                        }
+                    }
+                }
+
+                //. One line per change, to be consumed while running.
+                if json {
+                    return match NightLight::from_bytes_with_strictness(bytes, strictness) {
+                        Ok(night_light) => {
+                            let value: serde_json::Value =
+                                serde_json::from_str(&night_light.to_json())
+                                    .expect("`NightLight` JSON should be valid");
+                            println!("{value}");
+                            notifier.notify("nightLight", value);
+                            None
+                        }
+                        Err(error) => Some(Err(error.into())),
+                    };
+                }
+
+                println!(
+                    "{}",
+                    format!("{value_id:?} registry value changed").to_uppercase()
+                );
+
+                //. When parsing fails, the user must at least see the bytes to be able to ask for support.
+                let hex_bytes = HexBytes::new(bytes.bytes_of_value(value_id));
+                println!("{}", format!("(bytes: {})", hex_bytes).dimmed());
+
+                println!(
+                    "(diff against previous: {})",
+                    hex_bytes.diff_against(event.previous_bytes.as_deref().unwrap_or_default())
+                );
+                println!();
+
+                let mut night_light =
+                    match NightLight::from_bytes_with_strictness(bytes, strictness) {
+                        Ok(night_light) => night_light,
+                        Err(error) => return Some(Err(error.into())),
+                    };
+                night_light.set_uses_12_hour_clock(am_pm);
+                println!("{night_light:?}");
+                println!();
+
+                notifier.notify(
+                    "nightLight",
+                    serde_json::from_str(&night_light.to_json())
+                        .expect("`NightLight` JSON should be valid"),
+                );
+
+                None
+            })?;
+
+            //. After the JSON lines, the timings go to stderr, so that they don't disturb their consumers.
+            if stats {
+                let stats = monitor::stats::snapshot();
+                if json {
+                    eprintln!("{stats}");
+                } else {
+                    println!("TIMINGS");
+                    println!("{stats}");
+                }
+            }
+        }
+
+        Some(Subcmd::Undo) if dry_run => match NightLight::undo_points()?.pop() {
+            Some(point) => println!(
+                "Would revert to:\n{}",
+                serde_json::to_string_pretty(&point)?
+            ),
+            None => println!("Nothing to undo."),
+        },
+
+        Some(Subcmd::Undo) => {
+            match NightLight::revert_last_with_strictness(Strictness::from_lenient_bool(lenient))? {
+                Some(_) if json => println!("{}", read_status(lenient)?),
+                Some(_) => {}
+                None => return Err(anyhow!("nothing to undo")),
+            }
+        }
+
+        Some(Subcmd::Schedule {
+            action: Some(ScheduleAction::Next),
+            ..
+        }) => {
+            let transition =
+                NightLight::from_reg_with_strictness(Strictness::from_lenient_bool(lenient))?
+                    .next_transition();
+
+            if json {
+                println!(
+                    "{}",
+                    match transition {
+                        Some(transition) => serde_json::json!({
+                            "time": transition
+                                .time
+                                .to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+                            "activates": transition.activates,
+                        }),
+                        None => serde_json::Value::Null,
+                    }
+                );
+            } else {
+                match transition {
+                    Some(transition) => println!(
+                        "Night Light turns {} at {}.",
+                        if transition.activates { "on" } else { "off" },
+                        transition.time.format(if am_pm {
+                            "%Y-%m-%d, %I:%M %P"
+                        } else {
+                            "%Y-%m-%d, %H:%M"
+                        })
+                    ),
+                    None => println!("The schedule won't turn Night Light on or off."),
+                }
+            }
+        }
+
+        Some(Subcmd::Rollout { profile, offline }) => {
+            let change_set = config
+                .profiles
+                .get(&profile)
+                .ok_or_else(|| anyhow!("no profile '{profile}' in the configuration file"))?;
+            let outcomes = NightLight::apply_to_all_users(
+                change_set,
+                offline,
+                Strictness::from_lenient_bool(lenient),
+            )?;
+
+            let mut failure_count = 0;
+            for outcome in &outcomes {
+                let (changed, error) = match &outcome.result {
+                    Ok(writes) => (!writes.is_empty(), None),
+                    Err(error) => {
+                        failure_count += 1;
+                        (false, Some(error.to_string()))
+                    }
+                };
+
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::json!({
+                            "sid": outcome.sid,
+                            "offline": outcome.was_offline,
+                            "changed": changed,
+                            "error": error,
+                        })
+                    );
+                } else {
+                    let result = match error {
+                        Some(error) => format!("error: {error}").red().to_string(),
+                        None if changed => "changed".to_string(),
+                        None => "unchanged".dimmed().to_string(),
+                    };
+                    let offline_note = if outcome.was_offline {
+                        " (offline)"
+                    } else {
+                        ""
+                    };
+                    println!("{}{offline_note}: {result}", outcome.sid);
+                }
+            }
+
+            if failure_count > 0 {
+                return Err(anyhow!(
+                    "couldn't apply the profile for {failure_count} of {} users",
+                    outcomes.len()
+                ));
+            }
+        }
+
+        Some(Subcmd::History { last, value }) => {
+            let journal = open_journal()?;
+
+            //. Queried per registry value to know which one each entry belongs to.
+            let mut entries = Vec::new();
+            for (reg_value_path, (history_value, value_id)) in
+                NightLight::REG_VALUE_PATHS.into_iter().zip([
+                    (HistoryValue::State, night_light::RegValueId::State),
+                    (HistoryValue::Settings, night_light::RegValueId::Settings),
+                ])
+            {
+                if value.is_some_and(|value| value != history_value) {
+                    continue;
+                }
+
+                entries.extend(
+                    journal
+                        .query(Some(reg_value_path), None)?
+                        .into_iter()
+                        .map(|entry| (value_id, entry)),
+                );
+            }
+            entries.sort_by_key(|(_, entry)| entry.epoch_millis);
+            if let Some(last) = last {
+                entries.drain(..entries.len().saturating_sub(last));
+            }
+
+            for (value_id, entry) in entries {
+                if json {
+                    println!("{}", serde_json::to_string(&entry)?);
+                    continue;
+                }
+
+                let time = chrono::DateTime::<chrono::Local>::from(entry.time());
+                println!(
+                    "{}",
+                    format!("{} - {value_id:?}", time.format("%Y-%m-%d %X")).to_uppercase()
+                );
+                if let Some(summary) = &entry.summary {
+                    println!("{summary}");
+                }
+                match &entry.new_bytes {
+                    Some(new_bytes) => println!(
+                        "(diff against previous: {})",
+                        HexBytes::new(new_bytes)
+                            .diff_against(entry.old_bytes.as_deref().unwrap_or_default())
+                    ),
+                    None => println!("{}", "(bytes unknown)".dimmed()),
+                }
+                println!();
+            }
+        }
+
+        Some(Subcmd::Init {
+            init_duration_arg: InitDurationArg { duration },
+            wait_after,
+        }) => {
+            init_night_light(init_duration(duration), wait_after, lenient)?;
+        }
+
+        Some(Subcmd::KeepIniting {
+            stop,
+            delay,
+            tray,
+            hotkey,
+            cycle_hotkey,
+            ramp,
+            ramp_minutes,
+            ramp_coordinates,
+            battery_warmth,
+            status,
+            no_replace,
+            init_duration_arg: InitDurationArg { duration },
+            ..
+        }) => {
+            if status {
+                print_keep_initing_health(json)?;
+            } else if stop {
+                stop_keep_initing()?;
+            } else {
+                let _instance_mutex = acquire_keep_initing_mutex(!no_replace)?;
+                let duration = init_duration(duration);
+                init_night_light(duration, false, lenient)?;
+
+                //. Remove console, if this is the only process using it.
+                //. For cases where the process was started from a shortcut file or so and the console window shouldn't continue to linger around.
+                if !has_shared_console()? {
+                    unsafe { FreeConsole()? };
+                }
+
+                keep_initing(
+                    delay
+                        .or(config.night_light.keep_initing_delay)
+                        .unwrap_or(DEFAULT_KEEP_INITING_DELAY),
+                    duration,
+                    lenient,
+                    Interaction {
+                        tray,
+                        toggle_hotkey: hotkey,
+                        cycle_hotkey,
+                        gamma: config.night_light.gamma,
+                        ramp: ramp.map(|(start_percentage, end_percentage)| RampMode {
+                            ramp: WarmthRamp {
+                                start_warmth: start_percentage as f32 / 100.0,
+                                end_warmth: end_percentage as f32 / 100.0,
+                                duration: Duration::from_secs(ramp_minutes as u64 * 60),
+                                gamma: config.night_light.gamma.unwrap_or(1.0),
+                            },
+                            coordinates: ramp_coordinates,
+                        }),
+                        battery_night_color_temp: battery_warmth.map(|warmth| match warmth {
+                            WarmthArg::Factor(warmth) => NightLight::night_color_temp_from_warmth(
+                                warmth.powf(1.0 / config.night_light.gamma.unwrap_or(1.0)),
+                            ),
+                            WarmthArg::Named(temp) => temp,
+                        }),
+                        ctl_pipe: true,
+                    },
+                )?;
+            }
+        }
+
+        Some(Subcmd::Ctl { request }) => ctl(
+            &daemon::keep_initing_pipe_name()?,
+            "no instance running; start one with 'night-light keep-initing'",
+            request,
+        )?,
+
+        Some(Subcmd::Cycle { gamma }) => {
+            const NUM_CYCLES: usize = 6;
+            const FRAME_DURATION: Duration = Duration::from_millis(54);
+            const NUM_STEPS_PER_HALF_CYCLE: usize = 17;
+            const STEP_SIZE: f32 = 1.0 / NUM_STEPS_PER_HALF_CYCLE as f32;
+            let inverse_gamma = 1.0 / gamma.or(config.night_light.gamma).unwrap_or(1.0);
+
+            // Make iterator.
+            let cold_to_warm_iter =
+                (0..=NUM_STEPS_PER_HALF_CYCLE).map(|i| (i as f32 * STEP_SIZE).powf(inverse_gamma));
+            let warm_to_cold_iter = cold_to_warm_iter.clone().rev();
+            let mut cycling_iter =
+                iter::repeat(cold_to_warm_iter.skip(1).chain(warm_to_cold_iter.skip(1)))
+                    .take(NUM_CYCLES)
+                    .flatten();
+
+            // Cycle.
+            let orig_night_light = NightLight::from_reg()?;
+
+            println!("Cycling Night Light for a couple of seconds...");
+
+            let must_abort = Arc::new(Mutex::new(false));
+            let moved_must_abort = must_abort.clone();
+            ctrlc::set_handler(move || {
+                *moved_must_abort.lock().unwrap() = true;
+            })?;
+
+            //. The guard restores the color temperature; if cycling fails, the configuration from before is applied again.
+            let cycle_result = (|| -> anyhow::Result<()> {
+                let mut guard = NightPreviewGuard::activate(Strictness::Strict, FRAME_DURATION)?;
+                guard.restore_night_color_temp_on_release(orig_night_light.night_color_temp());
+
+                let cycle_result = cycling_iter.try_for_each(|warmth| {
+                    if *must_abort.lock().unwrap() {
+                        Ok(())
+                    } else {
+                        guard.set_night_color_temp(Some(NightLight::night_color_temp_from_warmth(
+                            warmth,
+                        )))
+                    }
+                });
+
+                // Restore previous configuration.
+                guard.release()?;
+
+                Ok(cycle_result?)
+            })();
+
+            if let Err(error) = cycle_result {
+                if let Err(restore_error) =
+                    NightLight::apply(&ChangeSet::from_night_light(&orig_night_light))
+                {
+                    return Err(anyhow!(
+                        "{error}; restoring the configuration failed too: {restore_error}"
+                    ));
+                }
+                return Err(error);
+            }
+        }
+
+        Some(Subcmd::Fade {
+            temp_args,
+            millis,
+            steps,
+        }) => {
+            if temp_args.kelvin.is_none()
+                && temp_args.warmth.is_none()
+                && temp_args.increase.is_none()
+                && temp_args.decrease.is_none()
+                && !temp_args.default_temp
+            {
+                return Err(anyhow!("the target color temperature must be specified"));
+            }
+
+            let strictness = Strictness::from_lenient_bool(lenient);
+            let mut target = NightLight::from_reg_with_strictness(strictness)?;
+            apply_temp_args(&mut target, &temp_args, config);
+
+            let stop_signal = StopSignal::new();
+            let moved_stop_signal = stop_signal.clone();
+            ctrlc::set_handler(move || moved_stop_signal.stop())?;
+
+            NightLight::fade_to_temp_with_strictness(
+                target.night_color_temp(),
+                Duration::from_millis(millis as _),
+                steps,
+                strictness,
+                Some(&stop_signal),
+            )?;
+        }
+
+        Some(Subcmd::Enforce {
+            on_off_args,
+            temp_args,
+            debounce,
+        }) => {
+            let strictness = Strictness::from_lenient_bool(lenient);
+            let mut night_light = NightLight::from_reg_with_strictness(strictness)?;
+            let mut enforced_config = ChangeSet::default();
+
+            if let Some(on_off_args) = on_off_args {
+                enforced_config.active = Some(if on_off_args.toggle {
+                    !night_light.active()
+                } else {
+                    on_off_args.on
+                });
+            }
+
+            if let Some(temp_args) = temp_args {
+                apply_temp_args(&mut night_light, &temp_args, config);
+                enforced_config.night_color_temp = Some(night_light.night_color_temp());
+            }
+
+            if enforced_config.is_empty() {
+                enforced_config = ChangeSet::from_night_light(&night_light);
+            }
+
+            if !json {
+                println!("Enforcing the configuration. Press Ctrl+C to abort.");
+                println!();
+            }
+
+            let (stop_sender, stop_receiver) = oneshot::channel::<()>();
+            let mut stop_sender = Some(stop_sender);
+            ctrlc::set_handler(move || {
+                if let Some(stop_sender) = stop_sender.take() {
+                    stop_sender.send(()).unwrap();
+                }
+            })?;
+
+            NightLight::enforce(
+                &enforced_config,
+                Duration::from_millis(debounce as _),
+                Some(stop_receiver),
+                strictness,
+                |change| -> Option<Result<(), night_light::Error>> {
+                    if json {
+                        let mut wrote_back = serde_json::Map::new();
+                        if let Some(active) = change.active {
+                            wrote_back.insert("active".into(), active.into());
+                        }
+                        if let Some(night_color_temp) = change.night_color_temp {
+                            wrote_back.insert("nightColorTemp".into(), night_color_temp.into());
+                        }
+                        if let Some(schedule_active) = change.schedule_active {
+                            wrote_back.insert("scheduleActive".into(), schedule_active.into());
+                        }
+                        if let Some(schedule_type) = change.schedule_type {
+                            wrote_back.insert(
+                                "scheduleType".into(),
+                                serde_json::to_value(schedule_type)
+                                    .expect("`ScheduleType` should be serializable"),
+                            );
+                        }
+                        if let Some(scheduled_night) = change.scheduled_night {
+                            wrote_back.insert(
+                                "scheduledNight".into(),
+                                scheduled_night.format(false).into(),
+                            );
+                        }
+
+                        println!(
+                            "{}",
+                            serde_json::json!({
+                                "time": chrono::Local::now()
+                                    .to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+                                "wroteBack": wrote_back,
+                            })
+                        );
+                    } else {
+                        println!(
+                            "{} wrote back {change:?}",
+                            chrono::Local::now().format("%X")
+                        );
+                    }
+
+                    None
+                },
+            )?;
+        }
+
+        // Subcommands that need a common parsed `NightLight`.
+        subcmd => {
+            //. Before loading, because the location lookup may take longer than the `NightLight` expiration timeout.
+            let sun_coordinates = match &subcmd {
+                Some(Subcmd::Schedule {
+                    schedule_args:
+                        ScheduleArgs {
+                            from_sun: true,
+                            coordinates,
+                            ..
+                        },
+                    ..
+                }) => Some(match coordinates {
+                    Some(coordinates) => *coordinates,
+                    None => Coordinates::from_location_service().map_err(|error| {
+                        anyhow!("couldn't determine the location ({error}); pass '--coordinates'")
+                    })?,
+                }),
+                _ => None,
+            };
+
+            let mut night_light =
+                NightLight::from_reg_with_strictness(Strictness::from_lenient_bool(lenient))?;
+            let undo_point = ChangeSet::from_night_light(&night_light);
+
+            night_light.set_uses_12_hour_clock(am_pm);
+
+            //. Changes that may combine the active-state with the schedule are written in steps.
+            let mut sequenced_change_set = None;
+            let temp_args = match &subcmd {
+                None => {
+                    if json {
+                        println!("{}", night_light.to_json());
+                    } else {
+                        println!("{night_light}");
+                        println!();
+                        println!("{}", "Pass '--help' to see available actions.".dimmed());
+                    }
+
+                    None
+                }
+
+                Some(Subcmd::Switch {
+                    on_off_args: RequiredOnOffArgs { toggle, on, .. },
+                    temp_args,
+                }) => {
+                    night_light.set_active(if *toggle { !night_light.active() } else { *on });
+                    Some(temp_args)
+                }
+
+                Some(Subcmd::Temp { temp_args }) => Some(temp_args),
+
+                Some(Subcmd::Preview {
+                    on_off_args: RequiredOnOffArgs { toggle, on, .. },
+                    temp_args,
+                }) => {
+                    night_light.set_night_preview_active(if *toggle {
+                        !night_light.night_preview_active()
+                    } else {
+                        *on
+                    });
+                    Some(temp_args)
+                }
+
+                Some(Subcmd::Schedule {
+                    schedule_args:
+                        ScheduleArgs {
+                            on_off_args,
+                            r#type,
+                            night,
+                            sunset_offset,
+                            sunrise_offset,
+                            temp_args,
+                            ..
+                        },
+                    ..
+                }) => {
+                    if let Some(on_off_args) = on_off_args {
+                        night_light.set_schedule_active(if on_off_args.toggle {
+                            !night_light.schedule_active()
+                        } else {
+                            on_off_args.on
+                        });
+                    }
+
+                    if let Some(r#type) = r#type {
+                        night_light.set_schedule_type(match r#type {
+                            cli::ScheduleType::Explicit => night_light::ScheduleType::Explicit,
+                            cli::ScheduleType::Sun => night_light::ScheduleType::SunsetToSunrise,
+                        });
+                    }
+
+                    if let Some(night) = night {
+                        night_light.set_scheduled_night(*night);
+                    }
+
+                    if let Some(coordinates) = sun_coordinates {
+                        night_light
+                            .set_scheduled_night_from_sun(
+                                coordinates,
+                                SunOffsets {
+                                    sunset: chrono::Duration::minutes(*sunset_offset as _),
+                                    sunrise: chrono::Duration::minutes(*sunrise_offset as _),
+                                },
+                            )
+                            .ok_or_else(|| {
+                                anyhow!("the sun doesn't rise and set at these coordinates today")
+                            })?;
+                    }
+
+                    temp_args.as_ref()
+                }
+
+                Some(Subcmd::Profile { name }) => {
+                    let change_set = config
+                        .profiles
+                        .get(name)
+                        .ok_or_else(|| anyhow!("no profile '{name}' in the configuration file"))?
+                        .clone();
+                    change_set.apply_to(&mut night_light);
+                    sequenced_change_set = Some(change_set);
+                    None
+                }
+
+                Some(Subcmd::Preset {
+                    action: PresetAction::Apply { name },
+                }) => {
+                    let change_set = NightLight::preset(name)?;
+                    change_set.apply_to(&mut night_light);
+                    sequenced_change_set = Some(change_set);
+                    None
+                }
+
+                _ => unreachable!(),
+            };
+
+            if let Some(temp_args) = temp_args {
+                apply_temp_args(&mut night_light, temp_args, config);
+            }
+
+            if !dry_run {
+                write_with_undo_point(
+                    night_light,
+                    undo_point,
+                    sequenced_change_set.as_ref(),
+                    verify_delay,
+                    lenient,
+                )?;
+            } else if subcmd.is_some() {
+                if json {
+                    println!("{}", night_light.to_json());
+                } else {
+                    println!("{night_light}");
+                    println!();
+
+                    match night_light.plan_write() {
+                        Ok(plan) if plan.is_empty() => println!("Nothing would be written."),
+                        Ok(plan) => println!("Bytes that would be written:\n{plan}"),
+                        //. Steps after the first depend on Windows' reaction.
+                        Err(_)
+                            if sequenced_change_set
+                                .as_ref()
+                                .is_some_and(|change_set| change_set.batches().len() > 1) =>
+                        {
+                            println!("Would be written in steps, so the bytes can't be shown.")
+                        }
+                        Err(error) => return Err(error.into()),
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn apply_temp_args(night_light: &mut NightLight, temp_args: &TempArgs, config: &Config) {
+    let TempArgs {
+        kelvin,
+        warmth,
+        increase,
+        decrease,
+        default_temp,
+        gamma,
+    } = temp_args;
+    let gamma = gamma.or(config.night_light.gamma).unwrap_or(1.0);
+
+    if *default_temp {
+        night_light.set_night_color_temp(None);
+    } else if let Some(kelvin) = kelvin {
+        night_light.set_night_color_temp(Some(*kelvin));
+    } else if let Some(warmth) = warmth {
+        match warmth {
+            WarmthArg::Factor(warmth) => night_light.set_warmth(Some(warmth.powf(1.0 / gamma))),
+            WarmthArg::Named(temp) => night_light.set_night_color_temp(Some(*temp)),
+        }
+    } else if let Some(delta) = increase
+        .map(|percentage| percentage as f32)
+        .or(decrease.map(|percentage| -(percentage as f32)))
+    {
+        night_light.adjust_warmth_by_with_gamma(delta / 100.0, gamma);
+    }
+}
+
+pub fn keep_initing(
+    delay_millis: u16,
+    duration_millis: Option<u16>,
+    lenient: bool,
+    interaction: Interaction,
+) -> anyhow::Result<()> {
+    //! Performs the 'init' command whenever the first screen was turned back on, the machine resumed from sleep or the session was unlocked or connected to, until [`stop_keep_initing()`] is called, possibly from another process, Ctrl+C is pressed or quitting is chosen in the tray menu. Blocks the thread, which mustn't have a window message loop yet.
+
+    let stop_msg = keep_initing_stop_msg()?;
+    let gamma = interaction.gamma.unwrap_or(1.0);
+    let mut h_power_notify = None;
+    let mut h_power_source_notify = None;
+    let mut session_notify_registration = None;
+    let mut ac_night_color_temp = None; // While the battery color temperature is in effect.
+    let mut tray = None;
+    let mut hotkey_registrations = Vec::new();
+    let mut last_monitor_state = PowerMonitorOn;
+    let startup_instant = Instant::now(); // To ignore first status message.
+    let health = Arc::new(Mutex::new(InstanceHealth {
+        uptime_secs: 0,
+        //. The caller inits initially.
+        last_init_epoch_millis: Some(epoch_millis_now()),
+        last_error: None,
+        monitor_state: MonitorState::On,
+    }));
+
+    try_then_favor_app_error(|| -> anyhow::Result<()> {
+        let window_class = WindowClass::new(|hwnd, msg_id, wparam, lparam| {
+            if let Some(result) = tray
+                .as_mut()
+                .and_then(|tray: &mut Tray| tray.handle_msg(hwnd, msg_id, wparam, lparam))
+            {
+                return Some(result);
+            }
+
+            match msg_id {
+                WM_CREATE => {
+                    let success = try_or_quit_now(|| -> anyhow::Result<_> {
+                        ctrlc::set_handler(move || {
+                            let _ = unsafe { PostMessageW(hwnd, stop_msg, WPARAM(0), LPARAM(0)) };
+                        })?;
+
+                        h_power_notify = Some(
+                            ResGuard::with_acq_and_unregister_power_setting_notification(
+                                || unsafe {
+                                    RegisterPowerSettingNotification(
+                                        HANDLE(hwnd.0),
+                                        //TODO: Use `GUID_SESSION_DISPLAY_STATUS` instead? See <https://learn.microsoft.com/en-us/windows/win32/power/power-setting-guids#guid_session_display_status>. (Mind other occurrences besides this one.)
+                                        &GUID_CONSOLE_DISPLAY_STATE,
+                                        //TODO: See <https://github.com/microsoft/win32metadata/issues/1779>.
+                                        DEVICE_NOTIFY_WINDOW_HANDLE.0,
+                                    )
+                                },
+                            )?,
+                        );
+
+                        //. The color temperature is also lost on locking the session and when connecting to it remotely.
+                        session_notify_registration = Some(ResGuard::with_acquisition(
+                            || unsafe {
+                                WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION)
+                                    .map(|_| hwnd)
+                            },
+                            |hwnd| {
+                                let _ = unsafe { WTSUnRegisterSessionNotification(hwnd) };
+                            },
+                        )?);
+
+                        //. Windows immediately sends the current power source.
+                        if interaction.battery_night_color_temp.is_some() {
+                            h_power_source_notify = Some(
+                                ResGuard::with_acq_and_unregister_power_setting_notification(
+                                    || unsafe {
+                                        RegisterPowerSettingNotification(
+                                            HANDLE(hwnd.0),
+                                            &GUID_ACDC_POWER_SOURCE,
+                                            DEVICE_NOTIFY_WINDOW_HANDLE.0,
+                                        )
+                                    },
+                                )?,
+                            );
+                        }
+
+                        if interaction.tray {
+                            tray = Some(Tray::new(hwnd, stop_msg, lenient, gamma)?);
+                        }
+
+                        for (id, hotkey) in [
+                            (TOGGLE_HOTKEY_ID, interaction.toggle_hotkey),
+                            (CYCLE_HOTKEY_ID, interaction.cycle_hotkey),
+                        ] {
+                            if let Some(hotkey) = hotkey {
+                                hotkey_registrations.push(
+                                    HotkeyRegistration::new(hwnd, id, hotkey).map_err(|error| {
+                                        anyhow!("couldn't register hotkey {hotkey}: {error}")
+                                    })?,
+                                );
+                            }
+                        }
+
+                        if let Some(ramp_mode) = &interaction.ramp {
+                            unsafe {
+                                SetTimer(hwnd, RAMP_TIMER_ID, RAMP_INTERVAL.as_millis() as _, None)
+                            }
+                            .nonzero_or_win32_err()?;
+                            if let Err(error) = follow_ramp(ramp_mode, lenient) {
+                                record_error(&health, "following warmth ramp", &error);
+                            }
+                        }
+
+                        Ok(())
+                    })
+                    .is_some();
+
+                    Some(LRESULT(if success { 0 } else { -1 }))
+                }
+
+                WM_POWERBROADCAST => {
+                    // Author's experience on Windows 10 in Dec. 2023: With a multi-monitor setup, `GUID_CONSOLE_DISPLAY_STATE` isn't sent when just one monitor changes its on-off state, while others stay active, but only when all monitors at once or the last active monitor is turned on/off. When turning just one monitor on/off, while others stay active, there are various other messages of unclear relevance, though, like, e.g., `WM_DEVICECHANGE`, `WM_DISPLAYCHANGE` and `WM_SETTINGCHANGE`. All of this wasn't a problem though, because the OS only failed to reapply the color temperature when a single active monitor was turned off and then turned on again.
+
+                    match unsafe { translate_power_broadcast_msg(wparam, &lparam) } {
+                        PowerBroadcastMsg::PowerSettingChange { setting } => {
+                            if setting.PowerSetting == GUID_CONSOLE_DISPLAY_STATE {
+                                try_or_quit_now(|| -> anyhow::Result<_> {
+                                    let new_monitor_state =
+                                        unsafe { *setting.cast_data::<MONITOR_DISPLAY_STATE>()? };
+
+                                    if startup_instant.elapsed().as_millis() > 200
+                                        && new_monitor_state != last_monitor_state
+                                        && new_monitor_state == PowerMonitorOn
+                                    {
+                                        // Monitor just turned on.
+                                        log_info!("screen turned on; initing Night Light");
+                                        thread::sleep(Duration::from_millis(delay_millis as _));
+                                        if let Err(error) =
+                                            init_recording_health(&health, duration_millis, lenient)
+                                        {
+                                            log_error!("initing Night Light failed: {error:?}");
+                                        }
+                                    }
+
+                                    last_monitor_state = new_monitor_state;
+                                    health.lock().unwrap().monitor_state =
+                                        if new_monitor_state == PowerMonitorOff {
+                                            MonitorState::Off
+                                        } else if new_monitor_state == PowerMonitorDim {
+                                            MonitorState::Dimmed
+                                        } else {
+                                            MonitorState::On
+                                        };
+
+                                    Ok(LRESULT(1))
+                                })
+                            } else if setting.PowerSetting == GUID_ACDC_POWER_SOURCE {
+                                let battery_night_color_temp =
+                                    interaction.battery_night_color_temp?;
+                                try_or_quit_now(|| -> anyhow::Result<_> {
+                                    let power_condition =
+                                        unsafe { *setting.cast_data::<SYSTEM_POWER_CONDITION>()? };
+                                    let is_on_battery = power_condition != PoAc;
+
+                                    log_info!(
+                                        "running on {} power",
+                                        if is_on_battery { "battery" } else { "AC" }
+                                    );
+                                    if let Err(error) = follow_power_source(
+                                        is_on_battery,
+                                        battery_night_color_temp,
+                                        &mut ac_night_color_temp,
+                                        lenient,
+                                    ) {
+                                        record_error(&health, "following power source", &error);
+                                    }
+
+                                    //. The ramp takes over again from the restored color temperature.
+                                    if !is_on_battery {
+                                        if let Some(ramp_mode) = &interaction.ramp {
+                                            if let Err(error) = follow_ramp(ramp_mode, lenient) {
+                                                record_error(
+                                                    &health,
+                                                    "following warmth ramp",
+                                                    &error,
+                                                );
+                                            }
+                                        }
+                                    }
+
+                                    Ok(LRESULT(1))
+                                })
+                            } else {
+                                None
+                            }
+                        }
+                        //. Both are sent when the user wakes the machine. Reiniting twice is harmless.
+                        PowerBroadcastMsg::Other {
+                            event: PBT_APMRESUMEAUTOMATIC | PBT_APMRESUMESUSPEND,
+                        } => {
+                            log_info!("resumed from sleep; initing Night Light");
+                            thread::sleep(Duration::from_millis(delay_millis as _));
+                            if let Err(error) =
+                                init_recording_health(&health, duration_millis, lenient)
+                            {
+                                log_error!("initing Night Light failed: {error:?}");
+                            }
+
+                            Some(LRESULT(1))
+                        }
+                        _ => None,
+                    }
+                }
+
+                WM_WTSSESSION_CHANGE => {
+                    let event = match wparam.0 as u32 {
+                        WTS_SESSION_UNLOCK => "session unlocked",
+                        WTS_CONSOLE_CONNECT => "session connected to console",
+                        WTS_REMOTE_CONNECT => "session connected remotely",
+                        _ => return Some(LRESULT(0)),
+                    };
+
+                    log_info!("{event}; initing Night Light");
+                    thread::sleep(Duration::from_millis(delay_millis as _));
+                    if let Err(error) = init_recording_health(&health, duration_millis, lenient) {
+                        log_error!("initing Night Light failed: {error:?}");
+                    }
+
+                    Some(LRESULT(0))
+                }
+
+                WM_TIMER if wparam.0 == RAMP_TIMER_ID => {
+                    //. The battery color temperature takes precedence.
+                    if let (Some(ramp_mode), None) = (&interaction.ramp, ac_night_color_temp) {
+                        if let Err(error) = follow_ramp(ramp_mode, lenient) {
+                            record_error(&health, "following warmth ramp", &error);
+                        }
+                    }
+
+                    Some(LRESULT(0))
+                }
+
+                WM_HOTKEY => {
+                    let result = match wparam.0 as i32 {
+                        TOGGLE_HOTKEY_ID => toggle_night_light(lenient),
+                        CYCLE_HOTKEY_ID => cycle_warmth(gamma, lenient),
+                        _ => Ok(()),
+                    };
+
+                    if let Err(error) = result {
+                        record_error(&health, "hotkey action", &error);
+                    }
+
+                    Some(LRESULT(0))
+                }
+
+                id if id == stop_msg => {
+                    let _ = unsafe { DestroyWindow(hwnd) };
+                    Some(LRESULT(0))
+                }
+
+                WM_DESTROY => {
+                    //. Otherwise, the battery color temperature would remain after quitting.
+                    if let Some(battery_night_color_temp) = interaction.battery_night_color_temp {
+                        if let Err(error) = restore_ac_night_color_temp(
+                            battery_night_color_temp,
+                            &mut ac_night_color_temp,
+                            lenient,
+                        ) {
+                            log_error!("restoring AC color temperature failed: {error:?}");
+                        }
+                    }
+                    if interaction.ramp.is_some() {
+                        let _ = unsafe { KillTimer(hwnd, RAMP_TIMER_ID) };
+                    }
+                    hotkey_registrations.clear();
+                    drop(tray.take());
+                    drop(h_power_notify.take());
+                    drop(h_power_source_notify.take());
+                    drop(session_notify_registration.take());
+                    unsafe { PostQuitMessage(0) };
+                    Some(LRESULT(0))
+                }
+
+                _ => None,
+            }
+        })?;
+
+        let window = Window::new_invisible(&window_class)?;
+
+        let pipe_stopped = Arc::new(AtomicBool::new(false));
+        let pipe_thread = interaction.ctl_pipe.then(|| {
+            let hwnd = window.hwnd();
+            let pipe_stopped = pipe_stopped.clone();
+            let health = health.clone();
+            thread::spawn(move || {
+                if let Err(error) = serve_ctl_pipe(
+                    hwnd,
+                    stop_msg,
+                    &pipe_stopped,
+                    (&health, startup_instant),
+                    duration_millis,
+                    lenient,
+                ) {
+                    log_error!("serving control pipe failed: {error}");
+                }
+            })
+        });
+
+        msg_loop::run()?;
+
+        if let Some(pipe_thread) = pipe_thread {
+            //. Only when the server still runs, so that a new instance's server isn't stopped.
+            if !pipe_stopped.load(Ordering::SeqCst) && !pipe_thread.is_finished() {
+                if let Ok(pipe_name) = daemon::keep_initing_pipe_name() {
+                    let _ = daemon::send_request_to(&pipe_name, &Request::Stop);
+                }
+            }
+            pipe_thread
+                .join()
+                .map_err(|_| anyhow!("control pipe thread panicked"))?;
+        }
+
+        Ok(())
+    })
+    .map_err(|e| anyhow!(e))?;
+
+    Ok(())
+}
+
+fn serve_ctl_pipe(
+    hwnd: HWND,
+    stop_msg: u32,
+    pipe_stopped: &AtomicBool,
+    (health, startup_instant): (&Mutex<InstanceHealth>, Instant),
+    duration_millis: Option<u16>,
+    lenient: bool,
+) -> io::Result<()> {
+    //! Serves the requests for [`keep_initing()`] until a [`Request::Stop`], which also makes the window end. Creating the pipe is retried, since a replaced instance may still be ending.
+
+    let pipe_name = daemon::keep_initing_pipe_name()?;
+    let mut retry_count = 0;
+    loop {
+        let result = daemon::serve_on(&pipe_name, |request| {
+            let result = match request {
+                Request::Status => read_status(lenient),
+                Request::SetNightLight(change) => set_night_light(&change, lenient),
+                Request::Toggle => toggle_night_light(lenient).and_then(|_| read_status(lenient)),
+                Request::Reinit => init_recording_health(health, duration_millis, lenient)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| read_status(lenient)),
+                Request::Health => {
+                    let mut health = health.lock().unwrap().clone();
+                    health.uptime_secs = startup_instant.elapsed().as_secs();
+                    return Response::Health { health };
+                }
+                Request::Stop => {
+                    pipe_stopped.store(true, Ordering::SeqCst);
+                    let _ = unsafe { PostMessageW(hwnd, stop_msg, WPARAM(0), LPARAM(0)) };
+                    return Response::Done;
+                }
+            };
+
+            match result {
+                Ok(night_light) => Response::Status { night_light },
+                Err(error) => {
+                    log_error!("handling request failed: {error}");
+                    Response::Error {
+                        message: error.to_string(),
+                    }
+                }
+            }
+        });
+
+        match result {
+            Err(error)
+                if error.raw_os_error() == Some(ERROR_ACCESS_DENIED.0 as _)
+                    && retry_count < PIPE_CREATION_RETRY_COUNT =>
+            {
+                retry_count += 1;
+                thread::sleep(Duration::from_millis(50));
+            }
+            result => return result,
+        }
+    }
+}
+
+fn print_keep_initing_health(json: bool) -> anyhow::Result<()> {
+    match daemon::send_request_to(&daemon::keep_initing_pipe_name()?, &Request::Health) {
+        Ok(Response::Health { health }) if json => println!("{}", serde_json::to_string(&health)?),
+        Ok(Response::Health { health }) => println!("{health}"),
+        Ok(Response::Error { message }) => return Err(anyhow!("request failed: {message}")),
+        Ok(response) => return Err(anyhow!("unexpected response: {response:?}")),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return Err(anyhow!("no instance running"))
+        }
+        Err(error) => return Err(error.into()),
+    }
+
+    Ok(())
+}
+
+fn init_recording_health(
+    health: &Mutex<InstanceHealth>,
+    duration_millis: Option<u16>,
+    lenient: bool,
+) -> Result<(), night_light::Error> {
+    //! Inits Night Light like [`init_night_light()`] and keeps the outcome for [`Request::Health`].
+
+    let result = init_night_light(duration_millis, false, lenient);
+    let mut health = health.lock().unwrap();
+    match &result {
+        Ok(()) => health.last_init_epoch_millis = Some(epoch_millis_now()),
+        Err(error) => health.last_error = Some(format!("initing Night Light failed: {error}")),
+    }
+
+    result
+}
+
+fn record_error(health: &Mutex<InstanceHealth>, action: &str, error: &anyhow::Error) {
+    //! Logs the error and keeps it for [`Request::Health`].
+
+    log_error!("{action} failed: {error:?}");
+    health.lock().unwrap().last_error = Some(format!("{action} failed: {error}"));
+}
+
+fn epoch_millis_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as _
+}
+
+pub fn ctl(pipe_name: &str, not_running_msg: &str, ctl_request: CtlRequest) -> anyhow::Result<()> {
+    //! Sends the request to the server of the pipe and prints the configuration it responds with as JSON. Shared by `night-light ctl` and `sem-reg ctl`.
+
+    let request = match ctl_request {
+        CtlRequest::Status => Request::Status,
+        CtlRequest::Set {
+            active,
+            schedule_active,
+            kelvin,
+            warmth,
+            default_temp,
+        } => Request::SetNightLight(ChangeSet {
+            active,
+            night_color_temp: if default_temp {
+                Some(None)
+            } else {
+                kelvin
+                    .or_else(|| warmth.map(NightLight::night_color_temp_from_warmth))
+                    .map(Some)
+            },
+            schedule_active,
+            ..Default::default()
+        }),
+        CtlRequest::Toggle => Request::Toggle,
+        CtlRequest::Reinit => Request::Reinit,
+        CtlRequest::Stop => Request::Stop,
+    };
+
+    match daemon::send_request_to(pipe_name, &request) {
+        Ok(Response::Status { night_light }) => {
+            println!("{}", serde_json::to_string_pretty(&night_light)?)
+        }
+        Ok(Response::Done) => {}
+        Ok(Response::Health { health }) => {
+            println!("{}", serde_json::to_string_pretty(&health)?)
+        }
+        Ok(Response::Error { message }) => return Err(anyhow!("request failed: {message}")),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return Err(anyhow!("{not_running_msg}"))
+        }
+        Err(error) => return Err(error.into()),
+    }
+
+    Ok(())
+}
+
+fn open_journal() -> anyhow::Result<ChangeJournal> {
+    let file_path = Config::night_light_journal_file_path()
+        .ok_or_else(|| anyhow!("'APPDATA' environment variable not set"))?;
+    Ok(ChangeJournal::open(file_path, Retention::default())?)
+}
+
+fn write_with_undo_point(
+    night_light: NightLight,
+    undo_point: ChangeSet,
+    change_set: Option<&ChangeSet>,
+    verify_delay: Option<Duration>,
+    lenient: bool,
+) -> anyhow::Result<()> {
+    //! Writes the instance or, if given, applies the change set, whose changes the instance must already contain. If the configuration changed, saves the previous one for `undo`. Failing to save it doesn't prevent the change.
+    //!
+    //! With a verification delay, fails if Windows reverted the written values. (Applying a change set verifies by itself.)
+
+    if ChangeSet::from_night_light(&night_light) != undo_point {
+        if let Err(error) = NightLight::push_undo_point(undo_point) {
+            log_warn!("couldn't save undo point: {error}");
+        }
+    }
+
+    match change_set {
+        Some(change_set) => {
+            let report = NightLight::apply_with_strictness(
+                change_set,
+                Strictness::from_lenient_bool(lenient),
+            )?;
+            if !report.succeeded() {
+                log_warn!(
+                    "changes not in effect after applying: {:?}",
+                    report.deviations
+                );
+            }
+        }
+        None => match verify_delay {
+            Some(verify_delay) => match night_light.write_to_reg_verified(verify_delay)? {
+                WriteOutcome::Accepted => {}
+                WriteOutcome::Reverted => {
+                    return Err(anyhow!("Windows reverted the change; try again"))
+                }
+                WriteOutcome::Superseded => {
+                    log_warn!("the change was superseded by a newer one before verifying")
+                }
+            },
+            None => night_light.write_to_reg()?,
+        },
+    }
+
+    Ok(())
+}
+
+pub fn read_status(lenient: bool) -> anyhow::Result<serde_json::Value> {
+    //! Returns the Night Light configuration in the JSON format of the `--json` flag.
+
+    let night_light = NightLight::from_reg_with_strictness(Strictness::from_lenient_bool(lenient))?;
+    Ok(serde_json::from_str(&night_light.to_json()).expect("`NightLight` JSON should be valid"))
+}
+
+pub fn set_night_light(change_set: &ChangeSet, lenient: bool) -> anyhow::Result<serde_json::Value> {
+    let mut night_light =
+        NightLight::from_reg_with_strictness(Strictness::from_lenient_bool(lenient))?;
+    let undo_point = ChangeSet::from_night_light(&night_light);
+    change_set.apply_to(&mut night_light);
+    write_with_undo_point(night_light, undo_point, Some(change_set), None, lenient)?;
+
+    read_status(lenient)
+}
+
+pub fn stop_keep_initing() -> anyhow::Result<()> {
+    //! Stops the current user's running instances of [`keep_initing()`] in all processes of the session and, via the control pipe, in another session. Instances of other users, e.g., started with `runas`, keep running.
+
+    let stop_msg = keep_initing_stop_msg()?;
+    unsafe { SendNotifyMessageW(HWND_BROADCAST, stop_msg, WPARAM(0), LPARAM(0))? };
+    //. Fails without an instance serving the pipe.
+    let _ = daemon::send_request_to(&daemon::keep_initing_pipe_name()?, &Request::Stop);
+    Ok(())
+}
+
+pub fn acquire_keep_initing_mutex(replaces: bool) -> anyhow::Result<InstanceMutex> {
+    //! Makes sure that there's only one instance of [`keep_initing()`] of the current user, even across sessions. When replacing, a running instance is stopped first; otherwise, it's an error if one runs. The returned mutex must be held on the current thread as long as the instance runs.
+
+    let name = format!(r"Global\sem-reg-keep-initing-{}", current_user_sid()?);
+    if let Some(mutex) = InstanceMutex::try_acquire(&name, Duration::ZERO)? {
+        return Ok(mutex);
+    }
+
+    if !replaces {
+        return Err(anyhow!(
+            "an instance is already running; omit '--no-replace' to replace it"
+        ));
+    }
+
+    log_info!("replacing running instance");
+    stop_keep_initing()?;
+    InstanceMutex::try_acquire(&name, REPLACE_TIMEOUT)?
+        .ok_or_else(|| anyhow!("the running instance didn't end in time"))
+}
+
+fn keep_initing_stop_msg() -> windows::core::Result<u32> {
+    //! Per user, since windows of other users' processes on the same desktop also receive broadcasts.
+
+    let name = format!(
+        "{{5dbd5965-0cd4-4fa5-8453-41e3871fd168}}-{}",
+        current_user_sid()?
+    );
+    unsafe { RegisterWindowMessageW(&HSTRING::from(name)) }.nonzero_or_win32_err()
+}
+
+pub fn init_night_light(
+    duration_millis: Option<u16>,
+    wait_after: bool,
+    lenient: bool,
+) -> Result<(), night_light::Error> {
+    NightLight::init_with_strictness(
+        duration_millis
+            .map(|millis| Duration::from_millis(millis as _))
+            .unwrap_or(NightLight::REASONABLE_INIT_DELAY),
+        wait_after,
+        Strictness::from_lenient_bool(lenient),
+    )
+}
+
+pub fn toggle_night_light(lenient: bool) -> anyhow::Result<()> {
+    let mut night_light =
+        NightLight::from_reg_with_strictness(Strictness::from_lenient_bool(lenient))?;
+    let undo_point = ChangeSet::from_night_light(&night_light);
+    night_light.set_active(!night_light.active());
+    write_with_undo_point(night_light, undo_point, None, None, lenient)?;
+    Ok(())
+}
+
+pub fn set_warmth_step(percentage: u8, gamma: f32, lenient: bool) -> anyhow::Result<()> {
+    let mut night_light =
+        NightLight::from_reg_with_strictness(Strictness::from_lenient_bool(lenient))?;
+    let undo_point = ChangeSet::from_night_light(&night_light);
+    night_light.set_warmth(Some((percentage as f32 / 100.0).powf(1.0 / gamma)));
+    write_with_undo_point(night_light, undo_point, None, None, lenient)?;
+    Ok(())
+}
+
+fn cycle_warmth(gamma: f32, lenient: bool) -> anyhow::Result<()> {
+    //! Sets the next of the [`WARMTH_STEPS`] above the current warmth, starting over after the last one.
+
+    let current_percentage =
+        NightLight::from_reg_with_strictness(Strictness::from_lenient_bool(lenient))?
+            .warmth()
+            .map_or(0.0, |warmth| warmth.powf(gamma) * 100.0);
+
+    let next_percentage = WARMTH_STEPS
+        .into_iter()
+        .find(|percentage| *percentage as f32 > current_percentage + 0.5)
+        .unwrap_or(WARMTH_STEPS[0]);
+    set_warmth_step(next_percentage, gamma, lenient)
+}
+
+fn follow_ramp(ramp_mode: &RampMode, lenient: bool) -> anyhow::Result<()> {
+    //! Sets the color temperature the warmth ramp has now. Without a known night, e.g., with the schedule off, nothing is changed.
+
+    let strictness = Strictness::from_lenient_bool(lenient);
+    let night_light = NightLight::from_reg_with_strictness(strictness)?;
+    let night = match ramp_mode.coordinates {
+        Some(coordinates) => NightLight::night_from_sun(coordinates, SunOffsets::default()),
+        None => night_light.effective_scheduled_night(),
+    };
+    let Some(night) = night else {
+        return Ok(());
+    };
+
+    let night_color_temp = ramp_mode.ramp.night_color_temp_at(night, ClockTime::now());
+    if night_light.night_color_temp() != Some(night_color_temp) {
+        //. Only the color temperature, which can always be written on its own.
+        NightLight::apply_with_strictness(
+            &ChangeSet {
+                night_color_temp: Some(Some(night_color_temp)),
+                ..Default::default()
+            },
+            strictness,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn follow_power_source(
+    is_on_battery: bool,
+    battery_night_color_temp: u16,
+    ac_night_color_temp: &mut Option<Option<u16>>,
+    lenient: bool,
+) -> anyhow::Result<()> {
+    //! Sets the battery color temperature and remembers the previous one in `ac_night_color_temp`, or restores the remembered one when back on AC power.
+
+    if !is_on_battery {
+        return restore_ac_night_color_temp(battery_night_color_temp, ac_night_color_temp, lenient);
+    }
+    if ac_night_color_temp.is_some() {
+        return Ok(());
+    }
+
+    let strictness = Strictness::from_lenient_bool(lenient);
+    let night_light = NightLight::from_reg_with_strictness(strictness)?;
+    *ac_night_color_temp = Some(night_light.night_color_temp());
+
+    NightLight::apply_with_strictness(
+        &ChangeSet {
+            night_color_temp: Some(Some(battery_night_color_temp)),
+            ..Default::default()
+        },
+        strictness,
+    )?;
+
+    Ok(())
+}
+
+fn restore_ac_night_color_temp(
+    battery_night_color_temp: u16,
+    ac_night_color_temp: &mut Option<Option<u16>>,
+    lenient: bool,
+) -> anyhow::Result<()> {
+    //! Restores the color temperature remembered by [`follow_power_source()`], if any. If it isn't the battery color temperature anymore, the user changed it while on battery power, and it's kept.
+
+    let Some(night_color_temp) = ac_night_color_temp.take() else {
+        return Ok(());
+    };
+
+    let mut night_light =
+        NightLight::from_reg_with_strictness(Strictness::from_lenient_bool(lenient))?;
+    if night_light.night_color_temp() != Some(battery_night_color_temp) {
+        log_info!("keeping color temperature changed while on battery power");
+        return Ok(());
+    }
+
+    night_light.set_night_color_temp(night_color_temp);
+    night_light.write_to_reg()?;
+
+    Ok(())
+}
+
+fn has_shared_console() -> windows::core::Result<bool> {
+    //! Returns whether the current process shares the console with other processes - e.g., because it was spawned in a terminal in a non-detaching way.
+
+    let mut process_ids = vec![0];
+    dual_call(FirstCallExpectation::Ok, |getting_len| unsafe {
+        if getting_len || process_ids.len() > 1 {
+            GetConsoleProcessList(&mut process_ids)
+                .nonzero_or_win32_err()
+                .map(|len| {
+                    if getting_len && len > 1 {
+                        process_ids.resize(len as _, 0);
+                    }
+                })
+        } else {
+            Ok(())
+        }
+    })?;
+
+    let current_id = unsafe { GetCurrentProcessId() };
+    Ok(process_ids.iter().any(|id| *id != current_id))
+}
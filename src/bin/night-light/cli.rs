@@ -1,24 +1,72 @@
-use std::str::FromStr;
+use std::{io, path::PathBuf, str::FromStr};
 
-use sem_reg::cloud_store::night_light::ClockTimeFrame;
+use super::error_output::ErrorFormat;
+use sem_reg::{
+    cloud_store::night_light::{ClockTimeFrame, NightLight},
+    config::LoggingConfig,
+    hotkey::Hotkey,
+    logging::{self, Level, LogConfig, LogFormat},
+    sun::Coordinates,
+};
 
 const DEFAULT_GAMMA: &str = "1.6";
 
-#[derive(clap::Parser, Debug)]
-#[command(name = env!("CARGO_BIN_NAME"), version)]
-pub struct Cli {
-    /// Show 12-hour instead of 24-hour clock times in most important places.
-    #[arg(short = 'm', long, visible_alias = "12")]
-    pub am_pm: bool,
-
+/// Flags shared by all settings' commands. They can be given before or after the subcommand.
+#[derive(clap::Args, Debug)]
+pub struct GlobalArgs {
     /// Be less strict when handling the registry values. Required when at least one of them doesn't exist. Generally to be avoided.
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub lenient: bool,
 
     /// Print current configuration as JSON for consumation by software.
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub json: bool,
 
+    /// Don't write to the registry, but print the configuration and the bytes that would be written.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// The minimum severity of log messages: 'error', 'warn', 'info' (default) or 'debug'.
+    #[arg(long, global = true)]
+    pub log_level: Option<Level>,
+
+    /// Write log messages to this file instead of stderr. The file is rotated when it grows beyond 1 MiB. Useful with commands that keep running in the background.
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// The format of log messages: 'text' (default) or 'json'.
+    #[arg(long, global = true)]
+    pub log_format: Option<LogFormat>,
+
+    /// The format of the error printed to stderr when the program fails. 'json' prints an object with a stable error code, category, message and remediation hint.
+    #[arg(long, value_enum, default_value_t, global = true)]
+    pub error_format: ErrorFormat,
+}
+
+impl GlobalArgs {
+    pub fn init_logging(&self, config: &LoggingConfig) -> Result<(), io::Error> {
+        //! Flags take precedence over the configuration file.
+
+        let default = LogConfig::default();
+        logging::init(LogConfig {
+            level: self.log_level.or(config.level).unwrap_or(default.level),
+            format: self.log_format.or(config.format).unwrap_or(default.format),
+            file_path: self.log_file.clone().or_else(|| config.file.clone()),
+            ..default
+        })
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct NightLightArgs {
+    /// Show 12-hour instead of 24-hour clock times in most important places.
+    #[arg(short = 'm', long, visible_alias = "12")]
+    pub am_pm: bool,
+
+    /// After changing the configuration, re-read the registry values after this many milliseconds, and fail, if Windows reverted them, so that scripts can retry.
+    #[arg(long, value_name = "MILLIS")]
+    pub verify: Option<u16>,
+
     #[command(subcommand)]
     pub subcmd: Option<Subcmd>,
 }
@@ -43,16 +91,56 @@ pub enum Subcmd {
 
     /// Keeps running and maintains the color temperature.
     ///
-    /// Performs the 'init' command initially and whenever the first screen was turned back on (i.e., after all have been turned off). If you find that, after turning the screen back on, the configured color temperature doesn't apply anymore, this command should correct that problem automatically in every case by running in the background. You can, e.g., create an autostart entry to run it.
+    /// Performs the 'init' command initially and whenever the first screen was turned back on (i.e., after all have been turned off), the machine resumed from sleep or hibernation, the session was unlocked or a remote desktop connected to it. If you find that, after turning the screen back on, the configured color temperature doesn't apply anymore, this command should correct that problem automatically in every case by running in the background. You can, e.g., create an autostart entry to run it.
     #[command(visible_alias = "keep")]
     KeepIniting {
         /// Simply stops a possibly running instance of this app that was also run with this command, and then ends.
         #[arg(short, long, conflicts_with_all = ["delay", "duration"])]
         stop: bool,
 
-        /// The number of milliseconds to delay the 'init' command after receiving the information that the screen was turned on. Too small values can prevent the command from working or make it unreliable. Omit the switch to use the default value.
-        #[arg(short = 'l', long, default_value = "100")]
-        delay: u16,
+        /// The number of milliseconds to delay the 'init' command after receiving the information that the screen was turned on. Too small values can prevent the command from working or make it unreliable. Omit the switch to use the value from the configuration file or the default value (100).
+        #[arg(short = 'D', long)]
+        delay: Option<u16>,
+
+        /// Show an icon in the notification area. Clicking it switches Night Light; its context menu also sets the warmth, applies presets and quits.
+        #[arg(short, long, conflicts_with = "stop")]
+        tray: bool,
+
+        /// A global hotkey switching Night Light, like 'Ctrl+Alt+N'. Modifiers are 'Ctrl', 'Alt', 'Shift' and 'Win'.
+        #[arg(long, conflicts_with = "stop")]
+        hotkey: Option<Hotkey>,
+
+        /// A global hotkey cycling the warmth through 25, 50, 75 and 100 %.
+        #[arg(long, conflicts_with = "stop")]
+        cycle_hotkey: Option<Hotkey>,
+
+        /// Continuously adjust the warmth over the night, like f.lux, from the first to the second percentage, e.g., '20-80'. The night is the one of the schedule in effect, unless '--ramp-coordinates' is passed. The color temperature is updated every minute, overwriting changes from elsewhere. The gamma from the configuration file is applied.
+        #[arg(long, value_name = "PERCENT-PERCENT", value_parser = ramp_value_parser, conflicts_with = "stop")]
+        ramp: Option<(u8, u8)>,
+
+        /// The number of minutes from the beginning of the night over which '--ramp' raises the warmth.
+        #[arg(long, requires = "ramp", default_value_t = 120)]
+        ramp_minutes: u16,
+
+        /// Make '--ramp' follow the night from sunset to sunrise at these coordinates, like '52.52,13.40', instead of the schedule.
+        #[arg(long, requires = "ramp", allow_hyphen_values = true)]
+        ramp_coordinates: Option<Coordinates>,
+
+        /// The warmth while running on battery power, from 0 to 1 or one of the names like with 'temp --warmth'. When AC power returns or the instance stops, the previous color temperature is restored, unless it was changed in the meantime. The gamma from the configuration file is applied.
+        #[arg(long, value_name = "WARMTH", value_parser = warmth_value_parser, conflicts_with = "stop")]
+        battery_warmth: Option<WarmthArg>,
+
+        /// Print how the running instance is doing, like its uptime, when it last inited Night Light and its last error, instead of starting one.
+        #[arg(long, exclusive = true)]
+        status: bool,
+
+        /// Stop an instance of the current user that is already running, also in another session, before starting. Other users' instances keep running. This is the default.
+        #[arg(long, conflicts_with = "stop")]
+        replace: bool,
+
+        /// Fail instead of replacing an instance that is already running.
+        #[arg(long, conflicts_with_all = ["stop", "replace"])]
+        no_replace: bool,
 
         #[command(flatten)]
         init_duration_arg: InitDurationArg,
@@ -102,30 +190,210 @@ pub enum Subcmd {
         gamma: Option<f32>,
     },
 
-    /// Configure the schedule.
-    #[command(visible_alias = "sch")]
+    /// Gradually change the color temperature.
+    ///
+    /// Preview mode is held active during the fade and restored afterwards. If Night Light is off, the color temperature is set directly, since a fade wouldn't be visible. Press Ctrl+C to stop at the current color temperature.
+    Fade {
+        #[command(flatten)]
+        temp_args: TempArgs,
+
+        /// The number of milliseconds the fade takes.
+        #[arg(long, default_value_t = 3000)]
+        millis: u32,
+
+        /// The number of color temperature changes.
+        #[arg(long, default_value_t = 30)]
+        steps: u32,
+    },
+
+    /// Keep a configuration in effect by writing it back whenever another program changes it.
+    ///
+    /// Only the given properties are enforced. Without any, the whole current configuration is locked down, except for the active-state with an active schedule. With '--json', prints a single line of JSON with the written-back properties per write-back. Press Ctrl+C to stop.
+    #[command(visible_alias = "enf")]
+    Enforce {
+        #[command(flatten)]
+        on_off_args: Option<OnOffArgs>,
+
+        #[command(flatten)]
+        temp_args: Option<TempArgs>,
+
+        /// The number of milliseconds to wait after a change before writing back, so that the other program can finish writing.
+        #[arg(long, default_value_t = 300)]
+        debounce: u16,
+    },
+
+    /// Configure the schedule, or show when it will next turn Night Light on or off.
+    #[command(visible_alias = "sch", args_conflicts_with_subcommands = true)]
     Schedule {
+        #[command(subcommand)]
+        action: Option<ScheduleAction>,
+
         #[command(flatten)]
         schedule_args: ScheduleArgs,
     },
 
-    /// Export registry values to .reg file.
+    /// Export registry values to .reg file, or to a JSON file that also contains the decoded configuration.
     #[command(visible_alias = "exp")]
     Export {
-        /// The file path to use. Should have .reg extension. If not specified, defaults to filename based on current local time.
+        /// The file path to use. Should have the format's extension. If not specified, defaults to filename based on current local time.
         #[arg(short, long)]
         output: Option<String>,
+
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Reg)]
+        format: ExportFormat,
+    },
+
+    /// Import registry values from .reg file, e.g., one written by 'export'.
+    ///
+    /// The timestamps are advanced, so that Windows doesn't revert the values as outdated. Values missing from the file are left unchanged.
+    #[command(visible_alias = "imp")]
+    Import {
+        /// The .reg file to import.
+        file: PathBuf,
     },
 
     /// Delete Night Light registry values to reset the feature. Requires log-off/restart.
     ///
     /// Useful in case the values became corrupted for any reason, leaving the feature in an unusable state. After deletion, you should restart or at least log-off.
     #[command(visible_alias = "del")]
-    Delete,
+    Delete {
+        /// Before deleting, write a .reg file to this path with which the deletion can be undone by importing it.
+        #[arg(short, long)]
+        backup: Option<String>,
+    },
 
     /// Monitor Night Light registry values for external changes, displaying technical details.
+    ///
+    /// With '--json', prints the new configuration as a single line of JSON per change instead.
     #[command(visible_alias = "mon")]
-    Monitor,
+    Monitor {
+        /// Measure how long the handling of the change events takes, and print the timings after aborting.
+        #[arg(long)]
+        stats: bool,
+
+        /// Also append every change to the journal '%APPDATA%\sem-reg\night-light-journal.jsonl', which 'history' shows.
+        #[arg(short, long)]
+        record: bool,
+    },
+
+    /// Show the changes recorded with 'monitor --record', oldest first.
+    ///
+    /// Every entry shows when the change was received, the changed registry value, the decoded configuration and the bytes as a diff against the previous ones. With '--json', prints the journal entries as JSON lines instead.
+    #[command(visible_alias = "hist")]
+    History {
+        /// Only show the newest entries.
+        #[arg(short = 'n', long)]
+        last: Option<usize>,
+
+        /// Only show changes of this registry value.
+        #[arg(short, long, value_enum)]
+        value: Option<HistoryValue>,
+    },
+
+    /// Revert the last change made with this program, or recorded with 'monitor --record'.
+    ///
+    /// Before every change, the previous configuration is saved to '%APPDATA%\sem-reg\night-light-undo.json', which keeps the last 20 ones. Like presets, they contain the color temperature, the schedule and the active-state.
+    Undo,
+
+    /// Apply a profile defined in the configuration file.
+    #[command(visible_alias = "prof")]
+    Profile {
+        /// The name of the profile (the part after 'profiles.' in the table header).
+        name: String,
+    },
+
+    /// Apply a profile from the configuration file to all local users. Requires elevation.
+    ///
+    /// Affects the users that are logged on, and with '--offline' also the others, whose registry hives are then loaded from their profiles. Users who never used Night Light are skipped, unless '--lenient' is passed.
+    Rollout {
+        /// The name of the profile.
+        profile: String,
+
+        /// Also apply it to users that aren't logged on.
+        #[arg(long)]
+        offline: bool,
+    },
+
+    /// Save the current configuration as a named preset, or apply, list or delete presets.
+    ///
+    /// Presets contain the color temperature, the schedule and the active-state, which is only applied, if the preset's schedule isn't active. They're stored in '%APPDATA%\sem-reg\night-light-presets.json'.
+    #[command(visible_alias = "pre")]
+    Preset {
+        #[command(subcommand)]
+        action: PresetAction,
+    },
+
+    /// Send a request to the running 'keep-initing' instance and print its response as JSON.
+    ///
+    /// Unlike direct changes, these don't race the instance's own registry writes.
+    Ctl {
+        #[command(subcommand)]
+        request: CtlRequest,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum CtlRequest {
+    /// Print the current Night Light configuration.
+    Status,
+
+    /// Change Night Light, e.g., its color temperature, and print the new configuration.
+    #[command(visible_alias = "set-temp")]
+    Set {
+        #[arg(short, long)]
+        active: Option<bool>,
+
+        #[arg(short, long)]
+        schedule_active: Option<bool>,
+
+        /// Night time color temperature in Kelvin.
+        #[arg(short, long, conflicts_with_all = ["warmth", "default_temp"])]
+        kelvin: Option<u16>,
+
+        /// Kelvin value expressed as an inversely proportional factor from 0.0 to 1.0.
+        #[arg(short, long, conflicts_with_all = ["kelvin", "default_temp"])]
+        warmth: Option<f32>,
+
+        /// Apply Night Light's default color temperature.
+        #[arg(short, long, conflicts_with_all = ["kelvin", "warmth"])]
+        default_temp: bool,
+    },
+
+    /// Switch Night Light on or off and print the new configuration.
+    #[command(visible_alias = "t")]
+    Toggle,
+
+    /// Init Night Light like the 'init' command and print the configuration.
+    Reinit,
+
+    /// Make the background process end.
+    #[command(visible_alias = "quit")]
+    Stop,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ScheduleAction {
+    /// Print when the schedule will next turn Night Light on or off.
+    ///
+    /// Windows may switch somewhat later. With the sunset-to-sunrise schedule, the times shift from day to day.
+    Next,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum PresetAction {
+    /// Save the current configuration under the name, replacing a preset of the same name.
+    Save { name: String },
+
+    /// Apply the preset with the name.
+    Apply { name: String },
+
+    /// List the saved presets.
+    #[command(visible_alias = "ls")]
+    List,
+
+    /// Delete the preset with the name.
+    #[command(visible_alias = "rm")]
+    Delete { name: String },
 }
 
 #[derive(clap::Args, Debug)]
@@ -174,10 +442,49 @@ pub struct ScheduleArgs {
     #[arg(short, long)]
     pub night: Option<ClockTimeFrame>,
 
+    /// Set the explicit schedule from today's sunset to tomorrow's sunrise. This emulates the sunset-to-sunrise schedule type without location services, but must be repeated daily to follow the changing times.
+    #[arg(long, conflicts_with_all = ["type", "night"])]
+    pub from_sun: bool,
+
+    /// Coordinates for '--from-sun' as latitude and longitude in degrees, like '52.52,13.40'. Without it, the Windows location service is queried.
+    #[arg(long, requires = "from_sun", allow_hyphen_values = true)]
+    pub coordinates: Option<Coordinates>,
+
+    /// Minutes by which to shift the sunset for '--from-sun'. Negative values make the night start earlier.
+    #[arg(
+        long,
+        requires = "from_sun",
+        allow_hyphen_values = true,
+        default_value_t = 0
+    )]
+    pub sunset_offset: i16,
+
+    /// Minutes by which to shift the sunrise for '--from-sun'. Positive values make the night end later.
+    #[arg(
+        long,
+        requires = "from_sun",
+        allow_hyphen_values = true,
+        default_value_t = 0
+    )]
+    pub sunrise_offset: i16,
+
     #[command(flatten)]
     pub temp_args: Option<TempArgs>,
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum HistoryValue {
+    State,
+    Settings,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum ExportFormat {
+    Reg,
+    /// The decoded configuration and the hex bytes of the registry values.
+    Json,
+}
+
 #[derive(clap::ValueEnum, Clone, Debug)]
 pub enum ScheduleType {
     #[value(aliases = ["e", "ex", "exp", "expl"])]
@@ -187,16 +494,24 @@ pub enum ScheduleType {
 
 #[derive(clap::Args, Debug)]
 pub struct TempArgs {
-    /// Night time color temperature in Kelvin.
-    #[arg(short, long, conflicts_with_all = ["warmth", "default_temp", "gamma"])]
+    /// Night time color temperature in Kelvin, or one of the names 'candle' (1900), 'warm' (2700), 'neutral' (4000) and 'cold' (6500).
+    #[arg(short, long, value_parser = kelvin_value_parser, conflicts_with_all = ["warmth", "default_temp", "gamma"])]
     pub kelvin: Option<u16>,
 
-    /// Kelvin value expressed as an inversely proportional factor from 0.0 to 1.0. Steps in the upper range are perceived as more intense, which is why they should be smaller to achieve the same step in perception as larger steps in the lower range. You can also use '--gamma' for this correction with this switch.
-    #[arg(short, long, conflicts_with_all = ["kelvin", "default_temp"])]
-    pub warmth: Option<f32>,
+    /// Kelvin value expressed as an inversely proportional factor from 0.0 to 1.0. Steps in the upper range are perceived as more intense, which is why they should be smaller to achieve the same step in perception as larger steps in the lower range. You can also use '--gamma' for this correction with this switch. Also accepts the names of '--kelvin', which aren't gamma-corrected.
+    #[arg(short, long, value_parser = warmth_value_parser, group = "warmth_change", conflicts_with_all = ["kelvin", "default_temp"])]
+    pub warmth: Option<WarmthArg>,
 
-    /// The gamma exponent whose inverse is applied to '--warmth'. When omitting the number, a default is used. When omitting the switch, gamma correction isn't applied.
-    #[arg(short, long, num_args = 0..=1, default_missing_value = DEFAULT_GAMMA, value_parser = gamma_value_parser, requires = "warmth")]
+    /// Increase the warmth by this many percentage points, e.g., from a hotkey script. With '--gamma', the steps are gamma-corrected like '--warmth'.
+    #[arg(long, value_name = "PERCENT", group = "warmth_change", conflicts_with_all = ["kelvin", "default_temp"])]
+    pub increase: Option<u8>,
+
+    /// Decrease the warmth by this many percentage points. See '--increase'.
+    #[arg(long, value_name = "PERCENT", group = "warmth_change", conflicts_with_all = ["kelvin", "default_temp"])]
+    pub decrease: Option<u8>,
+
+    /// The gamma exponent whose inverse is applied to '--warmth', '--increase' and '--decrease'. When omitting the number, a default is used. When omitting the switch, the gamma from the configuration file is applied, if any.
+    #[arg(short, long, num_args = 0..=1, default_missing_value = DEFAULT_GAMMA, value_parser = gamma_value_parser, requires = "warmth_change")]
     pub gamma: Option<f32>,
 
     /// Apply Night Light's default color temperature.
@@ -204,6 +519,50 @@ pub struct TempArgs {
     pub default_temp: bool,
 }
 
+/// The value of '--warmth'.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WarmthArg {
+    Factor(f32),
+    /// A named color temperature.
+    Named(u16),
+}
+
+fn kelvin_value_parser(string: &str) -> Result<u16, String> {
+    match NightLight::named_night_color_temp(string) {
+        Some(temp) => Ok(temp),
+        None => u16::from_str(string)
+            .map_err(|_| format!("not a number or one of the names {}", temp_names())),
+    }
+}
+
+fn warmth_value_parser(string: &str) -> Result<WarmthArg, String> {
+    match NightLight::named_night_color_temp(string) {
+        Some(temp) => Ok(WarmthArg::Named(temp)),
+        None => f32::from_str(string)
+            .map(WarmthArg::Factor)
+            .map_err(|_| format!("not a number or one of the names {}", temp_names())),
+    }
+}
+
+fn temp_names() -> String {
+    NightLight::NAMED_NIGHT_COLOR_TEMPS
+        .map(|(name, _)| format!("'{name}'"))
+        .join(", ")
+}
+
+fn ramp_value_parser(string: &str) -> Result<(u8, u8), String> {
+    let parse_percentage = |string: &str| {
+        u8::from_str(string)
+            .ok()
+            .filter(|percentage| *percentage <= 100)
+    };
+
+    string
+        .split_once('-')
+        .and_then(|(start, end)| Some((parse_percentage(start)?, parse_percentage(end)?)))
+        .ok_or_else(|| "expected two percentages from 0 to 100, like '20-80'".to_string())
+}
+
 fn gamma_value_parser(string: &str) -> Result<f32, String> {
     let gamma = f32::from_str(string).map_err(|e| e.to_string())?;
 
@@ -0,0 +1,367 @@
+//! Reporting of errors that end the program, either as text or as a JSON object for tools wrapping the binaries.
+
+use serde_json::json;
+use std::{io, process::ExitCode};
+
+use sem_reg::{
+    cloud_store::night_light::{self, DataError},
+    config::ConfigError,
+    data_conversion::ParseError,
+    energy_saver,
+    personalization::{accent_color, color_mode, taskbar},
+    reg::{reg_file::RegFileError, WrongValueType},
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, PartialEq, Debug)]
+pub enum ErrorFormat {
+    /// The error and its causes, for humans.
+    #[default]
+    Text,
+    /// A JSON object with code, category, message, causes and remediation hint.
+    Json,
+}
+
+/// A classification of an error for machine consumption.
+#[derive(PartialEq, Debug)]
+struct ErrorInfo {
+    /// Like `valuesMissing`. Stable across versions.
+    code: &'static str,
+    /// Like `registry`.
+    category: &'static str,
+    hint: Option<&'static str>,
+}
+
+pub fn report(error_format: ErrorFormat, result: anyhow::Result<()>) -> ExitCode {
+    //! Prints the error to stderr in the format and returns the exit code.
+
+    let Err(error) = result else {
+        return ExitCode::SUCCESS;
+    };
+
+    match error_format {
+        ErrorFormat::Text => eprintln!("Error: {error:?}"),
+        ErrorFormat::Json => {
+            let info = classify(&error);
+            eprintln!(
+                "{}",
+                json!({
+                    "error": {
+                        "code": info.code,
+                        "category": info.category,
+                        "message": error.to_string(),
+                        "causes": error.chain().skip(1).map(ToString::to_string).collect::<Vec<_>>(),
+                        "hint": info.hint,
+                    }
+                })
+            );
+        }
+    }
+
+    ExitCode::FAILURE
+}
+
+fn classify(error: &anyhow::Error) -> ErrorInfo {
+    for cause in error.chain() {
+        if let Some(error) = cause.downcast_ref::<night_light::Error>() {
+            return classify_night_light_error(error);
+        } else if let Some(error) = cause.downcast_ref::<night_light::ImportError>() {
+            return match error {
+                night_light::ImportError::RegFileError(RegFileError::IoError(error))
+                | night_light::ImportError::IoError(error) => classify_io_error(error),
+                night_light::ImportError::RegFileError(_)
+                | night_light::ImportError::NoValues
+                | night_light::ImportError::NotBinary => ErrorInfo {
+                    code: "invalidRegFile",
+                    category: "format",
+                    hint: Some("Import a .reg file written by 'night-light export'."),
+                },
+                night_light::ImportError::ParseError(error) => classify_parse_error(error),
+                night_light::ImportError::DataError(error) => classify_data_error(error),
+            };
+        } else if let Some(error) = cause.downcast_ref::<night_light::PresetError>() {
+            return match error {
+                night_light::PresetError::IoError(error) => classify_io_error(error),
+                night_light::PresetError::NotFound(_) => ErrorInfo {
+                    code: "presetNotFound",
+                    category: "config",
+                    hint: Some("List the saved presets with 'night-light preset list'."),
+                },
+                night_light::PresetError::JsonError(_) => ErrorInfo {
+                    code: "invalidPresetFile",
+                    category: "config",
+                    hint: Some("Fix or delete '%APPDATA%\\sem-reg\\night-light-presets.json'."),
+                },
+            };
+        } else if let Some(error) = cause.downcast_ref::<night_light::UndoError>() {
+            return match error {
+                night_light::UndoError::IoError(error) => classify_io_error(error),
+                night_light::UndoError::JsonError(_) => ErrorInfo {
+                    code: "invalidUndoFile",
+                    category: "config",
+                    hint: Some("Fix or delete '%APPDATA%\\sem-reg\\night-light-undo.json'."),
+                },
+                night_light::UndoError::NightLightError(error) => classify_night_light_error(error),
+            };
+        } else if let Some(error) = cause.downcast_ref::<accent_color::Error>() {
+            return match error {
+                accent_color::Error::IoError(error) => classify_io_error(error),
+                accent_color::Error::InvalidPalette => ErrorInfo {
+                    code: "invalidPalette",
+                    category: "format",
+                    hint: Some("Choose an accent color in the Windows settings once to rewrite the palette."),
+                },
+                accent_color::Error::Expired => classify_data_error(&DataError::Expired),
+            };
+        } else if let Some(error) = cause.downcast_ref::<color_mode::Error>() {
+            return match error {
+                color_mode::Error::IoError(error) => classify_io_error(error),
+                color_mode::Error::Expired => classify_data_error(&DataError::Expired),
+            };
+        } else if let Some(error) = cause.downcast_ref::<taskbar::Error>() {
+            return match error {
+                taskbar::Error::IoError(error) => classify_io_error(error),
+                taskbar::Error::InvalidValue { .. } => ErrorInfo {
+                    code: "invalidValue",
+                    category: "format",
+                    hint: Some(
+                        "Retry with '--lenient' or change the setting in the Windows settings.",
+                    ),
+                },
+                taskbar::Error::Expired => classify_data_error(&DataError::Expired),
+            };
+        } else if let Some(error) = cause.downcast_ref::<energy_saver::Error>() {
+            return match error {
+                energy_saver::Error::IoError(error) => classify_io_error(error),
+                energy_saver::Error::InvalidThreshold(_) => ErrorInfo {
+                    code: "invalidThreshold",
+                    category: "format",
+                    hint: Some(
+                        "Retry with '--lenient' or set the threshold in the Windows settings.",
+                    ),
+                },
+                energy_saver::Error::Expired => classify_data_error(&DataError::Expired),
+            };
+        } else if let Some(error) = cause.downcast_ref::<ParseError>() {
+            return classify_parse_error(error);
+        } else if let Some(error) = cause.downcast_ref::<DataError>() {
+            return classify_data_error(error);
+        } else if cause.downcast_ref::<ConfigError>().is_some() {
+            return ErrorInfo {
+                code: "invalidConfig",
+                category: "config",
+                hint: Some("Fix the configuration file with 'sem-reg config edit'."),
+            };
+        } else if let Some(error) = cause.downcast_ref::<io::Error>() {
+            return classify_io_error(error);
+        }
+    }
+
+    ErrorInfo {
+        code: "other",
+        category: "other",
+        hint: None,
+    }
+}
+
+fn classify_io_error(error: &io::Error) -> ErrorInfo {
+    if let Some(wrong_value_type) = WrongValueType::of(error) {
+        return ErrorInfo {
+            code: "wrongValueType",
+            category: "registry",
+            hint: Some(if wrong_value_type.is_empty_placeholder() {
+                "The registry value is empty, so there's nothing to restore. Reset Night Light with 'night-light delete' and log off."
+            } else {
+                "The registry value is corrupted. Restore it from a backup, or reset Night Light with 'night-light delete' and log off."
+            }),
+        };
+    }
+
+    match error.kind() {
+        io::ErrorKind::NotFound => ErrorInfo {
+            code: "notFound",
+            category: "registry",
+            hint: Some("If Night Light was never used, open its official settings once, toggle it and move the slider to create the registry values. Alternatively, use '--lenient'."),
+        },
+        io::ErrorKind::PermissionDenied => ErrorInfo {
+            code: "accessDenied",
+            category: "registry",
+            hint: Some("Run the command as administrator, if it accesses other users' settings."),
+        },
+        _ => ErrorInfo {
+            code: "ioError",
+            category: "io",
+            hint: None,
+        },
+    }
+}
+
+fn classify_night_light_error(error: &night_light::Error) -> ErrorInfo {
+    match error {
+        night_light::Error::IoError(error) => classify_io_error(error),
+        night_light::Error::ParseError(error) => classify_parse_error(error),
+        night_light::Error::DataError(error) => classify_data_error(error),
+        //. The hint concerns the original error.
+        night_light::Error::RestoreFailed { error, .. } => classify_night_light_error(error),
+    }
+}
+
+fn classify_parse_error(_error: &ParseError) -> ErrorInfo {
+    ErrorInfo {
+        code: "parseError",
+        category: "format",
+        hint: Some("The format may have changed with a Windows update. Check with 'sem-reg audit', retry with '--lenient' and please report the problem."),
+    }
+}
+
+fn classify_data_error(error: &DataError) -> ErrorInfo {
+    match error {
+        DataError::Expired => ErrorInfo {
+            code: "expired",
+            category: "data",
+            hint: Some("Retry the command."),
+        },
+        DataError::Irreconcilable(_) => ErrorInfo {
+            code: "irreconcilable",
+            category: "data",
+            hint: Some("Change the active state and the schedule in separate invocations."),
+        },
+        DataError::NightPreviewInProgress => ErrorInfo {
+            code: "nightPreviewInProgress",
+            category: "data",
+            hint: Some("Close the official Night Light settings or wait, and retry."),
+        },
+        DataError::ImplausibleTime => ErrorInfo {
+            code: "implausibleTime",
+            category: "data",
+            hint: Some("Check the system clock. To reset the timestamps, reset Night Light with 'night-light delete' and log off."),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Context};
+    use std::io;
+    use winreg::enums::RegType;
+
+    use super::classify;
+    use sem_reg::{
+        cloud_store::night_light::{self, DataError},
+        config::ConfigError,
+        data_conversion::ParseError,
+        energy_saver,
+        personalization::{accent_color, color_mode, taskbar},
+        reg::WrongValueType,
+    };
+
+    fn code(error: anyhow::Error) -> &'static str {
+        classify(&error).code
+    }
+
+    #[test]
+    fn night_light_errors() {
+        assert_eq!(
+            code(night_light::Error::from(io::Error::from(io::ErrorKind::NotFound)).into()),
+            "notFound"
+        );
+        assert_eq!(
+            code(night_light::Error::from(ParseError::ExpectedZero(3)).into()),
+            "parseError"
+        );
+        assert_eq!(
+            code(night_light::Error::from(DataError::NightPreviewInProgress).into()),
+            "nightPreviewInProgress"
+        );
+        assert_eq!(
+            code(
+                night_light::Error::RestoreFailed {
+                    error: Box::new(DataError::NightPreviewInProgress.into()),
+                    restore_error: Box::new(io::Error::from(io::ErrorKind::NotFound).into()),
+                }
+                .into()
+            ),
+            "nightPreviewInProgress"
+        );
+        assert_eq!(
+            code(night_light::ImportError::NoValues.into()),
+            "invalidRegFile"
+        );
+        assert_eq!(
+            code(night_light::PresetError::NotFound("reading".to_string()).into()),
+            "presetNotFound"
+        );
+        assert_eq!(
+            code(night_light::UndoError::from(serde_json::from_str::<()>("{").unwrap_err()).into()),
+            "invalidUndoFile"
+        );
+    }
+
+    #[test]
+    fn other_settings_errors() {
+        assert_eq!(
+            code(accent_color::Error::InvalidPalette.into()),
+            "invalidPalette"
+        );
+        assert_eq!(code(color_mode::Error::Expired.into()), "expired");
+        assert_eq!(
+            code(
+                taskbar::Error::InvalidValue {
+                    value_name: "TaskbarAl".to_string(),
+                    value: 7,
+                }
+                .into()
+            ),
+            "invalidValue"
+        );
+        assert_eq!(
+            code(energy_saver::Error::InvalidThreshold(101).into()),
+            "invalidThreshold"
+        );
+    }
+
+    #[test]
+    fn generic_errors() {
+        assert_eq!(
+            code(io::Error::from(io::ErrorKind::PermissionDenied).into()),
+            "accessDenied"
+        );
+        assert_eq!(
+            code(
+                io::Error::from(WrongValueType {
+                    expected: RegType::REG_BINARY,
+                    found: RegType::REG_NONE,
+                    len: 0,
+                })
+                .into()
+            ),
+            "wrongValueType"
+        );
+        let hint_of = |len| {
+            classify(
+                &io::Error::from(WrongValueType {
+                    expected: RegType::REG_BINARY,
+                    found: RegType::REG_NONE,
+                    len,
+                })
+                .into(),
+            )
+            .hint
+        };
+        assert_ne!(hint_of(0), hint_of(1));
+        assert_eq!(
+            code(ConfigError::from(io::Error::from(io::ErrorKind::InvalidData)).into()),
+            "invalidConfig"
+        );
+        assert_eq!(code(anyhow!("something else")), "other");
+
+        //. Causes are classified, too.
+        assert_eq!(
+            code(
+                Err::<(), _>(DataError::Expired)
+                    .context("couldn't write")
+                    .unwrap_err()
+            ),
+            "expired"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use windows::{
+    core::{w, HSTRING, PCWSTR},
+    Win32::{
+        Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WPARAM},
+        UI::WindowsAndMessaging::{
+            AppendMenuW, CreatePopupMenu, LoadIconW, PostMessageW, RegisterWindowMessageW,
+            SetForegroundWindow, TrackPopupMenu, IDI_APPLICATION, MF_CHECKED, MF_SEPARATOR,
+            MF_STRING, MF_UNCHECKED, TPM_RETURNCMD, TPM_RIGHTBUTTON, WM_APP,
+        },
+    },
+};
+use windows_helpers::{
+    core::CheckNumberError,
+    win32_app::tray_icon::{SimplifiedTrayIconMsg, TrayIcon},
+    Null, ResGuard,
+};
+
+use super::app::{set_warmth_step, toggle_night_light, WARMTH_STEPS};
+use sem_reg::{
+    cloud_store::night_light::{ChangeSet, NightLight},
+    data_conversion::Strictness,
+    log_error,
+};
+
+/// The window message ID the tray icon uses to notify the window.
+const TRAY_ICON_MSG: u32 = WM_APP;
+
+const TOGGLE_CMD: usize = 1;
+const QUIT_CMD: usize = 2;
+const FIRST_WARMTH_CMD: usize = 100;
+const FIRST_PRESET_CMD: usize = 200;
+
+/// An icon in the notification area, whose context menu switches Night Light, sets the warmth, applies presets and quits. Clicking it switches Night Light.
+pub struct Tray {
+    icon: TrayIcon,
+    lenient: bool,
+    /// The gamma applied to the warmth steps of the menu.
+    gamma: f32,
+    /// Sent to the window when the menu item to quit was chosen.
+    quit_msg: u32,
+    /// Broadcast when Explorer (re)started, which requires adding the icon again.
+    taskbar_created_msg: u32,
+}
+
+impl Tray {
+    pub fn new(
+        hwnd: HWND,
+        quit_msg: u32,
+        lenient: bool,
+        gamma: f32,
+    ) -> windows::core::Result<Self> {
+        //! Must be called with a window that receives broadcast messages, not with a message-only window.
+
+        let mut icon = TrayIcon::with_primary_id(hwnd, Some(TRAY_ICON_MSG))?;
+
+        //. Shared icons must not be destroyed.
+        unsafe { icon.set_icon(LoadIconW(HINSTANCE::NULL, IDI_APPLICATION)?)? };
+        icon.set_tooltip(Some("Night Light"))?;
+        icon.show(true)?;
+
+        Ok(Self {
+            icon,
+            lenient,
+            gamma,
+            quit_msg,
+            taskbar_created_msg: unsafe { RegisterWindowMessageW(w!("TaskbarCreated")) }
+                .nonzero_or_win32_err()?,
+        })
+    }
+
+    pub fn handle_msg(
+        &mut self,
+        hwnd: HWND,
+        msg_id: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> Option<LRESULT> {
+        //! Handles the messages concerning the tray icon and returns `None` for all others. Errors are logged, since there's no console to print them to.
+
+        if msg_id == self.taskbar_created_msg {
+            if let Err(error) = self.icon.readd() {
+                log_error!("re-adding tray icon failed: {error}");
+            }
+            return Some(LRESULT(0));
+        } else if msg_id != TRAY_ICON_MSG {
+            return None;
+        }
+
+        let result = match self.icon.simplifying_translate_window_msg(wparam, lparam) {
+            SimplifiedTrayIconMsg::Activated => self.run_cmd(hwnd, TOGGLE_CMD, &BTreeMap::new()),
+            SimplifiedTrayIconMsg::ContextMenuRequested { x, y } => {
+                self.show_menu(hwnd, x as _, y as _)
+            }
+            SimplifiedTrayIconMsg::Other(_) => Ok(()),
+        };
+
+        if let Err(error) = result {
+            log_error!("tray menu command failed: {error:?}");
+        }
+
+        Some(LRESULT(0))
+    }
+
+    fn show_menu(&self, hwnd: HWND, x: i32, y: i32) -> anyhow::Result<()> {
+        let night_light =
+            NightLight::from_reg_with_strictness(Strictness::from_lenient_bool(self.lenient))?;
+        let presets = NightLight::presets().unwrap_or_else(|error| {
+            log_error!("reading presets failed: {error}");
+            BTreeMap::new()
+        });
+
+        let menu = ResGuard::with_acq_and_destroy_menu(|| unsafe { CreatePopupMenu() })?;
+        let cmd = unsafe {
+            AppendMenuW(
+                *menu,
+                MF_STRING
+                    | if night_light.active() {
+                        MF_CHECKED
+                    } else {
+                        MF_UNCHECKED
+                    },
+                TOGGLE_CMD,
+                w!("Night Light"),
+            )?;
+
+            AppendMenuW(*menu, MF_SEPARATOR, 0, PCWSTR::null())?;
+            for (index, percentage) in WARMTH_STEPS.iter().enumerate() {
+                AppendMenuW(
+                    *menu,
+                    MF_STRING,
+                    FIRST_WARMTH_CMD + index,
+                    &HSTRING::from(format!("Warmth {percentage} %")),
+                )?;
+            }
+
+            if !presets.is_empty() {
+                AppendMenuW(*menu, MF_SEPARATOR, 0, PCWSTR::null())?;
+                for (index, name) in presets.keys().enumerate() {
+                    AppendMenuW(
+                        *menu,
+                        MF_STRING,
+                        FIRST_PRESET_CMD + index,
+                        &HSTRING::from(name.as_str()),
+                    )?;
+                }
+            }
+
+            AppendMenuW(*menu, MF_SEPARATOR, 0, PCWSTR::null())?;
+            AppendMenuW(*menu, MF_STRING, QUIT_CMD, w!("Quit"))?;
+
+            //. Otherwise, the menu doesn't close when clicking elsewhere.
+            let _ = SetForegroundWindow(hwnd);
+
+            //. Returns 0, if the menu was dismissed.
+            TrackPopupMenu(*menu, TPM_RETURNCMD | TPM_RIGHTBUTTON, x, y, 0, hwnd, None).0 as usize
+        };
+
+        self.run_cmd(hwnd, cmd, &presets)
+    }
+
+    fn run_cmd(
+        &self,
+        hwnd: HWND,
+        cmd: usize,
+        presets: &BTreeMap<String, ChangeSet>,
+    ) -> anyhow::Result<()> {
+        let warmth_range = FIRST_WARMTH_CMD..FIRST_WARMTH_CMD + WARMTH_STEPS.len();
+        let preset_range = FIRST_PRESET_CMD..FIRST_PRESET_CMD + presets.len();
+
+        match cmd {
+            QUIT_CMD => unsafe { PostMessageW(hwnd, self.quit_msg, WPARAM(0), LPARAM(0))? },
+            TOGGLE_CMD => toggle_night_light(self.lenient)?,
+            cmd if warmth_range.contains(&cmd) => set_warmth_step(
+                WARMTH_STEPS[cmd - FIRST_WARMTH_CMD],
+                self.gamma,
+                self.lenient,
+            )?,
+            cmd if preset_range.contains(&cmd) => {
+                let preset = presets
+                    .values()
+                    .nth(cmd - FIRST_PRESET_CMD)
+                    .expect("index should be in range");
+                NightLight::apply_with_strictness(
+                    preset,
+                    Strictness::from_lenient_bool(self.lenient),
+                )?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use crate::night_light::cli::GlobalArgs;
+use sem_reg::{
+    log_info,
+    personalization::accent_color::{AccentColor, Rgb},
+};
+
+pub fn run(global_args: &GlobalArgs, color: Option<Rgb>) -> anyhow::Result<()> {
+    let mut accent_color = AccentColor::from_reg()?;
+
+    if let Some(color) = color {
+        accent_color.set_accent_color(color);
+    }
+
+    if global_args.json {
+        println!("{}", accent_color.to_json());
+    } else {
+        println!("{accent_color}");
+    }
+
+    if color.is_some() && !global_args.dry_run {
+        accent_color.write_to_reg()?;
+        log_info!("wrote accent color");
+    }
+
+    Ok(())
+}
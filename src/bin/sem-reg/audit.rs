@@ -0,0 +1,63 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use serde_json::json;
+
+use crate::night_light::cli::GlobalArgs;
+use sem_reg::cloud_store::audit::{self, AuditStatus};
+
+pub fn run(global_args: &GlobalArgs) -> anyhow::Result<()> {
+    let audits = audit::audit();
+    let settings = audit::healthy_settings(&audits);
+
+    if global_args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "values": audits,
+                "healthySettings": settings
+                    .iter()
+                    .map(|(setting, healthy)| (setting.clone(), json!(healthy)))
+                    .collect::<serde_json::Map<_, _>>(),
+            }))?
+        );
+    } else {
+        for audit in &audits {
+            let status = match audit.status {
+                AuditStatus::Healthy => "healthy".green(),
+                AuditStatus::LenientOnly => "lenient only".yellow(),
+                AuditStatus::Unparsable => "unparsable".red(),
+                AuditStatus::Missing => "missing".dimmed(),
+                AuditStatus::WrongType => "wrong type".red(),
+                AuditStatus::Unreadable => "unreadable".red(),
+            };
+            println!("{}.{}: {status}", audit.setting, audit.value);
+
+            if let Some(error) = &audit.error {
+                println!("  {error}");
+            }
+            if let Some(deviation_index) = audit.deviation_index {
+                println!("  first deviation at byte {deviation_index}");
+            }
+        }
+
+        println!();
+        for (setting, healthy) in &settings {
+            println!(
+                "{setting}: {}",
+                if *healthy {
+                    "healthy".green()
+                } else {
+                    "unhealthy".red()
+                }
+            );
+        }
+    }
+
+    if settings.iter().all(|(_, healthy)| *healthy) {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "unhealthy settings found; please report them with 'sem-reg collect'"
+        ))
+    }
+}
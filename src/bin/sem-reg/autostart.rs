@@ -0,0 +1,120 @@
+use anyhow::anyhow;
+use std::env;
+
+use crate::{
+    cli::{AutostartAction, AutostartKindArg, ResidentMode},
+    night_light::cli::GlobalArgs,
+};
+use sem_reg::{
+    autostart::{self, AutostartEntry, AutostartKind},
+    log_info, log_warn,
+};
+
+pub fn run(global_args: &GlobalArgs, action: AutostartAction) -> anyhow::Result<()> {
+    let entries = autostart::list()?;
+
+    match action {
+        AutostartAction::List => {
+            if global_args.json {
+                let entries = entries
+                    .iter()
+                    .map(|entry| {
+                        let mut value = serde_json::to_value(entry)?;
+                        value["stale"] = entry.is_stale().into();
+                        Ok(value)
+                    })
+                    .collect::<Result<Vec<_>, serde_json::Error>>()?;
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else {
+                for entry in &entries {
+                    println!(
+                        "{} ({}){}: {}",
+                        entry.name,
+                        kind_name(entry.kind),
+                        if entry.is_stale() { ", stale" } else { "" },
+                        entry.command_line
+                    );
+                }
+            }
+        }
+
+        AutostartAction::Add { mode, kind, name } => {
+            let (default_name, args): (_, &[_]) = match mode {
+                ResidentMode::KeepIniting => ("keep-initing", &["night-light", "keep-initing"]),
+                ResidentMode::Daemon => ("daemon", &["daemon"]),
+            };
+            let entry = AutostartEntry {
+                name: name.unwrap_or_else(|| default_name.to_string()),
+                kind: to_autostart_kind(kind),
+                command_line: autostart::command_line(&env::current_exe()?, args),
+            };
+
+            for stale_entry in entries.iter().filter(|entry| entry.is_stale()) {
+                log_warn!(
+                    "stale entry '{}' ({}) found; remove it with 'sem-reg autostart prune'",
+                    stale_entry.name,
+                    kind_name(stale_entry.kind)
+                );
+            }
+
+            if global_args.dry_run {
+                println!("Would write: {}", entry.command_line);
+            } else {
+                entry.create_or_update()?;
+                log_info!("wrote autostart entry '{}'", entry.name);
+            }
+        }
+
+        AutostartAction::Remove { name, kind } => {
+            let matching_entries = entries
+                .iter()
+                .filter(|entry| {
+                    entry.name == name
+                        && kind.map_or(true, |kind| to_autostart_kind(kind) == entry.kind)
+                })
+                .collect::<Vec<_>>();
+
+            if matching_entries.is_empty() {
+                return Err(anyhow!("no autostart entry named '{name}'"));
+            }
+
+            remove_entries(global_args, matching_entries)?;
+        }
+
+        AutostartAction::Prune => {
+            remove_entries(
+                global_args,
+                entries.iter().filter(|entry| entry.is_stale()).collect(),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn remove_entries(global_args: &GlobalArgs, entries: Vec<&AutostartEntry>) -> anyhow::Result<()> {
+    for entry in entries {
+        if global_args.dry_run {
+            println!("Would remove '{}' ({}).", entry.name, kind_name(entry.kind));
+        } else {
+            entry.remove()?;
+            log_info!("removed autostart entry '{}'", entry.name);
+        }
+    }
+
+    Ok(())
+}
+
+fn to_autostart_kind(kind: AutostartKindArg) -> AutostartKind {
+    match kind {
+        AutostartKindArg::Run => AutostartKind::RunEntry,
+        AutostartKindArg::Task => AutostartKind::ScheduledTask,
+    }
+}
+
+fn kind_name(kind: AutostartKind) -> &'static str {
+    match kind {
+        AutostartKind::RunEntry => "run",
+        AutostartKind::ScheduledTask => "task",
+    }
+}
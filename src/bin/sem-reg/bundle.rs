@@ -0,0 +1,65 @@
+use anyhow::anyhow;
+use std::fs;
+
+use crate::{cli::BundleAction, night_light::cli::GlobalArgs};
+use sem_reg::{
+    bundle::Bundle,
+    cloud_store::{night_light::NightLight, schema::ValueSchema},
+    config::Config,
+    data_conversion::Strictness,
+    reg::{with_backup, OwnedRegValuePath},
+};
+
+pub fn run(global_args: &GlobalArgs, action: BundleAction) -> anyhow::Result<()> {
+    let dir_path = Config::schema_dir_path()
+        .ok_or_else(|| anyhow!("'APPDATA' environment variable not set"))?;
+    let schemas = ValueSchema::load_dir(dir_path)?;
+    let strictness = Strictness::from_lenient_bool(global_args.lenient);
+
+    match action {
+        BundleAction::Export { output } => {
+            let json = Bundle::export(&schemas, strictness)?.to_json();
+
+            if global_args.dry_run {
+                println!("{json}");
+            } else {
+                fs::write(output, json)?;
+            }
+        }
+
+        BundleAction::Apply { input, backup } => {
+            let bundle = Bundle::from_json(&fs::read_to_string(input)?)?;
+            let dry_run = global_args.dry_run;
+
+            let changes = match backup {
+                Some(backup_file_path) if !dry_run => {
+                    let mut reg_value_paths = NightLight::REG_VALUE_PATHS
+                        .into_iter()
+                        .map(OwnedRegValuePath::from)
+                        .collect::<Vec<_>>();
+                    for schema in &schemas {
+                        reg_value_paths.push(schema.reg_value_path()?);
+                    }
+
+                    with_backup(&reg_value_paths, backup_file_path, || {
+                        bundle.apply(&schemas, strictness, false)
+                    })?
+                }
+                _ => bundle.apply(&schemas, strictness, dry_run)?,
+            };
+
+            if global_args.json {
+                println!("{}", serde_json::to_string_pretty(&changes)?);
+            } else if changes.is_empty() {
+                println!("Nothing to change.");
+            } else {
+                println!("{}", if dry_run { "Would change:" } else { "Changed:" });
+                for change in changes {
+                    println!("  {change}");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,347 @@
+use std::path::PathBuf;
+
+use crate::night_light::cli::{CtlRequest, GlobalArgs, InitDurationArg, NightLightArgs};
+use sem_reg::personalization::accent_color::Rgb;
+
+/// Query and change Windows settings stored in the registry.
+#[derive(clap::Parser, Debug)]
+#[command(name = env!("CARGO_BIN_NAME"), version)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global_args: GlobalArgs,
+
+    #[command(subcommand)]
+    pub setting: Setting,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Setting {
+    /// Query and change Night Light, like the `night-light` binary.
+    #[command(visible_alias = "nl")]
+    NightLight(NightLightArgs),
+
+    /// Keep running and serve requests sent with 'ctl'.
+    ///
+    /// Maintains the color temperature like 'night-light keep-initing' (replacing a running instance of it), and keeps the current configuration up to date by monitoring the registry values. Only one daemon can run per user at a time.
+    Daemon {
+        /// See 'night-light keep-initing'.
+        #[arg(short = 'D', long)]
+        delay: Option<u16>,
+
+        #[command(flatten)]
+        init_duration_arg: InitDurationArg,
+    },
+
+    /// Manage entries that start resident modes on log-on.
+    ///
+    /// Only entries created with this command are handled. Stale entries, whose program doesn't exist anymore, are marked when listing.
+    Autostart {
+        #[command(subcommand)]
+        action: AutostartAction,
+    },
+
+    /// Locate, edit or show the configuration file.
+    ///
+    /// The configuration file provides defaults for flags. Flags specified on the command line take precedence.
+    #[command(visible_alias = "cfg")]
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Decode and edit CloudStore values with definitions loaded at runtime.
+    ///
+    /// Definitions are read from the '.toml' and '.json' files in the directory '%APPDATA%\sem-reg\schemas'. See the documentation of the 'sem_reg::cloud_store::schema' module for their format.
+    Schema {
+        #[command(subcommand)]
+        action: SchemaAction,
+    },
+
+    /// Export settings into a portable bundle file or apply one, e.g., on a new PC.
+    ///
+    /// Bundles contain Night Light and the values of all CloudStore value definitions in '%APPDATA%\sem-reg\schemas'. With '--dry-run', 'apply' prints the changes without writing.
+    Bundle {
+        #[command(subcommand)]
+        action: BundleAction,
+    },
+
+    /// Check the registry values against the crate's parsers, to detect format changes by Windows updates.
+    ///
+    /// Every value is parsed strictly and, if that fails, leniently. For failures, the index of the first byte deviating from the expected format is reported, if known. Fails, if a setting isn't healthy.
+    Audit,
+
+    /// Print structural fingerprints of the registry values, to report layout changes without sharing data.
+    ///
+    /// A fingerprint consists of a value's length, its constant leading bytes and the IDs and types of its fields, but no field values. Values with the same hash have the same layout.
+    Fingerprint,
+
+    /// Collect raw CloudStore values into a JSON file to attach to issues, helping to research formats.
+    ///
+    /// Every value is shown for review before it's included, unless '--yes' is specified. Redaction is based on parsing the values; values that can't be parsed are skipped when redacting.
+    Collect {
+        /// The JSON file to write.
+        output: PathBuf,
+
+        /// Only collect values whose key path contains this text (case-insensitive).
+        #[arg(short, long)]
+        filter: Option<String>,
+
+        /// Skip values the crate has dedicated types for.
+        #[arg(short, long)]
+        unknown_only: bool,
+
+        /// Replace the characters of strings with 'x'.
+        #[arg(long)]
+        redact_strings: bool,
+
+        /// Replace timestamps with 2000-01-01. Other numbers that look like timestamps are also affected.
+        #[arg(long)]
+        redact_timestamps: bool,
+
+        /// Include all values without reviewing them.
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Find out which CloudStore values back a setting by changing it in the Settings app.
+    ///
+    /// Takes a snapshot of all CloudStore values of the current user and, every time Enter is pressed, reports the values that changed since the last snapshot with their bytes colored as a diff.
+    Discover {
+        /// Only report values whose key path contains this text (case-insensitive).
+        #[arg(short, long)]
+        filter: Option<String>,
+    },
+
+    /// Export all CloudStore values of the current user into a .reg file.
+    ///
+    /// The values are read in parallel, so that even large CloudStore trees are exported quickly. Values deleted while exporting are written as deletions. With '--dry-run', prints the file's contents instead.
+    ExportAll {
+        /// The .reg file to write.
+        output: PathBuf,
+    },
+
+    /// Mirror Night Light changes of one user into the hives of other local users.
+    ///
+    /// Must run elevated. Only users that are logged on can be written to; others are caught up on the next change after they logged on. With '--dry-run', prints what would be written once and exits.
+    Mirror {
+        /// The user to mirror from, as an account name or SID. Defaults to the current user.
+        #[arg(short, long)]
+        from: Option<String>,
+
+        /// The users to mirror to, as account names or SIDs.
+        #[arg(short, long, required = true, num_args = 1..)]
+        to: Vec<String>,
+
+        /// How to handle target values that differ from the source values.
+        #[arg(short, long, value_enum, default_value_t = ConflictRuleArg::Newer)]
+        rule: ConflictRuleArg,
+    },
+
+    /// Complete or roll back sequences of writes that were interrupted by a crash.
+    ///
+    /// Sequences of multiple writes, like Night Light's settings and state, are recorded in a write-ahead log in '%APPDATA%\sem-reg\wal' before they're performed. Values changed after the crash are left alone. With '--dry-run', only lists the interrupted sequences.
+    Recover {
+        /// Restore the values from before the sequence instead of completing it.
+        #[arg(short, long)]
+        roll_back: bool,
+    },
+
+    /// Query and change whether apps and Windows use the light or dark theme.
+    ///
+    /// Without a mode, prints the current one. Running programs are notified of changes, so that they switch their theme immediately.
+    ColorMode {
+        /// The mode to switch to.
+        #[arg(value_enum)]
+        mode: Option<ColorModeArg>,
+
+        /// Only change the theme of apps.
+        #[arg(short, long, conflicts_with = "system_only", requires = "mode")]
+        apps_only: bool,
+
+        /// Only change the theme of Windows itself (taskbar, Start menu etc.).
+        #[arg(short, long, requires = "mode")]
+        system_only: bool,
+    },
+
+    /// Query and change the accent color.
+    ///
+    /// Without a color, prints the current one with its palette. Setting a color derives the palette's lighter and darker shades from it. Running programs are notified of changes.
+    AccentColor {
+        /// The color to switch to, as six hex digits, like '#0078d4'.
+        color: Option<Rgb>,
+    },
+
+    /// Query and change the taskbar's alignment, size, search box and auto-hiding.
+    ///
+    /// Without options, prints the current settings. Running programs are notified of changes.
+    Taskbar {
+        #[arg(short, long, value_enum)]
+        alignment: Option<TaskbarAlignmentArg>,
+
+        /// Ignored by Windows 11 since version 22H2.
+        #[arg(long, value_enum)]
+        size: Option<TaskbarSizeArg>,
+
+        #[arg(short, long, value_enum)]
+        search: Option<SearchBoxModeArg>,
+
+        #[arg(short = 'H', long)]
+        auto_hide: Option<bool>,
+    },
+
+    /// Query and change when the energy saver (battery saver) turns on.
+    ///
+    /// Without a threshold, prints the current one and whether the energy saver is on.
+    EnergySaver {
+        /// The battery percentage at or below which it turns on. 0 for never, 100 for always.
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+        threshold: Option<u8>,
+    },
+
+    /// Send a request to the running daemon and print its response as JSON.
+    Ctl {
+        #[command(subcommand)]
+        request: CtlRequest,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Print the path of the configuration file.
+    Path,
+
+    /// Open the configuration file in the editor from the 'EDITOR' environment variable or in Notepad. Creates the file with commented examples, if it doesn't exist.
+    Edit,
+
+    /// Print the effective configuration.
+    Show,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum SchemaAction {
+    /// List the loaded definitions.
+    List,
+
+    /// Print the registry value of a definition as JSON, with the defined field names. Undefined fields are named like '#30'.
+    Show {
+        /// The name of the definition.
+        name: String,
+    },
+
+    /// Set a field of the registry value of a definition.
+    Set {
+        /// The name of the definition.
+        name: String,
+
+        /// The dot-separated field names, like 'scheduledNightStart.hour'.
+        field: String,
+
+        /// The new value as JSON, like 'true', '30' or '"text"'. Unquoted text that isn't valid JSON is taken as a string. 'null' removes the field.
+        value: String,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum AutostartAction {
+    /// List the entries.
+    List,
+
+    /// Create an entry running this program in a resident mode, or update the entry with the same name.
+    Add {
+        mode: ResidentMode,
+
+        #[arg(short, long, value_enum, default_value_t = AutostartKindArg::Run)]
+        kind: AutostartKindArg,
+
+        /// Defaults to the name of the mode.
+        #[arg(short, long)]
+        name: Option<String>,
+    },
+
+    /// Remove the entries with the name.
+    Remove {
+        name: String,
+
+        /// Only remove the entry of this kind.
+        #[arg(short, long, value_enum)]
+        kind: Option<AutostartKindArg>,
+    },
+
+    /// Remove all stale entries.
+    Prune,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ResidentMode {
+    /// 'night-light keep-initing'.
+    #[value(alias = "keep")]
+    KeepIniting,
+    /// 'daemon'.
+    Daemon,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum AutostartKindArg {
+    /// A value of the 'Run' registry key.
+    Run,
+    /// A scheduled task triggered on log-on. Creating it may require an elevated prompt.
+    Task,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ColorModeArg {
+    Light,
+    Dark,
+    /// Switch to the opposite of the current mode. In the custom mode, where apps and Windows differ, the theme of apps decides.
+    Toggle,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum TaskbarAlignmentArg {
+    Left,
+    Center,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum TaskbarSizeArg {
+    Small,
+    Medium,
+    Large,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum SearchBoxModeArg {
+    Hidden,
+    Icon,
+    Box,
+    IconAndLabel,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ConflictRuleArg {
+    /// Always overwrite.
+    Overwrite,
+    /// Don't overwrite values the target user changed more recently.
+    Newer,
+    /// Don't overwrite values the target user changed since they were last mirrored.
+    KeepLocal,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum BundleAction {
+    /// Write the current settings into a bundle file.
+    Export {
+        /// The JSON file to write.
+        output: PathBuf,
+    },
+
+    /// Apply the settings of a bundle file that differ from the current ones.
+    Apply {
+        /// The bundle file to read.
+        input: PathBuf,
+
+        /// Write a .reg file to undo the changes with beforehand.
+        #[arg(short, long)]
+        backup: Option<PathBuf>,
+    },
+}
@@ -0,0 +1,93 @@
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use sem_reg::{
+    cloud_store::corpus::{self, Corpus, CorpusEntry, Redaction},
+    data_conversion::hex_bytes::HexBytes,
+    log_warn,
+    reg::read_reg_bin_value,
+};
+
+pub fn run(
+    output_file_path: &Path,
+    filter: Option<&str>,
+    unknown_only: bool,
+    redaction: Redaction,
+    yes: bool,
+) -> anyhow::Result<()> {
+    let filter = filter.map(str::to_lowercase);
+    let reg_value_paths = corpus::cloud_store_value_paths()?
+        .into_iter()
+        .filter(|reg_value_path| {
+            filter.as_ref().map_or(true, |filter| {
+                reg_value_path.subkey_path.to_lowercase().contains(filter)
+            })
+        })
+        .filter(|reg_value_path| !unknown_only || !corpus::is_known(reg_value_path))
+        .collect::<Vec<_>>();
+
+    let mut entries = Vec::new();
+    let mut include_all = yes;
+    let mut stdin = io::stdin().lock();
+
+    for (index, reg_value_path) in reg_value_paths.iter().enumerate() {
+        let bytes = match read_reg_bin_value(&reg_value_path.as_borrowed()) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                log_warn!("skipping '{}': {error}", reg_value_path.subkey_path);
+                continue;
+            }
+        };
+
+        //. Unredacted values could leak the personal data that was asked to be redacted.
+        let data = match corpus::redact(&bytes, redaction) {
+            Ok(data) => data,
+            Err(_) => {
+                log_warn!(
+                    "skipping '{}', because it can't be redacted",
+                    reg_value_path.subkey_path
+                );
+                continue;
+            }
+        };
+
+        if !include_all {
+            println!(
+                "[{}/{}] {}",
+                index + 1,
+                reg_value_paths.len(),
+                reg_value_path.subkey_path
+            );
+            println!("{}", HexBytes::new(&data).diff_against(&bytes));
+
+            match prompt(&mut stdin, "Include? [y]es, [n]o, [a]ll, [q]uit: ")?.as_str() {
+                "y" => {}
+                "a" => include_all = true,
+                "q" => break,
+                _ => continue,
+            }
+        }
+
+        entries.push(CorpusEntry::new(reg_value_path, data, !redaction.is_none()));
+    }
+
+    let num_entries = entries.len();
+    Corpus::new(entries).write(output_file_path)?;
+    println!(
+        "Wrote {num_entries} values to '{}'.",
+        output_file_path.display()
+    );
+
+    Ok(())
+}
+
+fn prompt<R: BufRead>(reader: &mut R, text: &str) -> Result<String, io::Error> {
+    print!("{text}");
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    Ok(line.trim().to_lowercase())
+}
@@ -0,0 +1,39 @@
+use crate::{cli::ColorModeArg, night_light::cli::GlobalArgs};
+use sem_reg::{log_info, personalization::color_mode::ColorMode};
+
+pub fn run(
+    global_args: &GlobalArgs,
+    mode: Option<ColorModeArg>,
+    apps_only: bool,
+    system_only: bool,
+) -> anyhow::Result<()> {
+    let mut color_mode = ColorMode::from_reg()?;
+
+    if let Some(mode) = mode {
+        let light = match mode {
+            ColorModeArg::Light => true,
+            ColorModeArg::Dark => false,
+            ColorModeArg::Toggle => !color_mode.apps_use_light_theme(),
+        };
+
+        if !system_only {
+            color_mode.set_apps_use_light_theme(light);
+        }
+        if !apps_only {
+            color_mode.set_system_uses_light_theme(light);
+        }
+    }
+
+    if global_args.json {
+        println!("{}", color_mode.to_json());
+    } else {
+        println!("{color_mode}");
+    }
+
+    if mode.is_some() && !global_args.dry_run {
+        color_mode.write_to_reg()?;
+        log_info!("wrote color mode");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use anyhow::anyhow;
+use std::{env, ffi::OsString, fs, process::Command};
+
+use crate::cli::ConfigAction;
+use sem_reg::config::Config;
+
+/// Written when editing a non-existent configuration file.
+const TEMPLATE: &str = r#"# Configuration of `sem-reg` and `night-light`. Flags specified on the command line take precedence.
+
+[nightLight]
+# amPm = true
+# gamma = 1.6
+# initDuration = 200
+# keepInitingDelay = 100
+# verifyDelay = 300
+
+[logging]
+# level = "info"
+# format = "text"
+# file = 'C:\Users\<user>\AppData\Roaming\sem-reg\sem-reg.log'
+
+[daemon]
+# initDuration = 200
+# keepInitingDelay = 100
+
+# Retrying of registry operations that fail transiently, e.g., while Windows rewrites values.
+[registry]
+# retryAttempts = 4
+# retryDelay = 25
+
+# Notified about changes by `sem-reg daemon` and `night-light monitor`. Only `http://` URLs are supported.
+# [[webhooks]]
+# url = "http://localhost:8123/api/webhook/night-light"
+# headers = { Authorization = "Bearer <token>" }
+# maxRetries = 3
+
+# Apply with `night-light profile movie`.
+# [profiles.movie]
+# active = true
+# nightColorTemp = 2700
+"#;
+
+pub fn run(action: ConfigAction) -> anyhow::Result<()> {
+    let file_path =
+        Config::file_path().ok_or_else(|| anyhow!("'APPDATA' environment variable not set"))?;
+
+    match action {
+        ConfigAction::Path => println!("{}", file_path.display()),
+
+        ConfigAction::Edit => {
+            if !file_path.exists() {
+                if let Some(dir_path) = file_path.parent() {
+                    fs::create_dir_all(dir_path)?;
+                }
+                fs::write(&file_path, TEMPLATE)?;
+            }
+
+            let editor = env::var_os("EDITOR").unwrap_or_else(|| OsString::from("notepad.exe"));
+            Command::new(editor).arg(&file_path).status()?;
+
+            //. Report mistakes immediately.
+            Config::load()?;
+        }
+
+        ConfigAction::Show => print!("{}", Config::load()?.to_toml()),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use anyhow::anyhow;
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::night_light::{
+    app,
+    cli::{CtlRequest, GlobalArgs},
+};
+use sem_reg::{
+    cloud_store::night_light::{self, NightLight},
+    config::Config,
+    daemon::{self, Request, Response},
+    log_debug, log_error, log_info,
+    reg::monitor::StopSignal,
+    webhook::Notifier,
+};
+
+pub fn run(
+    global_args: &GlobalArgs,
+    config: &Config,
+    delay_millis: Option<u16>,
+    duration_millis: Option<u16>,
+) -> anyhow::Result<()> {
+    if global_args.dry_run {
+        return Err(anyhow!("'--dry-run' isn't supported by this command"));
+    }
+
+    let lenient = global_args.lenient;
+    let delay_millis = delay_millis
+        .or(config.daemon.keep_initing_delay)
+        .or(config.night_light.keep_initing_delay)
+        .unwrap_or(app::DEFAULT_KEEP_INITING_DELAY);
+    let duration_millis = duration_millis
+        .or(config.daemon.init_duration)
+        .or(config.night_light.init_duration);
+
+    //. Held until the keep-initing thread was joined.
+    let _keep_initing_mutex = app::acquire_keep_initing_mutex(true)?;
+    app::init_night_light(duration_millis, false, lenient)?;
+
+    let keep_initing_thread = thread::spawn(move || {
+        let result = app::keep_initing(delay_millis, duration_millis, lenient, Default::default());
+
+        //. When ended by Ctrl+C or an error, end the whole daemon.
+        let _ = daemon::send_request(&Request::Stop);
+        result
+    });
+
+    //. Kept up to date by the monitor, so that status requests are answered without registry access.
+    let status = Arc::new(Mutex::new(app::read_status(lenient)?));
+
+    let monitor_stop_signal = StopSignal::new();
+    let monitor_thread = thread::spawn({
+        let status = status.clone();
+        let notifier = Notifier::new(config.webhooks.clone());
+        let monitor_stop_signal = monitor_stop_signal.clone();
+        move || {
+            NightLight::monitor_blocking(Some(&monitor_stop_signal), |_| {
+                //. Reading may fail while the values are being written; the next event brings the final state.
+                if let Ok(new_status) = app::read_status(lenient) {
+                    let mut status = status.lock().unwrap();
+                    if *status != new_status {
+                        notifier.notify("nightLight", new_status.clone());
+                    }
+                    *status = new_status;
+                }
+                None::<Result<(), night_light::Error>>
+            })
+        }
+    });
+
+    log_info!("daemon started");
+
+    let serve_result = daemon::serve(|request| {
+        log_debug!("received request: {request:?}");
+
+        let result = match request {
+            Request::Status => Ok(status.lock().unwrap().clone()),
+            Request::SetNightLight(change) => app::set_night_light(&change, lenient),
+            Request::Toggle => {
+                app::toggle_night_light(lenient).and_then(|_| app::read_status(lenient))
+            }
+            Request::Reinit => app::init_night_light(duration_millis, false, lenient)
+                .map_err(anyhow::Error::from)
+                .and_then(|_| app::read_status(lenient)),
+            Request::Health => {
+                return Response::Error {
+                    message: "only 'night-light keep-initing' reports its health".to_string(),
+                }
+            }
+            Request::Stop => return Response::Done,
+        };
+
+        match result {
+            Ok(night_light) => {
+                *status.lock().unwrap() = night_light.clone();
+                Response::Status { night_light }
+            }
+            Err(error) => {
+                log_error!("handling request failed: {error}");
+                Response::Error {
+                    message: error.to_string(),
+                }
+            }
+        }
+    });
+
+    log_info!("daemon stopping");
+
+    app::stop_keep_initing()?;
+    monitor_stop_signal.stop();
+
+    let keep_initing_result = keep_initing_thread
+        .join()
+        .map_err(|_| anyhow!("keep-initing thread panicked"))?;
+    let monitor_result = monitor_thread
+        .join()
+        .map_err(|_| anyhow!("monitor thread panicked"))?;
+
+    serve_result?;
+    keep_initing_result?;
+    monitor_result?;
+    Ok(())
+}
+
+pub fn ctl(global_args: &GlobalArgs, ctl_request: CtlRequest) -> anyhow::Result<()> {
+    if global_args.dry_run && !matches!(ctl_request, CtlRequest::Status) {
+        return Err(anyhow!("'--dry-run' isn't supported by this command"));
+    }
+
+    app::ctl(
+        &daemon::pipe_name()?,
+        "no daemon running; start one with 'sem-reg daemon'",
+        ctl_request,
+    )
+}
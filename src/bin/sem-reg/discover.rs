@@ -0,0 +1,47 @@
+use std::io::{self, BufRead, Write};
+
+use sem_reg::{cloud_store::discover, data_conversion::hex_bytes::HexBytes};
+
+pub fn run(filter: Option<&str>) -> anyhow::Result<()> {
+    let filter = filter.map(str::to_lowercase);
+    let mut stdin = io::stdin().lock();
+    let mut snapshot = discover::take_snapshot()?;
+    println!("Took snapshot of {} values.", snapshot.values.len());
+
+    loop {
+        print!("Change the setting, then press Enter (or enter 'q' to quit): ");
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 || line.trim().eq_ignore_ascii_case("q") {
+            return Ok(());
+        }
+
+        let new_snapshot = discover::take_snapshot()?;
+        let changes = discover::changes_between(&snapshot, &new_snapshot)
+            .into_iter()
+            .filter(|change| {
+                filter.as_ref().map_or(true, |filter| {
+                    change.key_path.to_lowercase().contains(filter)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if changes.is_empty() {
+            println!("No values changed.");
+        }
+
+        for change in &changes {
+            println!("{}", change.key_path);
+            match (&change.old_bytes, &change.new_bytes) {
+                (Some(old_bytes), Some(new_bytes)) => {
+                    println!("{}", HexBytes::new(new_bytes).diff_against(old_bytes))
+                }
+                (None, Some(new_bytes)) => println!("Added: {}", HexBytes::new(new_bytes)),
+                _ => println!("Removed."),
+            }
+        }
+
+        snapshot = new_snapshot;
+    }
+}
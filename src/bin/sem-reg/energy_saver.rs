@@ -0,0 +1,24 @@
+use crate::night_light::cli::GlobalArgs;
+use sem_reg::{data_conversion::Strictness, energy_saver::EnergySaver, log_info};
+
+pub fn run(global_args: &GlobalArgs, threshold: Option<u8>) -> anyhow::Result<()> {
+    let mut energy_saver =
+        EnergySaver::from_reg_with_strictness(Strictness::from_lenient_bool(global_args.lenient))?;
+
+    if let Some(threshold) = threshold {
+        energy_saver.set_battery_threshold(threshold);
+    }
+
+    if global_args.json {
+        println!("{}", energy_saver.to_json());
+    } else {
+        println!("{energy_saver}");
+    }
+
+    if threshold.is_some() && !global_args.dry_run {
+        energy_saver.write_to_reg()?;
+        log_info!("wrote energy saver threshold");
+    }
+
+    Ok(())
+}
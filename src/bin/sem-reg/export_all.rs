@@ -0,0 +1,17 @@
+use std::path::Path;
+
+use crate::night_light::cli::GlobalArgs;
+use sem_reg::{cloud_store::corpus, reg::reg_file::RegFile};
+
+pub fn run(global_args: &GlobalArgs, output_file_path: &Path) -> anyhow::Result<()> {
+    let reg_value_paths = corpus::cloud_store_value_paths()?;
+    let reg_file = RegFile::from_reg_values(&reg_value_paths, true)?;
+
+    if global_args.dry_run {
+        print!("{reg_file}");
+    } else {
+        reg_file.export(output_file_path)?;
+    }
+
+    Ok(())
+}
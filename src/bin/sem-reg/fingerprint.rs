@@ -0,0 +1,28 @@
+use crate::night_light::cli::GlobalArgs;
+use sem_reg::cloud_store::fingerprint;
+
+pub fn run(global_args: &GlobalArgs) -> anyhow::Result<()> {
+    let fingerprints = fingerprint::fingerprints()?;
+
+    if global_args.json {
+        println!("{}", serde_json::to_string_pretty(&fingerprints)?);
+    } else {
+        for fingerprint in &fingerprints {
+            let name = format!("{}.{}", fingerprint.setting, fingerprint.value);
+            match &fingerprint.structure {
+                Some(structure) => {
+                    println!("{name}: {}", structure.hash);
+                    println!("  length: {}", structure.len);
+                    println!("  prologue: {}", structure.prologue);
+                    println!(
+                        "  fields: {}",
+                        structure.fields.as_deref().unwrap_or("(not parsable)")
+                    );
+                }
+                None => println!("{name}: missing"),
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+mod accent_color;
+mod audit;
+mod autostart;
+mod bundle;
+mod cli;
+mod collect;
+mod color_mode;
+mod config;
+mod daemon;
+mod discover;
+mod energy_saver;
+mod export_all;
+mod fingerprint;
+mod mirror;
+mod recover;
+mod schema;
+mod taskbar;
+
+/// The modules of the `night-light` binary, shared to provide the same commands.
+#[path = "../night-light"]
+mod night_light {
+    pub mod app;
+    pub mod cli;
+    pub mod error_output;
+    pub mod tray;
+}
+
+use clap::Parser;
+use std::process::ExitCode;
+
+use cli::{Cli, Setting};
+use night_light::cli::InitDurationArg;
+use sem_reg::{cloud_store::corpus::Redaction, config::Config, reg::retry};
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let error_format = cli.global_args.error_format;
+
+    night_light::error_output::report(error_format, run(cli))
+}
+
+fn run(cli: Cli) -> anyhow::Result<()> {
+    //. Must work with an invalid configuration file, to be able to fix it.
+    if let Setting::Config { action } = cli.setting {
+        return config::run(action);
+    }
+
+    let config = Config::load()?;
+    cli.global_args.init_logging(&config.logging)?;
+    retry::set_policy(config.registry.retry_policy());
+
+    match cli.setting {
+        Setting::NightLight(night_light_args) => {
+            night_light::app::run(&cli.global_args, &config, night_light_args)
+        }
+        Setting::Daemon {
+            delay,
+            init_duration_arg: InitDurationArg { duration },
+        } => daemon::run(&cli.global_args, &config, delay, duration),
+        Setting::Schema { action } => schema::run(&cli.global_args, action),
+        Setting::Bundle { action } => bundle::run(&cli.global_args, action),
+        Setting::Audit => audit::run(&cli.global_args),
+        Setting::Fingerprint => fingerprint::run(&cli.global_args),
+        Setting::Collect {
+            output,
+            filter,
+            unknown_only,
+            redact_strings,
+            redact_timestamps,
+            yes,
+        } => collect::run(
+            &output,
+            filter.as_deref(),
+            unknown_only,
+            Redaction {
+                strings: redact_strings,
+                timestamps: redact_timestamps,
+            },
+            yes,
+        ),
+        Setting::Discover { filter } => discover::run(filter.as_deref()),
+        Setting::ExportAll { output } => export_all::run(&cli.global_args, &output),
+        Setting::Autostart { action } => autostart::run(&cli.global_args, action),
+        Setting::ColorMode {
+            mode,
+            apps_only,
+            system_only,
+        } => color_mode::run(&cli.global_args, mode, apps_only, system_only),
+        Setting::AccentColor { color } => accent_color::run(&cli.global_args, color),
+        Setting::Taskbar {
+            alignment,
+            size,
+            search,
+            auto_hide,
+        } => taskbar::run(&cli.global_args, alignment, size, search, auto_hide),
+        Setting::EnergySaver { threshold } => energy_saver::run(&cli.global_args, threshold),
+        Setting::Ctl { request } => daemon::ctl(&cli.global_args, request),
+        Setting::Mirror { from, to, rule } => mirror::run(&cli.global_args, from, to, rule),
+        Setting::Recover { roll_back } => recover::run(&cli.global_args, roll_back),
+        Setting::Config { .. } => unreachable!(),
+    }
+}
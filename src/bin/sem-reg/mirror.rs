@@ -0,0 +1,116 @@
+use futures::channel::oneshot;
+use std::collections::HashMap;
+
+use crate::{cli::ConflictRuleArg, night_light::cli::GlobalArgs};
+use sem_reg::{
+    cloud_store::night_light::NightLight,
+    log_info, log_warn,
+    mirror::{ConflictRule, Mirror, MirrorAction, MirrorDecision, SkipReason},
+    reg::sid::{current_user_sid, user_name_to_sid},
+};
+
+pub fn run(
+    global_args: &GlobalArgs,
+    from: Option<String>,
+    to: Vec<String>,
+    rule: ConflictRuleArg,
+) -> anyhow::Result<()> {
+    //. For messages, show users like they were specified.
+    let mut user_names_of_sids = HashMap::new();
+
+    let source_sid = match from {
+        Some(user) => resolve_user(&user, &mut user_names_of_sids)?,
+        None => current_user_sid()?,
+    };
+    let target_sids = to
+        .iter()
+        .map(|user| resolve_user(user, &mut user_names_of_sids))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut mirror = Mirror::new(
+        &NightLight::REG_VALUE_PATHS,
+        source_sid,
+        target_sids,
+        match rule {
+            ConflictRuleArg::Overwrite => ConflictRule::Overwrite,
+            ConflictRuleArg::Newer => ConflictRule::Newer,
+            ConflictRuleArg::KeepLocal => ConflictRule::KeepLocalChanges,
+        },
+    );
+
+    let describe = |action: &MirrorAction| {
+        format!(
+            "'{}' of '{}'",
+            action.reg_value_path.value_name,
+            user_names_of_sids
+                .get(&action.target_sid)
+                .unwrap_or(&action.target_sid)
+        )
+    };
+
+    if global_args.dry_run {
+        for action in mirror.plan() {
+            println!(
+                "{}: {}",
+                describe(&action),
+                match action.decision {
+                    MirrorDecision::Write(_) => "would write",
+                    MirrorDecision::Unchanged => "unchanged",
+                    MirrorDecision::Skip(reason) => skip_reason_text(reason),
+                }
+            );
+        }
+
+        return Ok(());
+    }
+
+    let (stop_sender, stop_receiver) = oneshot::channel::<()>();
+    let mut stop_sender = Some(stop_sender);
+    ctrlc::set_handler(move || {
+        if let Some(stop_sender) = stop_sender.take() {
+            let _ = stop_sender.send(());
+        }
+    })?;
+
+    println!("Mirroring. Press Ctrl+C to stop.");
+
+    mirror.run(Some(stop_receiver), |action, result| {
+        match (&action.decision, result) {
+            (MirrorDecision::Write(_), Ok(())) => log_info!("mirrored {}", describe(action)),
+            (MirrorDecision::Write(_), Err(error)) => {
+                log_warn!("mirroring {} failed: {error}", describe(action))
+            }
+            (MirrorDecision::Skip(reason), _) => {
+                log_info!("{}: {}", describe(action), skip_reason_text(*reason))
+            }
+            (MirrorDecision::Unchanged, _) => {}
+        }
+    })?;
+
+    Ok(())
+}
+
+fn resolve_user(
+    user: &str,
+    user_names_of_sids: &mut HashMap<String, String>,
+) -> anyhow::Result<String> {
+    //! Accepts SIDs as is.
+
+    let sid = if user.starts_with("S-1-") {
+        user.to_string()
+    } else {
+        user_name_to_sid(user)?
+    };
+
+    user_names_of_sids.insert(sid.clone(), user.to_string());
+    Ok(sid)
+}
+
+fn skip_reason_text(reason: SkipReason) -> &'static str {
+    match reason {
+        SkipReason::SourceMissing => "skipped, doesn't exist for source user",
+        SkipReason::HiveNotLoaded => "skipped, user not logged on",
+        SkipReason::TargetNewer => "skipped, changed more recently by user",
+        SkipReason::LocalChange => "skipped, changed by user since last mirrored",
+    }
+}
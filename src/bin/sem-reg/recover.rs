@@ -0,0 +1,55 @@
+use crate::night_light::cli::GlobalArgs;
+use sem_reg::{
+    log_info,
+    reg::wal::{self, WriteStatus},
+};
+
+pub fn run(global_args: &GlobalArgs, roll_back: bool) -> anyhow::Result<()> {
+    let transactions = wal::pending()?;
+
+    if transactions.is_empty() {
+        println!("No interrupted writes.");
+        return Ok(());
+    }
+
+    for transaction in transactions {
+        let time = chrono::DateTime::<chrono::Local>::from(transaction.intent.time());
+        println!(
+            "Interrupted at {} (process {}):",
+            time.format("%Y-%m-%d %H:%M:%S"),
+            transaction.intent.pid
+        );
+
+        for (write, status) in transaction
+            .intent
+            .writes
+            .iter()
+            .zip(transaction.statuses()?)
+        {
+            println!(
+                "  {}\\{}: {}",
+                write.key_path,
+                write.value_name,
+                match status {
+                    WriteStatus::Applied => "written",
+                    WriteStatus::NotApplied => "not written",
+                    WriteStatus::Diverged => "changed since, left alone",
+                }
+            );
+        }
+
+        if global_args.dry_run {
+            continue;
+        }
+
+        if roll_back {
+            transaction.roll_back()?;
+            log_info!("rolled back interrupted writes");
+        } else {
+            transaction.complete()?;
+            log_info!("completed interrupted writes");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use anyhow::anyhow;
+
+use crate::{cli::SchemaAction, night_light::cli::GlobalArgs};
+use sem_reg::{
+    cloud_store::schema::ValueSchema, config::Config, data_conversion::Strictness, log_info,
+};
+
+pub fn run(global_args: &GlobalArgs, action: SchemaAction) -> anyhow::Result<()> {
+    let dir_path = Config::schema_dir_path()
+        .ok_or_else(|| anyhow!("'APPDATA' environment variable not set"))?;
+    let schemas = ValueSchema::load_dir(&dir_path)?;
+    let find_schema = |name: &str| {
+        schemas
+            .iter()
+            .find(|schema| schema.name == name)
+            .ok_or_else(|| anyhow!("no definition named '{name}' in '{}'", dir_path.display()))
+    };
+    let strictness = Strictness::from_lenient_bool(global_args.lenient);
+
+    match action {
+        SchemaAction::List => {
+            if global_args.json {
+                println!("{}", serde_json::to_string_pretty(&schemas)?);
+            } else {
+                for schema in &schemas {
+                    println!(
+                        "{}: {}\\{}",
+                        schema.name, schema.key_path, schema.value_name
+                    );
+                }
+            }
+        }
+
+        SchemaAction::Show { name } => {
+            let schema = find_schema(&name)?;
+            let value = schema.read(strictness)?;
+            println!("{}", serde_json::to_string_pretty(&schema.to_json(&value))?);
+        }
+
+        SchemaAction::Set { name, field, value } => {
+            let schema = find_schema(&name)?;
+            let json = serde_json::from_str(&value).unwrap_or(serde_json::Value::String(value));
+
+            let mut cloud_store_value = schema.read(strictness)?;
+            schema.set(&mut cloud_store_value, &field, &json)?;
+
+            if global_args.dry_run {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&schema.to_json(&cloud_store_value))?
+                );
+            } else {
+                schema.write(&cloud_store_value)?;
+                log_info!("set field '{field}' of '{name}'");
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use crate::{
+    cli::{SearchBoxModeArg, TaskbarAlignmentArg, TaskbarSizeArg},
+    night_light::cli::GlobalArgs,
+};
+use sem_reg::{
+    data_conversion::Strictness,
+    log_info,
+    personalization::taskbar::{SearchBoxMode, Taskbar, TaskbarAlignment, TaskbarSize},
+};
+
+pub fn run(
+    global_args: &GlobalArgs,
+    alignment: Option<TaskbarAlignmentArg>,
+    size: Option<TaskbarSizeArg>,
+    search: Option<SearchBoxModeArg>,
+    auto_hide: Option<bool>,
+) -> anyhow::Result<()> {
+    let mut taskbar =
+        Taskbar::from_reg_with_strictness(Strictness::from_lenient_bool(global_args.lenient))?;
+
+    if let Some(alignment) = alignment {
+        taskbar.set_alignment(match alignment {
+            TaskbarAlignmentArg::Left => TaskbarAlignment::Left,
+            TaskbarAlignmentArg::Center => TaskbarAlignment::Center,
+        });
+    }
+    if let Some(size) = size {
+        taskbar.set_size(match size {
+            TaskbarSizeArg::Small => TaskbarSize::Small,
+            TaskbarSizeArg::Medium => TaskbarSize::Medium,
+            TaskbarSizeArg::Large => TaskbarSize::Large,
+        });
+    }
+    if let Some(search) = search {
+        taskbar.set_search_box_mode(match search {
+            SearchBoxModeArg::Hidden => SearchBoxMode::Hidden,
+            SearchBoxModeArg::Icon => SearchBoxMode::Icon,
+            SearchBoxModeArg::Box => SearchBoxMode::Box,
+            SearchBoxModeArg::IconAndLabel => SearchBoxMode::IconAndLabel,
+        });
+    }
+    if let Some(auto_hide) = auto_hide {
+        taskbar.set_auto_hide(auto_hide);
+    }
+
+    if global_args.json {
+        println!("{}", taskbar.to_json());
+    } else {
+        println!("{taskbar}");
+    }
+
+    let changing = alignment.is_some() || size.is_some() || search.is_some() || auto_hide.is_some();
+    if changing && !global_args.dry_run {
+        taskbar.write_to_reg()?;
+        log_info!("wrote taskbar settings");
+    }
+
+    Ok(())
+}
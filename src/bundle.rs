@@ -0,0 +1,344 @@
+//! Portable settings bundles: single JSON files with the parsed settings of a user, so that they can be carried to another PC and applied there. Every bundle records its format version and metadata of the machine it was exported on.
+//!
+//! Besides Night Light, bundles contain the values of all CloudStore value definitions (see [`schema`](crate::cloud_store::schema)) that are readable on export. On applying, only values whose definition is present are applied.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, env, io, str::FromStr, thread, time::Duration};
+use thiserror::Error;
+
+use crate::{
+    cloud_store::{
+        corpus::os_build,
+        night_light::{self, ClockTimeFrame, NightLight, ScheduleType},
+        schema::{SchemaError, ValueSchema},
+    },
+    data_conversion::Strictness,
+};
+
+/// The version of the bundle format written by this crate. Bundles with greater versions are rejected.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Between writing the Night Light settings and the state, so that Windows can react to schedule changes first.
+const STATE_WRITE_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Bundle {
+    pub format_version: u32,
+    /// The local time of the export in RFC 3339 format.
+    pub created: String,
+    pub machine: MachineInfo,
+    pub settings: BundleSettings,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MachineInfo {
+    pub computer_name: Option<String>,
+    /// Like `22631.2861`.
+    pub os_build: Option<String>,
+    /// The version of the crate that exported the bundle.
+    pub crate_version: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BundleSettings {
+    /// `None`, if Night Light was never used on the machine.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub night_light: Option<NightLightPrefs>,
+    /// The JSON representations of CloudStore values by the names of their definitions.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub schema_values: BTreeMap<String, serde_json::Value>,
+}
+
+/// The user-chosen part of the Night Light configuration. Transient properties like the preview state aren't included.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NightLightPrefs {
+    /// Only applied, if the schedule isn't active.
+    pub active: bool,
+    pub schedule_active: bool,
+    pub schedule_type: ScheduleType,
+    /// In 24-hour format, like `21:00-07:00`.
+    pub scheduled_night: String,
+    /// `None` for the default color temperature.
+    pub night_color_temp: Option<u16>,
+}
+
+impl Bundle {
+    pub fn export(schemas: &[ValueSchema], strictness: Strictness) -> Result<Self, BundleError> {
+        //! Reads the current settings. Values that don't exist are omitted.
+
+        let night_light = match NightLight::from_reg_with_strictness(strictness) {
+            Ok(night_light) => Some(NightLightPrefs {
+                active: night_light.active(),
+                schedule_active: night_light.schedule_active(),
+                schedule_type: night_light.schedule_type(),
+                scheduled_night: night_light.scheduled_night().format(false),
+                night_color_temp: night_light.night_color_temp(),
+            }),
+            Err(night_light::Error::IoError(error)) if error.kind() == io::ErrorKind::NotFound => {
+                None
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut schema_values = BTreeMap::new();
+        for schema in schemas {
+            match schema.read(strictness) {
+                Ok(value) => {
+                    schema_values.insert(schema.name.clone(), schema.to_json(&value));
+                }
+                Err(SchemaError::IoError(error)) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        Ok(Self {
+            format_version: FORMAT_VERSION,
+            created: chrono::Local::now().to_rfc3339(),
+            machine: MachineInfo {
+                computer_name: env::var("COMPUTERNAME").ok(),
+                os_build: os_build(),
+                crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            settings: BundleSettings {
+                night_light,
+                schema_values,
+            },
+        })
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, BundleError> {
+        //! Fails for bundles of a newer format version.
+
+        let bundle = serde_json::from_str::<Self>(json)?;
+        if bundle.format_version > FORMAT_VERSION {
+            return Err(BundleError::UnsupportedVersion(bundle.format_version));
+        }
+
+        Ok(bundle)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("serializing bundle to JSON shouldn't fail")
+    }
+
+    pub fn apply(
+        &self,
+        schemas: &[ValueSchema],
+        strictness: Strictness,
+        dry_run: bool,
+    ) -> Result<Vec<String>, BundleError> {
+        //! Writes the settings that differ from the current ones and returns descriptions of the changes. With `dry_run`, nothing is written. Schema values without a present definition or registry value are skipped with a description; fields undefined in the definition and lists are left unchanged.
+
+        let mut changes = Vec::new();
+
+        if let Some(prefs) = &self.settings.night_light {
+            self.apply_night_light(prefs, strictness, dry_run, &mut changes)?;
+        }
+
+        for (name, json) in &self.settings.schema_values {
+            let Some(schema) = schemas.iter().find(|schema| schema.name == *name) else {
+                changes.push(format!("{name} (skipped, no definition present)"));
+                continue;
+            };
+
+            let old_value = match schema.read(strictness) {
+                Ok(value) => value,
+                Err(SchemaError::IoError(error)) if error.kind() == io::ErrorKind::NotFound => {
+                    changes.push(format!("{name} (skipped, value not present)"));
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+            let mut new_value = old_value.clone();
+            let mut leaves = Vec::new();
+            json_leaves("", json, &mut leaves);
+            for (field_path, json) in leaves {
+                schema.set(&mut new_value, &field_path, json)?;
+            }
+
+            //. Not the bytes, which always carry a new prologue timestamp.
+            if new_value.fields != old_value.fields {
+                if !dry_run {
+                    schema.write(&new_value)?;
+                }
+                changes.push(name.clone());
+            }
+        }
+
+        Ok(changes)
+    }
+
+    fn apply_night_light(
+        &self,
+        prefs: &NightLightPrefs,
+        strictness: Strictness,
+        dry_run: bool,
+        changes: &mut Vec<String>,
+    ) -> Result<(), BundleError> {
+        let scheduled_night = ClockTimeFrame::from_str(&prefs.scheduled_night)
+            .map_err(|_| BundleError::InvalidValue("nightLight.scheduledNight".to_string()))?;
+
+        let mut night_light = NightLight::from_reg_with_strictness(strictness)?;
+        let mut settings_changed = false;
+        let mut change = |name: &str, differs: bool| {
+            if differs {
+                changes.push(format!("nightLight.{name}"));
+                settings_changed = true;
+            }
+        };
+
+        change(
+            "scheduleActive",
+            night_light.schedule_active() != prefs.schedule_active,
+        );
+        change(
+            "scheduleType",
+            night_light.schedule_type() != prefs.schedule_type,
+        );
+        change(
+            "scheduledNight",
+            night_light.scheduled_night() != scheduled_night,
+        );
+        change(
+            "nightColorTemp",
+            night_light.night_color_temp() != prefs.night_color_temp,
+        );
+
+        if settings_changed && !dry_run {
+            night_light.set_schedule_active(prefs.schedule_active);
+            night_light.set_schedule_type(prefs.schedule_type);
+            night_light.set_scheduled_night(scheduled_night);
+            night_light.set_night_color_temp(prefs.night_color_temp);
+            night_light.write_to_reg()?;
+        }
+
+        //. With an active schedule, Windows determines the state.
+        if !prefs.schedule_active {
+            if settings_changed && !dry_run {
+                thread::sleep(STATE_WRITE_DELAY);
+            }
+
+            let mut night_light = NightLight::from_reg_with_strictness(strictness)?;
+            if night_light.active() != prefs.active {
+                changes.push("nightLight.active".to_string());
+
+                if !dry_run {
+                    night_light.set_active(prefs.active);
+                    night_light.write_to_reg()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn json_leaves<'a>(
+    path: &str,
+    json: &'a serde_json::Value,
+    leaves: &mut Vec<(String, &'a serde_json::Value)>,
+) {
+    //! Collects the scalar values of a JSON object with their dot-separated paths. Undefined fields (named like `#30`) and arrays are omitted.
+
+    match json {
+        serde_json::Value::Object(map) => {
+            for (name, json) in map {
+                if name.starts_with('#') {
+                    continue;
+                }
+
+                let path = if path.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{path}.{name}")
+                };
+                json_leaves(&path, json, leaves);
+            }
+        }
+        serde_json::Value::Array(_) => {}
+        _ => leaves.push((path.to_string(), json)),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum BundleError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("bundle format version {0} is newer than supported; update the program")]
+    UnsupportedVersion(u32),
+    #[error("invalid value in bundle: {0}")]
+    InvalidValue(String),
+    #[error("Night Light error: {0}")]
+    NightLightError(#[from] night_light::Error),
+    #[error("{0}")]
+    SchemaError(#[from] SchemaError),
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{json_leaves, Bundle, BundleError, FORMAT_VERSION};
+
+    #[test]
+    fn parse_bundle() {
+        let json = r#"{
+            "formatVersion": 1,
+            "created": "2023-12-01T20:00:00+01:00",
+            "machine": { "computerName": "DESKTOP-1234", "osBuild": "22631.2861", "crateVersion": "0.1.1" },
+            "settings": {
+                "nightLight": {
+                    "active": false,
+                    "scheduleActive": true,
+                    "scheduleType": "sunsetToSunrise",
+                    "scheduledNight": "21:00-07:00",
+                    "nightColorTemp": 2700
+                }
+            }
+        }"#;
+
+        let bundle = Bundle::from_json(json).unwrap();
+        let night_light = bundle.settings.night_light.as_ref().unwrap();
+        assert_eq!(night_light.night_color_temp, Some(2700));
+        assert!(bundle.settings.schema_values.is_empty());
+        assert_eq!(Bundle::from_json(&bundle.to_json()).unwrap(), bundle);
+
+        let newer_json = json.replace(
+            r#""formatVersion": 1"#,
+            &format!(r#""formatVersion": {}"#, FORMAT_VERSION + 1),
+        );
+        assert!(matches!(
+            Bundle::from_json(&newer_json),
+            Err(BundleError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn schema_value_leaves() {
+        let json = json!({
+            "scheduleActive": true,
+            "scheduledNightStart": { "hour": 21, "minute": 0 },
+            "#30": 1,
+            "list": [1, 2],
+        });
+
+        let mut leaves = Vec::new();
+        json_leaves("", &json, &mut leaves);
+        leaves.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            leaves,
+            [
+                ("scheduleActive".to_string(), &json!(true)),
+                ("scheduledNightStart.hour".to_string(), &json!(21)),
+                ("scheduledNightStart.minute".to_string(), &json!(0)),
+            ]
+        );
+    }
+}
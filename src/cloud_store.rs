@@ -1,2 +1,13 @@
+pub mod annotated_hex;
+pub mod audit;
+pub mod corpus;
+pub mod discover;
+pub mod fields;
+pub mod fingerprint;
+pub mod focus_assist;
 pub mod night_light;
-mod prologue;
+pub(crate) mod prologue;
+pub mod schema;
+pub mod semantic_value;
+#[cfg(test)]
+pub(crate) mod test_fixtures;
@@ -0,0 +1,347 @@
+//! Hex dumps of CloudStore values in which every range of bytes is annotated with what it encodes: the prologue with its timestamp, and every field with its ID, type and value. Numbers that look like timestamps are additionally shown as dates. This helps reverse-engineering values the crate doesn't have dedicated types for.
+//!
+//! Bytes that can't be parsed, e.g., because of an unknown prologue variant, are dumped without annotation.
+
+use std::{fmt, ops::Range};
+
+use super::{
+    corpus::{MAX_EPOCH_SECS, MAX_FILETIME, REDACTED_EPOCH_SECS, REDACTED_FILETIME},
+    fields::{
+        read_field_header, read_field_value, read_len, FieldType, FieldValue, MAX_NESTING_DEPTH,
+    },
+    prologue::CloudStoreValuePrologue,
+};
+use crate::data_conversion::{
+    hex_bytes::HexBytes,
+    time::{utc_epoch_secs_to_local_iso_string, utc_filetime_to_local_iso_string},
+    ByteSeq, ParseError, Strictness,
+};
+
+/// The number of bytes per line of the dump.
+const BYTES_PER_LINE: usize = 16;
+
+pub struct AnnotatedHex<'a> {
+    bytes: &'a [u8],
+    annotations: Vec<Annotation>,
+    epoch_secs: Option<u32>,
+    num_body_bytes: Option<u32>,
+}
+
+/// A range of bytes and what it encodes.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Annotation {
+    pub range: Range<usize>,
+    /// The nesting depth within structs and lists, for indentation.
+    pub depth: usize,
+    /// Like `field 10: int32 = 1`.
+    pub note: String,
+}
+
+impl<'a> AnnotatedHex<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        //! Parses leniently as far as possible. The last annotation covers the unparsed rest, if any.
+
+        let mut byte_seq = ByteSeq::from_slice(bytes);
+        let mut annotations = Vec::new();
+        let prologue =
+            CloudStoreValuePrologue::from_byte_seq(&mut byte_seq, Strictness::Lenient).ok();
+
+        if let Some(prologue) = &prologue {
+            annotations.push(Annotation {
+                range: 0..byte_seq.read_index(),
+                depth: 0,
+                note: format!(
+                    "prologue: {}, {}",
+                    prologue
+                        .epoch_secs
+                        .and_then(utc_epoch_secs_to_local_iso_string)
+                        .unwrap_or_else(|| "no timestamp".to_string()),
+                    prologue.num_body_bytes.map_or_else(
+                        || "no body".to_string(),
+                        |num_body_bytes| format!("{num_body_bytes} body bytes")
+                    ),
+                ),
+            });
+
+            if prologue.num_body_bytes.is_some() {
+                let _ = annotate_body(&mut byte_seq, &mut annotations);
+            }
+        }
+
+        let parsed_len = annotations
+            .last()
+            .map_or(0, |annotation| annotation.range.end);
+        if parsed_len < bytes.len() {
+            annotations.push(Annotation {
+                range: parsed_len..bytes.len(),
+                depth: 0,
+                note: "unparsed".to_string(),
+            });
+        }
+
+        Self {
+            bytes,
+            annotations,
+            epoch_secs: prologue.as_ref().and_then(|prologue| prologue.epoch_secs),
+            num_body_bytes: prologue.and_then(|prologue| prologue.num_body_bytes),
+        }
+    }
+
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    pub fn epoch_secs(&self) -> Option<u32> {
+        //! The timestamp of the prologue. `None`, if it has none or couldn't be parsed.
+
+        self.epoch_secs
+    }
+
+    pub fn num_body_bytes(&self) -> Option<u32> {
+        //! The body length according to the prologue. `None`, if the value is bodyless or the prologue couldn't be parsed.
+
+        self.num_body_bytes
+    }
+}
+
+impl fmt::Display for AnnotatedHex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //! Writes a line per annotation, like `0013  d0 0a 02    field 10: int32 = 1`. Ranges longer than a line are continued on further lines without a note.
+
+        let hex_width = BYTES_PER_LINE * 3 - 1;
+
+        for (index, annotation) in self.annotations.iter().enumerate() {
+            let bytes = &self.bytes[annotation.range.clone()];
+            let mut chunks = bytes.chunks(BYTES_PER_LINE);
+
+            if index != 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "{:04x}  {:hex_width$}  {}{}",
+                annotation.range.start,
+                HexBytes::new(chunks.next().unwrap_or_default()).to_string(),
+                "  ".repeat(annotation.depth),
+                annotation.note,
+            )?;
+
+            for (chunk_index, chunk) in chunks.enumerate() {
+                write!(
+                    f,
+                    "\n{:04x}  {}",
+                    annotation.range.start + (chunk_index + 1) * BYTES_PER_LINE,
+                    HexBytes::new(chunk),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn annotate_body(
+    byte_seq: &mut ByteSeq,
+    annotations: &mut Vec<Annotation>,
+) -> Result<(), ParseError> {
+    let annotate_zero = |byte_seq: &mut ByteSeq, annotations: &mut Vec<Annotation>, note: &str| {
+        let start = byte_seq.read_index();
+        byte_seq.assert_zero()?;
+        annotations.push(Annotation {
+            range: start..byte_seq.read_index(),
+            depth: 0,
+            note: note.to_string(),
+        });
+
+        Ok(())
+    };
+
+    annotate_zero(byte_seq, annotations, "body start")?;
+    annotate_fields(byte_seq, 0, annotations)?;
+    for _ in 0..3 {
+        annotate_zero(byte_seq, annotations, "padding")?;
+    }
+
+    Ok(())
+}
+
+fn annotate_fields(
+    byte_seq: &mut ByteSeq,
+    depth: usize,
+    annotations: &mut Vec<Annotation>,
+) -> Result<(), ParseError> {
+    //! Annotates fields up to and including the terminating zero byte.
+
+    if depth > MAX_NESTING_DEPTH {
+        return Err(ParseError::NestedTooDeeply(byte_seq.read_index()));
+    }
+
+    loop {
+        let start = byte_seq.read_index();
+        let Some((id, field_type)) = read_field_header(byte_seq)? else {
+            annotations.push(Annotation {
+                range: start..byte_seq.read_index(),
+                depth,
+                note: "end".to_string(),
+            });
+            break Ok(());
+        };
+
+        annotate_value(
+            byte_seq,
+            start,
+            &format!("field {id}"),
+            field_type,
+            depth,
+            annotations,
+        )?;
+    }
+}
+
+fn annotate_value(
+    byte_seq: &mut ByteSeq,
+    start: usize,
+    label: &str,
+    field_type: FieldType,
+    depth: usize,
+    annotations: &mut Vec<Annotation>,
+) -> Result<(), ParseError> {
+    //! `start` is the index of the field header, which is included in the annotated range. List elements don't have headers.
+
+    let mut annotate = |byte_seq: &ByteSeq, note: String| {
+        annotations.push(Annotation {
+            range: start..byte_seq.read_index(),
+            depth,
+            note: format!("{label}: {note}"),
+        })
+    };
+
+    match field_type {
+        FieldType::Struct => {
+            annotate(byte_seq, "struct".to_string());
+            annotate_fields(byte_seq, depth + 1, annotations)
+        }
+        FieldType::List => {
+            if depth >= MAX_NESTING_DEPTH {
+                return Err(ParseError::NestedTooDeeply(byte_seq.read_index()));
+            }
+
+            let element_type_index = byte_seq.read_index();
+            let element_type = FieldType::from_code(byte_seq.read_int()?)
+                .ok_or(ParseError::ExpectedConst(element_type_index))?;
+            let len = read_len(byte_seq)?;
+            annotate(byte_seq, format!("list<{}>[{len}]", element_type.name()));
+
+            for index in 0..len {
+                annotate_value(
+                    byte_seq,
+                    byte_seq.read_index(),
+                    &format!("[{index}]"),
+                    element_type,
+                    depth + 1,
+                    annotations,
+                )?;
+            }
+
+            Ok(())
+        }
+        _ => {
+            let value = read_field_value(byte_seq, field_type, depth)?;
+            annotate(
+                byte_seq,
+                format!("{} = {}", field_type.name(), describe_value(&value)),
+            );
+
+            Ok(())
+        }
+    }
+}
+
+fn describe_value(value: &FieldValue) -> String {
+    //! Only for values that aren't structs or lists.
+
+    let describe_number = |number: u64, text: String| {
+        let date = if (REDACTED_EPOCH_SECS as u64..MAX_EPOCH_SECS).contains(&number) {
+            utc_epoch_secs_to_local_iso_string(number as _)
+        } else if (REDACTED_FILETIME..MAX_FILETIME).contains(&number) {
+            utc_filetime_to_local_iso_string(number as _)
+        } else {
+            None
+        };
+
+        match date {
+            Some(date) => format!("{text} ({date}?)"),
+            None => text,
+        }
+    };
+
+    match value {
+        FieldValue::Bool(bool) => bool.to_string(),
+        FieldValue::UInt(uint) => describe_number(*uint, uint.to_string()),
+        FieldValue::Int(int) if *int >= 0 => describe_number(*int as _, int.to_string()),
+        FieldValue::Int(int) => int.to_string(),
+        FieldValue::Float(float) => float.to_string(),
+        FieldValue::Double(double) => double.to_string(),
+        FieldValue::String(string) => format!("{string:?}"),
+        FieldValue::Struct(_) | FieldValue::List(..) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnnotatedHex;
+    use crate::cloud_store::test_fixtures::{
+        NIGHT_LIGHT_STATE_BYTES, NIGHT_LIGHT_STATE_FIELDS_INDEX,
+    };
+
+    #[test]
+    fn annotations() {
+        //. Night Light state with the field 10 changed to a negative number and field 20 to a list.
+        let mut bytes = NIGHT_LIGHT_STATE_BYTES[..NIGHT_LIGHT_STATE_FIELDS_INDEX].to_vec();
+        bytes[18] = 0x10;
+        bytes.extend([
+            0x10, 0x00, 0xd0, 0x0a, 0x03, 0xcb, 0x14, 0x02, 0x02, 0x01, 0x00, 0x00, 0x00, 0x00,
+            0x00,
+        ]);
+        let annotated_hex = AnnotatedHex::new(&bytes);
+
+        let notes = annotated_hex
+            .annotations()
+            .iter()
+            .map(|annotation| {
+                (
+                    annotation.range.clone(),
+                    annotation.depth,
+                    &annotation.note[..],
+                )
+            })
+            .skip(1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            notes,
+            [
+                (22..23, 0, "body start"),
+                (23..25, 0, "field 0: int32 = 0"),
+                (25..28, 0, "field 10: int32 = -2"),
+                (28..32, 0, "field 20: list<bool>[2]"),
+                (32..33, 1, "[0]: bool = true"),
+                (33..34, 1, "[1]: bool = false"),
+                (34..35, 0, "end"),
+                (35..36, 0, "padding"),
+                (36..37, 0, "padding"),
+                (37..38, 0, "padding"),
+            ]
+        );
+        assert_eq!(annotated_hex.num_body_bytes(), Some(16));
+        assert!(annotated_hex.annotations()[0]
+            .note
+            .starts_with("prologue: "));
+        assert!(annotated_hex.to_string().contains("\n0019  d0 0a 03 "));
+
+        //. The rest of truncated bytes is unparsed.
+        let annotated_hex = AnnotatedHex::new(&bytes[..30]);
+        let last_annotation = annotated_hex.annotations().last().unwrap();
+        assert_eq!(last_annotation.range, 28..30);
+        assert_eq!(last_annotation.note, "unparsed");
+    }
+}
@@ -0,0 +1,272 @@
+//! Checks of the live registry values against the crate's dedicated parsers, as an early warning for format changes introduced by Windows updates.
+
+use serde::Serialize;
+use std::io;
+
+use super::{
+    focus_assist::{self, FocusAssist},
+    night_light::{RawNightLightSettings, RawNightLightState},
+    semantic_value::SemanticRegValue,
+};
+use crate::{
+    data_conversion::{byte_seq::ParseError, Strictness},
+    reg::{monitor::journal::full_key_path, read_reg_bin_value, RegValuePath, WrongValueType},
+};
+
+pub(crate) type Parser = fn(&[u8], Strictness) -> Result<(), ParseError>;
+
+/// Every registry value with a dedicated parser: the setting, the name of the value within the setting, its path and the parser.
+pub(crate) const PARSED_VALUES: [(&str, &str, RegValuePath<'static>, Parser); 3] = [
+    (
+        "nightLight",
+        "settings",
+        RawNightLightSettings::REG_VALUE_PATH,
+        parse::<RawNightLightSettings>,
+    ),
+    (
+        "nightLight",
+        "state",
+        RawNightLightState::REG_VALUE_PATH,
+        parse::<RawNightLightState>,
+    ),
+    (
+        "focusAssist",
+        "settings",
+        FocusAssist::REG_VALUE_PATH,
+        focus_assist::parse,
+    ),
+];
+
+fn parse<T: SemanticRegValue>(bytes: &[u8], strictness: Strictness) -> Result<(), ParseError> {
+    T::from_bytes(bytes, strictness).map(|_| ())
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ValueAudit {
+    /// Like `nightLight`.
+    pub setting: String,
+    /// Like `state`.
+    pub value: String,
+    pub key_path: String,
+    pub status: AuditStatus,
+    /// The error of strict parsing or reading.
+    pub error: Option<String>,
+    /// The index of the first byte deviating from the expected format, if known.
+    pub deviation_index: Option<usize>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditStatus {
+    /// Parses strictly.
+    Healthy,
+    /// Only parses leniently, i.e., with `--lenient`.
+    LenientOnly,
+    /// Doesn't parse at all.
+    Unparsable,
+    /// Doesn't exist, e.g., because the feature was never used.
+    Missing,
+    /// Has another registry value type than `REG_BINARY`, e.g., because it was corrupted.
+    WrongType,
+    /// Couldn't be read for other reasons.
+    Unreadable,
+}
+
+impl AuditStatus {
+    pub fn is_healthy(&self) -> bool {
+        //! Missing values count as healthy, because Windows creates them in the expected format.
+
+        matches!(self, Self::Healthy | Self::Missing)
+    }
+}
+
+pub fn audit() -> Vec<ValueAudit> {
+    //! Reads and parses every registry value with a dedicated parser.
+
+    PARSED_VALUES
+        .iter()
+        .map(|(setting, value, reg_value_path, parser)| {
+            let (status, error, deviation_index) = match read_reg_bin_value(reg_value_path) {
+                Ok(bytes) => audit_bytes(&bytes, *parser),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    (AuditStatus::Missing, None, None)
+                }
+                Err(error) if WrongValueType::of(&error).is_some() => {
+                    (AuditStatus::WrongType, Some(error.to_string()), None)
+                }
+                Err(error) => (AuditStatus::Unreadable, Some(error.to_string()), None),
+            };
+
+            ValueAudit {
+                setting: setting.to_string(),
+                value: value.to_string(),
+                key_path: full_key_path(&(*reg_value_path).into()),
+                status,
+                error,
+                deviation_index,
+            }
+        })
+        .collect()
+}
+
+pub fn healthy_settings(audits: &[ValueAudit]) -> Vec<(String, bool)> {
+    //! Summarizes the audits per setting, in the order of their first appearance. A setting is healthy, if all of its values are.
+
+    let mut settings = Vec::<(String, bool)>::new();
+    for audit in audits {
+        let healthy = audit.status.is_healthy();
+        match settings
+            .iter_mut()
+            .find(|(setting, _)| *setting == audit.setting)
+        {
+            Some((_, setting_healthy)) => *setting_healthy &= healthy,
+            None => settings.push((audit.setting.clone(), healthy)),
+        }
+    }
+
+    settings
+}
+
+fn audit_bytes(bytes: &[u8], parser: Parser) -> (AuditStatus, Option<String>, Option<usize>) {
+    match parser(bytes, Strictness::Strict) {
+        Ok(()) => (AuditStatus::Healthy, None, None),
+        Err(error) => {
+            let status = if parser(bytes, Strictness::Lenient).is_ok() {
+                AuditStatus::LenientOnly
+            } else {
+                AuditStatus::Unparsable
+            };
+
+            (status, Some(error.to_string()), error.byte_index())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{audit_bytes, healthy_settings, AuditStatus, ValueAudit, PARSED_VALUES};
+    use crate::cloud_store::test_fixtures::{NIGHT_LIGHT_SETTINGS_BYTES, NIGHT_LIGHT_STATE_BYTES};
+    use crate::{
+        cloud_store::{fields::CloudStoreValue, fingerprint::Structure},
+        data_conversion::Strictness,
+    };
+
+    /// Xorshift, for reproducible pseudo-random bytes without a dependency.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+    }
+
+    fn parse_all(bytes: &[u8]) {
+        //! Runs every parser on the bytes. Errors are expected; only panics fail the test.
+
+        for strictness in [Strictness::Strict, Strictness::Lenient] {
+            for (.., parser) in PARSED_VALUES {
+                let _ = parser(bytes, strictness);
+            }
+            let _ = CloudStoreValue::from_bytes(bytes, strictness);
+        }
+        let _ = audit_bytes(bytes, PARSED_VALUES[0].3);
+        let _ = Structure::from_bytes(bytes);
+    }
+
+    #[test]
+    fn parsers_dont_panic() {
+        let mut rng = Rng(0x5eed_1234_abcd_ef01);
+
+        for valid_bytes in [&NIGHT_LIGHT_SETTINGS_BYTES[..], &NIGHT_LIGHT_STATE_BYTES] {
+            //. Every truncation.
+            for len in 0..=valid_bytes.len() {
+                parse_all(&valid_bytes[..len]);
+            }
+
+            //. Random mutations of valid values get furthest into the parsers.
+            for _ in 0..5000 {
+                let mut bytes = valid_bytes.to_vec();
+                for _ in 0..1 + rng.below(4) {
+                    match rng.below(4) {
+                        0 => bytes.truncate(rng.below(bytes.len() + 1)),
+                        1 => bytes.insert(rng.below(bytes.len() + 1), rng.next() as u8),
+                        _ if bytes.is_empty() => {}
+                        kind => {
+                            //. Maximal bytes make VLQs and lengths large.
+                            let index = rng.below(bytes.len());
+                            bytes[index] = if kind == 2 { 0xff } else { rng.next() as u8 };
+                        }
+                    }
+                }
+                parse_all(&bytes);
+            }
+        }
+
+        //. Entirely random bytes, partly with a valid start.
+        for _ in 0..5000 {
+            let mut bytes = if rng.below(2) == 0 {
+                NIGHT_LIGHT_SETTINGS_BYTES[..rng.below(24)].to_vec()
+            } else {
+                Vec::new()
+            };
+            bytes.extend((0..rng.below(64)).map(|_| rng.next() as u8));
+            parse_all(&bytes);
+        }
+    }
+
+    #[test]
+    fn audit_state_bytes() {
+        let parser = PARSED_VALUES[1].3;
+        let bytes = NIGHT_LIGHT_STATE_BYTES.to_vec();
+
+        assert_eq!(
+            audit_bytes(&bytes, parser),
+            (AuditStatus::Healthy, None, None)
+        );
+
+        let mut lenient_bytes = bytes.clone();
+        lenient_bytes[42] = 0x01;
+        let (status, error, deviation_index) = audit_bytes(&lenient_bytes, parser);
+        assert_eq!(status, AuditStatus::LenientOnly);
+        assert!(error.is_some());
+        assert_eq!(deviation_index, Some(42));
+
+        let mut broken_bytes = bytes;
+        broken_bytes[28] = 0xff;
+        let (status, _, deviation_index) = audit_bytes(&broken_bytes, parser);
+        assert_eq!(status, AuditStatus::Unparsable);
+        assert_eq!(deviation_index, Some(28));
+    }
+
+    #[test]
+    fn summarize_settings() {
+        let audit = |setting: &str, status| ValueAudit {
+            setting: setting.to_string(),
+            value: String::new(),
+            key_path: String::new(),
+            status,
+            error: None,
+            deviation_index: None,
+        };
+
+        assert_eq!(
+            healthy_settings(&[
+                audit("nightLight", AuditStatus::Healthy),
+                audit("nightLight", AuditStatus::LenientOnly),
+                audit("other", AuditStatus::Missing),
+            ]),
+            [
+                ("nightLight".to_string(), false),
+                ("other".to_string(), true)
+            ]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Collection of raw CloudStore values into a single JSON document that users can attach to issues, to help reverse-engineering formats the crate doesn't support yet. Strings and timestamps can be redacted.
+
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+use winreg::{
+    enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, KEY_QUERY_VALUE, KEY_READ},
+    RegKey,
+};
+
+use super::{
+    audit::PARSED_VALUES,
+    fields::{CloudStoreValue, Field, FieldValue},
+    prologue::CloudStoreValuePrologue,
+};
+use crate::{
+    data_conversion::{
+        byte_seq::{ByteSeq, ParseError},
+        hex_bytes::hex_str_serde,
+        Strictness,
+    },
+    reg::{hkey_to_str, OwnedRegValuePath},
+};
+
+const STORE_SUBKEY_PATH: &str =
+    r"SOFTWARE\Microsoft\Windows\CurrentVersion\CloudStore\Store\DefaultAccount";
+
+/// 2000-01-01T00:00:00Z.
+pub(super) const REDACTED_EPOCH_SECS: u32 = 946_684_800;
+/// 2000-01-01T00:00:00Z.
+pub(super) const REDACTED_FILETIME: u64 = 125_911_584_000_000_000;
+/// 2100-01-01T00:00:00Z. Together with the redacted values the range of numbers considered timestamps.
+pub(super) const MAX_EPOCH_SECS: u64 = 4_102_444_800;
+pub(super) const MAX_FILETIME: u64 = 157_469_184_000_000_000;
+
+/// What to replace in values before sharing them.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Redaction {
+    /// Replaces every character of string fields with `x`.
+    pub strings: bool,
+    /// Replaces the prologue timestamp and integer fields in the range of Unix timestamps or FILETIMEs from 2000 to 2100 with 2000-01-01. Other numbers in these ranges are also affected.
+    pub timestamps: bool,
+}
+
+impl Redaction {
+    pub fn is_none(&self) -> bool {
+        !self.strings && !self.timestamps
+    }
+}
+
+/// The document written by [`Corpus::write()`].
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Corpus {
+    /// The version of the crate that collected the values.
+    pub crate_version: String,
+    /// Like `22631.2861`.
+    pub os_build: Option<String>,
+    pub values: Vec<CorpusEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CorpusEntry {
+    pub key_path: String,
+    pub value_name: String,
+    /// Whether the crate has a dedicated type for the value.
+    pub known: bool,
+    pub redacted: bool,
+    #[serde(with = "hex_str_serde")]
+    pub data: Vec<u8>,
+}
+
+impl Corpus {
+    pub fn new(values: Vec<CorpusEntry>) -> Self {
+        Self {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            os_build: os_build(),
+            values,
+        }
+    }
+
+    pub fn write<T: AsRef<Path>>(&self, file_path: T) -> Result<(), io::Error> {
+        fs::write(file_path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl CorpusEntry {
+    pub fn new(reg_value_path: &OwnedRegValuePath, data: Vec<u8>, redacted: bool) -> Self {
+        Self {
+            key_path: format!(
+                r"{}\{}",
+                hkey_to_str(reg_value_path.hkey),
+                reg_value_path.subkey_path
+            ),
+            value_name: reg_value_path.value_name.clone(),
+            known: is_known(reg_value_path),
+            redacted,
+            data,
+        }
+    }
+}
+
+pub fn cloud_store_value_paths() -> Result<Vec<OwnedRegValuePath>, io::Error> {
+    //! Finds the CloudStore values of the current user, i.e., the `Data` values two key levels below `Current` and `Cloud`.
+
+    let store_key =
+        RegKey::predef(HKEY_CURRENT_USER).open_subkey_with_flags(STORE_SUBKEY_PATH, KEY_READ)?;
+    let mut reg_value_paths = Vec::new();
+
+    for scope_name in ["Current", "Cloud"] {
+        let Ok(scope_key) = store_key.open_subkey_with_flags(scope_name, KEY_READ) else {
+            continue;
+        };
+
+        for collection_name in scope_key.enum_keys() {
+            let collection_name = collection_name?;
+            let collection_key = scope_key.open_subkey_with_flags(&collection_name, KEY_READ)?;
+
+            for item_name in collection_key.enum_keys() {
+                let item_name = item_name?;
+                let has_data_value = collection_key
+                    .open_subkey_with_flags(&item_name, KEY_QUERY_VALUE)
+                    .and_then(|item_key| item_key.get_raw_value("Data"))
+                    .is_ok();
+
+                if has_data_value {
+                    reg_value_paths.push(OwnedRegValuePath::new(
+                        HKEY_CURRENT_USER,
+                        format!(r"{STORE_SUBKEY_PATH}\{scope_name}\{collection_name}\{item_name}"),
+                        "Data",
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(reg_value_paths)
+}
+
+pub fn is_known(reg_value_path: &OwnedRegValuePath) -> bool {
+    //! Whether the crate has a dedicated type for the value.
+
+    PARSED_VALUES
+        .iter()
+        .any(|(.., known_path, _)| *known_path == reg_value_path.as_borrowed())
+}
+
+pub fn redact(bytes: &[u8], redaction: Redaction) -> Result<Vec<u8>, ParseError> {
+    //! Fails, if the value can't be parsed into fields, in which case it can't be redacted.
+
+    if redaction.is_none() {
+        return Ok(bytes.to_vec());
+    }
+
+    let prologue = CloudStoreValuePrologue::from_byte_seq(
+        &mut ByteSeq::from_slice(bytes),
+        Strictness::Strict,
+    )?;
+    let mut value = CloudStoreValue::from_bytes(bytes, Strictness::Strict)?;
+    let epoch_secs = if redaction.timestamps {
+        prologue.epoch_secs.map(|_| REDACTED_EPOCH_SECS)
+    } else {
+        prologue.epoch_secs
+    };
+
+    if prologue.num_body_bytes.is_none() {
+        return Ok(CloudStoreValuePrologue {
+            epoch_secs,
+            ..prologue
+        }
+        .to_byte_seq(None)
+        .into());
+    }
+
+    redact_fields(&mut value.fields, redaction);
+    Ok(value.to_bytes_with_epoch_secs(epoch_secs.ok_or(ParseError::InconsistentData)?))
+}
+
+fn redact_fields(fields: &mut [Field], redaction: Redaction) {
+    for field in fields {
+        redact_field_value(&mut field.value, redaction);
+    }
+}
+
+fn redact_field_value(value: &mut FieldValue, redaction: Redaction) {
+    match value {
+        FieldValue::String(string) if redaction.strings => {
+            *string = "x".repeat(string.chars().count());
+        }
+        FieldValue::UInt(uint) if redaction.timestamps => *uint = redact_timestamp(*uint),
+        FieldValue::Int(int) if redaction.timestamps && *int >= 0 => {
+            *int = redact_timestamp(*int as _) as _;
+        }
+        FieldValue::Struct(fields) => redact_fields(fields, redaction),
+        FieldValue::List(_, elements) => {
+            for element in elements {
+                redact_field_value(element, redaction);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn redact_timestamp(number: u64) -> u64 {
+    if (REDACTED_EPOCH_SECS as u64..MAX_EPOCH_SECS).contains(&number) {
+        REDACTED_EPOCH_SECS as _
+    } else if (REDACTED_FILETIME..MAX_FILETIME).contains(&number) {
+        REDACTED_FILETIME
+    } else {
+        number
+    }
+}
+
+pub(crate) fn os_build() -> Option<String> {
+    //! Like `22631.2861`.
+
+    let key = RegKey::predef(HKEY_LOCAL_MACHINE)
+        .open_subkey_with_flags(
+            r"SOFTWARE\Microsoft\Windows NT\CurrentVersion",
+            KEY_QUERY_VALUE,
+        )
+        .ok()?;
+    let build = key.get_value::<String, _>("CurrentBuild").ok()?;
+
+    Some(match key.get_value::<u32, _>("UBR") {
+        Ok(ubr) => format!("{build}.{ubr}"),
+        Err(_) => build,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redact, Redaction};
+    use crate::cloud_store::test_fixtures::NIGHT_LIGHT_STATE_BYTES;
+    use crate::{
+        cloud_store::fields::{CloudStoreValue, FieldValue},
+        data_conversion::Strictness,
+    };
+
+    #[test]
+    fn redact_night_light_state() {
+        let bytes = NIGHT_LIGHT_STATE_BYTES;
+
+        assert_eq!(redact(&bytes, Redaction::default()).unwrap(), bytes);
+        assert_eq!(
+            redact(
+                &bytes,
+                Redaction {
+                    strings: true,
+                    timestamps: false,
+                }
+            )
+            .unwrap(),
+            bytes
+        );
+
+        let redacted_bytes = redact(
+            &bytes,
+            Redaction {
+                strings: false,
+                timestamps: true,
+            },
+        )
+        .unwrap();
+        let value = CloudStoreValue::from_bytes(&redacted_bytes, Strictness::Strict).unwrap();
+        assert_eq!(value.prologue_epoch_secs, Some(946_684_800));
+        assert_eq!(value.fields[0].value, FieldValue::Int(0));
+        assert_eq!(value.fields[1].value, FieldValue::Int(1));
+        assert_eq!(
+            value.fields[2].value,
+            FieldValue::UInt(125_911_584_000_000_000)
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Finding out which CloudStore value backs a setting: take a snapshot of all values with [`take_snapshot()`], change the setting in the Settings app, take another snapshot and compare them with [`changes_between()`].
+
+use std::{collections::HashMap, io};
+
+use super::corpus::cloud_store_value_paths;
+use crate::reg::value_set::{RegValueSet, RegValueSetEntry};
+
+/// A value that was added, removed or changed between two snapshots.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ValueChange {
+    /// The full path of the registry value's key, starting with the root key name.
+    pub key_path: String,
+    pub value_name: String,
+    /// `None`, if the value was added.
+    pub old_bytes: Option<Vec<u8>>,
+    /// `None`, if the value was removed.
+    pub new_bytes: Option<Vec<u8>>,
+}
+
+pub fn take_snapshot() -> Result<RegValueSet, io::Error> {
+    //! Reads the current user's CloudStore values in one pass. Values that vanish or become unreadable while reading are left out.
+
+    Ok(RegValueSet::from_existing_reg_values(
+        &cloud_store_value_paths()?,
+    ))
+}
+
+pub fn changes_between(earlier: &RegValueSet, later: &RegValueSet) -> Vec<ValueChange> {
+    //! Changed and added values in the order of the later snapshot, followed by removed values in the order of the earlier one.
+
+    fn by_path(value_set: &RegValueSet) -> HashMap<(&str, &str), &RegValueSetEntry> {
+        value_set
+            .values
+            .iter()
+            .map(|entry| ((entry.key_path.as_str(), entry.value_name.as_str()), entry))
+            .collect()
+    }
+
+    fn path(entry: &RegValueSetEntry) -> (&str, &str) {
+        (&entry.key_path, &entry.value_name)
+    }
+
+    let earlier_entries = by_path(earlier);
+    let later_entries = by_path(later);
+
+    let changed = later
+        .values
+        .iter()
+        .filter(|entry| {
+            earlier_entries
+                .get(&path(entry))
+                .map_or(true, |old_entry| old_entry.data != entry.data)
+        })
+        .map(|entry| ValueChange {
+            key_path: entry.key_path.clone(),
+            value_name: entry.value_name.clone(),
+            old_bytes: earlier_entries
+                .get(&path(entry))
+                .map(|old_entry| old_entry.data.clone()),
+            new_bytes: Some(entry.data.clone()),
+        });
+    let removed = earlier
+        .values
+        .iter()
+        .filter(|entry| !later_entries.contains_key(&path(entry)))
+        .map(|entry| ValueChange {
+            key_path: entry.key_path.clone(),
+            value_name: entry.value_name.clone(),
+            old_bytes: Some(entry.data.clone()),
+            new_bytes: None,
+        });
+
+    changed.chain(removed).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{changes_between, ValueChange};
+    use crate::reg::value_set::{RegValueSet, RegValueSetEntry};
+
+    #[test]
+    fn changes() {
+        let entry = |name: &str, data: &[u8]| RegValueSetEntry {
+            key_path: format!(r"HKEY_CURRENT_USER\{name}"),
+            value_name: "Data".to_string(),
+            type_name: "REG_BINARY".to_string(),
+            data: data.to_vec(),
+        };
+        let change = |name: &str, old_bytes: Option<&[u8]>, new_bytes: Option<&[u8]>| ValueChange {
+            key_path: format!(r"HKEY_CURRENT_USER\{name}"),
+            value_name: "Data".to_string(),
+            old_bytes: old_bytes.map(<[u8]>::to_vec),
+            new_bytes: new_bytes.map(<[u8]>::to_vec),
+        };
+        let earlier = RegValueSet {
+            values: vec![entry("a", &[1, 2]), entry("b", &[3]), entry("c", &[4])],
+        };
+        let later = RegValueSet {
+            values: vec![entry("a", &[1, 2]), entry("c", &[5]), entry("d", &[6])],
+        };
+
+        assert_eq!(
+            changes_between(&earlier, &later),
+            [
+                change("c", Some(&[4]), Some(&[5])),
+                change("d", None, Some(&[6])),
+                change("b", Some(&[3]), None),
+            ]
+        );
+        assert!(changes_between(&later, &later).is_empty());
+    }
+}
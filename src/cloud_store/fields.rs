@@ -0,0 +1,669 @@
+//! Generic parsing and serialization of CloudStore value bodies as trees of tagged fields, without knowledge of what the fields mean. The body encoding matches Bond's Compact Binary protocol (version 1): every field starts with a header containing its ID and type, and structs end with a zero byte.
+//!
+//! This is used to work with values the crate doesn't have dedicated types for. See the [`schema`](super::schema) module for giving the fields names. Dedicated types iterate over the fields with [`ByteSeq::fields()`] and only interpret them.
+
+use serde::{Deserialize, Serialize};
+use std::str;
+
+use super::prologue::CloudStoreValuePrologue;
+use crate::data_conversion::{
+    byte_seq::{ByteSeq, ParseError},
+    time::{
+        epoch_duration_to_epoch_secs, next_epoch_secs, now_as_epoch_duration, ImplausibleTimeError,
+    },
+    ResultOrElseIf, Strictness,
+};
+
+/// The maximum nesting depth of structs and lists. Real values only nest a few levels deep; the limit keeps corrupt data from overflowing the stack.
+pub(super) const MAX_NESTING_DEPTH: usize = 32;
+
+/// A CloudStore value with its body parsed into fields.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CloudStoreValue {
+    pub prologue_epoch_secs: Option<u32>,
+    /// In the order of the bytes, which isn't always sorted by ID, and IDs may repeat.
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Field {
+    pub id: u16,
+    pub field_type: FieldType,
+    pub value: FieldValue,
+}
+
+/// The type of a field as encoded in its header.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Bool,
+    /// A single byte.
+    UInt8,
+    /// This and the other wider unsigned integers are VLQ-encoded.
+    UInt16,
+    UInt32,
+    UInt64,
+    /// 4 bytes, little endian.
+    Float,
+    /// 8 bytes, little endian.
+    Double,
+    /// UTF-8, prefixed with the VLQ-encoded number of bytes.
+    String,
+    Struct,
+    /// Prefixed with the element type and the VLQ-encoded number of elements.
+    List,
+    /// A single byte.
+    Int8,
+    /// This and the other wider signed integers are zigzag-VLQ-encoded.
+    Int16,
+    Int32,
+    Int64,
+    /// UTF-16, prefixed with the VLQ-encoded number of code units.
+    WString,
+}
+
+impl FieldType {
+    const ALL: [Self; 15] = [
+        Self::Bool,
+        Self::UInt8,
+        Self::UInt16,
+        Self::UInt32,
+        Self::UInt64,
+        Self::Float,
+        Self::Double,
+        Self::String,
+        Self::Struct,
+        Self::List,
+        Self::Int8,
+        Self::Int16,
+        Self::Int32,
+        Self::Int64,
+        Self::WString,
+    ];
+
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Bool => 2,
+            Self::UInt8 => 3,
+            Self::UInt16 => 4,
+            Self::UInt32 => 5,
+            Self::UInt64 => 6,
+            Self::Float => 7,
+            Self::Double => 8,
+            Self::String => 9,
+            Self::Struct => 10,
+            Self::List => 11,
+            Self::Int8 => 14,
+            Self::Int16 => 15,
+            Self::Int32 => 16,
+            Self::Int64 => 17,
+            Self::WString => 18,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        //! Like `uint8`, as used in schemas.
+
+        match self {
+            Self::Bool => "bool",
+            Self::UInt8 => "uint8",
+            Self::UInt16 => "uint16",
+            Self::UInt32 => "uint32",
+            Self::UInt64 => "uint64",
+            Self::Float => "float",
+            Self::Double => "double",
+            Self::String => "string",
+            Self::Struct => "struct",
+            Self::List => "list",
+            Self::Int8 => "int8",
+            Self::Int16 => "int16",
+            Self::Int32 => "int32",
+            Self::Int64 => "int64",
+            Self::WString => "wstring",
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        //! Sets (code 12) are treated like lists. Maps (code 13) aren't supported.
+
+        if code == 12 {
+            return Some(Self::List);
+        }
+
+        Self::ALL
+            .into_iter()
+            .find(|field_type| field_type.code() == code)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum FieldValue {
+    Bool(bool),
+    /// For all unsigned integer types.
+    UInt(u64),
+    /// For all signed integer types.
+    Int(i64),
+    Float(f32),
+    Double(f64),
+    /// For both string types.
+    String(String),
+    Struct(Vec<Field>),
+    List(FieldType, Vec<FieldValue>),
+}
+
+impl CloudStoreValue {
+    pub fn from_bytes(bytes: &[u8], strictness: Strictness) -> Result<Self, ParseError> {
+        let mut byte_seq = ByteSeq::from_slice(bytes);
+
+        let prologue = CloudStoreValuePrologue::from_byte_seq(&mut byte_seq, strictness)?;
+        if prologue.num_body_bytes.is_none() {
+            return Ok(Self {
+                prologue_epoch_secs: prologue.epoch_secs,
+                fields: Vec::new(),
+            });
+        }
+
+        byte_seq
+            .assert_zero()
+            .or_else_if(strictness.is_lenient(), |_| Ok(()))?;
+        let fields = read_fields(&mut byte_seq, 0)?;
+
+        (0..3)
+            .try_for_each(|_| byte_seq.assert_zero())
+            .and_then(|_| byte_seq.assert_exhausted())
+            .or_else_if(strictness.is_lenient(), |_| Ok(()))?;
+
+        Ok(Self {
+            prologue_epoch_secs: prologue.epoch_secs,
+            fields,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SerializeError> {
+        //! Always writes a body, even if the value was bodyless. The prologue timestamp is advanced like with the Night Light values. Fails if a field's value doesn't match its type.
+
+        if let Some(field) = self
+            .fields
+            .iter()
+            .find(|field| !field.value.matches_type(field.field_type))
+        {
+            return Err(SerializeError::FieldTypeMismatch(field.id));
+        }
+
+        let now_epoch_secs = epoch_duration_to_epoch_secs(now_as_epoch_duration());
+        Ok(self.to_bytes_with_epoch_secs(
+            next_epoch_secs(self.prologue_epoch_secs, now_epoch_secs)
+                .ok_or(ImplausibleTimeError)?,
+        ))
+    }
+
+    pub(crate) fn to_bytes_with_epoch_secs(&self, epoch_secs: u32) -> Vec<u8> {
+        //! # Panics
+        //! Panics if a field's value doesn't match its type, which can't happen with parsed values.
+
+        let mut bytes = Vec::new();
+        CloudStoreValuePrologue::write_value_into(&mut bytes, epoch_secs, 0, |body_byte_seq| {
+            body_byte_seq.push_zero();
+            push_fields(body_byte_seq, &self.fields);
+            for _ in 0..3 {
+                body_byte_seq.push_zero();
+            }
+        });
+
+        bytes
+    }
+}
+
+impl FieldValue {
+    pub fn matches_type(&self, field_type: FieldType) -> bool {
+        //! Whether the value can be serialized with the type. Struct fields and list elements are checked recursively.
+
+        match (field_type, self) {
+            (FieldType::Bool, FieldValue::Bool(_))
+            | (
+                FieldType::UInt8 | FieldType::UInt16 | FieldType::UInt32 | FieldType::UInt64,
+                FieldValue::UInt(_),
+            )
+            | (FieldType::Float, FieldValue::Float(_))
+            | (FieldType::Double, FieldValue::Double(_))
+            | (FieldType::String | FieldType::WString, FieldValue::String(_))
+            | (
+                FieldType::Int8 | FieldType::Int16 | FieldType::Int32 | FieldType::Int64,
+                FieldValue::Int(_),
+            ) => true,
+            (FieldType::Struct, FieldValue::Struct(fields)) => fields
+                .iter()
+                .all(|field| field.value.matches_type(field.field_type)),
+            (FieldType::List, FieldValue::List(element_type, elements)) => elements
+                .iter()
+                .all(|element| element.matches_type(*element_type)),
+            _ => false,
+        }
+    }
+}
+
+/// Iterator over the fields of a struct. See [`ByteSeq::fields()`].
+pub struct FieldIter<'s, 'a> {
+    byte_seq: &'s mut ByteSeq<'a>,
+    field_index: usize,
+    done: bool,
+}
+
+impl<'a> ByteSeq<'a> {
+    pub fn fields(&mut self) -> FieldIter<'_, 'a> {
+        //! Iterates over the fields starting at the read index, yielding each with its ID (tag), type and decoded value, like `(20, FieldType::Struct, FieldValue::Struct(...))`. The iteration ends before the zero byte terminating the struct, which is left unread, since the top-level struct of a body is followed by more zero bytes. It also ends at the end of the bytes and after an error.
+
+        let field_index = self.read_index();
+        FieldIter {
+            byte_seq: self,
+            field_index,
+            done: false,
+        }
+    }
+}
+
+impl FieldIter<'_, '_> {
+    pub fn field_index(&self) -> usize {
+        //! The byte index of the header of the field yielded last, for errors about it.
+
+        self.field_index
+    }
+
+    pub fn try_for_each_ordered<F>(
+        mut self,
+        strictness: Strictness,
+        mut f: F,
+    ) -> Result<(), ParseError>
+    where
+        F: FnMut(Field, usize) -> Result<(), ParseError>,
+    {
+        //! Calls the function with each field and its [`Self::field_index()`], stopping at the first error. With [`Strictness::Strict`], the IDs must be ascending, like Windows writes them.
+
+        let mut last_id = None;
+        while let Some(field) = self.next() {
+            let field = field?;
+            let field_index = self.field_index();
+            if strictness.is_strict() && last_id.is_some_and(|id| field.id <= id) {
+                return Err(ParseError::UnexpectedField(field_index));
+            }
+            last_id = Some(field.id);
+
+            f(field, field_index)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for FieldIter<'_, '_> {
+    type Item = Result<Field, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.byte_seq.exhausted() {
+            return None;
+        }
+
+        let checkpoint = self.byte_seq.checkpoint();
+        self.field_index = self.byte_seq.read_index();
+        let result = read_field_header(self.byte_seq).and_then(|header| {
+            Ok(match header {
+                Some((id, field_type)) => Some(Field {
+                    id,
+                    field_type,
+                    value: read_field_value(self.byte_seq, field_type, 0)?,
+                }),
+                None => {
+                    //. Leave the terminating zero byte unread.
+                    self.byte_seq.rollback(checkpoint);
+                    None
+                }
+            })
+        });
+
+        match result {
+            Ok(Some(field)) => Some(Ok(field)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+fn read_fields(byte_seq: &mut ByteSeq, depth: usize) -> Result<Vec<Field>, ParseError> {
+    //! Reads fields up to and including the terminating zero byte. `depth` is the number of enclosing structs and lists.
+
+    if depth > MAX_NESTING_DEPTH {
+        return Err(ParseError::NestedTooDeeply(byte_seq.read_index()));
+    }
+
+    let mut fields = Vec::new();
+
+    loop {
+        let Some((id, field_type)) = read_field_header(byte_seq)? else {
+            break Ok(fields);
+        };
+        let value = read_field_value(byte_seq, field_type, depth)?;
+
+        fields.push(Field {
+            id,
+            field_type,
+            value,
+        });
+    }
+}
+
+pub(super) fn read_field_header(
+    byte_seq: &mut ByteSeq,
+) -> Result<Option<(u16, FieldType)>, ParseError> {
+    //! Reads the ID and type of the next field. `None` for the zero byte terminating a struct.
+
+    let header_index = byte_seq.read_index();
+    let header: u8 = byte_seq.read_int()?;
+    if header == 0 {
+        return Ok(None);
+    }
+
+    let id = match header >> 5 {
+        6 => byte_seq.read_int::<u8>()? as u16,
+        7 => byte_seq.read_int()?,
+        id => id as u16,
+    };
+    let field_type =
+        FieldType::from_code(header & 0b1_1111).ok_or(ParseError::ExpectedConst(header_index))?;
+
+    Ok(Some((id, field_type)))
+}
+
+pub(super) fn read_field_value(
+    byte_seq: &mut ByteSeq,
+    field_type: FieldType,
+    depth: usize,
+) -> Result<FieldValue, ParseError> {
+    Ok(match field_type {
+        FieldType::Bool => FieldValue::Bool(byte_seq.read_int::<u8>()? != 0),
+        FieldType::UInt8 => FieldValue::UInt(byte_seq.read_int::<u8>()? as _),
+        FieldType::UInt16 | FieldType::UInt32 | FieldType::UInt64 => {
+            FieldValue::UInt(byte_seq.read_vlq_64()?)
+        }
+        FieldType::Float => FieldValue::Float(f32::from_bits(byte_seq.read_int()?)),
+        FieldType::Double => FieldValue::Double(f64::from_bits(byte_seq.read_int()?)),
+        FieldType::String => FieldValue::String(byte_seq.read_len_prefixed_string()?),
+        FieldType::Struct => FieldValue::Struct(read_fields(byte_seq, depth + 1)?),
+        FieldType::List => {
+            if depth >= MAX_NESTING_DEPTH {
+                return Err(ParseError::NestedTooDeeply(byte_seq.read_index()));
+            }
+
+            let element_type_index = byte_seq.read_index();
+            let element_type = FieldType::from_code(byte_seq.read_int()?)
+                .ok_or(ParseError::ExpectedConst(element_type_index))?;
+            let len = read_len(byte_seq)?;
+            FieldValue::List(
+                element_type,
+                (0..len)
+                    .map(|_| read_field_value(byte_seq, element_type, depth + 1))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        FieldType::Int8 => FieldValue::Int(byte_seq.read_int::<i8>()? as _),
+        FieldType::Int16 | FieldType::Int32 | FieldType::Int64 => {
+            FieldValue::Int(byte_seq.read_zigzag_vlq_64()?)
+        }
+        FieldType::WString => FieldValue::String(byte_seq.read_len_prefixed_wide_string()?),
+    })
+}
+
+pub(super) fn read_len(byte_seq: &mut ByteSeq) -> Result<usize, ParseError> {
+    let len: usize = byte_seq
+        .read_vlq_64()?
+        .try_into()
+        .map_err(|_| ParseError::ValueNotInRange)?;
+
+    // Protects against allocating huge amounts of memory for corrupt data.
+    if len > byte_seq.num_bytes_left() {
+        return Err(ParseError::InconsistentData);
+    }
+
+    Ok(len)
+}
+
+fn push_fields(byte_seq: &mut ByteSeq, fields: &[Field]) {
+    //! Pushes the fields followed by the terminating zero byte.
+
+    for field in fields {
+        let code = field.field_type.code();
+        match field.id {
+            id @ 0..=5 => byte_seq.push_int((id as u8) << 5 | code),
+            id @ 6..=0xff => {
+                byte_seq.push_int(0b110 << 5 | code);
+                byte_seq.push_int(id as u8);
+            }
+            id => {
+                byte_seq.push_int(0b111 << 5 | code);
+                byte_seq.push_int(id);
+            }
+        }
+
+        push_field_value(byte_seq, field.field_type, &field.value);
+    }
+
+    byte_seq.push_zero();
+}
+
+fn push_field_value(byte_seq: &mut ByteSeq, field_type: FieldType, value: &FieldValue) {
+    //! # Panics
+    //! Panics if the value doesn't match the type.
+
+    match (field_type, value) {
+        (FieldType::Bool, FieldValue::Bool(bool)) => byte_seq.push_int(*bool as u8),
+        (FieldType::UInt8, FieldValue::UInt(uint)) => byte_seq.push_int(*uint as u8),
+        (FieldType::UInt16 | FieldType::UInt32 | FieldType::UInt64, FieldValue::UInt(uint)) => {
+            byte_seq.push_vlq_64(*uint)
+        }
+        (FieldType::Float, FieldValue::Float(float)) => byte_seq.push_int(float.to_bits()),
+        (FieldType::Double, FieldValue::Double(double)) => byte_seq.push_int(double.to_bits()),
+        (FieldType::String, FieldValue::String(string)) => {
+            byte_seq.push_len_prefixed_string(string)
+        }
+        (FieldType::Struct, FieldValue::Struct(fields)) => push_fields(byte_seq, fields),
+        (FieldType::List, FieldValue::List(element_type, elements)) => {
+            byte_seq.push_int(element_type.code());
+            byte_seq.push_vlq_64(elements.len() as _);
+            for element in elements {
+                push_field_value(byte_seq, *element_type, element);
+            }
+        }
+        (FieldType::Int8, FieldValue::Int(int)) => byte_seq.push_int(*int as i8),
+        (FieldType::Int16 | FieldType::Int32 | FieldType::Int64, FieldValue::Int(int)) => {
+            byte_seq.push_zigzag_vlq_64(*int)
+        }
+        (FieldType::WString, FieldValue::String(string)) => {
+            byte_seq.push_len_prefixed_wide_string(string)
+        }
+        _ => panic!("field value doesn't match field type"),
+    }
+}
+
+#[derive(thiserror::Error, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SerializeError {
+    #[error("{0}")]
+    ImplausibleTime(#[from] ImplausibleTimeError),
+    /// The value of the top-level field with the ID, or of one of its subfields, doesn't match the type.
+    #[error("value of field {0} doesn't match its type")]
+    FieldTypeMismatch(u16),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CloudStoreValue, Field, FieldType, FieldValue, SerializeError};
+    use crate::cloud_store::test_fixtures::{
+        NIGHT_LIGHT_SETTINGS_BYTES, NIGHT_LIGHT_STATE_BYTES, NIGHT_LIGHT_STATE_FIELDS_INDEX,
+    };
+    use crate::data_conversion::{
+        byte_seq::{ByteSeq, ParseError},
+        Strictness,
+    };
+
+    #[test]
+    fn night_light_settings_fields() {
+        let bytes = NIGHT_LIGHT_SETTINGS_BYTES;
+
+        let value = CloudStoreValue::from_bytes(&bytes, Strictness::Strict).unwrap();
+        assert_eq!(value.fields.len(), 8);
+        assert_eq!(
+            value.fields[0],
+            Field {
+                id: 0,
+                field_type: FieldType::Bool,
+                value: FieldValue::Bool(true),
+            }
+        );
+        assert_eq!(
+            value.fields[2],
+            Field {
+                id: 20,
+                field_type: FieldType::Struct,
+                value: FieldValue::Struct(vec![
+                    Field {
+                        id: 0,
+                        field_type: FieldType::Int8,
+                        value: FieldValue::Int(8),
+                    },
+                    Field {
+                        id: 1,
+                        field_type: FieldType::Int8,
+                        value: FieldValue::Int(15),
+                    },
+                ]),
+            }
+        );
+        assert_eq!(value.fields[4].value, FieldValue::Int(2684));
+
+        // Everything but the timestamp is reproduced.
+        let new_bytes = value.to_bytes().unwrap();
+        assert_eq!(new_bytes.len(), bytes.len());
+        assert_eq!(new_bytes[15..], bytes[15..]);
+
+        //. A timestamp that can't be advanced isn't replaced by a different one.
+        let value = CloudStoreValue {
+            prologue_epoch_secs: Some(u32::MAX),
+            ..value
+        };
+        assert!(value.to_bytes().is_err());
+
+        //. A value that doesn't match its type is rejected instead of serialized.
+        let mut value = value;
+        value.prologue_epoch_secs = None;
+        value.fields[4].field_type = FieldType::String;
+        assert_eq!(
+            value.to_bytes(),
+            Err(SerializeError::FieldTypeMismatch(value.fields[4].id))
+        );
+    }
+
+    #[test]
+    fn iterate_fields() {
+        //. Fields of Night Light's state, followed by the trailing zero bytes.
+        let bytes = &NIGHT_LIGHT_STATE_BYTES[NIGHT_LIGHT_STATE_FIELDS_INDEX..];
+        let mut byte_seq = ByteSeq::from_slice(bytes);
+
+        let mut fields = byte_seq.fields();
+        assert_eq!(
+            fields.next(),
+            Some(Ok(Field {
+                id: 0,
+                field_type: FieldType::Int32,
+                value: FieldValue::Int(0),
+            }))
+        );
+        assert_eq!(
+            fields.next().unwrap().map(|field| (field.id, field.value)),
+            Ok((10, FieldValue::Int(1)))
+        );
+        assert_eq!(
+            fields.next().unwrap().map(|field| field.field_type),
+            Ok(FieldType::UInt64)
+        );
+        assert_eq!(fields.field_index(), 5);
+        assert_eq!(fields.next(), None);
+        assert_eq!(byte_seq.read_index(), 16);
+
+        //. Errors end the iteration.
+        let mut byte_seq = ByteSeq::from_slice(&[0x02, 0x01, 0xff, 0x02, 0x01]);
+        let mut fields = byte_seq.fields();
+        assert!(fields.next().unwrap().is_ok());
+        assert_eq!(fields.next(), Some(Err(ParseError::ExpectedConst(2))));
+        assert_eq!(fields.next(), None);
+    }
+
+    #[test]
+    fn adversarial_bytes() {
+        let body = |body: &[u8]| {
+            let mut byte_seq = ByteSeq::from_bytes(vec![
+                0x43, 0x42, 0x01, 0x00, 0x0a, 0x02, 0x01, 0x00, 0x2a, 0x06, 0xfe, 0xcf, 0xee, 0xa9,
+                0x06, 0x2a, 0x2b, 0x0e,
+            ]);
+            byte_seq.push_vlq_64(body.len() as u64);
+            byte_seq.push_const(&[0x43, 0x42, 0x01]);
+            byte_seq.push_const(body);
+            Vec::from(byte_seq)
+        };
+
+        //. Structs nested deeper than the stack could handle without a limit.
+        let mut nested = vec![0x00];
+        nested.extend([0x0a; 100_000]);
+        let error = CloudStoreValue::from_bytes(&body(&nested), Strictness::Lenient).unwrap_err();
+        assert!(matches!(error, ParseError::NestedTooDeeply(_)));
+
+        //. Lists of lists.
+        let mut nested = vec![0x00, 0x0b];
+        for _ in 0..100 {
+            nested.extend([0x0b, 0x01]);
+        }
+        let error = CloudStoreValue::from_bytes(&body(&nested), Strictness::Lenient).unwrap_err();
+        assert!(matches!(error, ParseError::NestedTooDeeply(_)));
+
+        //. Nesting within the limit still works.
+        let mut nested = vec![0x00];
+        nested.extend([0x0a; 10]);
+        nested.extend([0x00; 11 + 3]);
+        assert!(CloudStoreValue::from_bytes(&body(&nested), Strictness::Strict).is_ok());
+
+        //. A WString length whose byte count overflows.
+        let mut wstring = vec![0x00, 0x12];
+        wstring.extend([0xff; 9]);
+        wstring.push(0x01);
+        assert!(CloudStoreValue::from_bytes(&body(&wstring), Strictness::Lenient).is_err());
+    }
+
+    #[test]
+    fn other_field_types() {
+        let value = CloudStoreValue {
+            prologue_epoch_secs: Some(1700000000),
+            fields: vec![
+                Field {
+                    id: 300,
+                    field_type: FieldType::WString,
+                    value: FieldValue::String("Nachtmodus".to_string()),
+                },
+                Field {
+                    id: 301,
+                    field_type: FieldType::List,
+                    value: FieldValue::List(
+                        FieldType::Double,
+                        vec![FieldValue::Double(0.5), FieldValue::Double(-2.0)],
+                    ),
+                },
+            ],
+        };
+
+        let parsed_value =
+            CloudStoreValue::from_bytes(&value.to_bytes().unwrap(), Strictness::Strict).unwrap();
+        assert_eq!(parsed_value.fields, value.fields);
+    }
+}
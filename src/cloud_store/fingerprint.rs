@@ -0,0 +1,174 @@
+//! Structural fingerprints of the CloudStore values the crate has dedicated types for. A fingerprint consists of the value's length, its constant leading bytes and the IDs and types of its fields, but none of the field values. Users of Insider builds can share it to report a changed layout without exposing their data; two fingerprints with the same hash have the same layout.
+
+use serde::Serialize;
+use std::io;
+
+use super::{
+    audit::PARSED_VALUES,
+    fields::{CloudStoreValue, Field, FieldType, FieldValue},
+};
+use crate::{
+    data_conversion::{hex_bytes::to_hex_str, Strictness},
+    reg::read_reg_bin_value,
+};
+
+/// The bytes ending the constant part of the known prologue variants. The VLQ-encoded timestamp follows, if any.
+const PROLOGUE_CONST_ENDS: [&[u8]; 3] = [&[0x2a, 0x2a], &[0x2a, 0x06], &[0x26]];
+const MAX_PROLOGUE_CONST_LEN: usize = 12;
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Fingerprint {
+    /// Like `nightLight`.
+    pub setting: String,
+    /// Like `state`.
+    pub value: String,
+    /// `None` if the value doesn't exist.
+    pub structure: Option<Structure>,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Structure {
+    /// The number of bytes of the whole value.
+    pub len: usize,
+    /// The leading bytes up to the timestamp, as hex string. Like `43420100 0a020100 2a06`, but without spaces.
+    pub prologue: String,
+    /// The body fields, like `{0:int32,10:int32,20:uint64}`. Structs are nested in braces; lists are written like `list<uint8>[16]`. `None` if the value doesn't parse as tagged fields.
+    pub fields: Option<String>,
+    /// 16 hex digits identifying the other members, to quickly compare fingerprints. Stable across versions of the crate.
+    pub hash: String,
+}
+
+impl Structure {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let prologue_len = PROLOGUE_CONST_ENDS
+            .iter()
+            .filter_map(|const_end| {
+                bytes[..bytes.len().min(MAX_PROLOGUE_CONST_LEN)]
+                    .windows(const_end.len())
+                    .position(|window| window == *const_end)
+                    .map(|index| index + const_end.len())
+            })
+            .min()
+            .unwrap_or(bytes.len().min(MAX_PROLOGUE_CONST_LEN));
+        let prologue = to_hex_str(&bytes[..prologue_len]);
+
+        let fields = CloudStoreValue::from_bytes(bytes, Strictness::Lenient)
+            .ok()
+            .map(|value| fields_layout(&value.fields));
+
+        let hash = fnv1a_64(
+            format!(
+                "{}|{prologue}|{}",
+                bytes.len(),
+                fields.as_deref().unwrap_or("?")
+            )
+            .as_bytes(),
+        );
+
+        Self {
+            len: bytes.len(),
+            prologue,
+            fields,
+            hash: format!("{hash:016x}"),
+        }
+    }
+}
+
+pub fn fingerprints() -> Result<Vec<Fingerprint>, io::Error> {
+    //! Reads every registry value with a dedicated type and fingerprints it.
+
+    PARSED_VALUES
+        .iter()
+        .map(|(setting, value, reg_value_path, _)| {
+            let structure = match read_reg_bin_value(reg_value_path) {
+                Ok(bytes) => Some(Structure::from_bytes(&bytes)),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+                Err(error) => return Err(error),
+            };
+
+            Ok(Fingerprint {
+                setting: setting.to_string(),
+                value: value.to_string(),
+                structure,
+            })
+        })
+        .collect()
+}
+
+fn fields_layout(fields: &[Field]) -> String {
+    let fields = fields
+        .iter()
+        .map(|field| {
+            format!(
+                "{}:{}",
+                field.id,
+                value_layout(field.field_type, &field.value)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    format!("{{{}}}", fields.join(","))
+}
+
+fn value_layout(field_type: FieldType, value: &FieldValue) -> String {
+    match value {
+        FieldValue::Struct(fields) => fields_layout(fields),
+        FieldValue::List(element_type, elements) => {
+            let mut layout = format!("list<{}>[{}]", element_type.name(), elements.len());
+
+            //. Struct elements are described individually, since their fields may differ.
+            if *element_type == FieldType::Struct {
+                let element_layouts = elements
+                    .iter()
+                    .map(|element| value_layout(*element_type, element))
+                    .collect::<Vec<_>>();
+                layout.push_str(&element_layouts.concat());
+            }
+
+            layout
+        }
+        _ => field_type.name().to_string(),
+    }
+}
+
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    //! Unlike `DefaultHasher`, guaranteed to stay the same.
+
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Structure;
+    use crate::cloud_store::test_fixtures::NIGHT_LIGHT_STATE_BYTES;
+
+    #[test]
+    fn state_structure() {
+        let bytes = NIGHT_LIGHT_STATE_BYTES;
+
+        let structure = Structure::from_bytes(&bytes);
+        assert_eq!(structure.len, 43);
+        assert_eq!(structure.prologue, "434201000a0201002a06");
+        assert_eq!(
+            structure.fields.as_deref(),
+            Some("{0:int32,10:int32,20:uint64}")
+        );
+
+        //. Other timestamps and field values don't change the fingerprint.
+        let mut other_bytes = bytes;
+        other_bytes[12] = 0xd3;
+        other_bytes[27] = 0x04;
+        other_bytes[31] = 0xfe;
+        assert_eq!(Structure::from_bytes(&other_bytes), structure);
+
+        let mut changed_bytes = bytes;
+        changed_bytes[25] = 0xcf;
+        let changed_structure = Structure::from_bytes(&changed_bytes);
+        assert_ne!(changed_structure.fields, structure.fields);
+        assert_ne!(changed_structure.hash, structure.hash);
+    }
+}
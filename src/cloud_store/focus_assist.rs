@@ -0,0 +1,468 @@
+//! Types to retrieve and switch the profile of Focus Assist (called "Do not disturb" in Windows 11), which suppresses notifications.
+//!
+//! The active profile is stored as its ID string in a field of the `quiethourssettings` CloudStore value. The other fields, like those of automatic rules, are preserved when writing. Every profile additionally has a `quiethoursprofile` value with its settings, like the apps and contacts allowed to notify; these are only available as generic fields for now.
+//!
+//! Like with `NightLight`, you should read, mutate and write without delays in between. `FocusAssist` instances expire after a short duration to enforce this.
+
+use convert_case::{Case, Casing};
+use core::fmt;
+use futures::channel::oneshot;
+use serde_json::json;
+use std::{io, path::Path, time::Duration};
+use winreg::enums::HKEY_CURRENT_USER;
+
+use super::fields::{CloudStoreValue, Field, FieldType, FieldValue, SerializeError};
+use crate::{
+    data_conversion::{ParseError, Strictness, TrackedValue},
+    expiry::Expiry,
+    reg::{
+        export_reg_values,
+        monitor::{MonitorLoopError, RegValueMonitor},
+        read_reg_bin_value,
+        wal::write_reg_bin_value_logged,
+        RegValuePath,
+    },
+};
+
+/// The Focus Assist configuration.
+pub struct FocusAssist {
+    settings: CloudStoreValue,
+    profile: TrackedValue<FocusAssistProfile>,
+    expiry: Expiry,
+    /// Whether the instance is a lenient fallback for a non-existent registry value, meaning its key may have to be created when writing.
+    is_fallback: bool,
+}
+
+impl FocusAssist {
+    pub const REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: r"SOFTWARE\Microsoft\Windows\CurrentVersion\CloudStore\Store\DefaultAccount\Current\default$windows.data.notifications.quiethourssettings\windows.data.notifications.quiethourssettings",
+        value_name: "Data",
+    };
+
+    /// The ID of the field containing the ID string of the active profile.
+    const PROFILE_FIELD_ID: u16 = 20;
+
+    /// Default duration after which an instance expires. See [`Self::set_expiration_timeout()`].
+    pub const EXPIRATION_TIMEOUT: Duration = Duration::from_millis(1000);
+
+    pub fn from_reg() -> Result<Self, self::Error> {
+        //! Creates a strict instance using [`Self::from_reg_with_strictness()`].
+
+        Self::from_reg_with_strictness(Strictness::Strict)
+    }
+
+    pub fn from_reg_lenient() -> Result<Self, self::Error> {
+        Self::from_reg_with_strictness(Strictness::Lenient)
+    }
+
+    pub fn from_reg_with_strictness(strictness: Strictness) -> Result<Self, self::Error> {
+        //! Returns a fallback instance with Focus Assist off, if the registry value doesn't exist in lenient mode.
+
+        Ok(match read_reg_bin_value(&Self::REG_VALUE_PATH) {
+            Ok(bytes) => Self::from_bytes_with_strictness(&bytes, strictness)?,
+            Err(error) => {
+                if error.kind() == io::ErrorKind::NotFound && strictness.is_lenient() {
+                    Self::lenient_fallback()
+                } else {
+                    Err(error)?
+                }
+            }
+        })
+    }
+
+    pub fn from_bytes_with_strictness(
+        bytes: &[u8],
+        strictness: Strictness,
+    ) -> Result<Self, ParseError> {
+        //! A missing or unknown profile ID is only tolerated in lenient mode, in which case Focus Assist counts as off.
+
+        let settings = CloudStoreValue::from_bytes(bytes, strictness)
+            .map_err(|error| error.in_field("focusAssist.settings").with_excerpt(bytes))?;
+        let profile = match Self::profile_field(&settings) {
+            Some(Field {
+                value: FieldValue::String(profile_id),
+                ..
+            }) => FocusAssistProfile::from_id(profile_id).ok_or(ParseError::ValueNotInRange),
+            _ => Err(ParseError::InconsistentData),
+        }
+        .or_else(|error| {
+            if strictness.is_lenient() {
+                Ok(FocusAssistProfile::Off)
+            } else {
+                Err(error)
+            }
+        })?;
+
+        Ok(Self::new(settings, profile))
+    }
+
+    pub fn lenient_fallback() -> Self {
+        Self {
+            is_fallback: true,
+            ..Self::new(
+                CloudStoreValue {
+                    prologue_epoch_secs: None,
+                    fields: Vec::new(),
+                },
+                FocusAssistProfile::Off,
+            )
+        }
+    }
+
+    fn new(settings: CloudStoreValue, profile: FocusAssistProfile) -> Self {
+        Self {
+            settings,
+            profile: TrackedValue::new(profile),
+            expiry: Expiry::new(Self::EXPIRATION_TIMEOUT),
+            is_fallback: false,
+        }
+    }
+
+    fn profile_field(settings: &CloudStoreValue) -> Option<&Field> {
+        settings
+            .fields
+            .iter()
+            .find(|field| field.id == Self::PROFILE_FIELD_ID)
+    }
+
+    pub fn export_reg<T: AsRef<Path>>(file_path: T) -> Result<(), io::Error> {
+        export_reg_values(&[Self::REG_VALUE_PATH], file_path)
+    }
+
+    pub fn monitor_changes<F, T, E>(
+        stop_receiver: Option<oneshot::Receiver<T>>,
+        strictness: Strictness,
+        mut callback: F,
+    ) -> Result<T, MonitorLoopError<E>>
+    where
+        F: FnMut(FocusAssistProfile) -> Option<Result<T, E>>,
+        T: Default,
+        E: From<self::Error>,
+    {
+        //! Calls the callback with the new profile whenever it changes, be it by the user or by Windows, e.g., when an automatic rule like the one for playing games takes effect. Changes of the registry value that didn't change the profile are skipped. Read and parse errors stop the loop.
+
+        //. Created before reading, so that no change in between is missed.
+        let mut monitor = RegValueMonitor::new([((), &Self::REG_VALUE_PATH)])?;
+
+        let mut previous_profile = Self::from_reg_with_strictness(strictness)
+            .map_err(|error| MonitorLoopError::Other(error.into()))?
+            .profile();
+
+        monitor.r#loop(stop_receiver, |_| {
+            let profile = match Self::from_reg_with_strictness(strictness) {
+                Ok(focus_assist) => focus_assist.profile(),
+                Err(error) => return Some(Err(error.into())),
+            };
+
+            if profile == previous_profile {
+                return None;
+            }
+            previous_profile = profile;
+
+            callback(profile)
+        })
+    }
+
+    pub fn profile(&self) -> FocusAssistProfile {
+        *self.profile
+    }
+
+    pub fn set_profile(&mut self, profile: FocusAssistProfile) {
+        self.profile.set(profile);
+    }
+
+    pub fn set_expiration_timeout(&mut self, expiration_timeout: Duration) {
+        //! Changes the duration after loading after which the instance can't be written anymore, to avoid race conditions with other writers, like Windows' automatic rules.
+
+        self.expiry.set_timeout(expiration_timeout);
+    }
+
+    pub fn time_left(&self) -> Duration {
+        //! The duration until the instance expires. Zero, if it already did.
+
+        self.expiry.time_left()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        //! Whether writing would fail with [`Error::Expired`].
+
+        self.expiry.is_expired()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&json!({
+            "profile": format!("{:?}", self.profile()).to_case(Case::Camel),
+        }))
+        .expect("serializing to JSON shouldn't fail")
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SerializeError> {
+        //! The bytes of the registry value with the profile set. The prologue timestamp is advanced past the loaded one.
+
+        let mut settings = self.settings.clone();
+        let profile_field = Field {
+            id: Self::PROFILE_FIELD_ID,
+            field_type: FieldType::WString,
+            value: FieldValue::String(self.profile().id().to_string()),
+        };
+
+        //. Fields aren't necessarily sorted by ID, so a missing field is inserted before the first one with a greater ID.
+        match settings
+            .fields
+            .iter()
+            .position(|field| field.id == Self::PROFILE_FIELD_ID)
+        {
+            Some(index) => settings.fields[index] = profile_field,
+            None => {
+                let index = settings
+                    .fields
+                    .iter()
+                    .position(|field| field.id > Self::PROFILE_FIELD_ID)
+                    .unwrap_or(settings.fields.len());
+                settings.fields.insert(index, profile_field);
+            }
+        }
+
+        settings.to_bytes()
+    }
+
+    pub fn write_to_reg(self) -> Result<(), self::Error> {
+        //! Writes the registry value, if the profile was changed, which immediately applies it. A lenient fallback instance creates the value and its key.
+
+        if self.is_expired() {
+            return Err(Error::Expired);
+        }
+
+        if self.profile.changed() {
+            write_reg_bin_value_logged(&Self::REG_VALUE_PATH, &self.to_bytes()?, self.is_fallback)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for FocusAssist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Focus Assist: {}", self.profile())
+    }
+}
+
+/// What Focus Assist lets through.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FocusAssistProfile {
+    /// All notifications are shown.
+    Off,
+    /// Only notifications from the apps and contacts on the priority list, and alarms.
+    PriorityOnly,
+    /// Only alarms.
+    AlarmsOnly,
+}
+
+impl FocusAssistProfile {
+    pub const ALL: [Self; 3] = [Self::Off, Self::PriorityOnly, Self::AlarmsOnly];
+
+    const SETTINGS_REG_VALUE_PATHS: [RegValuePath<'static>; 3] = [
+        RegValuePath {
+            hkey: HKEY_CURRENT_USER,
+            subkey_path: r"SOFTWARE\Microsoft\Windows\CurrentVersion\CloudStore\Store\DefaultAccount\Current\microsoft.quiethoursprofile.unrestricted$windows.data.notifications.quiethoursprofile\windows.data.notifications.quiethoursprofile",
+            value_name: "Data",
+        },
+        RegValuePath {
+            hkey: HKEY_CURRENT_USER,
+            subkey_path: r"SOFTWARE\Microsoft\Windows\CurrentVersion\CloudStore\Store\DefaultAccount\Current\microsoft.quiethoursprofile.priorityonly$windows.data.notifications.quiethoursprofile\windows.data.notifications.quiethoursprofile",
+            value_name: "Data",
+        },
+        RegValuePath {
+            hkey: HKEY_CURRENT_USER,
+            subkey_path: r"SOFTWARE\Microsoft\Windows\CurrentVersion\CloudStore\Store\DefaultAccount\Current\microsoft.quiethoursprofile.alarmsonly$windows.data.notifications.quiethoursprofile\windows.data.notifications.quiethoursprofile",
+            value_name: "Data",
+        },
+    ];
+
+    pub fn id(&self) -> &'static str {
+        //! The ID string Windows stores for the profile.
+
+        match self {
+            Self::Off => "Microsoft.QuietHoursProfile.Unrestricted",
+            Self::PriorityOnly => "Microsoft.QuietHoursProfile.PriorityOnly",
+            Self::AlarmsOnly => "Microsoft.QuietHoursProfile.AlarmsOnly",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        //! Compares case-insensitively, since the registry key names contain the IDs in lowercase.
+
+        Self::ALL
+            .into_iter()
+            .find(|profile| profile.id().eq_ignore_ascii_case(id))
+    }
+
+    pub fn settings_reg_value_path(&self) -> RegValuePath<'static> {
+        //! The path of the `quiethoursprofile` value with the profile's settings.
+
+        Self::SETTINGS_REG_VALUE_PATHS[*self as usize]
+    }
+
+    pub fn settings_from_reg(
+        &self,
+        strictness: Strictness,
+    ) -> Result<CloudStoreValue, self::Error> {
+        //! Reads the profile's settings as generic fields, since their meaning isn't known yet.
+
+        Ok(CloudStoreValue::from_bytes(
+            &read_reg_bin_value(&self.settings_reg_value_path())?,
+            strictness,
+        )?)
+    }
+
+    pub fn write_settings_to_reg(&self, settings: &CloudStoreValue) -> Result<(), self::Error> {
+        //! Writes settings read with [`Self::settings_from_reg()`] and modified. The prologue timestamp is advanced.
+
+        write_reg_bin_value_logged(
+            &self.settings_reg_value_path(),
+            &settings.to_bytes()?,
+            false,
+        )?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for FocusAssistProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::PriorityOnly => "priority only",
+            Self::AlarmsOnly => "alarms only",
+        })
+    }
+}
+
+pub(crate) fn parse(bytes: &[u8], strictness: Strictness) -> Result<(), ParseError> {
+    //! For [`super::audit`].
+
+    FocusAssist::from_bytes_with_strictness(bytes, strictness).map(|_| ())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("parse error: {0}")]
+    ParseError(#[from] ParseError),
+    /// The object expired to enforce avoidance of race conditions.
+    #[error("object expired: duration between reading and writing was too long")]
+    Expired,
+    #[error("{0}")]
+    SerializeError(#[from] SerializeError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FocusAssist, FocusAssistProfile};
+    use crate::{
+        cloud_store::fields::{CloudStoreValue, Field, FieldType, FieldValue},
+        data_conversion::Strictness,
+    };
+
+    #[test]
+    fn profile_ids() {
+        for profile in FocusAssistProfile::ALL {
+            assert_eq!(FocusAssistProfile::from_id(profile.id()), Some(profile));
+            assert_eq!(
+                FocusAssistProfile::from_id(&profile.id().to_lowercase()),
+                Some(profile)
+            );
+        }
+        assert_eq!(
+            FocusAssistProfile::from_id("Microsoft.QuietHoursProfile.Unknown"),
+            None
+        );
+
+        assert!(FocusAssistProfile::PriorityOnly
+            .settings_reg_value_path()
+            .subkey_path
+            .contains("microsoft.quiethoursprofile.priorityonly$"));
+    }
+
+    #[test]
+    fn set_profile() {
+        //. The field before the profile field and the struct after it must be preserved.
+        let other_fields = vec![
+            Field {
+                id: 10,
+                field_type: FieldType::Bool,
+                value: FieldValue::Bool(true),
+            },
+            Field {
+                id: 40,
+                field_type: FieldType::Struct,
+                value: FieldValue::Struct(vec![Field {
+                    id: 20,
+                    field_type: FieldType::Int32,
+                    value: FieldValue::Int(1),
+                }]),
+            },
+        ];
+        let bytes = CloudStoreValue {
+            prologue_epoch_secs: Some(1_700_000_000),
+            fields: other_fields.clone(),
+        }
+        .to_bytes_with_epoch_secs(1_700_000_000);
+
+        //. Without a profile field.
+        assert!(FocusAssist::from_bytes_with_strictness(&bytes, Strictness::Strict).is_err());
+        let mut focus_assist =
+            FocusAssist::from_bytes_with_strictness(&bytes, Strictness::Lenient).unwrap();
+        assert_eq!(focus_assist.profile(), FocusAssistProfile::Off);
+        assert!(!focus_assist.profile.changed());
+
+        focus_assist.set_profile(FocusAssistProfile::AlarmsOnly);
+        assert!(focus_assist.profile.changed());
+        let bytes = focus_assist.to_bytes().unwrap();
+
+        let reparsed = FocusAssist::from_bytes_with_strictness(&bytes, Strictness::Strict).unwrap();
+        assert_eq!(reparsed.profile(), FocusAssistProfile::AlarmsOnly);
+        assert!(reparsed.settings.prologue_epoch_secs > Some(1_700_000_000));
+        assert_eq!(reparsed.settings.fields.len(), 3);
+        assert_eq!(reparsed.settings.fields[0], other_fields[0]);
+        assert_eq!(reparsed.settings.fields[2], other_fields[1]);
+
+        //. Replacing an existing profile field.
+        let mut focus_assist = reparsed;
+        focus_assist.set_profile(FocusAssistProfile::PriorityOnly);
+        let reparsed = FocusAssist::from_bytes_with_strictness(
+            &focus_assist.to_bytes().unwrap(),
+            Strictness::Strict,
+        )
+        .unwrap();
+        assert_eq!(reparsed.profile(), FocusAssistProfile::PriorityOnly);
+        assert_eq!(reparsed.settings.fields.len(), 3);
+
+        //. Replacing a profile field that comes after a field with a greater ID.
+        let bytes = CloudStoreValue {
+            prologue_epoch_secs: Some(1_700_000_000),
+            fields: vec![
+                other_fields[1].clone(),
+                Field {
+                    id: 20,
+                    field_type: FieldType::WString,
+                    value: FieldValue::String(FocusAssistProfile::AlarmsOnly.id().to_string()),
+                },
+            ],
+        }
+        .to_bytes_with_epoch_secs(1_700_000_000);
+        let mut focus_assist =
+            FocusAssist::from_bytes_with_strictness(&bytes, Strictness::Strict).unwrap();
+        focus_assist.set_profile(FocusAssistProfile::Off);
+        let reparsed = FocusAssist::from_bytes_with_strictness(
+            &focus_assist.to_bytes().unwrap(),
+            Strictness::Strict,
+        )
+        .unwrap();
+        assert_eq!(reparsed.profile(), FocusAssistProfile::Off);
+        assert_eq!(reparsed.settings.fields.len(), 2);
+        assert_eq!(reparsed.settings.fields[0], other_fields[1]);
+    }
+}
@@ -3,60 +3,97 @@
 //! In regular use cases, you should use the `NightLight` type. With the underlying raw types, which are also public, there can be unexpected behavior or inconsistent states that, when written to the registry, may not get resolved until restart of the OS or at least logging off:
 //!
 //! - Changing schedule-related settings - written to the settings registry value - may entail the state registry value - incl. the active-state - being changed by the OS. Trying to change both the settings and state registry value in close temporal proximity in an irreconcilable way may lead to one not being applied.
-//! - Night preview must not be switched or held active while changing anything that may potentially switch the active-state (incl. schedule-related settings).
+//! - Night preview must not be switched or held active while changing anything that may potentially switch the active-state (incl. schedule-related settings). [`NightPreviewGuard`] makes sure that it's deactivated again.
 //! - After logging on to the OS, changing the color temperature on its own remains ineffective if preview mode wasn't at least once activated in the session. (Briefly activating it also restores the color temperature after turning the screen back on.) If Night Light is off, changing the temperature and then, not in very close temporal proximity, turning Night Light on, correctly applies the temperature in that instance, however.
 //!
 //! With all types, there can be race conditions when simultaneously changing the Night Light settings elsewhere in the system. This is why you should read, mutate and write without delays in between. `NightLight` instances expire after a short duration to enforce this.
 //!
 //! The `NightLight` type encapsulates both the state and settings registry value and only writes one when you de facto changed its properties, compared with the data retrieved on instance creation, failing if changes don't harmonize with other properties (changed or unchanged). Using `NightLight` twice in direct succession won't help you writing both registry values in an irreconcilable way (the error may just be silent). If you need to do that, use a delay between writing a `NightLight` instance to registry and creating the next, causing the state registry value with the active-state to be changed last.
 
+mod all_users;
+mod cache;
+mod change;
+mod change_set;
+mod preset;
+mod preview;
+mod ramp;
 mod settings;
 mod state;
 mod time;
+mod undo;
 
-use chrono::SecondsFormat;
+pub use all_users::UserOutcome;
+pub use cache::NightLightCache;
+pub use change::NightLightChange;
+pub use change_set::{ApplyReport, ChangeSet};
+use chrono::{DateTime, Local, NaiveDate, SecondsFormat, TimeZone, Timelike, Utc};
 use convert_case::{Case, Casing};
 use core::fmt;
 use futures::channel::oneshot;
+pub use preset::PresetError;
+pub use preview::NightPreviewGuard;
+pub use ramp::WarmthRamp;
 use serde_json::json;
 pub use settings::{RawNightLightSettings, ScheduleType};
 pub use state::{RawNightLightState, TransitionCause};
 use std::{
-    io,
+    cell::OnceCell,
+    collections::{BTreeMap, HashMap},
+    fs, io,
     ops::Sub,
-    path::Path,
+    path::{Path, PathBuf},
     thread,
     time::{Duration, Instant, SystemTime},
 };
-pub use time::{ClockTime, ClockTimeFrame, Meridiem};
+pub use time::{ClockTime, ClockTimeFrame, Meridiem, ScheduledTransition, SubMinuteTimeError};
+pub use undo::{UndoError, MAX_UNDO_POINTS};
 use winreg::{
-    enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, KEY_QUERY_VALUE},
+    enums::{RegType, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, KEY_QUERY_VALUE},
     RegKey,
 };
 
+use super::{
+    fields::CloudStoreValue, prologue::CloudStoreValuePrologue, semantic_value::SemanticRegValue,
+};
 use crate::{
+    config::Config,
     data_conversion::{
         format::write_table,
+        hex_bytes::HexBytes,
         time::{
-            epoch_duration_to_filetime, utc_epoch_secs_to_local_iso_string,
+            epoch_duration_to_epoch_secs, epoch_duration_to_filetime, format_local_date_time,
+            next_epoch_secs, now_as_epoch_duration, utc_epoch_secs_to_local_iso_string,
             utc_filetime_to_local_date_time, utc_filetime_to_local_iso_string,
         },
         ParseError, Strictness,
     },
     reg::{
-        delete_reg_value, export_reg_bin_values,
-        monitor::{MonitorLoopError, RegValueMonitor},
-        read_reg_bin_value, write_reg_bin_value, RegValuePath,
+        export_reg_values,
+        monitor::{MonitorLoopError, RegValueEvent, RegValueMonitor, StopSignal},
+        provider::{RegProvider, UserHiveProvider, WinRegProvider},
+        read_reg_bin_values,
+        reg_file::{RegFile, RegFileError},
+        sid::UserScope,
+        wal::{write_reg_bin_value_logged, Transaction},
+        with_backup,
+        write_scheduler::RegWriteScheduler,
+        OwnedRegValuePath, RegValuePath,
     },
+    sun::{Coordinates, SunOffsets, SunTimes},
 };
 
+#[derive(Clone)]
 pub struct NightLight {
     state: RawNightLightState,
     settings: RawNightLightSettings,
-    sunset_to_sunrise_possible: Option<bool>,
+    /// Read from the registry on first access, since it's only needed for the effective schedule type.
+    sunset_to_sunrise_possible: OnceCell<Option<bool>>,
     uses_12_hour_clock: bool,
     loaded_instant: Instant,
+    expiration_timeout: Duration,
     strictness: Strictness,
+    /// Whether the instance is a lenient fallback for non-existent registry values, meaning both values must be written to create them.
+    is_fallback: bool,
 }
 
 impl NightLight {
@@ -76,10 +113,24 @@ impl NightLight {
         - (Self::DEFAULT_NIGHT_COLOR_TEMP - Self::MIN_NIGHT_COLOR_TEMP) as f32
             / (Self::MAX_NIGHT_COLOR_TEMP - Self::MIN_NIGHT_COLOR_TEMP) as f32;
 
+    /// Color temperatures by name, e.g., for users who don't know Kelvin values, from warmest to coldest. The names are lowercase. See [`Self::named_night_color_temp()`].
+    pub const NAMED_NIGHT_COLOR_TEMPS: [(&'static str, u16); 4] = [
+        ("candle", 1900),
+        ("warm", 2700),
+        ("neutral", Self::DEFAULT_NIGHT_COLOR_TEMP),
+        ("cold", Self::COLDEST_NIGHT_COLOR_TEMP),
+    ];
+
     /// The delay [`Self::init_with_strictness()`] should wait for, if no better information is available. (Defined as a common animation duration.)
     pub const REASONABLE_INIT_DELAY: Duration = Duration::from_millis(200);
 
-    /// Duration after which an instance expires. May be shortened in future versions.
+    /// The registry values that Night Light's configuration consists of. Can, e.g., be used with [`crate::reg::with_backup()`] around [`Self::write_to_reg()`].
+    pub const REG_VALUE_PATHS: [RegValuePath<'static>; 2] = [
+        RawNightLightState::REG_VALUE_PATH,
+        RawNightLightSettings::REG_VALUE_PATH,
+    ];
+
+    /// Default duration after which an instance expires. May be shortened in future versions. See [`Self::set_expiration_timeout()`].
     pub const EXPIRATION_TIMEOUT: Duration = Duration::from_millis(1000);
 
     pub fn from_reg() -> Result<Self, self::Error> {
@@ -95,7 +146,29 @@ impl NightLight {
     pub fn from_reg_with_strictness(strictness: Strictness) -> Result<Self, self::Error> {
         //! Returns a fallback instance if one of the registry values doesn't exist in lenient mode.
 
-        Ok(match NightLightBytes::from_reg() {
+        Self::from_bytes_result(NightLightBytes::from_reg(), strictness)
+    }
+
+    pub fn from_provider<P: RegProvider + ?Sized>(
+        provider: &P,
+        strictness: Strictness,
+    ) -> Result<Self, self::Error> {
+        //! Like [`Self::from_reg_with_strictness()`], but reads through the provider, e.g., an in-memory registry in tests.
+
+        Self::from_bytes_result(NightLightBytes::from_provider(provider), strictness)
+    }
+
+    pub fn from_reg_for_sid(user_sid: &str) -> Result<Self, self::Error> {
+        //! Like [`Self::from_reg()`], but reads the registry values from the hive of the specified user (under `HKEY_USERS\<SID>`), e.g., the interactive user's, when running as a service. Reading other users' hives requires elevation. For leniency, use [`Self::from_provider()`] with a [`UserHiveProvider`].
+
+        Self::from_provider(&UserHiveProvider::new(user_sid), Strictness::Strict)
+    }
+
+    fn from_bytes_result(
+        bytes_result: Result<NightLightBytes, io::Error>,
+        strictness: Strictness,
+    ) -> Result<Self, self::Error> {
+        Ok(match bytes_result {
             Ok(bytes) => Self::from_bytes_with_strictness(bytes, strictness)?,
             Err(error) => {
                 if error.kind() == io::ErrorKind::NotFound && strictness.is_lenient() {
@@ -120,12 +193,14 @@ impl NightLight {
         strictness: Strictness,
     ) -> Result<Self, ParseError> {
         Ok(Self {
-            state: RawNightLightState::from_bytes(bytes.state, strictness)?,
-            settings: RawNightLightSettings::from_bytes(bytes.settings, strictness)?,
-            sunset_to_sunrise_possible: Self::sunset_to_sunrise_possible(),
+            state: RawNightLightState::from_bytes(&bytes.state, strictness)?,
+            settings: RawNightLightSettings::from_bytes(&bytes.settings, strictness)?,
+            sunset_to_sunrise_possible: OnceCell::new(),
             uses_12_hour_clock: false,
             loaded_instant: Instant::now(),
+            expiration_timeout: Self::EXPIRATION_TIMEOUT,
             strictness,
+            is_fallback: false,
         })
     }
 
@@ -134,10 +209,12 @@ impl NightLight {
         Self {
             state: RawNightLightState::lenient_fallback(now),
             settings: RawNightLightSettings::lenient_fallback(now),
-            sunset_to_sunrise_possible: Self::sunset_to_sunrise_possible(),
+            sunset_to_sunrise_possible: OnceCell::new(),
             uses_12_hour_clock: false,
             loaded_instant: Instant::now(),
+            expiration_timeout: Self::EXPIRATION_TIMEOUT,
             strictness: Strictness::Lenient,
+            is_fallback: true,
         }
     }
 
@@ -157,58 +234,456 @@ impl NightLight {
         //! Concretely, it activates preview mode, waits for the delay duration, and deactivates it again. Doesn't write a registry value and wait, if preview mode was already active. To make the intermediate previewing as invisible as possible, the color temperature is temporarily set to the coldest possible, if Night Light is inactive.
         //!
         //! Normally to be used with [`Self::REASONABLE_INIT_DELAY`]. Additionally waiting after the last write can be activated in a command line context, if the user plans to follow the action up with another write.
+        //!
+        //! The delay is an upper bound: the registry values are watched, and waiting ends as soon as Windows changed one of them in reaction to the write. If they can't be watched, the whole delay is waited for.
 
-        let mut inst = Self::from_reg_with_strictness(strictness)?;
-        if !inst.night_preview_active() {
-            // Activate preview mode.
-            let previous_temp = if inst.active() {
-                None
-            } else {
-                let temp = inst.night_color_temp();
-                inst.set_night_color_temp(Some(Self::COLDEST_NIGHT_COLOR_TEMP)); // Make it invisible.
-                temp
-            };
-            inst.set_night_preview_active(true);
-            inst.write_to_reg()?;
+        //. Created before writing, so that no reaction is missed.
+        let mut monitor = RegValueMonitor::new([
+            (RegValueId::State, &RawNightLightState::REG_VALUE_PATH),
+            (RegValueId::Settings, &RawNightLightSettings::REG_VALUE_PATH),
+        ])
+        .map(RegValueMonitor::capture_bytes)
+        .ok();
 
-            thread::sleep(delay);
+        let guard = NightPreviewGuard::activate_invisibly(strictness, Duration::ZERO)?;
+        if !guard.was_active() {
+            Self::wait_for_reaction(monitor.as_mut(), delay);
 
-            // Deactivate preview mode.
-            let mut inst = Self::from_reg_with_strictness(strictness)?;
-            if let Some(temp) = previous_temp {
-                inst.set_night_color_temp(Some(temp));
-            };
-            inst.set_night_preview_active(false);
-            inst.write_to_reg()?;
+            //. Discard pending events, so that they aren't taken for a reaction to the next write.
+            if let Some(monitor) = &mut monitor {
+                while let Some(Ok(_)) = monitor.next_event_blocking(Some(Duration::ZERO)) {}
+            }
+            guard.release()?;
 
             if also_wait_after {
-                thread::sleep(delay);
+                Self::wait_for_reaction(monitor.as_mut(), delay);
             }
         }
 
         Ok(())
     }
 
+    pub fn apply(change_set: &ChangeSet) -> Result<ApplyReport, self::Error> {
+        //! Writes the changes in as many steps as needed for Windows to accept them (see the module documentation), waiting for Windows to react to a step and re-reading the registry values before the next one. The state is written last. Every wait is bounded by [`Self::REASONABLE_INIT_DELAY`].
+
+        Self::apply_with_strictness(change_set, Strictness::Strict)
+    }
+
+    pub fn apply_with_strictness(
+        change_set: &ChangeSet,
+        strictness: Strictness,
+    ) -> Result<ApplyReport, self::Error> {
+        //. Created before writing, so that no reaction is missed.
+        let mut monitor = RegValueMonitor::new([
+            (RegValueId::State, &RawNightLightState::REG_VALUE_PATH),
+            (RegValueId::Settings, &RawNightLightSettings::REG_VALUE_PATH),
+        ])
+        .map(RegValueMonitor::capture_bytes)
+        .ok();
+
+        let mut report = ApplyReport::default();
+        for batch in change_set.batches() {
+            let mut inst = Self::from_reg_with_strictness(strictness)?;
+            let change = batch.apply_to(&mut inst);
+            if change.is_empty() {
+                continue;
+            }
+
+            inst.write_to_reg()?;
+            if batch.changes_schedule() {
+                Self::wait_for_reaction(monitor.as_mut(), Self::REASONABLE_INIT_DELAY);
+                //. Discard pending events, so that they aren't taken for a reaction to the next write.
+                if let Some(monitor) = &mut monitor {
+                    while let Some(Ok(_)) = monitor.next_event_blocking(Some(Duration::ZERO)) {}
+                }
+            }
+            report.writes.push(change);
+        }
+
+        report.deviations = change_set.deviations_of(&Self::from_reg_with_strictness(strictness)?);
+        Ok(report)
+    }
+
+    pub fn apply_to_all_users(
+        change_set: &ChangeSet,
+        includes_offline: bool,
+        strictness: Strictness,
+    ) -> Result<Vec<UserOutcome>, io::Error> {
+        //! Applies the changes to every local user with a profile whose hive is loaded, i.e., who is logged on (also the current user). With `includes_offline`, the hives of the other users are loaded from their profiles for the time of the write. Requires an elevated process. Fails only if the users can't be enumerated; the outcome for each user is reported separately.
+        //!
+        //! Users who never used Night Light don't have the registry values; lenient mode writes the fallback configuration with the changes for them.
+
+        all_users::apply_to_all_users(change_set, includes_offline, strictness)
+    }
+
+    fn wait_for_reaction(monitor: Option<&mut RegValueMonitor<RegValueId>>, delay: Duration) {
+        //! Waits until an event of the monitor brings bytes other than those just written, but at most for the delay.
+
+        let Some(monitor) = monitor else {
+            thread::sleep(delay);
+            return;
+        };
+
+        //. Read right after writing, before Windows had the chance to react.
+        let written_bytes = NightLightBytes::from_reg().ok();
+
+        let deadline = Instant::now() + delay;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+
+            match monitor.next_event_blocking(Some(remaining)) {
+                Some(Ok(event)) => {
+                    let written_bytes = written_bytes
+                        .as_ref()
+                        .map(|bytes| bytes.bytes_of_value(event.id));
+                    // (Events of the own write bring the written bytes.)
+                    if event.bytes.as_deref() != written_bytes {
+                        break;
+                    }
+                }
+                Some(Err(_)) => {
+                    thread::sleep(remaining);
+                    break;
+                }
+                None => break,
+            }
+        }
+    }
+
+    pub fn fade_to_temp(
+        target_night_color_temp: Option<u16>,
+        duration: Duration,
+        num_steps: u32,
+    ) -> Result<(), self::Error> {
+        //! Uses [`Self::fade_to_temp_with_strictness()`] strictly and without stop signal.
+
+        Self::fade_to_temp_with_strictness(
+            target_night_color_temp,
+            duration,
+            num_steps,
+            Strictness::Strict,
+            None,
+        )
+    }
+
+    pub fn fade_to_temp_with_strictness(
+        target_night_color_temp: Option<u16>,
+        duration: Duration,
+        num_steps: u32,
+        strictness: Strictness,
+        stop_signal: Option<&StopSignal>,
+    ) -> Result<(), self::Error> {
+        //! Changes the color temperature linearly in `num_steps` equal steps spread over the duration, blocking the thread. Step `k` is written after `k / num_steps` of the duration, so that the target is reached when the duration ends. `None` fades to the default color temperature and sets it in the last step, like [`Self::set_night_color_temp()`]. Every step reads the registry values anew, so that instances don't expire and changes made in the meantime, e.g., by the schedule, are kept.
+        //!
+        //! Preview mode is held active during the fade, since changing the color temperature on its own may be ineffective (see the module documentation), and restored afterwards. If Night Light is inactive, the fade wouldn't be visible, and the target is written in a single step without preview mode.
+        //!
+        //! When the stop signal is triggered, also while waiting for the next step, the fade ends at the current color temperature. If restoring the preview state fails after the fade failed, both errors are returned as [`Error::RestoreFailed`].
+
+        let target_night_color_temp = target_night_color_temp.map(|target_night_color_temp| {
+            target_night_color_temp.clamp(Self::MIN_NIGHT_COLOR_TEMP, Self::MAX_NIGHT_COLOR_TEMP)
+        });
+        let (start_night_color_temp, orig_night_preview_active, num_steps, duration) = {
+            let inst = Self::from_reg_with_strictness(strictness)?;
+            let (num_steps, duration) = if inst.active() {
+                (num_steps.max(1), duration)
+            } else {
+                (1, Duration::ZERO)
+            };
+            (
+                inst.night_color_temp_in_range()
+                    .unwrap_or(Self::DEFAULT_NIGHT_COLOR_TEMP),
+                inst.night_preview_active(),
+                num_steps,
+                duration,
+            )
+        };
+        let uses_preview = num_steps > 1;
+
+        let mut write_scheduler = RegWriteScheduler::new(Duration::ZERO, Duration::ZERO);
+        let mut write_step = |step: u32| -> Result<(), self::Error> {
+            let temp = if step == num_steps {
+                target_night_color_temp
+            } else {
+                Some(interpolate_temp(
+                    start_night_color_temp,
+                    target_night_color_temp.unwrap_or(Self::DEFAULT_NIGHT_COLOR_TEMP),
+                    step,
+                    num_steps,
+                ))
+            };
+
+            let mut inst = Self::from_reg_with_strictness(strictness)?;
+            if uses_preview {
+                inst.set_night_preview_active(true);
+            }
+            inst.set_night_color_temp(temp);
+            inst.queue_writes(&mut write_scheduler)?;
+            write_scheduler.flush()?;
+
+            Ok(())
+        };
+
+        let fade_result = run_fade_steps(duration, num_steps, stop_signal, &mut write_step);
+
+        let mut restore_preview = || -> Result<(), self::Error> {
+            if uses_preview && !orig_night_preview_active {
+                let mut inst = Self::from_reg_with_strictness(strictness)?;
+                inst.set_night_preview_active(false);
+                inst.queue_writes(&mut write_scheduler)?;
+                write_scheduler.flush()?;
+            }
+
+            Ok(())
+        };
+
+        match (fade_result, restore_preview()) {
+            (Err(error), Err(restore_error)) => Err(Error::RestoreFailed {
+                error: Box::new(error),
+                restore_error: Box::new(restore_error),
+            }),
+            (fade_result, restore_result) => fade_result.and(restore_result),
+        }
+    }
+
     pub fn export_reg<T: AsRef<Path>>(file_path: T) -> Result<(), io::Error> {
         //! Writes the Night Light registry values to a file in .reg file format.
 
-        export_reg_bin_values(
-            &[
-                RawNightLightState::REG_VALUE_PATH,
-                RawNightLightSettings::REG_VALUE_PATH,
-            ],
-            file_path,
-        )
+        export_reg_values(&Self::REG_VALUE_PATHS, file_path)
+    }
+
+    pub fn export_json<T: AsRef<Path>>(
+        file_path: T,
+        strictness: Strictness,
+    ) -> Result<(), io::Error> {
+        //! Writes the JSON of [`Self::to_export_json()`] for the current registry values to a file.
+
+        let bytes = NightLightBytes::from_reg()?;
+        fs::write(file_path, Self::to_export_json(&bytes, strictness))
+    }
+
+    pub fn to_export_json(bytes: &NightLightBytes, strictness: Strictness) -> String {
+        //! Combines the JSON of [`Self::to_json()`] under `nightLight` with the hex bytes of both registry values, so that an export is both readable and exact. If the bytes can't be parsed, `nightLight` is `null` and `parseError` contains the error.
+
+        let (night_light, parse_error) =
+            match Self::from_bytes_with_strictness(bytes.clone(), strictness) {
+                Ok(night_light) => (night_light.to_json_value(), None),
+                Err(error) => (serde_json::Value::Null, Some(error.to_string())),
+            };
+
+        serde_json::to_string_pretty(&json!({
+            "nightLight": night_light,
+            "parseError": parse_error,
+            "stateBytes": HexBytes::new(&bytes.state).to_string(),
+            "settingsBytes": HexBytes::new(&bytes.settings).to_string(),
+        }))
+        .expect("serializing to JSON shouldn't fail")
+    }
+
+    pub fn save_preset(&self, name: &str) -> Result<(), PresetError> {
+        //! Saves the user-chosen part of the configuration under the name to `%APPDATA%\sem-reg\night-light-presets.json`, replacing a preset of the same name.
+
+        let file_path = preset_file_path()?;
+        let mut presets = preset::read_presets(&file_path)?;
+        presets.insert(name.to_string(), ChangeSet::from_night_light(self));
+        preset::write_presets(&file_path, &presets)
+    }
+
+    pub fn preset(name: &str) -> Result<ChangeSet, PresetError> {
+        //! The changes saved with [`Self::save_preset()`], to be written with [`Self::apply()`], which also writes combinations the setters don't allow. The active-state is only contained, if the preset's schedule isn't active.
+
+        Self::presets()?
+            .remove(name)
+            .ok_or_else(|| PresetError::NotFound(name.to_string()))
+    }
+
+    pub fn presets() -> Result<BTreeMap<String, ChangeSet>, PresetError> {
+        //! The saved presets by their names.
+
+        preset::read_presets(&preset_file_path()?)
+    }
+
+    pub fn delete_preset(name: &str) -> Result<(), PresetError> {
+        let file_path = preset_file_path()?;
+        let mut presets = preset::read_presets(&file_path)?;
+        if presets.remove(name).is_none() {
+            return Err(PresetError::NotFound(name.to_string()));
+        }
+
+        preset::write_presets(&file_path, &presets)
+    }
+
+    pub fn push_undo_point(point: ChangeSet) -> Result<(), UndoError> {
+        //! Appends the configuration, usually that from before a change, to the undo history in `%APPDATA%\sem-reg\night-light-undo.json`, which keeps the last [`MAX_UNDO_POINTS`] ones. A point equal to the newest one isn't appended again.
+
+        let file_path = undo_file_path()?;
+        let _lock = undo::lock_undo_history()?;
+        let mut undo_history = undo::read_undo_history(&file_path)?;
+        undo::push_undo_point(&mut undo_history.points, point);
+        undo_history.reverted = None;
+        undo::write_undo_history(&file_path, &undo_history)
+    }
+
+    pub fn push_observed_undo_point(point: ChangeSet, new: &ChangeSet) -> Result<(), UndoError> {
+        //! Like [`Self::push_undo_point()`], but for a change from `point` to `new` observed while monitoring, which may have been made by another process. The change made by [`Self::revert_last()`] isn't pushed, since undoing it would redo what was just undone.
+
+        let file_path = undo_file_path()?;
+        let _lock = undo::lock_undo_history()?;
+        let mut undo_history = undo::read_undo_history(&file_path)?;
+        undo::push_observed_undo_point(&mut undo_history, point, new);
+        undo::write_undo_history(&file_path, &undo_history)
+    }
+
+    pub fn undo_points() -> Result<Vec<ChangeSet>, UndoError> {
+        //! The configurations saved with [`Self::push_undo_point()`], oldest first.
+
+        Ok(undo::read_undo_history(&undo_file_path()?)?.points)
+    }
+
+    pub fn revert_last() -> Result<Option<ChangeSet>, UndoError> {
+        //! Applies the newest configuration of the undo history and removes it from the history. Returns `None`, if the history is empty. Since the configuration is written like any other change, Windows accepts it even though the bytes it came from are older.
+
+        Self::revert_last_with_strictness(Strictness::Strict)
+    }
+
+    pub fn revert_last_with_strictness(
+        strictness: Strictness,
+    ) -> Result<Option<ChangeSet>, UndoError> {
+        let file_path = undo_file_path()?;
+        //. Held while writing, so that a monitoring process sees the point as reverted when recording the change.
+        let _lock = undo::lock_undo_history()?;
+        let mut undo_history = undo::read_undo_history(&file_path)?;
+        let Some(point) = undo_history.points.pop() else {
+            return Ok(None);
+        };
+
+        Self::apply_with_strictness(&point, strictness)?;
+
+        //. Only after writing, so that the point isn't lost on failure.
+        undo_history.reverted = Some(point.clone());
+        undo::write_undo_history(&file_path, &undo_history)?;
+
+        Ok(Some(point))
+    }
+
+    pub fn import_reg<T: AsRef<Path>>(file_path: T) -> Result<(), ImportError> {
+        //! Restores the Night Light registry values from a .reg file, like one written by [`Self::export_reg()`]. Values missing from the file are left unchanged. The timestamps are advanced past those of the current values, since Windows would otherwise revert the values as outdated.
+
+        let reg_file = RegFile::from_file(file_path)?;
+        let mut write_scheduler = RegWriteScheduler::new(Duration::ZERO, Duration::ZERO);
+        Self::queue_imports(&reg_file, &WinRegProvider, &mut write_scheduler)?;
+        write_scheduler.flush()?;
+
+        Ok(())
+    }
+
+    pub fn import_reg_to_provider<P: RegProvider + ?Sized>(
+        reg_file: &RegFile,
+        provider: &P,
+    ) -> Result<(), ImportError> {
+        //! Like [`Self::import_reg()`], but with a parsed .reg file and writing through the provider, e.g., an in-memory registry in tests.
+
+        let mut write_scheduler = RegWriteScheduler::new(Duration::ZERO, Duration::ZERO);
+        Self::queue_imports(reg_file, provider, &mut write_scheduler)?;
+        write_scheduler.flush_to(provider)?;
+
+        Ok(())
+    }
+
+    fn queue_imports<P: RegProvider + ?Sized>(
+        reg_file: &RegFile,
+        provider: &P,
+        write_scheduler: &mut RegWriteScheduler,
+    ) -> Result<(), ImportError> {
+        //! Queues the writes of [`Self::import_reg()`], with timestamps advanced past those of the values the provider currently holds.
+
+        let now_epoch_secs = epoch_duration_to_epoch_secs(now_as_epoch_duration());
+
+        // Write settings first, then state.
+        for reg_value_path in [
+            RawNightLightSettings::REG_VALUE_PATH,
+            RawNightLightState::REG_VALUE_PATH,
+        ] {
+            //. Like with `regedit.exe`, the last occurrence wins.
+            let Some(reg_value) = reg_file
+                .keys
+                .iter()
+                .filter(|key| {
+                    !key.delete
+                        && key.hkey == reg_value_path.hkey
+                        && key
+                            .subkey_path
+                            .eq_ignore_ascii_case(reg_value_path.subkey_path)
+                })
+                .flat_map(|key| &key.values)
+                .rfind(|value| value.name.eq_ignore_ascii_case(reg_value_path.value_name))
+            else {
+                continue;
+            };
+
+            let bytes = match &reg_value.value {
+                Some(reg_value) if reg_value.vtype == RegType::REG_BINARY => &reg_value.bytes,
+                _ => return Err(ImportError::NotBinary),
+            };
+            if reg_value_path == RawNightLightState::REG_VALUE_PATH {
+                RawNightLightState::from_bytes(bytes, Strictness::Lenient)?;
+            } else {
+                RawNightLightSettings::from_bytes(bytes, Strictness::Lenient)?;
+            }
+
+            let current_epoch_secs = provider
+                .read_bin_value(&reg_value_path)
+                .ok()
+                .and_then(|current_bytes| CloudStoreValuePrologue::epoch_secs_of(&current_bytes));
+            let epoch_secs = next_epoch_secs(
+                current_epoch_secs.max(CloudStoreValuePrologue::epoch_secs_of(bytes)),
+                now_epoch_secs,
+            )
+            .ok_or(DataError::ImplausibleTime)?;
+
+            write_scheduler.queue(
+                reg_value_path,
+                CloudStoreValue::from_bytes(bytes, Strictness::Lenient)?
+                    .to_bytes_with_epoch_secs(epoch_secs),
+                true,
+            );
+        }
+
+        if write_scheduler.is_empty() {
+            return Err(ImportError::NoValues);
+        }
+
+        Ok(())
     }
 
     pub fn delete_reg() -> Result<(), io::Error> {
         //! Deletes the Night Light registry values to reset the Windows feature. May help when they've been corrupted and Night Light became unusable. User should restart or at least log-off after deletion.
 
+        let transaction = Transaction::begin([
+            (RawNightLightSettings::REG_VALUE_PATH, None),
+            (RawNightLightState::REG_VALUE_PATH, None),
+        ]);
+        let result = Self::delete_from_provider(&WinRegProvider);
+        transaction.commit();
+
+        result
+    }
+
+    pub fn delete_from_provider<P: RegProvider + ?Sized>(provider: &P) -> Result<(), io::Error> {
+        //! Like [`Self::delete_reg()`], but deletes through the provider, without the write-ahead log.
+
         // Deletion order may be relevant. This order made the fewest problems so far.
-        delete_reg_value(&RawNightLightSettings::REG_VALUE_PATH)?;
-        delete_reg_value(&RawNightLightState::REG_VALUE_PATH)?;
+        provider
+            .delete_value(&RawNightLightSettings::REG_VALUE_PATH)
+            .and_then(|()| provider.delete_value(&RawNightLightState::REG_VALUE_PATH))
+    }
 
-        Ok(())
+    pub fn delete_reg_with_backup<T: AsRef<Path>>(backup_file_path: T) -> Result<(), io::Error> {
+        //! Like [`Self::delete_reg()`], but first writes a .reg file with which the deletion can be undone.
+
+        with_backup(&Self::REG_VALUE_PATHS, backup_file_path, Self::delete_reg)
     }
 
     pub fn monitor<F, T, E>(
@@ -227,6 +702,197 @@ impl NightLight {
         monitor.r#loop(stop_receiver, |value_id| callback(value_id))
     }
 
+    pub fn monitor_events<F, T, E>(
+        stop_receiver: Option<oneshot::Receiver<T>>,
+        callback: F,
+    ) -> Result<T, MonitorLoopError<E>>
+    where
+        F: FnMut(RegValueEvent<RegValueId>) -> Option<Result<T, E>>,
+        T: Default,
+    {
+        //! Like [`Self::monitor()`], but passes the callback the whole event, with the changed registry value's bytes from before and after the change. See [`RegValueMonitor::capture_previous_bytes()`].
+
+        let mut monitor = RegValueMonitor::new([
+            (RegValueId::State, &RawNightLightState::REG_VALUE_PATH),
+            (RegValueId::Settings, &RawNightLightSettings::REG_VALUE_PATH),
+        ])?
+        .capture_previous_bytes();
+
+        monitor.loop_events(stop_receiver, callback)
+    }
+
+    pub fn monitor_blocking<F, T, E>(
+        stop_signal: Option<&StopSignal>,
+        callback: F,
+    ) -> Result<T, MonitorLoopError<E>>
+    where
+        F: FnMut(RegValueId) -> Option<Result<T, E>>,
+        T: Default,
+    {
+        //! Like [`Self::monitor()`], but stopped with a [`StopSignal`]. See [`RegValueMonitor::loop_blocking()`].
+
+        let mut monitor = RegValueMonitor::new([
+            (RegValueId::State, &RawNightLightState::REG_VALUE_PATH),
+            (RegValueId::Settings, &RawNightLightSettings::REG_VALUE_PATH),
+        ])?;
+
+        monitor.loop_blocking(stop_signal, callback)
+    }
+
+    pub fn monitor_changes<F, T, E>(
+        stop_receiver: Option<oneshot::Receiver<T>>,
+        strictness: Strictness,
+        mut callback: F,
+    ) -> Result<T, MonitorLoopError<E>>
+    where
+        F: FnMut(&NightLightChange, &NightLight) -> Option<Result<T, E>>,
+        T: Default,
+        E: From<self::Error>,
+    {
+        //! Like [`Self::monitor()`], but reads both registry values on every change and passes the callback which properties changed compared to the previous configuration, together with the new one. Changes of the registry values that didn't change any property, e.g., only their timestamps, are skipped. Read and parse errors stop the loop.
+        //!
+        //! The instance passed to the callback is meant for reading, like with [`NightLightCache::with()`].
+
+        //. Created before reading, so that no change in between is missed.
+        let mut monitor = RegValueMonitor::new([
+            (RegValueId::State, &RawNightLightState::REG_VALUE_PATH),
+            (RegValueId::Settings, &RawNightLightSettings::REG_VALUE_PATH),
+        ])?;
+
+        let mut previous = Self::from_reg_with_strictness(strictness)
+            .map_err(|error| MonitorLoopError::Other(error.into()))?;
+
+        monitor.r#loop(stop_receiver, |_| {
+            let current = match Self::from_reg_with_strictness(strictness) {
+                Ok(current) => current,
+                Err(error) => return Some(Err(error.into())),
+            };
+
+            let change = NightLightChange::between(&previous, &current);
+            previous = current;
+            if change.is_empty() {
+                return None;
+            }
+
+            callback(&change, &previous)
+        })
+    }
+
+    pub fn monitor_changes_with_user_scope<F, T, E>(
+        user_scope: UserScope,
+        stop_receiver: Option<oneshot::Receiver<T>>,
+        strictness: Strictness,
+        mut callback: F,
+    ) -> Result<T, MonitorLoopError<E>>
+    where
+        F: FnMut(&str, &NightLightChange, &NightLight) -> Option<Result<T, E>>,
+        T: Default,
+        E: From<self::Error>,
+    {
+        //! Like [`Self::monitor_changes()`], but watches the hives of the users in scope, e.g., each logged-on user's, when running as a service, and additionally passes the callback the SID of the user whose configuration changed. The registry values are read from that user's hive. Users whose hives don't contain the registry key aren't watched; if only the values are missing, changes are reported from the one after the values were created.
+
+        let mut previous_of_sids = HashMap::new();
+        let scoped_sids = user_scope
+            .resolve()
+            .map_err(|error| MonitorLoopError::Other(self::Error::from(error).into()))?;
+        let resolved_paths = OwnedRegValuePath::from(RawNightLightState::REG_VALUE_PATH)
+            .resolve_user_hives(&scoped_sids);
+
+        //. Created before reading, so that no change in between is missed.
+        let mut monitor = RegValueMonitor::with_scoped_sids(
+            [
+                (RegValueId::State, &RawNightLightState::REG_VALUE_PATH),
+                (RegValueId::Settings, &RawNightLightSettings::REG_VALUE_PATH),
+            ],
+            &scoped_sids,
+        )?;
+
+        for sid in resolved_paths.into_iter().filter_map(|(sid, _)| sid) {
+            match Self::from_provider(&UserHiveProvider::new(&sid), strictness) {
+                Ok(previous) => {
+                    previous_of_sids.insert(sid, previous);
+                }
+                //. The key exists, but the values don't yet. They're picked up on their first change.
+                Err(Error::IoError(error)) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => return Err(MonitorLoopError::Other(error.into())),
+            }
+        }
+
+        monitor.loop_events(stop_receiver, |event| {
+            let sid = event.user_sid?;
+            let current = match Self::from_provider(&UserHiveProvider::new(&sid), strictness) {
+                Ok(current) => current,
+                Err(error) => return Some(Err(error.into())),
+            };
+
+            //. A hive without the registry values when starting may have gotten them since.
+            let Some(previous) = previous_of_sids.get_mut(&sid) else {
+                previous_of_sids.insert(sid, current);
+                return None;
+            };
+            let change = NightLightChange::between(previous, &current);
+            *previous = current;
+            if change.is_empty() {
+                return None;
+            }
+
+            callback(&sid, &change, previous)
+        })
+    }
+
+    pub fn enforce<F, T, E>(
+        config: &ChangeSet,
+        debounce: Duration,
+        stop_receiver: Option<oneshot::Receiver<T>>,
+        strictness: Strictness,
+        mut callback: F,
+    ) -> Result<T, MonitorLoopError<E>>
+    where
+        F: FnMut(&NightLightChange) -> Option<Result<T, E>>,
+        T: Default,
+        E: From<self::Error>,
+    {
+        //! Keeps the configuration in effect against other programs changing it. Deviating properties are written back right away and then whenever the registry values changed, after waiting for the debounce duration, so that other programs can finish writing; changes during the wait are handled by the same write-back. The callback is passed the properties that were written back. Read, parse and write errors stop the loop.
+        //!
+        //! The active-state isn't enforced, if the configuration enforces the schedule to be active, since the enforcement would fight the schedule.
+
+        let config = ChangeSet {
+            active: config
+                .active
+                .filter(|_| config.schedule_active != Some(true)),
+            ..config.clone()
+        };
+        let enforce_once = || -> Result<NightLightChange, self::Error> {
+            let mut night_light = Self::from_reg_with_strictness(strictness)?;
+            let change = config.apply_to(&mut night_light);
+            if !change.is_empty() {
+                night_light.write_to_reg()?;
+            }
+
+            Ok(change)
+        };
+
+        //. Created before enforcing, so that no change in between is missed.
+        let mut monitor = RegValueMonitor::new([
+            (RegValueId::State, &RawNightLightState::REG_VALUE_PATH),
+            (RegValueId::Settings, &RawNightLightSettings::REG_VALUE_PATH),
+        ])?;
+
+        let change = enforce_once().map_err(|error| MonitorLoopError::Other(error.into()))?;
+        if !change.is_empty() {
+            if let Some(result) = callback(&change) {
+                return result.map_err(MonitorLoopError::Other);
+            }
+        }
+
+        //. The own writes also trigger changes, but then, nothing deviates.
+        monitor.loop_debounced(stop_receiver, debounce, |_| match enforce_once() {
+            Ok(change) if change.is_empty() => None,
+            Ok(change) => callback(&change),
+            Err(error) => Some(Err(error.into())),
+        })
+    }
+
     pub fn sunset_to_sunrise_possible() -> Option<bool> {
         //! Whether the "Sunset to sunrise" option is available, because location services are turned on. If not, the explicit schedule is the fallback. Returns `None` on registry access failure.
 
@@ -317,7 +983,7 @@ impl NightLight {
 
         match *self.settings.schedule_type {
             ScheduleType::SunsetToSunrise => {
-                if self.sunset_to_sunrise_possible? {
+                if self.cached_sunset_to_sunrise_possible()? {
                     Some(ScheduleType::SunsetToSunrise)
                 } else {
                     Some(ScheduleType::Explicit)
@@ -327,28 +993,91 @@ impl NightLight {
         }
     }
 
-    pub fn set_schedule_type(&mut self, schedule_type: ScheduleType) {
-        //! Because the explicit schedule is the fallback of "Sunset to sunrise", there won't be a change of the effective schedule type in the official Night Light settings when location services are off. The change is still perfomed however - just that it can only become effective when location services are turned on.
+    pub fn set_schedule_type(&mut self, schedule_type: ScheduleType) {
+        //! Because the explicit schedule is the fallback of "Sunset to sunrise", there won't be a change of the effective schedule type in the official Night Light settings when location services are off. The change is still perfomed however - just that it can only become effective when location services are turned on.
+
+        self.settings.schedule_type.set(schedule_type);
+    }
+
+    pub fn sunset_to_sunrise(&self) -> Option<ClockTimeFrame> {
+        //! Returns `None`, if all of the respective hour and minute values in the registry value were zero.
+
+        self.settings.sunset_to_sunrise
+    }
+
+    pub fn scheduled_night(&self) -> ClockTimeFrame {
+        //! The clock times defining the explicit schedule.
+
+        *self.settings.scheduled_night
+    }
+
+    pub fn set_scheduled_night(&mut self, scheduled_night: ClockTimeFrame) {
+        //! You can use *any* combination of clock times precise to the minute, and it will be adhered to. The time pickers in the official Night Light settings, however, will just display the times with 15-minute accuracy, rounded down. Two times the same clock time means zero-length night.
+
+        self.settings.scheduled_night.set(scheduled_night);
+    }
+
+    pub fn next_transition(&self) -> Option<ScheduledTransition<Local>> {
+        //! When the schedule will next turn Night Light on or off. `None`, if the schedule is inactive, the night has zero length, or the sunset-to-sunrise times aren't known. Windows may flip the state somewhat later, and with the sunset-to-sunrise schedule, the times shift from day to day.
+
+        self.next_transition_after(&Local::now())
+    }
+
+    pub fn next_transition_after<Tz: TimeZone>(
+        &self,
+        time: &DateTime<Tz>,
+    ) -> Option<ScheduledTransition<Tz>> {
+        //! Like [`Self::next_transition()`], but after the specified point in time, and with the clock times of the schedule interpreted in its time zone.
+
+        self.effective_scheduled_night()?
+            .next_transition_after(time)
+    }
+
+    pub fn is_night_now(&self) -> Option<bool> {
+        //! Whether the current local time is in the night of the schedule in effect, i.e., whether the schedule has Night Light on now. Unlike [`Self::active()`], this doesn't lag behind until Windows flips the state. `None`, if the schedule is inactive or the sunset-to-sunrise times aren't known.
+
+        self.is_night_at(ClockTime::now())
+    }
+
+    pub fn is_night_at(&self, clock_time: ClockTime) -> Option<bool> {
+        //! Like [`Self::is_night_now()`], but for the clock time. The night includes its start, but not its end.
 
-        self.settings.schedule_type.set(schedule_type);
+        Some(self.effective_scheduled_night()?.contains(clock_time))
     }
 
-    pub fn sunset_to_sunrise(&self) -> Option<ClockTimeFrame> {
-        //! Returns `None`, if all of the respective hour and minute values in the registry value were zero.
+    pub fn effective_scheduled_night(&self) -> Option<ClockTimeFrame> {
+        //! The night of the schedule in effect. `None`, if the schedule is inactive or its times aren't known.
 
-        self.settings.sunset_to_sunrise
+        if !self.schedule_active() {
+            return None;
+        }
+
+        match self.effective_schedule_type()? {
+            ScheduleType::Explicit => Some(self.scheduled_night()),
+            ScheduleType::SunsetToSunrise => self.sunset_to_sunrise(),
+        }
     }
 
-    pub fn scheduled_night(&self) -> ClockTimeFrame {
-        //! The clock times defining the explicit schedule.
+    pub fn set_scheduled_night_from_sun(
+        &mut self,
+        coordinates: Coordinates,
+        offsets: SunOffsets,
+    ) -> Option<ClockTimeFrame> {
+        //! Switches to the explicit schedule type with the scheduled night lasting from today's sunset to tomorrow's sunrise at the coordinates, shifted by the offsets. This emulates the "Sunset to sunrise" schedule type, e.g., when location services are off. Since the times change from day to day, it should be repeated daily.
+        //!
+        //! Returns the new scheduled night. During polar day or night, nothing is changed and `None` is returned.
 
-        *self.settings.scheduled_night
+        let scheduled_night = Self::night_from_sun(coordinates, offsets)?;
+        self.set_schedule_type(ScheduleType::Explicit);
+        self.set_scheduled_night(scheduled_night);
+
+        Some(scheduled_night)
     }
 
-    pub fn set_scheduled_night(&mut self, scheduled_night: ClockTimeFrame) {
-        //! You can use *any* combination of clock times precise to the minute, and it will be adhered to. The time pickers in the official Night Light settings, however, will just display the times with 15-minute accuracy, rounded down. Two times the same clock time means zero-length night.
+    pub fn night_from_sun(coordinates: Coordinates, offsets: SunOffsets) -> Option<ClockTimeFrame> {
+        //! The night from today's sunset to tomorrow's sunrise at the coordinates, shifted by the offsets, as local clock times. `None` during polar day or night.
 
-        self.settings.scheduled_night.set(scheduled_night);
+        scheduled_night_from_sun(coordinates, Local::now().date_naive(), offsets, &Local)
     }
 
     pub fn night_color_temp(&self) -> Option<u16> {
@@ -371,13 +1100,28 @@ impl NightLight {
         self.settings.night_color_temp.set(night_color_temp);
     }
 
+    pub fn named_night_color_temp(name: &str) -> Option<u16> {
+        //! Looks the name up in [`Self::NAMED_NIGHT_COLOR_TEMPS`], ignoring case.
+
+        Self::NAMED_NIGHT_COLOR_TEMPS
+            .iter()
+            .find(|(temp_name, _)| temp_name.eq_ignore_ascii_case(name))
+            .map(|(_, temp)| *temp)
+    }
+
     pub fn warmth(&self) -> Option<f32> {
         //! A factor in the range from 0 to 1, based on the color temperature range constants. May return `None` like the color temperature getter. Corresponds to the "Strength" slider in the official Night Light settings, which shows a percentage.
 
-        self.night_color_temp_in_range().map(|temp| {
-            1.0 - (temp - Self::MIN_NIGHT_COLOR_TEMP) as f32
-                / (Self::MAX_NIGHT_COLOR_TEMP - Self::MIN_NIGHT_COLOR_TEMP) as f32
-        })
+        self.night_color_temp_in_range()
+            .map(Self::warmth_from_night_color_temp)
+    }
+
+    pub fn warmth_from_night_color_temp(night_color_temp: u16) -> f32 {
+        //! The inverse of [`Self::night_color_temp_from_warmth()`]. Color temperatures outside of the valid range are clamped.
+
+        let temp = night_color_temp.clamp(Self::MIN_NIGHT_COLOR_TEMP, Self::MAX_NIGHT_COLOR_TEMP);
+        1.0 - (temp - Self::MIN_NIGHT_COLOR_TEMP) as f32
+            / (Self::MAX_NIGHT_COLOR_TEMP - Self::MIN_NIGHT_COLOR_TEMP) as f32
     }
 
     pub fn set_warmth(&mut self, warmth: Option<f32>) {
@@ -386,16 +1130,41 @@ impl NightLight {
         //! # Panics
         //! Panics on NaN.
 
-        self.set_night_color_temp(warmth.map(|warmth| {
-            if warmth.is_nan() {
-                panic!("value is NaN");
-            }
+        self.set_night_color_temp(warmth.map(Self::night_color_temp_from_warmth));
+    }
 
-            let precise_temp = (Self::MAX_NIGHT_COLOR_TEMP - Self::MIN_NIGHT_COLOR_TEMP) as f32
-                * (1.0 - warmth)
-                + Self::MIN_NIGHT_COLOR_TEMP as f32;
-            precise_temp.round().clamp(0f32, u16::MAX as f32) as u16
-        }));
+    pub fn adjust_warmth_by(&mut self, delta: f32) -> f32 {
+        //! Adds the delta, e.g., `0.1` or `-0.1`, to the warmth factor, clamping the result to the range from 0 to 1, and returns the new warmth factor. The default color temperature counts as [`Self::DEFAULT_WARMTH`].
+        //!
+        //! # Panics
+        //! Panics on NaN.
+
+        self.adjust_warmth_by_with_gamma(delta, 1.0)
+    }
+
+    pub fn adjust_warmth_by_with_gamma(&mut self, delta: f32, gamma: f32) -> f32 {
+        //! Like [`Self::adjust_warmth_by()`], but steps the warmth factor as corrected with the gamma (see [`Self::set_warmth()`]), so that equal steps are perceived as equally intense. The warmth factor before the correction, i.e., the current one raised to the power of the gamma, is changed and returned.
+
+        let warmth =
+            (self.warmth().unwrap_or(Self::DEFAULT_WARMTH).powf(gamma) + delta).clamp(0.0, 1.0);
+        self.set_warmth(Some(warmth.powf(1.0 / gamma)));
+        warmth
+    }
+
+    pub fn night_color_temp_from_warmth(warmth: f32) -> u16 {
+        //! The color temperature that [`Self::set_warmth()`] sets for the warmth factor.
+        //!
+        //! # Panics
+        //! Panics on NaN.
+
+        if warmth.is_nan() {
+            panic!("value is NaN");
+        }
+
+        let precise_temp = (Self::MAX_NIGHT_COLOR_TEMP - Self::MIN_NIGHT_COLOR_TEMP) as f32
+            * (1.0 - warmth)
+            + Self::MIN_NIGHT_COLOR_TEMP as f32;
+        precise_temp.round().clamp(0f32, u16::MAX as f32) as u16
     }
 
     pub fn night_preview_active(&self) -> bool {
@@ -408,6 +1177,37 @@ impl NightLight {
         self.settings.night_preview_active.set(night_preview_active);
     }
 
+    pub fn set_sunset_to_sunrise_possible(&mut self, sunset_to_sunrise_possible: Option<bool>) {
+        //! Provides the value of [`Self::sunset_to_sunrise_possible()`], e.g., when it was already determined for another instance, so that the registry isn't read again. Otherwise, it's read on first use.
+
+        self.sunset_to_sunrise_possible = OnceCell::from(sunset_to_sunrise_possible);
+    }
+
+    fn cached_sunset_to_sunrise_possible(&self) -> Option<bool> {
+        *self
+            .sunset_to_sunrise_possible
+            .get_or_init(Self::sunset_to_sunrise_possible)
+    }
+
+    pub fn set_expiration_timeout(&mut self, expiration_timeout: Duration) {
+        //! Changes the duration after loading after which the instance can't be written anymore, to avoid race conditions with other writers. Longer durations make it more likely that changes made in the meantime, e.g., by Windows' schedule, are overwritten.
+
+        self.expiration_timeout = expiration_timeout;
+    }
+
+    pub fn time_left(&self) -> Duration {
+        //! The duration until the instance expires. Zero, if it already did.
+
+        self.expiration_timeout
+            .saturating_sub(self.loaded_instant.elapsed())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        //! Whether writing would fail with [`DataError::Expired`].
+
+        self.loaded_instant.elapsed() > self.expiration_timeout
+    }
+
     pub fn set_uses_12_hour_clock(&mut self, uses_12_hour_clock: bool) {
         //! Only for display purposes.
 
@@ -415,7 +1215,12 @@ impl NightLight {
     }
 
     pub fn to_json(&self) -> String {
-        serde_json::to_string_pretty(&json!({
+        serde_json::to_string_pretty(&self.to_json_value())
+            .expect("serializing to JSON shouldn't fail")
+    }
+
+    fn to_json_value(&self) -> serde_json::Value {
+        json!({
             "active": *self.state.active,
             "transitionCause": format!("{:?}", self.state.transition_cause).to_case(Case::Camel),
             "stateModifiedTimestamp": utc_filetime_to_local_iso_string(self.state.modified_filetime).expect("`FILETIME` should be valid"),
@@ -423,41 +1228,217 @@ impl NightLight {
             "latestPossibleSettingsModifiedTimestamp": utc_epoch_secs_to_local_iso_string(self.settings.prologue_epoch_secs).expect("epoch secs should be valid"),
             "scheduleActive": *self.settings.schedule_active,
             "scheduleType": format!("{:?}", *self.settings.schedule_type).to_case(Case::Camel),
-            "sunsetToSunrisePossible": self.sunset_to_sunrise_possible,
+            "sunsetToSunrisePossible": self.cached_sunset_to_sunrise_possible(),
             "effectiveScheduleType": self.effective_schedule_type().map(|r#type| format!("{:?}", r#type).to_case(Case::Camel)),
             "sunsetToSunrise": self.settings.sunset_to_sunrise,
             "scheduledNight": *self.settings.scheduled_night,
             "nightColorTemp": *self.settings.night_color_temp,
             "warmth": self.warmth(),
             "nightPreviewActive": *self.settings.night_preview_active,
-        }))
-        .expect("serializing to JSON shouldn't fail")
+        })
     }
 
-    pub fn write_to_reg(mut self) -> Result<(), self::Error> {
+    pub fn write_to_reg(self) -> Result<(), self::Error> {
         //! Writes the data to the registry values, which immediately applies it.
 
-        if self.loaded_instant.elapsed() > Self::EXPIRATION_TIMEOUT {
-            return Err(DataError::Expired.into());
+        let mut write_scheduler = RegWriteScheduler::new(Duration::ZERO, Duration::ZERO);
+        self.queue_writes(&mut write_scheduler)?;
+        write_scheduler.flush()?;
+
+        Ok(())
+    }
+
+    pub fn plan_write(&self) -> Result<WritePlan, self::Error> {
+        //! Serializes the instance like [`Self::write_to_reg()`] would, but without writing, and reads the current registry values to compare with. Fails like writing would, e.g., for irreconcilable changes.
+
+        let mut write_scheduler = RegWriteScheduler::new(Duration::ZERO, Duration::ZERO);
+        self.clone().queue_writes(&mut write_scheduler)?;
+
+        let mut plan = WritePlan {
+            current: NightLightBytes::from_reg()?,
+            state: None,
+            settings: None,
+        };
+        for (reg_value_path, bytes) in write_scheduler.queued_writes() {
+            if reg_value_path == RawNightLightState::REG_VALUE_PATH {
+                plan.state = Some(bytes.to_vec());
+            } else {
+                plan.settings = Some(bytes.to_vec());
+            }
         }
 
-        let (state_changed, settings_changed) = self.verify_state_and_settings()?;
+        Ok(plan)
+    }
+
+    pub fn write_to_reg_verified(self, delay: Duration) -> Result<WriteOutcome, self::Error> {
+        //! Like [`Self::write_to_reg()`], but re-reads the written values after the delay and reports whether Windows kept them, so that callers can retry. Unlike [`Self::write_to_reg_guarded()`], doesn't need change notifications and doesn't write again by itself.
+        //!
+        //! Note that changing the schedule may make Windows change the state, which is reported as [`WriteOutcome::Superseded`], if the state was written, too.
+
+        let mut write_scheduler = RegWriteScheduler::new(Duration::ZERO, Duration::ZERO);
+        self.queue_writes(&mut write_scheduler)?;
+        let written = write_scheduler
+            .queued_writes()
+            .map(|(reg_value_path, bytes)| {
+                let reg_value_id = if reg_value_path == RawNightLightState::REG_VALUE_PATH {
+                    RegValueId::State
+                } else {
+                    RegValueId::Settings
+                };
+                (reg_value_id, bytes.to_vec())
+            })
+            .collect::<Vec<_>>();
+        write_scheduler.flush()?;
 
-        let state_bytes = state_changed.then(|| {
-            //. Only Windows is allowed to write the other value, because it does so by schedule.
-            self.state.transition_cause = TransitionCause::Manual;
+        thread::sleep(delay);
 
-            self.state.to_bytes()
-        });
-        let settings_bytes = settings_changed.then(|| self.settings.to_bytes());
+        Ok(WriteOutcome::of(&written, &NightLightBytes::from_reg()?))
+    }
+
+    pub fn write_to_provider<P: RegProvider + ?Sized>(
+        self,
+        provider: &P,
+    ) -> Result<(), self::Error> {
+        //! Like [`Self::write_to_reg()`], but writes through the provider, e.g., an in-memory registry in tests.
+
+        let mut write_scheduler = RegWriteScheduler::new(Duration::ZERO, Duration::ZERO);
+        self.queue_writes(&mut write_scheduler)?;
+        write_scheduler.flush_to(provider)?;
+
+        Ok(())
+    }
+
+    pub fn write_to_reg_for_sid(self, user_sid: &str) -> Result<(), self::Error> {
+        //! Like [`Self::write_to_reg()`], but writes to the hive of the specified user. See [`Self::from_reg_for_sid()`].
+
+        self.write_to_provider(&UserHiveProvider::new(user_sid))
+    }
+
+    pub fn write_to_reg_guarded(
+        self,
+        guard: RevertGuard,
+    ) -> Result<GuardedWriteOutcome, self::Error> {
+        //! Like [`Self::write_to_reg()`], but watches the written values for the grace period afterwards. Windows sometimes reverts values whose prologue timestamp it doesn't accept; a reverted value is written again with a timestamp further ahead, which restarts the grace period. Changes of values that weren't written, like of the state after changing the schedule, are expected and ignored.
+
+        //. Created before writing, so that no revert is missed.
+        let monitor = RegValueMonitor::new([
+            (RegValueId::State, &RawNightLightState::REG_VALUE_PATH),
+            (RegValueId::Settings, &RawNightLightSettings::REG_VALUE_PATH),
+        ])
+        .map(RegValueMonitor::capture_bytes);
+
+        let mut write_scheduler = RegWriteScheduler::new(Duration::ZERO, Duration::ZERO);
+        self.queue_writes(&mut write_scheduler)?;
+        let mut written = write_scheduler
+            .queued_writes()
+            .map(|(reg_value_path, bytes)| (reg_value_path, bytes.to_vec()))
+            .collect::<Vec<_>>();
+        write_scheduler.flush()?;
+
+        let Ok(mut monitor) = monitor else {
+            return Ok(GuardedWriteOutcome::Unverified);
+        };
+
+        let mut num_reapplies = 0;
+        'grace_periods: loop {
+            let deadline = Instant::now() + guard.grace_period;
+
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    break 'grace_periods Ok(GuardedWriteOutcome::Kept { num_reapplies });
+                }
+
+                let event = match monitor.next_event_blocking(Some(remaining)) {
+                    Some(Ok(event)) => event,
+                    Some(Err(_)) => return Ok(GuardedWriteOutcome::Unverified),
+                    None => continue,
+                };
+                let reg_value_path = match event.id {
+                    RegValueId::State => RawNightLightState::REG_VALUE_PATH,
+                    RegValueId::Settings => RawNightLightSettings::REG_VALUE_PATH,
+                };
+                let Some((_, written_bytes)) = written
+                    .iter_mut()
+                    .find(|(written_path, _)| *written_path == reg_value_path)
+                else {
+                    continue;
+                };
+
+                match ObservedChange::of(written_bytes, event.bytes.as_deref()) {
+                    ObservedChange::Own => {}
+                    ObservedChange::Newer => return Ok(GuardedWriteOutcome::Superseded),
+                    ObservedChange::Revert => {
+                        if num_reapplies == guard.max_reapplies {
+                            return Ok(GuardedWriteOutcome::Reverted);
+                        }
+                        num_reapplies += 1;
+
+                        let epoch_secs = next_epoch_secs(
+                            CloudStoreValuePrologue::epoch_secs_of(written_bytes),
+                            epoch_duration_to_epoch_secs(now_as_epoch_duration()),
+                        )
+                        .ok_or(DataError::ImplausibleTime)?;
+                        *written_bytes =
+                            CloudStoreValue::from_bytes(written_bytes, Strictness::Lenient)?
+                                .to_bytes_with_epoch_secs(epoch_secs);
+                        write_reg_bin_value_logged(&reg_value_path, written_bytes, false)?;
+
+                        continue 'grace_periods;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn queue_writes(
+        mut self,
+        write_scheduler: &mut RegWriteScheduler<'static>,
+    ) -> Result<(), self::Error> {
+        //! Like [`Self::write_to_reg()`], but only queues the writes, so they're performed on the next flush. This allows high-frequency callers to rate-limit their writes. The instance must not expire before queuing, but note that the bytes may be outdated when the flush happens late.
+
+        if self.is_expired() {
+            return Err(DataError::Expired.into());
+        }
+
+        let (mut state_changed, mut settings_changed) = self.verify_state_and_settings()?;
+        if self.is_fallback {
+            state_changed = true;
+            settings_changed = true;
+        }
+
+        let settings_bytes = settings_changed
+            .then(|| {
+                let mut bytes = write_scheduler.take_buffer();
+                self.settings.to_bytes_into(&mut bytes).map(|()| bytes)
+            })
+            .transpose()
+            .map_err(|_| DataError::ImplausibleTime)?;
+        let state_bytes = state_changed
+            .then(|| {
+                //. Only Windows is allowed to write the other value, because it does so by schedule.
+                self.state.transition_cause = TransitionCause::Manual;
+
+                let mut bytes = write_scheduler.take_buffer();
+                self.state.to_bytes_into(&mut bytes).map(|()| bytes)
+            })
+            .transpose()
+            .map_err(|_| DataError::ImplausibleTime)?;
+
+        // In lenient mode, the keys may not exist yet on machines that never used Night Light.
+        let creates_key = self.strictness.is_lenient();
 
         // Write settings first, then state.
         if let Some(settings_bytes) = settings_bytes {
-            write_reg_bin_value(&RawNightLightSettings::REG_VALUE_PATH, &settings_bytes)?;
+            write_scheduler.queue(
+                RawNightLightSettings::REG_VALUE_PATH,
+                settings_bytes,
+                creates_key,
+            );
             // (When state-changing settings were changed, Windows may now change the state registry value.)
         }
         if let Some(state_bytes) = state_bytes {
-            write_reg_bin_value(&RawNightLightState::REG_VALUE_PATH, &state_bytes)?;
+            write_scheduler.queue(RawNightLightState::REG_VALUE_PATH, state_bytes, creates_key);
         }
 
         Ok(())
@@ -581,13 +1562,17 @@ impl fmt::Display for NightLight {
                             self.settings.prologue_epoch_secs as _,
                         )),
                     ))
-                    .ok_or(fmt::Error)?
-                    .format(if self.uses_12_hour_clock {
-                        "%Y-%m-%d, %I:%M:%S %P"
-                    } else {
-                        "%Y-%m-%d, %H:%M:%S"
-                    })
-                    .to_string(),
+                    .ok_or(fmt::Error)
+                    .map(|date_time| {
+                        format_local_date_time(
+                            &date_time,
+                            if self.uses_12_hour_clock {
+                                "%Y-%m-%d, %I:%M:%S %P"
+                            } else {
+                                "%Y-%m-%d, %H:%M:%S"
+                            },
+                        )
+                    })?,
                 )),
             ],
         )?;
@@ -632,7 +1617,7 @@ impl fmt::Debug for NightLight {
                 )),
                 Some((
                     "sunset-to-sunrise possible (other)",
-                    format!("{:?}", self.sunset_to_sunrise_possible),
+                    format!("{:?}", self.cached_sunset_to_sunrise_possible()),
                 )),
                 Some((
                     "effective schedule type (settings & other)",
@@ -678,6 +1663,69 @@ impl fmt::Debug for NightLight {
     }
 }
 
+fn scheduled_night_from_sun<Tz: TimeZone>(
+    coordinates: Coordinates,
+    date: NaiveDate,
+    offsets: SunOffsets,
+    tz: &Tz,
+) -> Option<ClockTimeFrame> {
+    //! From the sunset on the date to the sunrise on the following date, as clock times in the time zone.
+
+    let to_clock_time = |date_time: DateTime<Utc>| {
+        let date_time = date_time.with_timezone(tz);
+        ClockTime::from_h_min(date_time.hour() as _, date_time.minute() as _)
+            .expect("hour and minute should be valid")
+    };
+
+    Some(ClockTimeFrame {
+        start: to_clock_time(SunTimes::on_date(coordinates, date)?.sunset + offsets.sunset),
+        end: to_clock_time(
+            SunTimes::on_date(coordinates, date.succ_opt()?)?.sunrise + offsets.sunrise,
+        ),
+    })
+}
+
+fn run_fade_steps<F, E>(
+    duration: Duration,
+    num_steps: u32,
+    stop_signal: Option<&StopSignal>,
+    mut write_step: F,
+) -> Result<(), E>
+where
+    F: FnMut(u32) -> Result<(), E>,
+{
+    //! Calls `write_step` with the steps from 1 to `num_steps`, step `k` when `k / num_steps` of the duration elapsed. Ends early when the stop signal is triggered, also while waiting.
+
+    let start_instant = Instant::now();
+
+    for step in 1..=num_steps {
+        let step_instant = start_instant + duration.mul_f64(step as f64 / num_steps as f64);
+        let remaining = step_instant.saturating_duration_since(Instant::now());
+
+        let stopped = match stop_signal {
+            Some(stop_signal) => stop_signal.wait_timeout(remaining),
+            None => {
+                thread::sleep(remaining);
+                false
+            }
+        };
+        if stopped {
+            break;
+        }
+
+        write_step(step)?;
+    }
+
+    Ok(())
+}
+
+fn interpolate_temp(start_temp: u16, target_temp: u16, step: u32, num_steps: u32) -> u16 {
+    //! The color temperature of a step of a linear fade, where step `num_steps` reaches the target.
+
+    let temp_delta = target_temp as i64 - start_temp as i64;
+    (start_temp as i64 + temp_delta * step as i64 / num_steps as i64) as u16
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     /// Error interacting with the registry, e.g., because of non-existent registry value. If the user never used Night Light in their OS installation, they should be advised to change something in the official settings to create the registry values and try again (turning Night Light on/off and moving the temperature slider should suffice).
@@ -689,6 +1737,44 @@ pub enum Error {
     /// Couldn't serialize the data from an instance into a byte stream.
     #[error("data error: {0}")]
     DataError(#[from] DataError),
+    /// An operation failed, and undoing its partial effects failed too, e.g., ending the preview mode after a failed fade.
+    #[error("{error}; restoring failed too: {restore_error}")]
+    RestoreFailed {
+        error: Box<Error>,
+        restore_error: Box<Error>,
+    },
+}
+
+fn preset_file_path() -> Result<PathBuf, PresetError> {
+    Ok(Config::night_light_preset_file_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "'APPDATA' not set"))?)
+}
+
+fn undo_file_path() -> Result<PathBuf, io::Error> {
+    Config::night_light_undo_file_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "'APPDATA' not set"))
+}
+
+/// Error of [`NightLight::import_reg()`].
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    /// Couldn't read or parse the .reg file.
+    #[error(".reg file error: {0}")]
+    RegFileError(#[from] RegFileError),
+    /// The .reg file contains neither of the Night Light registry values.
+    #[error(".reg file doesn't contain Night Light registry values")]
+    NoValues,
+    /// A Night Light registry value in the .reg file isn't binary or is to be deleted.
+    #[error("Night Light registry value in .reg file isn't binary")]
+    NotBinary,
+    /// Couldn't parse a Night Light registry value from the .reg file.
+    #[error("parse error: {0}")]
+    ParseError(#[from] ParseError),
+    #[error("data error: {0}")]
+    DataError(#[from] DataError),
+    /// Error writing the registry values.
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -702,6 +1788,9 @@ pub enum DataError {
     /// Night preview is currently active, because of other software or earlier use of this crate. The user could, e.g., right now be dragging the color tempature slider in the official Night Light settings.
     #[error("night preview was active while trying to change props irreconcilable with it")]
     NightPreviewInProgress,
+    /// A registry value's timestamp is so far in the future that it can't be advanced, e.g., because it was written with a wrong system clock. Deleting the registry values resets it.
+    #[error("registry value's timestamp is implausibly far in the future")]
+    ImplausibleTime,
 }
 
 #[derive(Clone, Debug)]
@@ -712,9 +1801,22 @@ pub struct NightLightBytes {
 
 impl NightLightBytes {
     pub fn from_reg() -> Result<Self, io::Error> {
+        let [state, settings]: [_; 2] = read_reg_bin_values(&NightLight::REG_VALUE_PATHS)
+            .try_into()
+            .expect("one result per path");
+
+        Ok(Self {
+            state: state?,
+            settings: settings?,
+        })
+    }
+
+    pub fn from_provider<P: RegProvider + ?Sized>(provider: &P) -> Result<Self, io::Error> {
+        //! Like [`Self::from_reg()`], but reads the values one after another through the provider.
+
         Ok(Self {
-            state: read_reg_bin_value(&RawNightLightState::REG_VALUE_PATH)?,
-            settings: read_reg_bin_value(&RawNightLightSettings::REG_VALUE_PATH)?,
+            state: provider.read_bin_value(&RawNightLightState::REG_VALUE_PATH)?,
+            settings: provider.read_bin_value(&RawNightLightSettings::REG_VALUE_PATH)?,
         })
     }
 
@@ -724,6 +1826,13 @@ impl NightLightBytes {
             RegValueId::Settings => &*self.settings,
         }
     }
+
+    pub fn set_bytes_of_value(&mut self, reg_value_id: RegValueId, bytes: Vec<u8>) {
+        match reg_value_id {
+            RegValueId::State => self.state = bytes,
+            RegValueId::Settings => self.settings = bytes,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -747,6 +1856,142 @@ impl fmt::Display for CompetingProps {
     }
 }
 
+/// The result of [`NightLight::plan_write()`]. Its `Display` implementation shows the bytes to be written as colored diffs against the current ones.
+#[derive(Clone, Debug)]
+pub struct WritePlan {
+    /// The registry values when planning.
+    pub current: NightLightBytes,
+    /// `None`, if the value wouldn't be written.
+    pub state: Option<Vec<u8>>,
+    /// `None`, if the value wouldn't be written.
+    pub settings: Option<Vec<u8>>,
+}
+
+impl WritePlan {
+    pub fn is_empty(&self) -> bool {
+        self.state.is_none() && self.settings.is_none()
+    }
+
+    pub fn bytes_of_value(&self, reg_value_id: RegValueId) -> Option<&[u8]> {
+        match reg_value_id {
+            RegValueId::State => self.state.as_deref(),
+            RegValueId::Settings => self.settings.as_deref(),
+        }
+    }
+}
+
+impl fmt::Display for WritePlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, reg_value_id) in [RegValueId::State, RegValueId::Settings]
+            .into_iter()
+            .enumerate()
+        {
+            if index > 0 {
+                writeln!(f)?;
+            }
+
+            match self.bytes_of_value(reg_value_id) {
+                Some(bytes) => write!(
+                    f,
+                    "{reg_value_id:?}: {}",
+                    HexBytes::new(bytes).diff_against(self.current.bytes_of_value(reg_value_id))
+                )?,
+                None => write!(f, "{reg_value_id:?}: unchanged")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// How [`NightLight::write_to_reg_guarded()`] watches for reverts.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RevertGuard {
+    /// How long to watch after every write.
+    pub grace_period: Duration,
+    /// How often a reverted value is written again at most.
+    pub max_reapplies: u32,
+}
+
+/// The result of [`NightLight::write_to_reg_guarded()`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GuardedWriteOutcome {
+    /// No revert happened in the last grace period.
+    Kept {
+        /// How often reverted values had to be written again.
+        num_reapplies: u32,
+    },
+    /// The values were still reverted after the maximum number of re-applications.
+    Reverted,
+    /// A written value was overwritten with a newer one, e.g., from the official settings, so watching was stopped.
+    Superseded,
+    /// The values couldn't be watched, so it's unknown whether the write was kept.
+    Unverified,
+}
+
+/// The result of [`NightLight::write_to_reg_verified()`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WriteOutcome {
+    /// The written values were still in the registry. Also the case, if nothing had to be written.
+    Accepted,
+    /// Windows replaced a written value with an older one, e.g., because it didn't accept the prologue timestamp. Writing again may succeed.
+    Reverted,
+    /// A written value was replaced with a newer one, e.g., from the official settings or by Windows reacting to a schedule change.
+    Superseded,
+}
+
+impl WriteOutcome {
+    fn of(written: &[(RegValueId, Vec<u8>)], current: &NightLightBytes) -> Self {
+        let observed_changes = written.iter().map(|(reg_value_id, written_bytes)| {
+            ObservedChange::of(written_bytes, Some(current.bytes_of_value(*reg_value_id)))
+        });
+
+        let mut outcome = Self::Accepted;
+        for observed_change in observed_changes {
+            match observed_change {
+                ObservedChange::Own => {}
+                //. Reverts are reported with priority, since they can be fixed by retrying.
+                ObservedChange::Revert => return Self::Reverted,
+                ObservedChange::Newer => outcome = Self::Superseded,
+            }
+        }
+
+        outcome
+    }
+}
+
+/// How a written value changed afterwards.
+#[derive(PartialEq, Debug)]
+enum ObservedChange {
+    /// The event of the write itself.
+    Own,
+    /// Replaced with a value having an older timestamp.
+    Revert,
+    /// Replaced with a value having the same, a newer or no timestamp, or deleted.
+    Newer,
+}
+
+impl ObservedChange {
+    fn of(written_bytes: &[u8], observed_bytes: Option<&[u8]>) -> Self {
+        let Some(observed_bytes) = observed_bytes else {
+            return Self::Newer;
+        };
+
+        let epoch_secs = CloudStoreValuePrologue::epoch_secs_of(observed_bytes)
+            .zip(CloudStoreValuePrologue::epoch_secs_of(written_bytes));
+
+        if observed_bytes == written_bytes {
+            Self::Own
+        } else if epoch_secs
+            .is_some_and(|(observed_secs, written_secs)| observed_secs < written_secs)
+        {
+            Self::Revert
+        } else {
+            Self::Newer
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum RegValueId {
     State,
@@ -755,7 +2000,34 @@ pub enum RegValueId {
 
 #[cfg(test)]
 mod tests {
-    use crate::cloud_store::night_light::NightLight;
+    use crate::cloud_store::test_fixtures::NIGHT_LIGHT_STATE_BYTES;
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use std::{
+        str::FromStr,
+        thread,
+        time::{Duration, Instant},
+    };
+    use winreg::{enums::RegType, RegValue};
+
+    use super::{
+        interpolate_temp, run_fade_steps, ImportError, NightLightBytes, ObservedChange,
+        RawNightLightSettings, RawNightLightState, RegValueId, WriteOutcome, WritePlan,
+    };
+    use crate::{
+        cloud_store::{
+            fields::CloudStoreValue,
+            night_light::{ClockTime, NightLight, ScheduleType},
+            prologue::CloudStoreValuePrologue,
+            semantic_value::SemanticRegValue,
+        },
+        data_conversion::{hex_bytes::HexBytes, Strictness},
+        reg::{
+            monitor::StopSignal,
+            provider::MemRegProvider,
+            reg_file::{RegFile, RegFileKey, RegFileValue},
+        },
+        sun::{Coordinates, SunOffsets},
+    };
 
     #[ignore]
     #[test]
@@ -806,11 +2078,341 @@ mod tests {
         }
     }
 
+    #[test]
+    fn provider_round_trip() -> Result<(), super::Error> {
+        let provider = MemRegProvider::new();
+        assert!(NightLight::from_provider(&provider, Strictness::Strict).is_err());
+
+        let mut night_light = NightLight::from_provider(&provider, Strictness::Lenient)?;
+        night_light.set_active(true);
+        night_light.set_night_color_temp(Some(2700));
+        night_light.write_to_provider(&provider)?;
+
+        let mut night_light = NightLight::from_provider(&provider, Strictness::Strict)?;
+        assert!(night_light.active());
+        assert_eq!(night_light.night_color_temp(), Some(2700));
+
+        night_light.set_active(false);
+        night_light.write_to_provider(&provider)?;
+        let night_light = NightLight::from_provider(&provider, Strictness::Strict)?;
+        assert!(!night_light.active());
+        assert_eq!(night_light.night_color_temp(), Some(2700));
+
+        NightLight::delete_from_provider(&provider)?;
+        assert_eq!(provider.value(&RawNightLightState::REG_VALUE_PATH), None);
+        assert_eq!(provider.value(&RawNightLightSettings::REG_VALUE_PATH), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn import_reg_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let provider = MemRegProvider::new();
+        let mut night_light = NightLight::from_provider(&provider, Strictness::Lenient)?;
+        night_light.set_schedule_active(false);
+        night_light.set_night_color_temp(Some(2700));
+        night_light.write_to_provider(&provider)?;
+
+        //. Like `export_reg()`, but from the in-memory registry.
+        let exported_bytes = NightLightBytes::from_provider(&provider)?;
+        let reg_file = RegFile {
+            keys: [
+                (RawNightLightState::REG_VALUE_PATH, &exported_bytes.state),
+                (
+                    RawNightLightSettings::REG_VALUE_PATH,
+                    &exported_bytes.settings,
+                ),
+            ]
+            .into_iter()
+            .map(|(reg_value_path, bytes)| RegFileKey {
+                hkey: reg_value_path.hkey,
+                subkey_path: reg_value_path.subkey_path.to_string(),
+                delete: false,
+                values: vec![RegFileValue {
+                    name: reg_value_path.value_name.to_string(),
+                    value: Some(RegValue {
+                        bytes: bytes.clone(),
+                        vtype: RegType::REG_BINARY,
+                    }),
+                }],
+            })
+            .collect(),
+        };
+        let reg_file = RegFile::from_str(&reg_file.to_string())?;
+
+        //. Changed after exporting, and then restored by importing.
+        let mut night_light = NightLight::from_provider(&provider, Strictness::Strict)?;
+        night_light.set_night_color_temp(Some(4000));
+        night_light.write_to_provider(&provider)?;
+        let changed_bytes = NightLightBytes::from_provider(&provider)?;
+
+        NightLight::import_reg_to_provider(&reg_file, &provider)?;
+        let night_light = NightLight::from_provider(&provider, Strictness::Strict)?;
+        assert_eq!(night_light.night_color_temp(), Some(2700));
+        assert!(!night_light.schedule_active());
+
+        //. Everything but the timestamps is restored, which are newer than those of the overwritten values.
+        let imported_bytes = NightLightBytes::from_provider(&provider)?;
+        for (imported, exported, changed) in [
+            (
+                &imported_bytes.state,
+                &exported_bytes.state,
+                &changed_bytes.state,
+            ),
+            (
+                &imported_bytes.settings,
+                &exported_bytes.settings,
+                &changed_bytes.settings,
+            ),
+        ] {
+            assert_eq!(imported.len(), exported.len());
+            assert!(
+                CloudStoreValuePrologue::epoch_secs_of(imported)
+                    > CloudStoreValuePrologue::epoch_secs_of(changed)
+            );
+        }
+        assert_eq!(imported_bytes.settings[15..], exported_bytes.settings[15..]);
+
+        assert!(matches!(
+            NightLight::import_reg_to_provider(&RegFile { keys: Vec::new() }, &provider),
+            Err(ImportError::NoValues)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn to_export_json() -> Result<(), super::Error> {
+        let provider = MemRegProvider::new();
+        let mut night_light = NightLight::from_provider(&provider, Strictness::Lenient)?;
+        night_light.set_night_color_temp(Some(2700));
+        night_light.write_to_provider(&provider)?;
+
+        let mut bytes = NightLightBytes::from_provider(&provider)?;
+        let json: serde_json::Value =
+            serde_json::from_str(&NightLight::to_export_json(&bytes, Strictness::Strict)).unwrap();
+        assert_eq!(json["nightLight"]["nightColorTemp"], 2700);
+        assert_eq!(json["parseError"], serde_json::Value::Null);
+        assert_eq!(
+            json["stateBytes"],
+            HexBytes::new(&bytes.state).to_string().as_str()
+        );
+
+        bytes.settings.truncate(4);
+        let json: serde_json::Value =
+            serde_json::from_str(&NightLight::to_export_json(&bytes, Strictness::Strict)).unwrap();
+        assert_eq!(json["nightLight"], serde_json::Value::Null);
+        assert!(json["parseError"].is_string());
+        assert_eq!(
+            json["settingsBytes"],
+            HexBytes::new(&bytes.settings).to_string().as_str()
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn sunset_to_sunrise_possible_is_some() {
         assert!(NightLight::sunset_to_sunrise_possible().is_some());
     }
 
+    #[test]
+    fn observed_changes() {
+        let bytes = NIGHT_LIGHT_STATE_BYTES;
+        let value = CloudStoreValue::from_bytes(&bytes, Strictness::Strict).unwrap();
+        let written_bytes = value.to_bytes_with_epoch_secs(1_700_000_002);
+
+        assert_eq!(
+            ObservedChange::of(&written_bytes, Some(&written_bytes)),
+            ObservedChange::Own
+        );
+        assert_eq!(
+            ObservedChange::of(
+                &written_bytes,
+                Some(&value.to_bytes_with_epoch_secs(1_700_000_000))
+            ),
+            ObservedChange::Revert
+        );
+        assert_eq!(
+            ObservedChange::of(
+                &written_bytes,
+                Some(&value.to_bytes_with_epoch_secs(1_700_000_004))
+            ),
+            ObservedChange::Newer
+        );
+        assert_eq!(
+            ObservedChange::of(&written_bytes, None),
+            ObservedChange::Newer
+        );
+
+        let written = [(RegValueId::Settings, written_bytes.clone())];
+        let mut current = NightLightBytes {
+            state: Vec::new(),
+            settings: written_bytes.clone(),
+        };
+        assert_eq!(WriteOutcome::of(&written, &current), WriteOutcome::Accepted);
+        assert_eq!(WriteOutcome::of(&[], &current), WriteOutcome::Accepted);
+        current.settings = value.to_bytes_with_epoch_secs(1_700_000_000);
+        assert_eq!(WriteOutcome::of(&written, &current), WriteOutcome::Reverted);
+        current.settings = value.to_bytes_with_epoch_secs(1_700_000_004);
+        assert_eq!(
+            WriteOutcome::of(&written, &current),
+            WriteOutcome::Superseded
+        );
+    }
+
+    #[test]
+    fn is_night_at() {
+        let mut night_light = NightLight::lenient_fallback();
+        night_light.set_schedule_type(ScheduleType::Explicit);
+        night_light.set_scheduled_night("21:00-7:00".parse().unwrap());
+        let clock_time = |s: &str| s.parse::<ClockTime>().unwrap();
+
+        night_light.set_schedule_active(false);
+        assert_eq!(night_light.is_night_at(clock_time("23:00")), None);
+
+        night_light.set_schedule_active(true);
+        assert_eq!(night_light.is_night_at(clock_time("23:00")), Some(true));
+        assert_eq!(night_light.is_night_at(clock_time("7:00")), Some(false));
+        assert_eq!(
+            night_light
+                .next_transition_after(&Utc.with_ymd_and_hms(2024, 3, 1, 23, 0, 0).unwrap())
+                .map(|transition| transition.activates),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn named_night_color_temps() {
+        assert_eq!(NightLight::named_night_color_temp("Warm"), Some(2700));
+        assert_eq!(NightLight::named_night_color_temp("hot"), None);
+
+        //. Ordered from warmest to coldest, all in the valid range.
+        let temps = NightLight::NAMED_NIGHT_COLOR_TEMPS.map(|(_, temp)| temp);
+        assert!(temps.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(temps.iter().all(|temp| {
+            (NightLight::MIN_NIGHT_COLOR_TEMP..=NightLight::MAX_NIGHT_COLOR_TEMP).contains(temp)
+        }));
+
+        for temp in temps {
+            assert_eq!(
+                NightLight::night_color_temp_from_warmth(NightLight::warmth_from_night_color_temp(
+                    temp
+                )),
+                temp
+            );
+        }
+    }
+
+    #[test]
+    fn adjust_warmth_by() {
+        let mut night_light = NightLight::lenient_fallback();
+        night_light.set_warmth(Some(0.5));
+        assert!((night_light.adjust_warmth_by(0.1) - 0.6).abs() < 1e-6);
+        assert!((night_light.warmth().unwrap() - 0.6).abs() < 1e-3);
+        assert_eq!(night_light.adjust_warmth_by(0.5), 1.0);
+        assert_eq!(night_light.adjust_warmth_by(-2.0), 0.0);
+
+        //. With gamma 2, the warmth factor 0.5 is corrected to its square root.
+        night_light.set_warmth(Some(0.5f32.sqrt()));
+        assert!((night_light.adjust_warmth_by_with_gamma(0.1, 2.0) - 0.6).abs() < 1e-3);
+        assert!((night_light.warmth().unwrap() - 0.6f32.sqrt()).abs() < 1e-3);
+
+        night_light.set_night_color_temp(None);
+        night_light.adjust_warmth_by(-0.1);
+        assert!((night_light.warmth().unwrap() - (NightLight::DEFAULT_WARMTH - 0.1)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn scheduled_night_from_sun() {
+        let berlin = Coordinates::new(52.52, 13.405).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let scheduled_night = super::scheduled_night_from_sun(
+            berlin,
+            date,
+            SunOffsets {
+                sunset: chrono::Duration::minutes(-30),
+                sunrise: chrono::Duration::zero(),
+            },
+            &Utc,
+        )
+        .unwrap();
+        //. Sunset at 19:33, sunrise at 02:43 UTC.
+        assert_eq!(scheduled_night.start.hour(), 19);
+        assert!((1..=5).contains(&scheduled_night.start.minute()));
+        assert_eq!(scheduled_night.end.hour(), 2);
+        assert!((41..=45).contains(&scheduled_night.end.minute()));
+
+        let tromsø = Coordinates::new(69.65, 18.96).unwrap();
+        assert_eq!(
+            super::scheduled_night_from_sun(tromsø, date, SunOffsets::default(), &Utc),
+            None
+        );
+    }
+
+    #[test]
+    fn fade_steps() {
+        let temps = (1..=4)
+            .map(|step| interpolate_temp(6500, 1200, step, 4))
+            .collect::<Vec<_>>();
+        assert_eq!(temps, [5175, 3850, 2525, 1200]);
+
+        assert_eq!(interpolate_temp(2000, 2003, 1, 2), 2001);
+        assert_eq!(interpolate_temp(4000, 4000, 1, 1), 4000);
+    }
+
+    #[test]
+    fn fade_step_timing() {
+        let duration = Duration::from_millis(90);
+        let start_instant = Instant::now();
+        let mut step_offsets = Vec::new();
+        run_fade_steps(duration, 3, None, |step| {
+            step_offsets.push((step, start_instant.elapsed()));
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+
+        assert_eq!(step_offsets.len(), 3);
+        for (step, offset) in step_offsets {
+            assert!(offset >= duration * step / 3, "step {step} at {offset:?}");
+        }
+
+        //. A stop signal ends the fade while waiting for the first step.
+        let stop_signal = StopSignal::new();
+        thread::spawn({
+            let stop_signal = stop_signal.clone();
+            move || {
+                thread::sleep(Duration::from_millis(20));
+                stop_signal.stop();
+            }
+        });
+
+        let start_instant = Instant::now();
+        let mut num_steps_written = 0;
+        run_fade_steps(Duration::from_secs(60), 2, Some(&stop_signal), |_| {
+            num_steps_written += 1;
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+        assert_eq!(num_steps_written, 0);
+        assert!(start_instant.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn expiration() {
+        let mut night_light = NightLight::lenient_fallback();
+        assert!(!night_light.is_expired());
+        assert!(night_light.time_left() <= NightLight::EXPIRATION_TIMEOUT);
+
+        night_light.set_expiration_timeout(Duration::ZERO);
+        thread::sleep(Duration::from_millis(1));
+        assert!(night_light.is_expired());
+        assert_eq!(night_light.time_left(), Duration::ZERO);
+
+        night_light.set_expiration_timeout(Duration::from_secs(3600));
+        assert!(night_light.time_left() > Duration::from_secs(3500));
+    }
+
     #[test]
     fn verify_warmth_setter() -> Result<(), super::Error> {
         let mut night_light = NightLight::from_reg()?;
@@ -823,4 +2425,23 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn write_plan_display() {
+        let plan = WritePlan {
+            current: NightLightBytes {
+                state: vec![0x43, 0x42, 0x01],
+                settings: vec![0x43, 0x42, 0x02],
+            },
+            state: None,
+            settings: Some(vec![0x43, 0x42, 0x03]),
+        };
+        assert!(!plan.is_empty());
+
+        let string = plan.to_string();
+        let (state_line, settings_line) = string.split_once('\n').unwrap();
+        assert_eq!(state_line, "State: unchanged");
+        assert!(settings_line.starts_with("Settings: "));
+        assert!(settings_line.contains("02") && settings_line.contains("03"));
+    }
 }
@@ -0,0 +1,93 @@
+use std::{collections::HashSet, io};
+
+use super::{ChangeSet, Error, NightLight, NightLightChange};
+use crate::{
+    data_conversion::Strictness,
+    reg::{
+        offline_hive::OfflineHive,
+        provider::UserHiveProvider,
+        sid::{loaded_user_sids, local_user_profiles},
+    },
+};
+
+/// The result of [`NightLight::apply_to_all_users()`] for one user.
+#[derive(Debug)]
+pub struct UserOutcome {
+    pub sid: String,
+    /// Whether the user wasn't logged on, so that the hive was loaded from the profile's `NTUSER.DAT`.
+    pub was_offline: bool,
+    /// The properties each write changed, like [`super::ApplyReport::writes`]. Empty, if the configuration was already in effect.
+    pub result: Result<Vec<NightLightChange>, Error>,
+}
+
+pub(super) fn apply_to_all_users(
+    change_set: &ChangeSet,
+    includes_offline: bool,
+    strictness: Strictness,
+) -> Result<Vec<UserOutcome>, io::Error> {
+    let loaded_sids = loaded_user_sids()?;
+    let profiles = local_user_profiles()?;
+
+    //. Loaded hives of accounts without profile, e.g., of services, are left alone.
+    let profile_sids = profiles
+        .iter()
+        .map(|profile| profile.sid.as_str())
+        .collect::<HashSet<_>>();
+    let mut outcomes = loaded_sids
+        .iter()
+        .filter(|sid| profile_sids.contains(sid.as_str()))
+        .map(|sid| UserOutcome {
+            sid: sid.clone(),
+            was_offline: false,
+            result: apply_to_hive(change_set, &UserHiveProvider::new(sid), strictness),
+        })
+        .collect::<Vec<_>>();
+
+    if includes_offline {
+        for profile in profiles
+            .iter()
+            .filter(|profile| !loaded_sids.contains(&profile.sid))
+        {
+            let result = OfflineHive::load(
+                profile.hive_file_path(),
+                &format!("sem-reg-{}", profile.sid),
+            )
+            .map_err(Error::from)
+            .and_then(|hive| {
+                apply_to_hive(
+                    change_set,
+                    &UserHiveProvider::new(hive.mount_key_name()),
+                    strictness,
+                )
+            });
+
+            outcomes.push(UserOutcome {
+                sid: profile.sid.clone(),
+                was_offline: true,
+                result,
+            });
+        }
+    }
+
+    Ok(outcomes)
+}
+
+fn apply_to_hive(
+    change_set: &ChangeSet,
+    provider: &UserHiveProvider,
+    strictness: Strictness,
+) -> Result<Vec<NightLightChange>, Error> {
+    //! Writes the batches without waiting in between, since Windows' reaction can't be awaited in other users' sessions.
+
+    let mut writes = Vec::new();
+    for batch in change_set.batches() {
+        let mut inst = NightLight::from_provider(provider, strictness)?;
+        let change = batch.apply_to(&mut inst);
+        if !change.is_empty() {
+            inst.write_to_provider(provider)?;
+            writes.push(change);
+        }
+    }
+
+    Ok(writes)
+}
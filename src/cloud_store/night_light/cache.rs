@@ -0,0 +1,278 @@
+use std::{
+    convert::Infallible,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+};
+use wmi::WMIError;
+
+use super::{
+    Error, NightLight, NightLightBytes, RawNightLightSettings, RawNightLightState, RegValueId,
+};
+use crate::{
+    cloud_store::semantic_value::SemanticRegValue,
+    data_conversion::Strictness,
+    log_warn,
+    reg::{
+        monitor::{RegValueMonitor, StopSignal},
+        provider::{RegProvider, WinRegProvider},
+    },
+};
+
+/// Serves repeated reads of Night Light's configuration without accessing the registry every time, for GUI front-ends and daemons querying it many times per second. The registry values are only read and parsed again after a monitor running on a background thread reported a change.
+///
+/// If the monitor fails later on, every read accesses the registry, like without a cache.
+pub struct NightLightCache {
+    shared: Arc<Shared>,
+    stop_signal: StopSignal,
+    monitor_thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    /// The Windows registry, except in tests.
+    provider: Arc<dyn RegProvider>,
+    strictness: Strictness,
+    entry: Mutex<Option<CacheEntry>>,
+    monitored: AtomicBool,
+}
+
+struct CacheEntry {
+    bytes: NightLightBytes,
+    night_light: NightLight,
+}
+
+impl NightLightCache {
+    pub fn new(strictness: Strictness) -> Result<Self, WMIError> {
+        //! Starts the monitor thread. Fails, if the monitor can't be created. The registry values are read on the first access.
+
+        let shared = Arc::new(Shared::new(Arc::new(WinRegProvider), strictness));
+        let stop_signal = StopSignal::new();
+        let (setup_sender, setup_receiver) = mpsc::channel();
+
+        let monitor_thread = thread::spawn({
+            let shared = shared.clone();
+            let stop_signal = stop_signal.clone();
+            move || {
+                //. WMI connections can't be sent to other threads, so the monitor is created on its own.
+                let mut monitor = match RegValueMonitor::new([
+                    (RegValueId::State, &RawNightLightState::REG_VALUE_PATH),
+                    (RegValueId::Settings, &RawNightLightSettings::REG_VALUE_PATH),
+                ]) {
+                    Ok(monitor) => {
+                        let _ = setup_sender.send(Ok(()));
+                        monitor
+                    }
+                    Err(error) => {
+                        let _ = setup_sender.send(Err(error));
+                        return;
+                    }
+                };
+
+                let result = monitor.loop_blocking(Some(&stop_signal), |_| {
+                    shared.invalidate();
+                    None::<Result<(), Infallible>>
+                });
+
+                if let Err(error) = result {
+                    log_warn!("Night Light cache stopped monitoring: {error}");
+                    shared.monitored.store(false, Ordering::SeqCst);
+                    shared.invalidate();
+                }
+            }
+        });
+
+        setup_receiver
+            .recv()
+            .expect("monitor thread should report its setup")?;
+
+        Ok(Self {
+            shared,
+            stop_signal,
+            monitor_thread: Some(monitor_thread),
+        })
+    }
+
+    pub fn with<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&NightLight) -> R,
+    {
+        //! Calls the function with the cached instance, reading the registry values first, if they changed since the last read.
+        //!
+        //! The instance is meant for reading. It expires like one created with [`NightLight::from_reg()`] counting from when the registry values were read, so use a new instance for changing the configuration.
+
+        let entry = self.shared.current_entry()?;
+        Ok(f(&entry
+            .as_ref()
+            .expect("entry should be current")
+            .night_light))
+    }
+
+    pub fn bytes(&self) -> Result<NightLightBytes, Error> {
+        //! The raw bytes the cached instance was parsed from.
+
+        let entry = self.shared.current_entry()?;
+        Ok(entry
+            .as_ref()
+            .expect("entry should be current")
+            .bytes
+            .clone())
+    }
+
+    pub fn invalidate(&self) {
+        //! Makes the next access read the registry values, e.g., after writing them, to not depend on the delay of the monitor.
+
+        self.shared.invalidate();
+    }
+
+    pub fn is_monitored(&self) -> bool {
+        //! Whether the monitor still works. If not, every access reads the registry.
+
+        self.shared.monitored.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for NightLightCache {
+    fn drop(&mut self) {
+        self.stop_signal.stop();
+        if let Some(monitor_thread) = self.monitor_thread.take() {
+            let _ = monitor_thread.join();
+        }
+    }
+}
+
+impl Shared {
+    fn new(provider: Arc<dyn RegProvider>, strictness: Strictness) -> Self {
+        Self {
+            provider,
+            strictness,
+            entry: Mutex::new(None),
+            monitored: AtomicBool::new(true),
+        }
+    }
+
+    fn current_entry(&self) -> Result<MutexGuard<'_, Option<CacheEntry>>, Error> {
+        //! Reading happens while the lock is held, so that an invalidation by a change during the read can't be overwritten with the outdated result.
+
+        let mut entry = self.entry.lock().unwrap();
+
+        if entry.is_none() || !self.monitored.load(Ordering::SeqCst) {
+            let bytes = NightLightBytes::from_provider(&*self.provider)?;
+            let night_light =
+                NightLight::from_bytes_with_strictness(bytes.clone(), self.strictness)?;
+            *entry = Some(CacheEntry { bytes, night_light });
+        }
+
+        Ok(entry)
+    }
+
+    fn invalidate(&self) {
+        *self.entry.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::Ordering, Arc},
+        time::SystemTime,
+    };
+
+    use super::Shared;
+    use crate::{
+        cloud_store::{
+            night_light::{settings::RawNightLightSettings, state::RawNightLightState},
+            semantic_value::SemanticRegValue,
+            test_fixtures::{NIGHT_LIGHT_SETTINGS_BYTES, NIGHT_LIGHT_STATE_BYTES},
+        },
+        data_conversion::Strictness,
+        reg::provider::{MemRegProvider, RegProvider},
+    };
+
+    fn shared_with_fixtures() -> (Arc<MemRegProvider>, Shared) {
+        let provider = Arc::new(MemRegProvider::new());
+        provider
+            .write_bin_value(
+                &RawNightLightState::REG_VALUE_PATH,
+                &NIGHT_LIGHT_STATE_BYTES,
+                true,
+            )
+            .unwrap();
+        provider
+            .write_bin_value(
+                &RawNightLightSettings::REG_VALUE_PATH,
+                &NIGHT_LIGHT_SETTINGS_BYTES,
+                true,
+            )
+            .unwrap();
+
+        let shared = Shared::new(provider.clone(), Strictness::Strict);
+        (provider, shared)
+    }
+
+    fn cached_state_bytes(shared: &Shared) -> Vec<u8> {
+        shared
+            .current_entry()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .bytes
+            .state
+            .clone()
+    }
+
+    fn new_state_bytes() -> Vec<u8> {
+        RawNightLightState::lenient_fallback(SystemTime::now())
+            .to_bytes()
+            .unwrap()
+    }
+
+    #[test]
+    fn hits_until_invalidated() {
+        let (provider, shared) = shared_with_fixtures();
+        assert_eq!(cached_state_bytes(&shared), NIGHT_LIGHT_STATE_BYTES);
+
+        //. Without a change reported by the monitor, the registry isn't read again.
+        let new_bytes = new_state_bytes();
+        provider
+            .write_bin_value(&RawNightLightState::REG_VALUE_PATH, &new_bytes, false)
+            .unwrap();
+        assert_eq!(cached_state_bytes(&shared), NIGHT_LIGHT_STATE_BYTES);
+
+        shared.invalidate();
+        assert_eq!(cached_state_bytes(&shared), new_bytes);
+    }
+
+    #[test]
+    fn reads_every_time_when_unmonitored() {
+        let (provider, shared) = shared_with_fixtures();
+        assert_eq!(cached_state_bytes(&shared), NIGHT_LIGHT_STATE_BYTES);
+
+        //. A stale entry isn't served after the monitor failed.
+        shared.monitored.store(false, Ordering::SeqCst);
+        let new_bytes = new_state_bytes();
+        provider
+            .write_bin_value(&RawNightLightState::REG_VALUE_PATH, &new_bytes, false)
+            .unwrap();
+        assert_eq!(cached_state_bytes(&shared), new_bytes);
+    }
+
+    #[test]
+    fn failed_read_not_cached() {
+        let (provider, shared) = shared_with_fixtures();
+        provider
+            .write_bin_value(&RawNightLightState::REG_VALUE_PATH, &[0x43], false)
+            .unwrap();
+        assert!(shared.current_entry().is_err());
+
+        provider
+            .write_bin_value(
+                &RawNightLightState::REG_VALUE_PATH,
+                &NIGHT_LIGHT_STATE_BYTES,
+                false,
+            )
+            .unwrap();
+        assert_eq!(cached_state_bytes(&shared), NIGHT_LIGHT_STATE_BYTES);
+    }
+}
@@ -0,0 +1,93 @@
+use super::{ClockTimeFrame, NightLight, ScheduleType};
+
+/// The properties that differ between two configurations, as passed to the callback of [`NightLight::monitor_changes()`]. Every field is `None` if the property stayed the same, and contains the new value otherwise.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct NightLightChange {
+    pub active: Option<bool>,
+    pub night_color_temp: Option<Option<u16>>,
+    pub night_preview_active: Option<bool>,
+    pub schedule_active: Option<bool>,
+    pub schedule_type: Option<ScheduleType>,
+    pub scheduled_night: Option<ClockTimeFrame>,
+    pub sunset_to_sunrise: Option<Option<ClockTimeFrame>>,
+}
+
+impl NightLightChange {
+    pub fn between(old: &NightLight, new: &NightLight) -> Self {
+        //! Compares the properties of the instances. Timestamps aren't compared, so that rewriting the same configuration doesn't count as a change.
+
+        fn changed<T: PartialEq>(old: T, new: T) -> Option<T> {
+            (old != new).then_some(new)
+        }
+
+        Self {
+            active: changed(old.active(), new.active()),
+            night_color_temp: changed(old.night_color_temp(), new.night_color_temp()),
+            night_preview_active: changed(old.night_preview_active(), new.night_preview_active()),
+            schedule_active: changed(old.schedule_active(), new.schedule_active()),
+            schedule_type: changed(old.schedule_type(), new.schedule_type()),
+            scheduled_night: changed(old.scheduled_night(), new.scheduled_night()),
+            sunset_to_sunrise: changed(old.sunset_to_sunrise(), new.sunset_to_sunrise()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn schedule_changed(&self) -> bool {
+        //! Whether any property that decides when Night Light is active by schedule changed.
+
+        self.schedule_active.is_some()
+            || self.schedule_type.is_some()
+            || self.scheduled_night.is_some()
+            || self.sunset_to_sunrise.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NightLightChange;
+    use crate::cloud_store::night_light::{ClockTime, ClockTimeFrame, NightLight, ScheduleType};
+
+    #[test]
+    fn between() {
+        let old = NightLight::lenient_fallback();
+        let mut new = NightLight::lenient_fallback();
+
+        let change = NightLightChange::between(&old, &new);
+        assert!(change.is_empty());
+        assert!(!change.schedule_changed());
+
+        new.set_active(!old.active());
+        new.set_night_color_temp(Some(2700));
+        let scheduled_night = ClockTimeFrame {
+            start: ClockTime::from_h_min(22, 30).unwrap(),
+            end: ClockTime::from_h_min(6, 15).unwrap(),
+        };
+        new.set_scheduled_night(scheduled_night);
+        new.set_schedule_type(match old.schedule_type() {
+            ScheduleType::Explicit => ScheduleType::SunsetToSunrise,
+            ScheduleType::SunsetToSunrise => ScheduleType::Explicit,
+        });
+
+        let change = NightLightChange::between(&old, &new);
+        assert_eq!(
+            change,
+            NightLightChange {
+                active: Some(new.active()),
+                night_color_temp: Some(Some(2700)),
+                schedule_type: Some(new.schedule_type()),
+                scheduled_night: Some(scheduled_night),
+                ..Default::default()
+            }
+        );
+        assert!(!change.is_empty());
+        assert!(change.schedule_changed());
+
+        //. Back to the old configuration.
+        let change = NightLightChange::between(&new, &old);
+        assert_eq!(change.night_color_temp, Some(old.night_color_temp()));
+        assert_eq!(change.active, Some(old.active()));
+    }
+}
@@ -0,0 +1,348 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+use super::{ClockTimeFrame, NightLight, NightLightChange, ScheduleType};
+
+/// Changes to apply with [`NightLight::apply()`]. `None` fields are left unchanged. Unlike with the setters, any combination is allowed.
+///
+/// Used for configuration profiles, presets, undo points, daemon requests and [`NightLight::enforce()`]. When deserializing, the color temperature can also be given as `warmth` from 0 to 1, and `defaultTemp = true` sets the default color temperature. The scheduled night is in 24-hour format, like `21:00-07:00`.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(try_from = "ChangeSetRepr", into = "ChangeSetRepr")]
+pub struct ChangeSet {
+    pub active: Option<bool>,
+    /// `Some(None)` sets Windows' default color temperature.
+    pub night_color_temp: Option<Option<u16>>,
+    pub schedule_active: Option<bool>,
+    pub schedule_type: Option<ScheduleType>,
+    pub scheduled_night: Option<ClockTimeFrame>,
+}
+
+impl ChangeSet {
+    pub fn from_night_light(night_light: &NightLight) -> Self {
+        //! All properties the user chooses, as they are in the instance, like for restoring the configuration later. Transient properties like the preview state aren't included. With an active schedule, the active-state isn't included either, since Windows determines it then.
+
+        Self {
+            active: (!night_light.schedule_active()).then_some(night_light.active()),
+            night_color_temp: Some(night_light.night_color_temp()),
+            schedule_active: Some(night_light.schedule_active()),
+            schedule_type: Some(night_light.schedule_type()),
+            scheduled_night: Some(night_light.scheduled_night()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn changes_schedule(&self) -> bool {
+        //! Whether the change set contains settings that may make Windows change the state.
+
+        self.schedule_active.is_some()
+            || self.schedule_type.is_some()
+            || self.scheduled_night.is_some()
+    }
+
+    pub fn batches(&self) -> Vec<ChangeSet> {
+        //! Splits the change set into parts that can each be written at once, in the order they must be written. The state comes last, so that it isn't overwritten by Windows reacting to schedule changes.
+
+        if self.active.is_none() || !self.changes_schedule() {
+            return if self.is_empty() {
+                Vec::new()
+            } else {
+                vec![self.clone()]
+            };
+        }
+
+        vec![
+            Self {
+                active: None,
+                ..self.clone()
+            },
+            Self {
+                active: self.active,
+                ..Default::default()
+            },
+        ]
+    }
+
+    pub fn apply_to(&self, night_light: &mut NightLight) -> NightLightChange {
+        //! Sets the properties that deviate, and returns which ones were set. Writing the instance afterwards fails for irreconcilable combinations; see [`Self::batches()`].
+
+        let change = self.deviations_of(night_light);
+
+        if let Some(schedule_active) = change.schedule_active {
+            night_light.set_schedule_active(schedule_active);
+        }
+        if let Some(schedule_type) = change.schedule_type {
+            night_light.set_schedule_type(schedule_type);
+        }
+        if let Some(scheduled_night) = change.scheduled_night {
+            night_light.set_scheduled_night(scheduled_night);
+        }
+        if let Some(night_color_temp) = change.night_color_temp {
+            night_light.set_night_color_temp(night_color_temp);
+        }
+        if let Some(active) = change.active {
+            night_light.set_active(active);
+        }
+
+        change
+    }
+
+    pub fn deviations_of(&self, night_light: &NightLight) -> NightLightChange {
+        //! The requested properties that the instance doesn't have, e.g., because Windows changed the state after the schedule was changed.
+
+        fn deviating<T: PartialEq + Copy>(requested: Option<T>, current: T) -> Option<T> {
+            requested.filter(|requested| *requested != current)
+        }
+
+        NightLightChange {
+            active: deviating(self.active, night_light.active()),
+            night_color_temp: deviating(self.night_color_temp, night_light.night_color_temp()),
+            schedule_active: deviating(self.schedule_active, night_light.schedule_active()),
+            schedule_type: deviating(self.schedule_type, night_light.schedule_type()),
+            scheduled_night: deviating(self.scheduled_night, night_light.scheduled_night()),
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for ChangeSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //! Lists the contained changes, like `on, 2700 K, schedule on`. Empty for an empty change set.
+
+        let mut parts = Vec::new();
+        if let Some(active) = self.active {
+            parts.push(if active { "on" } else { "off" }.to_string());
+        }
+        match self.night_color_temp {
+            Some(Some(night_color_temp)) => parts.push(format!("{night_color_temp} K")),
+            Some(None) => parts.push("default temperature".to_string()),
+            None => {}
+        }
+        if let Some(schedule_active) = self.schedule_active {
+            parts.push(format!(
+                "schedule {}",
+                if schedule_active { "on" } else { "off" }
+            ));
+        }
+        if let Some(schedule_type) = self.schedule_type {
+            parts.push(format!("{schedule_type:?} schedule"));
+        }
+        if let Some(scheduled_night) = self.scheduled_night {
+            parts.push(format!("night {}", scheduled_night.format(false)));
+        }
+
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// The serialized form of [`ChangeSet`].
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct ChangeSetRepr {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    night_color_temp: Option<u16>,
+    /// Ignored, if `night_color_temp` is specified.
+    #[serde(skip_serializing)]
+    warmth: Option<f32>,
+    /// Takes precedence over `night_color_temp` and `warmth`.
+    #[serde(skip_serializing_if = "is_false")]
+    default_temp: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schedule_active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schedule_type: Option<ScheduleType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scheduled_night: Option<String>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl TryFrom<ChangeSetRepr> for ChangeSet {
+    type Error = String;
+
+    fn try_from(repr: ChangeSetRepr) -> Result<Self, Self::Error> {
+        let night_color_temp = if repr.default_temp {
+            Some(None)
+        } else if let Some(night_color_temp) = repr.night_color_temp {
+            Some(Some(night_color_temp))
+        } else if let Some(warmth) = repr.warmth {
+            if !(0.0..=1.0).contains(&warmth) {
+                return Err(format!("warmth not between 0 and 1: {warmth}"));
+            }
+            Some(Some(NightLight::night_color_temp_from_warmth(warmth)))
+        } else {
+            None
+        };
+
+        let scheduled_night = repr
+            .scheduled_night
+            .map(|scheduled_night| {
+                ClockTimeFrame::from_str(&scheduled_night)
+                    .map_err(|_| format!("invalid scheduled night: '{scheduled_night}'"))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            active: repr.active,
+            night_color_temp,
+            schedule_active: repr.schedule_active,
+            schedule_type: repr.schedule_type,
+            scheduled_night,
+        })
+    }
+}
+
+impl From<ChangeSet> for ChangeSetRepr {
+    fn from(change_set: ChangeSet) -> Self {
+        Self {
+            active: change_set.active,
+            night_color_temp: change_set.night_color_temp.flatten(),
+            warmth: None,
+            default_temp: change_set.night_color_temp == Some(None),
+            schedule_active: change_set.schedule_active,
+            schedule_type: change_set.schedule_type,
+            scheduled_night: change_set
+                .scheduled_night
+                .map(|scheduled_night| scheduled_night.format(false)),
+        }
+    }
+}
+
+/// The result of [`NightLight::apply()`].
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct ApplyReport {
+    /// The properties each write changed, in order. Batches whose properties were already in effect weren't written.
+    pub writes: Vec<NightLightChange>,
+    /// The requested properties not in effect after the last write, re-read from the registry. Normally empty.
+    pub deviations: NightLightChange,
+}
+
+impl ApplyReport {
+    pub fn succeeded(&self) -> bool {
+        self.deviations.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChangeSet;
+    use crate::cloud_store::night_light::{
+        ClockTime, ClockTimeFrame, NightLight, NightLightChange, ScheduleType,
+    };
+
+    #[test]
+    fn batches() {
+        assert!(ChangeSet::default().batches().is_empty());
+
+        let change_set = ChangeSet {
+            active: Some(true),
+            night_color_temp: Some(Some(2700)),
+            ..Default::default()
+        };
+        let batches = change_set.batches();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0], change_set);
+
+        let change_set = ChangeSet {
+            active: Some(true),
+            night_color_temp: Some(Some(2700)),
+            schedule_active: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(
+            change_set.batches(),
+            [
+                ChangeSet {
+                    night_color_temp: Some(Some(2700)),
+                    schedule_active: Some(false),
+                    ..Default::default()
+                },
+                ChangeSet {
+                    active: Some(true),
+                    ..Default::default()
+                },
+            ]
+        );
+
+        //. Every batch must be writable on its own, unlike the whole change set.
+        let fallback = NightLight::lenient_fallback();
+        let change_set = ChangeSet {
+            active: Some(!fallback.active()),
+            schedule_active: Some(!fallback.schedule_active()),
+            ..Default::default()
+        };
+        for batch in change_set.batches() {
+            let mut night_light = NightLight::lenient_fallback();
+            batch.apply_to(&mut night_light);
+            assert!(night_light.verify_state_and_settings().is_ok());
+        }
+        let mut night_light = NightLight::lenient_fallback();
+        change_set.apply_to(&mut night_light);
+        assert!(night_light.verify_state_and_settings().is_err());
+    }
+
+    #[test]
+    fn serde() {
+        let mut night_light = NightLight::lenient_fallback();
+        night_light.set_schedule_active(false);
+        night_light.set_night_color_temp(None);
+        let change_set = ChangeSet::from_night_light(&night_light);
+        assert!(change_set.active.is_some());
+
+        let json = serde_json::to_string(&change_set).unwrap();
+        assert!(json.contains(r#""defaultTemp":true"#));
+        assert!(!json.contains("nightColorTemp"));
+        assert_eq!(
+            serde_json::from_str::<ChangeSet>(&json).unwrap(),
+            change_set
+        );
+
+        let change_set: ChangeSet =
+            toml::from_str("warmth = 1.0\nscheduledNight = '22:30-06:15'").unwrap();
+        assert_eq!(
+            change_set.night_color_temp,
+            Some(Some(NightLight::MIN_NIGHT_COLOR_TEMP))
+        );
+        assert_eq!(
+            change_set.scheduled_night.map(|frame| frame.format(false)),
+            Some("22:30-06:15".to_string())
+        );
+        assert_eq!(change_set.to_string(), "1200 K, night 22:30-06:15");
+
+        assert!(toml::from_str::<ChangeSet>("scheduledNight = '22:30'").is_err());
+        assert!(toml::from_str::<ChangeSet>("warmth = 2.0").is_err());
+
+        night_light.set_schedule_active(true);
+        assert_eq!(ChangeSet::from_night_light(&night_light).active, None);
+    }
+
+    #[test]
+    fn apply_to() {
+        let mut night_light = NightLight::lenient_fallback();
+        let scheduled_night = ClockTimeFrame {
+            start: ClockTime::from_h_min(22, 30).unwrap(),
+            end: ClockTime::from_h_min(6, 15).unwrap(),
+        };
+        let change_set = ChangeSet {
+            active: Some(night_light.active()),
+            schedule_type: Some(ScheduleType::Explicit),
+            scheduled_night: Some(scheduled_night),
+            ..Default::default()
+        };
+
+        let change = change_set.apply_to(&mut night_light);
+        assert_eq!(change.active, None);
+        assert_eq!(change.scheduled_night, Some(scheduled_night));
+        assert_eq!(night_light.scheduled_night(), scheduled_night);
+        assert_eq!(
+            change_set.deviations_of(&night_light),
+            NightLightChange::default()
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+use super::ChangeSet;
+
+pub(super) fn read_presets(file_path: &Path) -> Result<BTreeMap<String, ChangeSet>, PresetError> {
+    //! A missing file counts as containing no presets.
+
+    match fs::read_to_string(file_path) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+pub(super) fn write_presets(
+    file_path: &Path,
+    presets: &BTreeMap<String, ChangeSet>,
+) -> Result<(), PresetError> {
+    Ok(write_file(
+        file_path,
+        &serde_json::to_string_pretty(presets)?,
+    )?)
+}
+
+pub(super) fn write_file(file_path: &Path, json: &str) -> Result<(), io::Error> {
+    if let Some(dir_path) = file_path.parent() {
+        fs::create_dir_all(dir_path)?;
+    }
+
+    //. Rename a complete file, so that a crash doesn't lose the other entries.
+    let temp_file_path = file_path.with_extension("tmp");
+    let mut file = File::create(&temp_file_path)?;
+    file.write_all(json.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(temp_file_path, file_path)?;
+
+    Ok(())
+}
+
+/// Error of the preset methods of [`NightLight`].
+#[derive(thiserror::Error, Debug)]
+pub enum PresetError {
+    #[error("no preset named '{0}'")]
+    NotFound(String),
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::cloud_store::night_light::{ChangeSet, NightLight, ScheduleType};
+
+    #[test]
+    fn apply_to() {
+        let mut night_light = NightLight::lenient_fallback();
+        night_light.set_schedule_active(false);
+        night_light.set_schedule_type(ScheduleType::Explicit);
+        night_light.set_night_color_temp(Some(2700));
+        let preset = ChangeSet::from_night_light(&night_light);
+
+        let json = serde_json::to_string(&BTreeMap::from([("reading", preset.clone())])).unwrap();
+        let presets: BTreeMap<String, ChangeSet> = serde_json::from_str(&json).unwrap();
+        assert_eq!(presets["reading"], preset);
+
+        let mut other = NightLight::lenient_fallback();
+        other.set_night_color_temp(None);
+        other.set_active(!night_light.active());
+        preset.apply_to(&mut other);
+        assert_eq!(ChangeSet::from_night_light(&other), preset);
+    }
+}
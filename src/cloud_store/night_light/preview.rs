@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use super::{Error, NightLight};
+use crate::{data_conversion::Strictness, log_warn, reg::write_scheduler::RegWriteScheduler};
+
+/// Holds night preview mode active while it exists, and deactivates it when released or dropped, also when unwinding from a panic. Since active preview mode makes changes of the state and the schedule irreconcilable (see the module documentation), it mustn't be left active.
+///
+/// If preview mode was already active when the guard was created, e.g., because the user is dragging the color temperature slider in the official settings, it's left active.
+pub struct NightPreviewGuard {
+    strictness: Strictness,
+    write_scheduler: RegWriteScheduler<'static>,
+    was_active: bool,
+    /// Written when deactivating, if `Some`.
+    restored_night_color_temp: Option<Option<u16>>,
+    is_released: bool,
+}
+
+impl NightPreviewGuard {
+    pub fn activate(strictness: Strictness, min_write_interval: Duration) -> Result<Self, Error> {
+        //! Activates preview mode. The writes of the guard, incl. that of [`Self::set_night_color_temp()`], are at least `min_write_interval` apart.
+
+        Self::activate_with(strictness, min_write_interval, false)
+    }
+
+    pub fn activate_invisibly(
+        strictness: Strictness,
+        min_write_interval: Duration,
+    ) -> Result<Self, Error> {
+        //! Like [`Self::activate()`], but if Night Light is inactive, also sets the coldest color temperature, so that the previewing can't be seen, and restores the previous one when deactivating.
+
+        Self::activate_with(strictness, min_write_interval, true)
+    }
+
+    fn activate_with(
+        strictness: Strictness,
+        min_write_interval: Duration,
+        invisibly: bool,
+    ) -> Result<Self, Error> {
+        let mut guard = Self {
+            strictness,
+            write_scheduler: RegWriteScheduler::new(Duration::ZERO, min_write_interval),
+            was_active: false,
+            restored_night_color_temp: None,
+            is_released: true,
+        };
+
+        let mut inst = NightLight::from_reg_with_strictness(strictness)?;
+        guard.was_active = inst.night_preview_active();
+        if !guard.was_active {
+            if invisibly && !inst.active() {
+                guard.restored_night_color_temp = Some(inst.night_color_temp());
+                inst.set_night_color_temp(Some(NightLight::COLDEST_NIGHT_COLOR_TEMP));
+            }
+            inst.set_night_preview_active(true);
+            inst.queue_writes(&mut guard.write_scheduler)?;
+            guard.write_scheduler.flush()?;
+        }
+
+        //. Only now there's something to undo.
+        guard.is_released = false;
+        Ok(guard)
+    }
+
+    pub fn was_active(&self) -> bool {
+        //! Whether preview mode was already active before creating the guard, so that it's left active.
+
+        self.was_active
+    }
+
+    pub fn set_night_color_temp(&mut self, night_color_temp: Option<u16>) -> Result<(), Error> {
+        //! Writes the color temperature while holding preview mode active, e.g., for an animation.
+
+        let mut inst = NightLight::from_reg_with_strictness(self.strictness)?;
+        inst.set_night_preview_active(true);
+        inst.set_night_color_temp(night_color_temp);
+        inst.queue_writes(&mut self.write_scheduler)?;
+        Ok(self.write_scheduler.flush()?)
+    }
+
+    pub fn restore_night_color_temp_on_release(&mut self, night_color_temp: Option<u16>) {
+        //! Makes deactivating also write the color temperature, e.g., the one from before an animation.
+
+        self.restored_night_color_temp = Some(night_color_temp);
+    }
+
+    pub fn release(mut self) -> Result<(), Error> {
+        //! Deactivates preview mode, unless it was already active before, and reports errors, unlike dropping.
+
+        self.deactivate()
+    }
+
+    fn deactivate(&mut self) -> Result<(), Error> {
+        //. Not retried on failure, also not by `drop()`.
+        self.is_released = true;
+
+        if self.was_active && self.restored_night_color_temp.is_none() {
+            return Ok(());
+        }
+
+        let mut inst = NightLight::from_reg_with_strictness(self.strictness)?;
+        if !self.was_active {
+            inst.set_night_preview_active(false);
+        }
+        if let Some(night_color_temp) = self.restored_night_color_temp {
+            inst.set_night_color_temp(night_color_temp);
+        }
+        inst.queue_writes(&mut self.write_scheduler)?;
+        Ok(self.write_scheduler.flush()?)
+    }
+}
+
+impl Drop for NightPreviewGuard {
+    fn drop(&mut self) {
+        if !self.is_released {
+            if let Err(error) = self.deactivate() {
+                log_warn!("couldn't deactivate night preview: {error}");
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use super::{ClockTime, ClockTimeFrame, NightLight};
+
+/// A gradual change of the warmth over the night, like with f.lux: Starting at the beginning of the night, the warmth rises from one factor to another over the duration, and is then kept until the night ends. Outside of the night, the starting warmth applies, so that it's already in effect when the night begins.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WarmthRamp {
+    /// The warmth factor from 0 to 1 at the beginning of the night.
+    pub start_warmth: f32,
+    /// The warmth factor from 0 to 1 reached after the duration.
+    pub end_warmth: f32,
+    pub duration: Duration,
+    /// The gamma with which the warmth factors are corrected, like with [`NightLight::adjust_warmth_by_with_gamma()`]. The warmth rises linearly before the correction.
+    pub gamma: f32,
+}
+
+impl WarmthRamp {
+    pub fn warmth_at(&self, night: ClockTimeFrame, clock_time: ClockTime) -> f32 {
+        //! The gamma-corrected warmth factor at the clock time in the night, as [`NightLight::set_warmth()`] takes it.
+        //!
+        //! # Panics
+        //! Panics on NaN.
+
+        let progress = if night.contains(clock_time) {
+            let elapsed = ClockTimeFrame {
+                start: night.start,
+                end: clock_time,
+            }
+            .duration()
+            .to_std()
+            .expect("duration should be positive");
+
+            if self.duration.is_zero() {
+                1.0
+            } else {
+                (elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+            }
+        } else {
+            0.0
+        };
+
+        let warmth = self.start_warmth + (self.end_warmth - self.start_warmth) * progress;
+        warmth.clamp(0.0, 1.0).powf(1.0 / self.gamma)
+    }
+
+    pub fn night_color_temp_at(&self, night: ClockTimeFrame, clock_time: ClockTime) -> u16 {
+        //! Like [`Self::warmth_at()`], but converted to a color temperature.
+
+        NightLight::night_color_temp_from_warmth(self.warmth_at(night, clock_time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::WarmthRamp;
+    use crate::cloud_store::night_light::{ClockTime, ClockTimeFrame};
+
+    #[test]
+    fn warmth_at() {
+        let ramp = WarmthRamp {
+            start_warmth: 0.2,
+            end_warmth: 0.8,
+            duration: Duration::from_secs(2 * 60 * 60),
+            gamma: 1.0,
+        };
+        let night = "22:00-6:00".parse::<ClockTimeFrame>().unwrap();
+        let warmth_at = |s: &str| ramp.warmth_at(night, s.parse::<ClockTime>().unwrap());
+
+        assert!((warmth_at("12:00") - 0.2).abs() < 1e-6);
+        assert!((warmth_at("22:00") - 0.2).abs() < 1e-6);
+        assert!((warmth_at("23:00") - 0.5).abs() < 1e-6);
+        assert!((warmth_at("0:00") - 0.8).abs() < 1e-6);
+        assert!((warmth_at("5:59") - 0.8).abs() < 1e-6);
+        assert!((warmth_at("6:00") - 0.2).abs() < 1e-6);
+
+        let ramp = WarmthRamp {
+            duration: Duration::ZERO,
+            gamma: 2.0,
+            ..ramp
+        };
+        assert!((ramp.warmth_at(night, "23:00".parse().unwrap()) - 0.8f32.sqrt()).abs() < 1e-6);
+    }
+}
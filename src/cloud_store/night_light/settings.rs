@@ -1,23 +1,25 @@
-use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
 use winreg::enums::HKEY_CURRENT_USER;
 
 use super::{
-    time::{BinConvertClockTime, ClockTime, ClockTimeFrame},
+    time::{clock_time_from_fields, BinConvertClockTime, ClockTime, ClockTimeFrame},
     NightLight,
 };
 use crate::{
-    cloud_store::prologue::CloudStoreValuePrologue,
+    cloud_store::{
+        fields::{Field, FieldType, FieldValue},
+        semantic_value::SemanticRegValue,
+    },
     data_conversion::{
         byte_seq::{ByteSeq, ParseError},
-        time::{
-            epoch_duration_to_epoch_secs, now_as_epoch_duration, system_time_to_epoch_duration,
-        },
+        time::{epoch_duration_to_epoch_secs, system_time_to_epoch_duration},
         ResultOrElseIf, Strictness, TrackedValue,
     },
-    reg::{read_reg_bin_value, RegValuePath},
+    reg::RegValuePath,
 };
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct RawNightLightSettings {
     pub prologue_epoch_secs: u32,
     pub schedule_active: TrackedValue<bool>,
@@ -28,88 +30,84 @@ pub struct RawNightLightSettings {
     pub night_preview_active: TrackedValue<bool>,
 }
 
-impl RawNightLightSettings {
-    pub const REG_VALUE_PATH: RegValuePath<'_> = RegValuePath {
+impl SemanticRegValue for RawNightLightSettings {
+    const REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
         hkey: HKEY_CURRENT_USER,
         subkey_path: r"SOFTWARE\Microsoft\Windows\CurrentVersion\CloudStore\Store\DefaultAccount\Current\default$windows.data.bluelightreduction.settings\windows.data.bluelightreduction.settings",
         value_name: "Data",
     };
+    const NAME: &'static str = "nightLight.settings";
+    const MAX_BODY_LEN: usize = 45;
 
-    pub fn from_reg(strictness: Strictness) -> Result<Self, super::Error> {
-        Ok(Self::from_bytes(
-            read_reg_bin_value(&Self::REG_VALUE_PATH)?,
-            strictness,
-        )?)
+    fn prologue_epoch_secs(&self) -> u32 {
+        self.prologue_epoch_secs
     }
 
-    pub fn from_bytes(bytes: Vec<u8>, strictness: Strictness) -> Result<Self, ParseError> {
-        let mut byte_seq = ByteSeq::from_bytes(bytes);
-
-        let prologue = CloudStoreValuePrologue::from_byte_seq(&mut byte_seq, strictness)?;
-        let prologue_epoch_secs = prologue.epoch_secs.ok_or(ParseError::InconsistentData)?;
-        prologue
-            .num_body_bytes
-            .ok_or(ParseError::InconsistentData)
-            .or_else_if(strictness.is_lenient(), |_| Ok(0))?;
+    fn read_fields(
+        byte_seq: &mut ByteSeq,
+        prologue_epoch_secs: u32,
+        strictness: Strictness,
+    ) -> Result<Self, ParseError> {
+        let mut schedule_active = false;
+        let mut schedule_type = ScheduleType::SunsetToSunrise;
+        let mut scheduled_start = None;
+        let mut scheduled_end = None;
+        let mut night_color_temp = None;
+        let mut sunset_time = None;
+        let mut sunrise_time = None;
+        let mut night_preview_active = false;
 
         byte_seq
-            .assert_zero()
-            .or_else_if(strictness.is_lenient(), |_| Ok(()))?;
-        let schedule_active = TrackedValue::new(byte_seq.assert_const(&[0x02, 0x01]).is_ok());
-        let schedule_type =
-            TrackedValue::new(if byte_seq.assert_const(&[0xc2, 0x0a, 0x00]).is_ok() {
-                ScheduleType::Explicit
-            } else {
-                ScheduleType::SunsetToSunrise
-            });
+            .fields()
+            .try_for_each_ordered(strictness, |field, field_index| {
+                let clock_time = |inner_fields: &[Field], field_path| {
+                    clock_time_from_fields(inner_fields, field_index, strictness)
+                        .map_err(|error| error.in_field(field_path))
+                };
+                match (field.id, field.field_type, &field.value) {
+                    (0, FieldType::Bool, FieldValue::Bool(true)) => schedule_active = true,
+                    (10, FieldType::Bool, FieldValue::Bool(false)) => {
+                        schedule_type = ScheduleType::Explicit
+                    }
+                    (20, FieldType::Struct, FieldValue::Struct(inner_fields)) => {
+                        scheduled_start = Some(clock_time(inner_fields, "scheduledNight.start")?)
+                    }
+                    (30, FieldType::Struct, FieldValue::Struct(inner_fields)) => {
+                        scheduled_end = Some(clock_time(inner_fields, "scheduledNight.end")?)
+                    }
+                    (40, FieldType::Int16, FieldValue::Int(temp)) => {
+                        night_color_temp =
+                            Some((*temp).try_into().map_err(|_| {
+                                ParseError::ValueNotInRange.in_field("nightColorTemp")
+                            })?)
+                    }
+                    (50, FieldType::Struct, FieldValue::Struct(inner_fields)) => {
+                        sunset_time = Some(clock_time(inner_fields, "sunsetToSunrise.start")?)
+                    }
+                    (60, FieldType::Struct, FieldValue::Struct(inner_fields)) => {
+                        sunrise_time = Some(clock_time(inner_fields, "sunsetToSunrise.end")?)
+                    }
+                    (70, FieldType::Bool, FieldValue::Bool(true)) => night_preview_active = true,
+                    _ if strictness.is_lenient() => {}
+                    _ => return Err(ParseError::UnexpectedField(field_index)),
+                }
+
+                Ok(())
+            })?;
 
-        let const_error_to_midnight = |error| match error {
-            ParseError::ExpectedConst(_) => Ok(ClockTime::MIDNIGHT),
-            _ => Err(error),
+        //. Lenient mode assumes missing clock times to be midnight, which is also how they're written when zero.
+        let required = |clock_time: Option<ClockTime>, id| {
+            clock_time
+                .ok_or(ParseError::MissingField(id))
+                .or_else_if(strictness.is_lenient(), |_| Ok(ClockTime::MIDNIGHT))
         };
         let scheduled_night = TrackedValue::new(ClockTimeFrame {
-            start: {
-                byte_seq
-                    .assert_const(&[0xca, 0x14])
-                    .and_then(|_| byte_seq.read_clock_time())
-                    .or_else_if(strictness.is_lenient(), const_error_to_midnight)?
-            },
-            end: {
-                byte_seq
-                    .assert_zero()
-                    .or_else_if(strictness.is_lenient(), |_| Ok(()))?;
-                byte_seq
-                    .assert_const(&[0xca, 0x1e])
-                    .and_then(|_| byte_seq.read_clock_time())
-                    .or_else_if(strictness.is_lenient(), const_error_to_midnight)?
-            },
-        });
-
-        byte_seq
-            .assert_zero()
-            .or_else_if(strictness.is_lenient(), |_| Ok(()))?;
-        let night_color_temp = TrackedValue::new(if byte_seq.assert_const(&[0xcf, 0x28]).is_ok() {
-            Some(
-                byte_seq
-                    .read_zigzag_vlq_64()?
-                    .try_into()
-                    .map_err(|_| ParseError::ValueNotInRange)?,
-            )
-        } else {
-            None
+            start: required(scheduled_start, 20)?,
+            end: required(scheduled_end, 30)?,
         });
 
-        let sunset_time = byte_seq
-            .assert_const(&[0xca, 0x32])
-            .and_then(|_| byte_seq.read_clock_time())
-            .or_else_if(strictness.is_lenient(), const_error_to_midnight)?;
-        byte_seq
-            .assert_zero()
-            .or_else_if(strictness.is_lenient(), |_| Ok(()))?;
-        let sunrise_time = byte_seq
-            .assert_const(&[0xca, 0x3c])
-            .and_then(|_| byte_seq.read_clock_time())
-            .or_else_if(strictness.is_lenient(), const_error_to_midnight)?;
+        let sunset_time = required(sunset_time, 50)?;
+        let sunrise_time = required(sunrise_time, 60)?;
         let sunset_to_sunrise = if sunset_time.is_midnight() && sunrise_time.is_midnight() {
             None
         } else {
@@ -119,30 +117,10 @@ impl RawNightLightSettings {
             })
         };
 
-        byte_seq
-            .assert_zero()
-            .or_else_if(strictness.is_lenient(), |_| Ok(()))?;
-        let night_preview_active =
-            TrackedValue::new(byte_seq.assert_const(&[0xc2, 0x46]).map_or_else(
-                |_| Ok(false),
-                |_| {
-                    byte_seq.assert_const(&[0x01]).map_or_else(
-                        |error| {
-                            if strictness.is_lenient() {
-                                Ok(false)
-                            } else {
-                                Err(error)
-                            }
-                        },
-                        |_| Ok(true),
-                    )
-                },
-            )?);
-
-        (0..4)
-            .try_for_each(|_| byte_seq.assert_zero())
-            .and_then(|_| byte_seq.assert_exhausted())
-            .or_else_if(strictness.is_lenient(), |_| Ok(()))?;
+        let schedule_active = TrackedValue::new(schedule_active);
+        let schedule_type = TrackedValue::new(schedule_type);
+        let night_color_temp = TrackedValue::new(night_color_temp);
+        let night_preview_active = TrackedValue::new(night_preview_active);
 
         Ok(Self {
             prologue_epoch_secs,
@@ -155,81 +133,62 @@ impl RawNightLightSettings {
         })
     }
 
-    pub fn lenient_fallback(now: SystemTime) -> Self {
-        Self {
-            prologue_epoch_secs: epoch_duration_to_epoch_secs(system_time_to_epoch_duration(now)),
-            schedule_active: TrackedValue::new(false),
-            schedule_type: TrackedValue::new(ScheduleType::SunsetToSunrise),
-            scheduled_night: TrackedValue::new(ClockTimeFrame {
-                // Default according to <https://thegeekpage.com/how-to-set-a-schedule-to-turn-on-night-light-in-windows-11/>.
-                start: ClockTime::from_h_min(21, 0).unwrap(),
-                end: ClockTime::from_h_min(7, 0).unwrap(),
-            }),
-            night_color_temp: TrackedValue::new(Some(NightLight::DEFAULT_NIGHT_COLOR_TEMP)),
-            sunset_to_sunrise: None,
-            night_preview_active: TrackedValue::new(false),
-        }
-    }
-
-    pub fn to_bytes(&self) -> Vec<u8> {
-        const MAX_BODY_LEN: usize = 45;
-        let mut body_byte_seq = ByteSeq::with_capacity(MAX_BODY_LEN);
-
-        body_byte_seq.push_zero();
+    fn push_fields(&self, byte_seq: &mut ByteSeq, _now_epoch_duration: Duration) {
         if *self.schedule_active {
-            body_byte_seq.push_const(&[0x02, 0x01]);
+            byte_seq.push_const(&[0x02, 0x01]);
         }
         if *self.schedule_type == ScheduleType::Explicit {
-            body_byte_seq.push_const(&[0xc2, 0x0a, 0x00]);
+            byte_seq.push_const(&[0xc2, 0x0a, 0x00]);
         }
 
-        body_byte_seq.push_const(&[0xca, 0x14]);
-        body_byte_seq.push_clock_time(self.scheduled_night.start);
+        byte_seq.push_const(&[0xca, 0x14]);
+        byte_seq.push_clock_time(self.scheduled_night.start);
 
-        body_byte_seq.push_zero();
-        body_byte_seq.push_const(&[0xca, 0x1e]);
-        body_byte_seq.push_clock_time(self.scheduled_night.end);
+        byte_seq.push_zero();
+        byte_seq.push_const(&[0xca, 0x1e]);
+        byte_seq.push_clock_time(self.scheduled_night.end);
 
-        body_byte_seq.push_zero();
+        byte_seq.push_zero();
         if let Some(night_color_temp) = *self.night_color_temp {
-            body_byte_seq.push_const(&[0xcf, 0x28]);
-            body_byte_seq.push_zigzag_vlq_64(night_color_temp as _);
+            byte_seq.push_const(&[0xcf, 0x28]);
+            byte_seq.push_zigzag_vlq_64(night_color_temp as _);
         }
 
         let sunset_to_sunrise = self
             .sunset_to_sunrise
             .unwrap_or_else(|| ClockTimeFrame::MIDNIGHT_TO_MIDNIGHT);
-        body_byte_seq.push_const(&[0xca, 0x32]);
-        body_byte_seq.push_clock_time(sunset_to_sunrise.start);
+        byte_seq.push_const(&[0xca, 0x32]);
+        byte_seq.push_clock_time(sunset_to_sunrise.start);
 
-        body_byte_seq.push_zero();
-        body_byte_seq.push_const(&[0xca, 0x3c]);
-        body_byte_seq.push_clock_time(sunset_to_sunrise.end);
+        byte_seq.push_zero();
+        byte_seq.push_const(&[0xca, 0x3c]);
+        byte_seq.push_clock_time(sunset_to_sunrise.end);
 
-        body_byte_seq.push_zero();
+        byte_seq.push_zero();
         if *self.night_preview_active {
-            body_byte_seq.push_const(&[0xc2, 0x46, 0x01]);
-        }
-
-        for _ in 0..4 {
-            body_byte_seq.push_zero();
+            byte_seq.push_const(&[0xc2, 0x46, 0x01]);
         }
+    }
 
-        let mut byte_seq = CloudStoreValuePrologue {
-            epoch_secs: Some(
-                epoch_duration_to_epoch_secs(now_as_epoch_duration())
-                    .max(self.prologue_epoch_secs + 2),
-            ),
-            num_body_bytes: Some(body_byte_seq.len() as _),
+    fn lenient_fallback(now: SystemTime) -> Self {
+        Self {
+            prologue_epoch_secs: epoch_duration_to_epoch_secs(system_time_to_epoch_duration(now)),
+            schedule_active: TrackedValue::new(false),
+            schedule_type: TrackedValue::new(ScheduleType::SunsetToSunrise),
+            scheduled_night: TrackedValue::new(ClockTimeFrame {
+                // Default according to <https://thegeekpage.com/how-to-set-a-schedule-to-turn-on-night-light-in-windows-11/>.
+                start: ClockTime::from_h_min(21, 0).unwrap(),
+                end: ClockTime::from_h_min(7, 0).unwrap(),
+            }),
+            night_color_temp: TrackedValue::new(Some(NightLight::DEFAULT_NIGHT_COLOR_TEMP)),
+            sunset_to_sunrise: None,
+            night_preview_active: TrackedValue::new(false),
         }
-        .to_byte_seq(Some(MAX_BODY_LEN));
-        byte_seq.extend(&body_byte_seq);
-
-        byte_seq.into()
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
 pub enum ScheduleType {
     /// Based on the user's location.
     SunsetToSunrise,
@@ -241,9 +200,12 @@ pub enum ScheduleType {
 mod tests {
     use super::RawNightLightSettings;
     use crate::{
-        cloud_store::night_light::{
-            settings::ScheduleType,
-            time::{ClockTime, ClockTimeFrame},
+        cloud_store::{
+            night_light::{
+                settings::ScheduleType,
+                time::{ClockTime, ClockTimeFrame},
+            },
+            semantic_value::SemanticRegValue,
         },
         data_conversion::Strictness,
     };
@@ -257,7 +219,7 @@ mod tests {
         ];
 
         for strictness in [Strictness::Strict, Strictness::Lenient] {
-            match RawNightLightSettings::from_bytes(bytes.to_vec(), strictness) {
+            match RawNightLightSettings::from_bytes(&bytes, strictness) {
                 Ok(settings) => {
                     assert_eq!(*settings.schedule_active, false);
                     assert_eq!(*settings.schedule_type, ScheduleType::SunsetToSunrise);
@@ -269,7 +231,7 @@ mod tests {
                     assert_eq!(settings.sunset_to_sunrise, None);
                     assert_eq!(*settings.night_preview_active, false);
 
-                    assert_eq!(settings.to_bytes().len(), bytes.len());
+                    assert_eq!(settings.to_bytes().unwrap().len(), bytes.len());
                 }
                 result => panic!("{result:?}"),
             }
@@ -287,7 +249,7 @@ mod tests {
         ];
 
         for strictness in [Strictness::Strict, Strictness::Lenient] {
-            match RawNightLightSettings::from_bytes(bytes.to_vec(), strictness) {
+            match RawNightLightSettings::from_bytes(&bytes, strictness) {
                 Ok(settings) => {
                     assert_eq!(*settings.schedule_active, true);
                     assert_eq!(*settings.schedule_type, ScheduleType::Explicit);
@@ -308,7 +270,7 @@ mod tests {
                     );
                     assert_eq!(*settings.night_preview_active, true);
 
-                    assert_eq!(settings.to_bytes().len(), bytes.len());
+                    assert_eq!(settings.to_bytes().unwrap().len(), bytes.len());
                 }
                 result => panic!("{result:?}"),
             }
@@ -1,21 +1,24 @@
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use winreg::enums::HKEY_CURRENT_USER;
 
 use crate::{
-    cloud_store::prologue::CloudStoreValuePrologue,
+    cloud_store::{
+        fields::{FieldType, FieldValue},
+        semantic_value::SemanticRegValue,
+    },
     data_conversion::{
         byte_seq::{ByteSeq, ParseError},
         time::{
-            epoch_duration_to_epoch_secs, epoch_duration_to_filetime, now_as_epoch_duration,
+            epoch_duration_to_epoch_secs, epoch_duration_to_filetime,
             system_time_to_epoch_duration, LATEST_FILETIME,
         },
-        ResultOrElseIf, Strictness, TrackedValue,
+        Strictness, TrackedValue,
     },
-    reg::{read_reg_bin_value, RegValuePath},
+    reg::RegValuePath,
 };
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct RawNightLightState {
     pub prologue_epoch_secs: u32,
     pub active: TrackedValue<bool>,
@@ -23,53 +26,54 @@ pub struct RawNightLightState {
     pub modified_filetime: i64,
 }
 
-impl RawNightLightState {
-    pub const REG_VALUE_PATH: RegValuePath<'_> = RegValuePath {
+impl SemanticRegValue for RawNightLightState {
+    const REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
         hkey: HKEY_CURRENT_USER,
         subkey_path: r"SOFTWARE\Microsoft\Windows\CurrentVersion\CloudStore\Store\DefaultAccount\Current\default$windows.data.bluelightreduction.bluelightreductionstate\windows.data.bluelightreduction.bluelightreductionstate",
         value_name: "Data",
     };
+    const NAME: &'static str = "nightLight.state";
+    const MAX_BODY_LEN: usize = 21;
 
-    pub fn from_reg(strictness: Strictness) -> Result<Self, super::Error> {
-        Ok(Self::from_bytes(
-            read_reg_bin_value(&Self::REG_VALUE_PATH)?,
-            strictness,
-        )?)
+    fn prologue_epoch_secs(&self) -> u32 {
+        self.prologue_epoch_secs
     }
 
-    pub fn from_bytes(bytes: Vec<u8>, strictness: Strictness) -> Result<Self, ParseError> {
-        let mut byte_seq = ByteSeq::from_bytes(bytes);
-
-        let prologue = CloudStoreValuePrologue::from_byte_seq(&mut byte_seq, strictness)?;
-        let prologue_epoch_secs = prologue.epoch_secs.ok_or(ParseError::InconsistentData)?;
-        prologue
-            .num_body_bytes
-            .ok_or(ParseError::InconsistentData)
-            .or_else_if(strictness.is_lenient(), |_| Ok(0))?;
+    fn read_fields(
+        byte_seq: &mut ByteSeq,
+        prologue_epoch_secs: u32,
+        strictness: Strictness,
+    ) -> Result<Self, ParseError> {
+        let mut active = false;
+        let mut transition_cause = TransitionCause::Schedule;
+        let mut modified_filetime = None;
 
         byte_seq
-            .assert_zero()
-            .or_else_if(strictness.is_lenient(), |_| Ok(()))?;
-        let active = TrackedValue::new(byte_seq.assert_const(&[0x10, 0x00]).is_ok());
-        let transition_cause = if byte_seq.assert_const(&[0xd0, 0x0a, 0x02]).is_ok() {
-            TransitionCause::Manual
-        } else {
-            TransitionCause::Schedule
-        };
+            .fields()
+            .try_for_each_ordered(strictness, |field, field_index| {
+                match (field.id, field.field_type, field.value) {
+                    (0, FieldType::Int32, FieldValue::Int(0)) => active = true,
+                    (10, FieldType::Int32, FieldValue::Int(1)) => {
+                        transition_cause = TransitionCause::Manual
+                    }
+                    (20, FieldType::UInt64, FieldValue::UInt(filetime)) => {
+                        modified_filetime = Some(filetime)
+                    }
+                    _ if strictness.is_lenient() => {}
+                    _ => return Err(ParseError::UnexpectedField(field_index)),
+                }
 
-        byte_seq.assert_const(&[0xc6, 0x14])?;
-        let modified_filetime = byte_seq
-            .read_vlq_64()?
-            .try_into()
-            .map_err(|_| ParseError::ValueNotInRange)?;
-        if modified_filetime > LATEST_FILETIME {
-            return Err(ParseError::ValueNotInRange);
-        }
+                Ok(())
+            })?;
 
-        (0..4)
-            .try_for_each(|_| byte_seq.assert_zero())
-            .and_then(|_| byte_seq.assert_exhausted())
-            .or_else_if(strictness.is_lenient(), |_| Ok(()))?;
+        //. Even lenient mode can't make up the modification time.
+        let modified_filetime = modified_filetime
+            .ok_or(ParseError::MissingField(20))?
+            .try_into()
+            .ok()
+            .filter(|filetime| *filetime <= LATEST_FILETIME)
+            .ok_or_else(|| ParseError::ValueNotInRange.in_field("modifiedFiletime"))?;
+        let active = TrackedValue::new(active);
 
         Ok(Self {
             prologue_epoch_secs,
@@ -79,47 +83,26 @@ impl RawNightLightState {
         })
     }
 
-    pub fn lenient_fallback(now: SystemTime) -> Self {
-        let epoch_duration = system_time_to_epoch_duration(now);
-        Self {
-            prologue_epoch_secs: epoch_duration_to_epoch_secs(epoch_duration),
-            active: TrackedValue::new(false),
-            transition_cause: TransitionCause::Manual,
-            modified_filetime: epoch_duration_to_filetime(epoch_duration),
-        }
-    }
-
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let now_epoch_duration = now_as_epoch_duration();
-        let now_epoch_secs = epoch_duration_to_epoch_secs(now_epoch_duration);
-        let now_filetime = epoch_duration_to_filetime(now_epoch_duration);
-
-        const MAX_BODY_LEN: usize = 21;
-        let mut body_byte_seq = ByteSeq::with_capacity(MAX_BODY_LEN);
-
-        body_byte_seq.push_zero();
+    fn push_fields(&self, byte_seq: &mut ByteSeq, now_epoch_duration: Duration) {
         if *self.active {
-            body_byte_seq.push_const(&[0x10, 0x00]);
+            byte_seq.push_const(&[0x10, 0x00]);
         }
         if self.transition_cause == TransitionCause::Manual {
-            body_byte_seq.push_const(&[0xd0, 0x0a, 0x02]);
+            byte_seq.push_const(&[0xd0, 0x0a, 0x02]);
         }
 
-        body_byte_seq.push_const(&[0xc6, 0x14]);
-        body_byte_seq.push_vlq_64(now_filetime as _);
-
-        for _ in 0..4 {
-            body_byte_seq.push_zero();
-        }
+        byte_seq.push_const(&[0xc6, 0x14]);
+        byte_seq.push_vlq_64(epoch_duration_to_filetime(now_epoch_duration) as _);
+    }
 
-        let mut byte_seq = CloudStoreValuePrologue {
-            epoch_secs: Some(now_epoch_secs.max(self.prologue_epoch_secs + 2)),
-            num_body_bytes: Some(body_byte_seq.len() as _),
+    fn lenient_fallback(now: SystemTime) -> Self {
+        let epoch_duration = system_time_to_epoch_duration(now);
+        Self {
+            prologue_epoch_secs: epoch_duration_to_epoch_secs(epoch_duration),
+            active: TrackedValue::new(false),
+            transition_cause: TransitionCause::Manual,
+            modified_filetime: epoch_duration_to_filetime(epoch_duration),
         }
-        .to_byte_seq(Some(MAX_BODY_LEN));
-        byte_seq.extend(&body_byte_seq);
-
-        byte_seq.into()
     }
 }
 
@@ -133,7 +116,7 @@ pub enum TransitionCause {
 mod tests {
     use super::TransitionCause;
     use crate::{
-        cloud_store::night_light::state::RawNightLightState,
+        cloud_store::{night_light::state::RawNightLightState, semantic_value::SemanticRegValue},
         data_conversion::{
             time::{
                 epoch_duration_to_epoch_secs, epoch_duration_to_filetime, now_as_epoch_duration,
@@ -151,11 +134,11 @@ mod tests {
         ];
 
         for strictness in [Strictness::Strict, Strictness::Lenient] {
-            match RawNightLightState::from_bytes(bytes.to_vec(), strictness) {
+            match RawNightLightState::from_bytes(&bytes, strictness) {
                 Ok(state) => {
                     assert_eq!(*state.active, false);
                     assert_eq!(state.transition_cause, TransitionCause::Schedule);
-                    assert_eq!(state.to_bytes().len(), bytes.len());
+                    assert_eq!(state.to_bytes().unwrap().len(), bytes.len());
                 }
                 result => panic!("{result:?}"),
             }
@@ -172,11 +155,11 @@ mod tests {
         ];
 
         for strictness in [Strictness::Strict, Strictness::Lenient] {
-            match RawNightLightState::from_bytes(bytes.to_vec(), strictness) {
+            match RawNightLightState::from_bytes(&bytes, strictness) {
                 Ok(state) => {
                     assert_eq!(*state.active, true);
                     assert_eq!(state.transition_cause, TransitionCause::Manual);
-                    assert_eq!(state.to_bytes().len(), bytes.len());
+                    assert_eq!(state.to_bytes().unwrap().len(), bytes.len());
                 }
                 result => panic!("{result:?}"),
             }
@@ -195,7 +178,7 @@ mod tests {
             transition_cause: TransitionCause::Manual,
             modified_filetime: now_filetime,
         };
-        assert_eq!(state.to_bytes().len(), 43);
+        assert_eq!(state.to_bytes().unwrap().len(), 43);
 
         let state = RawNightLightState {
             prologue_epoch_secs: now_epoch_secs,
@@ -203,7 +186,7 @@ mod tests {
             transition_cause: TransitionCause::Manual,
             modified_filetime: now_filetime,
         };
-        assert_eq!(state.to_bytes().len(), 41);
+        assert_eq!(state.to_bytes().unwrap().len(), 41);
 
         let state = RawNightLightState {
             prologue_epoch_secs: now_epoch_secs,
@@ -211,7 +194,7 @@ mod tests {
             transition_cause: TransitionCause::Schedule,
             modified_filetime: now_filetime,
         };
-        assert_eq!(state.to_bytes().len(), 38);
+        assert_eq!(state.to_bytes().unwrap().len(), 38);
 
         // (The timestamps won't make the byte count grow until at least the year 3000.)
     }
@@ -1,10 +1,18 @@
 use std::{num::ParseIntError, str::FromStr};
 
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone, Timelike};
 use map_self::MapSelf;
 use serde::Serialize;
 use thiserror::Error;
 
-use crate::data_conversion::byte_seq::{ByteSeq, ParseError};
+use crate::{
+    cloud_store::fields::{Field, FieldType, FieldValue},
+    data_conversion::{
+        byte_seq::{ByteSeq, ParseError},
+        time::resolve_local_date_time,
+        LocalTimeError, LocalTimePolicy, Strictness,
+    },
+};
 
 #[derive(Clone, Copy, PartialEq, Serialize, Debug)]
 pub struct ClockTimeFrame {
@@ -18,6 +26,57 @@ impl ClockTimeFrame {
         end: ClockTime::MIDNIGHT,
     };
 
+    pub fn duration(&self) -> chrono::Duration {
+        //! How long the night lasts. Zero for a zero-length night.
+
+        const MINUTES_PER_DAY: i64 = 24 * 60;
+        chrono::Duration::minutes(
+            (self.end.minutes_of_day() - self.start.minutes_of_day()).rem_euclid(MINUTES_PER_DAY),
+        )
+    }
+
+    pub fn crosses_midnight(&self) -> bool {
+        //! Whether the night continues after midnight, on the following day. A night ending at midnight doesn't cross it.
+
+        self.end < self.start && !self.end.is_midnight()
+    }
+
+    pub fn contains(&self, clock_time: ClockTime) -> bool {
+        //! Whether the clock time is in the night, which includes the start, but not the end. A night ending before it starts lasts over midnight. A zero-length night contains no clock time.
+
+        if self.start <= self.end {
+            self.start <= clock_time && clock_time < self.end
+        } else {
+            self.start <= clock_time || clock_time < self.end
+        }
+    }
+
+    pub(super) fn next_transition_after<Tz: TimeZone>(
+        &self,
+        time: &DateTime<Tz>,
+    ) -> Option<ScheduledTransition<Tz>> {
+        //! The first start or end of the night after the point in time, in its time zone. `None` for a zero-length night, which never begins.
+
+        if self.start == self.end {
+            return None;
+        }
+
+        let tz = time.timezone();
+        let date = time.date_naive();
+        [date, date.succ_opt()?]
+            .into_iter()
+            .flat_map(|date| [(date, self.start, true), (date, self.end, false)])
+            .filter_map(|(date, clock_time, activates)| {
+                //. A skipped clock time is passed when the clocks are turned forward.
+                let time = clock_time
+                    .on_date(date, &tz, LocalTimePolicy::Latest)
+                    .ok()?;
+                Some(ScheduledTransition { time, activates })
+            })
+            .filter(|transition| transition.time > *time)
+            .min_by_key(|transition| transition.time.clone())
+    }
+
     pub fn format(&self, use_12_hour_clock: bool) -> String {
         format!(
             "{}-{}",
@@ -43,7 +102,16 @@ impl FromStr for ClockTimeFrame {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Serialize, Debug)]
+/// A change of the active-state by the schedule, as returned by [`super::NightLight::next_transition()`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct ScheduledTransition<Tz: TimeZone> {
+    pub time: DateTime<Tz>,
+    /// Whether the night begins, as opposed to ending.
+    pub activates: bool,
+}
+
+//. The field order makes the derived ordering chronological.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Debug)]
 pub struct ClockTime {
     pub(super) hour: u8,
     pub(super) minute: u8,
@@ -60,6 +128,14 @@ impl ClockTime {
         }
     }
 
+    pub fn now() -> Self {
+        //! The current local clock time, without the seconds.
+
+        let now = Local::now();
+        Self::from_h_min(now.hour() as _, now.minute() as _)
+            .expect("hour and minute should be valid")
+    }
+
     pub fn from_h_min_with_meridiem(mut hour: u8, minute: u8, meridiem: Meridiem) -> Option<Self> {
         if hour > 12 {
             return None;
@@ -98,6 +174,21 @@ impl ClockTime {
         self.hour == 0 && self.minute == 0
     }
 
+    fn minutes_of_day(&self) -> i64 {
+        self.hour as i64 * 60 + self.minute as i64
+    }
+
+    pub fn on_date<Tz: TimeZone>(
+        &self,
+        date: NaiveDate,
+        tz: &Tz,
+        policy: LocalTimePolicy,
+    ) -> Result<DateTime<Tz>, LocalTimeError> {
+        //! The point in time at which the clock shows this time on the date. On days with a daylight saving time transition, the clock time may occur twice or not at all, which is resolved according to the policy.
+
+        resolve_local_date_time(tz, date.and_time((*self).into()), policy)
+    }
+
     pub fn format(&self, use_12_hour_clock: bool) -> String {
         let (hour, meridiem) = if use_12_hour_clock {
             self.hour_meridiem()
@@ -147,6 +238,30 @@ impl FromStr for ClockTime {
     }
 }
 
+impl From<ClockTime> for NaiveTime {
+    fn from(clock_time: ClockTime) -> Self {
+        NaiveTime::from_hms_opt(clock_time.hour as _, clock_time.minute as _, 0)
+            .expect("hour and minute should be valid")
+    }
+}
+
+impl TryFrom<NaiveTime> for ClockTime {
+    type Error = SubMinuteTimeError;
+
+    fn try_from(time: NaiveTime) -> Result<Self, Self::Error> {
+        //! Fails for times with seconds or fractions of a second, which a clock time can't represent. To drop them, first use, e.g., `time.with_second(0)`.
+
+        if time.second() != 0 || time.nanosecond() != 0 {
+            return Err(SubMinuteTimeError);
+        }
+
+        Ok(Self {
+            hour: time.hour() as _,
+            minute: time.minute() as _,
+        })
+    }
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum Meridiem {
     Am,
@@ -162,28 +277,40 @@ impl Meridiem {
     }
 }
 
+pub(super) fn clock_time_from_fields(
+    fields: &[Field],
+    field_index: usize,
+    strictness: Strictness,
+) -> Result<ClockTime, ParseError> {
+    //! Interprets the fields of a clock time struct, whose header is at `field_index`. Zero hours and minutes are left out.
+
+    let mut hour = 0;
+    let mut minute = 0;
+    for field in fields {
+        match (field.id, field.field_type, &field.value) {
+            (0, FieldType::Int8, FieldValue::Int(value)) => {
+                hour = (*value)
+                    .try_into()
+                    .map_err(|_| ParseError::ValueNotInRange)?
+            }
+            (1, FieldType::Int8, FieldValue::Int(value)) => {
+                minute = (*value)
+                    .try_into()
+                    .map_err(|_| ParseError::ValueNotInRange)?
+            }
+            _ if strictness.is_lenient() => {}
+            _ => return Err(ParseError::UnexpectedField(field_index)),
+        }
+    }
+
+    ClockTime::from_h_min(hour, minute).ok_or(ParseError::ValueNotInRange)
+}
+
 pub(super) trait BinConvertClockTime {
-    fn read_clock_time(&mut self) -> Result<ClockTime, ParseError>;
     fn push_clock_time(&mut self, clock_time: ClockTime);
 }
 
-impl BinConvertClockTime for ByteSeq {
-    fn read_clock_time(&mut self) -> Result<ClockTime, ParseError> {
-        let hour = if self.assert_const(&[0x0e]).is_ok() {
-            self.read_int()?
-        } else {
-            0
-        };
-
-        let minute = if self.assert_const(&[0x2e]).is_ok() {
-            self.read_int()?
-        } else {
-            0
-        };
-
-        Ok(ClockTime::from_h_min(hour, minute).ok_or(ParseError::ValueNotInRange)?)
-    }
-
+impl BinConvertClockTime for ByteSeq<'_> {
     fn push_clock_time(&mut self, clock_time: ClockTime) {
         if clock_time.hour != 0 {
             self.push_const(&[0x0e]);
@@ -196,6 +323,10 @@ impl BinConvertClockTime for ByteSeq {
     }
 }
 
+#[derive(Error, PartialEq, Debug)]
+#[error("time isn't a whole minute, as `ClockTime` requires")]
+pub struct SubMinuteTimeError;
+
 #[derive(Error, PartialEq, Debug)]
 #[error("couldn't parse `ClockTime` or `ClockTimeFrame`")]
 pub struct ClockTimeOrFrameFromStrError;
@@ -208,6 +339,9 @@ impl From<ParseIntError> for ClockTimeOrFrameFromStrError {
 
 #[cfg(test)]
 mod tests {
+    use chrono::{NaiveTime, TimeZone, Utc};
+
+    use super::SubMinuteTimeError;
     use crate::cloud_store::night_light::{ClockTime, ClockTimeFrame, Meridiem};
 
     #[test]
@@ -240,4 +374,96 @@ mod tests {
         assert!("10:00-10:60".parse::<ClockTimeFrame>().is_err());
         assert!("10:00-24:00".parse::<ClockTimeFrame>().is_err());
     }
+
+    #[test]
+    fn contains() {
+        let clock_time = |s: &str| s.parse::<ClockTime>().unwrap();
+
+        let night = "22:30-6:15".parse::<ClockTimeFrame>().unwrap();
+        assert!(night.contains(clock_time("22:30")));
+        assert!(night.contains(clock_time("0:00")));
+        assert!(night.contains(clock_time("6:14")));
+        assert!(!night.contains(clock_time("6:15")));
+        assert!(!night.contains(clock_time("12:00")));
+
+        let night = "1:00-5:00".parse::<ClockTimeFrame>().unwrap();
+        assert!(night.contains(clock_time("1:00")));
+        assert!(!night.contains(clock_time("0:59")));
+        assert!(!night.contains(clock_time("5:00")));
+
+        let zero_length_night = "20:00-20:00".parse::<ClockTimeFrame>().unwrap();
+        assert!(!zero_length_night.contains(clock_time("20:00")));
+        assert!(!ClockTimeFrame::MIDNIGHT_TO_MIDNIGHT.contains(clock_time("12:00")));
+    }
+
+    #[test]
+    fn duration_and_crossing() {
+        let frame = |s: &str| s.parse::<ClockTimeFrame>().unwrap();
+
+        assert_eq!(
+            frame("22:30-6:15").duration(),
+            chrono::Duration::minutes(465)
+        );
+        assert!(frame("22:30-6:15").crosses_midnight());
+        assert_eq!(
+            frame("1:00-5:30").duration(),
+            chrono::Duration::minutes(270)
+        );
+        assert!(!frame("1:00-5:30").crosses_midnight());
+        assert_eq!(frame("22:00-0:00").duration(), chrono::Duration::hours(2));
+        assert!(!frame("22:00-0:00").crosses_midnight());
+        assert_eq!(frame("20:00-20:00").duration(), chrono::Duration::zero());
+    }
+
+    #[test]
+    fn naive_time_conversion() {
+        let clock_time = ClockTime::from_h_min(21, 5).unwrap();
+        let naive_time = NaiveTime::from_hms_opt(21, 5, 0).unwrap();
+        assert_eq!(NaiveTime::from(clock_time), naive_time);
+        assert_eq!(ClockTime::try_from(naive_time), Ok(clock_time));
+
+        assert_eq!(
+            ClockTime::try_from(NaiveTime::from_hms_opt(21, 5, 1).unwrap()),
+            Err(SubMinuteTimeError)
+        );
+        assert_eq!(
+            ClockTime::try_from(NaiveTime::from_hms_milli_opt(21, 5, 0, 1).unwrap()),
+            Err(SubMinuteTimeError)
+        );
+    }
+
+    #[test]
+    fn next_transition_after() {
+        let night = "22:30-6:15".parse::<ClockTimeFrame>().unwrap();
+        let transition = |h, min| {
+            night
+                .next_transition_after(&Utc.with_ymd_and_hms(2024, 3, 1, h, min, 0).unwrap())
+                .map(|transition| (transition.time, transition.activates))
+        };
+
+        assert_eq!(
+            transition(12, 0),
+            Some((Utc.with_ymd_and_hms(2024, 3, 1, 22, 30, 0).unwrap(), true))
+        );
+        assert_eq!(
+            transition(23, 0),
+            Some((Utc.with_ymd_and_hms(2024, 3, 2, 6, 15, 0).unwrap(), false))
+        );
+        assert_eq!(
+            transition(3, 0),
+            Some((Utc.with_ymd_and_hms(2024, 3, 1, 6, 15, 0).unwrap(), false))
+        );
+        //. The transition at the point in time itself is already past.
+        assert_eq!(
+            transition(22, 30),
+            Some((Utc.with_ymd_and_hms(2024, 3, 2, 6, 15, 0).unwrap(), false))
+        );
+
+        let zero_length_night = "20:00-20:00".parse::<ClockTimeFrame>().unwrap();
+        assert_eq!(
+            zero_length_night
+                .next_transition_after(&Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap()),
+            None
+        );
+    }
 }
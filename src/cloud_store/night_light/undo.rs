@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path, time::Duration};
+
+use super::{preset, ChangeSet};
+use crate::{daemon::InstanceMutex, reg::sid::current_user_sid};
+
+/// How many configurations [`super::NightLight::push_undo_point()`] keeps, dropping the oldest.
+pub const MAX_UNDO_POINTS: usize = 20;
+
+/// How long to wait for another process to finish with the undo history. Reverting holds it while Windows processes the write.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The contents of the undo history file.
+#[derive(Serialize, Deserialize, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct UndoHistory {
+    /// Oldest first.
+    pub points: Vec<ChangeSet>,
+    /// The point last applied by [`super::NightLight::revert_last()`], so that a process monitoring the registry values doesn't push the configuration from before reverting as a new undo point.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reverted: Option<ChangeSet>,
+}
+
+pub(super) fn lock_undo_history() -> Result<InstanceMutex, io::Error> {
+    //! Must be held across reading and writing the undo history, since both the CLI and a monitoring process modify it. The mutex is per user, like the file.
+
+    let name = format!(
+        r"Global\sem-reg-night-light-undo-{}",
+        current_user_sid().map_err(io::Error::from)?
+    );
+    InstanceMutex::try_acquire(&name, LOCK_TIMEOUT)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::TimedOut,
+            "undo history is locked by another process",
+        )
+    })
+}
+
+pub(super) fn read_undo_history(file_path: &Path) -> Result<UndoHistory, UndoError> {
+    //! A missing file counts as containing no undo points.
+
+    match fs::read_to_string(file_path) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(UndoHistory::default()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+pub(super) fn write_undo_history(
+    file_path: &Path,
+    undo_history: &UndoHistory,
+) -> Result<(), UndoError> {
+    Ok(preset::write_file(
+        file_path,
+        &serde_json::to_string_pretty(undo_history)?,
+    )?)
+}
+
+pub(super) fn push_undo_point(undo_points: &mut Vec<ChangeSet>, point: ChangeSet) {
+    //! Appends the point, unless it equals the newest one, e.g., because a change was recorded both by the process making it and by a monitoring process.
+
+    if undo_points.last() == Some(&point) {
+        return;
+    }
+
+    undo_points.push(point);
+    if undo_points.len() > MAX_UNDO_POINTS {
+        undo_points.drain(..undo_points.len() - MAX_UNDO_POINTS);
+    }
+}
+
+pub(super) fn push_observed_undo_point(
+    undo_history: &mut UndoHistory,
+    point: ChangeSet,
+    new: &ChangeSet,
+) {
+    //! Like [`push_undo_point()`], but for a change observed while monitoring, from `point` to `new`. The change made by reverting is recognized by `new` equaling the reverted-to point and isn't pushed, since undoing it would redo what was just undone.
+
+    if undo_history.reverted.take().as_ref() == Some(new) || *new == point {
+        return;
+    }
+
+    push_undo_point(&mut undo_history.points, point);
+}
+
+/// Error of the undo history methods of [`super::NightLight`], like [`super::NightLight::revert_last()`].
+#[derive(thiserror::Error, Debug)]
+pub enum UndoError {
+    /// Couldn't access the undo history file or its lock.
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+    /// The undo history file is malformed.
+    #[error("undo history JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    /// Couldn't read or write the Night Light registry values.
+    #[error("{0}")]
+    NightLightError(#[from] super::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{push_observed_undo_point, push_undo_point, UndoHistory, MAX_UNDO_POINTS};
+    use crate::cloud_store::night_light::{ChangeSet, NightLight};
+
+    #[test]
+    fn ring_buffer() {
+        let mut night_light = NightLight::lenient_fallback();
+        let mut undo_points = Vec::new();
+
+        for temp in 0..MAX_UNDO_POINTS as u16 + 5 {
+            night_light.set_night_color_temp(Some(2000 + temp));
+            let point = ChangeSet::from_night_light(&night_light);
+            push_undo_point(&mut undo_points, point.clone());
+            push_undo_point(&mut undo_points, point);
+        }
+
+        assert_eq!(undo_points.len(), MAX_UNDO_POINTS);
+        assert_eq!(undo_points[0].night_color_temp, Some(Some(2005)));
+        assert_eq!(
+            undo_points.last().unwrap().night_color_temp,
+            Some(Some(2000 + MAX_UNDO_POINTS as u16 + 4))
+        );
+    }
+
+    #[test]
+    fn observed_revert_skipped() {
+        let mut night_light = NightLight::lenient_fallback();
+        night_light.set_night_color_temp(Some(2700));
+        let warm = ChangeSet::from_night_light(&night_light);
+        night_light.set_night_color_temp(Some(4000));
+        let cold = ChangeSet::from_night_light(&night_light);
+
+        //. The history after reverting from `cold` to `warm`.
+        let mut undo_history = UndoHistory {
+            points: Vec::new(),
+            reverted: Some(warm.clone()),
+        };
+
+        push_observed_undo_point(&mut undo_history, cold.clone(), &warm);
+        assert_eq!(undo_history, UndoHistory::default());
+
+        //. Changes from elsewhere are recorded again.
+        push_observed_undo_point(&mut undo_history, warm.clone(), &cold);
+        assert_eq!(undo_history.points, [warm]);
+    }
+}
@@ -1,3 +1,5 @@
+use std::mem;
+
 use crate::data_conversion::{
     byte_seq::{ByteSeq, ParseError},
     ResultOrElseIf, Strictness,
@@ -12,6 +14,8 @@ pub struct CloudStoreValuePrologue {
 }
 
 impl CloudStoreValuePrologue {
+    const MAX_LEN: usize = 22;
+
     // Examples for registry values (see tests for locations):
     // 43 42 01 00 0a 02 01 00 2a 2a -- -- -- -- -- -- -- -- -- -- -- -- 00 00 00 00
     // 43 42 01 00 0a -- -- 00 26 -- 88 e2 be a9 06 -- -- -- -- -- -- -- 00
@@ -46,7 +50,12 @@ impl CloudStoreValuePrologue {
         let epoch_secs = if has_bytes_2a_2a {
             None
         } else {
-            Some(byte_seq.read_vlq_64()? as _)
+            Some(
+                byte_seq
+                    .read_vlq_64()?
+                    .try_into()
+                    .map_err(|_| ParseError::ValueNotInRange)?,
+            )
         };
 
         let num_body_bytes = if has_bytes_2a_2a {
@@ -104,15 +113,72 @@ impl CloudStoreValuePrologue {
         })
     }
 
-    pub fn to_byte_seq(&self, additional_capacity: Option<usize>) -> ByteSeq {
-        const MAX_PROLOGUE_LEN: usize = 22;
+    pub fn epoch_secs_of(bytes: &[u8]) -> Option<u32> {
+        //! Leniently parses only the timestamp of a value's bytes.
+
+        Self::from_byte_seq(&mut ByteSeq::from_slice(bytes), Strictness::Lenient)
+            .ok()?
+            .epoch_secs
+    }
+
+    pub fn with_epoch_secs(bytes: &[u8], epoch_secs: u32) -> Option<Vec<u8>> {
+        //! Returns the value's bytes with the timestamp replaced and the body kept. `None`, if the prologue can't be parsed leniently.
+
+        let mut byte_seq = ByteSeq::from_slice(bytes);
+        let prologue = Self::from_byte_seq(&mut byte_seq, Strictness::Lenient).ok()?;
+        let body = &bytes[bytes.len() - byte_seq.num_bytes_left()..];
+
+        let mut byte_seq = Self {
+            epoch_secs: Some(epoch_secs),
+            num_body_bytes: prologue.num_body_bytes.map(|_| body.len() as _),
+        }
+        .to_byte_seq(Some(body.len()));
+        byte_seq.push_const(body);
+
+        Some(byte_seq.into())
+    }
+
+    pub fn to_byte_seq(&self, additional_capacity: Option<usize>) -> ByteSeq<'static> {
         let mut byte_seq =
             ByteSeq::with_capacity(if let Some(additional_capacity) = additional_capacity {
-                MAX_PROLOGUE_LEN + additional_capacity
+                Self::MAX_LEN + additional_capacity
             } else {
-                MAX_PROLOGUE_LEN
+                Self::MAX_LEN
             });
+        self.push_to(&mut byte_seq);
 
+        byte_seq
+    }
+
+    pub fn write_value_into<F>(
+        bytes: &mut Vec<u8>,
+        epoch_secs: u32,
+        max_body_len: usize,
+        push_body: F,
+    ) where
+        F: FnOnce(&mut ByteSeq),
+    {
+        //! Replaces the buffer's contents with a value consisting of this prologue and the body, reusing the buffer's capacity. The body is pushed first, so that its length is known for the prologue, which is then rotated to the front.
+
+        bytes.clear();
+        bytes.reserve(Self::MAX_LEN + max_body_len);
+
+        let mut byte_seq = ByteSeq::from_bytes(mem::take(bytes));
+        push_body(&mut byte_seq);
+        let num_body_bytes = byte_seq.len();
+
+        Self {
+            epoch_secs: Some(epoch_secs),
+            num_body_bytes: Some(num_body_bytes as _),
+        }
+        .push_to(&mut byte_seq);
+
+        let prologue_len = byte_seq.len() - num_body_bytes;
+        *bytes = byte_seq.into();
+        bytes.rotate_right(prologue_len);
+    }
+
+    fn push_to(&self, byte_seq: &mut ByteSeq) {
         byte_seq.push_const(&[0x43, 0x42, 0x01]);
 
         byte_seq.push_zero();
@@ -149,8 +215,6 @@ impl CloudStoreValuePrologue {
                 }
             }
         }
-
-        byte_seq
     }
 }
 
@@ -199,7 +263,7 @@ mod tests {
         strictness: Strictness,
     ) -> Result<CloudStoreValuePrologue, ParseError> {
         CloudStoreValuePrologue::from_byte_seq(
-            &mut ByteSeq::from_bytes(VALUE_WITH_BYTES_2A_2A.to_vec()),
+            &mut ByteSeq::from_slice(&VALUE_WITH_BYTES_2A_2A),
             strictness,
         )
     }
@@ -208,7 +272,7 @@ mod tests {
         strictness: Strictness,
     ) -> Result<CloudStoreValuePrologue, ParseError> {
         CloudStoreValuePrologue::from_byte_seq(
-            &mut ByteSeq::from_bytes(VALUE_WITH_BYTE_26.to_vec()),
+            &mut ByteSeq::from_slice(&VALUE_WITH_BYTE_26),
             strictness,
         )
     }
@@ -217,7 +281,7 @@ mod tests {
         strictness: Strictness,
     ) -> Result<CloudStoreValuePrologue, ParseError> {
         CloudStoreValuePrologue::from_byte_seq(
-            &mut ByteSeq::from_bytes(NIGHT_LIGHT_STATE_VALUE.to_vec()),
+            &mut ByteSeq::from_slice(&NIGHT_LIGHT_STATE_VALUE),
             strictness,
         )
     }
@@ -260,4 +324,17 @@ mod tests {
         let lenient_result = parse_night_light_state_value(Strictness::Lenient);
         assert_eq!(strict_result, lenient_result);
     }
+
+    #[test]
+    fn write_value_into_reused_buffer() {
+        let mut bytes = vec![0xff; 40];
+        CloudStoreValuePrologue::write_value_into(&mut bytes, 1700191264, 0, |_| {});
+        assert_eq!(bytes, NIGHT_LIGHT_STATE_VALUE);
+
+        CloudStoreValuePrologue::write_value_into(&mut bytes, 1700191264, 2, |body_byte_seq| {
+            body_byte_seq.push_const(&[0x00, 0x00]);
+        });
+        assert_eq!(bytes[..18], NIGHT_LIGHT_STATE_VALUE[..18]);
+        assert_eq!(bytes[18..], [0x02, 0x43, 0x42, 0x01, 0x00, 0x00]);
+    }
 }
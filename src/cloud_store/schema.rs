@@ -0,0 +1,681 @@
+//! Definitions of CloudStore value formats, loaded at runtime from TOML or JSON files, which give the fields of a value (see the [`fields`](super::fields) module) names and types. They allow decoding and editing values the crate doesn't have dedicated types for. Definitions that turn out to be correct can be contributed to the crate.
+//!
+//! [`ValueSchema::decode()`] turns a parsed value into a [`DecodedValue`] with named, typed fields; [`ValueSchema::encode()`] turns it back for serialization.
+//!
+//! Example definition file:
+//!
+//! ```toml
+//! name = "night-light-settings"
+//! keyPath = 'HKEY_CURRENT_USER\SOFTWARE\Microsoft\Windows\CurrentVersion\CloudStore\Store\DefaultAccount\Current\default$windows.data.bluelightreduction.settings\windows.data.bluelightreduction.settings'
+//!
+//! [[fields]]
+//! id = 0
+//! name = "scheduleActive"
+//! type = "bool"
+//!
+//! [[fields]]
+//! id = 20
+//! name = "scheduledNightStart"
+//! type = "struct"
+//! fields = [
+//!     { id = 0, name = "hour", type = "int8" },
+//!     { id = 1, name = "minute", type = "int8" },
+//! ]
+//! ```
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{fs, io, path::Path};
+use thiserror::Error;
+
+use super::fields::{CloudStoreValue, Field, FieldType, FieldValue, SerializeError};
+use crate::{
+    data_conversion::{ParseError, Strictness},
+    reg::{
+        read_reg_bin_value, str_to_hkey, value_set::RegValueSetFormat,
+        wal::write_reg_bin_value_logged, OwnedRegValuePath,
+    },
+};
+
+/// The definition of a CloudStore value format.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ValueSchema {
+    /// The name to refer to the definition by, e.g., on the command line.
+    pub name: String,
+    /// The full path of the registry value's key, starting with the root key name.
+    pub key_path: String,
+    #[serde(default = "default_value_name")]
+    pub value_name: String,
+    #[serde(default)]
+    pub fields: Vec<FieldSchema>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct FieldSchema {
+    pub id: u16,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    /// The element type of lists.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub element_type: Option<FieldType>,
+    /// The fields of structs or of struct list elements.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldSchema>,
+}
+
+/// A value decoded with a definition, with its fields paired with their names. Undefined fields are named like `#30`. See [`ValueSchema::decode()`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct DecodedValue {
+    pub prologue_epoch_secs: Option<u32>,
+    /// In the order of the value. Not a map, since a value may contain a field ID more than once.
+    pub fields: Vec<(String, DecodedField)>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct DecodedField {
+    /// Kept for encoding the field again.
+    pub field_type: FieldType,
+    pub value: DecodedFieldValue,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum DecodedFieldValue {
+    /// Never `FieldValue::Struct` or `FieldValue::List`.
+    Scalar(FieldValue),
+    Struct(Vec<(String, DecodedField)>),
+    List(FieldType, Vec<DecodedFieldValue>),
+}
+
+fn default_value_name() -> String {
+    "Data".to_string()
+}
+
+impl ValueSchema {
+    pub fn from_file<T: AsRef<Path>>(file_path: T) -> Result<Self, SchemaError> {
+        //! The format is determined by the file extension (`.json` or `.toml`).
+
+        let format = RegValueSetFormat::from_file_path(&file_path)
+            .ok_or(SchemaError::UnknownFileExtension)?;
+        let text = fs::read_to_string(file_path)?;
+
+        Ok(match format {
+            RegValueSetFormat::Json => serde_json::from_str(&text)?,
+            RegValueSetFormat::Toml => toml::from_str(&text)?,
+        })
+    }
+
+    pub fn load_dir<T: AsRef<Path>>(dir_path: T) -> Result<Vec<Self>, SchemaError> {
+        //! Loads all `.json` and `.toml` files in the directory, sorted by name. Other files are ignored. A missing directory results in an empty `Vec`.
+
+        let entries = match fs::read_dir(dir_path) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut schemas = Vec::new();
+        for entry in entries {
+            let file_path = entry?.path();
+            if RegValueSetFormat::from_file_path(&file_path).is_some() {
+                schemas.push(Self::from_file(file_path)?);
+            }
+        }
+
+        schemas.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(schemas)
+    }
+
+    pub fn reg_value_path(&self) -> Result<OwnedRegValuePath, SchemaError> {
+        let (root_name, subkey_path) = self
+            .key_path
+            .split_once('\\')
+            .unwrap_or((&self.key_path, ""));
+        let hkey = str_to_hkey(root_name)
+            .ok_or_else(|| SchemaError::UnknownRootKey(root_name.to_string()))?;
+
+        Ok(OwnedRegValuePath::new(
+            hkey,
+            subkey_path,
+            self.value_name.as_str(),
+        ))
+    }
+
+    pub fn read(&self, strictness: Strictness) -> Result<CloudStoreValue, SchemaError> {
+        let bytes = read_reg_bin_value(&self.reg_value_path()?.as_borrowed())?;
+        Ok(CloudStoreValue::from_bytes(&bytes, strictness)?)
+    }
+
+    pub fn write(&self, value: &CloudStoreValue) -> Result<(), SchemaError> {
+        Ok(write_reg_bin_value_logged(
+            &self.reg_value_path()?.as_borrowed(),
+            &value.to_bytes()?,
+            false,
+        )?)
+    }
+
+    pub fn to_json(&self, value: &CloudStoreValue) -> serde_json::Value {
+        //! Represents the fields as a JSON object with the names from the definition. Undefined fields are named like `#30`.
+
+        fields_to_json(&value.fields, &self.fields)
+    }
+
+    pub fn decode(&self, value: &CloudStoreValue) -> DecodedValue {
+        //! Keys the fields by their names from the definition, recursively.
+
+        DecodedValue {
+            prologue_epoch_secs: value.prologue_epoch_secs,
+            fields: decode_fields(&value.fields, &self.fields),
+        }
+    }
+
+    pub fn encode(&self, value: &DecodedValue) -> Result<CloudStoreValue, SchemaError> {
+        //! The reverse of [`Self::decode()`]. The fields keep their order, since Windows doesn't always write them sorted by ID. Fails for names neither defined nor like `#30`, and for values that don't match their field's type.
+
+        Ok(CloudStoreValue {
+            prologue_epoch_secs: value.prologue_epoch_secs,
+            fields: encode_fields(&value.fields, &self.fields, "")?,
+        })
+    }
+
+    pub fn set(
+        &self,
+        value: &mut CloudStoreValue,
+        field_path: &str,
+        json: &serde_json::Value,
+    ) -> Result<(), SchemaError> {
+        //! Sets a field with a scalar type, given by its dot-separated path of names, like `scheduledNightStart.hour`, creating it and its parent structs, if necessary. JSON `null` removes the field.
+
+        let names = field_path.split('.').collect::<Vec<_>>();
+        let mut field_schemas = &self.fields;
+        let mut fields = &mut value.fields;
+
+        for (index, name) in names.iter().enumerate() {
+            let field_schema = field_schemas
+                .iter()
+                .find(|field_schema| field_schema.name == *name)
+                .ok_or_else(|| SchemaError::UnknownField(names[..=index].join(".")))?;
+            //. Fields aren't necessarily sorted by ID.
+            let field_index = fields.iter().position(|field| field.id == field_schema.id);
+
+            if index == names.len() - 1 {
+                if json.is_null() {
+                    if let Some(field_index) = field_index {
+                        fields.remove(field_index);
+                    }
+                } else {
+                    let field = Field {
+                        id: field_schema.id,
+                        field_type: field_schema.field_type,
+                        value: json_to_field_value(json, field_schema.field_type).ok_or_else(
+                            || SchemaError::InvalidFieldValue(field_path.to_string()),
+                        )?,
+                    };
+
+                    match field_index {
+                        Some(field_index) => fields[field_index] = field,
+                        None => insert_field(fields, field),
+                    }
+                }
+
+                break;
+            }
+
+            if field_schema.field_type != FieldType::Struct {
+                return Err(SchemaError::UnknownField(names[..=index + 1].join(".")));
+            }
+
+            let field_index = match field_index {
+                Some(field_index) => field_index,
+                None => insert_field(
+                    fields,
+                    Field {
+                        id: field_schema.id,
+                        field_type: FieldType::Struct,
+                        value: FieldValue::Struct(Vec::new()),
+                    },
+                ),
+            };
+
+            field_schemas = &field_schema.fields;
+            fields = match &mut fields[field_index].value {
+                FieldValue::Struct(fields) => fields,
+                _ => return Err(SchemaError::InvalidFieldValue(names[..=index].join("."))),
+            };
+        }
+
+        Ok(())
+    }
+}
+
+fn insert_field(fields: &mut Vec<Field>, field: Field) -> usize {
+    //! Inserts the field before the first field with a greater ID and returns its index.
+
+    let index = fields
+        .iter()
+        .position(|existing_field| existing_field.id > field.id)
+        .unwrap_or(fields.len());
+    fields.insert(index, field);
+
+    index
+}
+
+fn field_name(id: u16, field_schemas: &[FieldSchema]) -> (String, &[FieldSchema]) {
+    //! The name of the field with the ID and the definitions of its subfields.
+
+    match field_schemas
+        .iter()
+        .find(|field_schema| field_schema.id == id)
+    {
+        Some(field_schema) => (field_schema.name.clone(), &field_schema.fields),
+        None => (format!("#{id}"), &[]),
+    }
+}
+
+fn decode_fields(fields: &[Field], field_schemas: &[FieldSchema]) -> Vec<(String, DecodedField)> {
+    fields
+        .iter()
+        .map(|field| {
+            let (name, sub_schemas) = field_name(field.id, field_schemas);
+            let decoded_field = DecodedField {
+                field_type: field.field_type,
+                value: decode_field_value(&field.value, sub_schemas),
+            };
+
+            (name, decoded_field)
+        })
+        .collect()
+}
+
+fn decode_field_value(value: &FieldValue, field_schemas: &[FieldSchema]) -> DecodedFieldValue {
+    match value {
+        FieldValue::Struct(fields) => {
+            DecodedFieldValue::Struct(decode_fields(fields, field_schemas))
+        }
+        FieldValue::List(element_type, elements) => DecodedFieldValue::List(
+            *element_type,
+            elements
+                .iter()
+                .map(|element| decode_field_value(element, field_schemas))
+                .collect(),
+        ),
+        _ => DecodedFieldValue::Scalar(value.clone()),
+    }
+}
+
+fn encode_fields(
+    decoded_fields: &[(String, DecodedField)],
+    field_schemas: &[FieldSchema],
+    parent_path: &str,
+) -> Result<Vec<Field>, SchemaError> {
+    let mut fields = Vec::with_capacity(decoded_fields.len());
+
+    for (name, decoded_field) in decoded_fields {
+        let field_path = format!("{parent_path}{name}");
+        let field_schema = field_schemas
+            .iter()
+            .find(|field_schema| field_schema.name == *name);
+        let (id, sub_schemas) = match field_schema {
+            Some(field_schema) => (field_schema.id, &field_schema.fields[..]),
+            None => (
+                name.strip_prefix('#')
+                    .and_then(|id| id.parse().ok())
+                    .ok_or_else(|| SchemaError::UnknownField(field_path.clone()))?,
+                &[][..],
+            ),
+        };
+
+        let value = encode_field_value(&decoded_field.value, sub_schemas, &field_path)?;
+        if !value.matches_type(decoded_field.field_type) {
+            return Err(SchemaError::InvalidFieldValue(field_path));
+        }
+
+        fields.push(Field {
+            id,
+            field_type: decoded_field.field_type,
+            value,
+        });
+    }
+
+    Ok(fields)
+}
+
+fn encode_field_value(
+    value: &DecodedFieldValue,
+    field_schemas: &[FieldSchema],
+    field_path: &str,
+) -> Result<FieldValue, SchemaError> {
+    Ok(match value {
+        DecodedFieldValue::Scalar(value) => value.clone(),
+        DecodedFieldValue::Struct(fields) => FieldValue::Struct(encode_fields(
+            fields,
+            field_schemas,
+            &format!("{field_path}."),
+        )?),
+        DecodedFieldValue::List(element_type, elements) => FieldValue::List(
+            *element_type,
+            elements
+                .iter()
+                .map(|element| encode_field_value(element, field_schemas, field_path))
+                .collect::<Result<_, _>>()?,
+        ),
+    })
+}
+
+fn fields_to_json(fields: &[Field], field_schemas: &[FieldSchema]) -> serde_json::Value {
+    serde_json::Value::Object(
+        fields
+            .iter()
+            .map(|field| {
+                let field_schema = field_schemas
+                    .iter()
+                    .find(|field_schema| field_schema.id == field.id);
+                let name = field_schema.map_or_else(
+                    || format!("#{}", field.id),
+                    |field_schema| field_schema.name.clone(),
+                );
+                let sub_schemas = field_schema.map_or(&[][..], |field_schema| &field_schema.fields);
+
+                (name, field_value_to_json(&field.value, sub_schemas))
+            })
+            .collect(),
+    )
+}
+
+fn field_value_to_json(value: &FieldValue, field_schemas: &[FieldSchema]) -> serde_json::Value {
+    match value {
+        FieldValue::Bool(bool) => json!(bool),
+        FieldValue::UInt(uint) => json!(uint),
+        FieldValue::Int(int) => json!(int),
+        FieldValue::Float(float) => json!(float),
+        FieldValue::Double(double) => json!(double),
+        FieldValue::String(string) => json!(string),
+        FieldValue::Struct(fields) => fields_to_json(fields, field_schemas),
+        FieldValue::List(_, elements) => serde_json::Value::Array(
+            elements
+                .iter()
+                .map(|element| field_value_to_json(element, field_schemas))
+                .collect(),
+        ),
+    }
+}
+
+fn json_to_field_value(json: &serde_json::Value, field_type: FieldType) -> Option<FieldValue> {
+    //! Returns `None` for non-scalar types and values not fitting the type.
+
+    Some(match field_type {
+        FieldType::Bool => FieldValue::Bool(json.as_bool()?),
+        FieldType::UInt8 => FieldValue::UInt(u8::try_from(json.as_u64()?).ok()? as _),
+        FieldType::UInt16 => FieldValue::UInt(u16::try_from(json.as_u64()?).ok()? as _),
+        FieldType::UInt32 => FieldValue::UInt(u32::try_from(json.as_u64()?).ok()? as _),
+        FieldType::UInt64 => FieldValue::UInt(json.as_u64()?),
+        FieldType::Float => FieldValue::Float(json.as_f64()? as _),
+        FieldType::Double => FieldValue::Double(json.as_f64()?),
+        FieldType::String | FieldType::WString => FieldValue::String(json.as_str()?.to_string()),
+        FieldType::Int8 => FieldValue::Int(i8::try_from(json.as_i64()?).ok()? as _),
+        FieldType::Int16 => FieldValue::Int(i16::try_from(json.as_i64()?).ok()? as _),
+        FieldType::Int32 => FieldValue::Int(i32::try_from(json.as_i64()?).ok()? as _),
+        FieldType::Int64 => FieldValue::Int(json.as_i64()?),
+        FieldType::Struct | FieldType::List => return None,
+    })
+}
+
+#[derive(Error, Debug)]
+pub enum SchemaError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("TOML error: {0}")]
+    TomlError(#[from] toml::de::Error),
+    #[error("parse error: {0}")]
+    ParseError(#[from] ParseError),
+    #[error("file extension is neither `.json` nor `.toml`")]
+    UnknownFileExtension,
+    #[error("unknown root key: {0}")]
+    UnknownRootKey(String),
+    #[error("field not defined: {0}")]
+    UnknownField(String),
+    #[error("value doesn't fit the type of field {0}")]
+    InvalidFieldValue(String),
+    #[error("{0}")]
+    SerializeError(#[from] SerializeError),
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{DecodedField, DecodedFieldValue, FieldSchema, SchemaError, ValueSchema};
+    use crate::cloud_store::fields::{CloudStoreValue, Field, FieldType, FieldValue};
+
+    fn schema() -> ValueSchema {
+        ValueSchema {
+            name: "example".to_string(),
+            key_path: r"HKEY_CURRENT_USER\SOFTWARE\Example".to_string(),
+            value_name: "Data".to_string(),
+            fields: vec![
+                FieldSchema {
+                    id: 0,
+                    name: "active".to_string(),
+                    field_type: FieldType::Bool,
+                    element_type: None,
+                    fields: Vec::new(),
+                },
+                FieldSchema {
+                    id: 20,
+                    name: "start".to_string(),
+                    field_type: FieldType::Struct,
+                    element_type: None,
+                    fields: vec![FieldSchema {
+                        id: 1,
+                        name: "minute".to_string(),
+                        field_type: FieldType::Int8,
+                        element_type: None,
+                        fields: Vec::new(),
+                    }],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn json_representation() {
+        let value = CloudStoreValue {
+            prologue_epoch_secs: None,
+            fields: vec![
+                Field {
+                    id: 0,
+                    field_type: FieldType::Bool,
+                    value: FieldValue::Bool(true),
+                },
+                Field {
+                    id: 30,
+                    field_type: FieldType::UInt32,
+                    value: FieldValue::UInt(7),
+                },
+            ],
+        };
+
+        assert_eq!(schema().to_json(&value), json!({"active": true, "#30": 7}));
+    }
+
+    #[test]
+    fn set_fields() {
+        let schema = schema();
+        let mut value = CloudStoreValue {
+            prologue_epoch_secs: None,
+            fields: Vec::new(),
+        };
+
+        schema.set(&mut value, "start.minute", &json!(30)).unwrap();
+        schema.set(&mut value, "active", &json!(false)).unwrap();
+        assert_eq!(
+            schema.to_json(&value),
+            json!({"active": false, "start": {"minute": 30}})
+        );
+        assert_eq!(value.fields[0].id, 0);
+
+        schema.set(&mut value, "active", &json!(null)).unwrap();
+        assert_eq!(value.fields.len(), 1);
+
+        assert!(schema.set(&mut value, "start.minute", &json!(300)).is_err());
+        assert!(schema.set(&mut value, "start.hour", &json!(1)).is_err());
+        assert!(schema.set(&mut value, "active.x", &json!(1)).is_err());
+    }
+
+    #[test]
+    fn set_fields_out_of_order() {
+        let schema = schema();
+        let mut value = CloudStoreValue {
+            prologue_epoch_secs: None,
+            fields: vec![
+                Field {
+                    id: 30,
+                    field_type: FieldType::UInt32,
+                    value: FieldValue::UInt(7),
+                },
+                Field {
+                    id: 20,
+                    field_type: FieldType::Struct,
+                    value: FieldValue::Struct(vec![Field {
+                        id: 1,
+                        field_type: FieldType::Int8,
+                        value: FieldValue::Int(30),
+                    }]),
+                },
+                Field {
+                    id: 0,
+                    field_type: FieldType::Bool,
+                    value: FieldValue::Bool(true),
+                },
+            ],
+        };
+
+        let ids = |value: &CloudStoreValue| {
+            value
+                .fields
+                .iter()
+                .map(|field| field.id)
+                .collect::<Vec<_>>()
+        };
+
+        schema.set(&mut value, "active", &json!(false)).unwrap();
+        schema.set(&mut value, "start.minute", &json!(45)).unwrap();
+        assert_eq!(ids(&value), [30, 20, 0]);
+        assert_eq!(
+            schema.to_json(&value),
+            json!({"#30": 7, "start": {"minute": 45}, "active": false})
+        );
+
+        schema.set(&mut value, "active", &json!(null)).unwrap();
+        assert_eq!(ids(&value), [30, 20]);
+    }
+
+    #[test]
+    fn decode_and_encode() {
+        let schema = schema();
+        let value = CloudStoreValue {
+            prologue_epoch_secs: Some(1_700_000_000),
+            fields: vec![
+                Field {
+                    id: 0,
+                    field_type: FieldType::Bool,
+                    value: FieldValue::Bool(true),
+                },
+                Field {
+                    id: 20,
+                    field_type: FieldType::Struct,
+                    value: FieldValue::Struct(vec![Field {
+                        id: 1,
+                        field_type: FieldType::Int8,
+                        value: FieldValue::Int(30),
+                    }]),
+                },
+                Field {
+                    id: 30,
+                    field_type: FieldType::UInt32,
+                    value: FieldValue::UInt(7),
+                },
+            ],
+        };
+
+        let field = |fields: &[(String, DecodedField)], name: &str| {
+            fields
+                .iter()
+                .find(|(field_name, _)| field_name == name)
+                .map(|(_, field)| field.value.clone())
+                .unwrap()
+        };
+
+        let mut decoded = schema.decode(&value);
+        assert_eq!(
+            field(&decoded.fields, "#30"),
+            DecodedFieldValue::Scalar(FieldValue::UInt(7))
+        );
+        match field(&decoded.fields, "start") {
+            DecodedFieldValue::Struct(fields) => assert_eq!(
+                field(&fields, "minute"),
+                DecodedFieldValue::Scalar(FieldValue::Int(30))
+            ),
+            _ => panic!("`start` should be a struct"),
+        }
+
+        let encoded = schema.encode(&decoded).unwrap();
+        assert_eq!(encoded, value);
+        assert_eq!(encoded.to_bytes().unwrap(), value.to_bytes().unwrap());
+
+        //. Repeated field IDs survive the round trip.
+        let mut value_with_repetition = value.clone();
+        value_with_repetition.fields.push(Field {
+            id: 30,
+            field_type: FieldType::UInt32,
+            value: FieldValue::UInt(8),
+        });
+        let encoded = schema
+            .encode(&schema.decode(&value_with_repetition))
+            .unwrap();
+        assert_eq!(encoded, value_with_repetition);
+
+        //. So does the order of fields not sorted by ID.
+        let mut value_out_of_order = value_with_repetition;
+        value_out_of_order.fields.swap(0, 2);
+        value_out_of_order.fields.swap(0, 3);
+        let encoded = schema.encode(&schema.decode(&value_out_of_order)).unwrap();
+        assert_eq!(encoded, value_out_of_order);
+        assert_eq!(
+            encoded.to_bytes().unwrap(),
+            value_out_of_order.to_bytes().unwrap()
+        );
+
+        decoded.fields[0].1.field_type = FieldType::UInt8;
+        assert!(matches!(
+            schema.encode(&decoded),
+            Err(SchemaError::InvalidFieldValue(field_path)) if field_path == "active"
+        ));
+
+        decoded.fields[0].0 = "inactive".to_string();
+        assert!(schema.encode(&decoded).is_err());
+    }
+
+    #[test]
+    fn parse_definition() {
+        let schema: ValueSchema = serde_json::from_str(
+            r#"{
+                "name": "example",
+                "keyPath": "HKEY_CURRENT_USER\\SOFTWARE\\Example",
+                "fields": [
+                    {"id": 0, "name": "active", "type": "bool"},
+                    {"id": 20, "name": "start", "type": "struct", "fields": [
+                        {"id": 1, "name": "minute", "type": "int8"}
+                    ]}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(schema, self::schema());
+    }
+}
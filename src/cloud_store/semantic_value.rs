@@ -0,0 +1,247 @@
+//! The scaffolding shared by CloudStore values with dedicated types, like Night Light's state and settings: the prologue with its timestamp, the zero bytes framing the body, strictness handling and reading from the registry. Implementors only parse and serialize their fields.
+//!
+//! To support a new setting, implement [`SemanticRegValue`] for a type holding the fields, e.g.:
+//!
+//! ```ignore
+//! impl SemanticRegValue for RawExampleValue {
+//!     const REG_VALUE_PATH: RegValuePath<'static> = RegValuePath { /* ... */ };
+//!     const NAME: &'static str = "example";
+//!     const MAX_BODY_LEN: usize = 8;
+//!
+//!     fn prologue_epoch_secs(&self) -> u32 {
+//!         self.prologue_epoch_secs
+//!     }
+//!
+//!     fn read_fields(byte_seq: &mut ByteSeq, prologue_epoch_secs: u32, strictness: Strictness) -> Result<Self, ParseError> {
+//!         let mut enabled = false;
+//!         for field in byte_seq.fields() {
+//!             match field?.value {
+//!                 FieldValue::Bool(value) => enabled = value,
+//!                 _ if strictness.is_lenient() => {}
+//!                 _ => return Err(ParseError::InconsistentData),
+//!             }
+//!         }
+//!         Ok(Self { prologue_epoch_secs, enabled })
+//!     }
+//!
+//!     fn push_fields(&self, byte_seq: &mut ByteSeq, _now_epoch_duration: Duration) {
+//!         if self.enabled {
+//!             byte_seq.push_const(&[0x02, 0x01]);
+//!         }
+//!     }
+//!
+//!     fn lenient_fallback(now: SystemTime) -> Self {
+//!         let epoch_secs = now.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs() as u32);
+//!         Self { prologue_epoch_secs: epoch_secs, enabled: false }
+//!     }
+//! }
+//! ```
+
+use std::{
+    io,
+    time::{Duration, SystemTime},
+};
+
+use super::prologue::CloudStoreValuePrologue;
+use crate::{
+    data_conversion::{
+        time::{
+            epoch_duration_to_epoch_secs, next_epoch_secs, now_as_epoch_duration,
+            ImplausibleTimeError,
+        },
+        ByteSeq, ParseError, ResultOrElseIf, Strictness,
+    },
+    reg::{
+        provider::{RegProvider, WinRegProvider},
+        RegValuePath,
+    },
+};
+
+/// A CloudStore registry value whose body consists of a leading zero byte, the fields, and four trailing zero bytes.
+pub trait SemanticRegValue: Sized {
+    const REG_VALUE_PATH: RegValuePath<'static>;
+    /// The start of the field paths in errors, like `nightLight.settings`.
+    const NAME: &'static str;
+    /// An upper bound of the body length, to reserve the buffer's capacity in advance.
+    const MAX_BODY_LEN: usize;
+
+    fn prologue_epoch_secs(&self) -> u32;
+
+    /// Reads the fields between the leading and the trailing zero bytes of the body. Deviations that can be tolerated should only be tolerated with [`Strictness::Lenient`].
+    fn read_fields(
+        byte_seq: &mut ByteSeq,
+        prologue_epoch_secs: u32,
+        strictness: Strictness,
+    ) -> Result<Self, ParseError>;
+
+    /// Pushes the fields. `now_epoch_duration` is the time of serialization, for fields containing timestamps.
+    fn push_fields(&self, byte_seq: &mut ByteSeq, now_epoch_duration: Duration);
+
+    /// An instance with defaults, for when the registry value doesn't exist yet.
+    fn lenient_fallback(now: SystemTime) -> Self;
+
+    fn from_reg(strictness: Strictness) -> Result<Self, ReadError> {
+        Self::from_provider(&WinRegProvider, strictness)
+    }
+
+    fn from_provider<P: RegProvider + ?Sized>(
+        provider: &P,
+        strictness: Strictness,
+    ) -> Result<Self, ReadError> {
+        //! Like [`Self::from_reg()`], but reads through the provider, e.g., an in-memory registry in tests.
+
+        Ok(Self::from_bytes(
+            &provider.read_bin_value(&Self::REG_VALUE_PATH)?,
+            strictness,
+        )?)
+    }
+
+    fn from_bytes(bytes: &[u8], strictness: Strictness) -> Result<Self, ParseError> {
+        //! Errors carry the field path, starting with [`Self::NAME`], and an excerpt of the bytes.
+
+        let parse = || {
+            let mut byte_seq = ByteSeq::from_slice(bytes);
+
+            let prologue = CloudStoreValuePrologue::from_byte_seq(&mut byte_seq, strictness)
+                .map_err(|error| error.in_field("prologue"))?;
+            let prologue_epoch_secs = prologue.epoch_secs.ok_or(ParseError::InconsistentData)?;
+            prologue
+                .num_body_bytes
+                .ok_or(ParseError::InconsistentData)
+                .or_else_if(strictness.is_lenient(), |_| Ok(0))?;
+
+            byte_seq
+                .assert_zero()
+                .or_else_if(strictness.is_lenient(), |_| Ok(()))?;
+            let value = Self::read_fields(&mut byte_seq, prologue_epoch_secs, strictness)?;
+
+            (0..4)
+                .try_for_each(|_| byte_seq.assert_zero())
+                .and_then(|_| byte_seq.assert_exhausted())
+                .or_else_if(strictness.is_lenient(), |_| Ok(()))?;
+
+            Ok(value)
+        };
+
+        parse().map_err(|error: ParseError| error.in_field(Self::NAME).with_excerpt(bytes))
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, ImplausibleTimeError> {
+        let mut bytes = Vec::new();
+        self.to_bytes_into(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn to_bytes_into(&self, bytes: &mut Vec<u8>) -> Result<(), ImplausibleTimeError> {
+        //! Like [`Self::to_bytes()`], but replaces the buffer's contents, so that a buffer can be reused when writing at high frequency. The prologue timestamp is advanced past the previous one. On error, the buffer is left unchanged.
+
+        let now_epoch_duration = now_as_epoch_duration();
+        let epoch_secs = next_epoch_secs(
+            Some(self.prologue_epoch_secs()),
+            epoch_duration_to_epoch_secs(now_epoch_duration),
+        )
+        .ok_or(ImplausibleTimeError)?;
+
+        CloudStoreValuePrologue::write_value_into(
+            bytes,
+            epoch_secs,
+            Self::MAX_BODY_LEN,
+            |body_byte_seq| {
+                body_byte_seq.push_zero();
+                self.push_fields(body_byte_seq, now_epoch_duration);
+                for _ in 0..4 {
+                    body_byte_seq.push_zero();
+                }
+            },
+        );
+
+        Ok(())
+    }
+}
+
+/// Error of [`SemanticRegValue::from_reg()`] and [`SemanticRegValue::from_provider()`].
+#[derive(thiserror::Error, Debug)]
+pub enum ReadError {
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("parse error: {0}")]
+    ParseError(#[from] ParseError),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use winreg::enums::HKEY_CURRENT_USER;
+
+    use super::SemanticRegValue;
+    use crate::{
+        data_conversion::{ByteSeq, ParseError, Strictness},
+        reg::RegValuePath,
+    };
+
+    #[derive(PartialEq, Debug)]
+    struct RawTestValue {
+        prologue_epoch_secs: u32,
+        enabled: bool,
+    }
+
+    impl SemanticRegValue for RawTestValue {
+        const REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+            hkey: HKEY_CURRENT_USER,
+            subkey_path: r"Software\sem-reg test",
+            value_name: "Data",
+        };
+        const NAME: &'static str = "test";
+        const MAX_BODY_LEN: usize = 7;
+
+        fn prologue_epoch_secs(&self) -> u32 {
+            self.prologue_epoch_secs
+        }
+
+        fn read_fields(
+            byte_seq: &mut ByteSeq,
+            prologue_epoch_secs: u32,
+            _strictness: Strictness,
+        ) -> Result<Self, ParseError> {
+            Ok(Self {
+                prologue_epoch_secs,
+                enabled: byte_seq.assert_const(&[0x02, 0x01]).is_ok(),
+            })
+        }
+
+        fn push_fields(&self, byte_seq: &mut ByteSeq, _now_epoch_duration: Duration) {
+            if self.enabled {
+                byte_seq.push_const(&[0x02, 0x01]);
+            }
+        }
+
+        fn lenient_fallback(now: SystemTime) -> Self {
+            Self {
+                prologue_epoch_secs: now.duration_since(UNIX_EPOCH).unwrap().as_secs() as _,
+                enabled: false,
+            }
+        }
+    }
+
+    #[test]
+    fn from_and_to_bytes() {
+        let value = RawTestValue {
+            enabled: true,
+            ..RawTestValue::lenient_fallback(SystemTime::now())
+        };
+        let bytes = value.to_bytes().unwrap();
+        assert_eq!(
+            bytes[bytes.len() - 7..],
+            [0x00, 0x02, 0x01, 0x00, 0x00, 0x00, 0x00]
+        );
+
+        let parsed_value = RawTestValue::from_bytes(&bytes, Strictness::Strict).unwrap();
+        assert!(parsed_value.enabled);
+        assert!(parsed_value.prologue_epoch_secs > value.prologue_epoch_secs);
+
+        //. Missing trailing zero bytes are only tolerated leniently.
+        let truncated_bytes = &bytes[..bytes.len() - 2];
+        assert!(RawTestValue::from_bytes(truncated_bytes, Strictness::Strict).is_err());
+        assert!(RawTestValue::from_bytes(truncated_bytes, Strictness::Lenient).is_ok());
+    }
+}
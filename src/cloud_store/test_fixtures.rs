@@ -0,0 +1,20 @@
+//! Registry values shared by the tests of several modules.
+
+/// A Night Light state value, as written by Windows.
+pub const NIGHT_LIGHT_STATE_BYTES: [u8; 43] = [
+    0x43, 0x42, 0x01, 0x00, 0x0a, 0x02, 0x01, 0x00, 0x2a, 0x06, 0xae, 0x81, 0xd2, 0xa9, 0x06, 0x2a,
+    0x2b, 0x0e, 0x15, 0x43, 0x42, 0x01, 0x00, 0x10, 0x00, 0xd0, 0x0a, 0x02, 0xc6, 0x14, 0xe6, 0xfd,
+    0x92, 0xd6, 0xa9, 0x91, 0x81, 0xed, 0x01, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Where the fields of [`NIGHT_LIGHT_STATE_BYTES`] begin, after the prologue and the zero byte before the fields.
+pub const NIGHT_LIGHT_STATE_FIELDS_INDEX: usize = 23;
+
+/// A Night Light settings value, as written by Windows.
+pub const NIGHT_LIGHT_SETTINGS_BYTES: [u8; 67] = [
+    0x43, 0x42, 0x01, 0x00, 0x0a, 0x02, 0x01, 0x00, 0x2a, 0x06, 0xfe, 0xcf, 0xee, 0xa9, 0x06, 0x2a,
+    0x2b, 0x0e, 0x2d, 0x43, 0x42, 0x01, 0x00, 0x02, 0x01, 0xc2, 0x0a, 0x00, 0xca, 0x14, 0x0e, 0x08,
+    0x2e, 0x0f, 0x00, 0xca, 0x1e, 0x0e, 0x0e, 0x2e, 0x1e, 0x00, 0xcf, 0x28, 0xf8, 0x29, 0xca, 0x32,
+    0x0e, 0x15, 0x2e, 0x03, 0x00, 0xca, 0x3c, 0x0e, 0x06, 0x2e, 0x14, 0x00, 0xc2, 0x46, 0x01, 0x00,
+    0x00, 0x00, 0x00,
+];
@@ -0,0 +1,204 @@
+//! The configuration file of the binaries, `%APPDATA%\sem-reg\config.toml`, providing defaults for command line flags, so that they don't have to be repeated in every invocation. Keys are in camel case, like in the crate's JSON output.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, env, fs, io, path::PathBuf, time::Duration};
+use thiserror::Error;
+
+use crate::{
+    cloud_store::night_light::ChangeSet,
+    logging::{Level, LogFormat},
+    reg::retry::RetryPolicy,
+    webhook::WebhookConfig,
+};
+
+/// The contents of the configuration file. Missing keys take their default values.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct Config {
+    pub night_light: NightLightConfig,
+    pub logging: LoggingConfig,
+    pub daemon: DaemonConfig,
+    pub registry: RegistryConfig,
+    /// Endpoints notified about changes by `sem-reg daemon` and `night-light monitor`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<WebhookConfig>,
+    /// Named Night Light changes that can be applied with a single command.
+    pub profiles: BTreeMap<String, ChangeSet>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct NightLightConfig {
+    /// Show 12-hour instead of 24-hour clock times.
+    pub am_pm: bool,
+    /// The gamma applied to warmth values when no gamma is specified on the command line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gamma: Option<f32>,
+    /// The number of milliseconds to hold preview mode active when initing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub init_duration: Option<u16>,
+    /// The number of milliseconds to delay initing after the screen was turned on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_initing_delay: Option<u16>,
+    /// The number of milliseconds after which changes are re-read to verify that Windows kept them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_delay: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct LoggingConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<Level>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<LogFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+}
+
+/// Options of the daemon that take precedence over the corresponding Night Light options.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct DaemonConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub init_duration: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_initing_delay: Option<u16>,
+}
+
+/// Retrying of registry operations that failed transiently.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct RegistryConfig {
+    /// The number of attempts including the first one. `1` disables retrying.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_attempts: Option<u32>,
+    /// The number of milliseconds to wait before the first retry. Doubled for every further retry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_delay: Option<u16>,
+}
+
+impl RegistryConfig {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        let default = RetryPolicy::DEFAULT;
+        RetryPolicy {
+            max_attempts: self.retry_attempts.unwrap_or(default.max_attempts).max(1),
+            initial_delay: self.retry_delay.map_or(default.initial_delay, |millis| {
+                Duration::from_millis(millis as _)
+            }),
+            ..default
+        }
+    }
+}
+
+impl Config {
+    pub fn file_path() -> Option<PathBuf> {
+        //! Returns `None`, if the `APPDATA` environment variable isn't set.
+
+        Some(Self::dir_path()?.join("config.toml"))
+    }
+
+    pub fn schema_dir_path() -> Option<PathBuf> {
+        //! The directory with CloudStore value definitions (see [`schema`](crate::cloud_store::schema)). Returns `None`, if the `APPDATA` environment variable isn't set.
+
+        Some(Self::dir_path()?.join("schemas"))
+    }
+
+    pub fn wal_dir_path() -> Option<PathBuf> {
+        //! The directory with the intent files of the write-ahead log (see [`wal`](crate::reg::wal)). Returns `None`, if the `APPDATA` environment variable isn't set.
+
+        Some(Self::dir_path()?.join("wal"))
+    }
+
+    pub fn night_light_preset_file_path() -> Option<PathBuf> {
+        //! The file with the presets of [`NightLight::save_preset()`](crate::cloud_store::night_light::NightLight::save_preset). Returns `None`, if the `APPDATA` environment variable isn't set.
+
+        Some(Self::dir_path()?.join("night-light-presets.json"))
+    }
+
+    pub fn night_light_undo_file_path() -> Option<PathBuf> {
+        //! The file with the undo points of [`NightLight::push_undo_point()`](crate::cloud_store::night_light::NightLight::push_undo_point). Returns `None`, if the `APPDATA` environment variable isn't set.
+
+        Some(Self::dir_path()?.join("night-light-undo.json"))
+    }
+
+    pub fn night_light_journal_file_path() -> Option<PathBuf> {
+        //! The journal of Night Light changes recorded by `night-light monitor --record` (see [`journal`](crate::reg::monitor::journal)). Returns `None`, if the `APPDATA` environment variable isn't set.
+
+        Some(Self::dir_path()?.join("night-light-journal.jsonl"))
+    }
+
+    fn dir_path() -> Option<PathBuf> {
+        Some(PathBuf::from(env::var_os("APPDATA")?).join("sem-reg"))
+    }
+
+    pub fn load() -> Result<Self, ConfigError> {
+        //! Reads the configuration file. If it doesn't exist, the default configuration is returned.
+
+        let Some(file_path) = Self::file_path() else {
+            return Ok(Self::default());
+        };
+
+        match fs::read_to_string(&file_path) {
+            Ok(text) => Self::from_toml(&text),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("serializing config to TOML shouldn't fail")
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("invalid config file: {0}")]
+    TomlError(#[from] toml::de::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use crate::logging::Level;
+
+    #[test]
+    fn parse_config() {
+        let config = Config::from_toml(
+            r#"
+            [nightLight]
+            amPm = true
+            gamma = 1.8
+
+            [logging]
+            level = "debug"
+
+            [profiles.movie]
+            active = true
+            nightColorTemp = 2700
+
+            [[webhooks]]
+            url = "http://localhost:8123/hook"
+            headers = { Authorization = "Bearer x" }
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.night_light.am_pm);
+        assert_eq!(config.night_light.gamma, Some(1.8));
+        assert_eq!(config.night_light.init_duration, None);
+        assert_eq!(config.logging.level, Some(Level::Debug));
+        assert_eq!(config.profiles["movie"].night_color_temp, Some(Some(2700)));
+        assert_eq!(config.webhooks[0].url, "http://localhost:8123/hook");
+        assert_eq!(config.webhooks[0].max_retries, None);
+
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+        assert!(Config::from_toml("[nightLight]\nampm = true").is_err());
+    }
+}
@@ -0,0 +1,428 @@
+//! A resident process serving requests from other processes over a named pipe, so that repeated invocations don't pay COM and registry startup costs and state is centralized. Contains the protocol, the client and the server loop.
+//!
+//! Every message is a single line of JSON. A client connects, sends one request, reads one response and disconnects.
+//!
+//! Besides the daemon on the pipe named by [`pipe_name()`], `night-light keep-initing` serves the same protocol on the pipe named by [`keep_initing_pipe_name()`], so that scripts can control it instead of racing it with direct registry writes.
+
+use chrono::{DateTime, Local};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{self, BufRead, BufReader, Read, Write},
+    marker::PhantomData,
+    mem, ptr, thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use windows::{
+    core::HSTRING,
+    Win32::{
+        Foundation::{
+            LocalFree, ERROR_NO_DATA, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, HANDLE, HLOCAL,
+            WAIT_ABANDONED, WAIT_OBJECT_0, WAIT_TIMEOUT,
+        },
+        Security::{
+            Authorization::{
+                ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+            },
+            PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES,
+        },
+        Storage::FileSystem::{
+            FlushFileBuffers, ReadFile, WriteFile, FILE_FLAG_FIRST_PIPE_INSTANCE,
+            PIPE_ACCESS_DUPLEX,
+        },
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, PIPE_READMODE_BYTE,
+            PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_WAIT,
+        },
+        System::Threading::{CreateMutexW, ReleaseMutex, WaitForSingleObject},
+    },
+};
+use windows_helpers::ResGuard;
+
+use crate::{
+    cloud_store::night_light::ChangeSet, data_conversion::format::write_table, log_warn,
+    reg::sid::current_user_sid,
+};
+
+/// How often a client retries connecting while the daemon serves another client.
+const BUSY_RETRY_COUNT: usize = 40;
+const BUSY_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "request", rename_all = "camelCase")]
+pub enum Request {
+    /// Asks for the current Night Light configuration, answered with [`Response::Status`].
+    Status,
+    /// Changes Night Light, answered with [`Response::Status`] containing the new configuration.
+    SetNightLight(ChangeSet),
+    /// Switches Night Light on or off, answered like [`Request::SetNightLight`].
+    Toggle,
+    /// Inits Night Light like the `init` command does, e.g., when the color temperature wasn't restored. Answered with [`Response::Status`].
+    Reinit,
+    /// Makes the daemon or keep-initing instance end after answering with [`Response::Done`].
+    Stop,
+    /// Asks a keep-initing instance how it's doing, answered with [`Response::Health`]. The daemon answers with [`Response::Error`].
+    Health,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "response", rename_all = "camelCase")]
+pub enum Response {
+    /// Contains the JSON of [`NightLight::to_json()`](crate::cloud_store::night_light::NightLight::to_json).
+    Status {
+        night_light: serde_json::Value,
+    },
+    Done,
+    Error {
+        message: String,
+    },
+    Health {
+        health: InstanceHealth,
+    },
+}
+
+/// The state of a keep-initing instance.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceHealth {
+    pub uptime_secs: u64,
+    /// Milliseconds since the Unix epoch when Night Light was last inited.
+    pub last_init_epoch_millis: Option<u64>,
+    /// The message of the last error, e.g., from initing Night Light. Isn't cleared by later successes.
+    pub last_error: Option<String>,
+    /// The state Windows last reported for the monitors, which is `off` only when all are off.
+    pub monitor_state: MonitorState,
+}
+
+impl InstanceHealth {
+    pub fn last_init_time(&self) -> Option<SystemTime> {
+        self.last_init_epoch_millis
+            .map(|millis| UNIX_EPOCH + Duration::from_millis(millis))
+    }
+}
+
+impl fmt::Display for InstanceHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (hours, mins) = (self.uptime_secs / 3600, self.uptime_secs / 60 % 60);
+
+        write_table(
+            f,
+            &[
+                Some(("Uptime", format!("{hours} h {mins} min"))),
+                Some((
+                    "Last Init",
+                    self.last_init_time()
+                        .map(|time| {
+                            DateTime::<Local>::from(time)
+                                .format("%Y-%m-%d %X")
+                                .to_string()
+                        })
+                        .unwrap_or_else(|| "never".to_string()),
+                )),
+                Some((
+                    "Last Error",
+                    self.last_error
+                        .clone()
+                        .unwrap_or_else(|| "none".to_string()),
+                )),
+                Some((
+                    "Monitor State",
+                    format!("{:?}", self.monitor_state).to_lowercase(),
+                )),
+            ],
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum MonitorState {
+    On,
+    Off,
+    Dimmed,
+}
+
+pub fn pipe_name() -> Result<String, windows::core::Error> {
+    //! The name of the pipe that the current user's daemon serves. Pipe names are visible across sessions and users, so the user's SID makes it per user.
+
+    Ok(format!(r"\\.\pipe\sem-reg-{}", current_user_sid()?))
+}
+
+pub fn keep_initing_pipe_name() -> Result<String, windows::core::Error> {
+    //! The name of the pipe that the current user's `night-light keep-initing` instance serves. Per user, like [`pipe_name()`].
+
+    Ok(format!(
+        r"\\.\pipe\sem-reg-keep-initing-{}",
+        current_user_sid()?
+    ))
+}
+
+pub fn send_request(request: &Request) -> Result<Response, io::Error> {
+    //! Connects to the daemon, sends the request and waits for the response. Fails with [`io::ErrorKind::NotFound`], if no daemon is running.
+
+    send_request_to(&pipe_name()?, request)
+}
+
+pub fn send_request_to(pipe_name: &str, request: &Request) -> Result<Response, io::Error> {
+    //! Like [`send_request()`], but to the server of the pipe with the name, like that from [`keep_initing_pipe_name()`].
+
+    let mut retry_count = 0;
+    let mut pipe = loop {
+        match OpenOptions::new().read(true).write(true).open(pipe_name) {
+            Err(error)
+                if error.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as _)
+                    && retry_count < BUSY_RETRY_COUNT =>
+            {
+                retry_count += 1;
+                thread::sleep(BUSY_RETRY_INTERVAL);
+            }
+            result => break result?,
+        }
+    };
+
+    write_message(&mut pipe, request)?;
+    read_message(&mut BufReader::new(pipe))
+}
+
+pub fn serve<F>(handler: F) -> Result<(), io::Error>
+where
+    F: FnMut(Request) -> Response,
+{
+    //! Serves clients one after another, passing their requests to the handler, until a [`Request::Stop`] was answered. Malformed requests are answered with [`Response::Error`] without calling the handler, and clients that go away early are skipped. Fails, if another process already serves the pipe. Only the current user can connect.
+
+    serve_on(&pipe_name()?, handler)
+}
+
+pub fn serve_on<F>(pipe_name: &str, mut handler: F) -> Result<(), io::Error>
+where
+    F: FnMut(Request) -> Response,
+{
+    //! Like [`serve()`], but on the pipe with the name, like that from [`keep_initing_pipe_name()`].
+
+    let pipe = ServerPipe::create(pipe_name)?;
+
+    loop {
+        if !pipe.connect()? {
+            pipe.disconnect_client();
+            continue;
+        }
+
+        let mut stops = false;
+        let response = match read_message(&mut BufReader::new(&pipe)) {
+            Ok(request) => {
+                stops = request == Request::Stop;
+                handler(request)
+            }
+            Err(error) => Response::Error {
+                message: format!("invalid request: {error}"),
+            },
+        };
+
+        // The client may have gone away in the meantime, which mustn't end the daemon.
+        let _ = write_message(&mut &pipe, &response).and_then(|_| (&pipe).flush());
+        pipe.disconnect_client();
+
+        if stops {
+            break Ok(());
+        }
+    }
+}
+
+fn write_message<W: Write, T: Serialize>(writer: &mut W, message: &T) -> Result<(), io::Error> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes())
+}
+
+fn read_message<R: BufRead, T: DeserializeOwned>(reader: &mut R) -> Result<T, io::Error> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(serde_json::from_str(&line)?)
+}
+
+/// The server end of the named pipe, which is reused for all clients.
+struct ServerPipe {
+    handle: ResGuard<HANDLE>,
+}
+
+impl ServerPipe {
+    fn create(pipe_name: &str) -> Result<Self, io::Error> {
+        //! Only the current user gets access, so that other accounts can neither talk to the server nor create further instances of the pipe to intercept clients.
+
+        let mut security_descriptor = PSECURITY_DESCRIPTOR::default();
+        unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                &HSTRING::from(format!("D:P(A;;GA;;;{})", current_user_sid()?)),
+                SDDL_REVISION_1,
+                &mut security_descriptor,
+                None,
+            )?;
+        }
+        let security_attributes = SECURITY_ATTRIBUTES {
+            nLength: mem::size_of::<SECURITY_ATTRIBUTES>() as _,
+            lpSecurityDescriptor: security_descriptor.0,
+            bInheritHandle: false.into(),
+        };
+
+        let handle = unsafe {
+            CreateNamedPipeW(
+                &HSTRING::from(pipe_name),
+                // Fails, if the pipe already exists, i.e., another daemon is running.
+                PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                1,
+                4096,
+                4096,
+                0,
+                Some(ptr::addr_of!(security_attributes)),
+            )
+        };
+        let _ = unsafe { LocalFree(HLOCAL(security_descriptor.0)) };
+
+        if handle.is_invalid() {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            handle: ResGuard::with_res_and_close_handle(handle),
+        })
+    }
+
+    fn connect(&self) -> Result<bool, io::Error> {
+        //! Blocks until a client connects. Returns `false`, if the client already closed its end again, which still requires disconnecting.
+
+        match unsafe { ConnectNamedPipe(*self.handle, None) } {
+            Ok(()) => Ok(true),
+            // The client connected between creation or disconnection and this call.
+            Err(error) if error.code() == ERROR_PIPE_CONNECTED.to_hresult() => Ok(true),
+            Err(error) if error.code() == ERROR_NO_DATA.to_hresult() => Ok(false),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn disconnect_client(&self) {
+        //! Failing to disconnect is only logged, since it concerns a single client. If the pipe is broken, the next connection attempt fails.
+
+        if let Err(error) = unsafe { DisconnectNamedPipe(*self.handle) } {
+            log_warn!("couldn't disconnect pipe client: {error}");
+        }
+    }
+}
+
+impl Read for &ServerPipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut num_bytes_read = 0;
+        unsafe { ReadFile(*self.handle, Some(buf), Some(&mut num_bytes_read), None)? };
+        Ok(num_bytes_read as _)
+    }
+}
+
+impl Write for &ServerPipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut num_bytes_written = 0;
+        unsafe { WriteFile(*self.handle, Some(buf), Some(&mut num_bytes_written), None)? };
+        Ok(num_bytes_written as _)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        //! Waits until the client has read everything, so that disconnecting doesn't discard data.
+
+        Ok(unsafe { FlushFileBuffers(*self.handle) }?)
+    }
+}
+
+/// An owned named mutex, by which a resident process makes sure that it's the only instance. With a name in the `Global\` namespace, this also holds across sessions.
+pub struct InstanceMutex {
+    handle: ResGuard<HANDLE>,
+    /// Ownership belongs to the acquiring thread, which must also release it.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl InstanceMutex {
+    pub fn try_acquire(name: &str, timeout: Duration) -> Result<Option<Self>, io::Error> {
+        //! Waits up to the timeout for the mutex. Returns `None`, if another instance still owns it. A mutex abandoned by an instance that ended without releasing it is acquired.
+
+        let handle = ResGuard::with_res_and_close_handle(unsafe {
+            CreateMutexW(None, false, &HSTRING::from(name))?
+        });
+
+        match unsafe { WaitForSingleObject(*handle, timeout.as_millis() as _) } {
+            WAIT_OBJECT_0 | WAIT_ABANDONED => Ok(Some(Self {
+                handle,
+                _not_send: PhantomData,
+            })),
+            WAIT_TIMEOUT => Ok(None),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+impl Drop for InstanceMutex {
+    fn drop(&mut self) {
+        let _ = unsafe { ReleaseMutex(*self.handle) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_message, write_message, InstanceHealth, MonitorState, Request, Response};
+    use crate::cloud_store::night_light::ChangeSet;
+
+    #[test]
+    fn message_round_trip() {
+        let mut buffer = Vec::new();
+        let request = Request::SetNightLight(ChangeSet {
+            active: Some(true),
+            night_color_temp: Some(Some(2700)),
+            ..Default::default()
+        });
+        write_message(&mut buffer, &request).unwrap();
+        write_message(&mut buffer, &Response::Done).unwrap();
+
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.starts_with(r#"{"request":"setNightLight","active":true,"#));
+
+        let mut reader = buffer.as_slice();
+        assert_eq!(read_message::<_, Request>(&mut reader).unwrap(), request);
+        assert_eq!(
+            read_message::<_, Response>(&mut reader).unwrap(),
+            Response::Done
+        );
+        assert!(read_message::<_, Response>(&mut reader).is_err());
+    }
+
+    #[test]
+    fn health_response() {
+        let response = Response::Health {
+            health: InstanceHealth {
+                uptime_secs: 2 * 3600 + 5 * 60 + 7,
+                last_init_epoch_millis: None,
+                last_error: Some("initing Night Light failed".to_string()),
+                monitor_state: MonitorState::Dimmed,
+            },
+        };
+
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(
+            json,
+            r#"{"response":"health","health":{"uptimeSecs":7507,"lastInitEpochMillis":null,"lastError":"initing Night Light failed","monitorState":"dimmed"}}"#
+        );
+        assert_eq!(serde_json::from_str::<Response>(&json).unwrap(), response);
+
+        let Response::Health { health } = response else {
+            unreachable!()
+        };
+        assert_eq!(
+            health.to_string(),
+            "Uptime ......... 2 h 5 min\n\
+             Last Init ...... never\n\
+             Last Error ..... initing Night Light failed\n\
+             Monitor State .. dimmed"
+        );
+    }
+}
@@ -3,11 +3,12 @@ pub(crate) mod format;
 pub mod hex_bytes;
 pub(crate) mod time;
 
-pub use byte_seq::ParseError;
+pub use byte_seq::{ByteSeq, Datum, ParseError};
+pub use time::{LocalTimeError, LocalTimePolicy};
 
 use std::ops::Deref;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct TrackedValue<T: PartialEq> {
     old: T,
     new: Option<T>,
@@ -1,30 +1,53 @@
-use std::mem;
+use std::{borrow::Cow, mem};
+use windows::core::GUID;
 use zerocopy::{AsBytes, FromBytes};
 
+use super::time::{
+    epoch_duration_to_filetime, now_as_epoch_duration, utc_epoch_secs_to_local_iso_string,
+    utc_filetime_to_local_iso_string,
+};
+
+/// The maximum number of bytes of a VLQ with 64 data bits: 9 bytes with 7 data bits each, plus one concluding byte with the remaining bit.
+const MAX_VLQ_64_LEN: usize = 10;
+
+/// Bytes to parse or build. Parsing works on borrowed bytes without copying them; pushing to borrowed bytes copies them first.
 #[derive(Debug)]
-pub struct ByteSeq {
-    bytes: Vec<u8>,
+pub struct ByteSeq<'a> {
+    bytes: Cow<'a, [u8]>,
     read_index: usize,
 }
 
-impl ByteSeq {
+/// A read position of a [`ByteSeq`] to return to. See [`ByteSeq::checkpoint()`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Checkpoint(usize);
+
+impl ByteSeq<'static> {
     pub fn new() -> Self {
-        Self {
-            bytes: Vec::new(),
-            read_index: 0,
-        }
+        Self::from_bytes(Vec::new())
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::from_bytes(Vec::with_capacity(capacity))
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
         Self {
-            bytes: Vec::with_capacity(capacity),
+            bytes: Cow::Owned(bytes),
             read_index: 0,
         }
     }
+}
 
-    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+impl Default for ByteSeq<'static> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> ByteSeq<'a> {
+    pub fn from_slice(bytes: &'a [u8]) -> Self {
         Self {
-            bytes,
+            bytes: Cow::Borrowed(bytes),
             read_index: 0,
         }
     }
@@ -41,8 +64,12 @@ impl ByteSeq {
         self.bytes.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
     pub fn num_bytes_left(&self) -> usize {
-        self.bytes.len() - self.read_index
+        self.bytes.len().saturating_sub(self.read_index)
     }
 
     pub fn seek(&mut self, index: usize) -> bool {
@@ -55,35 +82,66 @@ impl ByteSeq {
     }
 
     pub fn seek_by(&mut self, num_bytes: usize) -> bool {
-        self.seek(self.read_index + num_bytes)
+        self.read_index
+            .checked_add(num_bytes)
+            .is_some_and(|index| self.seek(index))
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        //! Remembers the read index, to speculatively parse something and return with [`Self::rollback()`], if it didn't match.
+
+        Checkpoint(self.read_index)
+    }
+
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        self.read_index = checkpoint.0;
+    }
+
+    pub fn try_parse<T, F>(&mut self, parse: F) -> Result<T, ParseError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, ParseError>,
+    {
+        //! Calls the closure and rolls back the read index, if it fails, so that something else can be attempted at the same position, like with optional groups of fields.
+
+        let checkpoint = self.checkpoint();
+        let result = parse(self);
+        if result.is_err() {
+            self.rollback(checkpoint);
+        }
+
+        result
     }
 
     pub fn assert_const(&mut self, r#const: &[u8]) -> Result<(), ParseError> {
-        self.bytes[self.read_index..]
-            .starts_with(r#const)
-            .then(|| {
-                self.read_index += r#const.len();
-                ()
-            })
-            .ok_or(ParseError::ExpectedConst(self.read_index))
+        if self.remaining().starts_with(r#const) {
+            self.read_index += r#const.len();
+            Ok(())
+        } else {
+            Err(ParseError::ExpectedConst(self.read_index))
+        }
     }
 
     pub fn push_const(&mut self, r#const: &[u8]) {
-        self.bytes.extend_from_slice(r#const);
+        self.bytes.to_mut().extend_from_slice(r#const);
     }
 
     pub fn assert_zero(&mut self) -> Result<(), ParseError> {
-        self.bytes[self.read_index..]
-            .starts_with(&[0x00])
-            .then(|| {
-                self.read_index += 1;
-                ()
-            })
-            .ok_or(ParseError::ExpectedZero(self.read_index))
+        if self.remaining().starts_with(&[0x00]) {
+            self.read_index += 1;
+            Ok(())
+        } else {
+            Err(ParseError::ExpectedZero(self.read_index))
+        }
+    }
+
+    fn remaining(&self) -> &[u8] {
+        //! The bytes not read yet. Empty, if the read index is at or beyond the end.
+
+        self.bytes.get(self.read_index..).unwrap_or_default()
     }
 
     pub fn push_zero(&mut self) {
-        self.bytes.push(0);
+        self.bytes.to_mut().push(0);
     }
 
     pub fn read_int<T: FromBytes>(&mut self) -> Result<T, ParseError> {
@@ -98,11 +156,24 @@ impl ByteSeq {
     }
 
     fn get_int<T: FromBytes>(&self, index: usize) -> Option<(T, usize)> {
-        T::read_from_prefix(&self.bytes[index..]).map(|value| (value, mem::size_of::<T>()))
+        T::read_from_prefix(self.bytes.get(index..)?).map(|value| (value, mem::size_of::<T>()))
     }
 
     pub fn push_int<T: AsBytes>(&mut self, int: T) {
-        self.bytes.extend_from_slice(T::as_bytes(&int));
+        self.bytes.to_mut().extend_from_slice(T::as_bytes(&int));
+    }
+
+    pub fn read_slice(&mut self, len: usize) -> Result<&[u8], ParseError> {
+        //! Reads the number of bytes at once, e.g., for strings.
+
+        let start_index = self.read_index;
+        match self.bytes.get(start_index..start_index.saturating_add(len)) {
+            Some(slice) => {
+                self.read_index += len;
+                Ok(slice)
+            }
+            None => Err(ParseError::InconsistentData),
+        }
     }
 
     pub fn read_vlq_64(&mut self) -> Result<u64, ParseError> {
@@ -121,6 +192,11 @@ impl ByteSeq {
         let mut shift = 0;
 
         loop {
+            if index - start_index == MAX_VLQ_64_LEN {
+                // Too long - already ruled out by the overflow check below, but stated explicitly as the bound of the loop.
+                break None;
+            }
+
             let byte = if let Some(byte) = self.bytes.get(index) {
                 byte
             } else {
@@ -153,7 +229,7 @@ impl ByteSeq {
                 // One or more bytes will follow. Set continuation bit.
                 byte |= 0b1000_0000;
             }
-            self.bytes.push(byte);
+            self.bytes.to_mut().push(byte);
 
             if value == 0 {
                 break;
@@ -202,6 +278,139 @@ impl ByteSeq {
         }) as u64
     }
 
+    pub fn read_guid(&mut self) -> Result<GUID, ParseError> {
+        //! Reads a GUID in the Windows memory layout: the first three groups little endian, the last eight bytes as they are.
+
+        let bytes: [u8; 16] = self
+            .remaining()
+            .get(..16)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(ParseError::ExpectedGuid(self.read_index))?;
+        self.read_index += 16;
+
+        Ok(GUID::from_values(
+            u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            u16::from_le_bytes(bytes[4..6].try_into().unwrap()),
+            u16::from_le_bytes(bytes[6..8].try_into().unwrap()),
+            bytes[8..].try_into().unwrap(),
+        ))
+    }
+
+    pub fn push_guid(&mut self, guid: &GUID) {
+        let bytes = self.bytes.to_mut();
+        bytes.extend_from_slice(&guid.data1.to_le_bytes());
+        bytes.extend_from_slice(&guid.data2.to_le_bytes());
+        bytes.extend_from_slice(&guid.data3.to_le_bytes());
+        bytes.extend_from_slice(&guid.data4);
+    }
+
+    pub fn read_ansi_string(&mut self) -> Result<String, ParseError> {
+        //! Reads a zero-terminated string of single-byte characters, consuming the terminator. Every byte is taken as the Unicode code point of the same value (Latin-1), which covers ASCII and, except for `0x80..=0x9f`, Windows-1252.
+
+        let start_index = self.read_index;
+        let len = self
+            .remaining()
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or(ParseError::ExpectedString(start_index))?;
+
+        let string = self.remaining()[..len]
+            .iter()
+            .map(|&byte| char::from(byte))
+            .collect();
+        self.read_index += len + 1;
+        Ok(string)
+    }
+
+    pub fn push_ansi_string(&mut self, string: &str) {
+        //! Pushes the string as single-byte characters, followed by a zero terminator. Characters beyond Latin-1 are replaced with `?`, like Windows does for characters missing in the code page.
+
+        let bytes = self.bytes.to_mut();
+        bytes.extend(
+            string
+                .chars()
+                .map(|char| u8::try_from(char).unwrap_or(b'?')),
+        );
+        bytes.push(0);
+    }
+
+    pub fn read_wide_string(&mut self) -> Result<String, ParseError> {
+        //! Reads a zero-terminated UTF-16 string (little endian), consuming the terminator.
+
+        let start_index = self.read_index;
+        let len = self
+            .remaining()
+            .chunks_exact(2)
+            .position(|pair| pair == [0, 0])
+            .ok_or(ParseError::ExpectedString(start_index))?;
+
+        let string = Self::decode_utf16(&self.remaining()[..len * 2], start_index)?;
+        self.read_index += (len + 1) * 2;
+        Ok(string)
+    }
+
+    pub fn push_wide_string(&mut self, string: &str) {
+        //! Pushes the string as UTF-16 (little endian), followed by a zero terminator.
+
+        for code_unit in string.encode_utf16().chain([0]) {
+            self.push_int(code_unit);
+        }
+    }
+
+    pub fn read_len_prefixed_string(&mut self) -> Result<String, ParseError> {
+        //! Reads a UTF-8 string prefixed with its VLQ-encoded number of bytes, like CloudStore fields of type `string`.
+
+        let start_index = self.read_index;
+        self.try_parse(|byte_seq| {
+            std::str::from_utf8(byte_seq.read_len_prefixed_slice(1)?)
+                .map(str::to_string)
+                .map_err(|_| ParseError::InvalidString(start_index))
+        })
+    }
+
+    pub fn push_len_prefixed_string(&mut self, string: &str) {
+        self.push_vlq_64(string.len() as _);
+        self.push_const(string.as_bytes());
+    }
+
+    pub fn read_len_prefixed_wide_string(&mut self) -> Result<String, ParseError> {
+        //! Reads a UTF-16 string (little endian) prefixed with its VLQ-encoded number of code units, like CloudStore fields of type `wstring`.
+
+        let start_index = self.read_index;
+        self.try_parse(|byte_seq| {
+            Self::decode_utf16(byte_seq.read_len_prefixed_slice(2)?, start_index)
+        })
+    }
+
+    pub fn push_len_prefixed_wide_string(&mut self, string: &str) {
+        let code_units = string.encode_utf16().collect::<Vec<u16>>();
+        self.push_vlq_64(code_units.len() as _);
+        for code_unit in code_units {
+            self.push_int(code_unit);
+        }
+    }
+
+    fn read_len_prefixed_slice(&mut self, unit_size: usize) -> Result<&[u8], ParseError> {
+        let start_index = self.read_index;
+        let len = usize::try_from(self.read_vlq_64()?)
+            .ok()
+            .and_then(|len| len.checked_mul(unit_size))
+            .ok_or(ParseError::ExpectedString(start_index))?;
+
+        self.read_slice(len)
+            .map_err(|_| ParseError::ExpectedString(start_index))
+    }
+
+    fn decode_utf16(bytes: &[u8], start_index: usize) -> Result<String, ParseError> {
+        let code_units = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+
+        char::decode_utf16(code_units)
+            .collect::<Result<_, _>>()
+            .map_err(|_| ParseError::InvalidString(start_index))
+    }
+
     pub fn exhausted(&self) -> bool {
         self.read_index >= self.bytes.len()
     }
@@ -212,14 +421,175 @@ impl ByteSeq {
             .ok_or(ParseError::DataAfterExpectedEnd)
     }
 
-    pub fn extend(&mut self, other: &Self) {
-        self.bytes.extend_from_slice(&other.bytes);
+    pub fn extend(&mut self, other: &ByteSeq) {
+        self.bytes.to_mut().extend_from_slice(&other.bytes);
+    }
+
+    pub fn find_data(&self, reduced_amount: bool) -> Vec<Datum> {
+        //! Helper for finding out the format of registry values. Tries to interpret the data at every index in a variety of formats. Imperfect and not thoroughly tested. With `reduced_amount`, 32-bit numbers aren't considered as timestamps.
+        //!
+        //! Strings may also be length-prefixed, where the length may be a VLQ with possibly just one byte.
+
+        // From 2015-01-01T00:00Z to one year from now.
+        const MIN_EPOCH_SECS: u64 = 1420070400;
+        const MIN_FILETIME: u64 = 130645440000000000;
+        let now_epoch_duration = now_as_epoch_duration();
+        let max_epoch_secs = now_epoch_duration.as_secs() + 31536000;
+        let max_filetime = epoch_duration_to_filetime(now_epoch_duration) as u64 + 315360000000000;
+
+        let mut data = Vec::new();
+
+        for i in 0..self.bytes.len() {
+            let u_16 = self.get_int::<u16>(i);
+            let u_32 = self.get_int::<u32>(i);
+            let u_64 = self.get_int::<u64>(i);
+
+            let mut must_push_u_16 = false;
+            let mut must_push_u_32 = false;
+            let mut must_push_u_64 = false;
+
+            let vlq_64 = self.get_vlq_64(i).filter(|(_, size)| *size >= 2);
+
+            let mut byte_seq = ByteSeq::from_slice(&self.bytes);
+            byte_seq.seek(i);
+            let ascii_string = byte_seq.read_ansi_string().ok().filter(|string| {
+                string.len() >= 4 && string.chars().all(|char| (' '..'\u{80}').contains(&char))
+            });
+
+            let mut byte_seq = ByteSeq::from_slice(&self.bytes);
+            byte_seq.seek(i);
+            let wide_string = byte_seq.read_wide_string().ok().filter(|string| {
+                string.chars().count() >= 4
+                    && string.chars().all(|char| (' '..'\u{2600}').contains(&char))
+            });
+
+            // Byte count.
+            let bytes_left = self.bytes.len() - i;
+
+            let mut byte_count = None;
+
+            let mut set_byte_count =
+                |int: Option<(usize, usize)>, extra_action: &mut dyn FnMut()| {
+                    if let (None, Some((int, size))) = (byte_count, int) {
+                        let bytes_left_all = bytes_left - size;
+                        let bytes_left_less = bytes_left_all.saturating_sub(10);
+                        byte_count = (int > 0 && int >= bytes_left_less && int <= bytes_left_all)
+                            .then(|| {
+                                extra_action();
+                                int
+                            });
+                    }
+                };
+
+            set_byte_count(vlq_64.map(|(value, size)| (value as _, size)), &mut || {});
+            set_byte_count(u_16.map(|(value, size)| (value as _, size)), &mut || {
+                must_push_u_16 = true;
+            });
+            set_byte_count(u_32.map(|(value, size)| (value as _, size)), &mut || {
+                must_push_u_32 = true;
+            });
+
+            // Timestamp.
+            let epoch_secs_from_u_32 = u_32.map(|(value, _)| value as u64).filter(|value| {
+                (MIN_EPOCH_SECS..=max_epoch_secs).contains(value) && !reduced_amount
+            });
+            must_push_u_32 = must_push_u_32 || epoch_secs_from_u_32.is_some();
+
+            let filetime_from_u_64 = u_64
+                .map(|(value, _)| value)
+                .filter(|value| (MIN_FILETIME..=max_filetime).contains(value));
+            must_push_u_64 = must_push_u_64 || filetime_from_u_64.is_some();
+
+            let epoch_secs_from_vlq = vlq_64
+                .map(|(value, _)| value)
+                .filter(|value| (MIN_EPOCH_SECS..=max_epoch_secs).contains(value));
+            let filetime_from_vlq = vlq_64
+                .map(|(value, _)| value)
+                .filter(|value| (MIN_FILETIME..=max_filetime).contains(value));
+
+            // Return data.
+            if let Some(byte_count) = byte_count {
+                data.push(Datum::ByteCount(byte_count, i));
+            }
+
+            for epoch_secs in [epoch_secs_from_u_32, epoch_secs_from_vlq] {
+                if let Some(iso_string) = epoch_secs
+                    .and_then(|epoch_secs| utc_epoch_secs_to_local_iso_string(epoch_secs as _))
+                {
+                    data.push(Datum::EpochSecsTimestamp(iso_string, i));
+                }
+            }
+
+            for filetime in [filetime_from_u_64, filetime_from_vlq] {
+                if let Some(iso_string) =
+                    filetime.and_then(|filetime| utc_filetime_to_local_iso_string(filetime as _))
+                {
+                    data.push(Datum::FiletimeTimestamp(iso_string, i));
+                }
+            }
+
+            if let (true, Some((value, _))) = (must_push_u_16, u_16) {
+                data.push(Datum::U16(value, i));
+            }
+
+            if let (true, Some((value, _))) = (must_push_u_32, u_32) {
+                data.push(Datum::U32(value, i));
+            }
+
+            if let (true, Some((value, _))) = (must_push_u_64, u_64) {
+                data.push(Datum::U64(value, i));
+            }
+
+            if let Some((value, _)) = vlq_64 {
+                data.push(Datum::Vlq64(value, Self::zigzag_64_decode(value), i));
+            }
+
+            if let Some(ascii_string) = ascii_string {
+                data.push(Datum::AsciiString(ascii_string, i));
+            }
+
+            if let Some(wide_string) = wide_string {
+                //. Skip the tails of wide strings found before.
+                let is_tail = data.iter().any(|datum| {
+                    matches!(datum, Datum::Utf16WideString(other_wide_string, _) if other_wide_string.ends_with(&wide_string))
+                });
+
+                if !is_tail {
+                    data.push(Datum::Utf16WideString(wide_string, i));
+                }
+            }
+        }
+
+        data
     }
 }
 
-impl From<ByteSeq> for Vec<u8> {
+/// A possible interpretation of raw bytes, found by [`ByteSeq::find_data()`]. All variants have the index at which the datum was found as their last tuple item.
+#[derive(PartialEq, Debug)]
+pub enum Datum {
+    /// A number that also has another reasonable interpretation.
+    U16(u16, usize),
+    /// A number that also has another reasonable interpretation.
+    U32(u32, usize),
+    /// A number that also has another reasonable interpretation.
+    U64(u64, usize),
+    /// VLQ with at least 2 bytes. Additionally presented in zigzag-decoded form.
+    Vlq64(u64, i64, usize),
+    /// Possible number of bytes between the datum and the end.
+    ByteCount(usize, usize),
+    /// Only between the year 2015 and one year from now.
+    EpochSecsTimestamp(String, usize),
+    /// Only between the year 2015 and one year from now.
+    FiletimeTimestamp(String, usize),
+    /// Only code points from U+0020 to U+007F.
+    AsciiString(String, usize),
+    /// Only code points from U+0020 to U+25FF.
+    Utf16WideString(String, usize),
+}
+
+impl From<ByteSeq<'_>> for Vec<u8> {
     fn from(value: ByteSeq) -> Self {
-        value.bytes
+        value.bytes.into_owned()
     }
 }
 
@@ -246,4 +616,354 @@ pub enum ParseError {
     /// Expected the end of the byte stream, but still found data.
     #[error("expected end of byte stream, got more data")]
     DataAfterExpectedEnd,
+    /// Nested structures went deeper than any real value does. Parsing stops before the stack could overflow.
+    #[error("structures nested too deeply at byte index {0}")]
+    NestedTooDeeply(usize),
+    /// Expected the 16 bytes of a GUID.
+    #[error("expected a GUID at byte index {0}")]
+    ExpectedGuid(usize),
+    /// Expected a string, but found no terminator or fewer bytes than its length prefix says.
+    #[error("expected a string at byte index {0}")]
+    ExpectedString(usize),
+    /// A string's bytes aren't valid in its encoding.
+    #[error("invalid string encoding at byte index {0}")]
+    InvalidString(usize),
+    /// A field whose ID, type or value isn't known, or that is out of order.
+    #[error("unexpected field at byte index {0}")]
+    UnexpectedField(usize),
+    /// A required field with the ID wasn't found.
+    #[error("missing field with ID {0}")]
+    MissingField(u16),
+    /// Another error with information for diagnosing it remotely. Added with [`Self::in_field()`] and [`Self::with_excerpt()`].
+    #[error(
+        "{source}{}{}",
+        field_path.as_ref().map_or_else(String::new, |field_path| format!(" in `{field_path}`")),
+        excerpt.as_ref().map_or_else(String::new, |excerpt| format!(" (bytes: {excerpt})"))
+    )]
+    Context {
+        /// The semantic field being parsed, like `nightLight.settings.scheduledNight.start`.
+        field_path: Option<String>,
+        /// The bytes around the error's byte index as hex, with the deviating byte in brackets.
+        excerpt: Option<String>,
+        source: Box<ParseError>,
+    },
+}
+
+impl ParseError {
+    /// The number of bytes shown on each side of the deviating byte by [`Self::with_excerpt()`].
+    const EXCERPT_RADIUS: usize = 8;
+
+    pub fn in_field(self, field_name: &str) -> Self {
+        //! Adds the name of the field being parsed. Called from the inside out, the names are joined to a path, like `nightLight.settings.scheduledNight.start`.
+
+        match self {
+            Self::Context {
+                field_path,
+                excerpt,
+                source,
+            } => Self::Context {
+                field_path: Some(match field_path {
+                    Some(field_path) => format!("{field_name}.{field_path}"),
+                    None => field_name.to_string(),
+                }),
+                excerpt,
+                source,
+            },
+            _ => Self::Context {
+                field_path: Some(field_name.to_string()),
+                excerpt: None,
+                source: Box::new(self),
+            },
+        }
+    }
+
+    pub fn with_excerpt(self, bytes: &[u8]) -> Self {
+        //! Adds the bytes around the byte index, if known, from the bytes that were parsed.
+
+        let Some(index) = self.byte_index() else {
+            return self;
+        };
+        let excerpt = Some(hex_excerpt(bytes, index, Self::EXCERPT_RADIUS));
+
+        match self {
+            Self::Context {
+                field_path, source, ..
+            } => Self::Context {
+                field_path,
+                excerpt,
+                source,
+            },
+            _ => Self::Context {
+                field_path: None,
+                excerpt,
+                source: Box::new(self),
+            },
+        }
+    }
+
+    pub fn kind(&self) -> &Self {
+        //! The error without context, for matching.
+
+        match self {
+            Self::Context { source, .. } => source.kind(),
+            _ => self,
+        }
+    }
+
+    pub fn field_path(&self) -> Option<&str> {
+        match self {
+            Self::Context { field_path, .. } => field_path.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn byte_index(&self) -> Option<usize> {
+        //! The index of the byte where parsing deviated from the expected format, if known.
+
+        match *self {
+            Self::ExpectedConst(index)
+            | Self::ExpectedZero(index)
+            | Self::ExpectedInt(index)
+            | Self::ExpectedVlq64(index)
+            | Self::NestedTooDeeply(index)
+            | Self::ExpectedGuid(index)
+            | Self::ExpectedString(index)
+            | Self::InvalidString(index)
+            | Self::UnexpectedField(index) => Some(index),
+            Self::ValueNotInRange
+            | Self::InconsistentData
+            | Self::DataAfterExpectedEnd
+            | Self::MissingField(_) => None,
+            Self::Context { ref source, .. } => source.byte_index(),
+        }
+    }
+}
+
+fn hex_excerpt(bytes: &[u8], index: usize, radius: usize) -> String {
+    //! Like `… 0e 15 [ff] 2e …`. An index at or past the end is shown as `[end]`.
+
+    let start_index = index.saturating_sub(radius).min(bytes.len());
+    let end_index = index.saturating_add(radius + 1).min(bytes.len());
+
+    let mut parts = Vec::new();
+    if start_index > 0 {
+        parts.push("…".to_string());
+    }
+    for (byte_index, byte) in bytes[start_index..end_index].iter().enumerate() {
+        parts.push(if start_index + byte_index == index {
+            format!("[{byte:02x}]")
+        } else {
+            format!("{byte:02x}")
+        });
+    }
+    if index >= bytes.len() {
+        parts.push("[end]".to_string());
+    } else if end_index < bytes.len() {
+        parts.push("…".to_string());
+    }
+
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::core::GUID;
+
+    use super::{ByteSeq, Datum, ParseError, MAX_VLQ_64_LEN};
+
+    #[test]
+    fn error_context() {
+        let bytes = (0..20).collect::<Vec<u8>>();
+        let error = ParseError::ExpectedConst(10)
+            .in_field("start")
+            .in_field("scheduledNight")
+            .with_excerpt(&bytes);
+
+        assert_eq!(error.kind(), &ParseError::ExpectedConst(10));
+        assert_eq!(error.byte_index(), Some(10));
+        assert_eq!(error.field_path(), Some("scheduledNight.start"));
+        assert_eq!(
+            error.to_string(),
+            "expected one or more constant bytes at index 10 in `scheduledNight.start` (bytes: … 02 03 04 05 06 07 08 09 [0a] 0b 0c 0d 0e 0f 10 11 12 …)"
+        );
+
+        let error = ParseError::ExpectedInt(3).with_excerpt(&bytes[..3]);
+        assert_eq!(
+            error.to_string(),
+            "expected a fixed-width integer at byte index 3 (bytes: 00 01 02 [end])"
+        );
+
+        //. Without a byte index, there's no excerpt.
+        assert_eq!(
+            ParseError::ValueNotInRange.with_excerpt(&bytes),
+            ParseError::ValueNotInRange
+        );
+    }
+
+    #[test]
+    fn checkpoint_and_rollback() {
+        let mut byte_seq = ByteSeq::from_slice(&[0x02, 0x01, 0xc2, 0x0a, 0x00]);
+        let checkpoint = byte_seq.checkpoint();
+        assert!(byte_seq.assert_const(&[0x02, 0x01]).is_ok());
+        byte_seq.rollback(checkpoint);
+        assert_eq!(byte_seq.read_index(), 0);
+
+        //. An optional group that doesn't match leaves the read index as it was.
+        let result = byte_seq.try_parse(|byte_seq| {
+            byte_seq.assert_const(&[0x02, 0x01])?;
+            byte_seq.assert_const(&[0xc2, 0x0a, 0x01])
+        });
+        assert_eq!(result, Err(ParseError::ExpectedConst(2)));
+        assert_eq!(byte_seq.read_index(), 0);
+
+        let result = byte_seq.try_parse(|byte_seq| {
+            byte_seq.assert_const(&[0x02, 0x01])?;
+            byte_seq.assert_const(&[0xc2, 0x0a, 0x00])
+        });
+        assert_eq!(result, Ok(()));
+        assert!(byte_seq.exhausted());
+    }
+
+    #[test]
+    fn guid() {
+        let guid = GUID::from_u128(0x381b4222_f694_41f0_9685_ff5bb260df2e);
+        let mut byte_seq = ByteSeq::new();
+        byte_seq.push_guid(&guid);
+        assert_eq!(
+            byte_seq.as_slice(),
+            [
+                0x22, 0x42, 0x1b, 0x38, 0x94, 0xf6, 0xf0, 0x41, 0x96, 0x85, 0xff, 0x5b, 0xb2, 0x60,
+                0xdf, 0x2e
+            ]
+        );
+
+        assert_eq!(byte_seq.read_guid(), Ok(guid));
+        assert_eq!(byte_seq.read_guid(), Err(ParseError::ExpectedGuid(16)));
+
+        let mut byte_seq = ByteSeq::from_slice(&[0; 15]);
+        assert_eq!(byte_seq.read_guid(), Err(ParseError::ExpectedGuid(0)));
+        assert_eq!(byte_seq.read_index(), 0);
+    }
+
+    #[test]
+    fn strings() {
+        let mut byte_seq = ByteSeq::new();
+        byte_seq.push_ansi_string("Größe €");
+        byte_seq.push_wide_string("Nacht 🌙");
+        byte_seq.push_len_prefixed_string("Größe");
+        byte_seq.push_len_prefixed_wide_string("🌙");
+        assert_eq!(&byte_seq.as_slice()[..9], b"Gr\xf6\xdfe ?\0N");
+
+        assert_eq!(byte_seq.read_ansi_string(), Ok("Größe ?".to_string()));
+        assert_eq!(byte_seq.read_wide_string(), Ok("Nacht 🌙".to_string()));
+        assert_eq!(byte_seq.read_len_prefixed_string(), Ok("Größe".to_string()));
+        assert_eq!(
+            byte_seq.read_len_prefixed_wide_string(),
+            Ok("🌙".to_string())
+        );
+        assert!(byte_seq.exhausted());
+
+        //. Missing terminators and truncated data.
+        let mut byte_seq = ByteSeq::from_slice(&[0x41, 0x00, 0x42]);
+        assert_eq!(
+            byte_seq.read_wide_string(),
+            Err(ParseError::ExpectedString(0))
+        );
+        assert!(byte_seq.seek(2));
+        assert_eq!(
+            byte_seq.read_ansi_string(),
+            Err(ParseError::ExpectedString(2))
+        );
+        let mut byte_seq = ByteSeq::from_slice(&[0x01, 0x05, 0x41]);
+        assert!(byte_seq.seek(1));
+        assert_eq!(
+            byte_seq.read_len_prefixed_string(),
+            Err(ParseError::ExpectedString(1))
+        );
+        assert_eq!(byte_seq.read_index(), 1);
+
+        //. Invalid encodings.
+        let mut byte_seq = ByteSeq::from_slice(&[0x01, 0xff]);
+        assert_eq!(
+            byte_seq.read_len_prefixed_string(),
+            Err(ParseError::InvalidString(0))
+        );
+        assert_eq!(byte_seq.read_index(), 0);
+        let mut byte_seq = ByteSeq::from_slice(&[0x00, 0xd8, 0x00, 0x00]);
+        assert_eq!(
+            byte_seq.read_wide_string(),
+            Err(ParseError::InvalidString(0))
+        );
+    }
+
+    #[test]
+    fn find_data() {
+        let mut byte_seq = ByteSeq::new();
+        byte_seq.push_const(&[0xff]);
+        byte_seq.push_wide_string("Night Light");
+        byte_seq.push_vlq_64(300);
+        let data = byte_seq.find_data(true);
+
+        assert!(data.contains(&Datum::Utf16WideString("Night Light".to_string(), 1)));
+        //. The tail of the string isn't found again.
+        assert!(!data
+            .iter()
+            .any(|datum| matches!(datum, Datum::Utf16WideString(_, index) if *index > 1)));
+        assert!(data.contains(&Datum::Vlq64(300, 150, 25)));
+    }
+
+    #[test]
+    fn reads_past_end() {
+        let mut byte_seq = ByteSeq::from_slice(&[0x01, 0x02]);
+        assert!(byte_seq.seek(2));
+        assert!(!byte_seq.seek(3));
+        assert!(!byte_seq.seek_by(usize::MAX));
+        assert_eq!(byte_seq.read_index(), 2);
+        assert_eq!(byte_seq.num_bytes_left(), 0);
+
+        assert_eq!(
+            byte_seq.assert_const(&[0x03]),
+            Err(ParseError::ExpectedConst(2))
+        );
+        assert_eq!(byte_seq.assert_const(&[]), Ok(()));
+        assert_eq!(byte_seq.assert_zero(), Err(ParseError::ExpectedZero(2)));
+        assert_eq!(byte_seq.read_int::<u8>(), Err(ParseError::ExpectedInt(2)));
+        assert_eq!(byte_seq.read_vlq_64(), Err(ParseError::ExpectedVlq64(2)));
+        assert_eq!(
+            byte_seq.read_slice(usize::MAX),
+            Err(ParseError::InconsistentData)
+        );
+        assert_eq!(byte_seq.read_slice(0), Ok(&[][..]));
+        assert!(byte_seq.exhausted());
+    }
+
+    #[test]
+    fn vlq_max_len() {
+        let mut byte_seq = ByteSeq::new();
+        byte_seq.push_vlq_64(u64::MAX);
+        assert_eq!(byte_seq.len(), MAX_VLQ_64_LEN);
+        assert_eq!(byte_seq.read_vlq_64(), Ok(u64::MAX));
+
+        //. Overflowing last byte.
+        let mut bytes = vec![0xff; MAX_VLQ_64_LEN - 1];
+        bytes.push(0x02);
+        assert_eq!(
+            ByteSeq::from_slice(&bytes).read_vlq_64(),
+            Err(ParseError::ExpectedVlq64(0))
+        );
+
+        //. Overlong, even if the value would fit.
+        let mut bytes = vec![0x80; MAX_VLQ_64_LEN];
+        bytes.push(0x00);
+        assert_eq!(
+            ByteSeq::from_slice(&bytes).read_zigzag_vlq_64(),
+            Err(ParseError::ExpectedVlq64(0))
+        );
+
+        //. Endless continuation.
+        assert_eq!(
+            ByteSeq::from_slice(&[0xff; 100]).read_vlq_64(),
+            Err(ParseError::ExpectedVlq64(0))
+        );
+    }
 }
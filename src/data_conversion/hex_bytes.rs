@@ -91,9 +91,79 @@ impl fmt::Display for Color {
     }
 }
 
+pub(crate) fn to_hex_str(bytes: &[u8]) -> String {
+    //! Formats the bytes as contiguous lowercase hex digit pairs, like `"0a1b"`.
+
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub(crate) fn parse_hex_str(string: &str) -> Option<Vec<u8>> {
+    //! Parses contiguous hex digit pairs of any case.
+
+    string
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => {
+                Some(((*high as char).to_digit(16)? << 4 | (*low as char).to_digit(16)?) as u8)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// For `#[serde(with = "...")]` on `Vec<u8>` fields, to (de)serialize them as strings like [`to_hex_str()`] returns.
+pub(crate) mod hex_str_serde {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_hex_str(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        super::parse_hex_str(&String::deserialize(deserializer)?)
+            .ok_or_else(|| serde::de::Error::custom("invalid hex digit pairs"))
+    }
+}
+
+/// Like [`hex_str_serde`], but for `Option<Vec<u8>>` fields, with `None` as null.
+pub(crate) mod opt_hex_str_serde {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        bytes: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        bytes
+            .as_deref()
+            .map(super::to_hex_str)
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|string| {
+                super::parse_hex_str(&string)
+                    .ok_or_else(|| serde::de::Error::custom("invalid hex digit pairs"))
+            })
+            .transpose()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::data_conversion::hex_bytes::HexBytes;
+    use crate::data_conversion::hex_bytes::{parse_hex_str, to_hex_str, HexBytes};
+
+    #[test]
+    fn hex_str() {
+        assert_eq!(to_hex_str(&[0x0a, 0xff, 0x00]), "0aff00");
+        assert_eq!(parse_hex_str("0aFF00"), Some(vec![0x0a, 0xff, 0x00]));
+        assert_eq!(parse_hex_str(""), Some(vec![]));
+        assert_eq!(parse_hex_str("0af"), None);
+        assert_eq!(parse_hex_str("0g"), None);
+    }
 
     #[test]
     fn diff_output() {
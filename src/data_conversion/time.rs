@@ -1,6 +1,12 @@
-use std::time::{Duration, SystemTime};
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
 
-use chrono::{DateTime, Local, SecondsFormat};
+use chrono::{
+    DateTime, Duration as ChronoDuration, Local, LocalResult, NaiveDateTime, Offset, SecondsFormat,
+    TimeZone,
+};
 
 const HECTONANOS_1601_TO_1970: i64 = 11644473600_000_000_0;
 const HECTONANOS_PER_SEC: i64 = 1_000_000_0;
@@ -20,20 +26,40 @@ pub fn now_as_epoch_duration() -> Duration {
     system_time_to_epoch_duration(SystemTime::now())
 }
 
+/// How much later the timestamp of a new version of a CloudStore value is than that of the previous version at least.
+pub const MIN_EPOCH_SECS_STEP: u32 = 2;
+
 pub fn epoch_duration_to_epoch_secs(duration: Duration) -> u32 {
-    //! # Panics
-    //! Panics if the time is in the distant past or future.
+    //! Saturates at `u32::MAX`, i.e., in the year 2106, instead of wrapping.
 
-    duration
-        .as_secs()
-        .try_into()
-        .expect("now shouldn't be distant past or future")
+    duration.as_secs().try_into().unwrap_or(u32::MAX)
 }
 
 pub fn epoch_duration_to_filetime(duration: Duration) -> i64 {
-    (duration.as_nanos() / 100) as i64 + HECTONANOS_1601_TO_1970
+    //! Saturates at [`LATEST_FILETIME`].
+
+    i64::try_from(duration.as_nanos() / 100)
+        .unwrap_or(i64::MAX)
+        .saturating_add(HECTONANOS_1601_TO_1970)
+        .min(LATEST_FILETIME)
+}
+
+pub fn next_epoch_secs(previous_epoch_secs: Option<u32>, now_epoch_secs: u32) -> Option<u32> {
+    //! The timestamp for a new version of a value: the current time, but at least [`MIN_EPOCH_SECS_STEP`] later than the previous version's. This way, the new version is considered newer even when the system clock went backwards or the previous version was written with a clock running ahead. Returns `None`, if the previous timestamp is so far in the future that it can't be advanced.
+
+    match previous_epoch_secs {
+        Some(previous_epoch_secs) => {
+            Some(now_epoch_secs.max(previous_epoch_secs.checked_add(MIN_EPOCH_SECS_STEP)?))
+        }
+        None => Some(now_epoch_secs),
+    }
 }
 
+/// A registry value's timestamp is so far in the future that it can't be advanced by [`next_epoch_secs()`], e.g., because it was written with a wrong system clock.
+#[derive(thiserror::Error, Clone, Copy, PartialEq, Eq, Debug)]
+#[error("timestamp is implausibly far in the future")]
+pub struct ImplausibleTimeError;
+
 pub fn utc_epoch_secs_to_local_iso_string(secs: u32) -> Option<String> {
     Some(
         DateTime::from_timestamp(secs as _, 0)?
@@ -53,11 +79,286 @@ pub fn utc_filetime_to_local_iso_string(filetime: i64) -> Option<String> {
 }
 
 pub fn utc_filetime_to_local_date_time(filetime: i64) -> Option<DateTime<Local>> {
+    utc_filetime_to_date_time(filetime, &Local)
+}
+
+fn utc_filetime_to_date_time<Tz: TimeZone>(filetime: i64, tz: &Tz) -> Option<DateTime<Tz>> {
+    //. Euclidean division, so that times before 1970 don't yield negative nanoseconds.
+    let hectonanos = filetime.checked_sub(HECTONANOS_1601_TO_1970)?;
     Some(
         DateTime::from_timestamp(
-            (filetime - HECTONANOS_1601_TO_1970) / HECTONANOS_PER_SEC,
-            (filetime % HECTONANOS_PER_SEC * 100) as _,
+            hectonanos.div_euclid(HECTONANOS_PER_SEC),
+            (hectonanos.rem_euclid(HECTONANOS_PER_SEC) * 100) as _,
         )?
-        .with_timezone(&Local),
+        .with_timezone(tz),
+    )
+}
+
+pub fn format_local_date_time<Tz: TimeZone>(date_time: &DateTime<Tz>, format: &str) -> String
+where
+    Tz::Offset: fmt::Display,
+{
+    //! Formats the date and time, appending the UTC offset if the local time is ambiguous, because it occurs twice when the clocks are turned back at the end of daylight saving time.
+
+    let mut string = date_time.format(format).to_string();
+
+    if resolve_local_date_time(
+        &date_time.timezone(),
+        date_time.naive_local(),
+        LocalTimePolicy::Error,
     )
+    .is_err_and(|error| error == LocalTimeError::Ambiguous)
+    {
+        string.push_str(&date_time.format(" (UTC%:z)").to_string());
+    }
+
+    string
+}
+
+/// How to resolve a local date and time that doesn't denote exactly one point in time because of a daylight saving time transition.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LocalTimePolicy {
+    /// The earlier of the two points in time of an ambiguous local time. A nonexistent local time is moved back by the length of the gap, i.e., to before the clocks were turned forward.
+    Earliest,
+    /// The later of the two points in time of an ambiguous local time. A nonexistent local time is moved forward by the length of the gap.
+    Latest,
+    /// Fail with a [`LocalTimeError`].
+    Error,
+}
+
+#[derive(thiserror::Error, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LocalTimeError {
+    /// The local time occurs twice, because the clocks are turned back at that time.
+    #[error("local time is ambiguous because of a daylight saving time transition")]
+    Ambiguous,
+    /// The local time is skipped, because the clocks are turned forward at that time.
+    #[error("local time doesn't exist because of a daylight saving time transition")]
+    Nonexistent,
+}
+
+pub(crate) fn resolve_local_date_time<Tz: TimeZone>(
+    tz: &Tz,
+    naive: NaiveDateTime,
+    policy: LocalTimePolicy,
+) -> Result<DateTime<Tz>, LocalTimeError> {
+    match (tz.from_local_datetime(&naive), policy) {
+        (LocalResult::Single(date_time), _) => Ok(date_time),
+        (LocalResult::Ambiguous(earliest, _), LocalTimePolicy::Earliest) => Ok(earliest),
+        (LocalResult::Ambiguous(_, latest), LocalTimePolicy::Latest) => Ok(latest),
+        (LocalResult::Ambiguous(..), LocalTimePolicy::Error) => Err(LocalTimeError::Ambiguous),
+        (LocalResult::None, LocalTimePolicy::Error) => Err(LocalTimeError::Nonexistent),
+        (LocalResult::None, _) => {
+            //. Interpreting the local time with the offsets before and after the gap yields points in time after and before it. (Transitions are assumed to be more than a day apart.)
+            let offset = |naive_utc| {
+                ChronoDuration::seconds(
+                    tz.offset_from_utc_datetime(&naive_utc)
+                        .fix()
+                        .local_minus_utc() as _,
+                )
+            };
+            let day = ChronoDuration::days(1);
+            let with_offset_before = naive - offset(naive - day);
+            let with_offset_after = naive - offset(naive + day);
+
+            let naive_utc = if policy == LocalTimePolicy::Earliest {
+                with_offset_before.min(with_offset_after)
+            } else {
+                with_offset_before.max(with_offset_after)
+            };
+            Ok(tz.from_utc_datetime(&naive_utc))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+    use std::time::Duration;
+
+    use super::{
+        epoch_duration_to_epoch_secs, epoch_duration_to_filetime, format_local_date_time,
+        next_epoch_secs, resolve_local_date_time, utc_filetime_to_date_time, LocalTimeError,
+        LocalTimePolicy, LATEST_FILETIME, MIN_EPOCH_SECS_STEP,
+    };
+
+    /// Central European time in 2024: UTC+2 from Mar. 31, 01:00 UTC, until Oct. 27, 01:00 UTC, UTC+1 otherwise.
+    #[derive(Clone, Copy, Debug)]
+    struct TestZone;
+
+    impl TestZone {
+        fn naive(month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+            NaiveDate::from_ymd_opt(2024, month, day)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap()
+        }
+    }
+
+    impl TimeZone for TestZone {
+        type Offset = FixedOffset;
+
+        fn from_offset(_offset: &FixedOffset) -> Self {
+            Self
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            //. The larger offset yields the earlier point in time.
+            let offsets = [2, 1]
+                .map(|hours| FixedOffset::east_opt(hours * 3600).unwrap())
+                .into_iter()
+                .filter(|offset| {
+                    self.offset_from_utc_datetime(
+                        &(*local - chrono::Duration::seconds(offset.local_minus_utc() as _)),
+                    ) == *offset
+                })
+                .collect::<Vec<_>>();
+
+            match offsets[..] {
+                [] => LocalResult::None,
+                [offset] => LocalResult::Single(offset),
+                [earliest, latest] => LocalResult::Ambiguous(earliest, latest),
+                _ => unreachable!(),
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let hours = if (Self::naive(3, 31, 1, 0)..Self::naive(10, 27, 1, 0)).contains(utc) {
+                2
+            } else {
+                1
+            };
+            FixedOffset::east_opt(hours * 3600).unwrap()
+        }
+    }
+
+    #[test]
+    fn saturation() {
+        assert_eq!(
+            epoch_duration_to_epoch_secs(Duration::from_secs(u32::MAX as u64 + 1)),
+            u32::MAX
+        );
+        assert_eq!(epoch_duration_to_epoch_secs(Duration::MAX), u32::MAX);
+
+        assert_eq!(epoch_duration_to_filetime(Duration::MAX), LATEST_FILETIME);
+        assert_eq!(
+            epoch_duration_to_filetime(Duration::ZERO),
+            116_444_736_000_000_000
+        );
+    }
+
+    #[test]
+    fn monotonic_epoch_secs() {
+        let now = 1_700_000_000;
+
+        assert_eq!(next_epoch_secs(None, now), Some(now));
+        assert_eq!(next_epoch_secs(Some(now - 100), now), Some(now));
+        //. Clock went backwards.
+        assert_eq!(
+            next_epoch_secs(Some(now + 100), now),
+            Some(now + 100 + MIN_EPOCH_SECS_STEP)
+        );
+        assert_eq!(
+            next_epoch_secs(Some(now - 1), now),
+            Some(now - 1 + MIN_EPOCH_SECS_STEP)
+        );
+
+        assert_eq!(
+            next_epoch_secs(Some(u32::MAX - MIN_EPOCH_SECS_STEP), now),
+            Some(u32::MAX)
+        );
+        assert_eq!(next_epoch_secs(Some(u32::MAX - 1), now), None);
+        assert_eq!(next_epoch_secs(Some(u32::MAX), u32::MAX), None);
+    }
+
+    #[test]
+    fn dst_transitions() {
+        let utc_hour = |date_time: chrono::DateTime<TestZone>| date_time.naive_utc().hour();
+
+        //. Clocks turned back from 03:00 to 02:00.
+        let ambiguous = TestZone::naive(10, 27, 2, 30);
+        let resolve = |policy| resolve_local_date_time(&TestZone, ambiguous, policy);
+        assert_eq!(resolve(LocalTimePolicy::Earliest).map(utc_hour), Ok(0));
+        assert_eq!(resolve(LocalTimePolicy::Latest).map(utc_hour), Ok(1));
+        assert_eq!(
+            resolve(LocalTimePolicy::Error).map(utc_hour),
+            Err(LocalTimeError::Ambiguous)
+        );
+
+        //. Clocks turned forward from 02:00 to 03:00.
+        let nonexistent = TestZone::naive(3, 31, 2, 30);
+        let resolve = |policy| resolve_local_date_time(&TestZone, nonexistent, policy);
+        assert_eq!(
+            resolve(LocalTimePolicy::Earliest).unwrap().naive_local(),
+            TestZone::naive(3, 31, 1, 30)
+        );
+        assert_eq!(
+            resolve(LocalTimePolicy::Latest).unwrap().naive_local(),
+            TestZone::naive(3, 31, 3, 30)
+        );
+        assert_eq!(
+            resolve(LocalTimePolicy::Error).map(utc_hour),
+            Err(LocalTimeError::Nonexistent)
+        );
+
+        let regular = TestZone::naive(7, 1, 12, 0);
+        for policy in [
+            LocalTimePolicy::Earliest,
+            LocalTimePolicy::Latest,
+            LocalTimePolicy::Error,
+        ] {
+            assert_eq!(
+                resolve_local_date_time(&TestZone, regular, policy).map(utc_hour),
+                Ok(10)
+            );
+        }
+    }
+
+    #[test]
+    fn format_ambiguous() {
+        let format = "%Y-%m-%d, %H:%M:%S";
+
+        //. 00:30 and 01:30 UTC are both 02:30 local time.
+        let first = TestZone.from_utc_datetime(&TestZone::naive(10, 27, 0, 30));
+        let second = TestZone.from_utc_datetime(&TestZone::naive(10, 27, 1, 30));
+        assert_eq!(
+            format_local_date_time(&first, format),
+            "2024-10-27, 02:30:00 (UTC+02:00)"
+        );
+        assert_eq!(
+            format_local_date_time(&second, format),
+            "2024-10-27, 02:30:00 (UTC+01:00)"
+        );
+
+        let regular = TestZone.from_utc_datetime(&TestZone::naive(10, 27, 3, 0));
+        assert_eq!(
+            format_local_date_time(&regular, format),
+            "2024-10-27, 04:00:00"
+        );
+    }
+
+    #[test]
+    fn filetime_before_1970() {
+        let utc = FixedOffset::east_opt(0).unwrap();
+
+        //. 1969-12-31, 23:59:59.5.
+        let date_time =
+            utc_filetime_to_date_time(116_444_736_000_000_000 - 5_000_000, &utc).unwrap();
+        assert_eq!(
+            date_time.naive_utc(),
+            NaiveDate::from_ymd_opt(1969, 12, 31)
+                .unwrap()
+                .and_hms_milli_opt(23, 59, 59, 500)
+                .unwrap()
+        );
+
+        assert!(utc_filetime_to_date_time(i64::MIN, &utc).is_none());
+    }
 }
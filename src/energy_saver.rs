@@ -0,0 +1,299 @@
+//! Types to retrieve and change when the energy saver (called battery saver before Windows 11) turns on, and whether it currently is on.
+//!
+//! The threshold is a setting of the active power scheme, which Windows stores in registry values under `HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Control\Power`. It's read and written with the power management functions, since they fall back to the scheme's default for missing values and apply changes immediately. Like with `NightLight`, you should read, mutate and write without delays in between. `EnergySaver` instances expire after a short duration to enforce this.
+
+use futures::channel::oneshot;
+use serde_json::json;
+use std::{fmt, io, time::Duration};
+use windows::{
+    core::GUID,
+    Win32::{
+        Foundation::{LocalFree, HLOCAL, WIN32_ERROR},
+        System::{
+            Power::{
+                GetSystemPowerStatus, PowerGetActiveScheme, PowerReadDCValueIndex,
+                PowerSetActiveScheme, PowerWriteDCValueIndex, SYSTEM_POWER_STATUS,
+            },
+            Registry::HKEY,
+            SystemServices::{GUID_ENERGY_SAVER_BATTERY_THRESHOLD, GUID_ENERGY_SAVER_SUBGROUP},
+        },
+    },
+};
+use winreg::enums::HKEY_LOCAL_MACHINE;
+
+use crate::{
+    data_conversion::{Strictness, TrackedValue},
+    expiry::Expiry,
+    reg::{
+        export_reg_values,
+        monitor::{MonitorLoopError, RegValueMonitor},
+        OwnedRegValuePath,
+    },
+};
+
+/// The battery percentage at or below which the energy saver turns on, and its current state.
+pub struct EnergySaver {
+    scheme: GUID,
+    battery_threshold: TrackedValue<u8>,
+    active: bool,
+    expiry: Expiry,
+}
+
+impl EnergySaver {
+    /// The threshold with which the energy saver never turns on automatically.
+    pub const NEVER_THRESHOLD: u8 = 0;
+    /// The threshold with which the energy saver is always on while on battery power.
+    pub const ALWAYS_THRESHOLD: u8 = 100;
+
+    /// Default duration after which an instance expires. See [`Self::set_expiration_timeout()`].
+    pub const EXPIRATION_TIMEOUT: Duration = Duration::from_millis(1000);
+
+    pub fn from_reg() -> Result<Self, self::Error> {
+        //! Creates a strict instance using [`Self::from_reg_with_strictness()`].
+
+        Self::from_reg_with_strictness(Strictness::Strict)
+    }
+
+    pub fn from_reg_lenient() -> Result<Self, self::Error> {
+        Self::from_reg_with_strictness(Strictness::Lenient)
+    }
+
+    pub fn from_reg_with_strictness(strictness: Strictness) -> Result<Self, self::Error> {
+        //! Reads the threshold of the active power scheme. In lenient mode, thresholds above 100 % are clamped instead of being rejected.
+
+        let scheme = active_scheme()?;
+
+        let mut threshold = 0;
+        win32_result(unsafe {
+            PowerReadDCValueIndex(
+                HKEY::default(),
+                Some(&scheme),
+                Some(&GUID_ENERGY_SAVER_SUBGROUP),
+                Some(&GUID_ENERGY_SAVER_BATTERY_THRESHOLD),
+                &mut threshold,
+            )
+        })?;
+
+        let battery_threshold = match threshold {
+            0..=100 => threshold as u8,
+            _ if strictness.is_lenient() => Self::ALWAYS_THRESHOLD,
+            _ => return Err(Error::InvalidThreshold(threshold)),
+        };
+
+        let mut power_status = SYSTEM_POWER_STATUS::default();
+        unsafe { GetSystemPowerStatus(&mut power_status)? };
+
+        Ok(Self::new(
+            scheme,
+            battery_threshold,
+            power_status.SystemStatusFlag == 1,
+        ))
+    }
+
+    fn new(scheme: GUID, battery_threshold: u8, active: bool) -> Self {
+        Self {
+            scheme,
+            battery_threshold: TrackedValue::new(battery_threshold),
+            active,
+            expiry: Expiry::new(Self::EXPIRATION_TIMEOUT),
+        }
+    }
+
+    pub fn reg_value_path() -> Result<OwnedRegValuePath, self::Error> {
+        //! The registry value of the active power scheme containing the threshold. Only exists after the threshold was changed from the scheme's default.
+
+        Ok(threshold_reg_value_path(&active_scheme()?))
+    }
+
+    pub fn export_reg<T: AsRef<std::path::Path>>(file_path: T) -> Result<(), self::Error> {
+        Ok(export_reg_values(&[Self::reg_value_path()?], file_path)?)
+    }
+
+    pub fn battery_threshold(&self) -> u8 {
+        //! The battery percentage at or below which the energy saver turns on. See [`Self::NEVER_THRESHOLD`] and [`Self::ALWAYS_THRESHOLD`].
+
+        *self.battery_threshold
+    }
+
+    pub fn set_battery_threshold(&mut self, battery_threshold: u8) {
+        //! Values above 100 are clamped.
+
+        self.battery_threshold
+            .set(battery_threshold.min(Self::ALWAYS_THRESHOLD));
+    }
+
+    pub fn active(&self) -> bool {
+        //! Whether the energy saver was on when reading. Windows doesn't offer a way to switch it directly; use [`Self::ALWAYS_THRESHOLD`] to have it on while on battery power.
+
+        self.active
+    }
+
+    pub fn set_expiration_timeout(&mut self, expiration_timeout: Duration) {
+        //! Changes the duration after loading after which the instance can't be written anymore, to avoid race conditions with other writers.
+
+        self.expiry.set_timeout(expiration_timeout);
+    }
+
+    pub fn time_left(&self) -> Duration {
+        //! The duration until the instance expires. Zero, if it already did.
+
+        self.expiry.time_left()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        //! Whether writing would fail with [`Error::Expired`].
+
+        self.expiry.is_expired()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&json!({
+            "active": self.active(),
+            "batteryThreshold": self.battery_threshold(),
+        }))
+        .expect("serializing to JSON shouldn't fail")
+    }
+
+    pub fn write_to_reg(self) -> Result<(), self::Error> {
+        //! Writes the threshold, if it changed, and reactivates the power scheme, which applies it immediately.
+
+        if self.is_expired() {
+            return Err(Error::Expired);
+        }
+
+        if self.battery_threshold.changed() {
+            win32_result(unsafe {
+                PowerWriteDCValueIndex(
+                    HKEY::default(),
+                    &self.scheme,
+                    Some(&GUID_ENERGY_SAVER_SUBGROUP),
+                    Some(&GUID_ENERGY_SAVER_BATTERY_THRESHOLD),
+                    *self.battery_threshold as _,
+                )
+            })?;
+            unsafe { PowerSetActiveScheme(HKEY::default(), Some(&self.scheme))? };
+        }
+
+        Ok(())
+    }
+
+    pub fn monitor<F, T, E>(
+        stop_receiver: Option<oneshot::Receiver<T>>,
+        mut callback: F,
+    ) -> Result<T, MonitorLoopError<E>>
+    where
+        F: FnMut() -> Option<Result<T, E>>,
+        T: Default,
+        E: From<self::Error>,
+    {
+        //! Calls the callback whenever the threshold of the active power scheme changed. Fails, if the threshold was never changed from the scheme's default, since the registry key doesn't exist then. Changes of the current state aren't reported.
+
+        let reg_value_path =
+            Self::reg_value_path().map_err(|error| MonitorLoopError::Other(error.into()))?;
+        let mut monitor = RegValueMonitor::new([((), reg_value_path)])?;
+
+        monitor.r#loop(stop_receiver, |_| callback())
+    }
+}
+
+impl fmt::Display for EnergySaver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Active: {}", if self.active() { "yes" } else { "no" })?;
+        write!(
+            f,
+            "Turns on: {}",
+            match self.battery_threshold() {
+                Self::NEVER_THRESHOLD => "never".to_string(),
+                Self::ALWAYS_THRESHOLD => "always".to_string(),
+                threshold => format!("at {threshold} % battery"),
+            }
+        )
+    }
+}
+
+fn active_scheme() -> Result<GUID, io::Error> {
+    let mut scheme_ptr = std::ptr::null_mut();
+    unsafe { PowerGetActiveScheme(HKEY::default(), &mut scheme_ptr)? };
+
+    let scheme = unsafe { *scheme_ptr };
+    let _ = unsafe { LocalFree(HLOCAL(scheme_ptr as _)) };
+
+    Ok(scheme)
+}
+
+fn threshold_reg_value_path(scheme: &GUID) -> OwnedRegValuePath {
+    //! Like `windows` formats GUIDs, but lowercase and without braces, like the power scheme keys are named.
+
+    let key_name = |guid: &GUID| format!("{guid:?}").to_lowercase();
+
+    OwnedRegValuePath {
+        hkey: HKEY_LOCAL_MACHINE,
+        subkey_path: format!(
+            r"SYSTEM\CurrentControlSet\Control\Power\User\PowerSchemes\{}\{}\{}",
+            key_name(scheme),
+            key_name(&GUID_ENERGY_SAVER_SUBGROUP),
+            key_name(&GUID_ENERGY_SAVER_BATTERY_THRESHOLD),
+        ),
+        value_name: "DCSettingIndex".to_string(),
+    }
+}
+
+fn win32_result(error_code: u32) -> Result<(), io::Error> {
+    //! For power management functions returning a raw Win32 error code.
+
+    WIN32_ERROR(error_code).ok().map_err(io::Error::from)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+    /// The threshold isn't a percentage. Lenient mode clamps it.
+    #[error("invalid energy saver threshold: {0}")]
+    InvalidThreshold(u32),
+    /// The object expired to enforce avoidance of race conditions.
+    #[error("object expired: duration between reading and writing was too long")]
+    Expired,
+}
+
+impl From<windows::core::Error> for Error {
+    fn from(error: windows::core::Error) -> Self {
+        Self::IoError(error.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::core::GUID;
+
+    use super::{threshold_reg_value_path, EnergySaver};
+
+    #[test]
+    fn battery_threshold() {
+        let mut energy_saver = EnergySaver::new(GUID::zeroed(), 20, false);
+        assert_eq!(
+            energy_saver.to_string(),
+            "Active: no\nTurns on: at 20 % battery"
+        );
+
+        energy_saver.set_battery_threshold(150);
+        assert_eq!(
+            energy_saver.battery_threshold(),
+            EnergySaver::ALWAYS_THRESHOLD
+        );
+        assert!(energy_saver.battery_threshold.changed());
+        assert!(energy_saver.to_string().ends_with("always"));
+
+        energy_saver.set_battery_threshold(20);
+        assert!(!energy_saver.battery_threshold.changed());
+    }
+
+    #[test]
+    fn reg_value_path() {
+        let scheme = GUID::from_u128(0x381b4222_f694_41f0_9685_ff5bb260df2e);
+        assert_eq!(
+            threshold_reg_value_path(&scheme).subkey_path,
+            r"SYSTEM\CurrentControlSet\Control\Power\User\PowerSchemes\381b4222-f694-41f0-9685-ff5bb260df2e\de830923-a562-41af-a086-e3a2c6bad2da\e69653ca-cf7f-4f05-aa73-cb833fa90ad4"
+        );
+    }
+}
@@ -0,0 +1,54 @@
+//! Expiry of instances holding settings read from the system. Writing such an instance back after a delay could overwrite changes made elsewhere in the meantime, so the types fail writing after a short duration.
+
+use std::time::{Duration, Instant};
+
+/// When an instance was loaded and for how long it may be written afterwards.
+#[derive(Clone, Copy, Debug)]
+pub struct Expiry {
+    loaded_instant: Instant,
+    timeout: Duration,
+}
+
+impl Expiry {
+    pub fn new(timeout: Duration) -> Self {
+        //! Starts counting now.
+
+        Self {
+            loaded_instant: Instant::now(),
+            timeout,
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn time_left(&self) -> Duration {
+        //! Zero, if already expired.
+
+        self.timeout.saturating_sub(self.loaded_instant.elapsed())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.loaded_instant.elapsed() > self.timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Expiry;
+
+    #[test]
+    fn expiry() {
+        let mut expiry = Expiry::new(Duration::from_secs(3600));
+        assert!(!expiry.is_expired());
+        assert!(expiry.time_left() > Duration::ZERO);
+
+        expiry.set_timeout(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(expiry.is_expired());
+        assert_eq!(expiry.time_left(), Duration::ZERO);
+    }
+}
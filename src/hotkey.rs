@@ -0,0 +1,207 @@
+//! Global hotkeys, registered with `RegisterHotKey()` for a window, which then receives `WM_HOTKEY` messages with the registration's ID as `wparam`, even while other applications have the keyboard focus.
+
+use core::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+use windows::Win32::{
+    Foundation::HWND,
+    UI::Input::KeyboardAndMouse::{
+        RegisterHotKey, UnregisterHotKey, HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT,
+        MOD_SHIFT, MOD_WIN,
+    },
+};
+
+/// Keys with names, by their virtual-key codes. Letters and digits are named like their characters, function keys like `F1`.
+const NAMED_KEYS: [(&str, u16); 13] = [
+    ("Space", 0x20),
+    ("PageUp", 0x21),
+    ("PageDown", 0x22),
+    ("End", 0x23),
+    ("Home", 0x24),
+    ("Left", 0x25),
+    ("Up", 0x26),
+    ("Right", 0x27),
+    ("Down", 0x28),
+    ("Insert", 0x2d),
+    ("Delete", 0x2e),
+    ("Pause", 0x13),
+    ("ScrollLock", 0x91),
+];
+
+const VK_F1: u16 = 0x70;
+const NUM_FUNCTION_KEYS: u16 = 24;
+
+/// A key combination like `Ctrl+Alt+N`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Hotkey {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub win: bool,
+    /// The virtual-key code of the non-modifier key.
+    pub virtual_key: u16,
+}
+
+impl Hotkey {
+    fn modifiers(&self) -> HOT_KEY_MODIFIERS {
+        let mut modifiers = MOD_NOREPEAT;
+        for (pressed, modifier) in [
+            (self.ctrl, MOD_CONTROL),
+            (self.alt, MOD_ALT),
+            (self.shift, MOD_SHIFT),
+            (self.win, MOD_WIN),
+        ] {
+            if pressed {
+                modifiers |= modifier;
+            }
+        }
+
+        modifiers
+    }
+
+    fn key_name(&self) -> Option<String> {
+        match self.virtual_key {
+            key @ (0x30..=0x39 | 0x41..=0x5a) => Some(char::from(key as u8).to_string()),
+            key if (VK_F1..VK_F1 + NUM_FUNCTION_KEYS).contains(&key) => {
+                Some(format!("F{}", key - VK_F1 + 1))
+            }
+            key => NAMED_KEYS
+                .iter()
+                .find(|(_, named_key)| *named_key == key)
+                .map(|(name, _)| name.to_string()),
+        }
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = HotkeyFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        //! Parses modifiers and a key joined with `+`, like `Ctrl+Alt+N` or `Win+F9`, case-insensitively. Letters and digits require a modifier other than Shift, so that typing isn't swallowed.
+
+        let mut hotkey = Self {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            win: false,
+            virtual_key: 0,
+        };
+
+        let mut parts = s.split('+').map(str::trim).collect::<Vec<_>>();
+        let key = parts.pop().ok_or(HotkeyFromStrError)?;
+
+        for modifier in parts {
+            let pressed = match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut hotkey.ctrl,
+                "alt" => &mut hotkey.alt,
+                "shift" => &mut hotkey.shift,
+                "win" => &mut hotkey.win,
+                _ => return Err(HotkeyFromStrError),
+            };
+            if *pressed {
+                return Err(HotkeyFromStrError);
+            }
+            *pressed = true;
+        }
+
+        let upper_key = key.to_ascii_uppercase();
+        hotkey.virtual_key = match upper_key.as_bytes() {
+            [char @ (b'0'..=b'9' | b'A'..=b'Z')] => {
+                if !(hotkey.ctrl || hotkey.alt || hotkey.win) {
+                    return Err(HotkeyFromStrError);
+                }
+                *char as _
+            }
+            [b'F', number @ ..] if !number.is_empty() && number[0] != b'0' => {
+                match upper_key[1..].parse::<u16>() {
+                    Ok(number @ 1..=NUM_FUNCTION_KEYS) => VK_F1 + number - 1,
+                    _ => return Err(HotkeyFromStrError),
+                }
+            }
+            _ => {
+                NAMED_KEYS
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                    .ok_or(HotkeyFromStrError)?
+                    .1
+            }
+        };
+
+        Ok(hotkey)
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (pressed, name) in [
+            (self.ctrl, "Ctrl"),
+            (self.alt, "Alt"),
+            (self.shift, "Shift"),
+            (self.win, "Win"),
+        ] {
+            if pressed {
+                write!(f, "{name}+")?;
+            }
+        }
+
+        match self.key_name() {
+            Some(name) => write!(f, "{name}"),
+            None => write!(f, "0x{:02x}", self.virtual_key),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("hotkeys must consist of modifiers ('Ctrl', 'Alt', 'Shift', 'Win') and a key like 'N', 'F9' or 'Space', joined with '+'; letters and digits require a modifier other than 'Shift'")]
+pub struct HotkeyFromStrError;
+
+/// A registered hotkey, unregistered on drop. Must be dropped on the thread that registered it, before the window is destroyed.
+pub struct HotkeyRegistration {
+    hwnd: HWND,
+    id: i32,
+}
+
+impl HotkeyRegistration {
+    pub fn new(hwnd: HWND, id: i32, hotkey: Hotkey) -> windows::core::Result<Self> {
+        //! Fails, if another application already registered the hotkey. Holding the keys down doesn't repeat the `WM_HOTKEY` messages.
+
+        unsafe { RegisterHotKey(hwnd, id, hotkey.modifiers(), hotkey.virtual_key as _)? };
+        Ok(Self { hwnd, id })
+    }
+}
+
+impl Drop for HotkeyRegistration {
+    fn drop(&mut self) {
+        let _ = unsafe { UnregisterHotKey(self.hwnd, self.id) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Hotkey;
+
+    #[test]
+    fn parse_hotkey() {
+        let hotkey = "ctrl + Alt+n".parse::<Hotkey>().unwrap();
+        assert!(hotkey.ctrl && hotkey.alt && !hotkey.shift && !hotkey.win);
+        assert_eq!(hotkey.virtual_key, b'N' as u16);
+        assert_eq!(hotkey.to_string(), "Ctrl+Alt+N");
+
+        for s in ["Win+F9", "F24", "Shift+PageDown", "Ctrl+Shift+7"] {
+            assert_eq!(s.parse::<Hotkey>().unwrap().to_string(), s);
+        }
+
+        for s in [
+            "",
+            "N",
+            "Shift+N",
+            "Ctrl+Ctrl+N",
+            "Ctrl+F0",
+            "F25",
+            "Ctrl+",
+            "Hyper+N",
+        ] {
+            assert!(s.parse::<Hotkey>().is_err(), "{s:?} shouldn't parse");
+        }
+    }
+}
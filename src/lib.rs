@@ -1,3 +1,17 @@
+pub mod autostart;
+pub mod bundle;
 pub mod cloud_store;
+pub mod config;
+pub mod daemon;
 pub mod data_conversion;
+pub mod energy_saver;
+pub mod expiry;
+pub mod hotkey;
+pub mod logging;
+pub mod mirror;
+pub mod personalization;
 pub mod reg;
+pub mod sun;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+pub mod webhook;
@@ -0,0 +1,269 @@
+//! A small logging facility shared by the binaries, with a level, text or JSON lines, and output to stderr or to a size-rotated file. Use the macros [`log_error!`](crate::log_error), [`log_warn!`](crate::log_warn), [`log_info!`](crate::log_info) and [`log_debug!`](crate::log_debug).
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = &'static str;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        [Self::Error, Self::Warn, Self::Info, Self::Debug]
+            .into_iter()
+            .find(|level| level.as_str().eq_ignore_ascii_case(string))
+            .ok_or("expected 'error', 'warn', 'info' or 'debug'")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Lines like `2023-12-24T18:00:00.000+01:00 INFO message`.
+    Text,
+    /// Lines like `{"level":"info","message":"message","time":"2023-12-24T18:00:00.000+01:00"}`.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = &'static str;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        if string.eq_ignore_ascii_case("text") {
+            Ok(Self::Text)
+        } else if string.eq_ignore_ascii_case("json") {
+            Ok(Self::Json)
+        } else {
+            Err("expected 'text' or 'json'")
+        }
+    }
+}
+
+/// Configuration for [`init()`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct LogConfig {
+    /// Messages less severe than this are discarded.
+    pub level: Level,
+    pub format: LogFormat,
+    /// `None` logs to stderr.
+    pub file_path: Option<PathBuf>,
+    /// When the log file would exceed this size, it's renamed to `<file name>.1` (shifting older files), and a new one is started.
+    pub max_file_len: u64,
+    /// The number of rotated files to keep.
+    pub max_rotated_files: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: Level::Info,
+            format: LogFormat::Text,
+            file_path: None,
+            max_file_len: 1024 * 1024,
+            max_rotated_files: 3,
+        }
+    }
+}
+
+struct Logger {
+    config: LogConfig,
+    file: Option<File>,
+}
+
+pub fn init(config: LogConfig) -> Result<(), io::Error> {
+    //! Sets the process-wide configuration. Until called, the default configuration is used.
+
+    let file = config
+        .file_path
+        .as_ref()
+        .map(|file_path| open_log_file(file_path))
+        .transpose()?;
+
+    *LOGGER.lock().unwrap() = Some(Logger { config, file });
+    Ok(())
+}
+
+pub fn enabled(level: Level) -> bool {
+    level <= current_level()
+}
+
+pub fn log(level: Level, args: fmt::Arguments) {
+    //! Usually called via the macros. Failures to write are ignored.
+
+    let mut logger = LOGGER.lock().unwrap();
+    let logger = logger.get_or_insert_with(|| Logger {
+        config: LogConfig::default(),
+        file: None,
+    });
+
+    if level > logger.config.level {
+        return;
+    }
+
+    let line = format_line(
+        logger.config.format,
+        &chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+        level,
+        &args.to_string(),
+    );
+
+    match (&mut logger.file, &logger.config.file_path) {
+        (Some(file), Some(file_path)) => {
+            let file_len = file.metadata().map_or(0, |metadata| metadata.len());
+            if file_len > 0 && file_len + line.len() as u64 > logger.config.max_file_len {
+                if let Ok(new_file) = rotate(file_path, logger.config.max_rotated_files) {
+                    *file = new_file;
+                }
+            }
+
+            let _ = file.write_all(line.as_bytes());
+        }
+        _ => {
+            let _ = io::stderr().write_all(line.as_bytes());
+        }
+    }
+}
+
+fn current_level() -> Level {
+    LOGGER
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map_or(LogConfig::default().level, |logger| logger.config.level)
+}
+
+fn format_line(format: LogFormat, time: &str, level: Level, message: &str) -> String {
+    let mut line = match format {
+        LogFormat::Text => format!("{time} {} {message}", level.as_str().to_uppercase()),
+        LogFormat::Json => json!({
+            "time": time,
+            "level": level.as_str(),
+            "message": message,
+        })
+        .to_string(),
+    };
+
+    line.push('\n');
+    line
+}
+
+fn open_log_file(file_path: &Path) -> Result<File, io::Error> {
+    OpenOptions::new().create(true).append(true).open(file_path)
+}
+
+fn rotate(file_path: &Path, max_rotated_files: usize) -> Result<File, io::Error> {
+    //! Shifts the rotated files, renames the current file and opens a new one.
+
+    if max_rotated_files == 0 {
+        fs::remove_file(file_path)?;
+    } else {
+        let _ = fs::remove_file(rotated_file_path(file_path, max_rotated_files));
+        for num in (1..max_rotated_files).rev() {
+            let _ = fs::rename(
+                rotated_file_path(file_path, num),
+                rotated_file_path(file_path, num + 1),
+            );
+        }
+        fs::rename(file_path, rotated_file_path(file_path, 1))?;
+    }
+
+    open_log_file(file_path)
+}
+
+fn rotated_file_path(file_path: &Path, num: usize) -> PathBuf {
+    let mut file_name = file_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".{num}"));
+    file_path.with_file_name(file_name)
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Error, format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Warn, format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Info, format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Debug, format_args!($($arg)+))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{format_line, rotated_file_path, Level, LogFormat};
+
+    #[test]
+    fn line_formats() {
+        let time = "2023-12-24T18:00:00.000+01:00";
+
+        assert_eq!(
+            format_line(LogFormat::Text, time, Level::Warn, "a \"b\""),
+            "2023-12-24T18:00:00.000+01:00 WARN a \"b\"\n"
+        );
+        assert_eq!(
+            format_line(LogFormat::Json, time, Level::Warn, "a \"b\""),
+            "{\"level\":\"warn\",\"message\":\"a \\\"b\\\"\",\"time\":\"2023-12-24T18:00:00.000+01:00\"}\n"
+        );
+    }
+
+    #[test]
+    fn levels() {
+        assert!(Level::Error < Level::Debug);
+        assert_eq!("INFO".parse(), Ok(Level::Info));
+        assert!("verbose".parse::<Level>().is_err());
+    }
+
+    #[test]
+    fn rotated_file_paths() {
+        assert_eq!(
+            rotated_file_path(Path::new("logs/sem-reg.log"), 2),
+            PathBuf::from("logs/sem-reg.log.2")
+        );
+    }
+}
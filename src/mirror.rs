@@ -0,0 +1,362 @@
+//! Mirroring of settings from one user's hive into the hives of other local users, to keep shared PCs consistent. The registry values are copied byte by byte whenever they change in the source hive, only with the CloudStore timestamp advanced, so that Windows accepts them.
+//!
+//! Writing into other users' hives requires elevation. Only hives loaded in `HKEY_USERS`, i.e., of users that are logged on, can be written; other targets are skipped until their hive is loaded on a later change.
+
+use futures::channel::oneshot;
+use std::{collections::HashMap, io};
+use winreg::{enums::HKEY_USERS, RegKey};
+
+use crate::{
+    cloud_store::prologue::CloudStoreValuePrologue,
+    data_conversion::time::{epoch_duration_to_epoch_secs, next_epoch_secs, now_as_epoch_duration},
+    reg::{
+        monitor::{MonitorLoopError, RegValueMonitor},
+        read_reg_value_bytes,
+        wal::write_reg_bin_value_logged,
+        AsRegValuePath, OwnedRegValuePath,
+    },
+};
+
+/// How to handle a target value that differs from the source value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConflictRule {
+    /// Always overwrite the target value.
+    Overwrite,
+    /// Only overwrite the target value, if it isn't newer than the source value according to the CloudStore timestamps. Values without a timestamp are overwritten.
+    Newer,
+    /// Don't overwrite target values that were changed after the last mirroring, i.e., by the target user. Values mirrored before the mirror was created are unknown, so that the first mirroring overwrites.
+    KeepLocalChanges,
+}
+
+/// What [`Mirror::plan()`] determined for a value of a target user.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MirrorAction {
+    pub target_sid: String,
+    /// The path in the target user's hive.
+    pub reg_value_path: OwnedRegValuePath,
+    pub decision: MirrorDecision,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum MirrorDecision {
+    /// The source bytes, with a timestamp newer than the target value's, are to be written.
+    Write(Vec<u8>),
+    /// The target value already equals the source value.
+    Unchanged,
+    Skip(SkipReason),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SkipReason {
+    /// The value doesn't exist in the source hive. Target values aren't deleted.
+    SourceMissing,
+    /// The target user isn't logged on.
+    HiveNotLoaded,
+    /// The target value is newer ([`ConflictRule::Newer`]).
+    TargetNewer,
+    /// The target user changed the value ([`ConflictRule::KeepLocalChanges`]).
+    LocalChange,
+}
+
+pub struct Mirror {
+    /// Paths under `HKEY_CURRENT_USER`.
+    reg_value_paths: Vec<OwnedRegValuePath>,
+    source_sid: String,
+    target_sids: Vec<String>,
+    rule: ConflictRule,
+    /// The bytes last written, per path in a target hive.
+    mirrored_bytes: HashMap<OwnedRegValuePath, Vec<u8>>,
+}
+
+impl Mirror {
+    pub fn new<P: AsRegValuePath>(
+        reg_value_paths: &[P],
+        source_sid: String,
+        target_sids: Vec<String>,
+        rule: ConflictRule,
+    ) -> Self {
+        //! `reg_value_paths` are expected under `HKEY_CURRENT_USER` and resolved to the users' hives. The source user is removed from the targets.
+
+        let target_sids = target_sids
+            .into_iter()
+            .filter(|sid| *sid != source_sid)
+            .collect();
+
+        Self {
+            reg_value_paths: reg_value_paths
+                .iter()
+                .map(|path| OwnedRegValuePath::from(path.as_reg_value_path()))
+                .collect(),
+            source_sid,
+            target_sids,
+            rule,
+            mirrored_bytes: HashMap::new(),
+        }
+    }
+
+    pub fn plan(&self) -> Vec<MirrorAction> {
+        //! Determines what mirroring the current source values would do, without writing anything. Useful for a dry run.
+
+        let mut actions = Vec::new();
+        let now_epoch_secs = epoch_duration_to_epoch_secs(now_as_epoch_duration());
+
+        for reg_value_path in &self.reg_value_paths {
+            let source_bytes =
+                read_reg_value_bytes(&reg_value_path.in_user_hive(&self.source_sid).as_borrowed())
+                    .ok();
+
+            for target_sid in &self.target_sids {
+                let target_path = reg_value_path.in_user_hive(target_sid);
+
+                let decision = if !is_hive_loaded(target_sid) {
+                    MirrorDecision::Skip(SkipReason::HiveNotLoaded)
+                } else {
+                    decide(
+                        self.rule,
+                        source_bytes.as_deref(),
+                        read_reg_value_bytes(&target_path.as_borrowed())
+                            .ok()
+                            .as_deref(),
+                        self.mirrored_bytes.get(&target_path).map(Vec::as_slice),
+                        now_epoch_secs,
+                    )
+                };
+
+                actions.push(MirrorAction {
+                    target_sid: target_sid.clone(),
+                    reg_value_path: target_path,
+                    decision,
+                });
+            }
+        }
+
+        actions
+    }
+
+    pub fn apply(&mut self, action: &MirrorAction) -> Result<(), io::Error> {
+        //! Performs the action, if it's a write. Creates the target key, if necessary.
+
+        if let MirrorDecision::Write(bytes) = &action.decision {
+            write_reg_bin_value_logged(&action.reg_value_path.as_borrowed(), bytes, true)?;
+            self.mirrored_bytes
+                .insert(action.reg_value_path.clone(), bytes.clone());
+        }
+
+        Ok(())
+    }
+
+    pub fn run<F, U>(
+        &mut self,
+        stop_receiver: Option<oneshot::Receiver<U>>,
+        mut on_applied: F,
+    ) -> Result<U, MonitorLoopError<io::Error>>
+    where
+        F: FnMut(&MirrorAction, &Result<(), io::Error>),
+        U: Default,
+    {
+        //! Mirrors the current values, then keeps mirroring on every change in the source hive until a signal is sent to the `stop_receiver`. `on_applied` is called with every action and its result, including skips; failed writes don't end mirroring.
+
+        let mut sync = |mirror: &mut Self| {
+            for action in mirror.plan() {
+                let result = mirror.apply(&action);
+                on_applied(&action, &result);
+            }
+        };
+
+        let source_paths = self
+            .reg_value_paths
+            .iter()
+            .map(|path| path.in_user_hive(&self.source_sid))
+            .collect::<Vec<_>>();
+        //. Created before syncing, so that no change in between is missed.
+        let mut monitor = RegValueMonitor::new(source_paths.iter().map(|path| ((), path)))?;
+
+        sync(self);
+
+        monitor.r#loop(stop_receiver, |()| {
+            sync(self);
+            None::<Result<U, io::Error>>
+        })
+    }
+}
+
+fn is_hive_loaded(sid: &str) -> bool {
+    RegKey::predef(HKEY_USERS).open_subkey(sid).is_ok()
+}
+
+fn decide(
+    rule: ConflictRule,
+    source_bytes: Option<&[u8]>,
+    target_bytes: Option<&[u8]>,
+    mirrored_bytes: Option<&[u8]>,
+    now_epoch_secs: u32,
+) -> MirrorDecision {
+    let Some(source_bytes) = source_bytes else {
+        return MirrorDecision::Skip(SkipReason::SourceMissing);
+    };
+
+    if target_bytes.is_some_and(|target_bytes| eq_except_timestamp(target_bytes, source_bytes)) {
+        return MirrorDecision::Unchanged;
+    }
+
+    let skip_reason = match (rule, target_bytes) {
+        (ConflictRule::Newer, Some(target_bytes))
+            if CloudStoreValuePrologue::epoch_secs_of(target_bytes)
+                .zip(CloudStoreValuePrologue::epoch_secs_of(source_bytes))
+                .is_some_and(|(target_secs, source_secs)| target_secs > source_secs) =>
+        {
+            Some(SkipReason::TargetNewer)
+        }
+        (ConflictRule::KeepLocalChanges, Some(target_bytes))
+            if mirrored_bytes.is_some_and(|mirrored_bytes| mirrored_bytes != target_bytes) =>
+        {
+            Some(SkipReason::LocalChange)
+        }
+        _ => None,
+    };
+
+    match skip_reason {
+        Some(skip_reason) => MirrorDecision::Skip(skip_reason),
+        None => {
+            //. Windows reverts values whose timestamp isn't newer. Bytes that aren't CloudStore values are written as they are.
+            let target_epoch_secs = target_bytes.and_then(CloudStoreValuePrologue::epoch_secs_of);
+            MirrorDecision::Write(
+                next_epoch_secs(target_epoch_secs, now_epoch_secs)
+                    .and_then(|epoch_secs| {
+                        CloudStoreValuePrologue::with_epoch_secs(source_bytes, epoch_secs)
+                    })
+                    .unwrap_or_else(|| source_bytes.to_vec()),
+            )
+        }
+    }
+}
+
+fn eq_except_timestamp(a: &[u8], b: &[u8]) -> bool {
+    a == b
+        || CloudStoreValuePrologue::with_epoch_secs(a, 0)
+            .is_some_and(|a| CloudStoreValuePrologue::with_epoch_secs(b, 0) == Some(a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decide, ConflictRule, MirrorDecision, SkipReason};
+    use crate::cloud_store::test_fixtures::{NIGHT_LIGHT_SETTINGS_BYTES, NIGHT_LIGHT_STATE_BYTES};
+    use crate::{
+        cloud_store::{fields::CloudStoreValue, prologue::CloudStoreValuePrologue},
+        data_conversion::Strictness,
+    };
+
+    const NOW: u32 = 1_700_000_000;
+
+    #[test]
+    fn conflict_rules() {
+        let source = [1, 2, 3];
+        let target = [4, 5, 6];
+        let write = MirrorDecision::Write(source.to_vec());
+
+        for rule in [
+            ConflictRule::Overwrite,
+            ConflictRule::Newer,
+            ConflictRule::KeepLocalChanges,
+        ] {
+            assert_eq!(
+                decide(rule, None, Some(&target), None, NOW),
+                MirrorDecision::Skip(SkipReason::SourceMissing)
+            );
+            assert_eq!(
+                decide(rule, Some(&source), Some(&source), None, NOW),
+                MirrorDecision::Unchanged
+            );
+            assert_eq!(decide(rule, Some(&source), None, None, NOW), write);
+            //. Unparsable values have no timestamp.
+            assert_eq!(decide(rule, Some(&source), Some(&target), None, NOW), write);
+        }
+
+        assert_eq!(
+            decide(
+                ConflictRule::KeepLocalChanges,
+                Some(&source),
+                Some(&target),
+                Some(&[7]),
+                NOW
+            ),
+            MirrorDecision::Skip(SkipReason::LocalChange)
+        );
+        assert_eq!(
+            decide(
+                ConflictRule::KeepLocalChanges,
+                Some(&source),
+                Some(&target),
+                Some(&target),
+                NOW
+            ),
+            write
+        );
+    }
+
+    #[test]
+    fn newer_rule() {
+        let with_epoch_secs = |bytes: &[u8], epoch_secs| {
+            CloudStoreValue::from_bytes(bytes, Strictness::Strict)
+                .unwrap()
+                .to_bytes_with_epoch_secs(epoch_secs)
+        };
+
+        let older = with_epoch_secs(&NIGHT_LIGHT_STATE_BYTES, NOW);
+        let newer = with_epoch_secs(&NIGHT_LIGHT_SETTINGS_BYTES, NOW + 1);
+
+        assert_eq!(
+            decide(ConflictRule::Newer, Some(&older), Some(&newer), None, NOW),
+            MirrorDecision::Skip(SkipReason::TargetNewer)
+        );
+        assert_eq!(
+            decide(ConflictRule::Newer, Some(&newer), Some(&older), None, NOW),
+            MirrorDecision::Write(with_epoch_secs(&NIGHT_LIGHT_SETTINGS_BYTES, NOW + 2))
+        );
+        assert_eq!(
+            decide(
+                ConflictRule::Overwrite,
+                Some(&older),
+                Some(&newer),
+                None,
+                NOW
+            ),
+            MirrorDecision::Write(with_epoch_secs(&NIGHT_LIGHT_STATE_BYTES, NOW + 3))
+        );
+    }
+
+    #[test]
+    fn write_restamps() {
+        let bytes =
+            CloudStoreValuePrologue::with_epoch_secs(&NIGHT_LIGHT_STATE_BYTES, 1_600_000_000)
+                .unwrap();
+        let target = CloudStoreValuePrologue::with_epoch_secs(&bytes, NOW + 100).unwrap();
+        assert_eq!(
+            CloudStoreValuePrologue::epoch_secs_of(&bytes),
+            Some(1_600_000_000)
+        );
+
+        //. Only the timestamp differs.
+        assert_eq!(
+            decide(
+                ConflictRule::Overwrite,
+                Some(&bytes),
+                Some(&target),
+                None,
+                NOW
+            ),
+            MirrorDecision::Unchanged
+        );
+
+        let MirrorDecision::Write(written) =
+            decide(ConflictRule::Overwrite, Some(&bytes), Some(&[1]), None, NOW)
+        else {
+            panic!("should write");
+        };
+        assert_eq!(CloudStoreValuePrologue::epoch_secs_of(&written), Some(NOW));
+        assert_eq!(
+            CloudStoreValuePrologue::with_epoch_secs(&written, 1_600_000_000),
+            Some(bytes)
+        );
+    }
+}
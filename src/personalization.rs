@@ -0,0 +1,40 @@
+//! Settings of the "Personalization" page of the Windows settings, which, unlike the CloudStore values, are stored in plain registry values.
+
+pub mod accent_color;
+pub mod color_mode;
+pub mod taskbar;
+
+use windows::{
+    core::{w, PCWSTR},
+    Win32::{
+        Foundation::{LPARAM, WPARAM},
+        UI::WindowsAndMessaging::{
+            SendMessageTimeoutW, HWND_BROADCAST, SMTO_ABORTIFHUNG, WM_SETTINGCHANGE,
+        },
+    },
+};
+
+/// How long to wait for each window to process the change notification.
+const NOTIFICATION_TIMEOUT_MILLIS: u32 = 100;
+
+fn notify_color_set_change() {
+    //! Explorer and apps update their colors on this notification, like when changing them in the Windows settings.
+
+    broadcast_setting_change(w!("ImmersiveColorSet"));
+}
+
+fn broadcast_setting_change(area: PCWSTR) {
+    //! Sends `WM_SETTINGCHANGE` with the name of the changed area to all top-level windows.
+
+    unsafe {
+        SendMessageTimeoutW(
+            HWND_BROADCAST,
+            WM_SETTINGCHANGE,
+            WPARAM(0),
+            LPARAM(area.as_ptr() as _),
+            SMTO_ABORTIFHUNG,
+            NOTIFICATION_TIMEOUT_MILLIS,
+            None,
+        );
+    }
+}
@@ -0,0 +1,420 @@
+//! Types to retrieve and change the accent color ("Accent color" in the Windows settings), which Windows stores redundantly in several registry values: as DWM colors for title bars and window borders, and as a palette of shades for the taskbar, the Start menu and apps.
+//!
+//! Like with `NightLight`, you should read, mutate and write without delays in between. `AccentColor` instances expire after a short duration to enforce this.
+
+use serde_json::json;
+use std::{fmt, io, num::ParseIntError, str::FromStr, time::Duration};
+use thiserror::Error;
+use winreg::enums::HKEY_CURRENT_USER;
+
+use super::notify_color_set_change;
+use crate::{
+    data_conversion::TrackedValue,
+    expiry::Expiry,
+    reg::{
+        export_reg_values,
+        value::{read_reg_value_as, write_reg_value_as},
+        RegValuePath,
+    },
+};
+
+/// A color without alpha channel.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    fn from_abgr(abgr: u32) -> Self {
+        let [r, g, b, _] = abgr.to_le_bytes();
+        Self { r, g, b }
+    }
+
+    fn to_abgr(self, alpha: u8) -> u32 {
+        u32::from_le_bytes([self.r, self.g, self.b, alpha])
+    }
+
+    fn from_argb(argb: u32) -> Self {
+        let [b, g, r, _] = argb.to_le_bytes();
+        Self { r, g, b }
+    }
+
+    fn to_argb(self, alpha: u8) -> u32 {
+        u32::from_le_bytes([self.b, self.g, self.r, alpha])
+    }
+
+    fn mix(self, other: Self, other_weight: f32) -> Self {
+        let mix_channel = |channel: u8, other_channel: u8| {
+            (channel as f32 + (other_channel as f32 - channel as f32) * other_weight).round() as u8
+        };
+
+        Self {
+            r: mix_channel(self.r, other.r),
+            g: mix_channel(self.g, other.g),
+            b: mix_channel(self.b, other.b),
+        }
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //! Like `#0078d4`.
+
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+impl FromStr for Rgb {
+    type Err = RgbFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        //! Parses six hex digits with optional leading `#`, like `#0078d4`.
+
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(RgbFromStrError);
+        }
+
+        let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16);
+        Ok(Self::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("color must consist of six hex digits with optional leading '#', like '#0078d4'")]
+pub struct RgbFromStrError;
+
+impl From<ParseIntError> for RgbFromStrError {
+    fn from(_: ParseIntError) -> Self {
+        Self
+    }
+}
+
+/// The accent color with the values derived from it.
+pub struct AccentColor {
+    /// `0xAABBGGRR`.
+    dwm_accent_color: TrackedValue<u32>,
+    /// `0xAARRGGBB`.
+    colorization_color: TrackedValue<u32>,
+    palette: TrackedValue<[u8; AccentColor::PALETTE_LEN * 4]>,
+    /// `0xAABBGGRR`.
+    accent_color_menu: TrackedValue<u32>,
+    /// `0xAABBGGRR`.
+    start_color_menu: TrackedValue<u32>,
+    expiry: Expiry,
+}
+
+impl AccentColor {
+    const DWM_SUBKEY_PATH: &'static str = r"Software\Microsoft\Windows\DWM";
+    const ACCENT_SUBKEY_PATH: &'static str =
+        r"Software\Microsoft\Windows\CurrentVersion\Explorer\Accent";
+
+    pub const DWM_ACCENT_COLOR_REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: Self::DWM_SUBKEY_PATH,
+        value_name: "AccentColor",
+    };
+    pub const COLORIZATION_COLOR_REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: Self::DWM_SUBKEY_PATH,
+        value_name: "ColorizationColor",
+    };
+    pub const PALETTE_REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: Self::ACCENT_SUBKEY_PATH,
+        value_name: "AccentPalette",
+    };
+    pub const ACCENT_COLOR_MENU_REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: Self::ACCENT_SUBKEY_PATH,
+        value_name: "AccentColorMenu",
+    };
+    pub const START_COLOR_MENU_REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: Self::ACCENT_SUBKEY_PATH,
+        value_name: "StartColorMenu",
+    };
+    /// The registry values that the accent color consists of.
+    pub const REG_VALUE_PATHS: [RegValuePath<'static>; 5] = [
+        Self::DWM_ACCENT_COLOR_REG_VALUE_PATH,
+        Self::COLORIZATION_COLOR_REG_VALUE_PATH,
+        Self::PALETTE_REG_VALUE_PATH,
+        Self::ACCENT_COLOR_MENU_REG_VALUE_PATH,
+        Self::START_COLOR_MENU_REG_VALUE_PATH,
+    ];
+
+    /// The number of colors in the palette: three lighter shades, the accent color, three darker shades and a color Windows doesn't derive from the accent color.
+    pub const PALETTE_LEN: usize = 8;
+    /// The index of the accent color itself in the palette.
+    pub const PALETTE_ACCENT_INDEX: usize = 3;
+
+    /// Default duration after which an instance expires. See [`Self::set_expiration_timeout()`].
+    pub const EXPIRATION_TIMEOUT: Duration = Duration::from_millis(1000);
+
+    pub fn from_reg() -> Result<Self, self::Error> {
+        let palette_bytes = read_reg_value_as::<Vec<u8>>(&Self::PALETTE_REG_VALUE_PATH)?;
+
+        Ok(Self::new(
+            read_reg_value_as(&Self::DWM_ACCENT_COLOR_REG_VALUE_PATH)?,
+            read_reg_value_as(&Self::COLORIZATION_COLOR_REG_VALUE_PATH)?,
+            palette_bytes
+                .try_into()
+                .map_err(|_| Error::InvalidPalette)?,
+            read_reg_value_as(&Self::ACCENT_COLOR_MENU_REG_VALUE_PATH)?,
+            read_reg_value_as(&Self::START_COLOR_MENU_REG_VALUE_PATH)?,
+        ))
+    }
+
+    fn new(
+        dwm_accent_color: u32,
+        colorization_color: u32,
+        palette: [u8; Self::PALETTE_LEN * 4],
+        accent_color_menu: u32,
+        start_color_menu: u32,
+    ) -> Self {
+        Self {
+            dwm_accent_color: TrackedValue::new(dwm_accent_color),
+            colorization_color: TrackedValue::new(colorization_color),
+            palette: TrackedValue::new(palette),
+            accent_color_menu: TrackedValue::new(accent_color_menu),
+            start_color_menu: TrackedValue::new(start_color_menu),
+            expiry: Expiry::new(Self::EXPIRATION_TIMEOUT),
+        }
+    }
+
+    pub fn export_reg<T: AsRef<std::path::Path>>(file_path: T) -> Result<(), io::Error> {
+        export_reg_values(&Self::REG_VALUE_PATHS, file_path)
+    }
+
+    pub fn accent_color(&self) -> Rgb {
+        //! The accent color of the palette, which the taskbar and apps use.
+
+        self.palette()[Self::PALETTE_ACCENT_INDEX]
+    }
+
+    pub fn set_accent_color(&mut self, accent_color: Rgb) {
+        //! Sets the accent color in all registry values, deriving the palette's shades with [`Self::derive_palette()`].
+
+        let mut palette = Self::derive_palette(accent_color);
+        palette[Self::PALETTE_LEN - 1] = self.palette()[Self::PALETTE_LEN - 1];
+        self.set_palette(palette);
+
+        self.dwm_accent_color
+            .set(accent_color.to_abgr(self.dwm_accent_color.to_le_bytes()[3]));
+        self.colorization_color
+            .set(accent_color.to_argb(self.colorization_color.to_le_bytes()[3]));
+    }
+
+    pub fn dwm_accent_color(&self) -> Rgb {
+        //! The color of title bars and window borders, if enabled in the Windows settings.
+
+        Rgb::from_abgr(*self.dwm_accent_color)
+    }
+
+    pub fn colorization_color(&self) -> Rgb {
+        Rgb::from_argb(*self.colorization_color)
+    }
+
+    pub fn palette(&self) -> [Rgb; Self::PALETTE_LEN] {
+        //! From lightest to darkest, except for the last color.
+
+        let mut palette = [Rgb::new(0, 0, 0); Self::PALETTE_LEN];
+        for (color, rgba) in palette.iter_mut().zip(self.palette.chunks_exact(4)) {
+            *color = Rgb::new(rgba[0], rgba[1], rgba[2]);
+        }
+
+        palette
+    }
+
+    pub fn set_palette(&mut self, palette: [Rgb; Self::PALETTE_LEN]) {
+        //! Also sets the colors of the Start menu that Windows takes from the palette. Doesn't change the DWM colors.
+
+        //. The fourth bytes are kept.
+        let mut bytes = *self.palette;
+        for (rgba, color) in bytes.chunks_exact_mut(4).zip(palette) {
+            rgba[..3].copy_from_slice(&[color.r, color.g, color.b]);
+        }
+        self.palette.set(bytes);
+
+        self.accent_color_menu
+            .set(palette[Self::PALETTE_ACCENT_INDEX].to_abgr(0xff));
+        self.start_color_menu
+            .set(palette[Self::PALETTE_ACCENT_INDEX + 1].to_abgr(0xff));
+    }
+
+    pub fn derive_palette(accent_color: Rgb) -> [Rgb; Self::PALETTE_LEN] {
+        //! Approximates the shades Windows derives from an accent color by mixing it with white and black. The last color is gray.
+
+        let white = Rgb::new(0xff, 0xff, 0xff);
+        let black = Rgb::new(0, 0, 0);
+
+        [
+            accent_color.mix(white, 0.6),
+            accent_color.mix(white, 0.4),
+            accent_color.mix(white, 0.2),
+            accent_color,
+            accent_color.mix(black, 0.2),
+            accent_color.mix(black, 0.45),
+            accent_color.mix(black, 0.7),
+            Rgb::new(0x88, 0x88, 0x88),
+        ]
+    }
+
+    pub fn set_expiration_timeout(&mut self, expiration_timeout: Duration) {
+        //! Changes the duration after loading after which the instance can't be written anymore, to avoid race conditions with other writers, like automatic theme switchers.
+
+        self.expiry.set_timeout(expiration_timeout);
+    }
+
+    pub fn time_left(&self) -> Duration {
+        //! The duration until the instance expires. Zero, if it already did.
+
+        self.expiry.time_left()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        //! Whether writing would fail with [`Error::Expired`].
+
+        self.expiry.is_expired()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&json!({
+            "accentColor": self.accent_color().to_string(),
+            "dwmAccentColor": self.dwm_accent_color().to_string(),
+            "colorizationColor": self.colorization_color().to_string(),
+            "palette": self.palette().map(|color| color.to_string()),
+        }))
+        .expect("serializing to JSON shouldn't fail")
+    }
+
+    pub fn write_to_reg(self) -> Result<(), self::Error> {
+        //! Writes the changed registry values and notifies running programs, so that they switch their colors immediately.
+
+        if self.is_expired() {
+            return Err(Error::Expired);
+        }
+
+        let mut changed = false;
+        if self.palette.changed() {
+            write_reg_value_as(&Self::PALETTE_REG_VALUE_PATH, self.palette.to_vec())?;
+            changed = true;
+        }
+        for (tracked_value, reg_value_path) in [
+            (
+                &self.accent_color_menu,
+                Self::ACCENT_COLOR_MENU_REG_VALUE_PATH,
+            ),
+            (
+                &self.start_color_menu,
+                Self::START_COLOR_MENU_REG_VALUE_PATH,
+            ),
+            (
+                &self.dwm_accent_color,
+                Self::DWM_ACCENT_COLOR_REG_VALUE_PATH,
+            ),
+            (
+                &self.colorization_color,
+                Self::COLORIZATION_COLOR_REG_VALUE_PATH,
+            ),
+        ] {
+            if tracked_value.changed() {
+                write_reg_value_as(&reg_value_path, **tracked_value)?;
+                changed = true;
+            }
+        }
+
+        if changed {
+            notify_color_set_change();
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for AccentColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Accent color: {}", self.accent_color())?;
+        writeln!(f, "Title bars: {}", self.dwm_accent_color())?;
+        write!(
+            f,
+            "Palette: {}",
+            self.palette().map(|color| color.to_string()).join(" ")
+        )
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+    /// The palette registry value doesn't have the expected length.
+    #[error("accent palette has unexpected length")]
+    InvalidPalette,
+    /// The object expired to enforce avoidance of race conditions.
+    #[error("object expired: duration between reading and writing was too long")]
+    Expired,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccentColor, Rgb};
+
+    #[test]
+    fn rgb() {
+        let rgb = "#0078D4".parse::<Rgb>().unwrap();
+        assert_eq!(rgb, Rgb::new(0x00, 0x78, 0xd4));
+        assert_eq!(rgb.to_string(), "#0078d4");
+        assert_eq!("0078d4".parse::<Rgb>().unwrap(), rgb);
+        assert!("#0078d".parse::<Rgb>().is_err());
+        assert!("#0078dg".parse::<Rgb>().is_err());
+        assert!("#0078ä".parse::<Rgb>().is_err());
+
+        assert_eq!(rgb.to_abgr(0xff), 0xffd47800);
+        assert_eq!(rgb.to_argb(0xc4), 0xc40078d4);
+        assert_eq!(Rgb::from_abgr(0xffd47800), rgb);
+        assert_eq!(Rgb::from_argb(0xc40078d4), rgb);
+    }
+
+    #[test]
+    fn set_accent_color() {
+        let mut accent_color = AccentColor::new(
+            0xffd47800,
+            0xc40078d4,
+            [
+                0x99, 0xeb, 0xff, 0x00, 0x4c, 0xc2, 0xff, 0x00, 0x00, 0x91, 0xf8, 0x00, 0x00, 0x78,
+                0xd4, 0x00, 0x00, 0x67, 0xc0, 0x00, 0x00, 0x3e, 0x92, 0x00, 0x00, 0x1a, 0x68, 0x00,
+                0xf7, 0x63, 0x0c, 0x00,
+            ],
+            0xffd47800,
+            0xffc06700,
+        );
+        assert_eq!(accent_color.accent_color(), Rgb::new(0x00, 0x78, 0xd4));
+
+        let red = Rgb::new(0xe8, 0x11, 0x23);
+        accent_color.set_accent_color(red);
+        assert_eq!(accent_color.accent_color(), red);
+        assert_eq!(accent_color.dwm_accent_color(), red);
+        assert_eq!(accent_color.colorization_color(), red);
+        assert_eq!(*accent_color.colorization_color >> 24, 0xc4);
+        assert_eq!(*accent_color.accent_color_menu, red.to_abgr(0xff));
+
+        let palette = accent_color.palette();
+        assert_eq!(
+            palette[AccentColor::PALETTE_LEN - 1],
+            Rgb::new(0xf7, 0x63, 0x0c)
+        );
+        assert!(palette[0].g > palette[2].g && palette[2].g > red.g);
+        assert!(palette[4].r < red.r && palette[6].r < palette[4].r);
+        assert_eq!(
+            *accent_color.start_color_menu,
+            palette[AccentColor::PALETTE_ACCENT_INDEX + 1].to_abgr(0xff)
+        );
+    }
+}
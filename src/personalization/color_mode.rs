@@ -0,0 +1,207 @@
+//! Types to retrieve and change whether Windows and apps use the light or dark theme ("Choose your mode" in the Windows settings).
+//!
+//! Like with `NightLight`, you should read, mutate and write without delays in between. `ColorMode` instances expire after a short duration to enforce this.
+
+use serde_json::json;
+use std::{fmt, io, time::Duration};
+use winreg::enums::HKEY_CURRENT_USER;
+
+use super::notify_color_set_change;
+use crate::{
+    data_conversion::TrackedValue,
+    expiry::Expiry,
+    reg::{
+        export_reg_values,
+        value::{read_reg_value_as, write_reg_value, RegData},
+        RegValuePath,
+    },
+};
+
+/// Whether apps and Windows itself (taskbar, Start menu etc.) use the light theme.
+pub struct ColorMode {
+    apps_use_light_theme: TrackedValue<bool>,
+    system_uses_light_theme: TrackedValue<bool>,
+    expiry: Expiry,
+}
+
+impl ColorMode {
+    const SUBKEY_PATH: &'static str =
+        r"SOFTWARE\Microsoft\Windows\CurrentVersion\Themes\Personalize";
+
+    pub const APPS_REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: Self::SUBKEY_PATH,
+        value_name: "AppsUseLightTheme",
+    };
+    pub const SYSTEM_REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: Self::SUBKEY_PATH,
+        value_name: "SystemUsesLightTheme",
+    };
+    /// The registry values that the color mode consists of.
+    pub const REG_VALUE_PATHS: [RegValuePath<'static>; 2] =
+        [Self::APPS_REG_VALUE_PATH, Self::SYSTEM_REG_VALUE_PATH];
+
+    /// Default duration after which an instance expires. See [`Self::set_expiration_timeout()`].
+    pub const EXPIRATION_TIMEOUT: Duration = Duration::from_millis(1000);
+
+    pub fn from_reg() -> Result<Self, self::Error> {
+        //! Missing registry values count as the light theme, which is what Windows uses then.
+
+        Ok(Self::new(
+            read_light_theme(&Self::APPS_REG_VALUE_PATH)?,
+            read_light_theme(&Self::SYSTEM_REG_VALUE_PATH)?,
+        ))
+    }
+
+    fn new(apps_use_light_theme: bool, system_uses_light_theme: bool) -> Self {
+        Self {
+            apps_use_light_theme: TrackedValue::new(apps_use_light_theme),
+            system_uses_light_theme: TrackedValue::new(system_uses_light_theme),
+            expiry: Expiry::new(Self::EXPIRATION_TIMEOUT),
+        }
+    }
+
+    pub fn export_reg<T: AsRef<std::path::Path>>(file_path: T) -> Result<(), io::Error> {
+        export_reg_values(&Self::REG_VALUE_PATHS, file_path)
+    }
+
+    pub fn apps_use_light_theme(&self) -> bool {
+        *self.apps_use_light_theme
+    }
+
+    pub fn set_apps_use_light_theme(&mut self, apps_use_light_theme: bool) {
+        self.apps_use_light_theme.set(apps_use_light_theme);
+    }
+
+    pub fn system_uses_light_theme(&self) -> bool {
+        //! Whether the taskbar, the Start menu and other parts of the shell use the light theme.
+
+        *self.system_uses_light_theme
+    }
+
+    pub fn set_system_uses_light_theme(&mut self, system_uses_light_theme: bool) {
+        self.system_uses_light_theme.set(system_uses_light_theme);
+    }
+
+    pub fn dark(&self) -> Option<bool> {
+        //! Whether both apps and Windows use the dark theme (`Some(true)`) or the light theme (`Some(false)`). `None` for the "Custom" mode, where they differ.
+
+        (self.apps_use_light_theme() == self.system_uses_light_theme())
+            .then_some(!self.apps_use_light_theme())
+    }
+
+    pub fn set_dark(&mut self, dark: bool) {
+        //! Sets the theme of both apps and Windows.
+
+        self.set_apps_use_light_theme(!dark);
+        self.set_system_uses_light_theme(!dark);
+    }
+
+    pub fn set_expiration_timeout(&mut self, expiration_timeout: Duration) {
+        //! Changes the duration after loading after which the instance can't be written anymore, to avoid race conditions with other writers, like automatic theme switchers.
+
+        self.expiry.set_timeout(expiration_timeout);
+    }
+
+    pub fn time_left(&self) -> Duration {
+        //! The duration until the instance expires. Zero, if it already did.
+
+        self.expiry.time_left()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        //! Whether writing would fail with [`Error::Expired`].
+
+        self.expiry.is_expired()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&json!({
+            "dark": self.dark(),
+            "appsUseLightTheme": self.apps_use_light_theme(),
+            "systemUsesLightTheme": self.system_uses_light_theme(),
+        }))
+        .expect("serializing to JSON shouldn't fail")
+    }
+
+    pub fn write_to_reg(self) -> Result<(), self::Error> {
+        //! Writes the changed registry values and notifies running programs, so that they switch their theme immediately.
+
+        if self.is_expired() {
+            return Err(Error::Expired);
+        }
+
+        let mut changed = false;
+        for (tracked_value, reg_value_path) in [
+            (&self.apps_use_light_theme, Self::APPS_REG_VALUE_PATH),
+            (&self.system_uses_light_theme, Self::SYSTEM_REG_VALUE_PATH),
+        ] {
+            if tracked_value.changed() {
+                write_reg_value(&reg_value_path, &RegData::Dword(**tracked_value as u32))?;
+                changed = true;
+            }
+        }
+
+        if changed {
+            notify_color_set_change();
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ColorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let theme = |light: bool| if light { "light" } else { "dark" };
+
+        match self.dark() {
+            Some(dark) => writeln!(f, "Mode: {}", theme(!dark))?,
+            None => writeln!(f, "Mode: custom")?,
+        }
+        writeln!(f, "Apps: {}", theme(self.apps_use_light_theme()))?;
+        write!(f, "Windows: {}", theme(self.system_uses_light_theme()))
+    }
+}
+
+fn read_light_theme(reg_value_path: &RegValuePath) -> Result<bool, io::Error> {
+    match read_reg_value_as::<u32>(reg_value_path) {
+        Ok(dword) => Ok(dword != 0),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(true),
+        Err(error) => Err(error),
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+    /// The object expired to enforce avoidance of race conditions.
+    #[error("object expired: duration between reading and writing was too long")]
+    Expired,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ColorMode;
+
+    #[test]
+    fn dark() {
+        let mut color_mode = ColorMode::new(true, true);
+        assert_eq!(color_mode.dark(), Some(false));
+
+        color_mode.set_apps_use_light_theme(false);
+        assert_eq!(color_mode.dark(), None);
+        assert!(color_mode.to_string().starts_with("Mode: custom"));
+
+        color_mode.set_dark(true);
+        assert_eq!(color_mode.dark(), Some(true));
+        assert!(!color_mode.apps_use_light_theme());
+        assert!(!color_mode.system_uses_light_theme());
+        assert!(color_mode.apps_use_light_theme.changed());
+
+        color_mode.set_dark(false);
+        assert!(!color_mode.apps_use_light_theme.changed());
+        assert!(!color_mode.system_uses_light_theme.changed());
+    }
+}
@@ -0,0 +1,463 @@
+//! Types to retrieve and change the Windows 11 taskbar's alignment, size, search box and auto-hiding ("Taskbar" in the Windows settings).
+//!
+//! Like with `NightLight`, you should read, mutate and write without delays in between. `Taskbar` instances expire after a short duration to enforce this.
+
+use futures::channel::oneshot;
+use serde_json::json;
+use std::{fmt, io, mem, time::Duration};
+use windows::{
+    core::w,
+    Win32::UI::Shell::{SHAppBarMessage, ABM_GETSTATE, ABM_SETSTATE, ABS_AUTOHIDE, APPBARDATA},
+};
+use winreg::enums::HKEY_CURRENT_USER;
+
+use super::broadcast_setting_change;
+use crate::{
+    data_conversion::{Strictness, TrackedValue},
+    expiry::Expiry,
+    reg::{
+        export_reg_values,
+        monitor::{MonitorLoopError, RegValueMonitor},
+        value::{read_reg_value_as, write_reg_value_as},
+        RegValuePath,
+    },
+};
+
+/// Where the taskbar buttons are.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TaskbarAlignment {
+    Left,
+    Center,
+}
+
+/// The height of the taskbar. Windows 11 ignores it since version 22H2.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TaskbarSize {
+    Small,
+    Medium,
+    Large,
+}
+
+/// How the search is shown on the taskbar.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SearchBoxMode {
+    Hidden,
+    Icon,
+    Box,
+    /// Only available since Windows 11 version 23H2.
+    IconAndLabel,
+}
+
+/// Identifies the registry values in [`Taskbar::monitor()`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TaskbarValueId {
+    Alignment,
+    Size,
+    SearchBoxMode,
+    /// The binary value in which Explorer stores the auto-hide state, among others.
+    AutoHide,
+}
+
+pub struct Taskbar {
+    alignment: TrackedValue<TaskbarAlignment>,
+    size: TrackedValue<TaskbarSize>,
+    search_box_mode: TrackedValue<SearchBoxMode>,
+    auto_hide: TrackedValue<bool>,
+    expiry: Expiry,
+}
+
+impl Taskbar {
+    const ADVANCED_SUBKEY_PATH: &'static str =
+        r"SOFTWARE\Microsoft\Windows\CurrentVersion\Explorer\Advanced";
+
+    pub const ALIGNMENT_REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: Self::ADVANCED_SUBKEY_PATH,
+        value_name: "TaskbarAl",
+    };
+    pub const SIZE_REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: Self::ADVANCED_SUBKEY_PATH,
+        value_name: "TaskbarSi",
+    };
+    pub const SEARCH_BOX_MODE_REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: r"SOFTWARE\Microsoft\Windows\CurrentVersion\Search",
+        value_name: "SearchboxTaskbarMode",
+    };
+    /// Written by Explorer. Auto-hiding is changed through the shell instead, which applies it immediately.
+    pub const AUTO_HIDE_REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: r"SOFTWARE\Microsoft\Windows\CurrentVersion\Explorer\StuckRects3",
+        value_name: "Settings",
+    };
+    /// The registry values that the taskbar settings consist of.
+    pub const REG_VALUE_PATHS: [RegValuePath<'static>; 4] = [
+        Self::ALIGNMENT_REG_VALUE_PATH,
+        Self::SIZE_REG_VALUE_PATH,
+        Self::SEARCH_BOX_MODE_REG_VALUE_PATH,
+        Self::AUTO_HIDE_REG_VALUE_PATH,
+    ];
+
+    /// Default duration after which an instance expires. See [`Self::set_expiration_timeout()`].
+    pub const EXPIRATION_TIMEOUT: Duration = Duration::from_millis(1000);
+
+    pub fn from_reg() -> Result<Self, self::Error> {
+        //! Creates a strict instance using [`Self::from_reg_with_strictness()`].
+
+        Self::from_reg_with_strictness(Strictness::Strict)
+    }
+
+    pub fn from_reg_lenient() -> Result<Self, self::Error> {
+        Self::from_reg_with_strictness(Strictness::Lenient)
+    }
+
+    pub fn from_reg_with_strictness(strictness: Strictness) -> Result<Self, self::Error> {
+        //! Missing registry values count as Windows 11's defaults. Unknown values are rejected in strict mode and count as the defaults in lenient mode.
+
+        let alignment = read_dword_as(
+            &Self::ALIGNMENT_REG_VALUE_PATH,
+            TaskbarAlignment::from_dword,
+            TaskbarAlignment::Center,
+            strictness,
+        )?;
+        let size = read_dword_as(
+            &Self::SIZE_REG_VALUE_PATH,
+            TaskbarSize::from_dword,
+            TaskbarSize::Medium,
+            strictness,
+        )?;
+        let search_box_mode = read_dword_as(
+            &Self::SEARCH_BOX_MODE_REG_VALUE_PATH,
+            SearchBoxMode::from_dword,
+            SearchBoxMode::Box,
+            strictness,
+        )?;
+
+        Ok(Self::new(
+            alignment,
+            size,
+            search_box_mode,
+            app_bar_state() & ABS_AUTOHIDE != 0,
+        ))
+    }
+
+    fn new(
+        alignment: TaskbarAlignment,
+        size: TaskbarSize,
+        search_box_mode: SearchBoxMode,
+        auto_hide: bool,
+    ) -> Self {
+        Self {
+            alignment: TrackedValue::new(alignment),
+            size: TrackedValue::new(size),
+            search_box_mode: TrackedValue::new(search_box_mode),
+            auto_hide: TrackedValue::new(auto_hide),
+            expiry: Expiry::new(Self::EXPIRATION_TIMEOUT),
+        }
+    }
+
+    pub fn export_reg<T: AsRef<std::path::Path>>(file_path: T) -> Result<(), io::Error> {
+        export_reg_values(&Self::REG_VALUE_PATHS, file_path)
+    }
+
+    pub fn alignment(&self) -> TaskbarAlignment {
+        *self.alignment
+    }
+
+    pub fn set_alignment(&mut self, alignment: TaskbarAlignment) {
+        self.alignment.set(alignment);
+    }
+
+    pub fn size(&self) -> TaskbarSize {
+        *self.size
+    }
+
+    pub fn set_size(&mut self, size: TaskbarSize) {
+        self.size.set(size);
+    }
+
+    pub fn search_box_mode(&self) -> SearchBoxMode {
+        *self.search_box_mode
+    }
+
+    pub fn set_search_box_mode(&mut self, search_box_mode: SearchBoxMode) {
+        self.search_box_mode.set(search_box_mode);
+    }
+
+    pub fn auto_hide(&self) -> bool {
+        *self.auto_hide
+    }
+
+    pub fn set_auto_hide(&mut self, auto_hide: bool) {
+        self.auto_hide.set(auto_hide);
+    }
+
+    pub fn set_expiration_timeout(&mut self, expiration_timeout: Duration) {
+        //! Changes the duration after loading after which the instance can't be written anymore, to avoid race conditions with other writers, like shell-replacement tools.
+
+        self.expiry.set_timeout(expiration_timeout);
+    }
+
+    pub fn time_left(&self) -> Duration {
+        //! The duration until the instance expires. Zero, if it already did.
+
+        self.expiry.time_left()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        //! Whether writing would fail with [`Error::Expired`].
+
+        self.expiry.is_expired()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&json!({
+            "alignment": self.alignment().name(),
+            "size": self.size().name(),
+            "searchBoxMode": self.search_box_mode().name(),
+            "autoHide": self.auto_hide(),
+        }))
+        .expect("serializing to JSON shouldn't fail")
+    }
+
+    pub fn write_to_reg(self) -> Result<(), self::Error> {
+        //! Writes the changed registry values and notifies running programs with a `WM_SETTINGCHANGE` message for `TraySettings`, on which Explorer and shell-replacement tools update the taskbar. Auto-hiding is changed through the shell.
+
+        if self.is_expired() {
+            return Err(Error::Expired);
+        }
+
+        let mut changed = false;
+        for (changed_dword, reg_value_path) in [
+            (
+                self.alignment
+                    .changed()
+                    .then(|| self.alignment().to_dword()),
+                Self::ALIGNMENT_REG_VALUE_PATH,
+            ),
+            (
+                self.size.changed().then(|| self.size().to_dword()),
+                Self::SIZE_REG_VALUE_PATH,
+            ),
+            (
+                self.search_box_mode
+                    .changed()
+                    .then(|| self.search_box_mode().to_dword()),
+                Self::SEARCH_BOX_MODE_REG_VALUE_PATH,
+            ),
+        ] {
+            if let Some(dword) = changed_dword {
+                write_reg_value_as(&reg_value_path, dword)?;
+                changed = true;
+            }
+        }
+
+        if changed {
+            broadcast_setting_change(w!("TraySettings"));
+        }
+
+        if self.auto_hide.changed() {
+            let state = app_bar_state() & !ABS_AUTOHIDE;
+            let mut app_bar_data = new_app_bar_data();
+            app_bar_data.lParam.0 = (state | if self.auto_hide() { ABS_AUTOHIDE } else { 0 }) as _;
+            unsafe { SHAppBarMessage(ABM_SETSTATE, &mut app_bar_data) };
+        }
+
+        Ok(())
+    }
+
+    pub fn monitor<F, T, E>(
+        stop_receiver: Option<oneshot::Receiver<T>>,
+        callback: F,
+    ) -> Result<T, MonitorLoopError<E>>
+    where
+        F: FnMut(TaskbarValueId) -> Option<Result<T, E>>,
+        T: Default,
+    {
+        //! Calls the callback with the ID of every changed registry value, e.g., so that shell-replacement tools can follow the settings. See [`crate::reg::monitor::RegValueMonitor::r#loop()`].
+
+        let mut monitor = RegValueMonitor::new([
+            (TaskbarValueId::Alignment, &Self::ALIGNMENT_REG_VALUE_PATH),
+            (TaskbarValueId::Size, &Self::SIZE_REG_VALUE_PATH),
+            (
+                TaskbarValueId::SearchBoxMode,
+                &Self::SEARCH_BOX_MODE_REG_VALUE_PATH,
+            ),
+            (TaskbarValueId::AutoHide, &Self::AUTO_HIDE_REG_VALUE_PATH),
+        ])?;
+
+        monitor.r#loop(stop_receiver, callback)
+    }
+}
+
+impl fmt::Display for Taskbar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Alignment: {}", self.alignment().name())?;
+        writeln!(f, "Size: {}", self.size().name())?;
+        writeln!(f, "Search: {}", self.search_box_mode().name())?;
+        write!(
+            f,
+            "Auto-hide: {}",
+            if self.auto_hide() { "on" } else { "off" }
+        )
+    }
+}
+
+impl TaskbarAlignment {
+    fn from_dword(dword: u32) -> Option<Self> {
+        match dword {
+            0 => Some(Self::Left),
+            1 => Some(Self::Center),
+            _ => None,
+        }
+    }
+
+    fn to_dword(self) -> u32 {
+        match self {
+            Self::Left => 0,
+            Self::Center => 1,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Left => "left",
+            Self::Center => "center",
+        }
+    }
+}
+
+impl TaskbarSize {
+    fn from_dword(dword: u32) -> Option<Self> {
+        match dword {
+            0 => Some(Self::Small),
+            1 => Some(Self::Medium),
+            2 => Some(Self::Large),
+            _ => None,
+        }
+    }
+
+    fn to_dword(self) -> u32 {
+        self as _
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Small => "small",
+            Self::Medium => "medium",
+            Self::Large => "large",
+        }
+    }
+}
+
+impl SearchBoxMode {
+    fn from_dword(dword: u32) -> Option<Self> {
+        match dword {
+            0 => Some(Self::Hidden),
+            1 => Some(Self::Icon),
+            2 => Some(Self::Box),
+            3 => Some(Self::IconAndLabel),
+            _ => None,
+        }
+    }
+
+    fn to_dword(self) -> u32 {
+        self as _
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Hidden => "hidden",
+            Self::Icon => "icon",
+            Self::Box => "box",
+            Self::IconAndLabel => "iconAndLabel",
+        }
+    }
+}
+
+fn read_dword_as<T>(
+    reg_value_path: &RegValuePath,
+    from_dword: fn(u32) -> Option<T>,
+    default: T,
+    strictness: Strictness,
+) -> Result<T, self::Error> {
+    let dword = match read_reg_value_as::<u32>(reg_value_path) {
+        Ok(dword) => dword,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(default),
+        Err(error) => return Err(error.into()),
+    };
+
+    match from_dword(dword) {
+        Some(value) => Ok(value),
+        None if strictness.is_lenient() => Ok(default),
+        None => Err(Error::InvalidValue {
+            value_name: reg_value_path.value_name.to_string(),
+            value: dword,
+        }),
+    }
+}
+
+fn new_app_bar_data() -> APPBARDATA {
+    APPBARDATA {
+        cbSize: mem::size_of::<APPBARDATA>() as _,
+        ..Default::default()
+    }
+}
+
+fn app_bar_state() -> u32 {
+    //! The `ABS_*` flags of the taskbar.
+
+    let mut app_bar_data = new_app_bar_data();
+    unsafe { SHAppBarMessage(ABM_GETSTATE, &mut app_bar_data) as _ }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+    /// A registry value contains a number without known meaning. Lenient mode uses the default instead.
+    #[error("registry value '{value_name}' has unknown value {value}")]
+    InvalidValue { value_name: String, value: u32 },
+    /// The object expired to enforce avoidance of race conditions.
+    #[error("object expired: duration between reading and writing was too long")]
+    Expired,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SearchBoxMode, Taskbar, TaskbarAlignment, TaskbarSize};
+
+    #[test]
+    fn dwords() {
+        for alignment in [TaskbarAlignment::Left, TaskbarAlignment::Center] {
+            assert_eq!(
+                TaskbarAlignment::from_dword(alignment.to_dword()),
+                Some(alignment)
+            );
+        }
+        for size in [TaskbarSize::Small, TaskbarSize::Medium, TaskbarSize::Large] {
+            assert_eq!(TaskbarSize::from_dword(size.to_dword()), Some(size));
+        }
+        for mode in [
+            SearchBoxMode::Hidden,
+            SearchBoxMode::Icon,
+            SearchBoxMode::Box,
+            SearchBoxMode::IconAndLabel,
+        ] {
+            assert_eq!(SearchBoxMode::from_dword(mode.to_dword()), Some(mode));
+        }
+        assert_eq!(SearchBoxMode::from_dword(4), None);
+
+        let mut taskbar = Taskbar::new(
+            TaskbarAlignment::Center,
+            TaskbarSize::Medium,
+            SearchBoxMode::Box,
+            false,
+        );
+        taskbar.set_alignment(TaskbarAlignment::Left);
+        assert!(taskbar.alignment.changed());
+        assert!(!taskbar.search_box_mode.changed());
+        assert!(taskbar.to_string().starts_with("Alignment: left"));
+    }
+}
@@ -1,46 +1,296 @@
 pub mod monitor;
+pub mod offline_hive;
+mod parallel;
+pub mod provider;
+pub mod reg_file;
+pub mod retry;
+pub mod sid;
+pub mod snapshot;
+pub mod value;
+pub mod value_set;
+pub mod wal;
+pub mod write_scheduler;
 
-use std::fmt::Write as FmtWrite;
-use std::io::Write as IoWrite;
 use std::{
-    fs::File,
+    collections::HashMap,
+    fs,
     io::{self},
-    mem::ManuallyDrop,
+    mem::{self, ManuallyDrop},
     path::Path,
+    ptr,
 };
+use windows::Win32::{
+    Security::{
+        GetTokenInformation, TokenElevationType, TokenElevationTypeLimited, TOKEN_ELEVATION_TYPE,
+        TOKEN_QUERY,
+    },
+    System::Threading::{GetCurrentProcess, OpenProcessToken},
+};
+use windows_helpers::ResGuard;
 use winreg::{
-    enums::{RegType, KEY_QUERY_VALUE, KEY_SET_VALUE},
+    enums::{RegType, HKEY_CURRENT_USER, HKEY_USERS, KEY_QUERY_VALUE, KEY_SET_VALUE},
     RegKey, RegValue, HKEY,
 };
 
+use self::{reg_file::RegFile, retry::with_retries, sid::ScopedSids, snapshot::RegSnapshot};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct RegValuePath<'a> {
     pub hkey: HKEY,
     pub subkey_path: &'a str,
     pub value_name: &'a str,
 }
 
+/// Owned variant of [`RegValuePath`], for paths constructed at runtime, e.g., per user SID.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct OwnedRegValuePath {
+    pub hkey: HKEY,
+    pub subkey_path: String,
+    pub value_name: String,
+}
+
+impl OwnedRegValuePath {
+    pub fn new<S: Into<String>, V: Into<String>>(
+        hkey: HKEY,
+        subkey_path: S,
+        value_name: V,
+    ) -> Self {
+        Self {
+            hkey,
+            subkey_path: subkey_path.into(),
+            value_name: value_name.into(),
+        }
+    }
+
+    pub fn join(mut self, subkey_path: &str) -> Self {
+        //! Appends the relative subkey path (one or more backslash-separated key names) to the subkey path.
+
+        if !self.subkey_path.is_empty() && !subkey_path.is_empty() {
+            self.subkey_path.push('\\');
+        }
+        self.subkey_path.push_str(subkey_path);
+        self
+    }
+
+    pub fn with_value_name<V: Into<String>>(mut self, value_name: V) -> Self {
+        self.value_name = value_name.into();
+        self
+    }
+
+    pub fn in_user_hive(&self, user_sid: &str) -> Self {
+        //! If the path is under `HKEY_CURRENT_USER`, returns the corresponding path under `HKEY_USERS\<user_sid>`, i.e., in the hive of the specified user. Other paths are returned unchanged.
+
+        if self.hkey == HKEY_CURRENT_USER {
+            Self::new(HKEY_USERS, user_sid, self.value_name.clone()).join(&self.subkey_path)
+        } else {
+            self.clone()
+        }
+    }
+
+    pub fn resolve_user_hives(&self, scoped_sids: &ScopedSids) -> Vec<(Option<String>, Self)> {
+        //! Resolves a path under `HKEY_CURRENT_USER`, which is a link, to the corresponding paths in the hives of the users in scope (see [`UserScope::resolve()`](sid::UserScope::resolve)), each with the user's SID. With [`UserScope::AllUsers`](sid::UserScope::AllUsers), only hives containing the path's key are included. Other paths are returned unchanged, without SID.
+
+        if self.hkey != HKEY_CURRENT_USER {
+            return vec![(None, self.clone())];
+        }
+
+        let users_key = RegKey::predef(HKEY_USERS);
+        scoped_sids
+            .sids
+            .iter()
+            .filter(|sid| {
+                !scoped_sids.skips_missing_keys
+                    || users_key
+                        .open_subkey_with_flags(
+                            format!(r"{sid}\{}", self.subkey_path),
+                            KEY_QUERY_VALUE,
+                        )
+                        .is_ok()
+            })
+            .map(|sid| (Some(sid.clone()), self.in_user_hive(sid)))
+            .collect()
+    }
+
+    pub fn as_borrowed(&self) -> RegValuePath<'_> {
+        RegValuePath {
+            hkey: self.hkey,
+            subkey_path: &self.subkey_path,
+            value_name: &self.value_name,
+        }
+    }
+}
+
+impl From<RegValuePath<'_>> for OwnedRegValuePath {
+    fn from(reg_value_path: RegValuePath<'_>) -> Self {
+        Self::new(
+            reg_value_path.hkey,
+            reg_value_path.subkey_path,
+            reg_value_path.value_name,
+        )
+    }
+}
+
+/// Implemented by borrowed and owned registry value paths, so that functions taking multiple paths can be used with both.
+pub trait AsRegValuePath {
+    fn as_reg_value_path(&self) -> RegValuePath<'_>;
+}
+
+impl AsRegValuePath for RegValuePath<'_> {
+    fn as_reg_value_path(&self) -> RegValuePath<'_> {
+        *self
+    }
+}
+
+impl AsRegValuePath for OwnedRegValuePath {
+    fn as_reg_value_path(&self) -> RegValuePath<'_> {
+        self.as_borrowed()
+    }
+}
+
+impl<P: AsRegValuePath + ?Sized> AsRegValuePath for &P {
+    fn as_reg_value_path(&self) -> RegValuePath<'_> {
+        (**self).as_reg_value_path()
+    }
+}
+
 pub fn read_reg_bin_value(reg_value_path: &RegValuePath) -> Result<Vec<u8>, io::Error> {
-    let key = RegKey::predef(reg_value_path.hkey)
-        .open_subkey_with_flags(reg_value_path.subkey_path, KEY_QUERY_VALUE)?;
-    let value = key.get_raw_value(reg_value_path.value_name)?;
+    let value = with_retries(|| {
+        RegKey::predef(reg_value_path.hkey)
+            .open_subkey_with_flags(reg_value_path.subkey_path, KEY_QUERY_VALUE)?
+            .get_raw_value(reg_value_path.value_name)
+    })?;
+
+    into_bin_bytes(value)
+}
+
+pub fn read_reg_values(reg_value_paths: &[RegValuePath]) -> Vec<Result<RegValue, io::Error>> {
+    //! Reads multiple values of any type in one pass, opening every distinct subkey only once. Returns the results in the order of the paths.
+
+    let mut keys = HashMap::<(HKEY, &str), Result<RegKey, io::Error>>::new();
+
+    reg_value_paths
+        .iter()
+        .map(|reg_value_path| {
+            let key = keys
+                .entry((reg_value_path.hkey, reg_value_path.subkey_path))
+                .or_insert_with(|| {
+                    with_retries(|| {
+                        RegKey::predef(reg_value_path.hkey)
+                            .open_subkey_with_flags(reg_value_path.subkey_path, KEY_QUERY_VALUE)
+                    })
+                });
+
+            match key {
+                Ok(key) => with_retries(|| key.get_raw_value(reg_value_path.value_name)),
+                // `io::Error` isn't `Clone`.
+                Err(error) => Err(match error.raw_os_error() {
+                    Some(code) => io::Error::from_raw_os_error(code),
+                    None => io::Error::from(error.kind()),
+                }),
+            }
+        })
+        .collect()
+}
+
+pub fn read_reg_bin_values(reg_value_paths: &[RegValuePath]) -> Vec<Result<Vec<u8>, io::Error>> {
+    //! Like [`read_reg_values()`], but fails for values that aren't binary, like [`read_reg_bin_value()`].
+
+    read_reg_values(reg_value_paths)
+        .into_iter()
+        .map(|result| into_bin_bytes(result?))
+        .collect()
+}
+
+fn into_bin_bytes(value: RegValue) -> Result<Vec<u8>, io::Error> {
+    //! Zero-length binary values are returned as they are, leaving it to the parsers to reject them.
 
     if value.vtype == RegType::REG_BINARY {
         Ok(value.bytes)
     } else {
-        Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "expected binary value",
-        ))
+        Err(WrongValueType {
+            expected: RegType::REG_BINARY,
+            found: value.vtype,
+            len: value.bytes.len(),
+        }
+        .into())
+    }
+}
+
+/// A registry value exists, but doesn't have the type a function reads. Returned inside an [`io::Error`] of the kind [`io::ErrorKind::InvalidData`], so that the reading functions can keep their error type. Retrieve it with [`Self::of()`].
+#[derive(thiserror::Error, Clone, PartialEq, Debug)]
+#[error(
+    "expected registry value of type {expected:?}, found {}{found:?}",
+    if *len == 0 { "empty " } else { "" }
+)]
+pub struct WrongValueType {
+    pub expected: RegType,
+    pub found: RegType,
+    /// The number of data bytes. An empty `REG_NONE` value is what remains when a value was created without data, e.g., by a tool failing halfway.
+    pub len: usize,
+}
+
+impl WrongValueType {
+    pub fn of(error: &io::Error) -> Option<&Self> {
+        error.get_ref()?.downcast_ref()
+    }
+
+    pub fn is_empty_placeholder(&self) -> bool {
+        //! Whether the value is an empty `REG_NONE` value, which doesn't hold any former data to recover.
+
+        self.found == RegType::REG_NONE && self.len == 0
+    }
+}
+
+impl From<WrongValueType> for io::Error {
+    fn from(value: WrongValueType) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, value)
     }
 }
 
+pub(crate) fn read_reg_value_bytes(reg_value_path: &RegValuePath) -> Result<Vec<u8>, io::Error> {
+    //! Reads the raw bytes of a registry value of any type.
+
+    with_retries(|| {
+        Ok(RegKey::predef(reg_value_path.hkey)
+            .open_subkey_with_flags(reg_value_path.subkey_path, KEY_QUERY_VALUE)?
+            .get_raw_value(reg_value_path.value_name)?
+            .bytes)
+    })
+}
+
 pub fn write_reg_bin_value(
     reg_value_path: &RegValuePath,
     bytes: &Vec<u8>,
 ) -> Result<(), io::Error> {
-    let key = RegKey::predef(reg_value_path.hkey)
-        .open_subkey_with_flags(reg_value_path.subkey_path, KEY_SET_VALUE)?;
+    //! Fails, if the subkey doesn't exist.
+
+    with_retries(|| {
+        let key = RegKey::predef(reg_value_path.hkey)
+            .open_subkey_with_flags(reg_value_path.subkey_path, KEY_SET_VALUE)?;
+        set_reg_bin_value(&key, reg_value_path.value_name, bytes)
+    })?;
+
+    monitor::stats::record_write(reg_value_path);
+    Ok(())
+}
+
+pub fn write_reg_bin_value_creating_key(
+    reg_value_path: &RegValuePath,
+    bytes: &Vec<u8>,
+) -> Result<(), io::Error> {
+    //! Like [`write_reg_bin_value()`], but creates the subkey including missing parent keys, if necessary.
+
+    with_retries(|| {
+        let (key, _) = RegKey::predef(reg_value_path.hkey)
+            .create_subkey_with_flags(reg_value_path.subkey_path, KEY_SET_VALUE)?;
+        set_reg_bin_value(&key, reg_value_path.value_name, bytes)
+    })?;
 
+    monitor::stats::record_write(reg_value_path);
+    Ok(())
+}
+
+fn set_reg_bin_value(key: &RegKey, value_name: &str, bytes: &Vec<u8>) -> Result<(), io::Error> {
     //TODO: See https://github.com/gentoo90/winreg-rs/issues/64 ("RegValue should contain Cow<[u8]>, not Vec<u8>").
     let unsafe_reg_value = ManuallyDrop::new(RegValue {
         vtype: RegType::REG_BINARY,
@@ -49,7 +299,7 @@ pub fn write_reg_bin_value(
     });
 
     // A panic would leak the reg value, but at least not cause a double-drop.
-    let result = key.set_raw_value(reg_value_path.value_name, &unsafe_reg_value);
+    let result = key.set_raw_value(value_name, &unsafe_reg_value);
 
     // Drop only parts in fact owned. Use `ManuallyDrop` like `Vec::into_raw_parts()`, which is available in nightly Rust (as of Nov. 2023).
     let RegValue { bytes, .. } = ManuallyDrop::into_inner(unsafe_reg_value);
@@ -59,65 +309,158 @@ pub fn write_reg_bin_value(
     Ok(())
 }
 
-pub(crate) fn export_reg_bin_values<T: AsRef<Path>>(
-    reg_value_paths: &[RegValuePath],
+pub(crate) fn export_reg_values<P: AsRegValuePath + Sync, T: AsRef<Path>>(
+    reg_value_paths: &[P],
     file_path: T,
 ) -> Result<(), io::Error> {
+    //! Writes the values of any type to a .reg file like `regedit.exe` does.
+    //!
     //! # Panics
     //! Panics in case of an unknown `HKEY`. As of Nov. 2023, there are 10 that the `winreg` crate re-exports.
 
-    let io_error = |_| io::Error::from(io::ErrorKind::Other);
+    RegFile::from_reg_values(reg_value_paths, false)?.export(file_path)
+}
+
+fn write_utf16_le_file<T: AsRef<Path>>(text: &str, file_path: T) -> Result<(), io::Error> {
+    //! Writes the text as UTF-16LE with BOM. This is how `regedit.exe` saves .reg files.
 
-    let mut text = String::with_capacity(2048);
+    //. Encoding into a buffer first lets the whole file be written at once.
+    let mut bytes = Vec::with_capacity((text.len() + 1) * 2);
+    for int16 in "\u{feff}".encode_utf16().chain(text.encode_utf16()) {
+        bytes.extend_from_slice(&int16.to_le_bytes());
+    }
 
-    text.push_str("\u{feff}Windows Registry Editor Version 5.00\r\n");
-    text.push_str("\r\n");
+    fs::write(file_path, bytes)
+}
 
-    for reg_value_path in reg_value_paths {
-        write!(
-            text,
-            "[{}\\{}]\r\n",
-            hkey_to_str(reg_value_path.hkey),
-            reg_value_path.subkey_path
-        )
-        .map_err(io_error)?;
+pub fn with_backup<P, T, F, R, E>(reg_value_paths: &[P], backup_file_path: T, f: F) -> Result<R, E>
+where
+    P: AsRegValuePath,
+    T: AsRef<Path>,
+    F: FnOnce() -> Result<R, E>,
+    E: From<io::Error>,
+{
+    //! Writes an undo .reg file of the values before calling the function that changes or deletes them. Importing the file restores the values, including deleting those that didn't exist before. If writing the file fails, the function isn't called.
+    //!
+    //! The file is made from a [`RegSnapshot`]. To restore the values automatically when the function fails instead, use [`snapshot::restore_on_error()`].
 
-        write!(text, "\"{}\"=hex:", reg_value_path.value_name).map_err(io_error)?;
+    RegSnapshot::take(reg_value_paths)?
+        .to_reg_file()
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))?
+        .export(backup_file_path)?;
+    f()
+}
+
+/// The kind of access to check with [`can_access()`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Access {
+    /// Reading the value, which must exist.
+    Read,
+    /// Writing the value, which may or may not exist.
+    Write,
+}
+
+/// The reason why [`can_access()`] determined a registry value to be inaccessible.
+#[derive(thiserror::Error, Debug)]
+pub enum AccessDenial {
+    /// The value's key doesn't exist.
+    #[error("registry key doesn't exist")]
+    KeyMissing,
+    /// The value doesn't exist, but should be read.
+    #[error("registry value doesn't exist")]
+    ValueMissing,
+    /// Access was denied, but the process runs with a limited token of an administrator. Running it elevated ("as administrator") may grant the access.
+    #[error("access denied; try running as administrator")]
+    NeedsElevation,
+    /// Access was denied by the key's security descriptor, and elevation wouldn't help.
+    #[error("access denied by registry key's permissions")]
+    AclDenies,
+    /// Another error occurred while checking.
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+}
+
+pub fn can_access(reg_value_path: &RegValuePath, desired: Access) -> Result<(), AccessDenial> {
+    //! Checks whether the value can be accessed as desired with the effective permissions of the process, without changing anything. Useful to print actionable messages before attempting writes.
+    //!
+    //! Note that the situation may change between the check and the actual access.
 
-        let mut first = true;
-        for byte in read_reg_bin_value(reg_value_path)? {
-            write!(text, "{}{:02x}", if first { "" } else { "," }, byte).map_err(io_error)?;
-            first = false;
+    let desired_flags = match desired {
+        Access::Read => KEY_QUERY_VALUE,
+        Access::Write => KEY_SET_VALUE,
+    };
+
+    let key = match RegKey::predef(reg_value_path.hkey)
+        .open_subkey_with_flags(reg_value_path.subkey_path, desired_flags)
+    {
+        Ok(key) => key,
+        Err(error) => {
+            return Err(match error.kind() {
+                io::ErrorKind::NotFound => AccessDenial::KeyMissing,
+                io::ErrorKind::PermissionDenied => {
+                    if has_limited_token().map_err(io::Error::from)? {
+                        AccessDenial::NeedsElevation
+                    } else {
+                        AccessDenial::AclDenies
+                    }
+                }
+                _ => error.into(),
+            })
         }
+    };
 
-        text.push_str("\r\n");
-        text.push_str("\r\n");
+    if desired == Access::Read {
+        match key.get_raw_value(reg_value_path.value_name) {
+            Ok(_) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Err(AccessDenial::ValueMissing)
+            }
+            Err(error) => return Err(error.into()),
+        }
     }
 
-    // Write file as UTF-16LE. BOM was added at the beginning. This is how `regedit.exe` saves .reg files.
-    let mut file = File::create(file_path)?;
-    for int16 in text.encode_utf16() {
-        file.write(&int16.to_le_bytes())?;
+    Ok(())
+}
+
+fn has_limited_token() -> Result<bool, windows::core::Error> {
+    //! Whether the process runs with the filtered token of an administrator, as is the case without elevation when UAC is enabled.
+
+    let process_token_handle = ResGuard::with_mut_acq_and_close_handle(|handle| unsafe {
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, handle)
+    })?;
+
+    let mut elevation_type = TOKEN_ELEVATION_TYPE(0);
+    let mut return_len = 0;
+    unsafe {
+        GetTokenInformation(
+            *process_token_handle,
+            TokenElevationType,
+            Some(ptr::addr_of_mut!(elevation_type).cast()),
+            mem::size_of::<TOKEN_ELEVATION_TYPE>() as _,
+            &mut return_len,
+        )?;
     }
 
-    Ok(())
+    Ok(elevation_type == TokenElevationTypeLimited)
 }
 
 pub(crate) fn delete_reg_value(reg_value_path: &RegValuePath) -> Result<(), io::Error> {
-    let key = RegKey::predef(reg_value_path.hkey)
-        .open_subkey_with_flags(reg_value_path.subkey_path, KEY_SET_VALUE)?;
-
-    key.delete_value(reg_value_path.value_name)
-        .or_else(|error| {
-            if error.kind() == io::ErrorKind::NotFound {
-                Ok(())
-            } else {
-                Err(error)
-            }
-        })
+    with_retries(|| {
+        let key = RegKey::predef(reg_value_path.hkey)
+            .open_subkey_with_flags(reg_value_path.subkey_path, KEY_SET_VALUE)?;
+
+        key.delete_value(reg_value_path.value_name)
+            .or_else(|error| {
+                if error.kind() == io::ErrorKind::NotFound {
+                    Ok(())
+                } else {
+                    Err(error)
+                }
+            })
+    })
 }
 
-const fn hkey_to_str(hkey: HKEY) -> &'static str {
+pub(crate) const fn hkey_to_str(hkey: HKEY) -> &'static str {
     use winreg::enums::*;
 
     match hkey {
@@ -134,3 +477,93 @@ const fn hkey_to_str(hkey: HKEY) -> &'static str {
         _ => panic!("unknown `HKEY`"),
     }
 }
+
+pub(crate) fn str_to_hkey(string: &str) -> Option<HKEY> {
+    //! The inverse of `hkey_to_str()`.
+
+    use winreg::enums::*;
+
+    Some(match string {
+        "HKEY_CLASSES_ROOT" => HKEY_CLASSES_ROOT,
+        "HKEY_CURRENT_USER" => HKEY_CURRENT_USER,
+        "HKEY_LOCAL_MACHINE" => HKEY_LOCAL_MACHINE,
+        "HKEY_USERS" => HKEY_USERS,
+        "HKEY_PERFORMANCE_DATA" => HKEY_PERFORMANCE_DATA,
+        "HKEY_PERFORMANCE_TEXT" => HKEY_PERFORMANCE_TEXT,
+        "HKEY_PERFORMANCE_NLSTEXT" => HKEY_PERFORMANCE_NLSTEXT,
+        "HKEY_CURRENT_CONFIG" => HKEY_CURRENT_CONFIG,
+        "HKEY_DYN_DATA" => HKEY_DYN_DATA,
+        "HKEY_CURRENT_USER_LOCAL_SETTINGS" => HKEY_CURRENT_USER_LOCAL_SETTINGS,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use winreg::{
+        enums::{RegType, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, HKEY_USERS},
+        RegValue,
+    };
+
+    use super::{into_bin_bytes, OwnedRegValuePath, RegValuePath, WrongValueType};
+
+    #[test]
+    fn owned_reg_value_path() {
+        let path = OwnedRegValuePath::new(HKEY_CURRENT_USER, "", "Data")
+            .join(r"SOFTWARE\Example")
+            .join("Sub");
+        assert_eq!(path.subkey_path, r"SOFTWARE\Example\Sub");
+
+        assert_eq!(
+            path.in_user_hive("S-1-5-18").as_borrowed(),
+            RegValuePath {
+                hkey: HKEY_USERS,
+                subkey_path: r"S-1-5-18\SOFTWARE\Example\Sub",
+                value_name: "Data",
+            }
+        );
+
+        let path = OwnedRegValuePath::from(RegValuePath {
+            hkey: HKEY_LOCAL_MACHINE,
+            subkey_path: "SOFTWARE",
+            value_name: "",
+        })
+        .with_value_name("Data");
+        assert_eq!(path.in_user_hive("S-1-5-18"), path);
+    }
+
+    #[test]
+    fn wrong_value_type() {
+        let error = into_bin_bytes(RegValue {
+            vtype: RegType::REG_NONE,
+            bytes: vec![],
+        })
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let wrong_value_type = WrongValueType::of(&error).unwrap();
+        assert!(wrong_value_type.is_empty_placeholder());
+        assert_eq!(
+            wrong_value_type.to_string(),
+            "expected registry value of type REG_BINARY, found empty REG_NONE"
+        );
+
+        let error = into_bin_bytes(RegValue {
+            vtype: RegType::REG_DWORD,
+            bytes: vec![0; 4],
+        })
+        .unwrap_err();
+        assert!(!WrongValueType::of(&error).unwrap().is_empty_placeholder());
+
+        assert_eq!(
+            into_bin_bytes(RegValue {
+                vtype: RegType::REG_BINARY,
+                bytes: vec![],
+            })
+            .unwrap(),
+            Vec::<u8>::new()
+        );
+        assert!(WrongValueType::of(&io::Error::from(io::ErrorKind::InvalidData)).is_none());
+    }
+}
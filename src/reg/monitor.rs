@@ -1,134 +1,367 @@
 use futures::{
     channel::oneshot,
-    select,
-    stream::{FusedStream, StreamExt},
+    future,
+    stream::{FusedStream, Stream, StreamExt},
     FutureExt,
 };
 use map_self::MapSelf;
 use serde::Deserialize;
-use std::{collections::HashMap, pin::Pin};
-use thiserror::Error;
-use windows::{
-    core::PWSTR,
-    Win32::{
-        Foundation::ERROR_INSUFFICIENT_BUFFER,
-        Security::{
-            Authorization::ConvertSidToStringSidW, GetTokenInformation, TokenUser,
-            SID_AND_ATTRIBUTES, TOKEN_QUERY,
-        },
-        System::Threading::{GetCurrentProcess, OpenProcessToken},
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io, mem,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
     },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread, ThreadId},
+    time::{Duration, Instant, SystemTime},
 };
-use windows_helpers::{dual_call, FirstCallExpectation, ResGuard};
-use winreg::enums::{HKEY_CURRENT_USER, HKEY_USERS};
+use thiserror::Error;
+use windows::{core::HRESULT, Win32::Foundation::E_FAIL};
 use wmi::{query::quote_and_escape_wql_str, COMLibrary, WMIConnection, WMIError, WMIResult};
 
-use super::{hkey_to_str, RegValuePath};
+pub mod journal;
+pub mod stats;
+
+use self::journal::ChangeJournal;
+use super::{
+    hkey_to_str, read_reg_value_bytes,
+    sid::{ScopedSids, UserScope},
+    AsRegValuePath, OwnedRegValuePath,
+};
 
 // Alternatively, a similar implementation could use `RegNotifyChangeKeyValue()`, which may be faster that WMI.
-/// Note that, on changes in very quick succession, reading a registry value after receiving a change event for it may yield newer data than from the write that triggered the event.
+/// Note that, on changes in very quick succession, reading a registry value after receiving a change event for it may yield newer data than from the write that triggered the event. Use [`Self::capture_bytes()`] to have the bytes read as soon as the event arrives, which narrows the window in which this can happen.
+///
+/// Implements [`Stream`], yielding the IDs of changed registry values, so that it can be combined with other event sources in async code. [`Self::events()`] streams whole events.
 pub struct RegValueMonitor<T: Copy> {
-    _wmi_con: WMIConnection,
-    ids_of_reg_value_changes: HashMap<RegValueChange, T>,
+    _context: MonitorContext,
+    watched_values_of_reg_value_changes: HashMap<RegValueChange, WatchedValue<T>>,
     event_stream: Pin<Box<dyn FusedStream<Item = WMIResult<RegValueChange>>>>,
+    captures_bytes: bool,
+    captures_previous_bytes: bool,
+    journal: Option<ChangeJournal>,
 }
 
 impl<T: Copy> RegValueMonitor<T> {
-    pub fn new<'a, I>(reg_value_paths: I) -> Result<Self, WMIError>
+    pub fn new<I, P>(reg_value_paths: I) -> Result<Self, WMIError>
+    where
+        I: IntoIterator<Item = (T, P)>,
+        P: AsRegValuePath,
+    {
+        Self::with_user_scope(reg_value_paths, UserScope::CurrentUser)
+    }
+
+    pub fn with_user_scope<I, P>(
+        reg_value_paths: I,
+        user_scope: UserScope,
+    ) -> Result<Self, WMIError>
     where
-        I: IntoIterator<Item = (T, &'a RegValuePath<'a>)>,
+        I: IntoIterator<Item = (T, P)>,
+        P: AsRegValuePath,
     {
-        let wmi_con = WMIConnection::new(COMLibrary::new()?)?;
+        //! Like [`Self::new()`], but lets you choose whose hives registry value paths under `HKEY_CURRENT_USER` refer to. Use [`Self::next_event()`] to learn which user a change belongs to.
 
-        let mut ids_of_reg_value_changes = HashMap::new();
-        let mut sid = None;
+        //. Resolved once, so that all paths refer to the same users.
+        let scoped_sids = user_scope.resolve().map_err(io_error_to_wmi_error)?;
+
+        Self::with_scoped_sids(reg_value_paths, &scoped_sids)
+    }
+
+    pub fn with_scoped_sids<I, P>(
+        reg_value_paths: I,
+        scoped_sids: &ScopedSids,
+    ) -> Result<Self, WMIError>
+    where
+        I: IntoIterator<Item = (T, P)>,
+        P: AsRegValuePath,
+    {
+        //! Like [`Self::with_user_scope()`], but with users already resolved, e.g., because the caller reads the values of the same users before watching them.
+
+        Self::with_context(
+            reg_value_paths,
+            scoped_sids,
+            &MonitorContext::for_current_thread()?,
+        )
+    }
+
+    pub fn with_context<I, P>(
+        reg_value_paths: I,
+        scoped_sids: &ScopedSids,
+        context: &MonitorContext,
+    ) -> Result<Self, WMIError>
+    where
+        I: IntoIterator<Item = (T, P)>,
+        P: AsRegValuePath,
+    {
+        //! Like [`Self::with_scoped_sids()`], but uses the WMI connection of the context instead of the one of the current thread.
+
+        let mut watched_values_of_reg_value_changes = HashMap::new();
 
         let mut query = String::from(r"SELECT * FROM RegistryValueChangeEvent WHERE");
 
         let mut first = true;
         for (id, reg_value_path) in reg_value_paths {
-            //TODO: See <https://github.com/ohadravid/wmi-rs/issues/86> ("Helper to resolve registry links"). Otherwise, offer `current_user_sid()` to `whoami` crate.
+            let reg_value_path = OwnedRegValuePath::from(reg_value_path.as_reg_value_path());
+
+            //TODO: See <https://github.com/ohadravid/wmi-rs/issues/86> ("Helper to resolve registry links").
             // Resolve links.
-            let (corrected_hkey, subkey_path_prefix) = match reg_value_path.hkey {
-                HKEY_CURRENT_USER => {
-                    if sid.is_none() {
-                        sid = Some(current_user_sid().map_err(|error| WMIError::HResultError {
-                            hres: error.code().0,
-                        })?);
-                    }
-                    (HKEY_USERS, sid.as_ref())
-                }
-                // (`HKEY_CLASSES_ROOT` links to `HKEY_LOCAL_MACHINE\SOFTWARE\Classes` as well as `HKEY_CURRENT_USER\SOFTWARE\Classes` in a merging way, which is why it can't be resolved here.)
-                hkey => (hkey, None),
-            };
+            // (`HKEY_CLASSES_ROOT` links to `HKEY_LOCAL_MACHINE\SOFTWARE\Classes` as well as `HKEY_CURRENT_USER\SOFTWARE\Classes` in a merging way, which is why it can't be resolved here.)
+            // (With `UserScope::AllUsers`, only hives in which the key exists are watched. WMI would reject the query otherwise.)
+            let resolved_paths = reg_value_path.resolve_user_hives(scoped_sids);
 
-            // Make proper path.
-            let expected_reg_value_change = RegValueChange {
-                hive: hkey_to_str(corrected_hkey).to_string(),
-                key_path: if let Some(prefix) = subkey_path_prefix {
-                    prefix.to_string() + r"\" + reg_value_path.subkey_path
-                } else {
-                    reg_value_path.subkey_path.to_string()
-                },
-                value_name: reg_value_path.value_name.to_string(),
-            };
+            for (sid, corrected_reg_value_path) in resolved_paths {
+                let expected_reg_value_change = RegValueChange {
+                    hive: hkey_to_str(corrected_reg_value_path.hkey).to_string(),
+                    key_path: corrected_reg_value_path.subkey_path.clone(),
+                    value_name: corrected_reg_value_path.value_name.clone(),
+                };
 
-            // Build query.
-            // (Parentheses aren't necessary: "When more than one logical operator is used in a statement, the OR operators are evaluated after the AND operators." [https://learn.microsoft.com/en-us/windows/win32/wmisdk/wql-sql-for-wmi])
-            if !first {
-                query.push_str(r" OR");
-            }
+                // Build query.
+                // (Parentheses aren't necessary: "When more than one logical operator is used in a statement, the OR operators are evaluated after the AND operators." [https://learn.microsoft.com/en-us/windows/win32/wmisdk/wql-sql-for-wmi])
+                if !first {
+                    query.push_str(r" OR");
+                }
 
-            query.push_str(r" Hive=");
-            query.push_str(&quote_and_escape_wql_str(&expected_reg_value_change.hive));
+                query.push_str(r" Hive=");
+                query.push_str(&quote_and_escape_wql_str(&expected_reg_value_change.hive));
 
-            query.push_str(r" AND KeyPath=");
-            query.push_str(&quote_and_escape_wql_str(
-                &expected_reg_value_change.key_path,
-            ));
+                query.push_str(r" AND KeyPath=");
+                query.push_str(&quote_and_escape_wql_str(
+                    &expected_reg_value_change.key_path,
+                ));
 
-            query.push_str(r" AND ValueName=");
-            query.push_str(&quote_and_escape_wql_str(
-                &expected_reg_value_change.value_name,
-            ));
+                query.push_str(r" AND ValueName=");
+                query.push_str(&quote_and_escape_wql_str(
+                    &expected_reg_value_change.value_name,
+                ));
 
-            // Build `HashMap` to associate events with registry value IDs from user.
-            ids_of_reg_value_changes.insert(expected_reg_value_change, id);
+                // Build `HashMap` to associate events with registry value IDs from user.
+                watched_values_of_reg_value_changes.insert(
+                    expected_reg_value_change,
+                    WatchedValue {
+                        id,
+                        user_sid: sid,
+                        requested_reg_value_path: reg_value_path.clone(),
+                        exists: read_reg_value_bytes(&corrected_reg_value_path.as_borrowed())
+                            .is_ok(),
+                        reg_value_path: corrected_reg_value_path,
+                        previous_bytes: None,
+                    },
+                );
 
-            first = false;
+                first = false;
+            }
+        }
+
+        if first {
+            // No user hive contained any of the keys.
+            return Err(WMIError::ResultEmpty);
         }
 
         let event_stream = Box::pin(
-            wmi_con
+            context
+                .wmi_con
                 .async_raw_notification::<RegValueChange>(query)?
                 .fuse(),
         );
 
         Ok(Self {
-            _wmi_con: wmi_con,
-            ids_of_reg_value_changes,
+            _context: context.clone(),
+            watched_values_of_reg_value_changes,
             event_stream,
+            captures_bytes: false,
+            captures_previous_bytes: false,
+            journal: None,
         })
     }
 
-    pub async fn next_change(&mut self) -> Option<Result<T, WMIError>> {
+    pub fn capture_bytes(mut self) -> Self {
+        //! Makes the monitor read the changed registry value's bytes immediately on receiving an event, so that they're available in [`RegValueEvent::bytes`].
+
+        self.captures_bytes = true;
+        self
+    }
+
+    pub fn capture_previous_bytes(mut self) -> Self {
+        //! Like [`Self::capture_bytes()`], but additionally keeps the bytes of every event, so that the next event of the same registry value also has them in [`RegValueEvent::previous_bytes`]. The current bytes of the watched values are read now, to know the previous bytes of the first changes. This saves consumers that diff changes from reading and caching the bytes themselves.
+
+        for watched_value in self.watched_values_of_reg_value_changes.values_mut() {
+            watched_value.previous_bytes =
+                read_reg_value_bytes(&watched_value.reg_value_path.as_borrowed()).ok();
+        }
+
+        self.captures_previous_bytes = true;
+        self.capture_bytes()
+    }
+
+    pub fn with_journal(mut self, journal: ChangeJournal) -> Self {
+        //! Makes the monitor record every event in the journal, including the bytes before and after the change. Implies [`Self::capture_previous_bytes()`].
+        //!
+        //! Failures to write to the journal are ignored, so that they don't disrupt monitoring.
+
+        self.journal = Some(journal);
+        self.capture_previous_bytes()
+    }
+
+    pub async fn next_event(&mut self) -> Option<Result<RegValueEvent<T>, WMIError>> {
+        //! Like [`Self::next_change()`], but also tells whether the registry value was created, modified or deleted, which user's hive the change happened in, when the event was received and, if activated, the bytes of the registry value.
+
+        future::poll_fn(|cx| self.poll_next_event(cx)).await
+    }
+
+    pub fn events(&mut self) -> EventStream<'_, T> {
+        //! Returns a [`Stream`] of whole events, like [`Self::next_event()`] yields them, while the monitor itself streams only the IDs. Any executor can drive it; there's no dedicated thread. Since WMI connections can't be sent to other threads, the monitor and the stream aren't [`Send`], so, with Tokio, drive them with `block_on()` or in a `LocalSet`, not with `tokio::spawn()`.
+        //!
+        //! # Examples
+        //! ```ignore
+        //! let mut events = monitor.events();
+        //! while let Some(event) = events.next().await {
+        //!     let event = event?;
+        //!     println!("{:?} {:?}", event.id, event.kind);
+        //! }
+        //! ```
+
+        EventStream { monitor: self }
+    }
+
+    fn poll_next_event(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<RegValueEvent<T>, WMIError>>> {
+        self.poll_next_timed_event(cx)
+            .map(|option| option.map(|result| result.map(|(event, _)| event)))
+    }
+
+    fn poll_next_timed_event(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<TimedEvent<T>, WMIError>>> {
+        //! Like [`Self::poll_next_event()`], but also returns when the event was received, for the statistics.
+
         loop {
-            break match self.event_stream.next().await {
-                Some(result) => Some(match result {
-                    Ok(changed_value) => {
-                        Ok(match self.ids_of_reg_value_changes.get(&changed_value) {
-                            Some(id) => *id,
-                            // Skip unrelated nonsense, which shouldn't actually happen.
-                            None => continue,
-                        })
+            break match self.event_stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(changed_value))) => {
+                    match self
+                        .watched_values_of_reg_value_changes
+                        .get_mut(&changed_value)
+                    {
+                        Some(watched_value) => {
+                            let received = Instant::now();
+                            let time = SystemTime::now();
+                            let read_result =
+                                read_reg_value_bytes(&watched_value.reg_value_path.as_borrowed());
+
+                            // Other errors, like missing access rights, don't tell anything about the existence.
+                            let exists = match &read_result {
+                                Ok(_) => true,
+                                Err(error) if error.kind() == io::ErrorKind::NotFound => false,
+                                Err(_) => watched_value.exists,
+                            };
+                            let kind = match (watched_value.exists, exists) {
+                                (false, true) => RegValueEventKind::Created,
+                                (true, false) => RegValueEventKind::Deleted,
+                                _ => RegValueEventKind::Modified,
+                            };
+                            watched_value.exists = exists;
+
+                            let bytes = if self.captures_bytes {
+                                read_result.ok()
+                            } else {
+                                None
+                            };
+                            let previous_bytes = if self.captures_previous_bytes {
+                                mem::replace(&mut watched_value.previous_bytes, bytes.clone())
+                            } else {
+                                None
+                            };
+
+                            let event = RegValueEvent {
+                                id: watched_value.id,
+                                kind,
+                                user_sid: watched_value.user_sid.clone(),
+                                reg_value_path: watched_value.reg_value_path.clone(),
+                                time,
+                                bytes,
+                                previous_bytes,
+                            };
+
+                            if let Some(journal) = &self.journal {
+                                let _ = journal.record(&event);
+                            }
+
+                            stats::record_event(
+                                [
+                                    &watched_value.reg_value_path,
+                                    &watched_value.requested_reg_value_path,
+                                ],
+                                received,
+                            );
+
+                            Poll::Ready(Some(Ok((event, received))))
+                        }
+                        // Skip unrelated nonsense, which shouldn't actually happen.
+                        None => continue,
                     }
-                    Err(error) => Err(error),
-                }),
-                None => None,
+                }
+                Poll::Ready(Some(Err(error))) => Poll::Ready(Some(Err(error))),
+                Poll::Ready(None) => Poll::Ready(None),
+                Poll::Pending => Poll::Pending,
             };
         }
     }
 
+    pub async fn next_change(&mut self) -> Option<Result<T, WMIError>> {
+        self.next().await
+    }
+
+    pub fn next_event_blocking(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Option<Result<RegValueEvent<T>, WMIError>> {
+        //! Like [`Self::next_event()`], but blocks the current thread instead of requiring an executor. Returns `None` when the timeout elapsed without an event. `None` as the timeout waits indefinitely.
+
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            match self.poll_next_event(&mut cx) {
+                Poll::Ready(option) => break option,
+                // (Spurious wake-ups are handled by polling again.)
+                Poll::Pending => match deadline {
+                    Some(deadline) => {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        if remaining.is_zero() {
+                            break None;
+                        }
+                        thread::park_timeout(remaining);
+                    }
+                    None => thread::park(),
+                },
+            }
+        }
+    }
+
+    pub fn iter_changes(&mut self, timeout: Option<Duration>) -> ChangeIter<'_, T> {
+        //! Returns a blocking iterator over the IDs of changed registry values, for simple console tools and tests that don't use async code. The iterator ends when the timeout elapses without a change (or when the event stream ends, which normally doesn't happen).
+        //!
+        //! # Examples
+        //! ```ignore
+        //! for change in monitor.iter_changes(Some(Duration::from_secs(10))) {
+        //!     println!("{:?}", change?);
+        //! }
+        //! ```
+
+        ChangeIter {
+            monitor: self,
+            timeout,
+        }
+    }
+
     pub fn r#loop<F, U, E>(
         &mut self,
         stop_receiver: Option<oneshot::Receiver<U>>,
@@ -171,36 +404,349 @@ impl<T: Copy> RegValueMonitor<T> {
         //! }
         //! ```
 
-        //. With no receiver, make one, so the loop works.
+        self.loop_events(stop_receiver, |event| callback(event.id))
+    }
+
+    pub fn loop_events<F, U, E>(
+        &mut self,
+        stop_receiver: Option<oneshot::Receiver<U>>,
+        callback: F,
+    ) -> Result<U, MonitorLoopError<E>>
+    where
+        F: FnMut(RegValueEvent<T>) -> Option<Result<U, E>>,
+        U: Default,
+    {
+        //! Like [`Self::r#loop()`], but passes the callback the whole event, e.g., with the bytes before and after the change, if [`Self::capture_previous_bytes()`] was used.
+
         let (_stop_sender, mut stop_receiver) = if let Some(orig_receiver) = stop_receiver {
             (None, orig_receiver)
         } else {
             oneshot::channel().map_self(|(sender, receiver)| (Some(sender), receiver))
         };
 
-        futures::executor::block_on(async {
-            loop {
-                select! {
-                    change_event = self.next_change().fuse() => {
-                        match change_event {
-                            // New change.
-                            Some(Ok(id)) => if let Some(result) = callback(id) {
-                                result.map_err(|err_value| MonitorLoopError::Other(err_value))?;
-                            },
-                            // Stream error.
-                            Some(Err(error)) => break Err(MonitorLoopError::WmiError(error)),
-                            // Stream should never be exhausted: "The `notification` method returns an iterator that waits for any incoming events resulting from the provided query. Loops reading from this iterator will not end until they are broken." (https://docs.rs/wmi/latest/wmi/#subscribing-to-event-notifications)
-                            None => unreachable!(),
-                        }
-                    },
-                    // User desires to stop loop.
-                    value = stop_receiver => break Ok(value.unwrap_or_default()),
+        self.loop_parked(
+            |cx| stop_receiver.poll_unpin(cx).map(Result::unwrap_or_default),
+            None,
+            callback,
+        )
+    }
+
+    pub fn loop_debounced<F, U, E>(
+        &mut self,
+        stop_receiver: Option<oneshot::Receiver<U>>,
+        debounce: Duration,
+        callback: F,
+    ) -> Result<U, MonitorLoopError<E>>
+    where
+        F: FnMut(RegValueEvent<T>) -> Option<Result<U, E>>,
+        U: Default,
+    {
+        //! Like [`Self::loop_events()`], but waits for the debounce duration after an event and discards the events that arrived meanwhile, so that the callback is called once per burst of writes, e.g., by another program. The callback gets the first event of the burst and should read the current values itself.
+
+        let (_stop_sender, mut stop_receiver) = if let Some(orig_receiver) = stop_receiver {
+            (None, orig_receiver)
+        } else {
+            oneshot::channel().map_self(|(sender, receiver)| (Some(sender), receiver))
+        };
+
+        self.loop_parked(
+            |cx| stop_receiver.poll_unpin(cx).map(Result::unwrap_or_default),
+            Some(debounce),
+            callback,
+        )
+    }
+
+    pub fn loop_blocking<F, U, E>(
+        &mut self,
+        stop_signal: Option<&StopSignal>,
+        mut callback: F,
+    ) -> Result<U, MonitorLoopError<E>>
+    where
+        F: FnMut(T) -> Option<Result<U, E>>,
+        U: Default,
+    {
+        //! Like [`Self::r#loop()`], but stopped with a [`StopSignal`], which doesn't involve futures. Meant for monitors running for the whole lifetime of a background process, where the thread should just sleep until a change or the stop signal arrives.
+
+        let Some(stop_signal) = stop_signal else {
+            return self.loop_parked(|_| Poll::Pending, None, |event| callback(event.id));
+        };
+
+        let _registration = stop_signal.register_current_thread();
+        self.loop_parked(
+            |_| {
+                if stop_signal.is_stopped() {
+                    Poll::Ready(U::default())
+                } else {
+                    Poll::Pending
                 }
+            },
+            None,
+            |event| callback(event.id),
+        )
+    }
+
+    fn loop_parked<S, F, U, E>(
+        &mut self,
+        mut poll_stop: S,
+        debounce: Option<Duration>,
+        mut callback: F,
+    ) -> Result<U, MonitorLoopError<E>>
+    where
+        S: FnMut(&mut Context<'_>) -> Poll<U>,
+        F: FnMut(RegValueEvent<T>) -> Option<Result<U, E>>,
+    {
+        //! Polls the stop condition and the event stream with a waker unparking the current thread, and parks it while neither is ready. Avoids an executor and rebuilding `select!` futures per event.
+
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            // User desires to stop loop.
+            if let Poll::Ready(value) = poll_stop(&mut cx) {
+                break Ok(value);
             }
+
+            match self.poll_next_timed_event(&mut cx) {
+                // New change.
+                Poll::Ready(Some(Ok((event, received)))) => {
+                    if let Some(debounce) = debounce {
+                        thread::sleep(debounce);
+                        while let Poll::Ready(Some(Ok(_))) = self.poll_next_timed_event(&mut cx) {}
+                    }
+
+                    let called = Instant::now();
+                    let callback_result = callback(event);
+                    stats::record_callback(received, called);
+
+                    if let Some(result) = callback_result {
+                        result.map_err(|err_value| MonitorLoopError::Other(err_value))?;
+                    }
+                }
+                // Stream error.
+                Poll::Ready(Some(Err(error))) => break Err(MonitorLoopError::WmiError(error)),
+                // Stream should never be exhausted: "The `notification` method returns an iterator that waits for any incoming events resulting from the provided query. Loops reading from this iterator will not end until they are broken." (https://docs.rs/wmi/latest/wmi/#subscribing-to-event-notifications)
+                Poll::Ready(None) => unreachable!(),
+                // (Spurious wake-ups are handled by polling again.)
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+}
+
+impl<T: Copy> Stream for RegValueMonitor<T> {
+    type Item = Result<T, WMIError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut()
+            .poll_next_event(cx)
+            .map(|option| option.map(|result| result.map(|event| event.id)))
+    }
+}
+
+impl<T: Copy> FusedStream for RegValueMonitor<T> {
+    fn is_terminated(&self) -> bool {
+        self.event_stream.is_terminated()
+    }
+}
+
+// The event stream is already pinned on the heap, and nothing else is structurally pinned.
+impl<T: Copy> Unpin for RegValueMonitor<T> {}
+
+/// A COM library initialization and WMI connection, shared by the monitors created with it instead of each one opening its own WMI session. WMI connections can't be sent to other threads.
+#[derive(Clone)]
+pub struct MonitorContext {
+    wmi_con: WMIConnection,
+}
+
+impl MonitorContext {
+    pub fn new() -> Result<Self, WMIError> {
+        Ok(Self {
+            wmi_con: WMIConnection::new(COMLibrary::new()?)?,
+        })
+    }
+
+    pub fn for_current_thread() -> Result<Self, WMIError> {
+        //! Returns the context of the current thread, creating it on the first call. [`RegValueMonitor::new()`] and [`RegValueMonitor::with_user_scope()`] use it, so that monitors of several setting groups on the same thread share one connection. It's kept until the thread ends.
+
+        thread_local! {
+            static CONTEXT: RefCell<Option<MonitorContext>> = const { RefCell::new(None) };
+        }
+
+        CONTEXT.with(|context| {
+            let mut context = context.borrow_mut();
+            if let Some(context) = &*context {
+                return Ok(context.clone());
+            }
+
+            let new_context = Self::new()?;
+            *context = Some(new_context.clone());
+            Ok(new_context)
         })
     }
 }
 
+/// Stops [`RegValueMonitor::loop_blocking()`] from any thread. Clones refer to the same signal, which can stop multiple loops. Once stopped, it stays stopped.
+#[derive(Clone, Default)]
+pub struct StopSignal {
+    inner: Arc<StopSignalInner>,
+}
+
+#[derive(Default)]
+struct StopSignalInner {
+    stopped: AtomicBool,
+    /// The threads of the loops waiting for the signal.
+    waiting_threads: Mutex<Vec<Thread>>,
+}
+
+impl StopSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stop(&self) {
+        self.inner.stopped.store(true, Ordering::SeqCst);
+        for thread in self.inner.waiting_threads.lock().unwrap().iter() {
+            thread.unpark();
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stopped.load(Ordering::SeqCst)
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        //! Blocks the thread until the signal is stopped or the timeout elapsed. Returns whether the signal is stopped.
+
+        let deadline = Instant::now() + timeout;
+        let _registration = self.register_current_thread();
+
+        while !self.is_stopped() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            thread::park_timeout(remaining);
+        }
+
+        true
+    }
+
+    fn register_current_thread(&self) -> StopSignalRegistration<'_> {
+        //! The thread is unparked on [`Self::stop()`] until the returned value is dropped.
+
+        let thread = thread::current();
+        let thread_id = thread.id();
+        self.inner.waiting_threads.lock().unwrap().push(thread);
+
+        StopSignalRegistration {
+            stop_signal: self,
+            thread_id,
+        }
+    }
+}
+
+struct StopSignalRegistration<'a> {
+    stop_signal: &'a StopSignal,
+    thread_id: ThreadId,
+}
+
+impl Drop for StopSignalRegistration<'_> {
+    fn drop(&mut self) {
+        let mut waiting_threads = self.stop_signal.inner.waiting_threads.lock().unwrap();
+        if let Some(index) = waiting_threads
+            .iter()
+            .position(|thread| thread.id() == self.thread_id)
+        {
+            waiting_threads.swap_remove(index);
+        }
+    }
+}
+
+/// Returned by [`RegValueMonitor::events()`].
+pub struct EventStream<'a, T: Copy> {
+    monitor: &'a mut RegValueMonitor<T>,
+}
+
+impl<T: Copy> Stream for EventStream<'_, T> {
+    type Item = Result<RegValueEvent<T>, WMIError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().monitor.poll_next_event(cx)
+    }
+}
+
+impl<T: Copy> FusedStream for EventStream<'_, T> {
+    fn is_terminated(&self) -> bool {
+        self.monitor.is_terminated()
+    }
+}
+
+/// Returned by [`RegValueMonitor::iter_changes()`].
+pub struct ChangeIter<'a, T: Copy> {
+    monitor: &'a mut RegValueMonitor<T>,
+    timeout: Option<Duration>,
+}
+
+impl<T: Copy> Iterator for ChangeIter<'_, T> {
+    type Item = Result<T, WMIError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.monitor
+            .next_event_blocking(self.timeout)
+            .map(|result| result.map(|event| event.id))
+    }
+}
+
+/// Wakes a thread parked in [`RegValueMonitor::next_event_blocking()`] or a monitor loop.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// A change of a watched registry value.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RegValueEvent<T> {
+    /// The ID the user associated with the registry value path.
+    pub id: T,
+    pub kind: RegValueEventKind,
+    /// The string SID of the user in whose hive the registry value changed. `None`, if the registry value path isn't under `HKEY_CURRENT_USER`.
+    pub user_sid: Option<String>,
+    /// The path of the changed registry value. Paths under `HKEY_CURRENT_USER` are resolved to the user's hive under `HKEY_USERS`.
+    pub reg_value_path: OwnedRegValuePath,
+    /// When the event was received (not when the registry value was written, which can't be retrieved).
+    pub time: SystemTime,
+    /// The bytes of the registry value, read on receiving the event, if [`RegValueMonitor::capture_bytes()`] was used. `None` also if the registry value couldn't be read, e.g., because it was deleted.
+    pub bytes: Option<Vec<u8>>,
+    /// The bytes of the previous event of the registry value (or from the creation of the monitor), if [`RegValueMonitor::capture_previous_bytes()`] was used. `None` also if the registry value couldn't be read back then.
+    pub previous_bytes: Option<Vec<u8>>,
+}
+
+/// An event together with when it was received.
+type TimedEvent<T> = (RegValueEvent<T>, Instant);
+
+/// What happened to a watched registry value. Determined by whether the registry value exists on receiving the event compared to the previous event (or the creation of the monitor), so that changes in very quick succession may be merged into one kind.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RegValueEventKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+struct WatchedValue<T> {
+    id: T,
+    user_sid: Option<String>,
+    /// The path as passed to the monitor, before resolving `HKEY_CURRENT_USER`, to match writes using it in the [`stats`]. With [`UserScope::AllUsers`], it's shared by the watched values of all users.
+    requested_reg_value_path: OwnedRegValuePath,
+    /// Whether the registry value existed on the last event or, before that, on creating the monitor.
+    exists: bool,
+    reg_value_path: OwnedRegValuePath,
+    /// The bytes on the last event or, before that, on activating [`RegValueMonitor::capture_previous_bytes()`].
+    previous_bytes: Option<Vec<u8>>,
+}
+
 #[derive(Deserialize, PartialEq, Eq, Hash, Debug)]
 #[serde(rename = "RegistryValueChangeEvent")]
 #[serde(rename_all = "PascalCase")]
@@ -218,39 +764,62 @@ pub enum MonitorLoopError<T> {
     Other(T),
 }
 
-fn current_user_sid() -> Result<String, windows::core::Error> {
-    let process_token_handle = ResGuard::with_mut_acq_and_close_handle(|handle| unsafe {
-        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, handle)
-    })?;
-
-    let mut sid_and_attrs_buffer = Vec::<u8>::new();
-    let mut sid_and_attrs_buffer_size = 0;
-
-    dual_call(
-        FirstCallExpectation::Win32Error(ERROR_INSUFFICIENT_BUFFER),
-        |getting_buffer_size| unsafe {
-            GetTokenInformation(
-                *process_token_handle,
-                TokenUser,
-                (!getting_buffer_size).then(|| {
-                    sid_and_attrs_buffer.resize(sid_and_attrs_buffer_size as _, 0);
-                    sid_and_attrs_buffer.as_mut_ptr().cast()
-                }),
-                sid_and_attrs_buffer_size,
-                &mut sid_and_attrs_buffer_size,
-            )
-        },
-    )?;
-
-    let string_sid = unsafe {
-        ResGuard::<PWSTR>::with_mut_acq_and_local_free(|pwstr| {
-            ConvertSidToStringSidW(
-                (&*sid_and_attrs_buffer.as_ptr().cast::<SID_AND_ATTRIBUTES>()).Sid,
-                pwstr,
-            )
-        })?
-        .to_string()?
+fn io_error_to_wmi_error(error: io::Error) -> WMIError {
+    WMIError::HResultError {
+        hres: error
+            .raw_os_error()
+            .map_or(E_FAIL.0, |code| HRESULT::from_win32(code as _).0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
     };
 
-    Ok(string_sid)
+    use super::StopSignal;
+
+    #[test]
+    fn stop_signal_unparks() {
+        let stop_signal = StopSignal::new();
+
+        let join_handle = thread::spawn({
+            let stop_signal = stop_signal.clone();
+            move || {
+                let _registration = stop_signal.register_current_thread();
+                while !stop_signal.is_stopped() {
+                    thread::park();
+                }
+            }
+        });
+
+        thread::sleep(Duration::from_millis(20));
+        stop_signal.stop();
+        join_handle.join().unwrap();
+
+        assert!(stop_signal.is_stopped());
+        assert!(stop_signal.inner.waiting_threads.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn stop_signal_wait_timeout() {
+        let stop_signal = StopSignal::new();
+
+        let start_instant = Instant::now();
+        assert!(!stop_signal.wait_timeout(Duration::from_millis(20)));
+        assert!(start_instant.elapsed() >= Duration::from_millis(20));
+
+        let join_handle = thread::spawn({
+            let stop_signal = stop_signal.clone();
+            move || stop_signal.wait_timeout(Duration::from_secs(60))
+        });
+
+        thread::sleep(Duration::from_millis(20));
+        stop_signal.stop();
+        assert!(join_handle.join().unwrap());
+        assert!(start_instant.elapsed() < Duration::from_secs(10));
+        assert!(stop_signal.wait_timeout(Duration::from_secs(60)));
+    }
 }
@@ -0,0 +1,271 @@
+//! A persistent journal of registry value changes, to investigate intermittent problems like corrupted settings after the fact.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::RegValueEvent;
+use crate::data_conversion::hex_bytes::opt_hex_str_serde;
+use crate::reg::{hkey_to_str, AsRegValuePath, OwnedRegValuePath};
+
+/// Appends registry value changes to a JSONL file (one JSON object per line). Attach it to a monitor with [`super::RegValueMonitor::with_journal()`], or record events manually.
+pub struct ChangeJournal {
+    file_path: PathBuf,
+    retention: Retention,
+}
+
+/// Limits for the entries kept in the journal file. Entries violating one of the limits are removed when opening the journal and when calling [`ChangeJournal::prune()`].
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Retention {
+    /// Maximum age of entries.
+    pub max_age: Option<Duration>,
+    /// Maximum number of entries, keeping the newest.
+    pub max_entries: Option<usize>,
+}
+
+/// A recorded change of a registry value.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    /// The full path of the registry value's key, starting with the root key name, like in .reg files.
+    pub key_path: String,
+    pub value_name: String,
+    /// The string SID of the user in whose hive the change happened, if applicable.
+    pub user_sid: Option<String>,
+    /// Milliseconds since the Unix epoch when the event was received.
+    pub epoch_millis: u64,
+    /// The bytes before the change. `None` if the value didn't exist or they're unknown.
+    #[serde(with = "opt_hex_str_serde")]
+    pub old_bytes: Option<Vec<u8>>,
+    /// The bytes after the change. `None` if the value doesn't exist anymore or couldn't be read.
+    #[serde(with = "opt_hex_str_serde")]
+    pub new_bytes: Option<Vec<u8>>,
+    /// A human-readable interpretation of the new bytes, like the parsed setting, if the recorder provided one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+impl JournalEntry {
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.epoch_millis)
+    }
+}
+
+impl ChangeJournal {
+    pub fn open<T: AsRef<Path>>(file_path: T, retention: Retention) -> Result<Self, io::Error> {
+        //! Opens or creates the journal file and prunes it according to the retention settings.
+
+        let journal = Self {
+            file_path: file_path.as_ref().to_path_buf(),
+            retention,
+        };
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal.file_path)?;
+        journal.prune()?;
+
+        Ok(journal)
+    }
+
+    pub fn record<T>(&self, event: &RegValueEvent<T>) -> Result<(), io::Error> {
+        //! Appends an entry for the event. The event's bytes and previous bytes should have been captured (see [`super::RegValueMonitor::capture_previous_bytes()`]); otherwise, they're recorded as unknown.
+
+        self.record_with_summary(event, None)
+    }
+
+    pub fn record_with_summary<T>(
+        &self,
+        event: &RegValueEvent<T>,
+        summary: Option<String>,
+    ) -> Result<(), io::Error> {
+        //! Like [`Self::record()`], but also stores an interpretation of the new bytes, which only the caller can provide, e.g., when a setting consists of multiple registry values.
+
+        let entry = JournalEntry {
+            key_path: full_key_path(&event.reg_value_path),
+            value_name: event.reg_value_path.value_name.clone(),
+            user_sid: event.user_sid.clone(),
+            epoch_millis: epoch_millis(event.time),
+            old_bytes: event.previous_bytes.clone(),
+            new_bytes: event.bytes.clone(),
+            summary,
+        };
+
+        let mut line = serde_json::to_string(&entry).map_err(io::Error::from)?;
+        line.push('\n');
+
+        OpenOptions::new()
+            .append(true)
+            .open(&self.file_path)?
+            .write_all(line.as_bytes())
+    }
+
+    pub fn entries(&self) -> Result<Vec<JournalEntry>, io::Error> {
+        //! Reads all entries, oldest first.
+
+        BufReader::new(File::open(&self.file_path)?)
+            .lines()
+            .filter(|result| result.as_ref().map_or(true, |line| !line.trim().is_empty()))
+            .map(|result| Ok(serde_json::from_str(&result?)?))
+            .collect()
+    }
+
+    pub fn query<P: AsRegValuePath>(
+        &self,
+        reg_value_path: Option<P>,
+        since: Option<SystemTime>,
+    ) -> Result<Vec<JournalEntry>, io::Error> {
+        //! Reads the entries of a certain registry value and/or since a certain time, oldest first.
+
+        let reg_value_path = reg_value_path.map(|path| {
+            let path = path.as_reg_value_path();
+            (full_key_path(&path.into()), path.value_name.to_string())
+        });
+        let since_epoch_millis = since.map(epoch_millis);
+
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter(|entry| {
+                reg_value_path
+                    .as_ref()
+                    .map_or(true, |(key_path, value_name)| {
+                        entry.key_path.eq_ignore_ascii_case(key_path)
+                            && entry.value_name.eq_ignore_ascii_case(value_name)
+                    })
+                    && since_epoch_millis.map_or(true, |millis| entry.epoch_millis >= millis)
+            })
+            .collect())
+    }
+
+    pub fn prune(&self) -> Result<(), io::Error> {
+        //! Removes the entries violating the retention settings by rewriting the file.
+
+        if self.retention == Retention::default() {
+            return Ok(());
+        }
+
+        let entries = self.entries()?;
+        let retained_entries = retain(&entries, self.retention, SystemTime::now());
+        if retained_entries.len() == entries.len() {
+            return Ok(());
+        }
+
+        let mut text = String::new();
+        for entry in retained_entries {
+            text.push_str(&serde_json::to_string(entry).map_err(io::Error::from)?);
+            text.push('\n');
+        }
+
+        fs::write(&self.file_path, text)
+    }
+}
+
+fn retain(entries: &[JournalEntry], retention: Retention, now: SystemTime) -> &[JournalEntry] {
+    //! Returns the suffix of the entries that complies with the retention settings. Assumes chronological order.
+
+    let mut start_index = 0;
+
+    if let Some(max_entries) = retention.max_entries {
+        start_index = entries.len().saturating_sub(max_entries);
+    }
+
+    if let Some(max_age) = retention.max_age {
+        let min_epoch_millis = epoch_millis(now).saturating_sub(max_age.as_millis() as u64);
+        start_index =
+            start_index.max(entries.partition_point(|entry| entry.epoch_millis < min_epoch_millis));
+    }
+
+    &entries[start_index..]
+}
+
+pub(crate) fn full_key_path(reg_value_path: &OwnedRegValuePath) -> String {
+    //! # Panics
+    //! Panics in case of an unknown `HKEY`.
+
+    if reg_value_path.subkey_path.is_empty() {
+        hkey_to_str(reg_value_path.hkey).to_string()
+    } else {
+        format!(
+            r"{}\{}",
+            hkey_to_str(reg_value_path.hkey),
+            reg_value_path.subkey_path
+        )
+    }
+}
+
+fn epoch_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{retain, JournalEntry, Retention};
+
+    fn entry(epoch_millis: u64) -> JournalEntry {
+        JournalEntry {
+            key_path: r"HKEY_CURRENT_USER\Software\Test".to_string(),
+            value_name: "Data".to_string(),
+            user_sid: None,
+            epoch_millis,
+            old_bytes: None,
+            new_bytes: Some(vec![0x43, 0x42, 0x00, 0xff]),
+            summary: None,
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let entry = entry(1_700_000_000_000);
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains(r#""newBytes":"434200ff""#));
+        assert!(json.contains(r#""oldBytes":null"#));
+        assert!(!json.contains("summary"));
+        assert_eq!(serde_json::from_str::<JournalEntry>(&json).unwrap(), entry);
+
+        let entry = JournalEntry {
+            summary: Some("active".to_string()),
+            ..entry
+        };
+        let json = serde_json::to_string(&entry).unwrap();
+        assert_eq!(serde_json::from_str::<JournalEntry>(&json).unwrap(), entry);
+    }
+
+    #[test]
+    fn retention() {
+        let entries: Vec<_> = (1..=5).map(|secs| entry(secs * 1000)).collect();
+        let now = UNIX_EPOCH + Duration::from_secs(5);
+
+        assert_eq!(retain(&entries, Retention::default(), now).len(), 5);
+        assert_eq!(
+            retain(
+                &entries,
+                Retention {
+                    max_age: None,
+                    max_entries: Some(2),
+                },
+                now
+            ),
+            &entries[3..]
+        );
+        assert_eq!(
+            retain(
+                &entries,
+                Retention {
+                    max_age: Some(Duration::from_millis(2500)),
+                    max_entries: Some(4),
+                },
+                now
+            ),
+            &entries[2..]
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Optional timing instrumentation of monitors and registry writes, to compare monitoring approaches and diagnose sluggish reactions to changes with real numbers. Disabled by default, in which case recording only costs an atomic load.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::reg::{AsRegValuePath, OwnedRegValuePath};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+
+/// Writes older than this without a change event are assumed to never cause one, e.g., because they didn't change the bytes, and are dropped.
+const MAX_PENDING_WRITE_AGE: Duration = Duration::from_secs(60);
+
+pub fn enable() {
+    //! Starts recording timings of all monitors and registry writes of the process.
+
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn disable() {
+    //! Stops recording. The statistics gathered so far are kept.
+
+    ENABLED.store(false, Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+pub fn snapshot() -> MonitorStats {
+    //! The statistics gathered since enabling or the last reset.
+
+    with_recorder(|recorder| recorder.snapshot(Instant::now()))
+}
+
+pub fn reset() {
+    with_recorder(|recorder| *recorder = Recorder::default());
+}
+
+/// Timings gathered while instrumentation was enabled.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct MonitorStats {
+    /// From receiving a change event to handing it on, which includes reading the registry value.
+    pub event_processing: LatencyStats,
+    /// From receiving a change event to calling the callback of a monitor loop.
+    pub event_to_callback: LatencyStats,
+    /// The time spent in the callbacks of monitor loops.
+    pub callback: LatencyStats,
+    /// From a registry write of this process returning to a monitor receiving the change event caused by it.
+    pub write_to_event: LatencyStats,
+    /// Writes of this process that no change event arrived for yet.
+    pub unobserved_writes: usize,
+}
+
+impl fmt::Display for MonitorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Event processing:    {}", self.event_processing)?;
+        writeln!(f, "Event to callback:   {}", self.event_to_callback)?;
+        writeln!(f, "Callback:            {}", self.callback)?;
+        writeln!(f, "Write to event:      {}", self.write_to_event)?;
+        write!(f, "Unobserved writes:   {}", self.unobserved_writes)
+    }
+}
+
+/// Aggregated durations of one kind.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+    pub total: Duration,
+}
+
+impl LatencyStats {
+    pub fn mean(&self) -> Option<Duration> {
+        //! `None`, if nothing was recorded.
+
+        (self.count != 0)
+            .then(|| Duration::from_nanos((self.total.as_nanos() / self.count as u128) as u64))
+    }
+
+    fn record(&mut self, duration: Duration) {
+        self.count += 1;
+        self.min = Some(self.min.map_or(duration, |min| min.min(duration)));
+        self.max = Some(self.max.map_or(duration, |max| max.max(duration)));
+        self.total = self.total.saturating_add(duration);
+    }
+}
+
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min, self.mean(), self.max) {
+            (Some(min), Some(mean), Some(max)) => write!(
+                f,
+                "{} × (min {:.2} ms, mean {:.2} ms, max {:.2} ms)",
+                self.count,
+                min.as_secs_f64() * 1000.0,
+                mean.as_secs_f64() * 1000.0,
+                max.as_secs_f64() * 1000.0,
+            ),
+            _ => f.write_str("no data"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Recorder {
+    stats: MonitorStats,
+    /// When the last write to each registry value returned.
+    pending_writes: HashMap<OwnedRegValuePath, Instant>,
+}
+
+impl Recorder {
+    fn record_event<'a, I>(&mut self, reg_value_paths: I, received: Instant, handed_on: Instant)
+    where
+        I: IntoIterator<Item = &'a OwnedRegValuePath>,
+    {
+        self.stats
+            .event_processing
+            .record(handed_on.saturating_duration_since(received));
+
+        //. The first path with a pending write is taken, so that an alias of the path isn't matched again by a later event.
+        if let Some(written) = reg_value_paths
+            .into_iter()
+            .find_map(|reg_value_path| self.pending_writes.remove(reg_value_path))
+        {
+            self.stats
+                .write_to_event
+                .record(received.saturating_duration_since(written));
+        }
+    }
+
+    fn record_write(&mut self, reg_value_path: OwnedRegValuePath, written: Instant) {
+        self.pending_writes.retain(|_, pending_written| {
+            written.saturating_duration_since(*pending_written) <= MAX_PENDING_WRITE_AGE
+        });
+        self.pending_writes.insert(reg_value_path, written);
+    }
+
+    fn snapshot(&self, now: Instant) -> MonitorStats {
+        MonitorStats {
+            unobserved_writes: self
+                .pending_writes
+                .values()
+                .filter(|written| now.saturating_duration_since(**written) <= MAX_PENDING_WRITE_AGE)
+                .count(),
+            ..self.stats.clone()
+        }
+    }
+}
+
+fn with_recorder<F: FnOnce(&mut Recorder) -> R, R>(f: F) -> R {
+    f(RECORDER
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .get_or_insert_with(Recorder::default))
+}
+
+pub(super) fn record_event<'a, I>(reg_value_paths: I, received: Instant)
+where
+    I: IntoIterator<Item = &'a OwnedRegValuePath>,
+{
+    //! Records the processing of a change event and matches it with a previous write. `reg_value_paths` are the paths of the changed registry value writes may have used, in order of preference.
+
+    if is_enabled() {
+        let handed_on = Instant::now();
+        with_recorder(|recorder| recorder.record_event(reg_value_paths, received, handed_on));
+    }
+}
+
+pub(super) fn record_callback(received: Instant, called: Instant) {
+    if is_enabled() {
+        let returned = Instant::now();
+        with_recorder(|recorder| {
+            recorder
+                .stats
+                .event_to_callback
+                .record(called.saturating_duration_since(received));
+            recorder
+                .stats
+                .callback
+                .record(returned.saturating_duration_since(called));
+        });
+    }
+}
+
+pub(crate) fn record_write<P: AsRegValuePath>(reg_value_path: &P) {
+    //! To be called after a registry write returned successfully.
+
+    if is_enabled() {
+        let written = Instant::now();
+        let reg_value_path = OwnedRegValuePath::from(reg_value_path.as_reg_value_path());
+        with_recorder(|recorder| recorder.record_write(reg_value_path, written));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use winreg::enums::HKEY_CURRENT_USER;
+
+    use super::{LatencyStats, Recorder, MAX_PENDING_WRITE_AGE};
+    use crate::reg::OwnedRegValuePath;
+
+    #[test]
+    fn latency_stats() {
+        let mut stats = LatencyStats::default();
+        assert_eq!(stats.mean(), None);
+        assert_eq!(stats.to_string(), "no data");
+
+        for millis in [4, 1, 7] {
+            stats.record(Duration::from_millis(millis));
+        }
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.min, Some(Duration::from_millis(1)));
+        assert_eq!(stats.max, Some(Duration::from_millis(7)));
+        assert_eq!(stats.mean(), Some(Duration::from_millis(4)));
+        assert_eq!(
+            stats.to_string(),
+            "3 × (min 1.00 ms, mean 4.00 ms, max 7.00 ms)"
+        );
+    }
+
+    #[test]
+    fn writes_matched_with_events() {
+        let requested_path = OwnedRegValuePath::new(HKEY_CURRENT_USER, r"Software\Test", "Value");
+        let resolved_path = requested_path.in_user_hive("S-1-5-21-1");
+        let other_path = requested_path.clone().with_value_name("Other");
+
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut recorder = Recorder::default();
+
+        recorder.record_write(requested_path.clone(), at(0));
+        recorder.record_write(other_path.clone(), at(1));
+        assert_eq!(recorder.snapshot(at(2)).unobserved_writes, 2);
+
+        recorder.record_event([&resolved_path, &requested_path], at(10), at(12));
+        //. No pending write anymore.
+        recorder.record_event([&resolved_path, &requested_path], at(20), at(21));
+
+        let stats = recorder.snapshot(at(30));
+        assert_eq!(stats.event_processing.count, 2);
+        assert_eq!(stats.event_processing.max, Some(Duration::from_millis(2)));
+        assert_eq!(stats.write_to_event.count, 1);
+        assert_eq!(stats.write_to_event.min, Some(Duration::from_millis(10)));
+        assert_eq!(stats.unobserved_writes, 1);
+
+        //. Writes without an event expire.
+        let expired = at(1) + MAX_PENDING_WRITE_AGE + Duration::from_millis(1);
+        assert_eq!(recorder.snapshot(expired).unobserved_writes, 0);
+        recorder.record_write(resolved_path.clone(), expired);
+        assert_eq!(recorder.pending_writes.len(), 1);
+        assert!(recorder.pending_writes.contains_key(&resolved_path));
+    }
+}
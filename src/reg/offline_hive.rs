@@ -0,0 +1,148 @@
+//! Loading offline hives, like the NTUSER.DAT of another user profile or of a mounted Windows image, to run the crate's parsers and writers against them.
+
+use std::{io, marker::PhantomData, path::Path};
+use windows::{
+    core::HSTRING,
+    Win32::{
+        Foundation::{GetLastError, LUID},
+        Security::{
+            AdjustTokenPrivileges, LookupPrivilegeValueW, LUID_AND_ATTRIBUTES, SE_BACKUP_NAME,
+            SE_PRIVILEGE_ENABLED, SE_RESTORE_NAME, TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES,
+        },
+        System::{
+            Registry::{self, RegLoadKeyW, RegOverridePredefKey, RegUnLoadKeyW},
+            Threading::{GetCurrentProcess, OpenProcessToken},
+        },
+    },
+};
+use windows_helpers::ResGuard;
+use winreg::{
+    enums::{HKEY_CURRENT_USER, HKEY_USERS},
+    RegKey,
+};
+
+/// A hive file loaded under `HKEY_USERS\<mount key name>`. It's unloaded again when dropped, which fails silently while handles to keys in it are still open.
+///
+/// Loading and unloading requires an elevated process. The necessary privileges are enabled automatically.
+pub struct OfflineHive {
+    mount_key_name: String,
+}
+
+impl OfflineHive {
+    pub fn load<T: AsRef<Path>>(
+        hive_file_path: T,
+        mount_key_name: &str,
+    ) -> Result<Self, io::Error> {
+        //! Loads the hive file (e.g., `C:\Users\Default\NTUSER.DAT`) under `HKEY_USERS\<mount_key_name>`. The mount key name must not exist yet.
+
+        enable_privileges()?;
+
+        unsafe {
+            RegLoadKeyW(
+                hkey_to_win32(HKEY_USERS),
+                &HSTRING::from(mount_key_name),
+                &HSTRING::from(hive_file_path.as_ref().as_os_str()),
+            )?;
+        }
+
+        Ok(Self {
+            mount_key_name: mount_key_name.to_string(),
+        })
+    }
+
+    pub fn mount_key_name(&self) -> &str {
+        &self.mount_key_name
+    }
+
+    pub fn subkey_path(&self, subkey_path: &str) -> String {
+        //! Returns the path relative to `HKEY_USERS` that corresponds to the path relative to the hive's root. Can be used to build a [`super::RegValuePath`] with `HKEY_USERS`.
+
+        format!("{}\\{subkey_path}", self.mount_key_name)
+    }
+
+    pub fn override_current_user(&self) -> Result<CurrentUserOverride<'_>, io::Error> {
+        //! Redirects `HKEY_CURRENT_USER` of the whole process to the hive, until the returned guard is dropped. This way, all of the crate's functionality working with `HKEY_CURRENT_USER` can be used with the hive, e.g., `NightLight::from_reg()` and `NightLight::write_to_reg()`.
+        //!
+        //! Since the redirection affects all threads, no other code in the process should access `HKEY_CURRENT_USER` meanwhile. Registry value monitoring doesn't follow the redirection.
+
+        let key = RegKey::predef(HKEY_USERS).open_subkey(&self.mount_key_name)?;
+        unsafe {
+            RegOverridePredefKey(
+                hkey_to_win32(HKEY_CURRENT_USER),
+                hkey_to_win32(key.raw_handle()),
+            )?
+        };
+
+        Ok(CurrentUserOverride {
+            _key: key,
+            _hive: PhantomData,
+        })
+    }
+}
+
+impl Drop for OfflineHive {
+    fn drop(&mut self) {
+        let _ = unsafe {
+            RegUnLoadKeyW(
+                hkey_to_win32(HKEY_USERS),
+                &HSTRING::from(self.mount_key_name.as_str()),
+            )
+        };
+    }
+}
+
+/// Guard of the redirection established by [`OfflineHive::override_current_user()`].
+pub struct CurrentUserOverride<'a> {
+    _key: RegKey,
+    _hive: PhantomData<&'a OfflineHive>,
+}
+
+impl Drop for CurrentUserOverride<'_> {
+    fn drop(&mut self) {
+        // Passing no key restores the default.
+        let _ = unsafe {
+            RegOverridePredefKey(hkey_to_win32(HKEY_CURRENT_USER), Registry::HKEY::default())
+        };
+    }
+}
+
+fn enable_privileges() -> Result<(), windows::core::Error> {
+    //! Enables the privileges that `RegLoadKeyW()` and `RegUnLoadKeyW()` require, which elevated processes have, but not enabled by default.
+
+    let process_token_handle = ResGuard::with_mut_acq_and_close_handle(|handle| unsafe {
+        OpenProcessToken(GetCurrentProcess(), TOKEN_ADJUST_PRIVILEGES, handle)
+    })?;
+
+    for privilege_name in [SE_BACKUP_NAME, SE_RESTORE_NAME] {
+        let mut luid = LUID::default();
+        unsafe { LookupPrivilegeValueW(None, privilege_name, &mut luid)? };
+
+        let token_privileges = TOKEN_PRIVILEGES {
+            PrivilegeCount: 1,
+            Privileges: [LUID_AND_ATTRIBUTES {
+                Luid: luid,
+                Attributes: SE_PRIVILEGE_ENABLED,
+            }],
+        };
+
+        unsafe {
+            AdjustTokenPrivileges(
+                *process_token_handle,
+                false,
+                Some(&token_privileges),
+                0,
+                None,
+                None,
+            )?;
+
+            // Succeeds with `ERROR_NOT_ALL_ASSIGNED`, if the process doesn't have the privilege.
+            GetLastError()?;
+        }
+    }
+
+    Ok(())
+}
+
+fn hkey_to_win32(hkey: winreg::HKEY) -> Registry::HKEY {
+    Registry::HKEY(hkey as _)
+}
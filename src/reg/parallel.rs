@@ -0,0 +1,197 @@
+//! Reading many registry values or keys at once with a bounded number of threads, while handing the results on in their original order. Used for bulk exports of large CloudStore trees, where reading value by value dominates the run time.
+
+use std::{
+    collections::BTreeMap,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Condvar, Mutex,
+    },
+    thread,
+};
+
+/// The upper bound of reading threads. Registry reads mostly wait on the registry's locks and the disk, so more threads don't help.
+const MAX_WORKERS: usize = 8;
+
+pub(crate) fn read_ordered<T, R, E, F, W>(items: &[T], read: F, mut write: W) -> Result<(), E>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+    W: FnMut(R) -> Result<(), E>,
+{
+    //! Calls `read` for every item on a pool of worker threads and `write` with the results on the calling thread, in the order of the items. Results that arrive early are held back until all previous ones were written. Workers don't read further ahead of the next item to write than a few items per worker, so that the held-back results stay bounded. If `write` fails, the workers stop taking new items and the error is returned.
+
+    let num_workers = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(MAX_WORKERS)
+        .min(items.len());
+
+    if num_workers <= 1 {
+        return items.iter().try_for_each(|item| write(read(item)));
+    }
+
+    //. How far workers may read ahead of the next item to write. The worker with that item never waits, so that the writer always makes progress.
+    let window = num_workers * 2;
+    let next_index = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let write_progress = Mutex::new(0);
+    let written = Condvar::new();
+
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..num_workers {
+            let sender = sender.clone();
+            let (next_index, stop, write_progress, written, read) =
+                (&next_index, &stop, &write_progress, &written, &read);
+
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let index = next_index.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+
+                    //. Bounds the results held back by the writer.
+                    drop(
+                        written
+                            .wait_while(write_progress.lock().unwrap(), |next_to_write| {
+                                index >= *next_to_write + window && !stop.load(Ordering::Relaxed)
+                            })
+                            .unwrap(),
+                    );
+
+                    if stop.load(Ordering::Relaxed) || sender.send((index, read(item))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut next_to_write = 0;
+
+        let result = receiver.iter().try_for_each(|(index, result)| {
+            pending.insert(index, result);
+
+            let prev_next_to_write = next_to_write;
+            while let Some(result) = pending.remove(&next_to_write) {
+                write(result)?;
+                next_to_write += 1;
+            }
+
+            if next_to_write != prev_next_to_write {
+                *write_progress.lock().unwrap() = next_to_write;
+                written.notify_all();
+            }
+
+            Ok(())
+        });
+
+        if result.is_err() {
+            //. The lock makes sure that no worker misses the notification between checking the condition and waiting.
+            let _write_progress = write_progress.lock().unwrap();
+            stop.store(true, Ordering::Relaxed);
+            written.notify_all();
+        }
+
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use super::read_ordered;
+
+    #[test]
+    fn ordered_results() {
+        let items = (0..100).collect::<Vec<u64>>();
+        let mut written = Vec::new();
+
+        read_ordered(
+            &items,
+            |&item| {
+                //. Later items finish earlier.
+                thread::sleep(Duration::from_micros((100 - item) * 20));
+                item * 2
+            },
+            |result| {
+                written.push(result);
+                Ok::<_, ()>(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            written,
+            items.iter().map(|item| item * 2).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn write_error_stops_reading() {
+        let items = (0..10_000).collect::<Vec<u32>>();
+        let num_read = AtomicUsize::new(0);
+        let mut num_written = 0;
+
+        let result = read_ordered(
+            &items,
+            |&item| {
+                num_read.fetch_add(1, Ordering::Relaxed);
+                item
+            },
+            |item| {
+                if item == 10 {
+                    return Err(item);
+                }
+                num_written += 1;
+                Ok(())
+            },
+        );
+
+        assert_eq!(result, Err(10));
+        assert_eq!(num_written, 10);
+        assert!(num_read.load(Ordering::Relaxed) < items.len());
+    }
+
+    #[test]
+    fn bounded_lookahead() {
+        let items = (0..200).collect::<Vec<u64>>();
+        let num_written = AtomicUsize::new(0);
+        let max_lookahead = AtomicUsize::new(0);
+
+        read_ordered(
+            &items,
+            |&item| {
+                //. The first item is slow, so that the others would otherwise all be read meanwhile.
+                if item == 0 {
+                    thread::sleep(Duration::from_millis(50));
+                }
+                let lookahead = item as usize - num_written.load(Ordering::SeqCst);
+                max_lookahead.fetch_max(lookahead, Ordering::SeqCst);
+                item
+            },
+            |_| {
+                num_written.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, ()>(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(num_written.load(Ordering::SeqCst), items.len());
+        assert!(max_lookahead.load(Ordering::SeqCst) < 2 * super::MAX_WORKERS);
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(read_ordered(&[] as &[u8], |_| (), |_| Err(())), Ok(()));
+    }
+}
@@ -0,0 +1,355 @@
+//! Access to registry values through a trait, so that code built on it can run against an in-memory registry in tests instead of mutating the user's real settings. [`WinRegProvider`] accesses the Windows registry, [`MemRegProvider`] a map in memory.
+//!
+//! # Examples
+//! ```ignore
+//! let provider = MemRegProvider::new();
+//! provider.write_bin_value(&RawNightLightState::REG_VALUE_PATH, &state_bytes, true)?;
+//! provider.write_bin_value(&RawNightLightSettings::REG_VALUE_PATH, &settings_bytes, true)?;
+//!
+//! let mut night_light = NightLight::from_provider(&provider, Strictness::Strict)?;
+//! night_light.set_active(true);
+//! night_light.write_to_provider(&provider)?;
+//! ```
+
+use std::{
+    collections::HashMap,
+    io,
+    sync::{mpsc, Mutex},
+    time::Duration,
+};
+
+use super::{
+    delete_reg_value, monitor::RegValueMonitor, read_reg_bin_value, sid::UserScope,
+    write_reg_bin_value, write_reg_bin_value_creating_key, OwnedRegValuePath, RegValuePath,
+};
+
+/// Reads, writes, deletes and monitors binary registry values.
+pub trait RegProvider: Send + Sync {
+    /// Fails with [`io::ErrorKind::NotFound`], if the value doesn't exist.
+    fn read_bin_value(&self, reg_value_path: &RegValuePath) -> Result<Vec<u8>, io::Error>;
+
+    /// With `creates_key`, the subkey is created, if necessary. Without it, the write fails, if the subkey doesn't exist.
+    fn write_bin_value(
+        &self,
+        reg_value_path: &RegValuePath,
+        bytes: &[u8],
+        creates_key: bool,
+    ) -> Result<(), io::Error>;
+
+    /// Succeeds, if the value doesn't exist.
+    fn delete_value(&self, reg_value_path: &RegValuePath) -> Result<(), io::Error>;
+
+    /// Watches the values for changes. The monitor yields the indices of changed values in `reg_value_paths`.
+    fn monitor(
+        &self,
+        reg_value_paths: &[RegValuePath],
+    ) -> Result<Box<dyn ProviderMonitor>, io::Error>;
+}
+
+/// Returned by [`RegProvider::monitor()`].
+pub trait ProviderMonitor {
+    /// Blocks until a watched value changed, returning its index. Returns `None` when the timeout elapsed without a change. `None` as the timeout waits indefinitely.
+    fn next_change(&mut self, timeout: Option<Duration>) -> Option<Result<usize, io::Error>>;
+}
+
+/// The Windows registry, accessed with the crate's registry functions.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct WinRegProvider;
+
+impl RegProvider for WinRegProvider {
+    fn read_bin_value(&self, reg_value_path: &RegValuePath) -> Result<Vec<u8>, io::Error> {
+        read_reg_bin_value(reg_value_path)
+    }
+
+    fn write_bin_value(
+        &self,
+        reg_value_path: &RegValuePath,
+        bytes: &[u8],
+        creates_key: bool,
+    ) -> Result<(), io::Error> {
+        let bytes = bytes.to_vec();
+        if creates_key {
+            write_reg_bin_value_creating_key(reg_value_path, &bytes)
+        } else {
+            write_reg_bin_value(reg_value_path, &bytes)
+        }
+    }
+
+    fn delete_value(&self, reg_value_path: &RegValuePath) -> Result<(), io::Error> {
+        delete_reg_value(reg_value_path)
+    }
+
+    fn monitor(
+        &self,
+        reg_value_paths: &[RegValuePath],
+    ) -> Result<Box<dyn ProviderMonitor>, io::Error> {
+        let monitor = RegValueMonitor::new(reg_value_paths.iter().copied().enumerate())
+            .map_err(io::Error::other)?;
+
+        Ok(Box::new(WinRegMonitor(monitor)))
+    }
+}
+
+/// The Windows registry, but with paths under `HKEY_CURRENT_USER` redirected to a hive under `HKEY_USERS`: the one of another user or an [`OfflineHive`](super::offline_hive::OfflineHive). Accessing other users' hives requires elevation.
+#[derive(Clone, PartialEq, Debug)]
+pub struct UserHiveProvider {
+    hive_key_name: String,
+}
+
+impl UserHiveProvider {
+    pub fn new<S: Into<String>>(hive_key_name: S) -> Self {
+        //! `hive_key_name` is the name of the hive's key under `HKEY_USERS`, i.e., a user's SID or the mount key name of an offline hive.
+
+        Self {
+            hive_key_name: hive_key_name.into(),
+        }
+    }
+
+    pub fn hive_key_name(&self) -> &str {
+        &self.hive_key_name
+    }
+
+    pub fn resolve(&self, reg_value_path: &RegValuePath) -> OwnedRegValuePath {
+        OwnedRegValuePath::from(*reg_value_path).in_user_hive(&self.hive_key_name)
+    }
+}
+
+impl RegProvider for UserHiveProvider {
+    fn read_bin_value(&self, reg_value_path: &RegValuePath) -> Result<Vec<u8>, io::Error> {
+        WinRegProvider.read_bin_value(&self.resolve(reg_value_path).as_borrowed())
+    }
+
+    fn write_bin_value(
+        &self,
+        reg_value_path: &RegValuePath,
+        bytes: &[u8],
+        creates_key: bool,
+    ) -> Result<(), io::Error> {
+        WinRegProvider.write_bin_value(
+            &self.resolve(reg_value_path).as_borrowed(),
+            bytes,
+            creates_key,
+        )
+    }
+
+    fn delete_value(&self, reg_value_path: &RegValuePath) -> Result<(), io::Error> {
+        WinRegProvider.delete_value(&self.resolve(reg_value_path).as_borrowed())
+    }
+
+    fn monitor(
+        &self,
+        reg_value_paths: &[RegValuePath],
+    ) -> Result<Box<dyn ProviderMonitor>, io::Error> {
+        let monitor = RegValueMonitor::with_user_scope(
+            reg_value_paths.iter().enumerate(),
+            UserScope::User(self.hive_key_name.clone()),
+        )
+        .map_err(io::Error::other)?;
+
+        Ok(Box::new(WinRegMonitor(monitor)))
+    }
+}
+
+struct WinRegMonitor(RegValueMonitor<usize>);
+
+impl ProviderMonitor for WinRegMonitor {
+    fn next_change(&mut self, timeout: Option<Duration>) -> Option<Result<usize, io::Error>> {
+        self.0
+            .next_event_blocking(timeout)
+            .map(|result| result.map(|event| event.id).map_err(io::Error::other))
+    }
+}
+
+/// A registry in memory, for tests. Subkeys aren't modeled: writes without `creates_key` only fail for subkeys in which no value was ever written. Paths are compared case-sensitively, unlike in the Windows registry.
+#[derive(Default)]
+pub struct MemRegProvider {
+    values: Mutex<HashMap<OwnedRegValuePath, Vec<u8>>>,
+    /// The subkeys in which values were written, with the hive.
+    keys: Mutex<Vec<OwnedRegValuePath>>,
+    watchers: Mutex<Vec<Watcher>>,
+}
+
+struct Watcher {
+    reg_value_paths: Vec<OwnedRegValuePath>,
+    sender: mpsc::Sender<usize>,
+}
+
+impl MemRegProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn value(&self, reg_value_path: &RegValuePath) -> Option<Vec<u8>> {
+        //! The bytes of the value, for assertions in tests.
+
+        self.values
+            .lock()
+            .unwrap()
+            .get(&OwnedRegValuePath::from(*reg_value_path))
+            .cloned()
+    }
+
+    fn notify(&self, reg_value_path: &OwnedRegValuePath) {
+        //! Drops watchers whose monitor was dropped.
+
+        self.watchers.lock().unwrap().retain(|watcher| {
+            match watcher
+                .reg_value_paths
+                .iter()
+                .position(|watched_path| watched_path == reg_value_path)
+            {
+                Some(index) => watcher.sender.send(index).is_ok(),
+                None => true,
+            }
+        });
+    }
+}
+
+impl RegProvider for MemRegProvider {
+    fn read_bin_value(&self, reg_value_path: &RegValuePath) -> Result<Vec<u8>, io::Error> {
+        self.value(reg_value_path)
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn write_bin_value(
+        &self,
+        reg_value_path: &RegValuePath,
+        bytes: &[u8],
+        creates_key: bool,
+    ) -> Result<(), io::Error> {
+        let reg_value_path = OwnedRegValuePath::from(*reg_value_path);
+        let key = reg_value_path.clone().with_value_name("");
+
+        {
+            let mut keys = self.keys.lock().unwrap();
+            if !keys.contains(&key) {
+                if !creates_key {
+                    return Err(io::ErrorKind::NotFound.into());
+                }
+                keys.push(key);
+            }
+        }
+
+        self.values
+            .lock()
+            .unwrap()
+            .insert(reg_value_path.clone(), bytes.to_vec());
+        self.notify(&reg_value_path);
+
+        Ok(())
+    }
+
+    fn delete_value(&self, reg_value_path: &RegValuePath) -> Result<(), io::Error> {
+        let reg_value_path = OwnedRegValuePath::from(*reg_value_path);
+        if self
+            .values
+            .lock()
+            .unwrap()
+            .remove(&reg_value_path)
+            .is_some()
+        {
+            self.notify(&reg_value_path);
+        }
+
+        Ok(())
+    }
+
+    fn monitor(
+        &self,
+        reg_value_paths: &[RegValuePath],
+    ) -> Result<Box<dyn ProviderMonitor>, io::Error> {
+        let (sender, receiver) = mpsc::channel();
+        self.watchers.lock().unwrap().push(Watcher {
+            reg_value_paths: reg_value_paths
+                .iter()
+                .map(|reg_value_path| OwnedRegValuePath::from(*reg_value_path))
+                .collect(),
+            sender,
+        });
+
+        Ok(Box::new(MemRegMonitor(receiver)))
+    }
+}
+
+struct MemRegMonitor(mpsc::Receiver<usize>);
+
+impl ProviderMonitor for MemRegMonitor {
+    fn next_change(&mut self, timeout: Option<Duration>) -> Option<Result<usize, io::Error>> {
+        match timeout {
+            Some(timeout) => self.0.recv_timeout(timeout).ok().map(Ok),
+            None => self.0.recv().ok().map(Ok),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+    use winreg::enums::{HKEY_CURRENT_USER, HKEY_USERS};
+
+    use super::{MemRegProvider, RegProvider, UserHiveProvider};
+    use crate::reg::{OwnedRegValuePath, RegValuePath};
+
+    const REG_VALUE_PATH: RegValuePath<'static> = RegValuePath {
+        hkey: HKEY_CURRENT_USER,
+        subkey_path: r"SOFTWARE\sem-reg-test",
+        value_name: "Data",
+    };
+
+    #[test]
+    fn mem_reg_provider() {
+        let provider = MemRegProvider::new();
+        let other_reg_value_path = RegValuePath {
+            value_name: "Other",
+            ..REG_VALUE_PATH
+        };
+        let mut monitor = provider
+            .monitor(&[other_reg_value_path, REG_VALUE_PATH])
+            .unwrap();
+
+        assert_eq!(
+            provider.read_bin_value(&REG_VALUE_PATH).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(provider
+            .write_bin_value(&REG_VALUE_PATH, &[1], false)
+            .is_err());
+
+        provider
+            .write_bin_value(&REG_VALUE_PATH, &[1, 2], true)
+            .unwrap();
+        provider
+            .write_bin_value(&other_reg_value_path, &[3], false)
+            .unwrap();
+        assert_eq!(provider.read_bin_value(&REG_VALUE_PATH).unwrap(), [1, 2]);
+
+        provider.delete_value(&REG_VALUE_PATH).unwrap();
+        provider.delete_value(&REG_VALUE_PATH).unwrap();
+        assert_eq!(provider.value(&REG_VALUE_PATH), None);
+
+        let timeout = Some(Duration::ZERO);
+        assert_eq!(monitor.next_change(timeout).unwrap().unwrap(), 1);
+        assert_eq!(monitor.next_change(timeout).unwrap().unwrap(), 0);
+        assert_eq!(monitor.next_change(timeout).unwrap().unwrap(), 1);
+        assert!(monitor.next_change(timeout).is_none());
+    }
+
+    #[test]
+    fn user_hive_resolution() {
+        let provider = UserHiveProvider::new("S-1-5-21-1-2-3-1001");
+        assert_eq!(
+            provider.resolve(&REG_VALUE_PATH),
+            OwnedRegValuePath::new(
+                HKEY_USERS,
+                r"S-1-5-21-1-2-3-1001\SOFTWARE\sem-reg-test",
+                "Data"
+            )
+        );
+
+        let other_hive_path = OwnedRegValuePath::new(HKEY_USERS, ".DEFAULT", "Data");
+        assert_eq!(
+            provider.resolve(&other_hive_path.as_borrowed()),
+            other_hive_path
+        );
+    }
+}
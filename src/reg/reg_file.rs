@@ -0,0 +1,784 @@
+//! .reg files in the "Windows Registry Editor Version 5.00" format that `regedit.exe` uses: Parsing and importing them into the registry, as well as creating them from the registry and formatting them interchangeably with `regedit.exe`'s own files.
+
+use std::{fmt, io, path::Path, str::FromStr};
+use winreg::{
+    enums::{RegType, KEY_READ},
+    RegKey, RegValue, HKEY,
+};
+
+use super::{
+    hkey_to_str, parallel::read_ordered, retry::with_retries, str_to_hkey, write_utf16_le_file,
+    AsRegValuePath,
+};
+
+const HEADER: &str = "Windows Registry Editor Version 5.00";
+
+/// Hex data lines are wrapped when reaching this length. Results in lines of at most 80 characters, like with `regedit.exe`.
+const MAX_HEX_LINE_LEN: usize = 77;
+
+/// The contents of a .reg file.
+#[derive(PartialEq, Debug)]
+pub struct RegFile {
+    /// The keys in the order of the file. The same key may occur multiple times.
+    pub keys: Vec<RegFileKey>,
+}
+
+/// A key section of a .reg file, i.e., a line like `[HKEY_CURRENT_USER\Software\Example]` with the value lines following it.
+#[derive(PartialEq, Debug)]
+pub struct RegFileKey {
+    pub hkey: HKEY,
+    /// Empty, if the section refers to the root key.
+    pub subkey_path: String,
+    /// Whether the key is to be deleted together with all its subkeys (`[-HKEY_...]`). The values are always empty then.
+    pub delete: bool,
+    pub values: Vec<RegFileValue>,
+}
+
+/// A value line of a .reg file.
+#[derive(PartialEq, Debug)]
+pub struct RegFileValue {
+    /// Empty for the default value of the key (`@`).
+    pub name: String,
+    /// `None`, if the value is to be deleted (`"name"=-`).
+    pub value: Option<RegValue>,
+}
+
+impl RegFile {
+    pub fn from_file<T: AsRef<Path>>(file_path: T) -> Result<Self, RegFileError> {
+        Self::from_bytes(&std::fs::read(file_path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RegFileError> {
+        //! Accepts UTF-16LE with BOM, as written by `regedit.exe`, as well as UTF-8 with or without BOM.
+
+        let text = if let Some(utf16_bytes) = bytes.strip_prefix(&[0xff, 0xfe]) {
+            let pairs = utf16_bytes.chunks_exact(2);
+            if !pairs.remainder().is_empty() {
+                return Err(RegFileError::InvalidEncoding);
+            }
+
+            let utf16: Vec<u16> = pairs
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16(&utf16).map_err(|_| RegFileError::InvalidEncoding)?
+        } else {
+            let utf8_bytes = bytes.strip_prefix(&[0xef, 0xbb, 0xbf]).unwrap_or(bytes);
+            String::from_utf8(utf8_bytes.to_vec()).map_err(|_| RegFileError::InvalidEncoding)?
+        };
+
+        Self::from_str(&text)
+    }
+
+    pub fn from_reg_values<P: AsRegValuePath + Sync>(
+        reg_value_paths: &[P],
+        deletion_for_missing: bool,
+    ) -> Result<Self, io::Error> {
+        //! Reads the values of any type from the registry. Consecutive values of the same key are put into the same key section. With `deletion_for_missing`, non-existent values or keys lead to value deletion lines instead of failing, so importing the file restores the current state.
+
+        let mut keys = Vec::<RegFileKey>::new();
+        let mut written_reg_value_paths = reg_value_paths.iter();
+
+        //. Values are read in parallel, since bulk exports may comprise hundreds of them.
+        read_ordered(
+            reg_value_paths,
+            |reg_value_path| {
+                let reg_value_path = reg_value_path.as_reg_value_path();
+                with_retries(|| {
+                    RegKey::predef(reg_value_path.hkey)
+                        .open_subkey_with_flags(reg_value_path.subkey_path, KEY_READ)
+                        .and_then(|key| key.get_raw_value(reg_value_path.value_name))
+                })
+            },
+            |result| {
+                let reg_value_path = written_reg_value_paths.next().unwrap().as_reg_value_path();
+                let value = match result {
+                    Ok(raw_value) => Some(raw_value),
+                    Err(error)
+                        if deletion_for_missing && error.kind() == io::ErrorKind::NotFound =>
+                    {
+                        None
+                    }
+                    Err(error) => return Err(error),
+                };
+
+                let value = RegFileValue {
+                    name: reg_value_path.value_name.to_string(),
+                    value,
+                };
+
+                match keys.last_mut() {
+                    Some(key)
+                        if key.hkey == reg_value_path.hkey
+                            && key.subkey_path == reg_value_path.subkey_path =>
+                    {
+                        key.values.push(value)
+                    }
+                    _ => keys.push(RegFileKey {
+                        hkey: reg_value_path.hkey,
+                        subkey_path: reg_value_path.subkey_path.to_string(),
+                        delete: false,
+                        values: vec![value],
+                    }),
+                }
+
+                Ok(())
+            },
+        )?;
+
+        Ok(Self { keys })
+    }
+
+    pub fn from_reg_key(hkey: HKEY, subkey_path: &str) -> Result<Self, io::Error> {
+        //! Reads the key with all its values and, recursively, all its subkeys, like when exporting a key with `regedit.exe`.
+
+        //. The key tree is enumerated first, so that the keys' values can then be read in parallel.
+        let mut subkey_paths = Vec::new();
+        push_subkey_paths(&mut subkey_paths, hkey, subkey_path.to_string())?;
+
+        let mut keys = Vec::with_capacity(subkey_paths.len());
+        let mut written_subkey_paths = subkey_paths.iter();
+        read_ordered(
+            &subkey_paths,
+            |subkey_path| with_retries(|| read_reg_key_values(hkey, subkey_path)),
+            |result| {
+                keys.push(RegFileKey {
+                    hkey,
+                    subkey_path: written_subkey_paths.next().unwrap().clone(),
+                    delete: false,
+                    values: result?,
+                });
+                Ok::<_, io::Error>(())
+            },
+        )?;
+
+        Ok(Self { keys })
+    }
+
+    pub fn export<T: AsRef<Path>>(&self, file_path: T) -> Result<(), io::Error> {
+        //! Writes the file like `regedit.exe` does.
+        //!
+        //! # Panics
+        //! Panics in case of an unknown `HKEY`.
+
+        write_utf16_le_file(&self.to_string(), file_path)
+    }
+
+    pub fn import(&self) -> Result<(), io::Error> {
+        //! Applies the file's contents to the registry like `regedit.exe` would: Keys are created as needed, and deletions of non-existent keys and values are ignored.
+
+        let ignore_not_found = |result: Result<(), io::Error>| match result {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        };
+
+        for key in &self.keys {
+            let root_key = RegKey::predef(key.hkey);
+
+            if key.delete {
+                if key.subkey_path.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "can't delete root key",
+                    ));
+                }
+
+                ignore_not_found(with_retries(|| {
+                    root_key.delete_subkey_all(&key.subkey_path)
+                }))?;
+                continue;
+            }
+
+            let (reg_key, _) = with_retries(|| root_key.create_subkey(&key.subkey_path))?;
+            for value in &key.values {
+                match &value.value {
+                    Some(raw_value) => {
+                        with_retries(|| reg_key.set_raw_value(&value.name, raw_value))?
+                    }
+                    None => ignore_not_found(with_retries(|| reg_key.delete_value(&value.name)))?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for RegFile {
+    type Err = RegFileError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        //! Parses the text of a .reg file. A leading BOM character is ignored.
+
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        let mut lines = logical_lines(text);
+
+        match lines.next() {
+            Some((_, header)) if header.trim_end() == HEADER => {}
+            _ => return Err(RegFileError::MissingHeader),
+        }
+
+        let mut keys = Vec::<RegFileKey>::new();
+
+        for (line_num, line) in lines {
+            let syntax_error = |message| RegFileError::SyntaxError {
+                line: line_num,
+                message,
+            };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(key_path) = line.strip_prefix('[') {
+                let key_path = key_path
+                    .strip_suffix(']')
+                    .ok_or_else(|| syntax_error("key line not terminated by `]`"))?;
+                let (delete, key_path) = match key_path.strip_prefix('-') {
+                    Some(key_path) => (true, key_path),
+                    None => (false, key_path),
+                };
+                let (root_name, subkey_path) = key_path.split_once('\\').unwrap_or((key_path, ""));
+
+                keys.push(RegFileKey {
+                    hkey: str_to_hkey(root_name).ok_or_else(|| syntax_error("unknown root key"))?,
+                    subkey_path: subkey_path.to_string(),
+                    delete,
+                    values: Vec::new(),
+                });
+            } else {
+                let key = keys
+                    .last_mut()
+                    .ok_or_else(|| syntax_error("value line before first key line"))?;
+                if key.delete {
+                    return Err(syntax_error("value line in section of key to delete"));
+                }
+
+                key.values
+                    .push(parse_value_line(line).map_err(syntax_error)?);
+            }
+        }
+
+        Ok(Self { keys })
+    }
+}
+
+impl fmt::Display for RegFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //! Formats the text like `regedit.exe`, with CRLF line endings, but without BOM. Strings are only written in quotes, if they can be parsed back to the identical bytes. Otherwise, hex data is written.
+        //!
+        //! # Panics
+        //! Panics in case of an unknown `HKEY`.
+
+        write!(f, "{HEADER}\r\n\r\n")?;
+
+        for key in &self.keys {
+            write!(
+                f,
+                "[{}{}",
+                if key.delete { "-" } else { "" },
+                hkey_to_str(key.hkey)
+            )?;
+            if !key.subkey_path.is_empty() {
+                write!(f, "\\{}", key.subkey_path)?;
+            }
+            f.write_str("]\r\n")?;
+
+            for value in &key.values {
+                fmt_value_line(f, value)?;
+            }
+
+            f.write_str("\r\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RegFileError {
+    /// Error reading the file.
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+    /// The bytes were neither valid UTF-16LE with BOM nor valid UTF-8.
+    #[error("invalid text encoding")]
+    InvalidEncoding,
+    /// The first line wasn't the header of the version 5.00 format.
+    #[error("missing header line of version 5.00 format")]
+    MissingHeader,
+    /// A line couldn't be parsed. The line number is 1-based and refers to the first physical line of lines joined by trailing backslashes.
+    #[error("syntax error in line {line}: {message}")]
+    SyntaxError { line: usize, message: &'static str },
+}
+
+fn logical_lines(text: &str) -> impl Iterator<Item = (usize, String)> + '_ {
+    //! Yields the lines with their 1-based line numbers, joining lines ending with a backslash (as with wrapped hex data) with the following line, whose leading whitespace is removed.
+
+    let mut physical_lines = text.lines().enumerate();
+
+    std::iter::from_fn(move || {
+        let (index, first_line) = physical_lines.next()?;
+        let mut line = first_line.trim_end().to_string();
+
+        // Comment lines can't be continued. Other lines only end with a backslash when continued, since strings are quoted.
+        if !line.trim_start().starts_with(';') {
+            while line.ends_with('\\') {
+                line.pop();
+                match physical_lines.next() {
+                    Some((_, next_line)) => line.push_str(next_line.trim()),
+                    None => break,
+                }
+            }
+        }
+
+        Some((index + 1, line))
+    })
+}
+
+fn push_subkey_paths(
+    subkey_paths: &mut Vec<String>,
+    hkey: HKEY,
+    subkey_path: String,
+) -> Result<(), io::Error> {
+    //! Pushes the path and, recursively, the paths of all subkeys, like `regedit.exe` orders them.
+
+    let subkey_names = RegKey::predef(hkey)
+        .open_subkey_with_flags(&subkey_path, KEY_READ)?
+        .enum_keys()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    subkey_paths.push(subkey_path.clone());
+
+    for subkey_name in subkey_names {
+        let subkey_subpath = if subkey_path.is_empty() {
+            subkey_name
+        } else {
+            format!("{subkey_path}\\{subkey_name}")
+        };
+        push_subkey_paths(subkey_paths, hkey, subkey_subpath)?;
+    }
+
+    Ok(())
+}
+
+fn read_reg_key_values(hkey: HKEY, subkey_path: &str) -> Result<Vec<RegFileValue>, io::Error> {
+    let mut values = RegKey::predef(hkey)
+        .open_subkey_with_flags(subkey_path, KEY_READ)?
+        .enum_values()
+        .map(|result| {
+            result.map(|(name, value)| RegFileValue {
+                name,
+                value: Some(value),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    // `regedit.exe` puts the default value first.
+    values.sort_by_key(|value| !value.name.is_empty());
+
+    Ok(values)
+}
+
+fn fmt_value_line(f: &mut fmt::Formatter<'_>, value: &RegFileValue) -> fmt::Result {
+    let name = if value.name.is_empty() {
+        "@".to_string()
+    } else {
+        quote_str(&value.name)
+    };
+    write!(f, "{name}=")?;
+
+    let Some(raw_value) = &value.value else {
+        return f.write_str("-\r\n");
+    };
+
+    if raw_value.vtype == RegType::REG_SZ {
+        if let Some(string) = quotable_sz_str(&raw_value.bytes) {
+            return write!(f, "{}\r\n", quote_str(&string));
+        }
+    } else if raw_value.vtype == RegType::REG_DWORD {
+        if let Ok(bytes) = raw_value.bytes.as_slice().try_into() {
+            return write!(f, "dword:{:08x}\r\n", u32::from_le_bytes(bytes));
+        }
+    }
+
+    let prefix = if raw_value.vtype == RegType::REG_BINARY {
+        "hex:".to_string()
+    } else {
+        format!("hex({:x}):", raw_value.vtype.clone() as u32)
+    };
+    f.write_str(&prefix)?;
+
+    // Like `regedit.exe`, count the value name in UTF-16 code units.
+    let mut line_len = name.encode_utf16().count() + "=".len() + prefix.len();
+
+    for (index, byte) in raw_value.bytes.iter().enumerate() {
+        write!(f, "{byte:02x}")?;
+        if index == raw_value.bytes.len() - 1 {
+            break;
+        }
+
+        f.write_str(",")?;
+        line_len += 3;
+
+        if line_len >= MAX_HEX_LINE_LEN {
+            f.write_str("\\\r\n  ")?;
+            line_len = 2;
+        }
+    }
+
+    f.write_str("\r\n")
+}
+
+fn quote_str(string: &str) -> String {
+    //! The inverse of `parse_quoted_str()`.
+
+    format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn quotable_sz_str(bytes: &[u8]) -> Option<String> {
+    //! Returns the string, if the `REG_SZ` bytes are exactly what parsing the quoted string would result in: a single trailing null terminator, and no line breaks.
+
+    let pairs = bytes.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+
+    let mut utf16: Vec<u16> = pairs
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    if utf16.pop() != Some(0) {
+        return None;
+    }
+
+    let string = String::from_utf16(&utf16).ok()?;
+    if string.contains(['\0', '\r', '\n']) {
+        None
+    } else {
+        Some(string)
+    }
+}
+
+fn parse_value_line(line: &str) -> Result<RegFileValue, &'static str> {
+    let (name, rest) = if let Some(rest) = line.strip_prefix('@') {
+        (String::new(), rest)
+    } else {
+        parse_quoted_str(line).ok_or("invalid value name")?
+    };
+
+    let data = rest
+        .trim_start()
+        .strip_prefix('=')
+        .ok_or("expected `=` after value name")?
+        .trim();
+
+    let value = if data == "-" {
+        None
+    } else if data.starts_with('"') {
+        match parse_quoted_str(data) {
+            Some((string, rest)) if rest.trim().is_empty() => Some(RegValue {
+                vtype: RegType::REG_SZ,
+                bytes: string
+                    .encode_utf16()
+                    .chain([0])
+                    .flat_map(|int16| int16.to_le_bytes())
+                    .collect(),
+            }),
+            _ => return Err("invalid string data"),
+        }
+    } else if let Some(hex_digits) = data.strip_prefix("dword:") {
+        if hex_digits.len() != 8 {
+            return Err("expected 8 hex digits for dword data");
+        }
+
+        Some(RegValue {
+            vtype: RegType::REG_DWORD,
+            bytes: u32::from_str_radix(hex_digits, 16)
+                .map_err(|_| "invalid dword data")?
+                .to_le_bytes()
+                .to_vec(),
+        })
+    } else if let Some(hex_bytes) = data.strip_prefix("hex:") {
+        Some(RegValue {
+            vtype: RegType::REG_BINARY,
+            bytes: parse_hex_bytes(hex_bytes)?,
+        })
+    } else if let Some(rest) = data.strip_prefix("hex(") {
+        let (type_num, hex_bytes) = rest.split_once("):").ok_or("invalid hex type prefix")?;
+        let type_num = u32::from_str_radix(type_num, 16).map_err(|_| "invalid hex type number")?;
+
+        Some(RegValue {
+            vtype: reg_type_from_num(type_num).ok_or("unsupported value type")?,
+            bytes: parse_hex_bytes(hex_bytes)?,
+        })
+    } else {
+        return Err("unknown value data format");
+    };
+
+    Ok(RegFileValue { name, value })
+}
+
+fn parse_quoted_str(text: &str) -> Option<(String, &str)> {
+    //! Parses a string in double quotes, in which backslashes and double quotes are escaped with a backslash, and returns it together with the remaining text.
+
+    let mut chars = text.strip_prefix('"')?.char_indices();
+    let mut string = String::new();
+
+    while let Some((index, char)) = chars.next() {
+        match char {
+            '\\' => string.push(chars.next()?.1),
+            '"' => return Some((string, &text[1 + index + 1..])),
+            _ => string.push(char),
+        }
+    }
+
+    None
+}
+
+fn parse_hex_bytes(text: &str) -> Result<Vec<u8>, &'static str> {
+    //! Parses comma-separated hex bytes. A trailing comma is allowed.
+
+    let text = text.trim();
+    let text = text.strip_suffix(',').unwrap_or(text);
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    text.split(',')
+        .map(|hex_byte| {
+            let hex_byte = hex_byte.trim();
+            if hex_byte.is_empty() || hex_byte.len() > 2 {
+                return Err("invalid hex byte");
+            }
+
+            u8::from_str_radix(hex_byte, 16).map_err(|_| "invalid hex byte")
+        })
+        .collect()
+}
+
+pub(super) fn reg_type_from_num(num: u32) -> Option<RegType> {
+    use RegType::*;
+
+    Some(match num {
+        0 => REG_NONE,
+        1 => REG_SZ,
+        2 => REG_EXPAND_SZ,
+        3 => REG_BINARY,
+        4 => REG_DWORD,
+        5 => REG_DWORD_BIG_ENDIAN,
+        6 => REG_LINK,
+        7 => REG_MULTI_SZ,
+        8 => REG_RESOURCE_LIST,
+        9 => REG_FULL_RESOURCE_DESCRIPTOR,
+        10 => REG_RESOURCE_REQUIREMENTS_LIST,
+        11 => REG_QWORD,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use winreg::{
+        enums::{RegType, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE},
+        RegValue,
+    };
+
+    use std::str::FromStr;
+
+    use super::{RegFile, RegFileError, RegFileKey, RegFileValue};
+    use crate::reg::value::RegData;
+
+    /// As exported by `regedit.exe`, including its line wrapping.
+    const REGEDIT_FILE: &str = "Windows Registry Editor Version 5.00\r
+\r
+[HKEY_CURRENT_USER\\Software\\sem-reg test]\r
+@=\"default\"\r
+\"String\"=\"C:\\\\Program Files\\\\\\\"quoted\\\"\"\r
+\"Dword\"=dword:0000002a\r
+\"Qword\"=hex(b):ff,ff,ff,ff,ff,ff,ff,fe\r
+\"Expand\"=hex(2):25,00,55,00,53,00,45,00,52,00,50,00,52,00,4f,00,46,00,49,00,4c,\\\r
+  00,45,00,25,00,00,00\r
+\"Multi\"=hex(7):61,00,00,00,62,00,00,00,00,00\r
+\"Binary\"=hex:43,42,01,00,0a,02,01,00,2a,06,a0,b8,db,aa,06,2a,2b,0e,15,43,42,01,\\\r
+  00,d0,0a,02,c6,14,b0,f4,ea,c9,a7,d9,eb,ee,01,00,00,00,00\r
+\"Empty\"=hex:\r
+\"None\"=hex(0):\r
+\"Deleted\"=-\r
+\r
+[-HKEY_LOCAL_MACHINE\\SOFTWARE\\sem-reg test]\r
+\r
+";
+
+    fn raw_value(data: RegData) -> Option<RegValue> {
+        Some(data.to_raw())
+    }
+
+    fn value(name: &str, value: Option<RegValue>) -> RegFileValue {
+        RegFileValue {
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn parse_regedit_file() {
+        let expected = RegFile {
+            keys: vec![
+                RegFileKey {
+                    hkey: HKEY_CURRENT_USER,
+                    subkey_path: "Software\\sem-reg test".to_string(),
+                    delete: false,
+                    values: vec![
+                        value("", raw_value(RegData::String("default".to_string()))),
+                        value(
+                            "String",
+                            raw_value(RegData::String("C:\\Program Files\\\"quoted\"".to_string())),
+                        ),
+                        value("Dword", raw_value(RegData::Dword(42))),
+                        value("Qword", raw_value(RegData::Qword(0xfeffffffffffffff))),
+                        value(
+                            "Expand",
+                            raw_value(RegData::ExpandString("%USERPROFILE%".to_string())),
+                        ),
+                        value(
+                            "Multi",
+                            raw_value(RegData::MultiString(vec!["a".to_string(), "b".to_string()])),
+                        ),
+                        value(
+                            "Binary",
+                            raw_value(RegData::Binary(vec![
+                                0x43, 0x42, 0x01, 0x00, 0x0a, 0x02, 0x01, 0x00, 0x2a, 0x06, 0xa0,
+                                0xb8, 0xdb, 0xaa, 0x06, 0x2a, 0x2b, 0x0e, 0x15, 0x43, 0x42, 0x01,
+                                0x00, 0xd0, 0x0a, 0x02, 0xc6, 0x14, 0xb0, 0xf4, 0xea, 0xc9, 0xa7,
+                                0xd9, 0xeb, 0xee, 0x01, 0x00, 0x00, 0x00, 0x00,
+                            ])),
+                        ),
+                        value("Empty", raw_value(RegData::Binary(vec![]))),
+                        value(
+                            "None",
+                            Some(RegValue {
+                                vtype: RegType::REG_NONE,
+                                bytes: vec![],
+                            }),
+                        ),
+                        value("Deleted", None),
+                    ],
+                },
+                RegFileKey {
+                    hkey: HKEY_LOCAL_MACHINE,
+                    subkey_path: "SOFTWARE\\sem-reg test".to_string(),
+                    delete: true,
+                    values: vec![],
+                },
+            ],
+        };
+
+        assert_eq!(RegFile::from_str(REGEDIT_FILE).unwrap(), expected);
+
+        // Encoded like `regedit.exe` saves the file.
+        let utf16_bytes: Vec<u8> = [0xfeff]
+            .into_iter()
+            .chain(REGEDIT_FILE.encode_utf16())
+            .flat_map(|int16: u16| int16.to_le_bytes())
+            .collect();
+        assert_eq!(RegFile::from_bytes(&utf16_bytes).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_exported_bin_value() {
+        // Unwrapped hex data, as written by earlier versions of this crate.
+        let text = "\u{feff}Windows Registry Editor Version 5.00\r\n\r\n[HKEY_CURRENT_USER\\Software\\Test]\r\n\"Data\"=hex:43,42,01,00\r\n\r\n";
+
+        let reg_file = RegFile::from_str(text).unwrap();
+        assert_eq!(reg_file.keys.len(), 1);
+        assert_eq!(
+            reg_file.keys[0].values,
+            vec![value(
+                "Data",
+                raw_value(RegData::Binary(vec![0x43, 0x42, 0x01, 0x00]))
+            )]
+        );
+    }
+
+    #[test]
+    fn regedit_file_round_trip() {
+        let reg_file = RegFile::from_str(REGEDIT_FILE).unwrap();
+        assert_eq!(reg_file.to_string(), REGEDIT_FILE);
+    }
+
+    #[test]
+    fn format_round_trip() {
+        let reg_file = RegFile {
+            keys: vec![RegFileKey {
+                hkey: HKEY_CURRENT_USER,
+                subkey_path: String::new(),
+                delete: false,
+                values: vec![
+                    value("Long", raw_value(RegData::Binary((0..=255).collect()))),
+                    value(
+                        "Line break",
+                        raw_value(RegData::String("a\r\nb".to_string())),
+                    ),
+                    value(
+                        "Unterminated",
+                        Some(RegValue {
+                            vtype: RegType::REG_SZ,
+                            bytes: vec![b'a', 0],
+                        }),
+                    ),
+                    value(
+                        "Short dword",
+                        Some(RegValue {
+                            vtype: RegType::REG_DWORD,
+                            bytes: vec![1],
+                        }),
+                    ),
+                    value("\"Quoted\" \\", raw_value(RegData::String(String::new()))),
+                ],
+            }],
+        };
+
+        let text = reg_file.to_string();
+        assert!(text.lines().all(|line| line.len() <= 80));
+        assert!(text.contains("\"Line break\"=hex(1):"));
+        assert!(text.contains("\"Unterminated\"=hex(1):61,00\r\n"));
+        assert!(text.contains("\"Short dword\"=hex(4):01\r\n"));
+        assert!(text.contains("\"\\\"Quoted\\\" \\\\\"=\"\"\r\n"));
+        assert_eq!(RegFile::from_str(&text).unwrap(), reg_file);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(
+            RegFile::from_str("REGEDIT4\r\n"),
+            Err(RegFileError::MissingHeader)
+        ));
+
+        for (text, error_line) in [
+            ("Windows Registry Editor Version 5.00\r\n\"a\"=dword:1\r\n", 2),
+            (
+                "Windows Registry Editor Version 5.00\r\n\r\n[HKEY_FOO\\Bar]\r\n",
+                3,
+            ),
+            (
+                "Windows Registry Editor Version 5.00\r\n[HKEY_CURRENT_USER\\A]\r\n\"a\"=dword:1\r\n",
+                3,
+            ),
+            (
+                "Windows Registry Editor Version 5.00\r\n[HKEY_CURRENT_USER\\A]\r\n\"a\"=hex:1,\\\r\n  2g\r\n",
+                3,
+            ),
+            (
+                "Windows Registry Editor Version 5.00\r\n[HKEY_CURRENT_USER\\A]\r\n\"a\"=hex(c):00\r\n",
+                3,
+            ),
+            (
+                "Windows Registry Editor Version 5.00\r\n[HKEY_CURRENT_USER\\A]\r\n\"a=\"b\"\r\n",
+                3,
+            ),
+        ] {
+            match RegFile::from_str(text) {
+                Err(RegFileError::SyntaxError { line, .. }) => assert_eq!(line, error_line),
+                result => panic!("unexpected result for {text:?}: {result:?}"),
+            }
+        }
+    }
+}
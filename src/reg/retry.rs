@@ -0,0 +1,147 @@
+//! Retrying of registry operations that fail transiently, e.g., with sharing violations while the CloudStore service rewrites a key during one of Windows' own bursts of updates. The reading, writing and deleting functions of the [`reg`](super) module, including the typed values and the imports of .reg files and value sets, use the process-wide policy set with [`set_policy()`].
+
+use std::{io, sync::Mutex, thread, time::Duration};
+use windows::Win32::Foundation::{
+    ERROR_BUSY, ERROR_KEY_DELETED, ERROR_LOCK_VIOLATION, ERROR_REGISTRY_IO_FAILED,
+    ERROR_SHARING_VIOLATION, WIN32_ERROR,
+};
+
+use crate::log_debug;
+
+/// Errors that are expected to go away after a short time.
+const TRANSIENT_ERRORS: [WIN32_ERROR; 5] = [
+    ERROR_SHARING_VIOLATION,
+    ERROR_LOCK_VIOLATION,
+    ERROR_BUSY,
+    ERROR_KEY_DELETED,
+    ERROR_REGISTRY_IO_FAILED,
+];
+
+static POLICY: Mutex<RetryPolicy> = Mutex::new(RetryPolicy::DEFAULT);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RetryPolicy {
+    /// The number of attempts including the first one. `1` disables retrying.
+    pub max_attempts: u32,
+    /// The delay before the second attempt. Doubled for every further attempt.
+    pub initial_delay: Duration,
+    /// The upper bound of the delays.
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub const DEFAULT: Self = Self {
+        max_attempts: 4,
+        initial_delay: Duration::from_millis(25),
+        max_delay: Duration::from_millis(500),
+    };
+
+    pub const NONE: Self = Self {
+        max_attempts: 1,
+        initial_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    pub fn delay(&self, num_failed_attempts: u32) -> Duration {
+        //! The delay after the number of failed attempts.
+
+        self.initial_delay
+            .saturating_mul(2_u32.saturating_pow(num_failed_attempts.saturating_sub(1)))
+            .min(self.max_delay)
+    }
+
+    pub fn run<T, F>(&self, mut f: F) -> Result<T, io::Error>
+    where
+        F: FnMut() -> Result<T, io::Error>,
+    {
+        //! Calls the function until it succeeds, fails with an error that isn't transient, or the attempts are used up.
+
+        let mut num_failed_attempts = 0;
+        loop {
+            match f() {
+                Err(error)
+                    if is_transient(&error) && num_failed_attempts + 1 < self.max_attempts =>
+                {
+                    num_failed_attempts += 1;
+                    log_debug!("retrying registry operation after transient error: {error}");
+                    thread::sleep(self.delay(num_failed_attempts));
+                }
+                result => break result,
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+pub fn set_policy(policy: RetryPolicy) {
+    //! Sets the process-wide policy. Until called, [`RetryPolicy::DEFAULT`] is used.
+
+    *POLICY.lock().unwrap() = policy;
+}
+
+pub fn policy() -> RetryPolicy {
+    *POLICY.lock().unwrap()
+}
+
+pub fn is_transient(error: &io::Error) -> bool {
+    error.raw_os_error().is_some_and(|code| {
+        TRANSIENT_ERRORS
+            .iter()
+            .any(|transient_error| transient_error.0 as i32 == code)
+    })
+}
+
+pub(crate) fn with_retries<T, F>(f: F) -> Result<T, io::Error>
+where
+    F: FnMut() -> Result<T, io::Error>,
+{
+    policy().run(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+    use windows::Win32::Foundation::{ERROR_ACCESS_DENIED, ERROR_SHARING_VIOLATION};
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn delays() {
+        let policy = RetryPolicy::DEFAULT;
+        assert_eq!(policy.delay(1), Duration::from_millis(25));
+        assert_eq!(policy.delay(3), Duration::from_millis(100));
+        assert_eq!(policy.delay(10), Duration::from_millis(500));
+        assert_eq!(policy.delay(100), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn retry_transient_errors() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        };
+        let failing = |code: u32, num_failures: u32| {
+            let mut num_calls = 0;
+            let result = policy.run(|| {
+                num_calls += 1;
+                if num_calls <= num_failures {
+                    Err(io::Error::from_raw_os_error(code as _))
+                } else {
+                    Ok(())
+                }
+            });
+            (result.is_ok(), num_calls)
+        };
+
+        assert_eq!(failing(ERROR_SHARING_VIOLATION.0, 2), (true, 3));
+        assert_eq!(failing(ERROR_SHARING_VIOLATION.0, 3), (false, 3));
+        assert_eq!(failing(ERROR_ACCESS_DENIED.0, 1), (false, 1));
+        assert_eq!(RetryPolicy::NONE.run(|| Ok::<_, io::Error>(1)).unwrap(), 1);
+    }
+}
@@ -0,0 +1,265 @@
+//! Security identifiers (SIDs) of users, in their string form like `S-1-5-21-...`, as used for the user hives under `HKEY_USERS`.
+
+use std::{env, io, path::PathBuf};
+use windows::{
+    core::{HSTRING, PWSTR},
+    Win32::{
+        Foundation::{ERROR_INSUFFICIENT_BUFFER, PSID},
+        Security::{
+            Authorization::ConvertSidToStringSidW, GetTokenInformation, LookupAccountNameW,
+            TokenUser, SID_AND_ATTRIBUTES, SID_NAME_USE, TOKEN_QUERY,
+        },
+        System::Threading::{GetCurrentProcess, OpenProcessToken},
+    },
+};
+use windows_helpers::{dual_call, FirstCallExpectation, ResGuard};
+use winreg::{
+    enums::{HKEY_LOCAL_MACHINE, HKEY_USERS, KEY_READ},
+    RegKey,
+};
+
+pub fn current_user_sid() -> Result<String, windows::core::Error> {
+    //! Returns the SID of the user the process runs as, whose hive `HKEY_CURRENT_USER` refers to.
+
+    let process_token_handle = ResGuard::with_mut_acq_and_close_handle(|handle| unsafe {
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, handle)
+    })?;
+
+    let mut sid_and_attrs_buffer = Vec::<u8>::new();
+    let mut sid_and_attrs_buffer_size = 0;
+
+    dual_call(
+        FirstCallExpectation::Win32Error(ERROR_INSUFFICIENT_BUFFER),
+        |getting_buffer_size| unsafe {
+            GetTokenInformation(
+                *process_token_handle,
+                TokenUser,
+                (!getting_buffer_size).then(|| {
+                    sid_and_attrs_buffer.resize(sid_and_attrs_buffer_size as _, 0);
+                    sid_and_attrs_buffer.as_mut_ptr().cast()
+                }),
+                sid_and_attrs_buffer_size,
+                &mut sid_and_attrs_buffer_size,
+            )
+        },
+    )?;
+
+    unsafe { sid_to_string((*sid_and_attrs_buffer.as_ptr().cast::<SID_AND_ATTRIBUTES>()).Sid) }
+}
+
+pub fn user_name_to_sid(user_name: &str) -> Result<String, windows::core::Error> {
+    //! Resolves an account name, either plain (`john`) or qualified with the domain or computer name (`DESKTOP-1234\john`), to the SID.
+
+    let account_name = HSTRING::from(user_name);
+
+    let mut sid_buffer = Vec::<u8>::new();
+    let mut sid_buffer_size = 0;
+    let mut domain_name_buffer = Vec::<u16>::new();
+    let mut domain_name_buffer_len = 0;
+    let mut sid_name_use = SID_NAME_USE::default();
+
+    dual_call(
+        FirstCallExpectation::Win32Error(ERROR_INSUFFICIENT_BUFFER),
+        |getting_buffer_size| unsafe {
+            let (sid, domain_name) = if getting_buffer_size {
+                (PSID::default(), PWSTR::null())
+            } else {
+                sid_buffer.resize(sid_buffer_size as _, 0);
+                domain_name_buffer.resize(domain_name_buffer_len as _, 0);
+                (
+                    PSID(sid_buffer.as_mut_ptr().cast()),
+                    PWSTR(domain_name_buffer.as_mut_ptr()),
+                )
+            };
+
+            LookupAccountNameW(
+                None,
+                &account_name,
+                sid,
+                &mut sid_buffer_size,
+                domain_name,
+                &mut domain_name_buffer_len,
+                &mut sid_name_use,
+            )
+        },
+    )?;
+
+    unsafe { sid_to_string(PSID(sid_buffer.as_mut_ptr().cast())) }
+}
+
+pub fn loaded_user_sids() -> Result<Vec<String>, io::Error> {
+    //! Returns the SIDs of all user hives loaded in `HKEY_USERS`, i.e., of the users that are logged on or whose hives were loaded by services. Omits the `.DEFAULT` hive and the `..._Classes` hives.
+
+    RegKey::predef(HKEY_USERS)
+        .enum_keys()
+        .filter(|result| result.as_ref().map_or(true, |name| is_user_hive_name(name)))
+        .collect()
+}
+
+/// Whose hives registry value paths under `HKEY_CURRENT_USER` refer to.
+#[derive(Clone, PartialEq, Debug)]
+pub enum UserScope {
+    /// Only the hive of the user running the process.
+    CurrentUser,
+    /// Only the hive of the user with the specified string SID, e.g., the interactive user's, when running as a service. The hive must be loaded, i.e., the user logged on. Accessing other users' hives may require elevation.
+    User(String),
+    /// Every user hive loaded in `HKEY_USERS` at the time of resolving that contains the respective key. Accessing other users' hives may require elevation.
+    AllUsers,
+}
+
+impl UserScope {
+    pub fn resolve(&self) -> Result<ScopedSids, io::Error> {
+        //! Determines the SIDs of the users in scope, so that they can be used for multiple registry value paths with [`OwnedRegValuePath::resolve_user_hives()`](super::OwnedRegValuePath::resolve_user_hives).
+
+        Ok(match self {
+            Self::CurrentUser => ScopedSids {
+                sids: vec![current_user_sid()?],
+                skips_missing_keys: false,
+            },
+            Self::User(sid) => ScopedSids {
+                sids: vec![sid.clone()],
+                skips_missing_keys: false,
+            },
+            Self::AllUsers => ScopedSids {
+                sids: loaded_user_sids()?,
+                skips_missing_keys: true,
+            },
+        })
+    }
+}
+
+/// The users of a [`UserScope`] at the time of resolving it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ScopedSids {
+    pub sids: Vec<String>,
+    /// Whether the hives not containing a path's key are left out, like with [`UserScope::AllUsers`].
+    pub skips_missing_keys: bool,
+}
+
+/// A user profile on the local machine, whose hive may not be loaded.
+#[derive(Clone, PartialEq, Debug)]
+pub struct UserProfile {
+    pub sid: String,
+    /// The directory containing the profile's hive file `NTUSER.DAT`.
+    pub dir_path: PathBuf,
+}
+
+impl UserProfile {
+    pub fn hive_file_path(&self) -> PathBuf {
+        self.dir_path.join("NTUSER.DAT")
+    }
+}
+
+pub fn local_user_profiles() -> Result<Vec<UserProfile>, io::Error> {
+    //! Returns the profiles of the accounts that ever logged on to the machine, also of those that aren't logged on. Omits the profiles of the service accounts `LocalSystem`, `LocalService` and `NetworkService`.
+
+    let profile_list_key = RegKey::predef(HKEY_LOCAL_MACHINE).open_subkey_with_flags(
+        r"SOFTWARE\Microsoft\Windows NT\CurrentVersion\ProfileList",
+        KEY_READ,
+    )?;
+
+    let mut profiles = Vec::new();
+    for sid in profile_list_key.enum_keys() {
+        let sid = sid?;
+        if !is_user_hive_name(&sid) || is_service_account_sid(&sid) {
+            continue;
+        }
+
+        //. Profiles being deleted may lack the path.
+        let Ok(dir_path) = profile_list_key
+            .open_subkey_with_flags(&sid, KEY_READ)
+            .and_then(|key| key.get_value::<String, _>("ProfileImagePath"))
+        else {
+            continue;
+        };
+
+        profiles.push(UserProfile {
+            sid,
+            dir_path: PathBuf::from(expand_env_vars(&dir_path)),
+        });
+    }
+
+    Ok(profiles)
+}
+
+fn is_service_account_sid(sid: &str) -> bool {
+    matches!(sid, "S-1-5-18" | "S-1-5-19" | "S-1-5-20")
+}
+
+fn expand_env_vars(string: &str) -> String {
+    //! Replaces `%NAME%` with the value of the environment variable, as needed for `REG_EXPAND_SZ` values. Unknown variables are kept.
+
+    let mut expanded = String::with_capacity(string.len());
+    let mut rest = string;
+    while let Some(start) = rest.find('%') {
+        expanded.push_str(&rest[..start]);
+        let after_start = &rest[start + 1..];
+
+        match after_start.find('%') {
+            Some(len) => {
+                let name = &after_start[..len];
+                match env::var(name) {
+                    Ok(value) if !name.is_empty() => expanded.push_str(&value),
+                    _ => expanded.push_str(&rest[start..start + len + 2]),
+                }
+                rest = &after_start[len + 1..];
+            }
+            None => {
+                expanded.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    expanded.push_str(rest);
+
+    expanded
+}
+
+fn is_user_hive_name(name: &str) -> bool {
+    name.starts_with("S-") && !name.ends_with("_Classes")
+}
+
+unsafe fn sid_to_string(sid: PSID) -> Result<String, windows::core::Error> {
+    Ok(
+        ResGuard::<PWSTR>::with_mut_acq_and_local_free(|pwstr| ConvertSidToStringSidW(sid, pwstr))?
+            .to_string()?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_env_vars, is_service_account_sid, is_user_hive_name};
+
+    #[test]
+    fn user_hive_names() {
+        assert!(is_user_hive_name("S-1-5-18"));
+        assert!(is_user_hive_name(
+            "S-1-5-21-1004336348-1177238915-682003330-1001"
+        ));
+        assert!(!is_user_hive_name(
+            "S-1-5-21-1004336348-1177238915-682003330-1001_Classes"
+        ));
+        assert!(!is_user_hive_name(".DEFAULT"));
+    }
+
+    #[test]
+    fn service_account_sids() {
+        assert!(is_service_account_sid("S-1-5-18"));
+        assert!(!is_service_account_sid(
+            "S-1-5-21-1004336348-1177238915-682003330-1001"
+        ));
+    }
+
+    #[test]
+    fn env_var_expansion() {
+        std::env::set_var("SEM_REG_TEST_DRIVE", "C:");
+        assert_eq!(
+            expand_env_vars(r"%SEM_REG_TEST_DRIVE%\Users\john"),
+            r"C:\Users\john"
+        );
+        assert_eq!(
+            expand_env_vars(r"%SEM_REG_TEST_UNKNOWN%\x%%y%"),
+            r"%SEM_REG_TEST_UNKNOWN%\x%%y%"
+        );
+    }
+}
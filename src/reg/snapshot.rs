@@ -0,0 +1,395 @@
+//! Snapshots of registry values of any type, including which of them didn't exist, to wrap risky changes: take a [`RegSnapshot`] before changing the values and restore it afterwards, possibly in another process after saving it to a file.
+//!
+//! Restoring is all-or-nothing as far as the registry allows: if values can't be restored, the values already restored are rolled back. For CloudStore values, prefer writing through their dedicated types, since Windows may ignore values whose prologue timestamp didn't advance.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs, io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use winreg::{enums::KEY_SET_VALUE, RegKey, RegValue};
+
+use super::{
+    delete_reg_value,
+    monitor::journal::full_key_path,
+    read_reg_values,
+    reg_file::{RegFile, RegFileKey, RegFileValue},
+    retry::with_retries,
+    value_set::{parse_value_path, RegValueSet, RegValueSetEntry, RegValueSetError},
+    AsRegValuePath, OwnedRegValuePath,
+};
+use crate::log_warn;
+
+/// The state of registry values at one point in time.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RegSnapshot {
+    /// Milliseconds since the Unix epoch when the snapshot was taken.
+    pub epoch_millis: u64,
+    /// The values that existed.
+    #[serde(flatten)]
+    pub value_set: RegValueSet,
+    /// The values that didn't exist, which restoring deletes.
+    pub missing: Vec<MissingValue>,
+}
+
+/// A registry value that didn't exist when a [`RegSnapshot`] was taken.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MissingValue {
+    /// The full path of the registry value's key, starting with the root key name.
+    pub key_path: String,
+    /// Empty for the default value of the key.
+    pub value_name: String,
+}
+
+impl RegSnapshot {
+    pub fn take<P: AsRegValuePath>(reg_value_paths: &[P]) -> Result<Self, io::Error> {
+        //! Reads the values. Values or keys that don't exist are recorded as missing, so that restoring deletes them.
+
+        let borrowed_paths = reg_value_paths
+            .iter()
+            .map(|reg_value_path| reg_value_path.as_reg_value_path())
+            .collect::<Vec<_>>();
+
+        let mut value_set = RegValueSet::default();
+        let mut missing = Vec::new();
+        for (reg_value_path, result) in borrowed_paths.iter().zip(read_reg_values(&borrowed_paths))
+        {
+            let reg_value_path = OwnedRegValuePath::from(*reg_value_path);
+            match result {
+                Ok(raw_value) => value_set
+                    .values
+                    .push(RegValueSetEntry::new(&reg_value_path, raw_value)),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    missing.push(MissingValue {
+                        key_path: full_key_path(&reg_value_path),
+                        value_name: reg_value_path.value_name,
+                    })
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(Self {
+            epoch_millis: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_millis() as u64),
+            value_set,
+            missing,
+        })
+    }
+
+    pub fn load<T: AsRef<Path>>(file_path: T) -> Result<Self, SnapshotError> {
+        //! Reads a snapshot saved with [`Self::save()`]. Fails for unknown root keys or types, so that restoring doesn't fail because of them.
+
+        let snapshot: Self = serde_json::from_str(&fs::read_to_string(file_path)?)?;
+        snapshot.reg_values()?;
+
+        Ok(snapshot)
+    }
+
+    pub fn save<T: AsRef<Path>>(&self, file_path: T) -> Result<(), SnapshotError> {
+        //! Writes the snapshot as JSON.
+
+        Ok(fs::write(file_path, serde_json::to_string_pretty(self)?)?)
+    }
+
+    pub fn reg_values(&self) -> Result<Vec<(OwnedRegValuePath, Option<RegValue>)>, SnapshotError> {
+        //! The paths and, except for the missing values, the values to write. The existing values come first.
+
+        let existing = self.value_set.values.iter().map(|entry| {
+            entry
+                .to_reg_value()
+                .map(|(reg_value_path, raw_value)| (reg_value_path, Some(raw_value)))
+        });
+        let missing = self.missing.iter().map(|value| {
+            parse_value_path(&value.key_path, &value.value_name)
+                .map(|reg_value_path| (reg_value_path, None))
+        });
+
+        Ok(existing.chain(missing).collect::<Result<_, _>>()?)
+    }
+
+    pub fn to_reg_file(&self) -> Result<RegFile, SnapshotError> {
+        //! A .reg file that restores the snapshot when imported, with deletion lines for the missing values. Values of the same key are put into the same key section.
+
+        let mut keys = Vec::<RegFileKey>::new();
+        for (reg_value_path, raw_value) in self.reg_values()? {
+            let value = RegFileValue {
+                name: reg_value_path.value_name,
+                value: raw_value,
+            };
+
+            match keys.iter_mut().find(|key| {
+                key.hkey == reg_value_path.hkey && key.subkey_path == reg_value_path.subkey_path
+            }) {
+                Some(key) => key.values.push(value),
+                None => keys.push(RegFileKey {
+                    hkey: reg_value_path.hkey,
+                    subkey_path: reg_value_path.subkey_path,
+                    delete: false,
+                    values: vec![value],
+                }),
+            }
+        }
+
+        Ok(RegFile { keys })
+    }
+
+    pub fn reg_value_paths(&self) -> Result<Vec<OwnedRegValuePath>, SnapshotError> {
+        Ok(self
+            .reg_values()?
+            .into_iter()
+            .map(|(reg_value_path, _)| reg_value_path)
+            .collect())
+    }
+
+    pub fn restore(&self) -> Result<(), SnapshotError> {
+        //! Writes the values, creating keys as needed, and deletes the values that were missing. Every value is attempted. If some values can't be restored, the values that were restored are set back to their state from before calling, and [`SnapshotError::RestoreFailed`] reports all values that failed.
+
+        let reg_values = self.reg_values()?;
+        let borrowed_paths = reg_values
+            .iter()
+            .map(|(reg_value_path, _)| reg_value_path.as_borrowed())
+            .collect::<Vec<_>>();
+        let previous_values = read_reg_values(&borrowed_paths)
+            .into_iter()
+            .map(|result| match result {
+                Ok(raw_value) => Ok(Some(raw_value)),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(error) => Err(error),
+            })
+            .collect::<Result<Vec<_>, io::Error>>()?;
+
+        let mut failures = Vec::new();
+        let mut restored = Vec::new();
+        for ((reg_value_path, raw_value), previous_value) in reg_values.iter().zip(previous_values)
+        {
+            match write_or_delete(reg_value_path, raw_value.as_ref()) {
+                Ok(()) => restored.push((reg_value_path, previous_value)),
+                Err(error) => failures.push(ValueFailure::new(reg_value_path, error)),
+            }
+        }
+
+        if failures.is_empty() {
+            return Ok(());
+        }
+
+        let rollback_failures = restored
+            .into_iter()
+            .rev()
+            .filter_map(|(reg_value_path, previous_value)| {
+                write_or_delete(reg_value_path, previous_value.as_ref())
+                    .err()
+                    .map(|error| ValueFailure::new(reg_value_path, error))
+            })
+            .collect();
+
+        Err(SnapshotError::RestoreFailed(RestoreFailure {
+            failures,
+            rollback_failures,
+        }))
+    }
+}
+
+fn write_or_delete(
+    reg_value_path: &OwnedRegValuePath,
+    raw_value: Option<&RegValue>,
+) -> Result<(), io::Error> {
+    //! Writes the value, creating the key as needed, or deletes it. Deleting a value that doesn't exist succeeds.
+
+    match raw_value {
+        Some(raw_value) => with_retries(|| {
+            let (key, _) = RegKey::predef(reg_value_path.hkey)
+                .create_subkey_with_flags(&reg_value_path.subkey_path, KEY_SET_VALUE)?;
+            key.set_raw_value(&reg_value_path.value_name, raw_value)
+        }),
+        None => match delete_reg_value(&reg_value_path.as_borrowed()) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        },
+    }
+}
+
+pub fn restore_on_error<P, F, R, E>(reg_value_paths: &[P], f: F) -> Result<R, E>
+where
+    P: AsRegValuePath,
+    F: FnOnce() -> Result<R, E>,
+    E: From<io::Error>,
+{
+    //! Takes a snapshot of the values before calling the function that changes them and restores it, if the function fails. The function's error is returned even if restoring fails, which is logged.
+
+    let snapshot = RegSnapshot::take(reg_value_paths)?;
+
+    f().inspect_err(|_| {
+        if let Err(error) = snapshot.restore() {
+            log_warn!("couldn't restore registry values after error: {error}");
+        }
+    })
+}
+
+/// A registry value that couldn't be written or deleted.
+#[derive(Debug)]
+pub struct ValueFailure {
+    pub key_path: String,
+    pub value_name: String,
+    pub error: io::Error,
+}
+
+impl ValueFailure {
+    fn new(reg_value_path: &OwnedRegValuePath, error: io::Error) -> Self {
+        Self {
+            key_path: full_key_path(reg_value_path),
+            value_name: reg_value_path.value_name.clone(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for ValueFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{}\"{}": {}"#,
+            self.key_path, self.value_name, self.error
+        )
+    }
+}
+
+/// The values that failed when restoring a snapshot, and those whose rollback failed in turn.
+#[derive(Debug)]
+pub struct RestoreFailure {
+    pub failures: Vec<ValueFailure>,
+    /// If empty, the registry is in the state from before restoring.
+    pub rollback_failures: Vec<ValueFailure>,
+}
+
+impl fmt::Display for RestoreFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |failures: &[ValueFailure]| {
+            failures
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; ")
+        };
+
+        write!(f, "{}", join(&self.failures))?;
+        if self.rollback_failures.is_empty() {
+            write!(f, " (rolled back)")
+        } else {
+            write!(f, " (rollback failed: {})", join(&self.rollback_failures))
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("{0}")]
+    ValueSetError(#[from] RegValueSetError),
+    #[error("couldn't restore registry values: {0}")]
+    RestoreFailed(RestoreFailure),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use winreg::enums::{RegType, HKEY_CURRENT_USER};
+
+    use super::{MissingValue, RegSnapshot, RestoreFailure, ValueFailure};
+    use crate::reg::{
+        value_set::{RegValueSet, RegValueSetEntry},
+        OwnedRegValuePath,
+    };
+
+    fn snapshot() -> RegSnapshot {
+        RegSnapshot {
+            epoch_millis: 1_700_000_000_000,
+            value_set: RegValueSet {
+                values: vec![RegValueSetEntry {
+                    key_path: r"HKEY_CURRENT_USER\Software\Example".to_string(),
+                    value_name: "Data".to_string(),
+                    type_name: "REG_BINARY".to_string(),
+                    data: vec![0x43, 0x42],
+                }],
+            },
+            missing: vec![MissingValue {
+                key_path: r"HKEY_CURRENT_USER\Software\Example".to_string(),
+                value_name: "Missing".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let snapshot = snapshot();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert!(json.contains(r#""data":"4342""#));
+        assert!(json.contains(r#""missing":[{"keyPath""#));
+        assert_eq!(
+            serde_json::from_str::<RegSnapshot>(&json).unwrap(),
+            snapshot
+        );
+    }
+
+    #[test]
+    fn reg_file() {
+        let reg_file = snapshot().to_reg_file().unwrap();
+        assert_eq!(reg_file.keys.len(), 1);
+        assert_eq!(reg_file.keys[0].subkey_path, r"Software\Example");
+        assert_eq!(reg_file.keys[0].values.len(), 2);
+        assert!(reg_file.keys[0].values[1].value.is_none());
+        assert!(reg_file
+            .to_string()
+            .contains("\"Data\"=hex:43,42\r\n\"Missing\"=-"));
+    }
+
+    #[test]
+    fn reg_values() {
+        let mut snapshot = snapshot();
+        let reg_values = snapshot.reg_values().unwrap();
+        assert_eq!(
+            reg_values[0].0,
+            OwnedRegValuePath::new(HKEY_CURRENT_USER, r"Software\Example", "Data")
+        );
+        assert_eq!(reg_values[0].1.as_ref().unwrap().vtype, RegType::REG_BINARY);
+        assert!(reg_values[1].1.is_none());
+
+        snapshot.value_set.values[0].type_name = "REG_UNKNOWN".to_string();
+        assert!(snapshot.reg_value_paths().is_err());
+
+        snapshot = self::snapshot();
+        snapshot.missing[0].key_path = r"HKEY_UNKNOWN\Software".to_string();
+        assert!(snapshot.reg_value_paths().is_err());
+    }
+
+    #[test]
+    fn restore_failure_message() {
+        let reg_value_path = OwnedRegValuePath::new(HKEY_CURRENT_USER, r"Software\Example", "Data");
+        let failure = RestoreFailure {
+            failures: vec![
+                ValueFailure::new(
+                    &reg_value_path,
+                    io::Error::from(io::ErrorKind::PermissionDenied),
+                ),
+                ValueFailure::new(
+                    &reg_value_path.clone().join("Sub"),
+                    io::Error::from(io::ErrorKind::PermissionDenied),
+                ),
+            ],
+            rollback_failures: Vec::new(),
+        };
+
+        assert_eq!(
+            failure.to_string(),
+            r#"HKEY_CURRENT_USER\Software\Example\"Data": permission denied; HKEY_CURRENT_USER\Software\Example\Sub\"Data": permission denied (rolled back)"#
+        );
+    }
+}
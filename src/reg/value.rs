@@ -0,0 +1,291 @@
+//! Typed access to registry values of the common types, for settings that aren't stored as binary values.
+
+use std::io;
+use winreg::{
+    enums::{RegType, KEY_QUERY_VALUE, KEY_SET_VALUE},
+    RegKey, RegValue,
+};
+
+use super::{monitor, retry::with_retries, RegValuePath};
+
+/// The data of a registry value, interpreted according to its type.
+#[derive(Clone, PartialEq, Debug)]
+pub enum RegData {
+    /// `REG_BINARY`.
+    Binary(Vec<u8>),
+    /// `REG_DWORD` (little endian).
+    Dword(u32),
+    /// `REG_QWORD` (little endian).
+    Qword(u64),
+    /// `REG_SZ`.
+    String(String),
+    /// `REG_EXPAND_SZ`. Environment variable references aren't expanded.
+    ExpandString(String),
+    /// `REG_MULTI_SZ`.
+    MultiString(Vec<String>),
+}
+
+impl RegData {
+    pub fn from_raw(raw_value: &RegValue) -> Result<Self, io::Error> {
+        //! Fails on types other than those of the variants, and on byte counts or strings not fitting the type.
+
+        let bytes = &raw_value.bytes;
+        Ok(match raw_value.vtype {
+            RegType::REG_BINARY => Self::Binary(bytes.clone()),
+            RegType::REG_DWORD => Self::Dword(u32::from_le_bytes(
+                bytes.as_slice().try_into().map_err(|_| invalid_data())?,
+            )),
+            RegType::REG_QWORD => Self::Qword(u64::from_le_bytes(
+                bytes.as_slice().try_into().map_err(|_| invalid_data())?,
+            )),
+            RegType::REG_SZ => Self::String(utf16_le_bytes_to_string(bytes)?),
+            RegType::REG_EXPAND_SZ => Self::ExpandString(utf16_le_bytes_to_string(bytes)?),
+            RegType::REG_MULTI_SZ => {
+                let string = utf16_le_bytes_to_string(bytes)?;
+                Self::MultiString(
+                    string
+                        .split('\0')
+                        .take_while(|string| !string.is_empty())
+                        .map(|string| string.to_string())
+                        .collect(),
+                )
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unsupported registry value type",
+                ))
+            }
+        })
+    }
+
+    pub fn to_raw(&self) -> RegValue {
+        RegValue {
+            vtype: self.reg_type(),
+            bytes: match self {
+                Self::Binary(bytes) => bytes.clone(),
+                Self::Dword(dword) => dword.to_le_bytes().to_vec(),
+                Self::Qword(qword) => qword.to_le_bytes().to_vec(),
+                Self::String(string) | Self::ExpandString(string) => str_to_utf16_le_bytes(string),
+                Self::MultiString(strings) => {
+                    let mut bytes = Vec::new();
+                    for string in strings {
+                        bytes.extend(str_to_utf16_le_bytes(string));
+                    }
+                    // Final terminator of the list.
+                    bytes.extend([0, 0]);
+                    bytes
+                }
+            },
+        }
+    }
+
+    pub fn reg_type(&self) -> RegType {
+        match self {
+            Self::Binary(_) => RegType::REG_BINARY,
+            Self::Dword(_) => RegType::REG_DWORD,
+            Self::Qword(_) => RegType::REG_QWORD,
+            Self::String(_) => RegType::REG_SZ,
+            Self::ExpandString(_) => RegType::REG_EXPAND_SZ,
+            Self::MultiString(_) => RegType::REG_MULTI_SZ,
+        }
+    }
+}
+
+/// A Rust type that corresponds to one or more [`RegData`] variants.
+pub trait RegDataType: Sized {
+    /// Returns `None`, if the variant doesn't correspond to the type.
+    fn from_reg_data(data: RegData) -> Option<Self>;
+    fn into_reg_data(self) -> RegData;
+}
+
+impl RegDataType for Vec<u8> {
+    fn from_reg_data(data: RegData) -> Option<Self> {
+        match data {
+            RegData::Binary(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    fn into_reg_data(self) -> RegData {
+        RegData::Binary(self)
+    }
+}
+
+impl RegDataType for u32 {
+    fn from_reg_data(data: RegData) -> Option<Self> {
+        match data {
+            RegData::Dword(dword) => Some(dword),
+            _ => None,
+        }
+    }
+
+    fn into_reg_data(self) -> RegData {
+        RegData::Dword(self)
+    }
+}
+
+impl RegDataType for u64 {
+    fn from_reg_data(data: RegData) -> Option<Self> {
+        match data {
+            RegData::Qword(qword) => Some(qword),
+            _ => None,
+        }
+    }
+
+    fn into_reg_data(self) -> RegData {
+        RegData::Qword(self)
+    }
+}
+
+impl RegDataType for String {
+    fn from_reg_data(data: RegData) -> Option<Self> {
+        //! Accepts `REG_SZ` as well as `REG_EXPAND_SZ`.
+
+        match data {
+            RegData::String(string) | RegData::ExpandString(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    fn into_reg_data(self) -> RegData {
+        //! Always makes a `REG_SZ` value.
+
+        RegData::String(self)
+    }
+}
+
+impl RegDataType for Vec<String> {
+    fn from_reg_data(data: RegData) -> Option<Self> {
+        match data {
+            RegData::MultiString(strings) => Some(strings),
+            _ => None,
+        }
+    }
+
+    fn into_reg_data(self) -> RegData {
+        RegData::MultiString(self)
+    }
+}
+
+pub fn read_reg_value(reg_value_path: &RegValuePath) -> Result<RegData, io::Error> {
+    let raw_value = with_retries(|| {
+        RegKey::predef(reg_value_path.hkey)
+            .open_subkey_with_flags(reg_value_path.subkey_path, KEY_QUERY_VALUE)?
+            .get_raw_value(reg_value_path.value_name)
+    })?;
+    RegData::from_raw(&raw_value)
+}
+
+pub fn write_reg_value(reg_value_path: &RegValuePath, data: &RegData) -> Result<(), io::Error> {
+    let raw_value = data.to_raw();
+    with_retries(|| {
+        RegKey::predef(reg_value_path.hkey)
+            .open_subkey_with_flags(reg_value_path.subkey_path, KEY_SET_VALUE)?
+            .set_raw_value(reg_value_path.value_name, &raw_value)
+    })?;
+
+    monitor::stats::record_write(reg_value_path);
+    Ok(())
+}
+
+pub fn read_reg_value_as<T: RegDataType>(reg_value_path: &RegValuePath) -> Result<T, io::Error> {
+    //! Reads a registry value and fails, if its type doesn't correspond to `T`.
+
+    T::from_reg_data(read_reg_value(reg_value_path)?).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "registry value type doesn't correspond to requested type",
+        )
+    })
+}
+
+pub fn write_reg_value_as<T: RegDataType>(
+    reg_value_path: &RegValuePath,
+    value: T,
+) -> Result<(), io::Error> {
+    write_reg_value(reg_value_path, &value.into_reg_data())
+}
+
+fn utf16_le_bytes_to_string(bytes: &[u8]) -> Result<String, io::Error> {
+    //! Decodes the bytes, stripping trailing null terminators. (The terminator may also be missing, since the registry doesn't enforce it.) Also used for `REG_MULTI_SZ`, in which case the caller needs to split the string.
+
+    let pairs = bytes.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(invalid_data());
+    }
+
+    let mut utf16: Vec<u16> = pairs
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+
+    // Remove trailing null terminators.
+    while utf16.last() == Some(&0) {
+        utf16.pop();
+    }
+
+    String::from_utf16(&utf16).map_err(|_| invalid_data())
+}
+
+fn str_to_utf16_le_bytes(string: &str) -> Vec<u8> {
+    //! Encodes the string including a null terminator.
+
+    string
+        .encode_utf16()
+        .chain([0])
+        .flat_map(|int16| int16.to_le_bytes())
+        .collect()
+}
+
+fn invalid_data() -> io::Error {
+    io::Error::from(io::ErrorKind::InvalidData)
+}
+
+#[cfg(test)]
+mod tests {
+    use winreg::{enums::RegType, RegValue};
+
+    use super::RegData;
+
+    #[test]
+    fn raw_round_trip() {
+        for data in [
+            RegData::Binary(vec![0x43, 0x42, 0x01, 0x00]),
+            RegData::Dword(0xdeadbeef),
+            RegData::Qword(u64::MAX - 1),
+            RegData::String("Allow".to_string()),
+            RegData::ExpandString(r"%USERPROFILE%\Pictures".to_string()),
+            RegData::MultiString(vec!["first".to_string(), "sëcond".to_string()]),
+            RegData::MultiString(vec![]),
+        ] {
+            assert_eq!(RegData::from_raw(&data.to_raw()).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn from_raw_edge_cases() {
+        // Missing null terminator.
+        let raw_value = RegValue {
+            vtype: RegType::REG_SZ,
+            bytes: vec![b'a', 0, b'b', 0],
+        };
+        assert_eq!(
+            RegData::from_raw(&raw_value).unwrap(),
+            RegData::String("ab".to_string())
+        );
+
+        // Odd number of bytes.
+        let raw_value = RegValue {
+            vtype: RegType::REG_SZ,
+            bytes: vec![b'a', 0, 0],
+        };
+        assert!(RegData::from_raw(&raw_value).is_err());
+
+        // Wrong size.
+        let raw_value = RegValue {
+            vtype: RegType::REG_DWORD,
+            bytes: vec![1, 0, 0],
+        };
+        assert!(RegData::from_raw(&raw_value).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+//! Sets of registry values as JSON or TOML documents, which are easier to diff, review and template than .reg files, e.g., in configuration-management pipelines.
+
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+use thiserror::Error;
+use winreg::{enums::RegType, RegKey, RegValue};
+
+use super::{
+    monitor::journal::full_key_path, read_reg_values, reg_file::reg_type_from_num,
+    retry::with_retries, str_to_hkey, AsRegValuePath, OwnedRegValuePath,
+};
+use crate::data_conversion::hex_bytes::hex_str_serde;
+
+/// A set of registry values with their types and data.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct RegValueSet {
+    pub values: Vec<RegValueSetEntry>,
+}
+
+/// A registry value in a [`RegValueSet`].
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RegValueSetEntry {
+    /// The full path of the registry value's key, starting with the root key name, like in .reg files.
+    pub key_path: String,
+    /// Empty for the default value of the key.
+    pub value_name: String,
+    /// The type name, like `REG_BINARY`.
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// The raw bytes, (de)serialized as a string of hex digit pairs.
+    #[serde(with = "hex_str_serde")]
+    pub data: Vec<u8>,
+}
+
+/// The document format of a [`RegValueSet`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RegValueSetFormat {
+    Json,
+    Toml,
+}
+
+impl RegValueSetFormat {
+    pub fn from_file_path<T: AsRef<Path>>(file_path: T) -> Option<Self> {
+        //! Determines the format from the file extension (`.json` or `.toml`, case-insensitive).
+
+        let extension = file_path.as_ref().extension()?.to_str()?;
+        if extension.eq_ignore_ascii_case("json") {
+            Some(Self::Json)
+        } else if extension.eq_ignore_ascii_case("toml") {
+            Some(Self::Toml)
+        } else {
+            None
+        }
+    }
+}
+
+impl RegValueSet {
+    pub fn from_reg_values<P: AsRegValuePath>(reg_value_paths: &[P]) -> Result<Self, io::Error> {
+        //! Reads the values of any type from the registry. Fails, if one of them doesn't exist.
+
+        Ok(Self {
+            values: read_entries(reg_value_paths)
+                .into_iter()
+                .collect::<Result<_, io::Error>>()?,
+        })
+    }
+
+    pub fn from_existing_reg_values<P: AsRegValuePath>(reg_value_paths: &[P]) -> Self {
+        //! Like [`Self::from_reg_values()`], but leaves out the values that don't exist or can't be read.
+
+        Self {
+            values: read_entries(reg_value_paths)
+                .into_iter()
+                .filter_map(Result::ok)
+                .collect(),
+        }
+    }
+
+    pub fn from_file<T: AsRef<Path>>(file_path: T) -> Result<Self, RegValueSetError> {
+        //! The format is determined by the file extension.
+
+        let format = RegValueSetFormat::from_file_path(&file_path)
+            .ok_or(RegValueSetError::UnknownFileExtension)?;
+        Self::from_str(&fs::read_to_string(file_path)?, format)
+    }
+
+    pub fn from_str(text: &str, format: RegValueSetFormat) -> Result<Self, RegValueSetError> {
+        let set: Self = match format {
+            RegValueSetFormat::Json => serde_json::from_str(text)?,
+            RegValueSetFormat::Toml => toml::from_str(text)?,
+        };
+
+        // Validate, so that importing doesn't fail half-way.
+        for entry in &set.values {
+            entry.to_reg_value()?;
+        }
+
+        Ok(set)
+    }
+
+    pub fn to_string(&self, format: RegValueSetFormat) -> Result<String, RegValueSetError> {
+        Ok(match format {
+            RegValueSetFormat::Json => serde_json::to_string_pretty(self)?,
+            RegValueSetFormat::Toml => toml::to_string(self)?,
+        })
+    }
+
+    pub fn export<T: AsRef<Path>>(&self, file_path: T) -> Result<(), RegValueSetError> {
+        //! The format is determined by the file extension.
+
+        let format = RegValueSetFormat::from_file_path(&file_path)
+            .ok_or(RegValueSetError::UnknownFileExtension)?;
+        Ok(fs::write(file_path, self.to_string(format)?)?)
+    }
+
+    pub fn import(&self) -> Result<(), RegValueSetError> {
+        //! Writes the values to the registry, creating keys as needed.
+
+        for entry in &self.values {
+            let (reg_value_path, raw_value) = entry.to_reg_value()?;
+            with_retries(|| {
+                let (key, _) = RegKey::predef(reg_value_path.hkey)
+                    .create_subkey(&reg_value_path.subkey_path)?;
+                key.set_raw_value(&reg_value_path.value_name, &raw_value)
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+impl RegValueSetEntry {
+    pub fn new(reg_value_path: &OwnedRegValuePath, raw_value: RegValue) -> Self {
+        Self {
+            key_path: full_key_path(reg_value_path),
+            value_name: reg_value_path.value_name.clone(),
+            type_name: format!("{:?}", raw_value.vtype),
+            data: raw_value.bytes,
+        }
+    }
+
+    pub fn to_reg_value(&self) -> Result<(OwnedRegValuePath, RegValue), RegValueSetError> {
+        Ok((
+            parse_value_path(&self.key_path, &self.value_name)?,
+            RegValue {
+                vtype: reg_type_from_name(&self.type_name)
+                    .ok_or_else(|| RegValueSetError::UnknownType(self.type_name.clone()))?,
+                bytes: self.data.clone(),
+            },
+        ))
+    }
+}
+
+fn read_entries<P: AsRegValuePath>(
+    reg_value_paths: &[P],
+) -> Vec<Result<RegValueSetEntry, io::Error>> {
+    let borrowed_paths: Vec<_> = reg_value_paths
+        .iter()
+        .map(|reg_value_path| reg_value_path.as_reg_value_path())
+        .collect();
+
+    borrowed_paths
+        .iter()
+        .zip(read_reg_values(&borrowed_paths))
+        .map(|(reg_value_path, result)| {
+            Ok(RegValueSetEntry::new(&(*reg_value_path).into(), result?))
+        })
+        .collect()
+}
+
+pub(super) fn parse_value_path(
+    key_path: &str,
+    value_name: &str,
+) -> Result<OwnedRegValuePath, RegValueSetError> {
+    //! The reverse of how [`RegValueSetEntry::new()`] makes the key path.
+
+    let (root_name, subkey_path) = key_path.split_once('\\').unwrap_or((key_path, ""));
+    let hkey = str_to_hkey(root_name)
+        .ok_or_else(|| RegValueSetError::UnknownRootKey(root_name.to_string()))?;
+
+    Ok(OwnedRegValuePath::new(hkey, subkey_path, value_name))
+}
+
+pub(super) fn reg_type_from_name(name: &str) -> Option<RegType> {
+    (0..)
+        .map_while(reg_type_from_num)
+        .find(|reg_type| format!("{reg_type:?}") == name)
+}
+
+#[derive(Error, Debug)]
+pub enum RegValueSetError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("TOML serialization error: {0}")]
+    TomlSerError(#[from] toml::ser::Error),
+    #[error("TOML deserialization error: {0}")]
+    TomlDeError(#[from] toml::de::Error),
+    #[error("file extension is neither `.json` nor `.toml`")]
+    UnknownFileExtension,
+    #[error("unknown root key: {0}")]
+    UnknownRootKey(String),
+    #[error("unknown registry value type: {0}")]
+    UnknownType(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use winreg::{
+        enums::{RegType, HKEY_CURRENT_USER},
+        RegValue,
+    };
+
+    use super::{RegValueSet, RegValueSetEntry, RegValueSetFormat};
+    use crate::reg::OwnedRegValuePath;
+
+    fn value_set() -> RegValueSet {
+        RegValueSet {
+            values: vec![
+                RegValueSetEntry {
+                    key_path: r"HKEY_CURRENT_USER\Software\Test".to_string(),
+                    value_name: "Data".to_string(),
+                    type_name: "REG_BINARY".to_string(),
+                    data: vec![0x43, 0x42, 0x01, 0x00],
+                },
+                RegValueSetEntry {
+                    key_path: r"HKEY_CURRENT_USER\Software\Test".to_string(),
+                    value_name: String::new(),
+                    type_name: "REG_DWORD".to_string(),
+                    data: vec![0x01, 0x00, 0x00, 0x00],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn document_round_trip() {
+        for format in [RegValueSetFormat::Json, RegValueSetFormat::Toml] {
+            let text = value_set().to_string(format).unwrap();
+            assert_eq!(RegValueSet::from_str(&text, format).unwrap(), value_set());
+        }
+    }
+
+    #[test]
+    fn json_document() {
+        let text = value_set().to_string(RegValueSetFormat::Json).unwrap();
+        assert!(text.contains(r#""type": "REG_BINARY""#));
+        assert!(text.contains(r#""data": "43420100""#));
+
+        let invalid_text = text.replace("REG_DWORD", "REG_NUMBER");
+        assert!(RegValueSet::from_str(&invalid_text, RegValueSetFormat::Json).is_err());
+    }
+
+    #[test]
+    fn entry_to_reg_value() {
+        assert_eq!(
+            value_set().values[0].to_reg_value().unwrap(),
+            (
+                OwnedRegValuePath::new(HKEY_CURRENT_USER, r"Software\Test", "Data"),
+                RegValue {
+                    vtype: RegType::REG_BINARY,
+                    bytes: vec![0x43, 0x42, 0x01, 0x00],
+                }
+            )
+        );
+    }
+}
@@ -0,0 +1,392 @@
+//! A write-ahead log for sequences of registry value writes that belong together, like the Night Light settings and state values. Before the first write, an intent file with the old and new bytes of all values is written; it's removed after the last write. Intent files left behind by crashed processes can be found with [`pending()`] and then completed or rolled back.
+//!
+//! Each sequence gets its own file in the directory [`Config::wal_dir_path()`], so that concurrent processes don't interfere. Single writes are recorded as well, so that every change of a binary value can be found and rolled back, even though the registry performs it atomically.
+//!
+//! Only binary values are recorded, because the log doesn't store value types. Writes of typed values, like of the personalization settings, imports of .reg files and value sets, and snapshot restores bypass the log.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use windows::Win32::{
+    Foundation::{ERROR_INVALID_PARAMETER, STILL_ACTIVE},
+    System::Threading::{GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION},
+};
+use windows_helpers::ResGuard;
+
+use super::{
+    delete_reg_value,
+    monitor::journal::full_key_path,
+    provider::{RegProvider, WinRegProvider},
+    read_reg_value_bytes, str_to_hkey, write_reg_bin_value_creating_key, OwnedRegValuePath,
+    RegValuePath,
+};
+use crate::{
+    cloud_store::prologue::CloudStoreValuePrologue,
+    config::Config,
+    data_conversion::{
+        hex_bytes::opt_hex_str_serde,
+        time::{epoch_duration_to_epoch_secs, next_epoch_secs, now_as_epoch_duration},
+    },
+    log_warn,
+};
+
+static NEXT_SEQUENCE_NUMBER: AtomicU32 = AtomicU32::new(0);
+
+/// The contents of an intent file.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Intent {
+    /// The ID of the process performing the writes.
+    pub pid: u32,
+    /// Milliseconds since the Unix epoch when the intent was written.
+    pub epoch_millis: u64,
+    /// In the order they're performed.
+    pub writes: Vec<IntendedWrite>,
+}
+
+impl Intent {
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.epoch_millis)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IntendedWrite {
+    /// The full path of the registry value's key, starting with the root key name, like in .reg files.
+    pub key_path: String,
+    pub value_name: String,
+    /// `None` if the value didn't exist.
+    #[serde(with = "opt_hex_str_serde")]
+    pub old_bytes: Option<Vec<u8>>,
+    /// `None` if the value is deleted.
+    #[serde(with = "opt_hex_str_serde")]
+    pub new_bytes: Option<Vec<u8>>,
+}
+
+/// Whether an intended write happened, determined by comparing the current bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WriteStatus {
+    /// The value has the new bytes.
+    Applied,
+    /// The value still has the old bytes.
+    NotApplied,
+    /// The value has neither the old nor the new bytes, e.g., because it was changed after the crash. It's left alone on recovery.
+    Diverged,
+}
+
+/// An ongoing sequence of writes. Call [`Self::commit()`] when the writes ended, even if one failed, because the process can then report the failure itself. A dropped transaction keeps its intent file, like after a crash.
+pub struct Transaction {
+    /// `None`, if the intent file couldn't be written.
+    file_path: Option<PathBuf>,
+}
+
+impl Transaction {
+    pub fn begin<'a, I>(writes: I) -> Self
+    where
+        I: IntoIterator<Item = (RegValuePath<'a>, Option<&'a [u8]>)>,
+    {
+        //! Writes the intent file with the current bytes as the old bytes. `None` as the new bytes means deleting the value. Failing to write the intent file is logged but doesn't prevent the writes, so that settings can still be changed.
+
+        let intent = Intent {
+            pid: process::id(),
+            epoch_millis: epoch_millis(SystemTime::now()),
+            writes: writes
+                .into_iter()
+                .map(|(reg_value_path, new_bytes)| IntendedWrite {
+                    key_path: full_key_path(&reg_value_path.into()),
+                    value_name: reg_value_path.value_name.to_string(),
+                    old_bytes: read_reg_value_bytes(&reg_value_path).ok(),
+                    new_bytes: new_bytes.map(<[u8]>::to_vec),
+                })
+                .collect(),
+        };
+
+        let file_path = write_intent_file(&intent)
+            .map_err(|error| log_warn!("couldn't write write-ahead log: {error}"))
+            .ok();
+
+        Self { file_path }
+    }
+
+    pub fn commit(self) {
+        if let Some(file_path) = &self.file_path {
+            if let Err(error) = fs::remove_file(file_path) {
+                log_warn!("couldn't remove write-ahead log file: {error}");
+            }
+        }
+    }
+}
+
+pub fn write_reg_bin_value_logged(
+    reg_value_path: &RegValuePath,
+    bytes: &[u8],
+    creates_key: bool,
+) -> Result<(), io::Error> {
+    //! Writes a single binary value in its own transaction. With `creates_key`, the key is created if necessary.
+
+    let transaction = Transaction::begin([(*reg_value_path, Some(bytes))]);
+    let result = WinRegProvider.write_bin_value(reg_value_path, bytes, creates_key);
+    transaction.commit();
+
+    result
+}
+
+/// An intent file of a process that isn't running anymore.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PendingTransaction {
+    pub file_path: PathBuf,
+    pub intent: Intent,
+}
+
+impl PendingTransaction {
+    pub fn statuses(&self) -> Result<Vec<WriteStatus>, io::Error> {
+        self.intent
+            .writes
+            .iter()
+            .map(|write| {
+                let current_bytes =
+                    read_reg_value_bytes(&reg_value_path(write)?.as_borrowed()).ok();
+                Ok(write_status(write, current_bytes.as_deref()))
+            })
+            .collect()
+    }
+
+    pub fn complete(self) -> Result<(), io::Error> {
+        //! Performs the writes that weren't applied and removes the intent file.
+
+        self.resolve(WriteStatus::NotApplied, |write| write.new_bytes.clone())
+    }
+
+    pub fn roll_back(self) -> Result<(), io::Error> {
+        //! Restores the old bytes of the values that were written, or deletes values that didn't exist, and removes the intent file. Old CloudStore values get a timestamp newer than the written ones', since Windows would revert them otherwise.
+
+        let now_epoch_secs = epoch_duration_to_epoch_secs(now_as_epoch_duration());
+        self.resolve(WriteStatus::Applied, |write| {
+            write.old_bytes.as_deref().map(|old_bytes| {
+                rollback_bytes(old_bytes, write.new_bytes.as_deref(), now_epoch_secs)
+            })
+        })
+    }
+
+    pub fn discard(self) -> Result<(), io::Error> {
+        //! Removes the intent file without changing values.
+
+        fs::remove_file(&self.file_path)
+    }
+
+    fn resolve<F>(self, status_to_change: WriteStatus, target_bytes: F) -> Result<(), io::Error>
+    where
+        F: Fn(&IntendedWrite) -> Option<Vec<u8>>,
+    {
+        let statuses = self.statuses()?;
+        let mut writes = self.intent.writes.iter().zip(statuses).collect::<Vec<_>>();
+        //. Roll back in reverse order.
+        if status_to_change == WriteStatus::Applied {
+            writes.reverse();
+        }
+
+        for (write, status) in writes {
+            if status != status_to_change {
+                continue;
+            }
+
+            let path = reg_value_path(write)?;
+            match target_bytes(write) {
+                Some(bytes) => write_reg_bin_value_creating_key(&path.as_borrowed(), &bytes)?,
+                None => delete_reg_value(&path.as_borrowed())?,
+            }
+        }
+
+        self.discard()
+    }
+}
+
+pub fn pending() -> Result<Vec<PendingTransaction>, io::Error> {
+    //! Reads the intent files of processes that aren't running anymore, oldest first. Unreadable files are skipped.
+
+    let Some(dir_path) = Config::wal_dir_path() else {
+        return Ok(Vec::new());
+    };
+    let entries = match fs::read_dir(dir_path) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+
+    let mut transactions = Vec::new();
+    for entry in entries {
+        let file_path = entry?.path();
+        if file_path
+            .extension()
+            .map_or(true, |extension| extension != "json")
+        {
+            continue;
+        }
+
+        let Ok(intent) = fs::read_to_string(&file_path)
+            .map_err(|_| ())
+            .and_then(|text| serde_json::from_str::<Intent>(&text).map_err(|_| ()))
+        else {
+            continue;
+        };
+
+        if !is_process_running(intent.pid) {
+            transactions.push(PendingTransaction { file_path, intent });
+        }
+    }
+
+    transactions.sort_by_key(|transaction| transaction.intent.epoch_millis);
+    Ok(transactions)
+}
+
+fn write_intent_file(intent: &Intent) -> Result<PathBuf, io::Error> {
+    let dir_path = Config::wal_dir_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "'APPDATA' not set"))?;
+    fs::create_dir_all(&dir_path)?;
+
+    let file_name = format!(
+        "{}-{}",
+        intent.pid,
+        NEXT_SEQUENCE_NUMBER.fetch_add(1, Ordering::Relaxed)
+    );
+    let temp_file_path = dir_path.join(format!("{file_name}.tmp"));
+    let file_path = dir_path.join(format!("{file_name}.json"));
+
+    //. Rename a synced file, so that there's never an incomplete intent file.
+    let mut file = File::create(&temp_file_path)?;
+    file.write_all(serde_json::to_string(intent)?.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(temp_file_path, &file_path)?;
+
+    Ok(file_path)
+}
+
+fn reg_value_path(write: &IntendedWrite) -> Result<OwnedRegValuePath, io::Error> {
+    let (root_name, subkey_path) = write
+        .key_path
+        .split_once('\\')
+        .unwrap_or((&write.key_path, ""));
+    let hkey = str_to_hkey(root_name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown root key '{root_name}'"),
+        )
+    })?;
+
+    Ok(OwnedRegValuePath::new(
+        hkey,
+        subkey_path,
+        write.value_name.as_str(),
+    ))
+}
+
+fn write_status(write: &IntendedWrite, current_bytes: Option<&[u8]>) -> WriteStatus {
+    //! If the old and new bytes are equal, the write counts as applied.
+
+    if current_bytes == write.new_bytes.as_deref() {
+        WriteStatus::Applied
+    } else if current_bytes == write.old_bytes.as_deref() {
+        WriteStatus::NotApplied
+    } else {
+        WriteStatus::Diverged
+    }
+}
+
+fn rollback_bytes(old_bytes: &[u8], written_bytes: Option<&[u8]>, now_epoch_secs: u32) -> Vec<u8> {
+    //! Bytes that aren't CloudStore values are returned as they are.
+
+    let written_epoch_secs = written_bytes.and_then(CloudStoreValuePrologue::epoch_secs_of);
+    next_epoch_secs(written_epoch_secs, now_epoch_secs)
+        .and_then(|epoch_secs| CloudStoreValuePrologue::with_epoch_secs(old_bytes, epoch_secs))
+        .unwrap_or_else(|| old_bytes.to_vec())
+}
+
+fn is_process_running(pid: u32) -> bool {
+    //! Processes that can't be opened, e.g., because of missing rights, count as running, to be on the safe side.
+
+    let process_handle = match ResGuard::with_acq_and_close_handle(|| unsafe {
+        OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid)
+    }) {
+        Ok(process_handle) => process_handle,
+        //. The error when the process doesn't exist.
+        Err(error) => return error.code() != ERROR_INVALID_PARAMETER.to_hresult(),
+    };
+
+    let mut exit_code = 0;
+    unsafe { GetExitCodeProcess(*process_handle, &mut exit_code) }
+        .map_or(true, |()| exit_code == STILL_ACTIVE.0 as u32)
+}
+
+fn epoch_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rollback_bytes, write_status, IntendedWrite, Intent, WriteStatus};
+    use crate::cloud_store::{
+        prologue::CloudStoreValuePrologue, test_fixtures::NIGHT_LIGHT_STATE_BYTES,
+    };
+
+    #[test]
+    fn write_statuses() {
+        let write = IntendedWrite {
+            key_path: r"HKEY_CURRENT_USER\Software\sem-reg-test".to_string(),
+            value_name: "Data".to_string(),
+            old_bytes: Some(vec![1, 2]),
+            new_bytes: Some(vec![3, 4]),
+        };
+
+        assert_eq!(write_status(&write, Some(&[3, 4])), WriteStatus::Applied);
+        assert_eq!(write_status(&write, Some(&[1, 2])), WriteStatus::NotApplied);
+        assert_eq!(write_status(&write, Some(&[5])), WriteStatus::Diverged);
+        assert_eq!(write_status(&write, None), WriteStatus::Diverged);
+
+        let deletion = IntendedWrite {
+            new_bytes: None,
+            ..write
+        };
+        assert_eq!(write_status(&deletion, None), WriteStatus::Applied);
+
+        let intent = Intent {
+            pid: 1234,
+            epoch_millis: 1_700_000_000_000,
+            writes: vec![deletion],
+        };
+        let json = serde_json::to_string(&intent).unwrap();
+        assert!(json.contains(r#""oldBytes":"0102","newBytes":null"#));
+        assert_eq!(serde_json::from_str::<Intent>(&json).unwrap(), intent);
+    }
+
+    #[test]
+    fn rollback_timestamps() {
+        const NOW: u32 = 1_700_000_000;
+
+        let with_epoch_secs = |epoch_secs| {
+            CloudStoreValuePrologue::with_epoch_secs(&NIGHT_LIGHT_STATE_BYTES, epoch_secs).unwrap()
+        };
+        let old_bytes = with_epoch_secs(NOW - 100);
+
+        //. The written bytes' timestamp is ahead of the clock.
+        let bytes = rollback_bytes(&old_bytes, Some(&with_epoch_secs(NOW + 10)), NOW);
+        assert_eq!(
+            CloudStoreValuePrologue::epoch_secs_of(&bytes),
+            Some(NOW + 12)
+        );
+        assert_eq!(bytes, with_epoch_secs(NOW + 12));
+
+        let bytes = rollback_bytes(&old_bytes, Some(&with_epoch_secs(NOW - 50)), NOW);
+        assert_eq!(CloudStoreValuePrologue::epoch_secs_of(&bytes), Some(NOW));
+
+        assert_eq!(rollback_bytes(&[1, 2], Some(&[3, 4]), NOW), [1, 2]);
+    }
+}
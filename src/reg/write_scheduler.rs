@@ -0,0 +1,150 @@
+//! Rate-limited, ordered writing of binary registry values, for callers that write at high frequency, like animations. The CloudStore values, e.g., are processed by a fragile engine that shouldn't be flooded with writes.
+
+use std::{
+    io, mem, thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    provider::{RegProvider, WinRegProvider},
+    wal::Transaction,
+    RegValuePath,
+};
+
+/// The maximum number of buffers of performed writes kept for reuse.
+const MAX_SPARE_BUFFERS: usize = 4;
+
+/// Queues writes of binary registry values and performs them in queue order on [`Self::flush()`], blocking as necessary to keep the configured durations. Queuing a value that's already queued replaces the queued bytes, keeping the position, so that only the latest bytes get written.
+pub struct RegWriteScheduler<'a> {
+    write_gap: Duration,
+    min_flush_interval: Duration,
+    queue: Vec<QueuedWrite<'a>>,
+    last_flush_instant: Option<Instant>,
+    last_write_instant: Option<Instant>,
+    spare_buffers: Vec<Vec<u8>>,
+}
+
+struct QueuedWrite<'a> {
+    reg_value_path: RegValuePath<'a>,
+    bytes: Vec<u8>,
+    creates_key: bool,
+}
+
+impl<'a> RegWriteScheduler<'a> {
+    pub fn new(write_gap: Duration, min_flush_interval: Duration) -> Self {
+        //! `write_gap` is the minimum duration between two consecutive writes, also across flushes. `min_flush_interval` is the minimum duration between the starts of two flushes, which rate-limits callers flushing in a loop.
+
+        Self {
+            write_gap,
+            min_flush_interval,
+            queue: Vec::new(),
+            last_flush_instant: None,
+            last_write_instant: None,
+            spare_buffers: Vec::new(),
+        }
+    }
+
+    pub fn queue(&mut self, reg_value_path: RegValuePath<'a>, bytes: Vec<u8>, creates_key: bool) {
+        //! With `creates_key`, the write uses [`write_reg_bin_value_creating_key()`](super::write_reg_bin_value_creating_key) instead of [`write_reg_bin_value()`](super::write_reg_bin_value).
+
+        let queued_write = self
+            .queue
+            .iter_mut()
+            .find(|queued_write| queued_write.reg_value_path == reg_value_path);
+
+        match queued_write {
+            Some(queued_write) => {
+                queued_write.creates_key |= creates_key;
+                let replaced_bytes = mem::replace(&mut queued_write.bytes, bytes);
+                self.recycle(replaced_bytes);
+            }
+            None => self.queue.push(QueuedWrite {
+                reg_value_path,
+                bytes,
+                creates_key,
+            }),
+        }
+    }
+
+    pub fn take_buffer(&mut self) -> Vec<u8> {
+        //! Returns an empty buffer, reusing the allocation of a performed or replaced write, if available. Callers writing at high frequency can serialize into it before queuing, to avoid allocating for every write.
+
+        self.spare_buffers.pop().unwrap_or_default()
+    }
+
+    fn recycle(&mut self, mut bytes: Vec<u8>) {
+        if self.spare_buffers.len() < MAX_SPARE_BUFFERS {
+            bytes.clear();
+            self.spare_buffers.push(bytes);
+        }
+    }
+
+    pub fn queued_writes(&self) -> impl Iterator<Item = (RegValuePath<'a>, &[u8])> {
+        //! The paths and bytes of the queued writes, in queue order.
+
+        self.queue
+            .iter()
+            .map(|queued_write| (queued_write.reg_value_path, queued_write.bytes.as_slice()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        //! Waits until the minimum flush interval has elapsed since the start of the previous flush, and then performs the queued writes, waiting for the write gap before each one. If a write fails, it and the following writes remain queued.
+        //!
+        //! The writes are recorded in the write-ahead log (see [`wal`](super::wal)), so that they can be recovered after a crash in between.
+
+        sleep_until_elapsed(self.last_flush_instant, self.min_flush_interval);
+        self.last_flush_instant = Some(Instant::now());
+
+        if self.queue.is_empty() {
+            return Ok(());
+        }
+
+        let transaction = Transaction::begin(self.queue.iter().map(|queued_write| {
+            (
+                queued_write.reg_value_path,
+                Some(queued_write.bytes.as_slice()),
+            )
+        }));
+        let result = self.write_queued(&WinRegProvider);
+        transaction.commit();
+
+        result
+    }
+
+    pub fn flush_to<P: RegProvider + ?Sized>(&mut self, provider: &P) -> Result<(), io::Error> {
+        //! Like [`Self::flush()`], but writes through the provider, e.g., an in-memory registry in tests. The write-ahead log isn't used, since its recovery writes to the Windows registry.
+
+        sleep_until_elapsed(self.last_flush_instant, self.min_flush_interval);
+        self.last_flush_instant = Some(Instant::now());
+
+        self.write_queued(provider)
+    }
+
+    fn write_queued<P: RegProvider + ?Sized>(&mut self, provider: &P) -> Result<(), io::Error> {
+        while let Some(queued_write) = self.queue.first() {
+            sleep_until_elapsed(self.last_write_instant, self.write_gap);
+
+            provider.write_bin_value(
+                &queued_write.reg_value_path,
+                &queued_write.bytes,
+                queued_write.creates_key,
+            )?;
+
+            self.last_write_instant = Some(Instant::now());
+            let queued_write = self.queue.remove(0);
+            self.recycle(queued_write.bytes);
+        }
+
+        Ok(())
+    }
+}
+
+fn sleep_until_elapsed(instant: Option<Instant>, duration: Duration) {
+    if let Some(remaining) = instant.and_then(|instant| duration.checked_sub(instant.elapsed())) {
+        thread::sleep(remaining);
+    }
+}
@@ -0,0 +1,210 @@
+//! Sunrise and sunset times computed from geographic coordinates, e.g., to emulate Night Light's "Sunset to sunrise" schedule type with the explicit schedule when location services are off. The coordinates can be supplied by the user or obtained from the Windows location service.
+//!
+//! The times are computed with the sunrise equation (see <https://en.wikipedia.org/wiki/Sunrise_equation>), which is accurate to about a minute outside of polar regions.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::{f64::consts::PI, num::ParseFloatError, str::FromStr};
+use thiserror::Error;
+use windows::Devices::Geolocation::Geolocator;
+
+/// The Julian date of 2000-01-01T12:00:00Z.
+const J2000: f64 = 2_451_545.0;
+/// The Julian date of 1970-01-01T00:00:00Z.
+const UNIX_EPOCH_JULIAN_DATE: f64 = 2_440_587.5;
+/// The altitude of the sun's center at sunrise and sunset, accounting for atmospheric refraction and the sun's radius.
+const SUNRISE_ALTITUDE_DEGREES: f64 = -0.833;
+/// The tilt of the earth's axis.
+const OBLIQUITY_DEGREES: f64 = 23.4397;
+
+/// A geographic position in degrees. Southern latitudes and western longitudes are negative.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Coordinates {
+    latitude: f64,
+    longitude: f64,
+}
+
+impl Coordinates {
+    pub fn new(latitude: f64, longitude: f64) -> Option<Self> {
+        //! `None`, if the latitude isn't in the range from -90 to 90 or the longitude not in the range from -180 to 180.
+
+        ((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude)).then_some(
+            Self {
+                latitude,
+                longitude,
+            },
+        )
+    }
+
+    pub fn from_location_service() -> Result<Self, windows::core::Error> {
+        //! Queries the Windows location service, blocking the thread until the position was determined. Fails, if location services are off or the app isn't permitted to access them.
+
+        let position = Geolocator::new()?
+            .GetGeopositionAsync()?
+            .get()?
+            .Coordinate()?
+            .Point()?
+            .Position()?;
+
+        Ok(Self {
+            latitude: position.Latitude,
+            longitude: position.Longitude,
+        })
+    }
+
+    pub fn latitude(&self) -> f64 {
+        self.latitude
+    }
+
+    pub fn longitude(&self) -> f64 {
+        self.longitude
+    }
+}
+
+impl FromStr for Coordinates {
+    type Err = CoordinatesFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        //! Parses the latitude and longitude separated by a comma, like `52.52,13.40`.
+
+        let (latitude, longitude) = s.split_once(',').ok_or(CoordinatesFromStrError)?;
+        Self::new(latitude.trim().parse()?, longitude.trim().parse()?)
+            .ok_or(CoordinatesFromStrError)
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("coordinates must be a latitude from -90 to 90 and a longitude from -180 to 180 in degrees, separated by a comma")]
+pub struct CoordinatesFromStrError;
+
+impl From<ParseFloatError> for CoordinatesFromStrError {
+    fn from(_: ParseFloatError) -> Self {
+        Self
+    }
+}
+
+/// Durations by which to shift sunset and sunrise, e.g., to start the night earlier. Positive durations shift to later times.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SunOffsets {
+    pub sunset: Duration,
+    pub sunrise: Duration,
+}
+
+impl Default for SunOffsets {
+    fn default() -> Self {
+        Self {
+            sunset: Duration::zero(),
+            sunrise: Duration::zero(),
+        }
+    }
+}
+
+/// The sunrise and sunset of a day.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SunTimes {
+    pub sunrise: DateTime<Utc>,
+    pub sunset: DateTime<Utc>,
+}
+
+impl SunTimes {
+    pub fn on_date(coordinates: Coordinates, date: NaiveDate) -> Option<Self> {
+        //! Computes the times of the day on which the sun culminates on the date, as seen from the coordinates' time zone. `None` during polar day or night, when the sun doesn't rise or set.
+
+        let latitude = coordinates.latitude.to_radians();
+
+        let days_since_j2000 = (date
+            - NaiveDate::from_ymd_opt(2000, 1, 1).expect("date should be valid"))
+        .num_days() as f64
+            + 0.0008;
+        let mean_solar_time = days_since_j2000 - coordinates.longitude / 360.0;
+
+        let mean_anomaly = (357.5291 + 0.985_600_28 * mean_solar_time)
+            .rem_euclid(360.0)
+            .to_radians();
+        let center = 1.9148 * mean_anomaly.sin()
+            + 0.0200 * (2.0 * mean_anomaly).sin()
+            + 0.0003 * (3.0 * mean_anomaly).sin();
+        let ecliptic_longitude = (mean_anomaly.to_degrees() + center + 180.0 + 102.9372)
+            .rem_euclid(360.0)
+            .to_radians();
+        let transit = J2000 + mean_solar_time + 0.0053 * mean_anomaly.sin()
+            - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+        let declination = (ecliptic_longitude.sin() * OBLIQUITY_DEGREES.to_radians().sin()).asin();
+        let cos_hour_angle = (SUNRISE_ALTITUDE_DEGREES.to_radians().sin()
+            - latitude.sin() * declination.sin())
+            / (latitude.cos() * declination.cos());
+        if !(-1.0..=1.0).contains(&cos_hour_angle) {
+            return None;
+        }
+        let hour_angle = cos_hour_angle.acos();
+
+        Some(Self {
+            sunrise: julian_date_to_date_time(transit - hour_angle / (2.0 * PI))?,
+            sunset: julian_date_to_date_time(transit + hour_angle / (2.0 * PI))?,
+        })
+    }
+}
+
+fn julian_date_to_date_time(julian_date: f64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(
+        ((julian_date - UNIX_EPOCH_JULIAN_DATE) * 86_400.0).round() as _,
+        0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDate, Utc};
+
+    use super::{Coordinates, SunTimes};
+
+    fn assert_close(actual: DateTime<Utc>, expected: &str) {
+        let expected = DateTime::parse_from_rfc3339(expected).unwrap();
+        let deviation = (actual - expected.with_timezone(&Utc)).num_seconds().abs();
+        assert!(deviation <= 120, "{actual} isn't close to {expected}");
+    }
+
+    #[test]
+    fn sun_times() {
+        //. Berlin at the summer solstice.
+        let sun_times = SunTimes::on_date(
+            "52.52,13.405".parse().unwrap(),
+            NaiveDate::from_ymd_opt(2024, 6, 21).unwrap(),
+        )
+        .unwrap();
+        assert_close(sun_times.sunrise, "2024-06-21T02:43:00Z");
+        assert_close(sun_times.sunset, "2024-06-21T19:33:00Z");
+
+        //. Sydney in winter.
+        let sun_times = SunTimes::on_date(
+            Coordinates::new(-33.87, 151.21).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 6, 21).unwrap(),
+        )
+        .unwrap();
+        assert_close(sun_times.sunrise, "2024-06-20T21:00:00Z");
+        assert_close(sun_times.sunset, "2024-06-21T06:54:00Z");
+
+        //. Midnight sun and polar night in Tromsø.
+        let tromsø = Coordinates::new(69.65, 18.96).unwrap();
+        assert_eq!(
+            SunTimes::on_date(tromsø, NaiveDate::from_ymd_opt(2024, 6, 21).unwrap()),
+            None
+        );
+        assert_eq!(
+            SunTimes::on_date(tromsø, NaiveDate::from_ymd_opt(2024, 12, 21).unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn coordinates_from_str() {
+        let coordinates = " -33.87 , 151.21".parse::<Coordinates>().unwrap();
+        assert_eq!(coordinates.latitude(), -33.87);
+        assert_eq!(coordinates.longitude(), 151.21);
+
+        assert!("91,0".parse::<Coordinates>().is_err());
+        assert!("0,181".parse::<Coordinates>().is_err());
+        assert!("52.52".parse::<Coordinates>().is_err());
+        assert!("north,east".parse::<Coordinates>().is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! Support for testing code that uses the crate against registry values that are modified concurrently, like by Windows itself. Values are sandboxed under a temporary key, so that real settings aren't touched, and external modifications are simulated from scripts, either on a background thread or step by step for deterministic interleaving.
+//!
+//! Only available with the `test-support` feature.
+//!
+//! # Examples
+//! ```ignore
+//! let sandbox = SandboxKey::create()?;
+//! sandbox.write("Data", &[1, 2, 3])?;
+//!
+//! let mut monitor = RegValueMonitor::new([((), sandbox.reg_value_path("Data"))])?;
+//! let writer = ExternalWriter::spawn(&sandbox, ModificationScript::rapid_writes("Data", &[vec![4], vec![5]], 100, Duration::ZERO));
+//! for change in monitor.iter_changes(Some(Duration::from_secs(1))) { /* ... */ }
+//! writer.join()?;
+//! ```
+
+use std::{
+    io, process,
+    sync::atomic::{AtomicU32, Ordering},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use winreg::{
+    enums::{HKEY_CURRENT_USER, KEY_ALL_ACCESS},
+    RegKey,
+};
+
+use crate::reg::{delete_reg_value, read_reg_bin_value, write_reg_bin_value, OwnedRegValuePath};
+
+const SANDBOX_PARENT_SUBKEY_PATH: &str = r"SOFTWARE\sem-reg-test-sandbox";
+
+static NEXT_SANDBOX_NUMBER: AtomicU32 = AtomicU32::new(0);
+
+/// A temporary key under `HKEY_CURRENT_USER`, unique per process and instance, which is deleted including its values when dropped. The shared parent key is deleted with the last sandbox.
+pub struct SandboxKey {
+    subkey_path: String,
+}
+
+impl SandboxKey {
+    pub fn create() -> Result<Self, io::Error> {
+        let subkey_path = format!(
+            r"{SANDBOX_PARENT_SUBKEY_PATH}\{}-{}",
+            process::id(),
+            NEXT_SANDBOX_NUMBER.fetch_add(1, Ordering::Relaxed)
+        );
+        RegKey::predef(HKEY_CURRENT_USER).create_subkey_with_flags(&subkey_path, KEY_ALL_ACCESS)?;
+
+        Ok(Self { subkey_path })
+    }
+
+    pub fn subkey_path(&self) -> &str {
+        //! Relative to `HKEY_CURRENT_USER`.
+
+        &self.subkey_path
+    }
+
+    pub fn reg_value_path(&self, value_name: &str) -> OwnedRegValuePath {
+        OwnedRegValuePath::new(HKEY_CURRENT_USER, self.subkey_path.as_str(), value_name)
+    }
+
+    pub fn write(&self, value_name: &str, bytes: &[u8]) -> Result<(), io::Error> {
+        //! Writes a binary value.
+
+        write_reg_bin_value(
+            &self.reg_value_path(value_name).as_borrowed(),
+            &bytes.to_vec(),
+        )
+    }
+
+    pub fn read(&self, value_name: &str) -> Result<Vec<u8>, io::Error> {
+        read_reg_bin_value(&self.reg_value_path(value_name).as_borrowed())
+    }
+
+    pub fn delete(&self, value_name: &str) -> Result<(), io::Error> {
+        //! Succeeds, if the value doesn't exist.
+
+        delete_reg_value(&self.reg_value_path(value_name).as_borrowed())
+    }
+}
+
+impl Drop for SandboxKey {
+    fn drop(&mut self) {
+        let current_user_key = RegKey::predef(HKEY_CURRENT_USER);
+        let _ = current_user_key.delete_subkey_all(&self.subkey_path);
+        //. Fails while other sandboxes, e.g., of concurrently running tests, still exist, which then delete it.
+        let _ = current_user_key.delete_subkey(SANDBOX_PARENT_SUBKEY_PATH);
+    }
+}
+
+/// A modification of a sandboxed value.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Modification {
+    pub value_name: String,
+    /// `None` deletes the value.
+    pub bytes: Option<Vec<u8>>,
+    /// Waited for after the modification when run by an [`ExternalWriter`].
+    pub delay_after: Duration,
+}
+
+/// A sequence of modifications, performed in order.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct ModificationScript {
+    pub modifications: Vec<Modification>,
+}
+
+impl ModificationScript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(mut self, value_name: &str, bytes: Vec<u8>, delay_after: Duration) -> Self {
+        self.modifications.push(Modification {
+            value_name: value_name.to_string(),
+            bytes: Some(bytes),
+            delay_after,
+        });
+        self
+    }
+
+    pub fn delete(mut self, value_name: &str, delay_after: Duration) -> Self {
+        self.modifications.push(Modification {
+            value_name: value_name.to_string(),
+            bytes: None,
+            delay_after,
+        });
+        self
+    }
+
+    pub fn rapid_writes(
+        value_name: &str,
+        byte_variants: &[Vec<u8>],
+        count: usize,
+        gap: Duration,
+    ) -> Self {
+        //! Writes the byte variants in turn, `count` times in total, like a program flooding the value with writes.
+
+        let mut script = Self::new();
+        for bytes in byte_variants.iter().cycle().take(count) {
+            script = script.write(value_name, bytes.clone(), gap);
+        }
+
+        script
+    }
+
+    pub fn run_step(&self, sandbox: &SandboxKey, index: usize) -> Result<(), io::Error> {
+        //! Performs a single modification on the current thread, without the delay. For deterministic interleaving with the code under test, e.g., between its read and its write.
+        //!
+        //! # Panics
+        //! Panics, if the index is out of bounds.
+
+        perform(sandbox.subkey_path(), &self.modifications[index])
+    }
+}
+
+/// Runs a [`ModificationScript`] on a background thread.
+pub struct ExternalWriter {
+    thread: JoinHandle<Result<(), io::Error>>,
+}
+
+impl ExternalWriter {
+    pub fn spawn(sandbox: &SandboxKey, script: ModificationScript) -> Self {
+        //! Starts immediately. The sandbox must outlive the writer; join it before dropping the sandbox.
+
+        let subkey_path = sandbox.subkey_path().to_string();
+        let thread = thread::spawn(move || {
+            for modification in &script.modifications {
+                perform(&subkey_path, modification)?;
+                thread::sleep(modification.delay_after);
+            }
+
+            Ok(())
+        });
+
+        Self { thread }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    pub fn join(self) -> Result<(), io::Error> {
+        //! Waits for the script to end. Returns the error of the first failed modification, which ended the script.
+
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("external writer thread panicked")))
+    }
+}
+
+fn perform(subkey_path: &str, modification: &Modification) -> Result<(), io::Error> {
+    let reg_value_path = OwnedRegValuePath::new(
+        HKEY_CURRENT_USER,
+        subkey_path,
+        modification.value_name.as_str(),
+    );
+
+    match &modification.bytes {
+        Some(bytes) => write_reg_bin_value(&reg_value_path.as_borrowed(), bytes),
+        None => delete_reg_value(&reg_value_path.as_borrowed()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{ExternalWriter, ModificationScript, SandboxKey};
+
+    #[test]
+    fn sandboxed_modifications() {
+        let sandbox = SandboxKey::create().unwrap();
+        let script = ModificationScript::rapid_writes(
+            "Data",
+            &[vec![1], vec![2], vec![3]],
+            10,
+            Duration::ZERO,
+        )
+        .delete("Other", Duration::ZERO);
+        assert_eq!(script.modifications.len(), 11);
+
+        script.run_step(&sandbox, 1).unwrap();
+        assert_eq!(sandbox.read("Data").unwrap(), [2]);
+
+        ExternalWriter::spawn(&sandbox, script).join().unwrap();
+        //. The 10th write is the first variant again.
+        assert_eq!(sandbox.read("Data").unwrap(), [1]);
+
+        let subkey_path = sandbox.subkey_path().to_string();
+        drop(sandbox);
+        assert!(winreg::RegKey::predef(winreg::enums::HKEY_CURRENT_USER)
+            .open_subkey(subkey_path)
+            .is_err());
+    }
+}
@@ -0,0 +1,332 @@
+//! Notification of external systems, like home automation or logging stacks, about setting changes by POSTing JSON payloads to user-configured URLs. Requests are sent from a background thread and retried with exponential backoff, so that monitoring isn't held up by slow or unreachable endpoints.
+//!
+//! Only plain `http://` URLs are supported. For HTTPS endpoints, a local relay is required.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::mpsc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use thiserror::Error;
+
+use crate::{log_debug, log_warn};
+
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An entry of the `webhooks` array of the configuration file.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Like `http://192.168.0.10:8123/api/webhook/night-light`.
+    pub url: String,
+    /// Additional request headers, e.g., for authorization.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// The number of retries after a failed request. Defaults to 3. The delay before the first retry is one second and doubles with every further retry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+}
+
+/// The JSON body of every request.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookPayload {
+    /// The changed setting, like `nightLight`.
+    pub setting: String,
+    /// The local time of the change in RFC 3339 format.
+    pub time: String,
+    /// The new state of the setting, like printed with `--json`.
+    pub value: serde_json::Value,
+}
+
+/// Delivers payloads to all configured webhooks in the background. Dropping it waits for pending deliveries, including their retries.
+pub struct Notifier {
+    sender: Option<mpsc::Sender<String>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Notifier {
+    pub fn new(webhooks: Vec<WebhookConfig>) -> Self {
+        //! With no webhooks, no thread is started and [`Self::notify()`] does nothing.
+
+        if webhooks.is_empty() {
+            return Self {
+                sender: None,
+                thread: None,
+            };
+        }
+
+        let (sender, receiver) = mpsc::channel::<String>();
+        let thread = thread::spawn(move || {
+            for body in receiver {
+                for webhook in &webhooks {
+                    if let Err(error) = post_with_retries(webhook, &body) {
+                        log_warn!("webhook '{}' failed: {error}", webhook.url);
+                    }
+                }
+            }
+        });
+
+        Self {
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    pub fn notify(&self, setting: &str, value: serde_json::Value) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        let payload = WebhookPayload {
+            setting: setting.to_string(),
+            time: chrono::Local::now().to_rfc3339(),
+            value,
+        };
+        let body = serde_json::to_string(&payload).expect("payload should be serializable");
+
+        //. Only fails when the thread panicked.
+        let _ = sender.send(body);
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn post_with_retries(webhook: &WebhookConfig, body: &str) -> Result<(), WebhookError> {
+    let max_retries = webhook.max_retries.unwrap_or(DEFAULT_MAX_RETRIES);
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut retry = 0;
+
+    loop {
+        match post(&webhook.url, &webhook.headers, body) {
+            Ok(()) => {
+                log_debug!("webhook '{}' notified", webhook.url);
+                return Ok(());
+            }
+            //. Retrying wouldn't help.
+            Err(error @ WebhookError::UnsupportedUrl(_)) => return Err(error),
+            Err(error) if retry >= max_retries => return Err(error),
+            Err(error) => {
+                log_debug!(
+                    "webhook '{}' failed, retrying in {delay:?}: {error}",
+                    webhook.url
+                );
+                thread::sleep(delay);
+                delay *= 2;
+                retry += 1;
+            }
+        }
+    }
+}
+
+pub fn post(url: &str, headers: &BTreeMap<String, String>, body: &str) -> Result<(), WebhookError> {
+    //! Sends a single HTTP/1.1 POST request with a JSON body. Succeeds, if the response has a 2xx status code.
+
+    let url = HttpUrl::parse(url)?;
+
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "host not resolvable");
+    let mut stream = None;
+    for socket_addr in (url.host, url.port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_addr, TIMEOUT) {
+            Ok(connected_stream) => {
+                stream = Some(connected_stream);
+                break;
+            }
+            Err(error) => last_error = error,
+        }
+    }
+    let mut stream = stream.ok_or(last_error)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: sem-reg/{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        url.path,
+        url.authority,
+        env!("CARGO_PKG_VERSION"),
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+    stream.write_all(request.as_bytes())?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    let status_code = parse_status_code(&status_line).ok_or(WebhookError::InvalidResponse)?;
+
+    if (200..300).contains(&status_code) {
+        Ok(())
+    } else {
+        Err(WebhookError::HttpStatus(status_code))
+    }
+}
+
+#[derive(PartialEq, Debug)]
+struct HttpUrl<'a> {
+    /// Host and port as in the URL, for the `Host` header.
+    authority: &'a str,
+    /// Without brackets of IPv6 addresses.
+    host: &'a str,
+    port: u16,
+    path: &'a str,
+}
+
+impl<'a> HttpUrl<'a> {
+    fn parse(url: &'a str) -> Result<Self, WebhookError> {
+        let unsupported = || WebhookError::UnsupportedUrl(url.to_string());
+
+        let rest = url.strip_prefix("http://").ok_or_else(unsupported)?;
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+
+        let (host, port) = match authority
+            .rsplit_once(':')
+            .filter(|(_, port)| !port.contains(']'))
+        {
+            Some((host, port)) => (host, port.parse().map_err(|_| unsupported())?),
+            None => (authority, 80),
+        };
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+
+        if host.is_empty() {
+            return Err(unsupported());
+        }
+
+        Ok(Self {
+            authority,
+            host,
+            port,
+            path,
+        })
+    }
+}
+
+fn parse_status_code(status_line: &str) -> Option<u16> {
+    let mut parts = status_line.split(' ');
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+
+    parts.next()?.trim().parse().ok()
+}
+
+#[derive(Error, Debug)]
+pub enum WebhookError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("unsupported URL (only 'http://' URLs are supported): {0}")]
+    UnsupportedUrl(String),
+    #[error("invalid HTTP response")]
+    InvalidResponse,
+    #[error("HTTP status code {0}")]
+    HttpStatus(u16),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::{parse_status_code, post, HttpUrl, WebhookError};
+
+    #[test]
+    fn parse_urls() {
+        assert_eq!(
+            HttpUrl::parse("http://192.168.0.10:8123/api/webhook/x").unwrap(),
+            HttpUrl {
+                authority: "192.168.0.10:8123",
+                host: "192.168.0.10",
+                port: 8123,
+                path: "/api/webhook/x",
+            }
+        );
+        assert_eq!(
+            HttpUrl::parse("http://[::1]").unwrap(),
+            HttpUrl {
+                authority: "[::1]",
+                host: "::1",
+                port: 80,
+                path: "/",
+            }
+        );
+        assert!(HttpUrl::parse("https://example.com/").is_err());
+        assert!(HttpUrl::parse("http://:80/").is_err());
+        assert!(HttpUrl::parse("http://example.com:x/").is_err());
+
+        assert_eq!(parse_status_code("HTTP/1.1 204 No Content\r\n"), Some(204));
+        assert_eq!(parse_status_code("HTTP/1.0 500\r\n"), Some(500));
+        assert_eq!(parse_status_code("SSH-2.0\r\n"), None);
+    }
+
+    #[test]
+    fn post_to_local_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+        let server_thread = thread::spawn(move || {
+            let mut requests = Vec::new();
+
+            for status in ["204 No Content", "503 Service Unavailable"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut head = String::new();
+                while !head.ends_with("\r\n\r\n") {
+                    reader.read_line(&mut head).unwrap();
+                }
+
+                let content_len = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                let mut body = vec![0; content_len];
+                reader.read_exact(&mut body).unwrap();
+                requests.push((head, String::from_utf8(body).unwrap()));
+
+                write!(reader.get_mut(), "HTTP/1.1 {status}\r\n\r\n").unwrap();
+            }
+
+            requests
+        });
+
+        let headers = BTreeMap::from([("Authorization".to_string(), "Bearer x".to_string())]);
+        post(&url, &headers, r#"{"a":1}"#).unwrap();
+        assert!(matches!(
+            post(&url, &BTreeMap::new(), "{}"),
+            Err(WebhookError::HttpStatus(503))
+        ));
+
+        let requests = server_thread.join().unwrap();
+        assert!(requests[0].0.starts_with("POST /hook HTTP/1.1\r\n"));
+        assert!(requests[0].0.contains("Authorization: Bearer x\r\n"));
+        assert_eq!(requests[0].1, r#"{"a":1}"#);
+        assert_eq!(requests[1].1, "{}");
+    }
+}